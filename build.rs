@@ -0,0 +1,9 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // use a vendored protoc so building doesn't require protobuf to be installed
+    std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path()?);
+    tonic_build::compile_protos("proto/cardamon.proto")?;
+    tonic_build::configure()
+        .build_server(false)
+        .compile(&["proto/cri.proto"], &["proto"])?;
+    Ok(())
+}
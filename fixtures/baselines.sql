@@ -0,0 +1,12 @@
+DELETE FROM baseline;
+DELETE FROM run;
+
+INSERT INTO baseline (id, start_time, stop_time, cpu_usage, core_count)
+VALUES
+('idle_1', 1717507500000, 1717507530000, 2.5, 4),
+('idle_2', 1717507600000, 1717507630000, 3.5, 4);
+
+INSERT INTO run (id, baseline_id)
+VALUES
+('1', 'idle_1'),
+('2', NULL);
@@ -0,0 +1,14 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! A deterministic workload for the end-to-end tests, keeps one core busy until it's stopped.
+
+fn main() {
+    let mut count: u64 = 0;
+    loop {
+        count = std::hint::black_box(count.wrapping_add(1));
+    }
+}
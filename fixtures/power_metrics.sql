@@ -0,0 +1,8 @@
+DELETE FROM power_metrics;
+
+INSERT INTO power_metrics (run_id, source, power, timestamp)
+VALUES
+('1', 'tasmota', 42.5, 1717507600000),
+('1', 'tasmota', 45.0, 1717507601000),
+('1', 'tasmota', 44.0, 1717507602000),
+('2', 'tasmota', 40.0, 1717507700000);
@@ -0,0 +1,8 @@
+DELETE FROM process_event;
+
+INSERT INTO process_event (run_id, process_name, event, timestamp) 
+VALUES 
+('1', 'postgres', 'stopped', 1717507592500),
+('1', 'postgres', 'started', 1717507592700),
+('1', 'postgres', 'oom_killed', 1717507598500),
+('2', 'postgres', 'stopped', 1717507690500);
@@ -0,0 +1,5 @@
+DELETE FROM run_impact;
+
+INSERT INTO run_impact (run_id, duration_hours, manufacture_gwp, use_gwp)
+VALUES
+('1', 0.5, 0.0143, 0.0021);
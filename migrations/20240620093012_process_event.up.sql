@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS process_event (
+    run_id TEXT NOT NULL,
+    process_name TEXT NOT NULL,
+    event TEXT NOT NULL,
+    timestamp BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS process_event_run ON process_event (run_id, timestamp);
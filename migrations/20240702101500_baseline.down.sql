@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS run;
+DROP TABLE IF EXISTS baseline;
@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS baseline (
+    id TEXT PRIMARY KEY NOT NULL,
+    start_time BIGINT NOT NULL,
+    stop_time BIGINT NOT NULL,
+    cpu_usage DOUBLE NOT NULL,
+    core_count INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS run (
+    id TEXT PRIMARY KEY NOT NULL,
+    baseline_id TEXT
+);
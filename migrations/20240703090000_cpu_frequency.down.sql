@@ -0,0 +1 @@
+ALTER TABLE cpu_metrics DROP COLUMN cpu_frequency;
@@ -0,0 +1 @@
+ALTER TABLE cpu_metrics ADD COLUMN cpu_frequency BIGINT;
@@ -0,0 +1 @@
+ALTER TABLE cpu_metrics DROP COLUMN memory_usage;
@@ -0,0 +1 @@
+ALTER TABLE cpu_metrics ADD COLUMN memory_usage BIGINT;
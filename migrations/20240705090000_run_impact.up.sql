@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS run_impact (
+    run_id TEXT PRIMARY KEY NOT NULL,
+    duration_hours DOUBLE NOT NULL,
+    manufacture_gwp DOUBLE NOT NULL,
+    use_gwp DOUBLE NOT NULL
+);
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS power_metrics (
+    run_id TEXT NOT NULL,
+    source TEXT NOT NULL,
+    power DOUBLE NOT NULL,
+    timestamp BIGINT NOT NULL,
+    PRIMARY KEY (run_id, source, timestamp)
+);
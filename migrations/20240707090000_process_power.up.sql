@@ -0,0 +1 @@
+ALTER TABLE cpu_metrics ADD COLUMN power DOUBLE;
@@ -0,0 +1 @@
+ALTER TABLE run ADD COLUMN archived BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,2 @@
+ALTER TABLE run DROP COLUMN project_id;
+DROP TABLE IF EXISTS project;
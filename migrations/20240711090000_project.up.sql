@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS project (
+    id TEXT PRIMARY KEY NOT NULL
+);
+
+INSERT INTO project (id) VALUES ('default');
+
+ALTER TABLE run ADD COLUMN project_id TEXT NOT NULL DEFAULT 'default';
@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS energy_mix (
+    run_id TEXT NOT NULL,
+    fuel TEXT NOT NULL,
+    percentage DOUBLE NOT NULL,
+    PRIMARY KEY (run_id, fuel)
+);
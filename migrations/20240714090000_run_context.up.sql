@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS run_context (
+    run_id TEXT PRIMARY KEY NOT NULL,
+    os_version TEXT,
+    kernel_version TEXT,
+    total_memory BIGINT NOT NULL,
+    core_count INTEGER NOT NULL,
+    cpu_governor TEXT,
+    container_runtime TEXT,
+    cardamon_version TEXT NOT NULL
+);
@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS reference_run (
+    project_id TEXT PRIMARY KEY NOT NULL,
+    run_id TEXT NOT NULL,
+    name TEXT NOT NULL
+);
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS iteration_result (
+    run_id TEXT NOT NULL,
+    scenario_name TEXT NOT NULL,
+    iteration INTEGER NOT NULL,
+    requests BIGINT,
+    p50_ms DOUBLE,
+    p95_ms DOUBLE,
+    p99_ms DOUBLE,
+    PRIMARY KEY (run_id, scenario_name, iteration)
+);
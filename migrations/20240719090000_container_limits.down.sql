@@ -0,0 +1,3 @@
+ALTER TABLE cpu_metrics DROP COLUMN throttled_periods;
+ALTER TABLE cpu_metrics DROP COLUMN cpu_periods;
+ALTER TABLE cpu_metrics DROP COLUMN memory_limit;
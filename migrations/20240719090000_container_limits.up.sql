@@ -0,0 +1,3 @@
+ALTER TABLE cpu_metrics ADD COLUMN memory_limit INTEGER;
+ALTER TABLE cpu_metrics ADD COLUMN cpu_periods INTEGER;
+ALTER TABLE cpu_metrics ADD COLUMN throttled_periods INTEGER;
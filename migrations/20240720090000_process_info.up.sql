@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS process_info (
+    run_id TEXT NOT NULL,
+    process_name TEXT NOT NULL,
+    image TEXT,
+    cpu_limit DOUBLE,
+    memory_limit BIGINT,
+    replicas INTEGER NOT NULL,
+    PRIMARY KEY (run_id, process_name)
+);
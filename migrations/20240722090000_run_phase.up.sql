@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS run_phase (
+    run_id TEXT NOT NULL,
+    phase TEXT NOT NULL,
+    start_time BIGINT NOT NULL,
+    stop_time BIGINT NOT NULL,
+    PRIMARY KEY (run_id, phase)
+);
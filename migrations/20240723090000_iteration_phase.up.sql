@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS iteration_phase (
+    run_id TEXT NOT NULL,
+    scenario_name TEXT NOT NULL,
+    iteration INTEGER NOT NULL,
+    phase TEXT NOT NULL,
+    start_time BIGINT NOT NULL,
+    stop_time BIGINT NOT NULL,
+    PRIMARY KEY (run_id, scenario_name, iteration, phase, start_time)
+);
@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS iteration_phase_untraced (
+    run_id TEXT NOT NULL,
+    scenario_name TEXT NOT NULL,
+    iteration INTEGER NOT NULL,
+    phase TEXT NOT NULL,
+    start_time BIGINT NOT NULL,
+    stop_time BIGINT NOT NULL,
+    PRIMARY KEY (run_id, scenario_name, iteration, phase, start_time)
+);
+INSERT OR IGNORE INTO iteration_phase_untraced
+    SELECT run_id, scenario_name, iteration, phase, start_time, stop_time FROM iteration_phase;
+DROP TABLE iteration_phase;
+ALTER TABLE iteration_phase_untraced RENAME TO iteration_phase;
@@ -0,0 +1,18 @@
+-- spans of traced requests can share a name and start time so phases are told apart by span id
+CREATE TABLE IF NOT EXISTS iteration_phase_traced (
+    run_id TEXT NOT NULL,
+    scenario_name TEXT NOT NULL,
+    iteration INTEGER NOT NULL,
+    phase TEXT NOT NULL,
+    start_time BIGINT NOT NULL,
+    stop_time BIGINT NOT NULL,
+    trace_id TEXT,
+    span_id TEXT
+);
+INSERT INTO iteration_phase_traced (run_id, scenario_name, iteration, phase, start_time, stop_time)
+    SELECT run_id, scenario_name, iteration, phase, start_time, stop_time FROM iteration_phase;
+DROP TABLE iteration_phase;
+ALTER TABLE iteration_phase_traced RENAME TO iteration_phase;
+CREATE UNIQUE INDEX IF NOT EXISTS iteration_phase_key ON iteration_phase (
+    run_id, scenario_name, iteration, phase, start_time, COALESCE(span_id, '')
+);
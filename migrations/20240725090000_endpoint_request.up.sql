@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS endpoint_request (
+    run_id TEXT NOT NULL,
+    scenario_name TEXT NOT NULL,
+    iteration INTEGER NOT NULL,
+    endpoint TEXT NOT NULL,
+    requests INTEGER NOT NULL,
+    PRIMARY KEY (run_id, scenario_name, iteration, endpoint)
+);
@@ -0,0 +1 @@
+ALTER TABLE run_context DROP COLUMN pinning;
@@ -0,0 +1 @@
+ALTER TABLE run_context ADD COLUMN pinning TEXT;
@@ -0,0 +1,2 @@
+ALTER TABLE run ADD COLUMN stop_time BIGINT;
+UPDATE run SET stop_time = (SELECT MAX(si.stop_time) FROM scenario_iteration si WHERE si.run_id = run.id);
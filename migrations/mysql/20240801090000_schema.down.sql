@@ -0,0 +1,16 @@
+DROP TABLE IF EXISTS endpoint_request;
+DROP TABLE IF EXISTS iteration_phase;
+DROP TABLE IF EXISTS run_phase;
+DROP TABLE IF EXISTS process_info;
+DROP TABLE IF EXISTS iteration_result;
+DROP TABLE IF EXISTS run_context;
+DROP TABLE IF EXISTS energy_mix;
+DROP TABLE IF EXISTS run_impact;
+DROP TABLE IF EXISTS process_event;
+DROP TABLE IF EXISTS power_metrics;
+DROP TABLE IF EXISTS cpu_metrics;
+DROP TABLE IF EXISTS scenario_iteration;
+DROP TABLE IF EXISTS reference_run;
+DROP TABLE IF EXISTS run;
+DROP TABLE IF EXISTS baseline;
+DROP TABLE IF EXISTS project;
@@ -0,0 +1,161 @@
+-- the SQLite migrations up to run_scenarios in one go. Text is compared byte for byte like it is
+-- in SQLite, and key columns are VARCHARs short enough for InnoDB to index.
+CREATE TABLE IF NOT EXISTS project (
+    id VARCHAR(191) PRIMARY KEY NOT NULL
+) DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_bin;
+
+INSERT INTO project (id) VALUES ('default');
+
+CREATE TABLE IF NOT EXISTS baseline (
+    id VARCHAR(64) PRIMARY KEY NOT NULL,
+    start_time BIGINT NOT NULL,
+    stop_time BIGINT NOT NULL,
+    cpu_usage DOUBLE NOT NULL,
+    core_count BIGINT NOT NULL
+) DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_bin;
+
+CREATE TABLE IF NOT EXISTS run (
+    id VARCHAR(64) PRIMARY KEY NOT NULL,
+    baseline_id VARCHAR(64),
+    archived BOOLEAN NOT NULL DEFAULT FALSE,
+    note TEXT,
+    project_id VARCHAR(191) NOT NULL DEFAULT 'default',
+    schedule TEXT,
+    iterations BIGINT,
+    observation TEXT,
+    ambient_load DOUBLE,
+    stop_time BIGINT,
+    scenarios TEXT
+) DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_bin;
+
+CREATE TABLE IF NOT EXISTS reference_run (
+    project_id VARCHAR(191) PRIMARY KEY NOT NULL,
+    run_id VARCHAR(64) NOT NULL,
+    name TEXT NOT NULL
+) DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_bin;
+
+CREATE TABLE IF NOT EXISTS scenario_iteration (
+    run_id VARCHAR(64) NOT NULL,
+    scenario_name VARCHAR(191) NOT NULL,
+    iteration BIGINT NOT NULL,
+    start_time BIGINT NOT NULL,
+    stop_time BIGINT NOT NULL,
+    status VARCHAR(64) NOT NULL DEFAULT 'ok',
+    cold_start BOOLEAN NOT NULL DEFAULT FALSE,
+    PRIMARY KEY (run_id, scenario_name, iteration)
+) DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_bin;
+
+CREATE TABLE IF NOT EXISTS cpu_metrics (
+    run_id VARCHAR(64) NOT NULL,
+    process_id VARCHAR(191) NOT NULL,
+    process_name VARCHAR(191) NOT NULL,
+    cpu_usage DOUBLE NOT NULL,
+    total_usage DOUBLE NOT NULL,
+    core_count BIGINT NOT NULL,
+    timestamp BIGINT NOT NULL,
+    cpu_frequency BIGINT,
+    memory_usage BIGINT,
+    power DOUBLE,
+    sample_interval DOUBLE,
+    memory_limit BIGINT,
+    cpu_periods BIGINT,
+    throttled_periods BIGINT,
+    PRIMARY KEY (run_id, process_id, timestamp),
+    INDEX cpu_metrics_run (run_id, timestamp)
+) DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_bin;
+
+CREATE TABLE IF NOT EXISTS power_metrics (
+    run_id VARCHAR(64) NOT NULL,
+    source VARCHAR(64) NOT NULL,
+    power DOUBLE NOT NULL,
+    timestamp BIGINT NOT NULL,
+    PRIMARY KEY (run_id, source, timestamp)
+) DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_bin;
+
+-- events have no natural key, the id keeps them in the order they were recorded
+CREATE TABLE IF NOT EXISTS process_event (
+    id BIGINT AUTO_INCREMENT PRIMARY KEY,
+    run_id VARCHAR(64) NOT NULL,
+    process_name VARCHAR(191) NOT NULL,
+    event TEXT NOT NULL,
+    timestamp BIGINT NOT NULL,
+    INDEX process_event_run (run_id, timestamp)
+) DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_bin;
+
+CREATE TABLE IF NOT EXISTS run_impact (
+    run_id VARCHAR(64) PRIMARY KEY NOT NULL,
+    duration_hours DOUBLE NOT NULL,
+    manufacture_gwp DOUBLE NOT NULL,
+    use_gwp DOUBLE NOT NULL
+) DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_bin;
+
+CREATE TABLE IF NOT EXISTS energy_mix (
+    run_id VARCHAR(64) NOT NULL,
+    fuel VARCHAR(64) NOT NULL,
+    percentage DOUBLE NOT NULL,
+    PRIMARY KEY (run_id, fuel)
+) DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_bin;
+
+CREATE TABLE IF NOT EXISTS run_context (
+    run_id VARCHAR(64) PRIMARY KEY NOT NULL,
+    os_version TEXT,
+    kernel_version TEXT,
+    total_memory BIGINT NOT NULL,
+    core_count BIGINT NOT NULL,
+    cpu_governor TEXT,
+    container_runtime TEXT,
+    cardamon_version TEXT NOT NULL,
+    pinning TEXT
+) DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_bin;
+
+CREATE TABLE IF NOT EXISTS iteration_result (
+    run_id VARCHAR(64) NOT NULL,
+    scenario_name VARCHAR(191) NOT NULL,
+    iteration BIGINT NOT NULL,
+    requests BIGINT,
+    p50_ms DOUBLE,
+    p95_ms DOUBLE,
+    p99_ms DOUBLE,
+    PRIMARY KEY (run_id, scenario_name, iteration)
+) DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_bin;
+
+CREATE TABLE IF NOT EXISTS process_info (
+    run_id VARCHAR(64) NOT NULL,
+    process_name VARCHAR(191) NOT NULL,
+    image TEXT,
+    cpu_limit DOUBLE,
+    memory_limit BIGINT,
+    replicas BIGINT NOT NULL,
+    PRIMARY KEY (run_id, process_name)
+) DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_bin;
+
+CREATE TABLE IF NOT EXISTS run_phase (
+    run_id VARCHAR(64) NOT NULL,
+    phase VARCHAR(64) NOT NULL,
+    start_time BIGINT NOT NULL,
+    stop_time BIGINT NOT NULL,
+    PRIMARY KEY (run_id, phase)
+) DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_bin;
+
+-- MySQL can't index an expression in a unique key, the span is keyed through a generated column
+CREATE TABLE IF NOT EXISTS iteration_phase (
+    run_id VARCHAR(64) NOT NULL,
+    scenario_name VARCHAR(191) NOT NULL,
+    iteration BIGINT NOT NULL,
+    phase VARCHAR(191) NOT NULL,
+    start_time BIGINT NOT NULL,
+    stop_time BIGINT NOT NULL,
+    trace_id VARCHAR(64),
+    span_id VARCHAR(64),
+    span_key VARCHAR(64) AS (COALESCE(span_id, '')) STORED NOT NULL,
+    UNIQUE KEY iteration_phase_key (run_id, scenario_name, iteration, phase, start_time, span_key)
+) DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_bin;
+
+CREATE TABLE IF NOT EXISTS endpoint_request (
+    run_id VARCHAR(64) NOT NULL,
+    scenario_name VARCHAR(191) NOT NULL,
+    iteration BIGINT NOT NULL,
+    endpoint VARCHAR(191) NOT NULL,
+    requests BIGINT NOT NULL,
+    PRIMARY KEY (run_id, scenario_name, iteration, endpoint)
+) DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_bin;
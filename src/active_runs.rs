@@ -0,0 +1,189 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! The runs in progress in this process and how they're getting on, so the server can show the
+//! progress of observations it started rather than leaving clients to wait for them to finish.
+//! Runs are cancelled through the registry too.
+
+use serde::Serialize;
+use std::{sync::Mutex, time::Instant};
+use tokio_util::sync::CancellationToken;
+
+static ACTIVE: Mutex<Vec<Entry>> = Mutex::new(Vec::new());
+
+struct Entry {
+    run_id: String,
+    started: Instant,
+    scenario: Option<String>,
+    iteration: Option<i64>,
+    power: Option<f64>,
+    cancel: CancellationToken,
+}
+
+/// Returned by a run which was cancelled, after the application has been shut down and what was
+/// measured before the cancellation has been saved.
+#[derive(Debug, thiserror::Error)]
+#[error("Run {0} was cancelled")]
+pub struct Cancelled(pub String);
+
+/// A run in progress.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ActiveRun {
+    pub run_id: String,
+    /// The scenario being run, None while the application is starting.
+    pub scenario: Option<String>,
+    /// The iteration of the scenario being run, counting from 1.
+    pub iteration: Option<i64>,
+    pub elapsed_secs: f64,
+    /// Watts being drawn by the observed processes, measured if there's a power meter and
+    /// estimated from the CPU's TDP otherwise.
+    pub power: Option<f64>,
+}
+
+/// Keeps the run in the registry until it's dropped.
+pub struct Registration {
+    run_id: String,
+    cancel: CancellationToken,
+}
+impl Registration {
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.is_cancelled()
+    }
+
+    /// Completes once the run is cancelled.
+    pub async fn cancelled(&self) {
+        self.cancel.cancelled().await
+    }
+
+    pub fn start_iteration(&self, scenario: &str, iteration: i64) {
+        self.update(|entry| {
+            entry.scenario = Some(scenario.to_string());
+            entry.iteration = Some(iteration);
+            entry.power = None;
+        });
+    }
+
+    pub fn update_power(&self, power: Option<f64>) {
+        self.update(|entry| entry.power = power);
+    }
+
+    fn update(&self, f: impl FnOnce(&mut Entry)) {
+        if let Ok(mut active) = ACTIVE.lock() {
+            if let Some(entry) = active.iter_mut().find(|entry| entry.run_id == self.run_id) {
+                f(entry);
+            }
+        }
+    }
+}
+impl Drop for Registration {
+    fn drop(&mut self) {
+        if let Ok(mut active) = ACTIVE.lock() {
+            active.retain(|entry| entry.run_id != self.run_id);
+        }
+    }
+}
+
+/// Adds the run to the registry, it's removed again when the registration is dropped.
+pub fn register(run_id: &str) -> Registration {
+    let cancel = CancellationToken::new();
+    if let Ok(mut active) = ACTIVE.lock() {
+        active.push(Entry {
+            run_id: run_id.to_string(),
+            started: Instant::now(),
+            scenario: None,
+            iteration: None,
+            power: None,
+            cancel: cancel.clone(),
+        });
+    }
+    Registration {
+        run_id: run_id.to_string(),
+        cancel,
+    }
+}
+
+/// Asks the run to stop, false if it isn't in progress in this process.
+pub fn cancel(run_id: &str) -> bool {
+    ACTIVE
+        .lock()
+        .ok()
+        .and_then(|active| {
+            active
+                .iter()
+                .find(|entry| entry.run_id == run_id)
+                .map(|entry| entry.cancel.cancel())
+        })
+        .is_some()
+}
+
+/// Asks every run in progress to stop, e.g. when cardamon is interrupted.
+pub fn cancel_all() {
+    if let Ok(active) = ACTIVE.lock() {
+        for entry in active.iter() {
+            entry.cancel.cancel();
+        }
+    }
+}
+
+/// The runs in progress, oldest first.
+pub fn active() -> Vec<ActiveRun> {
+    ACTIVE
+        .lock()
+        .map(|active| {
+            active
+                .iter()
+                .map(|entry| ActiveRun {
+                    run_id: entry.run_id.clone(),
+                    scenario: entry.scenario.clone(),
+                    iteration: entry.iteration,
+                    elapsed_secs: entry.started.elapsed().as_secs_f64(),
+                    power: entry.power,
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(run_id: &str) -> Option<ActiveRun> {
+        active().into_iter().find(|run| run.run_id == run_id)
+    }
+
+    #[test]
+    fn runs_are_active_until_their_registration_is_dropped() {
+        let run_id = format!("active-{}", nanoid::nanoid!(8));
+        let registration = register(&run_id);
+        let run = find(&run_id);
+        assert!(run.as_ref().is_some_and(|run| run.scenario.is_none()));
+
+        registration.start_iteration("basket_10", 2);
+        registration.update_power(Some(12.5));
+        let run = find(&run_id);
+        assert_eq!(
+            run.as_ref().and_then(|run| run.scenario.as_deref()),
+            Some("basket_10")
+        );
+        assert_eq!(run.as_ref().and_then(|run| run.iteration), Some(2));
+        assert_eq!(run.as_ref().and_then(|run| run.power), Some(12.5));
+
+        drop(registration);
+        assert_eq!(find(&run_id), None);
+    }
+
+    #[test]
+    fn only_active_runs_can_be_cancelled() {
+        let run_id = format!("active-{}", nanoid::nanoid!(8));
+        assert!(!cancel(&run_id));
+
+        let registration = register(&run_id);
+        assert!(!registration.is_cancelled());
+        assert!(cancel(&run_id));
+        assert!(registration.is_cancelled());
+    }
+}
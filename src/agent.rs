@@ -0,0 +1,222 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use crate::{
+    config::ProcessToObserve,
+    data_access::cpu_metrics::{self, CpuMetrics, CpuMetricsDao},
+    metrics_logger::{self, LoggingOptions},
+    secrets,
+};
+use std::{collections::VecDeque, time::Duration};
+use tokio_util::sync::CancellationToken;
+
+/// The maximum time to wait between attempts to reach the server when it's unavailable.
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Metrics waiting to be pushed to the server. When the server is unreachable metrics are kept
+/// here until it comes back, the oldest metrics are dropped if the buffer fills up.
+pub struct MetricsBuffer {
+    metrics: VecDeque<CpuMetrics>,
+    capacity: usize,
+}
+impl MetricsBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            metrics: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.metrics.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.metrics.is_empty()
+    }
+
+    pub fn extend(&mut self, metrics: Vec<CpuMetrics>) {
+        self.metrics.extend(metrics);
+
+        let overflow = self.metrics.len().saturating_sub(self.capacity);
+        if overflow > 0 {
+            tracing::warn!("Agent buffer full, dropping {overflow} of the oldest metrics");
+            self.metrics.drain(..overflow);
+        }
+    }
+
+    /// Pushes buffered metrics to the server in the order they were logged, stopping at the first
+    /// failure so nothing is lost.
+    ///
+    /// # Returns
+    ///
+    /// The number of metrics pushed or an error if the server couldn't be reached.
+    pub async fn flush(&mut self, dao: &dyn CpuMetricsDao) -> anyhow::Result<usize> {
+        let mut pushed = 0;
+        while let Some(metrics) = self.metrics.front() {
+            dao.persist(metrics).await?;
+            self.metrics.pop_front();
+            pushed += 1;
+        }
+        Ok(pushed)
+    }
+}
+
+/// Samples local processes and streams the metrics to a central cardamon server until the
+/// cancellation token is cancelled. Metrics are attributed to the given run so a single run can
+/// combine metrics from several machines.
+///
+/// # Arguments
+///
+/// * `run_id` - The id of the run on the central server these metrics belong to
+/// * `processes_to_observe` - The local processes to sample
+/// * `server_url` - The base url of the cardamon server
+/// * `flush_interval` - How often metrics are pushed to the server
+/// * `buffer_capacity` - The maximum number of metrics held locally while the server is down
+/// * `token` - Cancel this token to stop the agent, a final flush is attempted before returning
+pub async fn run_agent(
+    run_id: &str,
+    processes_to_observe: &[ProcessToObserve],
+    server_url: &str,
+    flush_interval: Duration,
+    buffer_capacity: usize,
+    token: CancellationToken,
+) -> anyhow::Result<()> {
+    let dao = cpu_metrics::RemoteDao::new(server_url);
+    let stop_handle =
+        metrics_logger::start_logging(processes_to_observe, LoggingOptions::default())?;
+    let mut buffer = MetricsBuffer::new(buffer_capacity);
+    let mut backoff = flush_interval;
+    let redacted_url = secrets::redact_url(server_url);
+
+    loop {
+        tokio::select! {
+            _ = token.cancelled() => break,
+            _ = tokio::time::sleep(backoff) => {}
+        }
+
+        buffer.extend(collect(&stop_handle, run_id));
+        match buffer.flush(&dao).await {
+            Ok(pushed) => {
+                tracing::debug!("Pushed {pushed} metrics to {redacted_url}");
+                backoff = flush_interval;
+            }
+            Err(err) => {
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+                tracing::warn!(
+                    "Unable to reach {redacted_url}, {} metrics buffered, retrying in {:?}: {}",
+                    buffer.len(),
+                    backoff,
+                    secrets::redact_url_in(&err.to_string(), server_url)
+                );
+            }
+        }
+    }
+
+    // stopping flushes the samples the collectors are still holding into the log
+    match stop_handle.stop().await {
+        Ok(metrics_log) => buffer.extend(
+            metrics_log
+                .get_metrics()
+                .iter()
+                .map(|metrics| metrics.into_data_access(run_id))
+                .collect(),
+        ),
+        Err(err) => tracing::warn!("{err}"),
+    }
+    if let Err(err) = buffer.flush(&dao).await {
+        tracing::error!("Exiting with {} unsent metrics: {err}", buffer.len());
+    }
+
+    Ok(())
+}
+
+fn collect(stop_handle: &metrics_logger::StopHandle, run_id: &str) -> Vec<CpuMetrics> {
+    stop_handle
+        .drain_metrics()
+        .iter()
+        .map(|metrics| metrics.into_data_access(run_id))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        data_access::TimeRange,
+        error::{CardamonError, Result},
+    };
+    use async_trait::async_trait;
+    use std::sync::Mutex;
+
+    /// Accepts a fixed number of metrics then fails as though the server went away.
+    struct FlakyDao {
+        accept: Mutex<usize>,
+        persisted: Mutex<Vec<i64>>,
+    }
+    #[async_trait]
+    impl CpuMetricsDao for FlakyDao {
+        async fn fetch_within(&self, _: &str, _: i64, _: i64) -> Result<Vec<CpuMetrics>> {
+            unimplemented!()
+        }
+
+        async fn fetch_within_ranges(&self, _: &[TimeRange]) -> Result<Vec<CpuMetrics>> {
+            unimplemented!()
+        }
+
+        async fn persist(&self, metrics: &CpuMetrics) -> Result<()> {
+            let mut accept = self.accept.lock().unwrap();
+            if *accept == 0 {
+                return Err(CardamonError::Io {
+                    context: "Error persisting cpu metrics to remote server".to_string(),
+                    source: std::io::ErrorKind::ConnectionRefused.into(),
+                });
+            }
+            *accept -= 1;
+            self.persisted.lock().unwrap().push(metrics.timestamp);
+            Ok(())
+        }
+    }
+
+    fn metrics(timestamp: i64) -> CpuMetrics {
+        CpuMetrics::new("1", "1337", "yarn", 50.0, 0.0, 4, timestamp)
+    }
+
+    #[tokio::test]
+    async fn unsent_metrics_are_kept_until_the_server_returns() -> anyhow::Result<()> {
+        let dao = FlakyDao {
+            accept: Mutex::new(2),
+            persisted: Mutex::new(vec![]),
+        };
+        let mut buffer = MetricsBuffer::new(10);
+        buffer.extend(vec![metrics(1), metrics(2), metrics(3)]);
+
+        assert!(buffer.flush(&dao).await.is_err());
+        assert_eq!(buffer.len(), 1);
+
+        // server comes back
+        *dao.accept.lock().unwrap() = 10;
+        buffer.extend(vec![metrics(4)]);
+        assert_eq!(buffer.flush(&dao).await?, 2);
+        assert!(buffer.is_empty());
+        assert_eq!(*dao.persisted.lock().unwrap(), vec![1, 2, 3, 4]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn oldest_metrics_are_dropped_when_the_buffer_is_full() {
+        let mut buffer = MetricsBuffer::new(2);
+        buffer.extend(vec![metrics(1), metrics(2), metrics(3)]);
+
+        let timestamps = buffer
+            .metrics
+            .iter()
+            .map(|m| m.timestamp)
+            .collect::<Vec<_>>();
+        assert_eq!(timestamps, vec![2, 3]);
+    }
+}
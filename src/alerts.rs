@@ -0,0 +1,207 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Alert rules checked against the metrics of a live run, so `card live` can act as a
+//! lightweight energy watchdog.
+
+use crate::{
+    config::{AlertMetric, AlertRule, Cpu},
+    data_access::cpu_metrics::CpuMetrics,
+};
+use anyhow::anyhow;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// An alert rule whose condition held for long enough.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Alert {
+    pub name: String,
+    /// The process the rule watched, None if it watched every observed process.
+    pub process: Option<String>,
+    pub value: f64,
+    pub above: f64,
+    /// When the alert fired, unix timestamp in milliseconds.
+    pub timestamp: i64,
+}
+impl Alert {
+    /// A short human readable description of the alert, e.g. for logs and chat integrations.
+    pub fn to_text(&self) -> String {
+        format!(
+            "Alert {}: {}{:.2} is above {:.2}",
+            self.name,
+            self.process
+                .as_ref()
+                .map(|process| format!("{process} "))
+                .unwrap_or_default(),
+            self.value,
+            self.above
+        )
+    }
+}
+
+/// Tracks how long each rule's condition has held. A rule fires once when its condition has held
+/// for its duration and can only fire again after the metric drops back below the threshold.
+#[derive(Debug)]
+pub struct AlertMonitor {
+    rules: Vec<AlertRule>,
+    cpu: Option<Cpu>,
+    /// When each rule's condition started holding and whether it has fired since.
+    breaches: Vec<Option<(i64, bool)>>,
+}
+impl AlertMonitor {
+    /// Fails if a power rule is given without a CPU to model power from.
+    pub fn new(rules: &[AlertRule], cpu: Option<&Cpu>) -> anyhow::Result<Self> {
+        if cpu.is_none() {
+            if let Some(rule) = rules.iter().find(|rule| rule.metric == AlertMetric::Power) {
+                return Err(anyhow!(
+                    "Alert {} watches power which needs a [cpu] section",
+                    rule.name
+                ));
+            }
+        }
+
+        Ok(Self {
+            rules: rules.to_vec(),
+            cpu: cpu.cloned(),
+            breaches: vec![None; rules.len()],
+        })
+    }
+
+    /// Checks newly collected metrics against every rule, one sample time at a time.
+    ///
+    /// # Returns
+    ///
+    /// The alerts which fired.
+    pub fn observe(&mut self, metrics: &[CpuMetrics]) -> Vec<Alert> {
+        let mut by_timestamp: BTreeMap<i64, Vec<&CpuMetrics>> = BTreeMap::new();
+        for metrics in metrics.iter() {
+            by_timestamp
+                .entry(metrics.timestamp)
+                .or_default()
+                .push(metrics);
+        }
+
+        let mut alerts = vec![];
+        for (timestamp, samples) in by_timestamp.into_iter() {
+            for (rule, breach) in self.rules.iter().zip(self.breaches.iter_mut()) {
+                let value = value_of(rule, self.cpu.as_ref(), &samples);
+                if value <= rule.above {
+                    *breach = None;
+                    continue;
+                }
+
+                let (since, fired) = breach.get_or_insert((timestamp, false));
+                if !*fired && timestamp - *since >= rule.duration as i64 * 1000 {
+                    *fired = true;
+                    alerts.push(Alert {
+                        name: rule.name.clone(),
+                        process: rule.process.clone(),
+                        value,
+                        above: rule.above,
+                        timestamp,
+                    });
+                }
+            }
+        }
+
+        alerts
+    }
+}
+
+/// The value of the rule's metric across the samples taken at one time.
+fn value_of(rule: &AlertRule, cpu: Option<&Cpu>, samples: &[&CpuMetrics]) -> f64 {
+    samples
+        .iter()
+        .filter(|metrics| {
+            rule.process
+                .as_ref()
+                .is_none_or(|process| *process == metrics.process_name)
+        })
+        .map(|metrics| match (rule.metric, cpu) {
+            (AlertMetric::Power, Some(cpu)) => {
+                cpu.total_tdp() * (metrics.cpu_usage / 100.0) / metrics.core_count.max(1) as f64
+            }
+            (AlertMetric::Power, None) => 0.0,
+            (AlertMetric::CpuUsage, _) => metrics.cpu_usage,
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::PowerModel;
+
+    fn rule(metric: AlertMetric, process: Option<&str>, above: f64, duration: u64) -> AlertRule {
+        AlertRule {
+            name: "test".to_string(),
+            metric,
+            process: process.map(String::from),
+            above,
+            duration,
+        }
+    }
+
+    #[test]
+    fn alerts_fire_once_the_condition_has_held_long_enough() -> anyhow::Result<()> {
+        let mut monitor = AlertMonitor::new(
+            &[rule(AlertMetric::CpuUsage, Some("server"), 50.0, 2)],
+            None,
+        )?;
+        let sample = |process_name: &str, cpu_usage, timestamp| {
+            CpuMetrics::new("1", "1", process_name, cpu_usage, 0.0, 4, timestamp)
+        };
+
+        // other processes are ignored
+        assert!(monitor.observe(&[sample("db", 90.0, 0)]).is_empty());
+
+        let alerts = monitor.observe(&[
+            sample("server", 60.0, 1000),
+            sample("server", 70.0, 2000),
+            sample("server", 80.0, 3000),
+        ]);
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].value, 80.0);
+        assert_eq!(alerts[0].timestamp, 3000);
+        assert_eq!(
+            alerts[0].to_text(),
+            "Alert test: server 80.00 is above 50.00"
+        );
+
+        // only fires again after recovering
+        assert!(monitor.observe(&[sample("server", 80.0, 4000)]).is_empty());
+        assert!(monitor.observe(&[sample("server", 10.0, 5000)]).is_empty());
+        assert!(monitor.observe(&[sample("server", 80.0, 6000)]).is_empty());
+        assert_eq!(monitor.observe(&[sample("server", 80.0, 8000)]).len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn power_alerts_need_a_cpu() -> anyhow::Result<()> {
+        let rules = [rule(AlertMetric::Power, None, 20.0, 0)];
+        assert!(AlertMonitor::new(&rules, None).is_err());
+
+        let cpu = Cpu {
+            name: "test".to_string(),
+            tdp: 100.0,
+            sockets: 1,
+            model: PowerModel::Rab,
+            max_frequency: None,
+        };
+        let mut monitor = AlertMonitor::new(&rules, Some(&cpu))?;
+
+        // two processes each using a full core of a 4 core cpu, 25 W each
+        let alerts = monitor.observe(&[
+            CpuMetrics::new("1", "1", "server", 100.0, 0.0, 4, 0),
+            CpuMetrics::new("1", "2", "db", 100.0, 0.0, 4, 0),
+        ]);
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].value, 50.0);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,229 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! SVG shields showing the energy or emissions of a scenario's latest run, or how it's trending,
+//! for embedding in READMEs.
+
+use crate::{config::Config, data_access::DataAccessService, model};
+use serde::Deserialize;
+use std::{fmt, str::FromStr};
+
+/// The latest run is compared against the average of up to this many runs before it.
+const PREVIOUS_RUNS: u32 = 4;
+
+/// Changes smaller than this percentage are shown as flat.
+const TREND_THRESHOLD: f64 = 5.0;
+
+/// Roughly how wide a character of 11px Verdana is, shields are sized to their text.
+const CHAR_WIDTH: f64 = 6.5;
+const PADDING: f64 = 10.0;
+
+const GREEN: &str = "#4c1";
+const RED: &str = "#e05d44";
+const BLUE: &str = "#007ec6";
+const GREY: &str = "#9f9f9f";
+
+/// What the badge shows.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Metric {
+    /// Wh per iteration.
+    #[default]
+    Energy,
+    /// gCO2e per iteration at the configured grid intensity.
+    Co2,
+    /// Change in energy against the previous runs.
+    Trend,
+}
+impl FromStr for Metric {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "energy" => Ok(Metric::Energy),
+            "co2" => Ok(Metric::Co2),
+            "trend" => Ok(Metric::Trend),
+            _ => Err(anyhow::anyhow!(
+                "Unknown badge metric {s}, expected energy, co2 or trend"
+            )),
+        }
+    }
+}
+impl fmt::Display for Metric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Metric::Energy => write!(f, "energy"),
+            Metric::Co2 => write!(f, "co2"),
+            Metric::Trend => write!(f, "trend"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Badge {
+    pub label: String,
+    pub message: String,
+    pub color: &'static str,
+}
+impl Badge {
+    /// A flat shield with the label on grey and the message on the badge's colour.
+    pub fn to_svg(&self) -> String {
+        let label_width = text_width(&self.label);
+        let message_width = text_width(&self.message);
+        let width = label_width + message_width;
+        let label = escape(&self.label);
+        let message = escape(&self.message);
+        let label_x = label_width / 2.0;
+        let message_x = label_width + message_width / 2.0;
+
+        format!(
+            r##"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="20" role="img" aria-label="{label}: {message}"><title>{label}: {message}</title><linearGradient id="s" x2="0" y2="100%"><stop offset="0" stop-color="#bbb" stop-opacity=".1"/><stop offset="1" stop-opacity=".1"/></linearGradient><clipPath id="r"><rect width="{width}" height="20" rx="3" fill="#fff"/></clipPath><g clip-path="url(#r)"><rect width="{label_width}" height="20" fill="#555"/><rect x="{label_width}" width="{message_width}" height="20" fill="{color}"/><rect width="{width}" height="20" fill="url(#s)"/></g><g fill="#fff" text-anchor="middle" font-family="Verdana,Geneva,DejaVu Sans,sans-serif" font-size="11"><text x="{label_x}" y="15" fill="#010101" fill-opacity=".3">{label}</text><text x="{label_x}" y="14">{label}</text><text x="{message_x}" y="15" fill="#010101" fill-opacity=".3">{message}</text><text x="{message_x}" y="14">{message}</text></g></svg>"##,
+            color = self.color,
+        )
+    }
+}
+
+fn text_width(text: &str) -> f64 {
+    (text.chars().count() as f64 * CHAR_WIDTH + PADDING).round()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Builds the badge from the energy the system under test used per iteration, modelled with the
+/// config like `stats`. Scenarios without runs get a grey "no runs" badge rather than an error so
+/// READMEs still show something.
+pub async fn scenario_badge(
+    scenario_name: &str,
+    metric: Metric,
+    config: &Config,
+    data_access_service: &dyn DataAccessService,
+) -> crate::error::Result<Badge> {
+    let observation_dataset = data_access_service
+        .fetch_observation_dataset(vec![scenario_name], PREVIOUS_RUNS + 1)
+        .await?;
+
+    // energy per iteration of each run, oldest first
+    let mut energy_by_run = vec![];
+    for scenario_dataset in observation_dataset.by_scenario().iter() {
+        let mut run_datasets = scenario_dataset.by_run();
+        run_datasets.sort_by_key(|run_dataset| run_dataset.start_time());
+        for run_dataset in run_datasets.iter() {
+            let iterations = run_dataset.by_iterations();
+            let energy_wh = model::sut_energy_wh(config, iterations);
+            energy_by_run.push(energy_wh / iterations.len().max(1) as f64);
+        }
+    }
+
+    Ok(badge(
+        scenario_name,
+        metric,
+        &energy_by_run,
+        config.grid_intensity(),
+    ))
+}
+
+/// # Arguments
+///
+/// * `energy_by_run` - Wh per iteration of recent runs, oldest first
+/// * `grid_intensity` - gCO2/kWh
+fn badge(scenario_name: &str, metric: Metric, energy_by_run: &[f64], grid_intensity: f64) -> Badge {
+    let Some((latest, previous)) = energy_by_run.split_last() else {
+        return Badge {
+            label: scenario_name.to_string(),
+            message: String::from("no runs"),
+            color: GREY,
+        };
+    };
+
+    let change = match previous.len() {
+        0 => None,
+        n => {
+            let mean = previous.iter().sum::<f64>() / n as f64;
+            (mean > 0.0).then(|| (latest - mean) / mean * 100.0)
+        }
+    };
+    let (arrow, color) = match change {
+        Some(change) if change > TREND_THRESHOLD => ("↑", RED),
+        Some(change) if change < -TREND_THRESHOLD => ("↓", GREEN),
+        Some(_) => ("→", BLUE),
+        None => ("", BLUE),
+    };
+    let with_arrow = |message: String| match arrow {
+        "" => message,
+        arrow => format!("{message} {arrow}"),
+    };
+
+    let message = match metric {
+        Metric::Energy => with_arrow(format!("{} Wh", significant(*latest))),
+        Metric::Co2 => with_arrow(format!(
+            "{} gCO2e",
+            significant(latest / 1000.0 * grid_intensity)
+        )),
+        Metric::Trend => match change {
+            Some(change) => with_arrow(format!("{change:+.1}%")),
+            None => String::from("first run"),
+        },
+    };
+    Badge {
+        label: scenario_name.to_string(),
+        message,
+        color,
+    }
+}
+
+/// Three significant figures, so tiny and large values are both readable on a badge.
+fn significant(value: f64) -> String {
+    if value == 0.0 {
+        return String::from("0");
+    }
+    let decimals = (2 - value.abs().log10().floor() as i32).max(0) as usize;
+    format!("{value:.decimals$}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn badges_show_the_latest_run_and_its_trend() {
+        let rising = badge("basket_10", Metric::Energy, &[1.0, 1.0, 1.5], 480.0);
+        assert_eq!(rising.message, "1.50 Wh ↑");
+        assert_eq!(rising.color, RED);
+
+        let falling = badge("basket_10", Metric::Trend, &[0.02, 0.01], 480.0);
+        assert_eq!(falling.message, "-50.0% ↓");
+        assert_eq!(falling.color, GREEN);
+
+        let first = badge("basket_10", Metric::Co2, &[2.0], 500.0);
+        assert_eq!(first.message, "1.00 gCO2e");
+        assert_eq!(
+            badge("basket_10", Metric::Trend, &[2.0], 500.0).message,
+            "first run"
+        );
+
+        let none = badge("basket_10", Metric::Energy, &[], 480.0);
+        assert_eq!(none.message, "no runs");
+        assert_eq!(none.color, GREY);
+    }
+
+    #[test]
+    fn badge_text_is_escaped() {
+        let svg = Badge {
+            label: String::from("a<b"),
+            message: String::from("1 Wh"),
+            color: BLUE,
+        }
+        .to_svg();
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains("a&lt;b"));
+        assert!(!svg.contains("a<b"));
+    }
+}
@@ -0,0 +1,71 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use crate::data_access::baseline::Baseline;
+use sysinfo::System;
+use tokio::time::Duration;
+
+/// Measures the CPU usage of the whole system while nothing is being observed. The machine
+/// should be left idle, as it would be before a run, while this is measuring.
+///
+/// # Arguments
+///
+/// * `duration` - How long to measure for, longer periods smooth out background spikes
+///
+/// # Returns
+///
+/// The baseline, ready to be persisted.
+pub async fn measure(duration: Duration) -> anyhow::Result<Baseline> {
+    let start_time = now()?;
+    let mut system = System::new();
+    system.refresh_cpu_usage();
+
+    // cpu usage is calculated from the difference between refreshes so the first sample is taken
+    // after sleeping
+    let mut samples = vec![];
+    let deadline = tokio::time::Instant::now() + duration;
+    while tokio::time::Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(1000)).await;
+        system.refresh_cpu_usage();
+        samples.push(system.global_cpu_info().cpu_usage() as f64);
+    }
+    let stop_time = now()?;
+
+    let cpu_usage = if samples.is_empty() {
+        0.0
+    } else {
+        samples.iter().sum::<f64>() / samples.len() as f64
+    };
+    let core_count = system.physical_core_count().unwrap_or(0) as i64;
+
+    Ok(Baseline::new(
+        &nanoid::nanoid!(5),
+        start_time,
+        stop_time,
+        cpu_usage,
+        core_count,
+    ))
+}
+
+fn now() -> anyhow::Result<i64> {
+    Ok(std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_millis() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn baseline_can_be_measured() -> anyhow::Result<()> {
+        let baseline = measure(Duration::from_millis(1500)).await?;
+
+        assert!(baseline.stop_time - baseline.start_time >= 1500);
+        assert!((0.0..=100.0).contains(&baseline.cpu_usage));
+        Ok(())
+    }
+}
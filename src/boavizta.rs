@@ -0,0 +1,219 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use crate::{
+    config::{Boavizta, Cloud},
+    data_access::run_impact::RunImpact,
+    dataset::IterationWithMetrics,
+};
+use anyhow::{anyhow, Context};
+use serde_json::{json, Value};
+
+/// The server to ask Boavizta about, either one of its archetypes or a cloud instance type.
+#[derive(Debug, PartialEq)]
+enum Server<'a> {
+    Archetype(&'a str),
+    CloudInstance {
+        provider: &'a str,
+        instance: &'a str,
+    },
+}
+
+/// Works out the lifecycle impact of the server a run executed on by asking the Boavizta API.
+///
+/// # Arguments
+///
+/// * `boavizta` - Where to find the API and how to describe the server
+/// * `cloud` - The cloud instance the run executed on, used when no archetype is configured
+/// * `run_id` - The run the impact belongs to
+/// * `iterations` - Every scenario iteration in the run, used for the duration and load
+///
+/// # Returns
+///
+/// The manufacturing and usage impact of the server over the duration of the run.
+pub async fn fetch_impact(
+    boavizta: &Boavizta,
+    cloud: Option<&Cloud>,
+    run_id: &str,
+    iterations: &[&IterationWithMetrics],
+) -> anyhow::Result<RunImpact> {
+    let server = match (&boavizta.archetype, cloud) {
+        (Some(archetype), _) => Server::Archetype(archetype),
+        (None, Some(cloud)) => Server::CloudInstance {
+            provider: &cloud.provider,
+            instance: &cloud.instance,
+        },
+        (None, None) => {
+            return Err(anyhow!(
+                "Boavizta needs either an archetype or a [cloud] instance to describe the server"
+            ))
+        }
+    };
+
+    let duration_hours = duration_hours(iterations);
+    let (path, body) = request(
+        &server,
+        boavizta.usage_location.as_deref(),
+        workload(iterations),
+    );
+
+    let response = reqwest::Client::new()
+        .post(format!(
+            "{}{path}",
+            boavizta.url.strip_suffix('/').unwrap_or(&boavizta.url)
+        ))
+        .query(&[
+            ("verbose", "false"),
+            ("criteria", "gwp"),
+            ("duration", &duration_hours.to_string()),
+        ])
+        .json(&body)
+        .send()
+        .await?
+        .error_for_status()
+        .context("Error fetching server impact from Boavizta")?
+        .json::<Value>()
+        .await?;
+
+    let (manufacture_gwp, use_gwp) = parse_impact(&response)?;
+    Ok(RunImpact::new(
+        run_id,
+        duration_hours,
+        manufacture_gwp,
+        use_gwp,
+    ))
+}
+
+fn request(server: &Server, usage_location: Option<&str>, workload: f64) -> (&'static str, Value) {
+    let mut usage = json!({ "time_workload": workload });
+    if let Some(usage_location) = usage_location {
+        usage["usage_location"] = json!(usage_location);
+    }
+
+    match server {
+        Server::Archetype(archetype) => (
+            "/v1/server/",
+            json!({ "model": { "archetype": archetype }, "usage": usage }),
+        ),
+        Server::CloudInstance { provider, instance } => (
+            "/v1/cloud/instance",
+            json!({ "provider": provider, "instance_type": instance, "usage": usage }),
+        ),
+    }
+}
+
+/// Extracts the manufacturing (embedded) and use global warming potential from a Boavizta
+/// response.
+fn parse_impact(response: &Value) -> anyhow::Result<(f64, f64)> {
+    let gwp = &response["impacts"]["gwp"];
+    let value_of = |phase: &str| {
+        gwp[phase]["value"]
+            .as_f64()
+            .context(format!("Boavizta response has no {phase} gwp: {gwp}"))
+    };
+
+    Ok((value_of("embedded")?, value_of("use")?))
+}
+
+/// Time from the start of the first iteration to the end of the last in hours.
+fn duration_hours(iterations: &[&IterationWithMetrics]) -> f64 {
+    let start = iterations
+        .iter()
+        .map(|it| it.scenario_iteration().start_time)
+        .min();
+    let stop = iterations
+        .iter()
+        .map(|it| it.scenario_iteration().stop_time)
+        .max();
+
+    match (start, stop) {
+        (Some(start), Some(stop)) => (stop - start) as f64 / 3_600_000.0,
+        _ => 0.0,
+    }
+}
+
+/// The average load on the machine during the iterations as a percentage of all its cores,
+/// assuming each metrics sample covers one second.
+fn workload(iterations: &[&IterationWithMetrics]) -> f64 {
+    let core_count = iterations
+        .iter()
+        .flat_map(|it| it.cpu_metrics())
+        .map(|metrics| metrics.core_count)
+        .max()
+        .unwrap_or(1)
+        .max(1) as f64;
+
+    let duration_secs = duration_hours(iterations) * 3600.0;
+    if duration_secs <= 0.0 {
+        return 0.0;
+    }
+
+    let cpu_secs = iterations
+        .iter()
+        .flat_map(|it| it.cpu_metrics())
+        .map(|metrics| metrics.cpu_usage / 100.0)
+        .sum::<f64>();
+
+    (100.0 * cpu_secs / (duration_secs * core_count)).clamp(0.0, 100.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_access::{cpu_metrics::CpuMetrics, scenario_iteration::ScenarioIteration};
+
+    #[test]
+    fn requests_describe_the_server() {
+        let (path, body) = request(&Server::Archetype("dellR740"), Some("FRA"), 25.0);
+        assert_eq!(path, "/v1/server/");
+        assert_eq!(body["model"]["archetype"], "dellR740");
+        assert_eq!(body["usage"]["usage_location"], "FRA");
+        assert_eq!(body["usage"]["time_workload"], 25.0);
+
+        let server = Server::CloudInstance {
+            provider: "aws",
+            instance: "m5.large",
+        };
+        let (path, body) = request(&server, None, 25.0);
+        assert_eq!(path, "/v1/cloud/instance");
+        assert_eq!(body["instance_type"], "m5.large");
+        assert!(body["usage"].get("usage_location").is_none());
+    }
+
+    #[test]
+    fn impacts_can_be_parsed() -> anyhow::Result<()> {
+        let response = json!({
+            "impacts": {
+                "gwp": {
+                    "embedded": { "value": 0.0143, "min": 0.01, "max": 0.02 },
+                    "use": { "value": 0.0021, "min": 0.001, "max": 0.003 },
+                    "unit": "kgCO2eq"
+                }
+            }
+        });
+        assert_eq!(parse_impact(&response)?, (0.0143, 0.0021));
+
+        let response = json!({ "impacts": { "gwp": { "embedded": "not implemented" } } });
+        assert!(parse_impact(&response).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn workload_is_the_share_of_all_cores_used() {
+        // a single core of a 4 core machine for 10 seconds
+        let metrics = (0..10)
+            .map(|i| CpuMetrics::new("1", "1", "yarn", 100.0, 0.0, 4, i * 1000))
+            .collect();
+        let iteration = IterationWithMetrics::new(
+            ScenarioIteration::new("1", "basket_10", 1, 0, 10_000),
+            metrics,
+            vec![],
+        );
+
+        assert!((workload(&[&iteration]) - 25.0).abs() < 1e-9);
+        assert!((duration_hours(&[&iteration]) - 10.0 / 3600.0).abs() < 1e-12);
+    }
+}
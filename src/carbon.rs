@@ -0,0 +1,421 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Carbon intensity and generation mix of the electricity grid, fetched from the GB Carbon
+//! Intensity API (https://carbonintensity.org.uk). Used to start runs when the grid is greenest
+//! and to record how the electricity used by a run was generated.
+//!
+//! Also holds the average intensity of national grids, used to work out what a run would have
+//! emitted in another region.
+
+use crate::config::CarbonIntensity;
+use anyhow::{anyhow, Context};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde_json::Value;
+use std::{str::FromStr, time::Duration};
+
+/// The format of timestamps used by the Carbon Intensity API, e.g. "2024-07-11T10:30Z".
+const TIME_FORMAT: &str = "%Y-%m-%dT%H:%MZ";
+
+/// Average carbon intensity of national grids over 2023 (gCO2/kWh), from Ember's yearly
+/// electricity data.
+const GRID_INTENSITY: [(&str, f64); 20] = [
+    ("AU", 510.0),
+    ("BR", 98.0),
+    ("CA", 128.0),
+    ("CN", 582.0),
+    ("DE", 381.0),
+    ("ES", 174.0),
+    ("FI", 79.0),
+    ("FR", 56.0),
+    ("GB", 238.0),
+    ("IE", 282.0),
+    ("IN", 713.0),
+    ("IT", 331.0),
+    ("JP", 485.0),
+    ("NL", 268.0),
+    ("NO", 30.0),
+    ("PL", 662.0),
+    ("SE", 41.0),
+    ("SG", 470.0),
+    ("US", 369.0),
+    ("ZA", 707.0),
+];
+
+/// Average carbon intensity of electricity generated worldwide over 2023 (gCO2/kWh), from Ember.
+pub const GLOBAL_AVERAGE_INTENSITY: f64 = 480.0;
+
+/// A region to compare emissions in, either a country code with a known average intensity, e.g.
+/// "FR", or a name with an explicit intensity in gCO2/kWh, e.g. "eu-west-1=300".
+#[derive(Debug, Clone, PartialEq)]
+pub struct Region {
+    pub name: String,
+    /// gCO2/kWh
+    pub intensity: f64,
+}
+impl Region {
+    /// Emissions from generating `energy_wh` of electricity in this region (gCO2e).
+    pub fn emissions_g(&self, energy_wh: f64) -> f64 {
+        energy_wh / 1000.0 * self.intensity
+    }
+}
+impl FromStr for Region {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((name, intensity)) => Ok(Region {
+                name: name.trim().to_string(),
+                intensity: intensity
+                    .trim()
+                    .parse()
+                    .context(format!("Invalid carbon intensity for region {name}"))?,
+            }),
+            None => {
+                let name = s.trim().to_uppercase();
+                GRID_INTENSITY
+                    .iter()
+                    .find(|(code, _)| *code == name)
+                    .map(|(_, intensity)| Region {
+                        name,
+                        intensity: *intensity,
+                    })
+                    .ok_or(anyhow!(
+                        "Unknown region {s}, give its intensity instead, e.g. {s}=250"
+                    ))
+            }
+        }
+    }
+}
+
+/// Forecast carbon intensity of the grid over a half hour period.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Forecast {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    /// gCO2/kWh
+    pub intensity: f64,
+}
+
+/// When to start a run so it uses the greenest electricity within a window.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schedule {
+    pub start: DateTime<Utc>,
+    /// Forecast intensity when the run starts (gCO2/kWh).
+    pub intensity: f64,
+    /// Forecast intensity right now (gCO2/kWh), if the forecast covers it.
+    pub current: Option<f64>,
+}
+impl Schedule {
+    /// How long to wait before starting the run.
+    pub fn wait(&self, now: DateTime<Utc>) -> Duration {
+        (self.start - now).to_std().unwrap_or_default()
+    }
+
+    /// A description of the decision, recorded against the run.
+    pub fn describe(&self, now: DateTime<Utc>) -> String {
+        let current = self
+            .current
+            .map(|current| format!(" (now {current:.0} gCO2/kWh)"))
+            .unwrap_or_default();
+
+        let wait = self.wait(now).as_secs() / 60;
+        if wait == 0 {
+            format!(
+                "Started immediately, forecast {:.0} gCO2/kWh is the lowest in the window{current}",
+                self.intensity
+            )
+        } else {
+            format!(
+                "Waited {}h {}m until {} for forecast {:.0} gCO2/kWh{current}",
+                wait / 60,
+                wait % 60,
+                self.start.format("%H:%M UTC"),
+                self.intensity
+            )
+        }
+    }
+}
+
+/// Fetches the forecast carbon intensity of the grid for the next 48 hours, for the configured
+/// region if there is one.
+pub async fn fetch_forecast(
+    carbon_intensity: &CarbonIntensity,
+    from: DateTime<Utc>,
+) -> anyhow::Result<Vec<Forecast>> {
+    let base_url = carbon_intensity
+        .url
+        .strip_suffix('/')
+        .unwrap_or(&carbon_intensity.url);
+    let from = from.format(TIME_FORMAT);
+    let url = match &carbon_intensity.postcode {
+        Some(postcode) => format!("{base_url}/regional/intensity/{from}/fw48h/postcode/{postcode}"),
+        None => format!("{base_url}/intensity/{from}/fw48h"),
+    };
+
+    let response = reqwest::Client::new()
+        .get(url)
+        .header("Accept", "application/json")
+        .send()
+        .await?
+        .error_for_status()
+        .context("Error fetching carbon intensity forecast")?
+        .json::<Value>()
+        .await?;
+
+    parse_forecast(&response)
+}
+
+/// Fetches the average share of each fuel in the grid's generation between two times, for the
+/// configured region if there is one.
+///
+/// # Returns
+///
+/// Each fuel with its percentage of generation, e.g. ("wind", 35.2).
+pub async fn fetch_generation_mix(
+    carbon_intensity: &CarbonIntensity,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> anyhow::Result<Vec<(String, f64)>> {
+    let base_url = carbon_intensity
+        .url
+        .strip_suffix('/')
+        .unwrap_or(&carbon_intensity.url);
+    let (from, to) = (from.format(TIME_FORMAT), to.format(TIME_FORMAT));
+    let url = match &carbon_intensity.postcode {
+        Some(postcode) => {
+            format!("{base_url}/regional/intensity/{from}/{to}/postcode/{postcode}")
+        }
+        None => format!("{base_url}/generation/{from}/{to}"),
+    };
+
+    let response = reqwest::Client::new()
+        .get(url)
+        .header("Accept", "application/json")
+        .send()
+        .await?
+        .error_for_status()
+        .context("Error fetching generation mix")?
+        .json::<Value>()
+        .await?;
+
+    parse_generation_mix(&response)
+}
+
+/// The half hour periods of a national or regional response.
+fn periods(response: &Value) -> anyhow::Result<&Vec<Value>> {
+    // regional responses nest the periods inside the region
+    match &response["data"] {
+        Value::Array(periods) => Ok(periods),
+        region => region["data"]
+            .as_array()
+            .context("Unexpected carbon intensity response"),
+    }
+}
+
+/// Averages the generation mix of every period in a response, fuels keep the order of the first
+/// period.
+fn parse_generation_mix(response: &Value) -> anyhow::Result<Vec<(String, f64)>> {
+    let periods = periods(response)?;
+    let mut mix: Vec<(String, f64)> = vec![];
+    for period in periods.iter() {
+        let fuels = period["generationmix"]
+            .as_array()
+            .context("Period is missing a generation mix")?;
+        for fuel in fuels.iter() {
+            let name = fuel["fuel"].as_str().context("Fuel is missing a name")?;
+            let perc = fuel["perc"]
+                .as_f64()
+                .context("Fuel is missing a percentage")?;
+            match mix.iter_mut().find(|(existing, _)| existing == name) {
+                Some((_, total)) => *total += perc,
+                None => mix.push((name.to_string(), perc)),
+            }
+        }
+    }
+
+    let count = periods.len().max(1) as f64;
+    Ok(mix
+        .into_iter()
+        .map(|(fuel, total)| (fuel, total / count))
+        .collect())
+}
+
+/// Reads the periods of a national or regional forecast response.
+fn parse_forecast(response: &Value) -> anyhow::Result<Vec<Forecast>> {
+    periods(response)?
+        .iter()
+        .map(|period| {
+            let time = |key: &str| -> anyhow::Result<DateTime<Utc>> {
+                let time = period[key]
+                    .as_str()
+                    .ok_or(anyhow!("Forecast period is missing {key}"))?;
+                Ok(NaiveDateTime::parse_from_str(time, TIME_FORMAT)
+                    .context(format!("Invalid time in forecast: {time}"))?
+                    .and_utc())
+            };
+
+            Ok(Forecast {
+                from: time("from")?,
+                to: time("to")?,
+                intensity: period["intensity"]["forecast"]
+                    .as_f64()
+                    .context("Forecast period is missing an intensity")?,
+            })
+        })
+        .collect()
+}
+
+/// Finds the greenest time to start a run within `window` of now. Earlier starts win ties so
+/// runs aren't delayed for nothing.
+pub fn greenest_start(
+    forecasts: &[Forecast],
+    now: DateTime<Utc>,
+    window: Duration,
+) -> Option<Schedule> {
+    let window_end = now + chrono::Duration::from_std(window).ok()?;
+    let current = forecasts
+        .iter()
+        .find(|forecast| forecast.from <= now && now < forecast.to)
+        .map(|forecast| forecast.intensity);
+
+    forecasts
+        .iter()
+        .filter(|forecast| forecast.to > now && forecast.from <= window_end)
+        .map(|forecast| Schedule {
+            start: forecast.from.max(now),
+            intensity: forecast.intensity,
+            current,
+        })
+        .min_by(|a, b| {
+            a.intensity
+                .total_cmp(&b.intensity)
+                .then(a.start.cmp(&b.start))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn at(time: &str) -> DateTime<Utc> {
+        NaiveDateTime::parse_from_str(time, TIME_FORMAT)
+            .unwrap()
+            .and_utc()
+    }
+
+    #[test]
+    fn national_and_regional_forecasts_can_be_parsed() -> anyhow::Result<()> {
+        let period = json!({
+            "from": "2024-07-11T10:00Z",
+            "to": "2024-07-11T10:30Z",
+            "intensity": { "forecast": 120, "index": "moderate" }
+        });
+        let expected = vec![Forecast {
+            from: at("2024-07-11T10:00Z"),
+            to: at("2024-07-11T10:30Z"),
+            intensity: 120.0,
+        }];
+
+        let national = json!({ "data": [period.clone()] });
+        assert_eq!(parse_forecast(&national)?, expected);
+
+        let regional = json!({ "data": { "regionid": 3, "data": [period] } });
+        assert_eq!(parse_forecast(&regional)?, expected);
+
+        assert!(parse_forecast(&json!({ "error": "oops" })).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn generation_mix_is_averaged_over_the_run() -> anyhow::Result<()> {
+        let period = |wind, gas| {
+            json!({
+                "from": "2024-07-11T10:00Z",
+                "to": "2024-07-11T10:30Z",
+                "generationmix": [
+                    { "fuel": "wind", "perc": wind },
+                    { "fuel": "gas", "perc": gas }
+                ]
+            })
+        };
+
+        let national = json!({ "data": [period(40.0, 60.0), period(60.0, 40.0)] });
+        assert_eq!(
+            parse_generation_mix(&national)?,
+            vec![("wind".to_string(), 50.0), ("gas".to_string(), 50.0)]
+        );
+
+        let regional = json!({ "data": { "regionid": 3, "data": [period(30.0, 70.0)] } });
+        assert_eq!(
+            parse_generation_mix(&regional)?,
+            vec![("wind".to_string(), 30.0), ("gas".to_string(), 70.0)]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn regions_are_known_countries_or_explicit_intensities() -> anyhow::Result<()> {
+        let france = "fr".parse::<Region>()?;
+        assert_eq!(france.name, "FR");
+        assert_eq!(france.intensity, 56.0);
+        assert_eq!(france.emissions_g(500.0), 28.0);
+
+        let custom = "eu-west-1=300".parse::<Region>()?;
+        assert_eq!(custom.name, "eu-west-1");
+        assert_eq!(custom.intensity, 300.0);
+
+        assert!("XX".parse::<Region>().is_err());
+        assert!("XX=lots".parse::<Region>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn runs_start_at_the_greenest_time_in_the_window() {
+        let forecast = |from: &str, to: &str, intensity| Forecast {
+            from: at(from),
+            to: at(to),
+            intensity,
+        };
+        let forecasts = vec![
+            forecast("2024-07-11T10:00Z", "2024-07-11T10:30Z", 200.0),
+            forecast("2024-07-11T10:30Z", "2024-07-11T11:00Z", 150.0),
+            forecast("2024-07-11T11:00Z", "2024-07-11T11:30Z", 150.0),
+            forecast("2024-07-11T11:30Z", "2024-07-11T12:00Z", 50.0),
+        ];
+        let now = at("2024-07-11T10:10Z");
+
+        // the greenest period is outside the window, the earliest of the rest wins
+        let schedule = greenest_start(&forecasts, now, Duration::from_secs(3600)).unwrap();
+        assert_eq!(schedule.start, at("2024-07-11T10:30Z"));
+        assert_eq!(schedule.intensity, 150.0);
+        assert_eq!(schedule.current, Some(200.0));
+        assert_eq!(
+            schedule.describe(now),
+            "Waited 0h 20m until 10:30 UTC for forecast 150 gCO2/kWh (now 200 gCO2/kWh)"
+        );
+
+        let schedule = greenest_start(&forecasts, now, Duration::from_secs(2 * 3600)).unwrap();
+        assert_eq!(schedule.start, at("2024-07-11T11:30Z"));
+
+        // already in the greenest period
+        let now = at("2024-07-11T11:40Z");
+        let schedule = greenest_start(&forecasts, now, Duration::from_secs(3600)).unwrap();
+        assert_eq!(schedule.start, now);
+        assert_eq!(schedule.wait(now), Duration::ZERO);
+
+        assert_eq!(
+            greenest_start(
+                &forecasts,
+                at("2024-07-11T13:00Z"),
+                Duration::from_secs(3600)
+            ),
+            None
+        );
+    }
+}
@@ -0,0 +1,172 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use anyhow::Context;
+use std::fmt::Display;
+
+/// Embodied carbon of a typical cloud host (kgCO2e) shared evenly between its vCPUs. Taken from
+/// the Cloud Carbon Footprint methodology which assumes a 48 core (96 vCPU) host.
+const EMBODIED_CARBON_PER_VCPU: f64 = 1200.0 / 96.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CloudProvider {
+    Aws,
+    Gcp,
+    Azure,
+}
+impl CloudProvider {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CloudProvider::Aws => "aws",
+            CloudProvider::Gcp => "gcp",
+            CloudProvider::Azure => "azure",
+        }
+    }
+
+    /// Power usage effectiveness of the provider's data centres, as published by each provider
+    /// and used by Cloud Carbon Footprint.
+    pub fn pue(&self) -> f64 {
+        match self {
+            CloudProvider::Aws => 1.135,
+            CloudProvider::Gcp => 1.1,
+            CloudProvider::Azure => 1.185,
+        }
+    }
+}
+impl Display for CloudProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// A cloud instance type with enough information to estimate its power without users having to
+/// look up the TDP of hardware they've never seen.
+#[derive(Debug, PartialEq)]
+pub struct InstanceType {
+    pub provider: CloudProvider,
+    pub name: &'static str,
+    pub cpu_name: &'static str,
+    pub vcpus: u32,
+    /// Power drawn by a single vCPU at full utilisation in watts.
+    pub max_watts_per_vcpu: f64,
+}
+impl InstanceType {
+    /// The share of the host's TDP available to this instance.
+    pub fn tdp(&self) -> f64 {
+        self.max_watts_per_vcpu * self.vcpus as f64
+    }
+
+    /// The share of the host's embodied carbon attributed to this instance in kgCO2e.
+    pub fn embodied_carbon(&self) -> f64 {
+        EMBODIED_CARBON_PER_VCPU * self.vcpus as f64
+    }
+
+    /// The `[cpu]` and `[cloud]` config sections describing this instance.
+    pub fn to_toml(&self) -> String {
+        format!(
+            "[cpu]\n\
+             name = \"{}\"\n\
+             tdp = {:.2}\n\
+             \n\
+             [cloud]\n\
+             provider = \"{}\"\n\
+             instance = \"{}\"\n\
+             pue = {}\n\
+             embodied_carbon = {:.1}\n",
+            self.cpu_name,
+            self.tdp(),
+            self.provider,
+            self.name,
+            self.provider.pue(),
+            self.embodied_carbon()
+        )
+    }
+}
+
+// Max watts per vCPU are the Cloud Carbon Footprint coefficients for each microarchitecture.
+const SKYLAKE: f64 = 4.26;
+const CASCADE_LAKE: f64 = 3.97;
+const GRAVITON2: f64 = 1.28;
+const EPYC_ROME: f64 = 2.55;
+const EPYC_MILAN: f64 = 2.02;
+
+#[rustfmt::skip]
+const CATALOGUE: &[InstanceType] = &[
+    InstanceType { provider: CloudProvider::Aws, name: "t3.medium", cpu_name: "Intel Xeon Platinum 8175M", vcpus: 2, max_watts_per_vcpu: SKYLAKE },
+    InstanceType { provider: CloudProvider::Aws, name: "m5.large", cpu_name: "Intel Xeon Platinum 8175M", vcpus: 2, max_watts_per_vcpu: SKYLAKE },
+    InstanceType { provider: CloudProvider::Aws, name: "m5.xlarge", cpu_name: "Intel Xeon Platinum 8175M", vcpus: 4, max_watts_per_vcpu: SKYLAKE },
+    InstanceType { provider: CloudProvider::Aws, name: "m5.2xlarge", cpu_name: "Intel Xeon Platinum 8175M", vcpus: 8, max_watts_per_vcpu: SKYLAKE },
+    InstanceType { provider: CloudProvider::Aws, name: "c5.large", cpu_name: "Intel Xeon Platinum 8124M", vcpus: 2, max_watts_per_vcpu: SKYLAKE },
+    InstanceType { provider: CloudProvider::Aws, name: "c5.xlarge", cpu_name: "Intel Xeon Platinum 8124M", vcpus: 4, max_watts_per_vcpu: SKYLAKE },
+    InstanceType { provider: CloudProvider::Aws, name: "r5.large", cpu_name: "Intel Xeon Platinum 8175M", vcpus: 2, max_watts_per_vcpu: SKYLAKE },
+    InstanceType { provider: CloudProvider::Aws, name: "m6g.large", cpu_name: "AWS Graviton2", vcpus: 2, max_watts_per_vcpu: GRAVITON2 },
+    InstanceType { provider: CloudProvider::Gcp, name: "e2-standard-2", cpu_name: "Intel Xeon (Cascade Lake)", vcpus: 2, max_watts_per_vcpu: CASCADE_LAKE },
+    InstanceType { provider: CloudProvider::Gcp, name: "n2-standard-2", cpu_name: "Intel Xeon Gold 6268CL", vcpus: 2, max_watts_per_vcpu: CASCADE_LAKE },
+    InstanceType { provider: CloudProvider::Gcp, name: "n2-standard-4", cpu_name: "Intel Xeon Gold 6268CL", vcpus: 4, max_watts_per_vcpu: CASCADE_LAKE },
+    InstanceType { provider: CloudProvider::Gcp, name: "c2-standard-4", cpu_name: "Intel Xeon Gold 6253CL", vcpus: 4, max_watts_per_vcpu: CASCADE_LAKE },
+    InstanceType { provider: CloudProvider::Gcp, name: "t2d-standard-2", cpu_name: "AMD EPYC 7B13", vcpus: 2, max_watts_per_vcpu: EPYC_MILAN },
+    InstanceType { provider: CloudProvider::Azure, name: "Standard_B2s", cpu_name: "Intel Xeon Platinum 8272CL", vcpus: 2, max_watts_per_vcpu: CASCADE_LAKE },
+    InstanceType { provider: CloudProvider::Azure, name: "Standard_D2s_v3", cpu_name: "Intel Xeon Platinum 8272CL", vcpus: 2, max_watts_per_vcpu: CASCADE_LAKE },
+    InstanceType { provider: CloudProvider::Azure, name: "Standard_D4s_v3", cpu_name: "Intel Xeon Platinum 8272CL", vcpus: 4, max_watts_per_vcpu: CASCADE_LAKE },
+    InstanceType { provider: CloudProvider::Azure, name: "Standard_F2s_v2", cpu_name: "Intel Xeon Platinum 8168", vcpus: 2, max_watts_per_vcpu: SKYLAKE },
+    InstanceType { provider: CloudProvider::Azure, name: "Standard_D2as_v4", cpu_name: "AMD EPYC 7452", vcpus: 2, max_watts_per_vcpu: EPYC_ROME },
+];
+
+/// Finds an instance type in the built-in catalogue.
+///
+/// # Arguments
+///
+/// * `spec` - The provider and instance type separated by a colon, e.g. `aws:m5.large`
+///
+/// # Returns
+///
+/// The instance type or an error listing the instances available for the provider.
+pub fn find_instance(spec: &str) -> anyhow::Result<&'static InstanceType> {
+    let (provider, name) = spec
+        .split_once(':')
+        .context(format!("Expected <provider>:<instance>, found {spec}"))?;
+    let provider = provider.to_lowercase();
+
+    CATALOGUE
+        .iter()
+        .find(|instance| {
+            instance.provider.as_str() == provider && instance.name.eq_ignore_ascii_case(name)
+        })
+        .with_context(|| {
+            let known = CATALOGUE
+                .iter()
+                .filter(|instance| instance.provider.as_str() == provider)
+                .map(|instance| instance.name)
+                .collect::<Vec<_>>();
+            if known.is_empty() {
+                format!("Unknown cloud provider {provider}, expected aws, gcp or azure")
+            } else {
+                format!(
+                    "Unknown {provider} instance {name}, known instances are: {}",
+                    known.join(", ")
+                )
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn instances_can_be_found_by_provider_and_name() -> anyhow::Result<()> {
+        let instance = find_instance("aws:m5.large")?;
+        assert_eq!(instance.cpu_name, "Intel Xeon Platinum 8175M");
+        assert!((instance.tdp() - 8.52).abs() < 1e-9);
+        assert!((instance.embodied_carbon() - 25.0).abs() < 1e-9);
+
+        assert!(find_instance("GCP:N2-Standard-2").is_ok());
+        assert!(find_instance("aws:m99.huge").is_err());
+        assert!(find_instance("ibm:m5.large").is_err());
+        assert!(find_instance("m5.large").is_err());
+        Ok(())
+    }
+}
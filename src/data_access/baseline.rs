@@ -0,0 +1,199 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use crate::error::{Context, Result};
+use async_trait::async_trait;
+
+/// CPU usage of the whole system measured while idle, used to separate the energy used by a
+/// scenario from background noise.
+#[derive(Debug, PartialEq, serde::Deserialize, serde::Serialize, sqlx::FromRow)]
+pub struct Baseline {
+    pub id: String,
+    pub start_time: i64,
+    pub stop_time: i64,
+    /// Average usage of the whole CPU as a percentage (0 - 100).
+    pub cpu_usage: f64,
+    pub core_count: i64,
+}
+impl Baseline {
+    pub fn new(id: &str, start_time: i64, stop_time: i64, cpu_usage: f64, core_count: i64) -> Self {
+        Self {
+            id: String::from(id),
+            start_time,
+            stop_time,
+            cpu_usage,
+            core_count,
+        }
+    }
+}
+
+#[async_trait]
+pub trait BaselineDao {
+    async fn fetch(&self, id: &str) -> Result<Option<Baseline>>;
+    async fn fetch_latest(&self) -> Result<Option<Baseline>>;
+    async fn persist(&self, baseline: &Baseline) -> Result<()>;
+}
+
+// //////////////////////////////////////
+// LocalDao
+
+pub struct LocalDao {
+    pub pool: sqlx::SqlitePool,
+}
+impl LocalDao {
+    pub fn new(pool: sqlx::SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+#[async_trait]
+impl BaselineDao for LocalDao {
+    async fn fetch(&self, id: &str) -> Result<Option<Baseline>> {
+        sqlx::query_as!(Baseline, "SELECT * FROM baseline WHERE id = ?1", id)
+            .fetch_optional(&self.pool)
+            .await
+            .context("Error fetching baseline from db.")
+    }
+
+    async fn fetch_latest(&self) -> Result<Option<Baseline>> {
+        sqlx::query_as!(
+            Baseline,
+            "SELECT * FROM baseline ORDER BY start_time DESC LIMIT 1"
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .context("Error fetching latest baseline from db.")
+    }
+
+    async fn persist(&self, baseline: &Baseline) -> Result<()> {
+        sqlx::query!(
+            "INSERT INTO baseline (id, start_time, stop_time, cpu_usage, core_count) VALUES (?1, ?2, ?3, ?4, ?5)",
+            baseline.id,
+            baseline.start_time,
+            baseline.stop_time,
+            baseline.cpu_usage,
+            baseline.core_count
+        )
+        .execute(&self.pool)
+        .await
+        .map(|_| ())
+        .context("Error inserting baseline into db.")
+    }
+}
+
+// //////////////////////////////////////
+// MySqlDao
+
+pub struct MySqlDao {
+    pub pool: sqlx::MySqlPool,
+}
+impl MySqlDao {
+    pub fn new(pool: sqlx::MySqlPool) -> Self {
+        Self { pool }
+    }
+}
+#[async_trait]
+impl BaselineDao for MySqlDao {
+    async fn fetch(&self, id: &str) -> Result<Option<Baseline>> {
+        sqlx::query_as("SELECT * FROM baseline WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+            .context("Error fetching baseline from db.")
+    }
+
+    async fn fetch_latest(&self) -> Result<Option<Baseline>> {
+        sqlx::query_as("SELECT * FROM baseline ORDER BY start_time DESC LIMIT 1")
+            .fetch_optional(&self.pool)
+            .await
+            .context("Error fetching latest baseline from db.")
+    }
+
+    async fn persist(&self, baseline: &Baseline) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO baseline (id, start_time, stop_time, cpu_usage, core_count) VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(&baseline.id)
+        .bind(baseline.start_time)
+        .bind(baseline.stop_time)
+        .bind(baseline.cpu_usage)
+        .bind(baseline.core_count)
+        .execute(&self.pool)
+        .await
+        .map(|_| ())
+        .context("Error inserting baseline into db.")
+    }
+}
+
+// //////////////////////////////////////
+// RemoteDao
+
+pub struct RemoteDao {
+    base_url: String,
+    client: reqwest::Client,
+}
+impl RemoteDao {
+    pub fn new(base_url: &str) -> Self {
+        let base_url = base_url.strip_suffix('/').unwrap_or(base_url);
+        Self {
+            base_url: String::from(base_url),
+            client: reqwest::Client::new(),
+        }
+    }
+}
+#[async_trait]
+impl BaselineDao for RemoteDao {
+    async fn fetch(&self, id: &str) -> Result<Option<Baseline>> {
+        self.client
+            .get(format!("{}/baseline/{id}", self.base_url))
+            .send()
+            .await?
+            .json::<Option<Baseline>>()
+            .await
+            .context("Error fetching baseline from remote server")
+    }
+
+    async fn fetch_latest(&self) -> Result<Option<Baseline>> {
+        self.client
+            .get(format!("{}/baseline", self.base_url))
+            .send()
+            .await?
+            .json::<Option<Baseline>>()
+            .await
+            .context("Error fetching latest baseline from remote server")
+    }
+
+    async fn persist(&self, baseline: &Baseline) -> Result<()> {
+        self.client
+            .post(format!("{}/baseline", self.base_url))
+            .json(baseline)
+            .send()
+            .await?
+            .error_for_status()
+            .map(|_| ())
+            .context("Error persisting baseline to remote server")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[sqlx::test(migrations = "./migrations", fixtures("../../fixtures/baselines.sql"))]
+    async fn local_baseline_fetch(pool: sqlx::SqlitePool) -> anyhow::Result<()> {
+        let baseline_service = LocalDao::new(pool.clone());
+
+        let baseline = baseline_service.fetch("idle_1").await?;
+        assert_eq!(baseline.map(|b| b.cpu_usage), Some(2.5));
+
+        let latest = baseline_service.fetch_latest().await?;
+        assert_eq!(latest.map(|b| b.id), Some("idle_2".to_string()));
+
+        assert!(baseline_service.fetch("nope").await?.is_none());
+
+        pool.close().await;
+        Ok(())
+    }
+}
@@ -4,10 +4,11 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
 
-use anyhow::Context;
+use super::{push_within_ranges, TimeRange};
+use crate::error::{Context, Result};
 use async_trait::async_trait;
 
-#[derive(Debug, PartialEq, serde::Deserialize, serde::Serialize, sqlx::FromRow)]
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize, sqlx::FromRow)]
 pub struct CpuMetrics {
     pub run_id: String,
     pub process_id: String,
@@ -16,6 +17,23 @@ pub struct CpuMetrics {
     pub total_usage: f64,
     pub core_count: i64,
     pub timestamp: i64,
+    /// Average frequency of the CPU in MHz when the sample was taken, if it's known.
+    pub cpu_frequency: Option<i64>,
+    /// Resident memory of the process in bytes when the sample was taken, if it's known.
+    pub memory_usage: Option<i64>,
+    /// Power drawn by the process in watts as measured by another tool (e.g. Scaphandre), if
+    /// it's known.
+    pub power: Option<f64>,
+    /// Seconds covered by the sample, i.e. since the previous sample of the process. Loggers back
+    /// off under load so this may be longer than a second, None for samples recorded before it
+    /// was tracked.
+    pub sample_interval: Option<f64>,
+    /// Bytes of memory the container may use, None if it isn't a container or it's unknown.
+    pub memory_limit: Option<i64>,
+    /// CPU quota enforcement periods of the container which elapsed during the sample, and how
+    /// many of them it was throttled in because it used up its quota.
+    pub cpu_periods: Option<i64>,
+    pub throttled_periods: Option<i64>,
 }
 impl CpuMetrics {
     pub fn new(
@@ -35,19 +53,60 @@ impl CpuMetrics {
             total_usage,
             core_count,
             timestamp,
+            cpu_frequency: None,
+            memory_usage: None,
+            power: None,
+            sample_interval: None,
+            memory_limit: None,
+            cpu_periods: None,
+            throttled_periods: None,
         }
     }
+
+    pub fn with_cpu_frequency(mut self, cpu_frequency: Option<i64>) -> Self {
+        self.cpu_frequency = cpu_frequency;
+        self
+    }
+
+    pub fn with_memory_usage(mut self, memory_usage: Option<i64>) -> Self {
+        self.memory_usage = memory_usage;
+        self
+    }
+
+    pub fn with_power(mut self, power: Option<f64>) -> Self {
+        self.power = power;
+        self
+    }
+
+    pub fn with_sample_interval(mut self, sample_interval: Option<f64>) -> Self {
+        self.sample_interval = sample_interval;
+        self
+    }
+
+    pub fn with_memory_limit(mut self, memory_limit: Option<i64>) -> Self {
+        self.memory_limit = memory_limit;
+        self
+    }
+
+    pub fn with_throttling(
+        mut self,
+        cpu_periods: Option<i64>,
+        throttled_periods: Option<i64>,
+    ) -> Self {
+        self.cpu_periods = cpu_periods;
+        self.throttled_periods = throttled_periods;
+        self
+    }
 }
 
 #[async_trait]
 pub trait CpuMetricsDao {
-    async fn fetch_within(
-        &self,
-        run_id: &str,
-        begin: i64,
-        end: i64,
-    ) -> anyhow::Result<Vec<CpuMetrics>>;
-    async fn persist(&self, model: &CpuMetrics) -> anyhow::Result<()>;
+    async fn fetch_within(&self, run_id: &str, begin: i64, end: i64) -> Result<Vec<CpuMetrics>>;
+
+    /// Fetches the cpu metrics within any of the given ranges at once, e.g. for every iteration of a
+    /// dataset. Rows within more than one range are only returned once.
+    async fn fetch_within_ranges(&self, ranges: &[TimeRange]) -> Result<Vec<CpuMetrics>>;
+    async fn persist(&self, model: &CpuMetrics) -> Result<()>;
 }
 
 // //////////////////////////////////////
@@ -63,12 +122,7 @@ impl LocalDao {
 }
 #[async_trait]
 impl CpuMetricsDao for LocalDao {
-    async fn fetch_within(
-        &self,
-        run_id: &str,
-        begin: i64,
-        end: i64,
-    ) -> anyhow::Result<Vec<CpuMetrics>> {
+    async fn fetch_within(&self, run_id: &str, begin: i64, end: i64) -> Result<Vec<CpuMetrics>> {
         sqlx::query_as!(
             CpuMetrics,
             r#"
@@ -83,16 +137,43 @@ impl CpuMetricsDao for LocalDao {
         .context("Error fetching cpu metrics from db.")
     }
 
-    async fn persist(&self, metrics: &CpuMetrics) -> anyhow::Result<()> {
-        sqlx::query!("INSERT INTO cpu_metrics (run_id, process_id, process_name, cpu_usage, total_usage, core_count, timestamp) \
-                      VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)", 
+    async fn fetch_within_ranges(&self, ranges: &[TimeRange]) -> Result<Vec<CpuMetrics>> {
+        let ranges_json = serde_json::to_string(ranges).expect("ranges should serialize");
+        sqlx::query_as!(
+            CpuMetrics,
+            r#"
+            SELECT * FROM cpu_metrics WHERE rowid IN (
+                SELECT m.rowid
+                FROM json_each(?1) r
+                JOIN cpu_metrics m ON m.run_id = json_extract(r.value, '$.run_id')
+                    AND m.timestamp >= json_extract(r.value, '$.begin')
+                    AND m.timestamp <= json_extract(r.value, '$.end')
+            )
+            "#,
+            ranges_json
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Error fetching cpu metrics from db.")
+    }
+
+    async fn persist(&self, metrics: &CpuMetrics) -> Result<()> {
+        sqlx::query!("INSERT INTO cpu_metrics (run_id, process_id, process_name, cpu_usage, total_usage, core_count, timestamp, cpu_frequency, memory_usage, power, sample_interval, memory_limit, cpu_periods, throttled_periods) \
+                      VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)", 
             metrics.run_id,
             metrics.process_id,
             metrics.process_name,
             metrics.cpu_usage,
             metrics.total_usage,
             metrics.core_count,
-            metrics.timestamp
+            metrics.timestamp,
+            metrics.cpu_frequency,
+            metrics.memory_usage,
+            metrics.power,
+            metrics.sample_interval,
+            metrics.memory_limit,
+            metrics.cpu_periods,
+            metrics.throttled_periods
         )
             .execute(&self.pool)
             .await
@@ -101,6 +182,72 @@ impl CpuMetricsDao for LocalDao {
     }
 }
 
+// //////////////////////////////////////
+// MySqlDao
+
+pub struct MySqlDao {
+    pub pool: sqlx::MySqlPool,
+}
+impl MySqlDao {
+    pub fn new(pool: sqlx::MySqlPool) -> Self {
+        Self { pool }
+    }
+}
+#[async_trait]
+impl CpuMetricsDao for MySqlDao {
+    async fn fetch_within(&self, run_id: &str, begin: i64, end: i64) -> Result<Vec<CpuMetrics>> {
+        sqlx::query_as(
+            "SELECT * FROM cpu_metrics WHERE run_id = ? AND timestamp >= ? AND timestamp <= ? \
+             ORDER BY timestamp",
+        )
+        .bind(run_id)
+        .bind(begin)
+        .bind(end)
+        .fetch_all(&self.pool)
+        .await
+        .context("Error fetching cpu metrics from db.")
+    }
+
+    async fn fetch_within_ranges(&self, ranges: &[TimeRange]) -> Result<Vec<CpuMetrics>> {
+        if ranges.is_empty() {
+            return Ok(vec![]);
+        }
+        let mut query = sqlx::QueryBuilder::new("SELECT * FROM cpu_metrics WHERE ");
+        push_within_ranges(&mut query, ranges);
+        query.push(" ORDER BY timestamp");
+        query
+            .build_query_as()
+            .fetch_all(&self.pool)
+            .await
+            .context("Error fetching cpu metrics from db.")
+    }
+
+    async fn persist(&self, metrics: &CpuMetrics) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO cpu_metrics (run_id, process_id, process_name, cpu_usage, total_usage, core_count, timestamp, cpu_frequency, memory_usage, power, sample_interval, memory_limit, cpu_periods, throttled_periods) \
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&metrics.run_id)
+        .bind(&metrics.process_id)
+        .bind(&metrics.process_name)
+        .bind(metrics.cpu_usage)
+        .bind(metrics.total_usage)
+        .bind(metrics.core_count)
+        .bind(metrics.timestamp)
+        .bind(metrics.cpu_frequency)
+        .bind(metrics.memory_usage)
+        .bind(metrics.power)
+        .bind(metrics.sample_interval)
+        .bind(metrics.memory_limit)
+        .bind(metrics.cpu_periods)
+        .bind(metrics.throttled_periods)
+        .execute(&self.pool)
+        .await
+        .map(|_| ())
+        .context("Error inserting cpu metrics into db.")
+    }
+}
+
 // //////////////////////////////////////
 // RemoteDao
 
@@ -119,12 +266,7 @@ impl RemoteDao {
 }
 #[async_trait]
 impl CpuMetricsDao for RemoteDao {
-    async fn fetch_within(
-        &self,
-        run_id: &str,
-        begin: i64,
-        end: i64,
-    ) -> anyhow::Result<Vec<CpuMetrics>> {
+    async fn fetch_within(&self, run_id: &str, begin: i64, end: i64) -> Result<Vec<CpuMetrics>> {
         self.client
             .get(format!(
                 "{}/cpu_metrics/{run_id}?begin={begin}&end={end}",
@@ -137,7 +279,23 @@ impl CpuMetricsDao for RemoteDao {
             .context("Error fetching cpu metrics with id {id} from remote server")
     }
 
-    async fn persist(&self, metrics: &CpuMetrics) -> anyhow::Result<()> {
+    async fn fetch_within_ranges(&self, ranges: &[TimeRange]) -> Result<Vec<CpuMetrics>> {
+        // the server has no bulk route so ranges are fetched one at a time
+        let mut rows = vec![];
+        for (i, range) in ranges.iter().enumerate() {
+            let fetched = self
+                .fetch_within(&range.run_id, range.begin, range.end)
+                .await?;
+            rows.extend(fetched.into_iter().filter(|row| {
+                !ranges[..i]
+                    .iter()
+                    .any(|earlier| earlier.contains(&row.run_id, row.timestamp))
+            }));
+        }
+        Ok(rows)
+    }
+
+    async fn persist(&self, metrics: &CpuMetrics) -> Result<()> {
         self.client
             .post(format!("{}/cpu_metrics", self.base_url))
             .json(metrics)
@@ -179,6 +337,41 @@ mod tests {
         pool.close().await;
         Ok(())
     }
+
+    #[sqlx::test(
+        migrations = "./migrations",
+        fixtures("../../fixtures/cpu_metrics.sql")
+    )]
+    async fn local_cpu_metrics_fetch_within_ranges(pool: sqlx::SqlitePool) -> anyhow::Result<()> {
+        let metrics_service = LocalDao::new(pool.clone());
+
+        let range = |begin, end| TimeRange {
+            run_id: "1".to_string(),
+            begin,
+            end,
+        };
+        let ranges = vec![
+            range(1717507590000, 1717507590400),
+            range(1717507590200, 1717507590800),
+            range(1717507592000, 1717507592000),
+        ];
+        let metrics = metrics_service.fetch_within_ranges(&ranges).await?;
+
+        // overlapping ranges don't return the same metrics twice
+        let mut expected = metrics_service
+            .fetch_within("1", 1717507590000, 1717507590800)
+            .await?;
+        expected.extend(
+            metrics_service
+                .fetch_within("1", 1717507592000, 1717507592000)
+                .await?,
+        );
+        assert_eq!(metrics, expected);
+        assert!(metrics_service.fetch_within_ranges(&[]).await?.is_empty());
+
+        pool.close().await;
+        Ok(())
+    }
     /*
     #[sqlx::test(migrations = "./migrations")]
     async fn test_remote_cpu_metrics_service(pool: sqlx::SqlitePool) -> anyhow::Result<()> {
@@ -0,0 +1,195 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use crate::error::{Context, Result};
+use async_trait::async_trait;
+
+/// How many requests an endpoint of the application served during an iteration, counted by the
+/// endpoint proxy or posted by middleware in the application, so the energy of the iteration can
+/// be shared between its endpoints.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize, sqlx::FromRow)]
+pub struct EndpointRequest {
+    pub run_id: String,
+    pub scenario_name: String,
+    pub iteration: i64,
+    /// Method and path of the endpoint, e.g. `GET /cart/:id`.
+    pub endpoint: String,
+    pub requests: i64,
+}
+impl EndpointRequest {
+    pub fn new(
+        run_id: &str,
+        scenario_name: &str,
+        iteration: i64,
+        endpoint: &str,
+        requests: i64,
+    ) -> Self {
+        Self {
+            run_id: String::from(run_id),
+            scenario_name: String::from(scenario_name),
+            iteration,
+            endpoint: String::from(endpoint),
+            requests,
+        }
+    }
+}
+
+#[async_trait]
+pub trait EndpointRequestDao {
+    async fn fetch(&self, run_id: &str) -> Result<Vec<EndpointRequest>>;
+    async fn persist(&self, endpoint_request: &EndpointRequest) -> Result<()>;
+}
+
+// //////////////////////////////////////
+// LocalDao
+
+pub struct LocalDao {
+    pub pool: sqlx::SqlitePool,
+}
+impl LocalDao {
+    pub fn new(pool: sqlx::SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+#[async_trait]
+impl EndpointRequestDao for LocalDao {
+    async fn fetch(&self, run_id: &str) -> Result<Vec<EndpointRequest>> {
+        sqlx::query_as!(
+            EndpointRequest,
+            "SELECT * FROM endpoint_request WHERE run_id = ?1 \
+             ORDER BY scenario_name, iteration, endpoint",
+            run_id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Error fetching endpoint requests from db.")
+    }
+
+    async fn persist(&self, endpoint_request: &EndpointRequest) -> Result<()> {
+        sqlx::query!(
+            "INSERT OR REPLACE INTO endpoint_request \
+             (run_id, scenario_name, iteration, endpoint, requests) VALUES (?1, ?2, ?3, ?4, ?5)",
+            endpoint_request.run_id,
+            endpoint_request.scenario_name,
+            endpoint_request.iteration,
+            endpoint_request.endpoint,
+            endpoint_request.requests
+        )
+        .execute(&self.pool)
+        .await
+        .map(|_| ())
+        .context("Error inserting endpoint request into db.")
+    }
+}
+
+// //////////////////////////////////////
+// MySqlDao
+
+pub struct MySqlDao {
+    pub pool: sqlx::MySqlPool,
+}
+impl MySqlDao {
+    pub fn new(pool: sqlx::MySqlPool) -> Self {
+        Self { pool }
+    }
+}
+#[async_trait]
+impl EndpointRequestDao for MySqlDao {
+    async fn fetch(&self, run_id: &str) -> Result<Vec<EndpointRequest>> {
+        sqlx::query_as(
+            "SELECT * FROM endpoint_request WHERE run_id = ? \
+             ORDER BY scenario_name, iteration, endpoint",
+        )
+        .bind(run_id)
+        .fetch_all(&self.pool)
+        .await
+        .context("Error fetching endpoint requests from db.")
+    }
+
+    async fn persist(&self, endpoint_request: &EndpointRequest) -> Result<()> {
+        sqlx::query(
+            "REPLACE INTO endpoint_request \
+             (run_id, scenario_name, iteration, endpoint, requests) VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(&endpoint_request.run_id)
+        .bind(&endpoint_request.scenario_name)
+        .bind(endpoint_request.iteration)
+        .bind(&endpoint_request.endpoint)
+        .bind(endpoint_request.requests)
+        .execute(&self.pool)
+        .await
+        .map(|_| ())
+        .context("Error inserting endpoint request into db.")
+    }
+}
+
+// //////////////////////////////////////
+// RemoteDao
+
+pub struct RemoteDao {
+    base_url: String,
+    client: reqwest::Client,
+}
+impl RemoteDao {
+    pub fn new(base_url: &str) -> Self {
+        let base_url = base_url.strip_suffix('/').unwrap_or(base_url);
+        Self {
+            base_url: String::from(base_url),
+            client: reqwest::Client::new(),
+        }
+    }
+}
+#[async_trait]
+impl EndpointRequestDao for RemoteDao {
+    async fn fetch(&self, run_id: &str) -> Result<Vec<EndpointRequest>> {
+        self.client
+            .get(format!("{}/endpoint_request/{run_id}", self.base_url))
+            .send()
+            .await?
+            .json::<Vec<EndpointRequest>>()
+            .await
+            .context("Error fetching endpoint requests from remote server")
+    }
+
+    async fn persist(&self, endpoint_request: &EndpointRequest) -> Result<()> {
+        self.client
+            .post(format!("{}/endpoint_request", self.base_url))
+            .json(endpoint_request)
+            .send()
+            .await?
+            .error_for_status()
+            .map(|_| ())
+            .context("Error persisting endpoint request to remote server")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn local_endpoint_request_fetch(pool: sqlx::SqlitePool) -> anyhow::Result<()> {
+        let endpoint_request_service = LocalDao::new(pool.clone());
+
+        let search = EndpointRequest::new("1", "shop", 0, "GET /search", 40);
+        let cart = EndpointRequest::new("1", "shop", 0, "GET /cart/:id", 10);
+        endpoint_request_service.persist(&search).await?;
+        endpoint_request_service.persist(&cart).await?;
+
+        // counts posted again for the same iteration replace the earlier ones
+        let cart = EndpointRequest::new("1", "shop", 0, "GET /cart/:id", 12);
+        endpoint_request_service.persist(&cart).await?;
+
+        assert_eq!(
+            endpoint_request_service.fetch("1").await?,
+            vec![cart, search]
+        );
+        assert!(endpoint_request_service.fetch("2").await?.is_empty());
+
+        pool.close().await;
+        Ok(())
+    }
+}
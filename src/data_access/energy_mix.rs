@@ -0,0 +1,201 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use crate::error::{Context, Result};
+use async_trait::async_trait;
+
+/// Fuels which generate electricity without burning anything.
+const LOW_CARBON_FUELS: [&str; 4] = ["hydro", "nuclear", "solar", "wind"];
+
+/// The share of the grid's electricity generated by a fuel while a run executed, as reported by
+/// the carbon intensity provider.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize, sqlx::FromRow)]
+pub struct EnergyMix {
+    pub run_id: String,
+    /// e.g. "wind", "solar" or "gas".
+    pub fuel: String,
+    /// Percentage of generation, 0-100.
+    pub percentage: f64,
+}
+impl EnergyMix {
+    pub fn new(run_id: &str, fuel: &str, percentage: f64) -> Self {
+        Self {
+            run_id: String::from(run_id),
+            fuel: String::from(fuel),
+            percentage,
+        }
+    }
+
+    pub fn is_low_carbon(&self) -> bool {
+        LOW_CARBON_FUELS.contains(&self.fuel.as_str())
+    }
+}
+
+/// Summarises a mix as e.g. "62% low carbon (wind 40.1%, gas 30.0%, ...)", largest share first.
+pub fn describe(energy_mix: &[EnergyMix]) -> String {
+    let mut fuels = energy_mix.iter().collect::<Vec<_>>();
+    fuels.sort_by(|a, b| b.percentage.total_cmp(&a.percentage));
+
+    let low_carbon = fuels
+        .iter()
+        .filter(|fuel| fuel.is_low_carbon())
+        .map(|fuel| fuel.percentage)
+        .sum::<f64>();
+    let fuels = fuels
+        .iter()
+        .filter(|fuel| fuel.percentage > 0.0)
+        .map(|fuel| format!("{} {:.1}%", fuel.fuel, fuel.percentage))
+        .collect::<Vec<_>>();
+
+    format!("{low_carbon:.0}% low carbon ({})", fuels.join(", "))
+}
+
+#[async_trait]
+pub trait EnergyMixDao {
+    async fn fetch(&self, run_id: &str) -> Result<Vec<EnergyMix>>;
+    async fn persist(&self, energy_mix: &EnergyMix) -> Result<()>;
+}
+
+// //////////////////////////////////////
+// LocalDao
+
+pub struct LocalDao {
+    pub pool: sqlx::SqlitePool,
+}
+impl LocalDao {
+    pub fn new(pool: sqlx::SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+#[async_trait]
+impl EnergyMixDao for LocalDao {
+    async fn fetch(&self, run_id: &str) -> Result<Vec<EnergyMix>> {
+        sqlx::query_as!(
+            EnergyMix,
+            "SELECT * FROM energy_mix WHERE run_id = ?1 ORDER BY fuel",
+            run_id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Error fetching energy mix from db.")
+    }
+
+    async fn persist(&self, energy_mix: &EnergyMix) -> Result<()> {
+        sqlx::query!(
+            "INSERT INTO energy_mix (run_id, fuel, percentage) VALUES (?1, ?2, ?3)",
+            energy_mix.run_id,
+            energy_mix.fuel,
+            energy_mix.percentage
+        )
+        .execute(&self.pool)
+        .await
+        .map(|_| ())
+        .context("Error inserting energy mix into db.")
+    }
+}
+
+// //////////////////////////////////////
+// MySqlDao
+
+pub struct MySqlDao {
+    pub pool: sqlx::MySqlPool,
+}
+impl MySqlDao {
+    pub fn new(pool: sqlx::MySqlPool) -> Self {
+        Self { pool }
+    }
+}
+#[async_trait]
+impl EnergyMixDao for MySqlDao {
+    async fn fetch(&self, run_id: &str) -> Result<Vec<EnergyMix>> {
+        sqlx::query_as("SELECT * FROM energy_mix WHERE run_id = ? ORDER BY fuel")
+            .bind(run_id)
+            .fetch_all(&self.pool)
+            .await
+            .context("Error fetching energy mix from db.")
+    }
+
+    async fn persist(&self, energy_mix: &EnergyMix) -> Result<()> {
+        sqlx::query("INSERT INTO energy_mix (run_id, fuel, percentage) VALUES (?, ?, ?)")
+            .bind(&energy_mix.run_id)
+            .bind(&energy_mix.fuel)
+            .bind(energy_mix.percentage)
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .context("Error inserting energy mix into db.")
+    }
+}
+
+// //////////////////////////////////////
+// RemoteDao
+
+pub struct RemoteDao {
+    base_url: String,
+    client: reqwest::Client,
+}
+impl RemoteDao {
+    pub fn new(base_url: &str) -> Self {
+        let base_url = base_url.strip_suffix('/').unwrap_or(base_url);
+        Self {
+            base_url: String::from(base_url),
+            client: reqwest::Client::new(),
+        }
+    }
+}
+#[async_trait]
+impl EnergyMixDao for RemoteDao {
+    async fn fetch(&self, run_id: &str) -> Result<Vec<EnergyMix>> {
+        self.client
+            .get(format!("{}/energy_mix/{run_id}", self.base_url))
+            .send()
+            .await?
+            .json::<Vec<EnergyMix>>()
+            .await
+            .context("Error fetching energy mix from remote server")
+    }
+
+    async fn persist(&self, energy_mix: &EnergyMix) -> Result<()> {
+        self.client
+            .post(format!("{}/energy_mix", self.base_url))
+            .json(energy_mix)
+            .send()
+            .await?
+            .error_for_status()
+            .map(|_| ())
+            .context("Error persisting energy mix to remote server")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn local_energy_mix_fetch(pool: sqlx::SqlitePool) -> anyhow::Result<()> {
+        let energy_mix_service = LocalDao::new(pool.clone());
+
+        let energy_mix = vec![
+            EnergyMix::new("1", "gas", 30.0),
+            EnergyMix::new("1", "nuclear", 15.5),
+            EnergyMix::new("1", "solar", 0.0),
+            EnergyMix::new("1", "wind", 54.5),
+        ];
+        for fuel in energy_mix.iter() {
+            energy_mix_service.persist(fuel).await?;
+        }
+        assert_eq!(energy_mix_service.fetch("1").await?, energy_mix);
+        assert!(energy_mix_service.fetch("2").await?.is_empty());
+
+        assert_eq!(
+            describe(&energy_mix),
+            "70% low carbon (wind 54.5%, gas 30.0%, nuclear 15.5%)"
+        );
+
+        pool.close().await;
+        Ok(())
+    }
+}
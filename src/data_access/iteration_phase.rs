@@ -0,0 +1,356 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use crate::error::{Context, Result};
+use async_trait::async_trait;
+
+/// Scenario commands print this followed by a name to start a phase of an iteration, e.g.
+/// `##cardamon:phase inference`. The phase lasts until the next marker or the end of the
+/// iteration, a marker without a name just ends the current phase. The OpenTelemetry span the
+/// phase belongs to can follow the name as `trace_id=<id> span_id=<id>` or
+/// `traceparent=<w3c traceparent>`.
+pub const PHASE_MARKER: &str = "##cardamon:phase";
+
+/// Part of an iteration marked by the scenario command, e.g. loading a model then running
+/// inference, so the energy of the iteration can be broken down by what it was doing.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize, sqlx::FromRow)]
+pub struct IterationPhase {
+    pub run_id: String,
+    pub scenario_name: String,
+    pub iteration: i64,
+    pub phase: String,
+    pub start_time: i64,
+    pub stop_time: i64,
+    /// The distributed trace the phase belongs to, so its energy can be attributed to the
+    /// request, e.g. by endpoint.
+    #[serde(default)]
+    pub trace_id: Option<String>,
+    /// The span within the trace, phases of concurrent requests may overlap.
+    #[serde(default)]
+    pub span_id: Option<String>,
+}
+impl IterationPhase {
+    /// Builds the phases of an iteration from the markers its command printed.
+    ///
+    /// # Arguments
+    ///
+    /// * lines - lines the command printed along with when they were printed, in milliseconds
+    ///   since the unix epoch
+    /// * stop_time - when the iteration finished, ending the last phase
+    pub fn from_markers(
+        run_id: &str,
+        scenario_name: &str,
+        iteration: i64,
+        lines: &[(i64, String)],
+        stop_time: i64,
+    ) -> Vec<Self> {
+        let mut phases = vec![];
+        let mut current: Option<Self> = None;
+        for (timestamp, line) in lines.iter() {
+            let Some(marker) = line.trim().strip_prefix(PHASE_MARKER) else {
+                continue;
+            };
+            // e.g. ##cardamon:phases isn't a marker
+            if !marker.is_empty() && !marker.starts_with(char::is_whitespace) {
+                continue;
+            }
+
+            if let Some(mut phase) = current.take() {
+                phase.stop_time = *timestamp;
+                phases.push(phase);
+            }
+
+            let mut words = marker.split_whitespace();
+            current = words.next().map(|name| {
+                let (trace_id, span_id) = parse_trace_context(words);
+                Self {
+                    run_id: String::from(run_id),
+                    scenario_name: String::from(scenario_name),
+                    iteration,
+                    phase: String::from(name),
+                    start_time: *timestamp,
+                    stop_time,
+                    trace_id,
+                    span_id,
+                }
+            });
+        }
+
+        phases.extend(current);
+        phases
+    }
+}
+
+/// Finds the trace and span ids in the words following a phase's name, given as `trace_id=`
+/// and `span_id=` or a W3C `traceparent=` (version-trace id-span id-flags).
+fn parse_trace_context<'a>(
+    words: impl Iterator<Item = &'a str>,
+) -> (Option<String>, Option<String>) {
+    let mut trace_id = None;
+    let mut span_id = None;
+    for word in words {
+        match word.split_once('=') {
+            Some(("trace_id", id)) => trace_id = Some(id.to_string()),
+            Some(("span_id", id)) => span_id = Some(id.to_string()),
+            Some(("traceparent", traceparent)) => {
+                if let [_, trace, span, _] = traceparent.split('-').collect::<Vec<_>>()[..] {
+                    trace_id = Some(trace.to_string());
+                    span_id = Some(span.to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+    (trace_id, span_id)
+}
+
+#[async_trait]
+pub trait IterationPhaseDao {
+    async fn fetch(&self, run_id: &str) -> Result<Vec<IterationPhase>>;
+    async fn persist(&self, iteration_phase: &IterationPhase) -> Result<()>;
+}
+
+// //////////////////////////////////////
+// LocalDao
+
+pub struct LocalDao {
+    pub pool: sqlx::SqlitePool,
+}
+impl LocalDao {
+    pub fn new(pool: sqlx::SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+#[async_trait]
+impl IterationPhaseDao for LocalDao {
+    async fn fetch(&self, run_id: &str) -> Result<Vec<IterationPhase>> {
+        sqlx::query_as!(
+            IterationPhase,
+            "SELECT * FROM iteration_phase WHERE run_id = ?1 \
+             ORDER BY scenario_name, iteration, start_time, span_id",
+            run_id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Error fetching iteration phases from db.")
+    }
+
+    async fn persist(&self, iteration_phase: &IterationPhase) -> Result<()> {
+        sqlx::query!(
+            "INSERT OR REPLACE INTO iteration_phase \
+             (run_id, scenario_name, iteration, phase, start_time, stop_time, trace_id, span_id) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            iteration_phase.run_id,
+            iteration_phase.scenario_name,
+            iteration_phase.iteration,
+            iteration_phase.phase,
+            iteration_phase.start_time,
+            iteration_phase.stop_time,
+            iteration_phase.trace_id,
+            iteration_phase.span_id
+        )
+        .execute(&self.pool)
+        .await
+        .map(|_| ())
+        .context("Error inserting iteration phase into db.")
+    }
+}
+
+// //////////////////////////////////////
+// MySqlDao
+
+pub struct MySqlDao {
+    pub pool: sqlx::MySqlPool,
+}
+impl MySqlDao {
+    pub fn new(pool: sqlx::MySqlPool) -> Self {
+        Self { pool }
+    }
+}
+#[async_trait]
+impl IterationPhaseDao for MySqlDao {
+    async fn fetch(&self, run_id: &str) -> Result<Vec<IterationPhase>> {
+        sqlx::query_as(
+            "SELECT * FROM iteration_phase WHERE run_id = ? \
+             ORDER BY scenario_name, iteration, start_time, span_id",
+        )
+        .bind(run_id)
+        .fetch_all(&self.pool)
+        .await
+        .context("Error fetching iteration phases from db.")
+    }
+
+    async fn persist(&self, iteration_phase: &IterationPhase) -> Result<()> {
+        sqlx::query(
+            "REPLACE INTO iteration_phase \
+             (run_id, scenario_name, iteration, phase, start_time, stop_time, trace_id, span_id) \
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&iteration_phase.run_id)
+        .bind(&iteration_phase.scenario_name)
+        .bind(iteration_phase.iteration)
+        .bind(&iteration_phase.phase)
+        .bind(iteration_phase.start_time)
+        .bind(iteration_phase.stop_time)
+        .bind(&iteration_phase.trace_id)
+        .bind(&iteration_phase.span_id)
+        .execute(&self.pool)
+        .await
+        .map(|_| ())
+        .context("Error inserting iteration phase into db.")
+    }
+}
+
+// //////////////////////////////////////
+// RemoteDao
+
+pub struct RemoteDao {
+    base_url: String,
+    client: reqwest::Client,
+}
+impl RemoteDao {
+    pub fn new(base_url: &str) -> Self {
+        let base_url = base_url.strip_suffix('/').unwrap_or(base_url);
+        Self {
+            base_url: String::from(base_url),
+            client: reqwest::Client::new(),
+        }
+    }
+}
+#[async_trait]
+impl IterationPhaseDao for RemoteDao {
+    async fn fetch(&self, run_id: &str) -> Result<Vec<IterationPhase>> {
+        self.client
+            .get(format!("{}/iteration_phase/{run_id}", self.base_url))
+            .send()
+            .await?
+            .json::<Vec<IterationPhase>>()
+            .await
+            .context("Error fetching iteration phases from remote server")
+    }
+
+    async fn persist(&self, iteration_phase: &IterationPhase) -> Result<()> {
+        self.client
+            .post(format!("{}/iteration_phase", self.base_url))
+            .json(iteration_phase)
+            .send()
+            .await?
+            .error_for_status()
+            .map(|_| ())
+            .context("Error persisting iteration phase to remote server")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(lines: &[(i64, &str)]) -> Vec<(i64, String)> {
+        lines
+            .iter()
+            .map(|(timestamp, line)| (*timestamp, line.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn phases_run_from_one_marker_to_the_next() {
+        let printed = lines(&[
+            (1000, "starting"),
+            (1100, "##cardamon:phase load-model"),
+            (1500, "  ##cardamon:phase inference"),
+            (1600, "##cardamon:phases aren't markers"),
+            (2500, "##cardamon:phase"),
+            (2600, "##cardamon:phase report extra=words"),
+        ]);
+        let phases = IterationPhase::from_markers("1", "llm", 0, &printed, 3000)
+            .into_iter()
+            .map(|p| (p.phase, p.start_time, p.stop_time))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            phases,
+            vec![
+                (String::from("load-model"), 1100, 1500),
+                (String::from("inference"), 1500, 2500),
+                (String::from("report"), 2600, 3000),
+            ]
+        );
+
+        assert!(
+            IterationPhase::from_markers("1", "llm", 0, &lines(&[(1000, "hi")]), 2000).is_empty()
+        );
+    }
+
+    #[test]
+    fn phases_can_belong_to_a_trace() {
+        let printed = lines(&[
+            (1000, "##cardamon:phase checkout trace_id=4bf92f35 span_id=00f067aa"),
+            (
+                2000,
+                "##cardamon:phase search traceparent=00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            ),
+            (3000, "##cardamon:phase report traceparent=invalid"),
+        ]);
+        let traces = IterationPhase::from_markers("1", "shop", 0, &printed, 4000)
+            .into_iter()
+            .map(|p| (p.trace_id, p.span_id))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            traces,
+            vec![
+                (
+                    Some(String::from("4bf92f35")),
+                    Some(String::from("00f067aa"))
+                ),
+                (
+                    Some(String::from("4bf92f3577b34da6a3ce929d0e0e4736")),
+                    Some(String::from("00f067aa0ba902b7"))
+                ),
+                (None, None),
+            ]
+        );
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn local_iteration_phase_fetch(pool: sqlx::SqlitePool) -> anyhow::Result<()> {
+        let iteration_phase_service = LocalDao::new(pool.clone());
+
+        let phases = IterationPhase::from_markers(
+            "1",
+            "llm",
+            0,
+            &lines(&[
+                (1000, "##cardamon:phase load-model"),
+                (2000, "##cardamon:phase inference"),
+            ]),
+            3000,
+        );
+        for phase in phases.iter() {
+            iteration_phase_service.persist(phase).await?;
+        }
+        assert_eq!(iteration_phase_service.fetch("1").await?, phases);
+        assert!(iteration_phase_service.fetch("2").await?.is_empty());
+
+        // concurrent requests to the same endpoint are kept apart by their spans
+        for span_id in ["a", "b", "a"] {
+            iteration_phase_service
+                .persist(&IterationPhase {
+                    run_id: String::from("2"),
+                    scenario_name: String::from("shop"),
+                    iteration: 0,
+                    phase: String::from("GET /cart"),
+                    start_time: 1000,
+                    stop_time: 1200,
+                    trace_id: Some(String::from("t")),
+                    span_id: Some(String::from(span_id)),
+                })
+                .await?;
+        }
+        assert_eq!(iteration_phase_service.fetch("2").await?.len(), 2);
+
+        pool.close().await;
+        Ok(())
+    }
+}
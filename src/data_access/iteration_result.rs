@@ -0,0 +1,203 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use crate::{
+    error::{Context, Result},
+    load_test,
+};
+use async_trait::async_trait;
+
+/// What a scenario command reported about the work it did in an iteration, e.g. how many requests
+/// it made, so energy can be given per request.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize, sqlx::FromRow)]
+pub struct IterationResult {
+    pub run_id: String,
+    pub scenario_name: String,
+    pub iteration: i64,
+    /// Requests, or other units of work, completed during the iteration.
+    pub requests: Option<i64>,
+    /// Latency percentiles in milliseconds.
+    pub p50_ms: Option<f64>,
+    pub p95_ms: Option<f64>,
+    pub p99_ms: Option<f64>,
+}
+
+impl IterationResult {
+    /// Finds the result a scenario command printed, see [`load_test::parse`].
+    ///
+    /// # Returns
+    ///
+    /// The result, None if the command didn't print one.
+    pub fn parse(run_id: &str, scenario_name: &str, iteration: i64, stdout: &str) -> Option<Self> {
+        let reported = load_test::parse(stdout)?;
+        Some(Self {
+            run_id: String::from(run_id),
+            scenario_name: String::from(scenario_name),
+            iteration,
+            requests: reported.requests,
+            p50_ms: reported.p50_ms,
+            p95_ms: reported.p95_ms,
+            p99_ms: reported.p99_ms,
+        })
+    }
+}
+
+#[async_trait]
+pub trait IterationResultDao {
+    async fn fetch(&self, run_id: &str) -> Result<Vec<IterationResult>>;
+    async fn persist(&self, iteration_result: &IterationResult) -> Result<()>;
+}
+
+// //////////////////////////////////////
+// LocalDao
+
+pub struct LocalDao {
+    pub pool: sqlx::SqlitePool,
+}
+impl LocalDao {
+    pub fn new(pool: sqlx::SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+#[async_trait]
+impl IterationResultDao for LocalDao {
+    async fn fetch(&self, run_id: &str) -> Result<Vec<IterationResult>> {
+        sqlx::query_as!(
+            IterationResult,
+            "SELECT * FROM iteration_result WHERE run_id = ?1 ORDER BY scenario_name, iteration",
+            run_id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Error fetching iteration results from db.")
+    }
+
+    async fn persist(&self, iteration_result: &IterationResult) -> Result<()> {
+        sqlx::query!(
+            "INSERT INTO iteration_result \
+             (run_id, scenario_name, iteration, requests, p50_ms, p95_ms, p99_ms) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            iteration_result.run_id,
+            iteration_result.scenario_name,
+            iteration_result.iteration,
+            iteration_result.requests,
+            iteration_result.p50_ms,
+            iteration_result.p95_ms,
+            iteration_result.p99_ms
+        )
+        .execute(&self.pool)
+        .await
+        .map(|_| ())
+        .context("Error inserting iteration result into db.")
+    }
+}
+
+// //////////////////////////////////////
+// MySqlDao
+
+pub struct MySqlDao {
+    pub pool: sqlx::MySqlPool,
+}
+impl MySqlDao {
+    pub fn new(pool: sqlx::MySqlPool) -> Self {
+        Self { pool }
+    }
+}
+#[async_trait]
+impl IterationResultDao for MySqlDao {
+    async fn fetch(&self, run_id: &str) -> Result<Vec<IterationResult>> {
+        sqlx::query_as(
+            "SELECT * FROM iteration_result WHERE run_id = ? ORDER BY scenario_name, iteration",
+        )
+        .bind(run_id)
+        .fetch_all(&self.pool)
+        .await
+        .context("Error fetching iteration results from db.")
+    }
+
+    async fn persist(&self, iteration_result: &IterationResult) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO iteration_result \
+             (run_id, scenario_name, iteration, requests, p50_ms, p95_ms, p99_ms) \
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&iteration_result.run_id)
+        .bind(&iteration_result.scenario_name)
+        .bind(iteration_result.iteration)
+        .bind(iteration_result.requests)
+        .bind(iteration_result.p50_ms)
+        .bind(iteration_result.p95_ms)
+        .bind(iteration_result.p99_ms)
+        .execute(&self.pool)
+        .await
+        .map(|_| ())
+        .context("Error inserting iteration result into db.")
+    }
+}
+
+// //////////////////////////////////////
+// RemoteDao
+
+pub struct RemoteDao {
+    base_url: String,
+    client: reqwest::Client,
+}
+impl RemoteDao {
+    pub fn new(base_url: &str) -> Self {
+        let base_url = base_url.strip_suffix('/').unwrap_or(base_url);
+        Self {
+            base_url: String::from(base_url),
+            client: reqwest::Client::new(),
+        }
+    }
+}
+#[async_trait]
+impl IterationResultDao for RemoteDao {
+    async fn fetch(&self, run_id: &str) -> Result<Vec<IterationResult>> {
+        self.client
+            .get(format!("{}/iteration_result/{run_id}", self.base_url))
+            .send()
+            .await?
+            .json::<Vec<IterationResult>>()
+            .await
+            .context("Error fetching iteration results from remote server")
+    }
+
+    async fn persist(&self, iteration_result: &IterationResult) -> Result<()> {
+        self.client
+            .post(format!("{}/iteration_result", self.base_url))
+            .json(iteration_result)
+            .send()
+            .await?
+            .error_for_status()
+            .map(|_| ())
+            .context("Error persisting iteration result to remote server")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn local_iteration_result_fetch(pool: sqlx::SqlitePool) -> anyhow::Result<()> {
+        let iteration_result_service = LocalDao::new(pool.clone());
+
+        let results = (0..2)
+            .filter_map(|iteration| {
+                IterationResult::parse("1", "checkout", iteration, "{\"requests\": 500}")
+            })
+            .collect::<Vec<_>>();
+        for result in results.iter() {
+            iteration_result_service.persist(result).await?;
+        }
+        assert_eq!(iteration_result_service.fetch("1").await?, results);
+        assert!(iteration_result_service.fetch("2").await?.is_empty());
+
+        pool.close().await;
+        Ok(())
+    }
+}
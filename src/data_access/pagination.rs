@@ -0,0 +1,159 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use serde::{Deserialize, Serialize};
+
+/// The largest page which can be requested, so a single request can't fetch everything.
+pub const MAX_PER_PAGE: u32 = 100;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortBy {
+    /// Average power measured by a smart plug or BMC.
+    Power,
+    Co2,
+    #[default]
+    LastRun,
+    Name,
+}
+impl SortBy {
+    /// The value bound into queries to pick the sort column.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SortBy::Power => "power",
+            SortBy::Co2 => "co2",
+            SortBy::LastRun => "last_run",
+            SortBy::Name => "name",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Order {
+    Asc,
+    #[default]
+    Desc,
+}
+impl Order {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Order::Asc => "asc",
+            Order::Desc => "desc",
+        }
+    }
+}
+
+fn default_page() -> u32 {
+    1
+}
+
+fn default_per_page() -> u32 {
+    20
+}
+
+/// Which page of a list to fetch and how the list is sorted. Doubles as the query parameters of
+/// the list endpoints, e.g. `?page=2&per_page=50&sort_by=co2&order=asc`.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct PageRequest {
+    /// 1-based page number.
+    #[serde(default = "default_page")]
+    pub page: u32,
+    #[serde(default = "default_per_page")]
+    pub per_page: u32,
+    #[serde(default)]
+    pub sort_by: SortBy,
+    #[serde(default)]
+    pub order: Order,
+}
+impl Default for PageRequest {
+    fn default() -> Self {
+        Self {
+            page: default_page(),
+            per_page: default_per_page(),
+            sort_by: SortBy::default(),
+            order: Order::default(),
+        }
+    }
+}
+impl PageRequest {
+    pub fn new(page: u32, per_page: u32) -> Self {
+        Self {
+            page,
+            per_page,
+            ..Default::default()
+        }
+    }
+
+    pub fn sorted(mut self, sort_by: SortBy, order: Order) -> Self {
+        self.sort_by = sort_by;
+        self.order = order;
+        self
+    }
+
+    /// The page number, treating 0 as the first page.
+    pub fn page(&self) -> u32 {
+        self.page.max(1)
+    }
+
+    /// The page size, limited to between 1 and `MAX_PER_PAGE`.
+    pub fn limit(&self) -> u32 {
+        self.per_page.clamp(1, MAX_PER_PAGE)
+    }
+
+    /// The number of items before the page, the last possible offset for pages too far in to
+    /// count.
+    pub fn offset(&self) -> u32 {
+        (self.page() - 1).saturating_mul(self.limit())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct Pagination {
+    pub page: u32,
+    pub per_page: u32,
+    pub total_items: u32,
+    pub total_pages: u32,
+}
+impl Pagination {
+    pub fn new(request: &PageRequest, total_items: u32) -> Self {
+        let per_page = request.limit();
+        Self {
+            page: request.page(),
+            per_page,
+            total_items,
+            total_pages: total_items.div_ceil(per_page),
+        }
+    }
+}
+
+/// A single page of a list along with the details needed to fetch the others.
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub pagination: Pagination,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn page_requests_are_kept_in_bounds() {
+        let request = PageRequest::new(0, 1000);
+        assert_eq!(request.page(), 1);
+        assert_eq!(request.limit(), MAX_PER_PAGE);
+        assert_eq!(request.offset(), 0);
+
+        let request = PageRequest::new(3, 20);
+        assert_eq!(request.offset(), 40);
+        assert_eq!(PageRequest::new(u32::MAX, 20).offset(), u32::MAX);
+
+        let pagination = Pagination::new(&request, 41);
+        assert_eq!(pagination.total_pages, 3);
+        assert_eq!(Pagination::new(&request, 0).total_pages, 0);
+    }
+}
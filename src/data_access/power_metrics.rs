@@ -0,0 +1,241 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use super::{push_within_ranges, TimeRange};
+use crate::error::{Context, Result};
+use async_trait::async_trait;
+
+/// Power drawn by the whole machine as measured at the wall, e.g. by a smart plug.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize, sqlx::FromRow)]
+pub struct PowerMetrics {
+    pub run_id: String,
+    /// The kind of meter which took the measurement.
+    pub source: String,
+    /// Power in watts.
+    pub power: f64,
+    pub timestamp: i64,
+}
+impl PowerMetrics {
+    pub fn new(run_id: &str, source: &str, power: f64, timestamp: i64) -> Self {
+        Self {
+            run_id: String::from(run_id),
+            source: String::from(source),
+            power,
+            timestamp,
+        }
+    }
+}
+
+#[async_trait]
+pub trait PowerMetricsDao {
+    async fn fetch_within(&self, run_id: &str, begin: i64, end: i64) -> Result<Vec<PowerMetrics>>;
+
+    /// Fetches the power metrics within any of the given ranges at once, e.g. for every iteration of a
+    /// dataset. Rows within more than one range are only returned once.
+    async fn fetch_within_ranges(&self, ranges: &[TimeRange]) -> Result<Vec<PowerMetrics>>;
+    async fn persist(&self, power_metrics: &PowerMetrics) -> Result<()>;
+}
+
+// //////////////////////////////////////
+// LocalDao
+
+pub struct LocalDao {
+    pub pool: sqlx::SqlitePool,
+}
+impl LocalDao {
+    pub fn new(pool: sqlx::SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+#[async_trait]
+impl PowerMetricsDao for LocalDao {
+    async fn fetch_within(&self, run_id: &str, begin: i64, end: i64) -> Result<Vec<PowerMetrics>> {
+        sqlx::query_as!(
+            PowerMetrics,
+            r#"
+            SELECT * FROM power_metrics WHERE run_id = ?1 AND timestamp >= ?2 AND timestamp <= ?3
+            ORDER BY timestamp
+            "#,
+            run_id,
+            begin,
+            end
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Error fetching power metrics from db.")
+    }
+
+    async fn fetch_within_ranges(&self, ranges: &[TimeRange]) -> Result<Vec<PowerMetrics>> {
+        let ranges_json = serde_json::to_string(ranges).expect("ranges should serialize");
+        sqlx::query_as!(
+            PowerMetrics,
+            r#"
+            SELECT * FROM power_metrics WHERE rowid IN (
+                SELECT m.rowid
+                FROM json_each(?1) r
+                JOIN power_metrics m ON m.run_id = json_extract(r.value, '$.run_id')
+                    AND m.timestamp >= json_extract(r.value, '$.begin')
+                    AND m.timestamp <= json_extract(r.value, '$.end')
+            )
+            ORDER BY timestamp
+            "#,
+            ranges_json
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Error fetching power metrics from db.")
+    }
+
+    async fn persist(&self, power_metrics: &PowerMetrics) -> Result<()> {
+        sqlx::query!(
+            "INSERT INTO power_metrics (run_id, source, power, timestamp) VALUES (?1, ?2, ?3, ?4)",
+            power_metrics.run_id,
+            power_metrics.source,
+            power_metrics.power,
+            power_metrics.timestamp
+        )
+        .execute(&self.pool)
+        .await
+        .map(|_| ())
+        .context("Error inserting power metrics into db.")
+    }
+}
+
+// //////////////////////////////////////
+// MySqlDao
+
+pub struct MySqlDao {
+    pub pool: sqlx::MySqlPool,
+}
+impl MySqlDao {
+    pub fn new(pool: sqlx::MySqlPool) -> Self {
+        Self { pool }
+    }
+}
+#[async_trait]
+impl PowerMetricsDao for MySqlDao {
+    async fn fetch_within(&self, run_id: &str, begin: i64, end: i64) -> Result<Vec<PowerMetrics>> {
+        sqlx::query_as(
+            "SELECT * FROM power_metrics WHERE run_id = ? AND timestamp >= ? AND timestamp <= ? \
+             ORDER BY timestamp",
+        )
+        .bind(run_id)
+        .bind(begin)
+        .bind(end)
+        .fetch_all(&self.pool)
+        .await
+        .context("Error fetching power metrics from db.")
+    }
+
+    async fn fetch_within_ranges(&self, ranges: &[TimeRange]) -> Result<Vec<PowerMetrics>> {
+        if ranges.is_empty() {
+            return Ok(vec![]);
+        }
+        let mut query = sqlx::QueryBuilder::new("SELECT * FROM power_metrics WHERE ");
+        push_within_ranges(&mut query, ranges);
+        query.push(" ORDER BY timestamp");
+        query
+            .build_query_as()
+            .fetch_all(&self.pool)
+            .await
+            .context("Error fetching power metrics from db.")
+    }
+
+    async fn persist(&self, power_metrics: &PowerMetrics) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO power_metrics (run_id, source, power, timestamp) VALUES (?, ?, ?, ?)",
+        )
+        .bind(&power_metrics.run_id)
+        .bind(&power_metrics.source)
+        .bind(power_metrics.power)
+        .bind(power_metrics.timestamp)
+        .execute(&self.pool)
+        .await
+        .map(|_| ())
+        .context("Error inserting power metrics into db.")
+    }
+}
+
+// //////////////////////////////////////
+// RemoteDao
+
+pub struct RemoteDao {
+    base_url: String,
+    client: reqwest::Client,
+}
+impl RemoteDao {
+    pub fn new(base_url: &str) -> Self {
+        let base_url = base_url.strip_suffix('/').unwrap_or(base_url);
+        Self {
+            base_url: String::from(base_url),
+            client: reqwest::Client::new(),
+        }
+    }
+}
+#[async_trait]
+impl PowerMetricsDao for RemoteDao {
+    async fn fetch_within(&self, run_id: &str, begin: i64, end: i64) -> Result<Vec<PowerMetrics>> {
+        self.client
+            .get(format!(
+                "{}/power_metrics/{run_id}?begin={begin}&end={end}",
+                self.base_url
+            ))
+            .send()
+            .await?
+            .json::<Vec<PowerMetrics>>()
+            .await
+            .context("Error fetching power metrics from remote server")
+    }
+
+    async fn fetch_within_ranges(&self, ranges: &[TimeRange]) -> Result<Vec<PowerMetrics>> {
+        // the server has no bulk route so ranges are fetched one at a time
+        let mut rows = vec![];
+        for (i, range) in ranges.iter().enumerate() {
+            let fetched = self
+                .fetch_within(&range.run_id, range.begin, range.end)
+                .await?;
+            rows.extend(fetched.into_iter().filter(|row| {
+                !ranges[..i]
+                    .iter()
+                    .any(|earlier| earlier.contains(&row.run_id, row.timestamp))
+            }));
+        }
+        Ok(rows)
+    }
+
+    async fn persist(&self, power_metrics: &PowerMetrics) -> Result<()> {
+        self.client
+            .post(format!("{}/power_metrics", self.base_url))
+            .json(power_metrics)
+            .send()
+            .await?
+            .error_for_status()
+            .map(|_| ())
+            .context("Error persisting power metrics to remote server")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[sqlx::test(
+        migrations = "./migrations",
+        fixtures("../../fixtures/power_metrics.sql")
+    )]
+    async fn local_power_metrics_fetch_within(pool: sqlx::SqlitePool) -> anyhow::Result<()> {
+        let power_metrics_service = LocalDao::new(pool.clone());
+
+        let power_metrics = power_metrics_service
+            .fetch_within("1", 1717507600000, 1717507601000)
+            .await?;
+        let power = power_metrics.iter().map(|m| m.power).collect::<Vec<_>>();
+        assert_eq!(power, vec![42.5, 45.0]);
+
+        pool.close().await;
+        Ok(())
+    }
+}
@@ -0,0 +1,243 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use super::{push_within_ranges, TimeRange};
+use crate::error::{Context, Result};
+use async_trait::async_trait;
+
+/// A change in state of an observed process (e.g. a container stopping or being OOM killed)
+/// which happened during a run.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize, sqlx::FromRow)]
+pub struct ProcessEvent {
+    pub run_id: String,
+    pub process_name: String,
+    pub event: String,
+    pub timestamp: i64,
+}
+impl ProcessEvent {
+    pub fn new(run_id: &str, process_name: &str, event: &str, timestamp: i64) -> Self {
+        Self {
+            run_id: String::from(run_id),
+            process_name: String::from(process_name),
+            event: String::from(event),
+            timestamp,
+        }
+    }
+}
+
+#[async_trait]
+pub trait ProcessEventDao {
+    async fn fetch_within(&self, run_id: &str, begin: i64, end: i64) -> Result<Vec<ProcessEvent>>;
+
+    /// Fetches the process events within any of the given ranges at once, e.g. for every iteration of a
+    /// dataset. Rows within more than one range are only returned once.
+    async fn fetch_within_ranges(&self, ranges: &[TimeRange]) -> Result<Vec<ProcessEvent>>;
+    async fn persist(&self, process_event: &ProcessEvent) -> Result<()>;
+}
+
+// //////////////////////////////////////
+// LocalDao
+
+pub struct LocalDao {
+    pub pool: sqlx::SqlitePool,
+}
+impl LocalDao {
+    pub fn new(pool: sqlx::SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+#[async_trait]
+impl ProcessEventDao for LocalDao {
+    async fn fetch_within(&self, run_id: &str, begin: i64, end: i64) -> Result<Vec<ProcessEvent>> {
+        sqlx::query_as!(
+            ProcessEvent,
+            r#"
+            SELECT * FROM process_event WHERE run_id = ?1 AND timestamp >= ?2 AND timestamp <= ?3
+            ORDER BY timestamp
+            "#,
+            run_id,
+            begin,
+            end
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Error fetching process events from db.")
+    }
+
+    async fn fetch_within_ranges(&self, ranges: &[TimeRange]) -> Result<Vec<ProcessEvent>> {
+        let ranges_json = serde_json::to_string(ranges).expect("ranges should serialize");
+        sqlx::query_as!(
+            ProcessEvent,
+            r#"
+            SELECT * FROM process_event WHERE rowid IN (
+                SELECT m.rowid
+                FROM json_each(?1) r
+                JOIN process_event m ON m.run_id = json_extract(r.value, '$.run_id')
+                    AND m.timestamp >= json_extract(r.value, '$.begin')
+                    AND m.timestamp <= json_extract(r.value, '$.end')
+            )
+            "#,
+            ranges_json
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Error fetching process events from db.")
+    }
+
+    async fn persist(&self, process_event: &ProcessEvent) -> Result<()> {
+        sqlx::query!(
+            "INSERT INTO process_event (run_id, process_name, event, timestamp) VALUES (?1, ?2, ?3, ?4)",
+            process_event.run_id,
+            process_event.process_name,
+            process_event.event,
+            process_event.timestamp
+        )
+        .execute(&self.pool)
+        .await
+        .map(|_| ())
+        .context("Error inserting process event into db.")
+    }
+}
+
+// //////////////////////////////////////
+// MySqlDao
+
+pub struct MySqlDao {
+    pub pool: sqlx::MySqlPool,
+}
+impl MySqlDao {
+    pub fn new(pool: sqlx::MySqlPool) -> Self {
+        Self { pool }
+    }
+}
+#[async_trait]
+impl ProcessEventDao for MySqlDao {
+    async fn fetch_within(&self, run_id: &str, begin: i64, end: i64) -> Result<Vec<ProcessEvent>> {
+        sqlx::query_as(
+            "SELECT * FROM process_event WHERE run_id = ? AND timestamp >= ? AND timestamp <= ? \
+             ORDER BY timestamp",
+        )
+        .bind(run_id)
+        .bind(begin)
+        .bind(end)
+        .fetch_all(&self.pool)
+        .await
+        .context("Error fetching process events from db.")
+    }
+
+    async fn fetch_within_ranges(&self, ranges: &[TimeRange]) -> Result<Vec<ProcessEvent>> {
+        if ranges.is_empty() {
+            return Ok(vec![]);
+        }
+        let mut query = sqlx::QueryBuilder::new("SELECT * FROM process_event WHERE ");
+        push_within_ranges(&mut query, ranges);
+        query.push(" ORDER BY id");
+        query
+            .build_query_as()
+            .fetch_all(&self.pool)
+            .await
+            .context("Error fetching process events from db.")
+    }
+
+    async fn persist(&self, process_event: &ProcessEvent) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO process_event (run_id, process_name, event, timestamp) VALUES (?, ?, ?, ?)",
+        )
+        .bind(&process_event.run_id)
+        .bind(&process_event.process_name)
+        .bind(&process_event.event)
+        .bind(process_event.timestamp)
+        .execute(&self.pool)
+        .await
+        .map(|_| ())
+        .context("Error inserting process event into db.")
+    }
+}
+
+// //////////////////////////////////////
+// RemoteDao
+
+pub struct RemoteDao {
+    base_url: String,
+    client: reqwest::Client,
+}
+impl RemoteDao {
+    pub fn new(base_url: &str) -> Self {
+        let base_url = base_url.strip_suffix('/').unwrap_or(base_url);
+        Self {
+            base_url: String::from(base_url),
+            client: reqwest::Client::new(),
+        }
+    }
+}
+#[async_trait]
+impl ProcessEventDao for RemoteDao {
+    async fn fetch_within(&self, run_id: &str, begin: i64, end: i64) -> Result<Vec<ProcessEvent>> {
+        self.client
+            .get(format!(
+                "{}/process_event/{run_id}?begin={begin}&end={end}",
+                self.base_url
+            ))
+            .send()
+            .await?
+            .json::<Vec<ProcessEvent>>()
+            .await
+            .context("Error fetching process events from remote server")
+    }
+
+    async fn fetch_within_ranges(&self, ranges: &[TimeRange]) -> Result<Vec<ProcessEvent>> {
+        // the server has no bulk route so ranges are fetched one at a time
+        let mut rows = vec![];
+        for (i, range) in ranges.iter().enumerate() {
+            let fetched = self
+                .fetch_within(&range.run_id, range.begin, range.end)
+                .await?;
+            rows.extend(fetched.into_iter().filter(|row| {
+                !ranges[..i]
+                    .iter()
+                    .any(|earlier| earlier.contains(&row.run_id, row.timestamp))
+            }));
+        }
+        Ok(rows)
+    }
+
+    async fn persist(&self, process_event: &ProcessEvent) -> Result<()> {
+        self.client
+            .post(format!("{}/process_event", self.base_url))
+            .json(process_event)
+            .send()
+            .await?
+            .error_for_status()
+            .map(|_| ())
+            .context("Error persisting process event to remote server")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[sqlx::test(
+        migrations = "./migrations",
+        fixtures("../../fixtures/process_events.sql")
+    )]
+    async fn local_process_event_fetch_within(pool: sqlx::SqlitePool) -> anyhow::Result<()> {
+        let process_event_service = LocalDao::new(pool.clone());
+
+        let events = process_event_service
+            .fetch_within("1", 1717507592000, 1717507593000)
+            .await?;
+
+        let events = events
+            .iter()
+            .map(|event| event.event.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(events, vec!["stopped", "started"]);
+
+        pool.close().await;
+        Ok(())
+    }
+}
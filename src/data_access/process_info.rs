@@ -0,0 +1,208 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use crate::error::{Context, Result};
+use async_trait::async_trait;
+
+const BYTES_PER_MB: f64 = 1024.0 * 1024.0;
+
+/// How an observed container was deployed, captured when the run starts. Explains differences
+/// between runs such as a smaller image or a tighter CPU limit.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize, sqlx::FromRow)]
+pub struct ProcessInfo {
+    pub run_id: String,
+    pub process_name: String,
+    /// The image the container was created from, e.g. "nginx:1.25-alpine".
+    pub image: Option<String>,
+    /// Cores the container may use, None if it isn't limited.
+    pub cpu_limit: Option<f64>,
+    /// Bytes of memory the container may use, None if it isn't limited.
+    pub memory_limit: Option<i64>,
+    /// Running containers of the same service, including this one.
+    pub replicas: i64,
+}
+impl ProcessInfo {
+    /// Summarises the container as e.g. "nginx:1.25-alpine, 2 CPUs, 512 MB, 3 replicas".
+    pub fn describe(&self) -> String {
+        let mut parts = vec![self
+            .image
+            .clone()
+            .unwrap_or_else(|| String::from("unknown image"))];
+        match self.cpu_limit {
+            Some(cpu_limit) => parts.push(format!("{cpu_limit} CPUs")),
+            None => parts.push(String::from("no CPU limit")),
+        }
+        match self.memory_limit {
+            Some(memory_limit) => {
+                parts.push(format!("{:.0} MB", memory_limit as f64 / BYTES_PER_MB))
+            }
+            None => parts.push(String::from("no memory limit")),
+        }
+        if self.replicas > 1 {
+            parts.push(format!("{} replicas", self.replicas));
+        }
+
+        parts.join(", ")
+    }
+}
+
+#[async_trait]
+pub trait ProcessInfoDao {
+    async fn fetch(&self, run_id: &str) -> Result<Vec<ProcessInfo>>;
+    async fn persist(&self, process_info: &ProcessInfo) -> Result<()>;
+}
+
+// //////////////////////////////////////
+// LocalDao
+
+pub struct LocalDao {
+    pub pool: sqlx::SqlitePool,
+}
+impl LocalDao {
+    pub fn new(pool: sqlx::SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+#[async_trait]
+impl ProcessInfoDao for LocalDao {
+    async fn fetch(&self, run_id: &str) -> Result<Vec<ProcessInfo>> {
+        sqlx::query_as!(
+            ProcessInfo,
+            "SELECT * FROM process_info WHERE run_id = ?1 ORDER BY process_name",
+            run_id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Error fetching process info from db.")
+    }
+
+    async fn persist(&self, process_info: &ProcessInfo) -> Result<()> {
+        sqlx::query!(
+            "INSERT OR REPLACE INTO process_info \
+             (run_id, process_name, image, cpu_limit, memory_limit, replicas) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            process_info.run_id,
+            process_info.process_name,
+            process_info.image,
+            process_info.cpu_limit,
+            process_info.memory_limit,
+            process_info.replicas
+        )
+        .execute(&self.pool)
+        .await
+        .map(|_| ())
+        .context("Error inserting process info into db.")
+    }
+}
+
+// //////////////////////////////////////
+// MySqlDao
+
+pub struct MySqlDao {
+    pub pool: sqlx::MySqlPool,
+}
+impl MySqlDao {
+    pub fn new(pool: sqlx::MySqlPool) -> Self {
+        Self { pool }
+    }
+}
+#[async_trait]
+impl ProcessInfoDao for MySqlDao {
+    async fn fetch(&self, run_id: &str) -> Result<Vec<ProcessInfo>> {
+        sqlx::query_as("SELECT * FROM process_info WHERE run_id = ? ORDER BY process_name")
+            .bind(run_id)
+            .fetch_all(&self.pool)
+            .await
+            .context("Error fetching process info from db.")
+    }
+
+    async fn persist(&self, process_info: &ProcessInfo) -> Result<()> {
+        sqlx::query(
+            "REPLACE INTO process_info \
+             (run_id, process_name, image, cpu_limit, memory_limit, replicas) \
+             VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&process_info.run_id)
+        .bind(&process_info.process_name)
+        .bind(&process_info.image)
+        .bind(process_info.cpu_limit)
+        .bind(process_info.memory_limit)
+        .bind(process_info.replicas)
+        .execute(&self.pool)
+        .await
+        .map(|_| ())
+        .context("Error inserting process info into db.")
+    }
+}
+
+// //////////////////////////////////////
+// RemoteDao
+
+pub struct RemoteDao {
+    base_url: String,
+    client: reqwest::Client,
+}
+impl RemoteDao {
+    pub fn new(base_url: &str) -> Self {
+        let base_url = base_url.strip_suffix('/').unwrap_or(base_url);
+        Self {
+            base_url: String::from(base_url),
+            client: reqwest::Client::new(),
+        }
+    }
+}
+#[async_trait]
+impl ProcessInfoDao for RemoteDao {
+    async fn fetch(&self, run_id: &str) -> Result<Vec<ProcessInfo>> {
+        self.client
+            .get(format!("{}/process_info/{run_id}", self.base_url))
+            .send()
+            .await?
+            .json::<Vec<ProcessInfo>>()
+            .await
+            .context("Error fetching process info from remote server")
+    }
+
+    async fn persist(&self, process_info: &ProcessInfo) -> Result<()> {
+        self.client
+            .post(format!("{}/process_info", self.base_url))
+            .json(process_info)
+            .send()
+            .await?
+            .error_for_status()
+            .map(|_| ())
+            .context("Error persisting process info to remote server")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn local_process_info_fetch(pool: sqlx::SqlitePool) -> anyhow::Result<()> {
+        let process_info_service = LocalDao::new(pool.clone());
+
+        let web = ProcessInfo {
+            run_id: String::from("1"),
+            process_name: String::from("web"),
+            image: Some(String::from("nginx:1.25-alpine")),
+            cpu_limit: Some(2.0),
+            memory_limit: Some(512 * 1024 * 1024),
+            replicas: 3,
+        };
+        process_info_service.persist(&web).await?;
+        assert_eq!(process_info_service.fetch("1").await?, vec![web.clone()]);
+        assert!(process_info_service.fetch("2").await?.is_empty());
+        assert_eq!(
+            web.describe(),
+            "nginx:1.25-alpine, 2 CPUs, 512 MB, 3 replicas"
+        );
+
+        pool.close().await;
+        Ok(())
+    }
+}
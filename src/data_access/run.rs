@@ -0,0 +1,1453 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use super::{
+    pagination::{Order, Page, PageRequest, Pagination, SortBy},
+    DEFAULT_PROJECT,
+};
+use crate::{
+    dataset::AggregationMethod,
+    error::{CardamonError, Context, Result},
+    stats::Spread,
+};
+use async_trait::async_trait;
+use std::collections::HashMap;
+
+/// Details of a single cardamon run which apply to all of its scenario iterations.
+#[derive(Debug, PartialEq, serde::Deserialize, serde::Serialize, sqlx::FromRow)]
+pub struct Run {
+    pub id: String,
+    pub baseline_id: Option<String>,
+    /// Archived runs are kept but left out of the datasets used for trends and comparisons.
+    #[serde(default)]
+    pub archived: bool,
+    /// Free text describing the run, e.g. what changed since the last one.
+    #[serde(default)]
+    pub note: Option<String>,
+    /// The project the run belongs to, keeping the scenarios of different teams apart when they
+    /// share a database.
+    #[serde(default = "default_project")]
+    pub project_id: String,
+    /// Why the run started when it did, e.g. after waiting for greener electricity.
+    #[serde(default)]
+    pub schedule: Option<String>,
+    /// Iterations each scenario ran for when the count in the config was overridden.
+    #[serde(default)]
+    pub iterations: Option<i64>,
+    /// The observation or scenario that was run, so an interrupted run can be resumed.
+    #[serde(default)]
+    pub observation: Option<String>,
+    /// CPU usage of the whole machine, as a percentage, measured just before the run started.
+    #[serde(default)]
+    pub ambient_load: Option<f64>,
+    /// When the run stopped (ms since the epoch), None while it's in progress or if cardamon
+    /// stopped before it finished.
+    #[serde(default)]
+    pub stop_time: Option<i64>,
+    /// The scenarios the run executes, separated by commas, so a resumed run leaves out any which
+    /// were filtered out with `--only` or `--skip`.
+    #[serde(default)]
+    pub scenarios: Option<String>,
+}
+impl Run {
+    pub fn new(id: &str, baseline_id: Option<&str>) -> Self {
+        Self {
+            id: String::from(id),
+            baseline_id: baseline_id.map(String::from),
+            archived: false,
+            note: None,
+            project_id: default_project(),
+            schedule: None,
+            iterations: None,
+            observation: None,
+            ambient_load: None,
+            stop_time: None,
+            scenarios: None,
+        }
+    }
+
+    pub fn with_scenarios(mut self, scenarios: &[&str]) -> Self {
+        self.scenarios = (!scenarios.is_empty()).then(|| scenarios.join(","));
+        self
+    }
+
+    /// Names of the scenarios the run executes, None if they weren't recorded.
+    pub fn scenario_names(&self) -> Option<Vec<String>> {
+        self.scenarios
+            .as_deref()
+            .map(|scenarios| scenarios.split(',').map(String::from).collect())
+    }
+
+    pub fn with_stop_time(mut self, stop_time: Option<i64>) -> Self {
+        self.stop_time = stop_time;
+        self
+    }
+
+    pub fn with_ambient_load(mut self, ambient_load: Option<f64>) -> Self {
+        self.ambient_load = ambient_load;
+        self
+    }
+
+    pub fn with_observation(mut self, observation: Option<&str>) -> Self {
+        self.observation = observation.map(String::from);
+        self
+    }
+
+    pub fn with_iterations(mut self, iterations: Option<i64>) -> Self {
+        self.iterations = iterations;
+        self
+    }
+
+    pub fn with_schedule(mut self, schedule: Option<&str>) -> Self {
+        self.schedule = schedule.map(String::from);
+        self
+    }
+
+    pub fn in_project(mut self, project: &str) -> Self {
+        self.project_id = String::from(project);
+        self
+    }
+
+    pub fn with_note(mut self, note: Option<&str>) -> Self {
+        self.note = note.map(String::from);
+        self
+    }
+}
+
+fn default_project() -> String {
+    String::from(DEFAULT_PROJECT)
+}
+
+/// Overview of a run across all of its scenario iterations.
+#[derive(Debug, PartialEq, serde::Deserialize, serde::Serialize, sqlx::FromRow)]
+pub struct RunSummary {
+    pub run_id: String,
+    pub start_time: i64,
+    pub stop_time: i64,
+    /// The number of scenarios executed by the run.
+    pub scenarios: i64,
+    pub archived: bool,
+    pub note: Option<String>,
+    /// Average power measured by a smart plug or BMC during the run (W), None without one.
+    /// `energy_wh` is modelled for every run.
+    #[serde(alias = "power")]
+    pub measured_power: Option<f64>,
+    /// Embodied and use emissions of the run (kgCO2eq).
+    pub co2: Option<f64>,
+    /// Change in average measured power from the project's reference run (W), None without a
+    /// reference.
+    #[serde(default)]
+    pub power_delta: Option<f64>,
+    /// Change in emissions from the project's reference run (kgCO2eq).
+    #[serde(default)]
+    pub co2_delta: Option<f64>,
+    /// How much the average power of each iteration varied (W), for the least stable scenario of
+    /// the run. None without measured power.
+    #[serde(default)]
+    #[sqlx(skip)]
+    pub power_spread: Option<Spread>,
+    /// Energy the system under test used during the run (Wh), modelled with the server's config
+    /// file. None if the server has no config file.
+    #[serde(default)]
+    #[sqlx(skip)]
+    pub energy_wh: Option<f64>,
+}
+
+/// The run a project's later runs are compared against, e.g. the last release.
+#[derive(Debug, PartialEq, serde::Deserialize, serde::Serialize, sqlx::FromRow)]
+pub struct ReferenceRun {
+    pub project_id: String,
+    pub run_id: String,
+    pub name: String,
+}
+
+#[async_trait]
+pub trait RunDao {
+    async fn fetch(&self, id: &str) -> Result<Option<Run>>;
+    async fn fetch_summaries(&self, page: &PageRequest) -> Result<Page<RunSummary>>;
+    async fn persist(&self, run: &Run) -> Result<()>;
+
+    /// Records when a run stopped, whether it completed or was cancelled. None marks it as in
+    /// progress again, e.g. when it's resumed.
+    async fn set_stop_time(&self, id: &str, stop_time: Option<i64>) -> Result<()>;
+
+    /// Names of every project with runs in the database.
+    async fn fetch_projects(&self) -> Result<Vec<String>>;
+
+    /// Archives or restores a run. Runs which were recorded without details are archived too.
+    async fn set_archived(&self, id: &str, archived: bool) -> Result<()>;
+
+    /// Sets the note describing a run, None removes it.
+    async fn set_note(&self, id: &str, note: Option<&str>) -> Result<()>;
+
+    /// Deletes a run along with its iterations, metrics, events and impact.
+    async fn delete(&self, id: &str) -> Result<()>;
+
+    /// The run the project's runs are compared against, if one is set.
+    async fn fetch_reference(&self) -> Result<Option<ReferenceRun>>;
+
+    /// Compares the project's runs against the given run, replacing any previous reference.
+    async fn set_reference(&self, id: &str, name: &str) -> Result<()>;
+
+    /// Stops comparing the project's runs against a reference run.
+    async fn clear_reference(&self) -> Result<()>;
+}
+
+/// Adds the spread of the power measured during each iteration to the summaries, and combines
+/// the iterations' power with the aggregation method if one was given. Iterations are given as
+/// their run, scenario and average power, oldest first.
+fn add_power_spreads(
+    summaries: &mut [RunSummary],
+    iterations: impl Iterator<Item = (String, String, f64)>,
+    aggregation: Option<AggregationMethod>,
+) {
+    let mut run_power: HashMap<String, Vec<f64>> = HashMap::new();
+    let mut iteration_power: HashMap<(String, String), Vec<f64>> = HashMap::new();
+    for (run_id, scenario_name, power) in iterations {
+        run_power.entry(run_id.clone()).or_default().push(power);
+        iteration_power
+            .entry((run_id, scenario_name))
+            .or_default()
+            .push(power);
+    }
+    for summary in summaries.iter_mut() {
+        summary.power_spread = iteration_power
+            .iter()
+            .filter(|((run_id, _), _)| run_id == &summary.run_id)
+            .filter_map(|(_, power)| Spread::new(power))
+            .max_by(|a, b| a.cv.total_cmp(&b.cv));
+        if let Some(aggregation) = aggregation {
+            summary.measured_power = run_power
+                .get(&summary.run_id)
+                .and_then(|power| aggregation.aggregate(power));
+        }
+    }
+}
+
+// //////////////////////////////////////
+// LocalDao
+
+pub struct LocalDao {
+    pub pool: sqlx::SqlitePool,
+    project: String,
+    aggregation: Option<AggregationMethod>,
+}
+impl LocalDao {
+    pub fn new(pool: sqlx::SqlitePool) -> Self {
+        Self {
+            pool,
+            project: String::from(DEFAULT_PROJECT),
+            aggregation: None,
+        }
+    }
+
+    /// Combines the measured power of each iteration of a run with the given method rather than
+    /// averaging every sample of the run. Sorting and the change from the reference run still
+    /// use the average.
+    pub fn with_aggregation(mut self, aggregation: Option<AggregationMethod>) -> Self {
+        self.aggregation = aggregation;
+        self
+    }
+
+    /// Only lists the runs of the given project. Runs created by archiving or annotating a run
+    /// recorded without details are added to it too.
+    pub fn for_project(mut self, project: &str) -> Self {
+        self.project = String::from(project);
+        self
+    }
+
+    /// Adds the spread of the power measured during each iteration to the summaries, and
+    /// combines the iterations' power with the aggregation method if one was given.
+    async fn with_power_spreads(&self, mut summaries: Vec<RunSummary>) -> Result<Vec<RunSummary>> {
+        let run_ids = summaries
+            .iter()
+            .map(|summary| summary.run_id.as_str())
+            .collect::<Vec<_>>();
+        let run_ids_json = serde_json::to_string(&run_ids).expect("run ids should serialize");
+        let rows = sqlx::query!(
+            r#"
+            SELECT
+                si.run_id AS "run_id!",
+                si.scenario_name AS "scenario_name!",
+                AVG(pm.power) AS "power!: f64"
+            FROM scenario_iteration si
+            JOIN power_metrics pm ON pm.run_id = si.run_id
+                AND pm.timestamp BETWEEN si.start_time AND si.stop_time
+            WHERE si.run_id IN (SELECT value FROM json_each(?1))
+            GROUP BY si.run_id, si.scenario_name, si.iteration
+            ORDER BY MIN(si.start_time)
+            "#,
+            run_ids_json
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Error fetching power of iterations from db.")?;
+
+        add_power_spreads(
+            &mut summaries,
+            rows.into_iter()
+                .map(|row| (row.run_id, row.scenario_name, row.power)),
+            self.aggregation,
+        );
+        Ok(summaries)
+    }
+
+    /// Runs are recorded by their iterations so a run may exist without any details.
+    async fn ensure_exists(&self, id: &str) -> Result<()> {
+        let exists = sqlx::query_scalar!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM run WHERE id = ?1
+                UNION SELECT 1 FROM scenario_iteration WHERE run_id = ?1
+            ) AS "exists!: bool"
+            "#,
+            id
+        )
+        .fetch_one(&self.pool)
+        .await
+        .context("Error fetching run from db.")?;
+
+        if exists {
+            Ok(())
+        } else {
+            Err(CardamonError::NotFound(format!(
+                "Unable to find run with id: {id}"
+            )))
+        }
+    }
+}
+#[async_trait]
+impl RunDao for LocalDao {
+    async fn fetch(&self, id: &str) -> Result<Option<Run>> {
+        sqlx::query_as!(Run, "SELECT * FROM run WHERE id = ?1", id)
+            .fetch_optional(&self.pool)
+            .await
+            .context("Error fetching run from db.")
+    }
+
+    async fn fetch_summaries(&self, page: &PageRequest) -> Result<Page<RunSummary>> {
+        let total_items = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(DISTINCT si.run_id) AS "count!: u32"
+            FROM scenario_iteration si
+            WHERE COALESCE((SELECT r.project_id FROM run r WHERE r.id = si.run_id), 'default') = ?1
+            "#,
+            self.project
+        )
+        .fetch_one(&self.pool)
+        .await
+        .context("Error counting runs in db.")?;
+
+        let sort_by = page.sort_by.as_str();
+        let order = page.order.as_str();
+        let limit = page.limit();
+        let offset = page.offset();
+        let rows = sqlx::query!(
+            r#"
+            WITH summary AS (
+                SELECT
+                    si.run_id AS run_id,
+                    MIN(si.start_time) AS start_time,
+                    MAX(si.stop_time) AS stop_time,
+                    COUNT(DISTINCT si.scenario_name) AS scenarios,
+                    EXISTS (
+                        SELECT 1 FROM run r WHERE r.id = si.run_id AND r.archived
+                    ) AS archived,
+                    (SELECT r.note FROM run r WHERE r.id = si.run_id) AS note,
+                    (
+                        SELECT AVG(pm.power)
+                        FROM power_metrics pm
+                        WHERE pm.run_id = si.run_id
+                    ) AS power,
+                    (
+                        SELECT ri.manufacture_gwp + ri.use_gwp
+                        FROM run_impact ri
+                        WHERE ri.run_id = si.run_id
+                    ) AS co2
+                FROM scenario_iteration si
+                WHERE COALESCE((SELECT r.project_id FROM run r WHERE r.id = si.run_id), 'default') = ?5
+                GROUP BY si.run_id
+            ),
+            reference AS (
+                SELECT
+                    (
+                        SELECT AVG(pm.power)
+                        FROM power_metrics pm
+                        WHERE pm.run_id = rr.run_id
+                    ) AS power,
+                    (
+                        SELECT ri.manufacture_gwp + ri.use_gwp
+                        FROM run_impact ri
+                        WHERE ri.run_id = rr.run_id
+                    ) AS co2
+                FROM reference_run rr
+                WHERE rr.project_id = ?5
+            )
+            SELECT
+                run_id AS "run_id!",
+                start_time AS "start_time!: i64",
+                stop_time AS "stop_time!: i64",
+                scenarios AS "scenarios!: i64",
+                archived AS "archived!: bool",
+                note AS "note: String",
+                power AS "power: f64",
+                co2 AS "co2: f64",
+                power - (SELECT power FROM reference) AS "power_delta: f64",
+                co2 - (SELECT co2 FROM reference) AS "co2_delta: f64"
+            FROM summary
+            ORDER BY
+                CASE WHEN ?2 = 'asc' THEN
+                    CASE ?1 WHEN 'power' THEN power WHEN 'co2' THEN co2 WHEN 'name' THEN run_id ELSE start_time END
+                END ASC,
+                CASE WHEN ?2 = 'desc' THEN
+                    CASE ?1 WHEN 'power' THEN power WHEN 'co2' THEN co2 WHEN 'name' THEN run_id ELSE start_time END
+                END DESC,
+                start_time DESC
+            LIMIT ?3 OFFSET ?4
+            "#,
+            sort_by,
+            order,
+            limit,
+            offset,
+            self.project
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Error fetching runs from db.")?;
+        let items = rows
+            .into_iter()
+            .map(|row| RunSummary {
+                run_id: row.run_id,
+                start_time: row.start_time,
+                stop_time: row.stop_time,
+                scenarios: row.scenarios,
+                archived: row.archived,
+                note: row.note,
+                measured_power: row.power,
+                co2: row.co2,
+                power_delta: row.power_delta,
+                co2_delta: row.co2_delta,
+                power_spread: None,
+                energy_wh: None,
+            })
+            .collect();
+
+        Ok(Page {
+            items: self.with_power_spreads(items).await?,
+            pagination: Pagination::new(page, total_items),
+        })
+    }
+
+    async fn persist(&self, run: &Run) -> Result<()> {
+        sqlx::query!(
+            "INSERT OR IGNORE INTO project (id) VALUES (?1)",
+            run.project_id
+        )
+        .execute(&self.pool)
+        .await
+        .context("Error inserting project into db.")?;
+
+        sqlx::query!(
+            "INSERT INTO run (id, baseline_id, archived, note, project_id, schedule, iterations, \
+             observation, ambient_load, stop_time, scenarios) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            run.id,
+            run.baseline_id,
+            run.archived,
+            run.note,
+            run.project_id,
+            run.schedule,
+            run.iterations,
+            run.observation,
+            run.ambient_load,
+            run.stop_time,
+            run.scenarios
+        )
+        .execute(&self.pool)
+        .await
+        .map(|_| ())
+        .context("Error inserting run into db.")
+    }
+
+    async fn set_stop_time(&self, id: &str, stop_time: Option<i64>) -> Result<()> {
+        sqlx::query!("UPDATE run SET stop_time = ?2 WHERE id = ?1", id, stop_time)
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .context("Error updating stop time of run in db.")
+    }
+
+    async fn fetch_projects(&self) -> Result<Vec<String>> {
+        sqlx::query_scalar!(
+            r#"
+            SELECT id AS "id!" FROM project
+            UNION SELECT project_id FROM run
+            ORDER BY 1
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Error fetching projects from db.")
+    }
+
+    async fn set_archived(&self, id: &str, archived: bool) -> Result<()> {
+        self.ensure_exists(id).await?;
+        sqlx::query!(
+            "INSERT INTO run (id, archived, project_id) VALUES (?1, ?2, ?3) \
+             ON CONFLICT (id) DO UPDATE SET archived = excluded.archived",
+            id,
+            archived,
+            self.project
+        )
+        .execute(&self.pool)
+        .await
+        .map(|_| ())
+        .context("Error archiving run in db.")
+    }
+
+    async fn set_note(&self, id: &str, note: Option<&str>) -> Result<()> {
+        self.ensure_exists(id).await?;
+        sqlx::query!(
+            "INSERT INTO run (id, note, project_id) VALUES (?1, ?2, ?3) \
+             ON CONFLICT (id) DO UPDATE SET note = excluded.note",
+            id,
+            note,
+            self.project
+        )
+        .execute(&self.pool)
+        .await
+        .map(|_| ())
+        .context("Error annotating run in db.")
+    }
+
+    async fn delete(&self, id: &str) -> Result<()> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .context("Error starting transaction.")?;
+
+        let mut deleted = 0;
+        deleted += sqlx::query!("DELETE FROM cpu_metrics WHERE run_id = ?1", id)
+            .execute(&mut *tx)
+            .await
+            .context("Error deleting cpu metrics from db.")?
+            .rows_affected();
+        deleted += sqlx::query!("DELETE FROM power_metrics WHERE run_id = ?1", id)
+            .execute(&mut *tx)
+            .await
+            .context("Error deleting power metrics from db.")?
+            .rows_affected();
+        deleted += sqlx::query!("DELETE FROM process_event WHERE run_id = ?1", id)
+            .execute(&mut *tx)
+            .await
+            .context("Error deleting process events from db.")?
+            .rows_affected();
+        deleted += sqlx::query!("DELETE FROM scenario_iteration WHERE run_id = ?1", id)
+            .execute(&mut *tx)
+            .await
+            .context("Error deleting scenario iterations from db.")?
+            .rows_affected();
+        deleted += sqlx::query!("DELETE FROM run_impact WHERE run_id = ?1", id)
+            .execute(&mut *tx)
+            .await
+            .context("Error deleting run impact from db.")?
+            .rows_affected();
+        deleted += sqlx::query!("DELETE FROM energy_mix WHERE run_id = ?1", id)
+            .execute(&mut *tx)
+            .await
+            .context("Error deleting energy mix from db.")?
+            .rows_affected();
+        deleted += sqlx::query!("DELETE FROM iteration_result WHERE run_id = ?1", id)
+            .execute(&mut *tx)
+            .await
+            .context("Error deleting iteration results from db.")?
+            .rows_affected();
+        deleted += sqlx::query!("DELETE FROM process_info WHERE run_id = ?1", id)
+            .execute(&mut *tx)
+            .await
+            .context("Error deleting process info from db.")?
+            .rows_affected();
+        deleted += sqlx::query!("DELETE FROM run_phase WHERE run_id = ?1", id)
+            .execute(&mut *tx)
+            .await
+            .context("Error deleting run phases from db.")?
+            .rows_affected();
+        deleted += sqlx::query!("DELETE FROM iteration_phase WHERE run_id = ?1", id)
+            .execute(&mut *tx)
+            .await
+            .context("Error deleting iteration phases from db.")?
+            .rows_affected();
+        deleted += sqlx::query!("DELETE FROM endpoint_request WHERE run_id = ?1", id)
+            .execute(&mut *tx)
+            .await
+            .context("Error deleting endpoint requests from db.")?
+            .rows_affected();
+        deleted += sqlx::query!("DELETE FROM run_context WHERE run_id = ?1", id)
+            .execute(&mut *tx)
+            .await
+            .context("Error deleting run context from db.")?
+            .rows_affected();
+        deleted += sqlx::query!("DELETE FROM run WHERE id = ?1", id)
+            .execute(&mut *tx)
+            .await
+            .context("Error deleting run from db.")?
+            .rows_affected();
+        sqlx::query!("DELETE FROM reference_run WHERE run_id = ?1", id)
+            .execute(&mut *tx)
+            .await
+            .context("Error deleting reference run from db.")?;
+
+        if deleted == 0 {
+            return Err(CardamonError::NotFound(format!(
+                "Unable to find run with id: {id}"
+            )));
+        }
+
+        tx.commit().await.context("Error deleting run from db.")
+    }
+
+    async fn fetch_reference(&self) -> Result<Option<ReferenceRun>> {
+        sqlx::query_as!(
+            ReferenceRun,
+            "SELECT * FROM reference_run WHERE project_id = ?1",
+            self.project
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .context("Error fetching reference run from db.")
+    }
+
+    async fn set_reference(&self, id: &str, name: &str) -> Result<()> {
+        self.ensure_exists(id).await?;
+        sqlx::query!(
+            "INSERT INTO reference_run (project_id, run_id, name) VALUES (?1, ?2, ?3) \
+             ON CONFLICT (project_id) DO UPDATE SET run_id = excluded.run_id, name = excluded.name",
+            self.project,
+            id,
+            name
+        )
+        .execute(&self.pool)
+        .await
+        .map(|_| ())
+        .context("Error setting reference run in db.")
+    }
+
+    async fn clear_reference(&self) -> Result<()> {
+        sqlx::query!(
+            "DELETE FROM reference_run WHERE project_id = ?1",
+            self.project
+        )
+        .execute(&self.pool)
+        .await
+        .map(|_| ())
+        .context("Error clearing reference run in db.")
+    }
+}
+
+// //////////////////////////////////////
+// MySqlDao
+
+pub struct MySqlDao {
+    pub pool: sqlx::MySqlPool,
+    project: String,
+}
+impl MySqlDao {
+    pub fn new(pool: sqlx::MySqlPool) -> Self {
+        Self {
+            pool,
+            project: String::from(DEFAULT_PROJECT),
+        }
+    }
+
+    /// Only lists the runs of the given project. Runs created by archiving or annotating a run
+    /// recorded without details are added to it too.
+    pub fn for_project(mut self, project: &str) -> Self {
+        self.project = String::from(project);
+        self
+    }
+
+    /// Adds the spread of the power measured during each iteration to the summaries.
+    async fn with_power_spreads(&self, mut summaries: Vec<RunSummary>) -> Result<Vec<RunSummary>> {
+        if summaries.is_empty() {
+            return Ok(summaries);
+        }
+
+        let mut query = sqlx::QueryBuilder::new(
+            r#"
+            SELECT si.run_id, si.scenario_name, AVG(pm.power)
+            FROM scenario_iteration si
+            JOIN power_metrics pm ON pm.run_id = si.run_id
+                AND pm.timestamp BETWEEN si.start_time AND si.stop_time
+            WHERE si.run_id IN (
+            "#,
+        );
+        let mut run_ids = query.separated(", ");
+        for summary in summaries.iter() {
+            run_ids.push_bind(summary.run_id.as_str());
+        }
+        query.push(
+            r#")
+            GROUP BY si.run_id, si.scenario_name, si.iteration
+            ORDER BY MIN(si.start_time)
+            "#,
+        );
+        let rows = query
+            .build_query_as::<(String, String, f64)>()
+            .fetch_all(&self.pool)
+            .await
+            .context("Error fetching power of iterations from db.")?;
+
+        add_power_spreads(&mut summaries, rows.into_iter(), None);
+        Ok(summaries)
+    }
+
+    /// Runs are recorded by their iterations so a run may exist without any details.
+    async fn ensure_exists(&self, id: &str) -> Result<()> {
+        let exists = sqlx::query_scalar::<_, bool>(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM run WHERE id = ?
+                UNION SELECT 1 FROM scenario_iteration WHERE run_id = ?
+            )
+            "#,
+        )
+        .bind(id)
+        .bind(id)
+        .fetch_one(&self.pool)
+        .await
+        .context("Error fetching run from db.")?;
+
+        if exists {
+            Ok(())
+        } else {
+            Err(CardamonError::NotFound(format!(
+                "Unable to find run with id: {id}"
+            )))
+        }
+    }
+}
+#[async_trait]
+impl RunDao for MySqlDao {
+    async fn fetch(&self, id: &str) -> Result<Option<Run>> {
+        sqlx::query_as("SELECT * FROM run WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+            .context("Error fetching run from db.")
+    }
+
+    async fn fetch_summaries(&self, page: &PageRequest) -> Result<Page<RunSummary>> {
+        let total_items = sqlx::query_scalar::<_, i64>(
+            r#"
+            SELECT COUNT(DISTINCT si.run_id)
+            FROM scenario_iteration si
+            WHERE COALESCE((SELECT r.project_id FROM run r WHERE r.id = si.run_id), 'default') = ?
+            "#,
+        )
+        .bind(&self.project)
+        .fetch_one(&self.pool)
+        .await
+        .context("Error counting runs in db.")?;
+
+        // MySQL can't sort by a CASE mixing text and numbers so the column is picked here
+        let sort_by = match page.sort_by {
+            SortBy::Power => "power",
+            SortBy::Co2 => "co2",
+            SortBy::Name => "run_id",
+            SortBy::LastRun => "start_time",
+        };
+        let order = match page.order {
+            Order::Asc => "ASC",
+            Order::Desc => "DESC",
+        };
+        let items = sqlx::query_as(
+            &format!(
+                r#"
+                WITH summary AS (
+                    SELECT
+                        si.run_id AS run_id,
+                        MIN(si.start_time) AS start_time,
+                        MAX(si.stop_time) AS stop_time,
+                        COUNT(DISTINCT si.scenario_name) AS scenarios,
+                        EXISTS (
+                            SELECT 1 FROM run r WHERE r.id = si.run_id AND r.archived
+                        ) AS archived,
+                        (SELECT r.note FROM run r WHERE r.id = si.run_id) AS note,
+                        (
+                            SELECT AVG(pm.power)
+                            FROM power_metrics pm
+                            WHERE pm.run_id = si.run_id
+                        ) AS power,
+                        (
+                            SELECT ri.manufacture_gwp + ri.use_gwp
+                            FROM run_impact ri
+                            WHERE ri.run_id = si.run_id
+                        ) AS co2
+                    FROM scenario_iteration si
+                    WHERE COALESCE((SELECT r.project_id FROM run r WHERE r.id = si.run_id), 'default') = ?
+                    GROUP BY si.run_id
+                ),
+                reference AS (
+                    SELECT
+                        (
+                            SELECT AVG(pm.power)
+                            FROM power_metrics pm
+                            WHERE pm.run_id = rr.run_id
+                        ) AS power,
+                        (
+                            SELECT ri.manufacture_gwp + ri.use_gwp
+                            FROM run_impact ri
+                            WHERE ri.run_id = rr.run_id
+                        ) AS co2
+                    FROM reference_run rr
+                    WHERE rr.project_id = ?
+                )
+                SELECT
+                    run_id,
+                    start_time,
+                    stop_time,
+                    scenarios,
+                    archived,
+                    note,
+                    power AS measured_power,
+                    co2,
+                    power - (SELECT power FROM reference) AS power_delta,
+                    co2 - (SELECT co2 FROM reference) AS co2_delta
+                FROM summary
+                ORDER BY {sort_by} {order}, start_time DESC
+                LIMIT ? OFFSET ?
+                "#
+            ),
+        )
+        .bind(&self.project)
+        .bind(&self.project)
+        .bind(page.limit())
+        .bind(page.offset())
+        .fetch_all(&self.pool)
+        .await
+        .context("Error fetching runs from db.")?;
+
+        Ok(Page {
+            items: self.with_power_spreads(items).await?,
+            pagination: Pagination::new(page, total_items as u32),
+        })
+    }
+
+    async fn persist(&self, run: &Run) -> Result<()> {
+        sqlx::query("INSERT IGNORE INTO project (id) VALUES (?)")
+            .bind(&run.project_id)
+            .execute(&self.pool)
+            .await
+            .context("Error inserting project into db.")?;
+
+        sqlx::query(
+            "INSERT INTO run (id, baseline_id, archived, note, project_id, schedule, iterations, \
+             observation, ambient_load, stop_time, scenarios) \
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&run.id)
+        .bind(&run.baseline_id)
+        .bind(run.archived)
+        .bind(&run.note)
+        .bind(&run.project_id)
+        .bind(&run.schedule)
+        .bind(run.iterations)
+        .bind(&run.observation)
+        .bind(run.ambient_load)
+        .bind(run.stop_time)
+        .bind(&run.scenarios)
+        .execute(&self.pool)
+        .await
+        .map(|_| ())
+        .context("Error inserting run into db.")
+    }
+
+    async fn set_stop_time(&self, id: &str, stop_time: Option<i64>) -> Result<()> {
+        sqlx::query("UPDATE run SET stop_time = ? WHERE id = ?")
+            .bind(stop_time)
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .context("Error updating stop time of run in db.")
+    }
+
+    async fn fetch_projects(&self) -> Result<Vec<String>> {
+        sqlx::query_scalar("SELECT id FROM project UNION SELECT project_id FROM run ORDER BY 1")
+            .fetch_all(&self.pool)
+            .await
+            .context("Error fetching projects from db.")
+    }
+
+    async fn set_archived(&self, id: &str, archived: bool) -> Result<()> {
+        self.ensure_exists(id).await?;
+        sqlx::query(
+            "INSERT INTO run (id, archived, project_id) VALUES (?, ?, ?) \
+             ON DUPLICATE KEY UPDATE archived = ?",
+        )
+        .bind(id)
+        .bind(archived)
+        .bind(&self.project)
+        .bind(archived)
+        .execute(&self.pool)
+        .await
+        .map(|_| ())
+        .context("Error archiving run in db.")
+    }
+
+    async fn set_note(&self, id: &str, note: Option<&str>) -> Result<()> {
+        self.ensure_exists(id).await?;
+        sqlx::query(
+            "INSERT INTO run (id, note, project_id) VALUES (?, ?, ?) \
+             ON DUPLICATE KEY UPDATE note = ?",
+        )
+        .bind(id)
+        .bind(note)
+        .bind(&self.project)
+        .bind(note)
+        .execute(&self.pool)
+        .await
+        .map(|_| ())
+        .context("Error annotating run in db.")
+    }
+
+    async fn delete(&self, id: &str) -> Result<()> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .context("Error starting transaction.")?;
+
+        let mut deleted = 0;
+        for table in [
+            "cpu_metrics",
+            "power_metrics",
+            "process_event",
+            "scenario_iteration",
+            "run_impact",
+            "energy_mix",
+            "iteration_result",
+            "process_info",
+            "run_phase",
+            "iteration_phase",
+            "endpoint_request",
+            "run_context",
+        ] {
+            deleted += sqlx::query(&format!("DELETE FROM {table} WHERE run_id = ?"))
+                .bind(id)
+                .execute(&mut *tx)
+                .await
+                .context(format!("Error deleting {table} from db."))?
+                .rows_affected();
+        }
+        deleted += sqlx::query("DELETE FROM run WHERE id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await
+            .context("Error deleting run from db.")?
+            .rows_affected();
+        sqlx::query("DELETE FROM reference_run WHERE run_id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await
+            .context("Error deleting reference run from db.")?;
+
+        if deleted == 0 {
+            return Err(CardamonError::NotFound(format!(
+                "Unable to find run with id: {id}"
+            )));
+        }
+
+        tx.commit().await.context("Error deleting run from db.")
+    }
+
+    async fn fetch_reference(&self) -> Result<Option<ReferenceRun>> {
+        sqlx::query_as("SELECT * FROM reference_run WHERE project_id = ?")
+            .bind(&self.project)
+            .fetch_optional(&self.pool)
+            .await
+            .context("Error fetching reference run from db.")
+    }
+
+    async fn set_reference(&self, id: &str, name: &str) -> Result<()> {
+        self.ensure_exists(id).await?;
+        sqlx::query(
+            "INSERT INTO reference_run (project_id, run_id, name) VALUES (?, ?, ?) \
+             ON DUPLICATE KEY UPDATE run_id = ?, name = ?",
+        )
+        .bind(&self.project)
+        .bind(id)
+        .bind(name)
+        .bind(id)
+        .bind(name)
+        .execute(&self.pool)
+        .await
+        .map(|_| ())
+        .context("Error setting reference run in db.")
+    }
+
+    async fn clear_reference(&self) -> Result<()> {
+        sqlx::query("DELETE FROM reference_run WHERE project_id = ?")
+            .bind(&self.project)
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .context("Error clearing reference run in db.")
+    }
+}
+
+// //////////////////////////////////////
+// RemoteDao
+
+pub struct RemoteDao {
+    base_url: String,
+    client: reqwest::Client,
+    project: String,
+    api_token: Option<String>,
+}
+impl RemoteDao {
+    pub fn new(base_url: &str) -> Self {
+        let base_url = base_url.strip_suffix('/').unwrap_or(base_url);
+        Self {
+            base_url: String::from(base_url),
+            client: reqwest::Client::new(),
+            project: String::from(DEFAULT_PROJECT),
+            api_token: None,
+        }
+    }
+
+    pub fn for_project(mut self, project: &str) -> Self {
+        self.project = String::from(project);
+        self
+    }
+
+    /// The server's api token, needed to change runs.
+    pub fn with_api_token(mut self, api_token: &str) -> Self {
+        self.api_token = Some(String::from(api_token));
+        self
+    }
+
+    fn authorized(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match self.api_token.as_deref() {
+            Some(api_token) => request.bearer_auth(api_token),
+            None => request,
+        }
+    }
+}
+#[async_trait]
+impl RunDao for RemoteDao {
+    async fn fetch(&self, id: &str) -> Result<Option<Run>> {
+        self.client
+            .get(format!("{}/run/{id}", self.base_url))
+            .send()
+            .await?
+            .json::<Option<Run>>()
+            .await
+            .context("Error fetching run from remote server")
+    }
+
+    async fn fetch_summaries(&self, page: &PageRequest) -> Result<Page<RunSummary>> {
+        self.client
+            .get(format!("{}/api/runs", self.base_url))
+            .query(page)
+            .query(&[("project", &self.project)])
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Page<RunSummary>>()
+            .await
+            .context("Error fetching runs from remote server")
+    }
+
+    async fn persist(&self, run: &Run) -> Result<()> {
+        self.client
+            .post(format!("{}/run", self.base_url))
+            .json(run)
+            .send()
+            .await?
+            .error_for_status()
+            .map(|_| ())
+            .context("Error persisting run to remote server")
+    }
+
+    async fn set_stop_time(&self, id: &str, stop_time: Option<i64>) -> Result<()> {
+        self.client
+            .post(format!("{}/run/{id}/stop_time", self.base_url))
+            .json(&serde_json::json!({ "stop_time": stop_time }))
+            .send()
+            .await?
+            .error_for_status()
+            .map(|_| ())
+            .context("Error updating stop time of run on remote server")
+    }
+
+    async fn fetch_projects(&self) -> Result<Vec<String>> {
+        self.client
+            .get(format!("{}/api/projects", self.base_url))
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Vec<String>>()
+            .await
+            .context("Error fetching projects from remote server")
+    }
+
+    async fn set_archived(&self, id: &str, archived: bool) -> Result<()> {
+        self.authorized(
+            self.client
+                .put(format!("{}/api/runs/{id}/archived", self.base_url)),
+        )
+        .json(&serde_json::json!({ "archived": archived }))
+        .send()
+        .await?
+        .error_for_status()
+        .map(|_| ())
+        .context("Error archiving run on remote server")
+    }
+
+    async fn set_note(&self, id: &str, note: Option<&str>) -> Result<()> {
+        self.authorized(
+            self.client
+                .patch(format!("{}/api/runs/{id}", self.base_url)),
+        )
+        .json(&serde_json::json!({ "note": note }))
+        .send()
+        .await?
+        .error_for_status()
+        .map(|_| ())
+        .context("Error annotating run on remote server")
+    }
+
+    async fn delete(&self, id: &str) -> Result<()> {
+        self.authorized(
+            self.client
+                .delete(format!("{}/api/runs/{id}", self.base_url)),
+        )
+        .send()
+        .await?
+        .error_for_status()
+        .map(|_| ())
+        .context("Error deleting run from remote server")
+    }
+
+    async fn fetch_reference(&self) -> Result<Option<ReferenceRun>> {
+        self.client
+            .get(format!("{}/api/reference", self.base_url))
+            .query(&[("project", &self.project)])
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Option<ReferenceRun>>()
+            .await
+            .context("Error fetching reference run from remote server")
+    }
+
+    async fn set_reference(&self, id: &str, name: &str) -> Result<()> {
+        self.authorized(self.client.put(format!("{}/api/reference", self.base_url)))
+            .query(&[("project", &self.project)])
+            .json(&serde_json::json!({ "run_id": id, "name": name }))
+            .send()
+            .await?
+            .error_for_status()
+            .map(|_| ())
+            .context("Error setting reference run on remote server")
+    }
+
+    async fn clear_reference(&self) -> Result<()> {
+        self.authorized(
+            self.client
+                .delete(format!("{}/api/reference", self.base_url)),
+        )
+        .query(&[("project", &self.project)])
+        .send()
+        .await?
+        .error_for_status()
+        .map(|_| ())
+        .context("Error clearing reference run on remote server")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_access::{
+        pagination::{Order, SortBy},
+        scenario_iteration::{self, ScenarioIterationDao},
+    };
+
+    #[sqlx::test(migrations = "./migrations", fixtures("../../fixtures/baselines.sql"))]
+    async fn local_run_fetch(pool: sqlx::SqlitePool) -> anyhow::Result<()> {
+        let run_service = LocalDao::new(pool.clone());
+
+        let run = run_service.fetch("1").await?;
+        assert_eq!(run, Some(Run::new("1", Some("idle_1"))));
+
+        run_service.persist(&Run::new("3", None)).await?;
+        let run = run_service.fetch("3").await?;
+        assert_eq!(run, Some(Run::new("3", None)));
+
+        // a resumed run only executes the scenarios it was filtered to
+        let filtered = Run::new("4", None).with_scenarios(&["basket_10", "checkout"]);
+        run_service.persist(&filtered).await?;
+        let run = run_service.fetch("4").await?;
+        assert_eq!(
+            run.and_then(|run| run.scenario_names()),
+            Some(vec![String::from("basket_10"), String::from("checkout")])
+        );
+
+        pool.close().await;
+        Ok(())
+    }
+
+    #[sqlx::test(
+        migrations = "./migrations",
+        fixtures(
+            "../../fixtures/scenario_iterations.sql",
+            "../../fixtures/power_metrics.sql",
+            "../../fixtures/run_impacts.sql"
+        )
+    )]
+    async fn run_summaries_can_be_paged_and_sorted(pool: sqlx::SqlitePool) -> anyhow::Result<()> {
+        let run_service = LocalDao::new(pool.clone());
+
+        // most recent first by default
+        let page = run_service.fetch_summaries(&PageRequest::new(1, 2)).await?;
+        let run_ids = page
+            .items
+            .iter()
+            .map(|run| run.run_id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(run_ids, vec!["3", "2"]);
+        assert_eq!(page.pagination.total_items, 3);
+        assert_eq!(page.pagination.total_pages, 2);
+        assert_eq!(page.items[1].scenarios, 2);
+        assert_eq!(page.items[1].measured_power, Some(40.0));
+        assert_eq!(page.items[1].power_spread, None);
+
+        // runs without an impact come last
+        let page = run_service
+            .fetch_summaries(&PageRequest::new(1, 10).sorted(SortBy::Co2, Order::Desc))
+            .await?;
+        assert_eq!(page.items[0].run_id, "1");
+        assert!(page.items[0].co2.is_some());
+        assert_eq!(page.items[1].co2, None);
+
+        pool.close().await;
+        Ok(())
+    }
+
+    #[sqlx::test(
+        migrations = "./migrations",
+        fixtures(
+            "../../fixtures/scenario_iterations.sql",
+            "../../fixtures/power_metrics.sql",
+            "../../fixtures/run_impacts.sql"
+        )
+    )]
+    async fn archived_runs_are_left_out_of_datasets(pool: sqlx::SqlitePool) -> anyhow::Result<()> {
+        let run_service = LocalDao::new(pool.clone());
+        let scenario_service = scenario_iteration::LocalDao::new(pool.clone());
+
+        // run 3 has no details yet so archiving it records them
+        run_service.set_archived("3", true).await?;
+        assert!(run_service
+            .fetch("3")
+            .await?
+            .is_some_and(|run| run.archived));
+
+        let iterations = scenario_service.fetch_last("scenario_3", 1).await?;
+        assert!(iterations.iter().all(|it| it.run_id == "2"));
+
+        let page = run_service.fetch_summaries(&PageRequest::default()).await?;
+        assert!(page.items[0].archived);
+        assert!(!page.items[1].archived);
+
+        run_service.set_archived("3", false).await?;
+        let iterations = scenario_service.fetch_last("scenario_3", 1).await?;
+        assert!(iterations.iter().all(|it| it.run_id == "3"));
+
+        let err = run_service.set_archived("42", true).await.unwrap_err();
+        assert_eq!(err.code(), "not_found");
+
+        pool.close().await;
+        Ok(())
+    }
+
+    #[sqlx::test(
+        migrations = "./migrations",
+        fixtures(
+            "../../fixtures/baselines.sql",
+            "../../fixtures/scenario_iterations.sql"
+        )
+    )]
+    async fn runs_can_be_annotated(pool: sqlx::SqlitePool) -> anyhow::Result<()> {
+        let run_service = LocalDao::new(pool.clone());
+
+        run_service
+            .persist(&Run::new("4", None).with_note(Some("before caching")))
+            .await?;
+        let run = run_service.fetch("4").await?;
+        assert_eq!(
+            run.and_then(|run| run.note).as_deref(),
+            Some("before caching")
+        );
+
+        // annotating keeps the rest of the run's details
+        run_service.set_note("1", Some("after caching")).await?;
+        let run = run_service.fetch("1").await?.expect("run 1 should exist");
+        assert_eq!(run.note.as_deref(), Some("after caching"));
+        assert_eq!(run.baseline_id.as_deref(), Some("idle_1"));
+
+        // run 3 only has iterations
+        run_service.set_note("3", Some("noisy neighbour")).await?;
+        let page = run_service.fetch_summaries(&PageRequest::new(1, 1)).await?;
+        assert_eq!(page.items[0].note.as_deref(), Some("noisy neighbour"));
+
+        run_service.set_note("1", None).await?;
+        assert_eq!(run_service.fetch("1").await?.and_then(|run| run.note), None);
+
+        pool.close().await;
+        Ok(())
+    }
+
+    #[sqlx::test(
+        migrations = "./migrations",
+        fixtures(
+            "../../fixtures/scenario_iterations.sql",
+            "../../fixtures/power_metrics.sql"
+        )
+    )]
+    async fn run_summaries_show_how_power_varied_between_iterations(
+        pool: sqlx::SqlitePool,
+    ) -> anyhow::Result<()> {
+        // one reading during each iteration of run 3
+        sqlx::query(
+            "INSERT INTO power_metrics (run_id, source, power, timestamp) VALUES \
+             ('3', 'tasmota', 10.0, 1717507790500), \
+             ('3', 'tasmota', 12.0, 1717507792500), \
+             ('3', 'tasmota', 14.0, 1717507794500)",
+        )
+        .execute(&pool)
+        .await?;
+
+        let run_service = LocalDao::new(pool.clone());
+        let page = run_service.fetch_summaries(&PageRequest::default()).await?;
+        let spread = page.items[0]
+            .power_spread
+            .as_ref()
+            .expect("run 3 should have a spread");
+        assert_eq!(page.items[0].run_id, "3");
+        assert_eq!((spread.mean, spread.stddev), (12.0, 2.0));
+        assert_eq!(spread.iterations, 3);
+
+        // run 2's only reading was taken between iterations
+        assert_eq!(page.items[1].power_spread, None);
+
+        // the iterations' power can be combined rather than averaged
+        let page = LocalDao::new(pool.clone())
+            .with_aggregation(Some(AggregationMethod::Max))
+            .fetch_summaries(&PageRequest::default())
+            .await?;
+        assert_eq!(page.items[0].measured_power, Some(14.0));
+        assert_eq!(page.items[1].measured_power, None);
+
+        pool.close().await;
+        Ok(())
+    }
+
+    #[sqlx::test(
+        migrations = "./migrations",
+        fixtures(
+            "../../fixtures/scenario_iterations.sql",
+            "../../fixtures/power_metrics.sql",
+            "../../fixtures/run_impacts.sql"
+        )
+    )]
+    async fn runs_are_compared_against_the_reference_run(
+        pool: sqlx::SqlitePool,
+    ) -> anyhow::Result<()> {
+        let run_service = LocalDao::new(pool.clone());
+        let power_delta = |page: &Page<RunSummary>, run_id: &str| {
+            page.items
+                .iter()
+                .find(|run| run.run_id == run_id)
+                .and_then(|run| run.power_delta)
+        };
+
+        // nothing to compare against until a reference is set
+        let page = run_service.fetch_summaries(&PageRequest::default()).await?;
+        assert!(page.items.iter().all(|run| run.power_delta.is_none()));
+
+        run_service.set_reference("1", "release 1.0").await?;
+        let reference = run_service.fetch_reference().await?;
+        assert_eq!(
+            reference.map(|reference| (reference.run_id, reference.name)),
+            Some(("1".to_string(), "release 1.0".to_string()))
+        );
+        let page = run_service.fetch_summaries(&PageRequest::default()).await?;
+        assert_eq!(power_delta(&page, "1"), Some(0.0));
+        // run 1 averaged 43.83W and run 2 40W
+        assert!(power_delta(&page, "2").is_some_and(|delta| (delta + 3.833).abs() < 0.001));
+
+        // references are kept per project and removed with their run
+        let other_project = LocalDao::new(pool.clone()).for_project("other");
+        assert_eq!(other_project.fetch_reference().await?, None);
+
+        run_service.delete("1").await?;
+        assert_eq!(run_service.fetch_reference().await?, None);
+
+        let err = run_service
+            .set_reference("42", "missing")
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), "not_found");
+
+        pool.close().await;
+        Ok(())
+    }
+
+    #[sqlx::test(
+        migrations = "./migrations",
+        fixtures(
+            "../../fixtures/baselines.sql",
+            "../../fixtures/scenario_iterations.sql",
+            "../../fixtures/cpu_metrics.sql",
+            "../../fixtures/power_metrics.sql",
+            "../../fixtures/run_impacts.sql"
+        )
+    )]
+    async fn deleting_a_run_removes_everything_it_recorded(
+        pool: sqlx::SqlitePool,
+    ) -> anyhow::Result<()> {
+        let run_service = LocalDao::new(pool.clone());
+
+        run_service.delete("1").await?;
+        assert_eq!(run_service.fetch("1").await?, None);
+
+        let remaining = sqlx::query_scalar!(
+            r#"
+            SELECT
+                (SELECT COUNT(*) FROM scenario_iteration WHERE run_id = '1')
+                + (SELECT COUNT(*) FROM cpu_metrics WHERE run_id = '1')
+                + (SELECT COUNT(*) FROM power_metrics WHERE run_id = '1')
+                + (SELECT COUNT(*) FROM run_impact WHERE run_id = '1') AS "count!: i64"
+            "#
+        )
+        .fetch_one(&pool)
+        .await?;
+        assert_eq!(remaining, 0);
+
+        // other runs are untouched
+        assert!(run_service.fetch("2").await?.is_some());
+
+        let err = run_service.delete("1").await.unwrap_err();
+        assert_eq!(err.code(), "not_found");
+
+        pool.close().await;
+        Ok(())
+    }
+}
@@ -0,0 +1,239 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use crate::error::{Context, Result};
+use async_trait::async_trait;
+
+const BYTES_PER_GB: f64 = 1024.0 * 1024.0 * 1024.0;
+
+/// The machine a run executed on, captured when the run starts. Helps explain why the same
+/// scenario behaves differently across machines.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize, sqlx::FromRow)]
+pub struct RunContext {
+    pub run_id: String,
+    /// e.g. "Linux 22.04 Ubuntu".
+    pub os_version: Option<String>,
+    pub kernel_version: Option<String>,
+    /// Bytes of physical memory.
+    pub total_memory: i64,
+    pub core_count: i64,
+    /// The CPU frequency scaling governor, e.g. "powersave", if the OS exposes one.
+    pub cpu_governor: Option<String>,
+    /// e.g. "Docker 24.0.5", None if no container runtime could be reached.
+    pub container_runtime: Option<String>,
+    pub cardamon_version: String,
+    /// The cores the application was pinned to, e.g. "db on 0-1; basket_10 on 4".
+    #[serde(default)]
+    pub pinning: Option<String>,
+}
+impl RunContext {
+    pub fn with_pinning(mut self, pinning: Option<String>) -> Self {
+        self.pinning = pinning;
+        self
+    }
+
+    /// Summarises the context as e.g. "Linux 22.04 Ubuntu (kernel 6.5.0), 8 cores, 15.5 GB RAM,
+    /// governor powersave, Docker 24.0.5, cardamon 0.1.0".
+    pub fn describe(&self) -> String {
+        let mut parts = vec![];
+        match (&self.os_version, &self.kernel_version) {
+            (Some(os), Some(kernel)) => parts.push(format!("{os} (kernel {kernel})")),
+            (Some(os), None) => parts.push(os.clone()),
+            (None, Some(kernel)) => parts.push(format!("kernel {kernel}")),
+            (None, None) => {}
+        }
+        parts.push(format!("{} cores", self.core_count));
+        parts.push(format!(
+            "{:.1} GB RAM",
+            self.total_memory as f64 / BYTES_PER_GB
+        ));
+        if let Some(governor) = &self.cpu_governor {
+            parts.push(format!("governor {governor}"));
+        }
+        if let Some(container_runtime) = &self.container_runtime {
+            parts.push(container_runtime.clone());
+        }
+        if let Some(pinning) = &self.pinning {
+            parts.push(format!("pinned {pinning}"));
+        }
+        parts.push(format!("cardamon {}", self.cardamon_version));
+
+        parts.join(", ")
+    }
+}
+
+#[async_trait]
+pub trait RunContextDao {
+    async fn fetch(&self, run_id: &str) -> Result<Option<RunContext>>;
+    async fn persist(&self, run_context: &RunContext) -> Result<()>;
+}
+
+// //////////////////////////////////////
+// LocalDao
+
+pub struct LocalDao {
+    pub pool: sqlx::SqlitePool,
+}
+impl LocalDao {
+    pub fn new(pool: sqlx::SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+#[async_trait]
+impl RunContextDao for LocalDao {
+    async fn fetch(&self, run_id: &str) -> Result<Option<RunContext>> {
+        sqlx::query_as!(
+            RunContext,
+            "SELECT * FROM run_context WHERE run_id = ?1",
+            run_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .context("Error fetching run context from db.")
+    }
+
+    async fn persist(&self, run_context: &RunContext) -> Result<()> {
+        sqlx::query!(
+            "INSERT INTO run_context (run_id, os_version, kernel_version, total_memory, core_count, cpu_governor, container_runtime, cardamon_version, pinning) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            run_context.run_id,
+            run_context.os_version,
+            run_context.kernel_version,
+            run_context.total_memory,
+            run_context.core_count,
+            run_context.cpu_governor,
+            run_context.container_runtime,
+            run_context.cardamon_version,
+            run_context.pinning
+        )
+        .execute(&self.pool)
+        .await
+        .map(|_| ())
+        .context("Error inserting run context into db.")
+    }
+}
+
+// //////////////////////////////////////
+// MySqlDao
+
+pub struct MySqlDao {
+    pub pool: sqlx::MySqlPool,
+}
+impl MySqlDao {
+    pub fn new(pool: sqlx::MySqlPool) -> Self {
+        Self { pool }
+    }
+}
+#[async_trait]
+impl RunContextDao for MySqlDao {
+    async fn fetch(&self, run_id: &str) -> Result<Option<RunContext>> {
+        sqlx::query_as("SELECT * FROM run_context WHERE run_id = ?")
+            .bind(run_id)
+            .fetch_optional(&self.pool)
+            .await
+            .context("Error fetching run context from db.")
+    }
+
+    async fn persist(&self, run_context: &RunContext) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO run_context (run_id, os_version, kernel_version, total_memory, core_count, cpu_governor, container_runtime, cardamon_version, pinning) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&run_context.run_id)
+        .bind(&run_context.os_version)
+        .bind(&run_context.kernel_version)
+        .bind(run_context.total_memory)
+        .bind(run_context.core_count)
+        .bind(&run_context.cpu_governor)
+        .bind(&run_context.container_runtime)
+        .bind(&run_context.cardamon_version)
+        .bind(&run_context.pinning)
+        .execute(&self.pool)
+        .await
+        .map(|_| ())
+        .context("Error inserting run context into db.")
+    }
+}
+
+// //////////////////////////////////////
+// RemoteDao
+
+pub struct RemoteDao {
+    base_url: String,
+    client: reqwest::Client,
+}
+impl RemoteDao {
+    pub fn new(base_url: &str) -> Self {
+        let base_url = base_url.strip_suffix('/').unwrap_or(base_url);
+        Self {
+            base_url: String::from(base_url),
+            client: reqwest::Client::new(),
+        }
+    }
+}
+#[async_trait]
+impl RunContextDao for RemoteDao {
+    async fn fetch(&self, run_id: &str) -> Result<Option<RunContext>> {
+        self.client
+            .get(format!("{}/run_context/{run_id}", self.base_url))
+            .send()
+            .await?
+            .json::<Option<RunContext>>()
+            .await
+            .context("Error fetching run context from remote server")
+    }
+
+    async fn persist(&self, run_context: &RunContext) -> Result<()> {
+        self.client
+            .post(format!("{}/run_context", self.base_url))
+            .json(run_context)
+            .send()
+            .await?
+            .error_for_status()
+            .map(|_| ())
+            .context("Error persisting run context to remote server")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn local_run_context_fetch(pool: sqlx::SqlitePool) -> anyhow::Result<()> {
+        let run_context_service = LocalDao::new(pool.clone());
+
+        let run_context = RunContext {
+            run_id: "1".to_string(),
+            os_version: Some("Linux 22.04 Ubuntu".to_string()),
+            kernel_version: Some("6.5.0".to_string()),
+            total_memory: 16 * 1024 * 1024 * 1024,
+            core_count: 8,
+            cpu_governor: Some("powersave".to_string()),
+            container_runtime: None,
+            cardamon_version: "0.1.0".to_string(),
+            pinning: None,
+        };
+        run_context_service.persist(&run_context).await?;
+        assert_eq!(
+            run_context_service.fetch("1").await?,
+            Some(run_context.clone())
+        );
+        assert_eq!(run_context_service.fetch("2").await?, None);
+
+        assert_eq!(
+            run_context.describe(),
+            "Linux 22.04 Ubuntu (kernel 6.5.0), 8 cores, 16.0 GB RAM, governor powersave, cardamon 0.1.0"
+        );
+        assert_eq!(
+            run_context
+                .with_pinning(Some(String::from("db on 0-1")))
+                .describe(),
+            "Linux 22.04 Ubuntu (kernel 6.5.0), 8 cores, 16.0 GB RAM, governor powersave, pinned db on 0-1, cardamon 0.1.0"
+        );
+
+        pool.close().await;
+        Ok(())
+    }
+}
@@ -0,0 +1,174 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use crate::error::{Context, Result};
+use async_trait::async_trait;
+
+/// Lifecycle impact of the server a run executed on over the duration of the run, as reported by
+/// the Boavizta API. Global warming potential is in kgCO2e.
+#[derive(Debug, PartialEq, serde::Deserialize, serde::Serialize, sqlx::FromRow)]
+pub struct RunImpact {
+    pub run_id: String,
+    pub duration_hours: f64,
+    /// The share of the carbon emitted manufacturing the server.
+    pub manufacture_gwp: f64,
+    /// Carbon emitted generating the electricity used by the server.
+    pub use_gwp: f64,
+}
+impl RunImpact {
+    pub fn new(run_id: &str, duration_hours: f64, manufacture_gwp: f64, use_gwp: f64) -> Self {
+        Self {
+            run_id: String::from(run_id),
+            duration_hours,
+            manufacture_gwp,
+            use_gwp,
+        }
+    }
+}
+
+#[async_trait]
+pub trait RunImpactDao {
+    async fn fetch(&self, run_id: &str) -> Result<Option<RunImpact>>;
+    async fn persist(&self, run_impact: &RunImpact) -> Result<()>;
+}
+
+// //////////////////////////////////////
+// LocalDao
+
+pub struct LocalDao {
+    pub pool: sqlx::SqlitePool,
+}
+impl LocalDao {
+    pub fn new(pool: sqlx::SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+#[async_trait]
+impl RunImpactDao for LocalDao {
+    async fn fetch(&self, run_id: &str) -> Result<Option<RunImpact>> {
+        sqlx::query_as!(
+            RunImpact,
+            "SELECT * FROM run_impact WHERE run_id = ?1",
+            run_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .context("Error fetching run impact from db.")
+    }
+
+    async fn persist(&self, run_impact: &RunImpact) -> Result<()> {
+        sqlx::query!(
+            "INSERT INTO run_impact (run_id, duration_hours, manufacture_gwp, use_gwp) VALUES (?1, ?2, ?3, ?4)",
+            run_impact.run_id,
+            run_impact.duration_hours,
+            run_impact.manufacture_gwp,
+            run_impact.use_gwp
+        )
+        .execute(&self.pool)
+        .await
+        .map(|_| ())
+        .context("Error inserting run impact into db.")
+    }
+}
+
+// //////////////////////////////////////
+// MySqlDao
+
+pub struct MySqlDao {
+    pub pool: sqlx::MySqlPool,
+}
+impl MySqlDao {
+    pub fn new(pool: sqlx::MySqlPool) -> Self {
+        Self { pool }
+    }
+}
+#[async_trait]
+impl RunImpactDao for MySqlDao {
+    async fn fetch(&self, run_id: &str) -> Result<Option<RunImpact>> {
+        sqlx::query_as("SELECT * FROM run_impact WHERE run_id = ?")
+            .bind(run_id)
+            .fetch_optional(&self.pool)
+            .await
+            .context("Error fetching run impact from db.")
+    }
+
+    async fn persist(&self, run_impact: &RunImpact) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO run_impact (run_id, duration_hours, manufacture_gwp, use_gwp) VALUES (?, ?, ?, ?)",
+        )
+        .bind(&run_impact.run_id)
+        .bind(run_impact.duration_hours)
+        .bind(run_impact.manufacture_gwp)
+        .bind(run_impact.use_gwp)
+        .execute(&self.pool)
+        .await
+        .map(|_| ())
+        .context("Error inserting run impact into db.")
+    }
+}
+
+// //////////////////////////////////////
+// RemoteDao
+
+pub struct RemoteDao {
+    base_url: String,
+    client: reqwest::Client,
+}
+impl RemoteDao {
+    pub fn new(base_url: &str) -> Self {
+        let base_url = base_url.strip_suffix('/').unwrap_or(base_url);
+        Self {
+            base_url: String::from(base_url),
+            client: reqwest::Client::new(),
+        }
+    }
+}
+#[async_trait]
+impl RunImpactDao for RemoteDao {
+    async fn fetch(&self, run_id: &str) -> Result<Option<RunImpact>> {
+        self.client
+            .get(format!("{}/run_impact/{run_id}", self.base_url))
+            .send()
+            .await?
+            .json::<Option<RunImpact>>()
+            .await
+            .context("Error fetching run impact from remote server")
+    }
+
+    async fn persist(&self, run_impact: &RunImpact) -> Result<()> {
+        self.client
+            .post(format!("{}/run_impact", self.base_url))
+            .json(run_impact)
+            .send()
+            .await?
+            .error_for_status()
+            .map(|_| ())
+            .context("Error persisting run impact to remote server")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[sqlx::test(
+        migrations = "./migrations",
+        fixtures("../../fixtures/run_impacts.sql")
+    )]
+    async fn local_run_impact_fetch(pool: sqlx::SqlitePool) -> anyhow::Result<()> {
+        let run_impact_service = LocalDao::new(pool.clone());
+
+        let run_impact = run_impact_service.fetch("1").await?;
+        assert_eq!(run_impact.map(|i| i.manufacture_gwp), Some(0.0143));
+
+        let run_impact = RunImpact::new("2", 0.25, 0.007, 0.001);
+        run_impact_service.persist(&run_impact).await?;
+        assert_eq!(run_impact_service.fetch("2").await?, Some(run_impact));
+
+        pool.close().await;
+        Ok(())
+    }
+}
@@ -0,0 +1,197 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use super::scenario_iteration::ScenarioIteration;
+use crate::error::{Context, Result};
+use async_trait::async_trait;
+
+/// From starting the processes until every one of them is ready.
+pub const PHASE_STARTUP: &str = "startup";
+
+/// From stopping the processes until every one of them has exited.
+pub const PHASE_SHUTDOWN: &str = "shutdown";
+
+/// Part of a run outside its scenarios, measured so the energy used starting and stopping the
+/// application isn't lost. For short-lived jobs it can be more than the scenarios themselves.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize, sqlx::FromRow)]
+pub struct RunPhase {
+    pub run_id: String,
+    /// `startup` or `shutdown`.
+    pub phase: String,
+    pub start_time: i64,
+    pub stop_time: i64,
+}
+impl RunPhase {
+    pub fn new(run_id: &str, phase: &str, start_time: i64, stop_time: i64) -> Self {
+        Self {
+            run_id: String::from(run_id),
+            phase: String::from(phase),
+            start_time,
+            stop_time,
+        }
+    }
+
+    /// The phase as an iteration of a scenario named after it, so its metrics can be fetched and
+    /// modelled the same way.
+    pub fn as_iteration(&self) -> ScenarioIteration {
+        ScenarioIteration::new(
+            &self.run_id,
+            &self.phase,
+            0,
+            self.start_time,
+            self.stop_time,
+        )
+    }
+}
+
+#[async_trait]
+pub trait RunPhaseDao {
+    async fn fetch(&self, run_id: &str) -> Result<Vec<RunPhase>>;
+    async fn persist(&self, run_phase: &RunPhase) -> Result<()>;
+}
+
+// //////////////////////////////////////
+// LocalDao
+
+pub struct LocalDao {
+    pub pool: sqlx::SqlitePool,
+}
+impl LocalDao {
+    pub fn new(pool: sqlx::SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+#[async_trait]
+impl RunPhaseDao for LocalDao {
+    async fn fetch(&self, run_id: &str) -> Result<Vec<RunPhase>> {
+        sqlx::query_as!(
+            RunPhase,
+            "SELECT * FROM run_phase WHERE run_id = ?1 ORDER BY start_time",
+            run_id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Error fetching run phases from db.")
+    }
+
+    async fn persist(&self, run_phase: &RunPhase) -> Result<()> {
+        sqlx::query!(
+            "INSERT OR REPLACE INTO run_phase (run_id, phase, start_time, stop_time) \
+             VALUES (?1, ?2, ?3, ?4)",
+            run_phase.run_id,
+            run_phase.phase,
+            run_phase.start_time,
+            run_phase.stop_time
+        )
+        .execute(&self.pool)
+        .await
+        .map(|_| ())
+        .context("Error inserting run phase into db.")
+    }
+}
+
+// //////////////////////////////////////
+// MySqlDao
+
+pub struct MySqlDao {
+    pub pool: sqlx::MySqlPool,
+}
+impl MySqlDao {
+    pub fn new(pool: sqlx::MySqlPool) -> Self {
+        Self { pool }
+    }
+}
+#[async_trait]
+impl RunPhaseDao for MySqlDao {
+    async fn fetch(&self, run_id: &str) -> Result<Vec<RunPhase>> {
+        sqlx::query_as("SELECT * FROM run_phase WHERE run_id = ? ORDER BY start_time")
+            .bind(run_id)
+            .fetch_all(&self.pool)
+            .await
+            .context("Error fetching run phases from db.")
+    }
+
+    async fn persist(&self, run_phase: &RunPhase) -> Result<()> {
+        sqlx::query(
+            "REPLACE INTO run_phase (run_id, phase, start_time, stop_time) VALUES (?, ?, ?, ?)",
+        )
+        .bind(&run_phase.run_id)
+        .bind(&run_phase.phase)
+        .bind(run_phase.start_time)
+        .bind(run_phase.stop_time)
+        .execute(&self.pool)
+        .await
+        .map(|_| ())
+        .context("Error inserting run phase into db.")
+    }
+}
+
+// //////////////////////////////////////
+// RemoteDao
+
+pub struct RemoteDao {
+    base_url: String,
+    client: reqwest::Client,
+}
+impl RemoteDao {
+    pub fn new(base_url: &str) -> Self {
+        let base_url = base_url.strip_suffix('/').unwrap_or(base_url);
+        Self {
+            base_url: String::from(base_url),
+            client: reqwest::Client::new(),
+        }
+    }
+}
+#[async_trait]
+impl RunPhaseDao for RemoteDao {
+    async fn fetch(&self, run_id: &str) -> Result<Vec<RunPhase>> {
+        self.client
+            .get(format!("{}/run_phase/{run_id}", self.base_url))
+            .send()
+            .await?
+            .json::<Vec<RunPhase>>()
+            .await
+            .context("Error fetching run phases from remote server")
+    }
+
+    async fn persist(&self, run_phase: &RunPhase) -> Result<()> {
+        self.client
+            .post(format!("{}/run_phase", self.base_url))
+            .json(run_phase)
+            .send()
+            .await?
+            .error_for_status()
+            .map(|_| ())
+            .context("Error persisting run phase to remote server")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn local_run_phase_fetch(pool: sqlx::SqlitePool) -> anyhow::Result<()> {
+        let run_phase_service = LocalDao::new(pool.clone());
+
+        let shutdown = RunPhase::new("1", PHASE_SHUTDOWN, 9000, 12000);
+        let startup = RunPhase::new("1", PHASE_STARTUP, 1000, 4000);
+        run_phase_service.persist(&shutdown).await?;
+        run_phase_service.persist(&startup).await?;
+        assert_eq!(
+            run_phase_service.fetch("1").await?,
+            vec![startup.clone(), shutdown]
+        );
+        assert!(run_phase_service.fetch("2").await?.is_empty());
+
+        let iteration = startup.as_iteration();
+        assert_eq!(iteration.scenario_name, PHASE_STARTUP);
+        assert_eq!((iteration.start_time, iteration.stop_time), (1000, 4000));
+
+        pool.close().await;
+        Ok(())
+    }
+}
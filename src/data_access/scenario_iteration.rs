@@ -4,9 +4,33 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
 
-use anyhow::Context;
+use super::{
+    pagination::{Order, Page, PageRequest, Pagination, SortBy},
+    search, DEFAULT_PROJECT,
+};
+use crate::{
+    dataset::AggregationMethod,
+    error::{CardamonError, Context, Result},
+    stats::Trend,
+};
 use async_trait::async_trait;
 
+/// The number of recent runs the trend of a scenario is fitted to by default.
+pub const TREND_RUNS: u32 = 10;
+
+/// Status of an iteration where every observed process kept running.
+pub const STATUS_OK: &str = "ok";
+
+/// Status of an iteration where an observed process exited before the scenario finished.
+pub const STATUS_PROCESS_EXITED: &str = "process_exited";
+
+/// Status of an iteration which was cut short because its run was cancelled.
+pub const STATUS_CANCELLED: &str = "cancelled";
+
+fn default_status() -> String {
+    STATUS_OK.to_string()
+}
+
 #[derive(PartialEq, Debug, serde::Deserialize, serde::Serialize, sqlx::FromRow)]
 pub struct ScenarioIteration {
     pub run_id: String,
@@ -14,6 +38,11 @@ pub struct ScenarioIteration {
     pub iteration: i64,
     pub start_time: i64,
     pub stop_time: i64,
+    #[serde(default = "default_status")]
+    pub status: String,
+    /// True if the observed processes were restarted before the iteration.
+    #[serde(default)]
+    pub cold_start: bool,
 }
 impl ScenarioIteration {
     pub fn new(
@@ -29,18 +58,80 @@ impl ScenarioIteration {
             iteration,
             start_time,
             stop_time,
+            status: default_status(),
+            cold_start: false,
         }
     }
+
+    pub fn with_status(mut self, status: &str) -> Self {
+        self.status = status.to_string();
+        self
+    }
+
+    pub fn with_cold_start(mut self, cold_start: bool) -> Self {
+        self.cold_start = cold_start;
+        self
+    }
+
+    /// True if every observed process kept running throughout the iteration.
+    pub fn is_ok(&self) -> bool {
+        self.status == STATUS_OK
+    }
+}
+
+/// A scenario across every run which included it.
+#[derive(PartialEq, Debug, serde::Deserialize, serde::Serialize, sqlx::FromRow)]
+pub struct ScenarioSummary {
+    pub scenario_name: String,
+    /// Start time of the most recent iteration.
+    pub last_run: i64,
+    /// The number of runs which included the scenario.
+    pub runs: i64,
+    /// Average power measured by a smart plug or BMC while the scenario was running (W), None
+    /// without one. `energy_wh` is modelled for every scenario.
+    #[serde(alias = "power")]
+    pub measured_power: Option<f64>,
+    /// Average embodied and use emissions of the runs which included the scenario (kgCO2eq).
+    pub co2: Option<f64>,
+    /// Trend of the CPU usage of an iteration over recent runs (% per run), None for fewer than
+    /// two runs.
+    #[serde(default, alias = "trend")]
+    #[sqlx(skip)]
+    pub cpu_trend: Option<Trend>,
+    /// Trend of the energy the system under test used per iteration over recent runs (Wh per
+    /// run), fitted like `card stats`. None without a server config file or for fewer than two
+    /// runs.
+    #[serde(default)]
+    #[sqlx(skip)]
+    pub energy_trend: Option<Trend>,
+    /// Energy the system under test used per iteration of the last run (Wh), modelled with the
+    /// server's config file. None if the server has no config file.
+    #[serde(default)]
+    #[sqlx(skip)]
+    pub energy_wh: Option<f64>,
 }
 
 #[async_trait]
 pub trait ScenarioIterationDao {
-    async fn fetch_last(
+    async fn fetch_last(&self, scenario_name: &str, n: u32) -> Result<Vec<ScenarioIteration>>;
+
+    /// Every iteration of the scenario in the given run, archived or not.
+    async fn fetch_run(&self, scenario_name: &str, run_id: &str) -> Result<Vec<ScenarioIteration>>;
+
+    /// Every iteration of every scenario in the given run, in the order they started.
+    async fn fetch_by_run(&self, run_id: &str) -> Result<Vec<ScenarioIteration>>;
+
+    async fn fetch_scenarios(&self, page: &PageRequest) -> Result<Page<ScenarioSummary>>;
+
+    /// Scenarios whose names fuzzy match the query, ignoring case, best matches first. The sort
+    /// order of the page request is ignored.
+    async fn fetch_by_query(
         &self,
-        scenario_name: &str,
-        n: u32,
-    ) -> anyhow::Result<Vec<ScenarioIteration>>;
-    async fn persist(&self, scenario_iteration: &ScenarioIteration) -> anyhow::Result<()>;
+        query: &str,
+        page: &PageRequest,
+    ) -> Result<Page<ScenarioSummary>>;
+
+    async fn persist(&self, scenario_iteration: &ScenarioIteration) -> Result<()>;
 }
 
 // //////////////////////////////////////
@@ -48,48 +139,436 @@ pub trait ScenarioIterationDao {
 
 pub struct LocalDao {
     pub pool: sqlx::SqlitePool,
+    project: String,
+    trend_runs: u32,
+    aggregation: Option<AggregationMethod>,
 }
 impl LocalDao {
     pub fn new(pool: sqlx::SqlitePool) -> Self {
-        Self { pool }
+        Self {
+            pool,
+            project: DEFAULT_PROJECT.to_string(),
+            trend_runs: TREND_RUNS,
+            aggregation: None,
+        }
+    }
+
+    /// Combines the measured power of each iteration with the given method rather than averaging
+    /// every sample of the scenario. Sorting by power still uses the average.
+    pub fn with_aggregation(mut self, aggregation: Option<AggregationMethod>) -> Self {
+        self.aggregation = aggregation;
+        self
+    }
+
+    /// Only reads the scenarios of runs recorded against the given project.
+    pub fn for_project(mut self, project: &str) -> Self {
+        self.project = project.to_string();
+        self
+    }
+
+    /// Fits the trend of each scenario summary to this many recent runs.
+    pub fn with_trend_runs(mut self, trend_runs: u32) -> Self {
+        self.trend_runs = trend_runs;
+        self
+    }
+
+    /// Fits a line to the average CPU usage of the scenario's iterations in each of its recent
+    /// unarchived runs. Each sample is weighted by the seconds it covers, a second if it didn't
+    /// record its interval, so the sampling rate doesn't change the usage.
+    async fn fetch_trend(&self, scenario_name: &str) -> Result<Option<Trend>> {
+        let usage = sqlx::query_scalar!(
+            r#"
+            WITH project_iteration AS (
+                SELECT si.*
+                FROM scenario_iteration si
+                WHERE si.scenario_name = ?1
+                    AND COALESCE((SELECT r.project_id FROM run r WHERE r.id = si.run_id), 'default') = ?3
+                    AND si.run_id NOT IN (SELECT id FROM run WHERE archived)
+            ),
+            recent_run AS (
+                SELECT run_id, MIN(start_time) AS start_time, COUNT(*) AS iterations
+                FROM project_iteration
+                GROUP BY run_id
+                ORDER BY start_time DESC
+                LIMIT ?2
+            )
+            SELECT
+                (
+                    SELECT COALESCE(SUM(
+                        cm.cpu_usage * COALESCE(cm.sample_interval, 1.0)
+                            / (NULLIF(si.stop_time - si.start_time, 0) / 1000.0)
+                    ), 0.0)
+                    FROM cpu_metrics cm
+                    JOIN project_iteration si ON cm.run_id = si.run_id
+                        AND cm.timestamp BETWEEN si.start_time AND si.stop_time
+                    WHERE si.run_id = rr.run_id
+                ) / rr.iterations AS "cpu_usage!: f64"
+            FROM recent_run rr
+            ORDER BY rr.start_time ASC
+            "#,
+            scenario_name,
+            self.trend_runs,
+            self.project
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Error fetching scenario trend from db.")?;
+
+        Ok(Trend::new(&usage))
+    }
+
+    /// Replaces the power of the summaries with the power of their iterations, oldest first,
+    /// combined with the aggregation method if one was given.
+    async fn with_aggregated_power(
+        &self,
+        mut summaries: Vec<ScenarioSummary>,
+    ) -> Result<Vec<ScenarioSummary>> {
+        let Some(aggregation) = self.aggregation else {
+            return Ok(summaries);
+        };
+
+        let names = summaries
+            .iter()
+            .map(|summary| summary.scenario_name.as_str())
+            .collect::<Vec<_>>();
+        let names_json = serde_json::to_string(&names).expect("names should serialize");
+        let rows = sqlx::query!(
+            r#"
+            WITH project_iteration AS (
+                SELECT si.*
+                FROM scenario_iteration si
+                WHERE COALESCE((SELECT r.project_id FROM run r WHERE r.id = si.run_id), 'default') = ?2
+                    AND si.run_id NOT IN (SELECT id FROM run WHERE archived)
+            )
+            SELECT
+                si.scenario_name AS "scenario_name!",
+                AVG(pm.power) AS "power!: f64"
+            FROM project_iteration si
+            JOIN power_metrics pm ON pm.run_id = si.run_id
+                AND pm.timestamp BETWEEN si.start_time AND si.stop_time
+            WHERE si.scenario_name IN (SELECT value FROM json_each(?1))
+            GROUP BY si.run_id, si.scenario_name, si.iteration
+            ORDER BY MIN(si.start_time)
+            "#,
+            names_json,
+            self.project
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Error fetching power of iterations from db.")?;
+
+        for summary in summaries.iter_mut() {
+            let power = rows
+                .iter()
+                .filter(|row| row.scenario_name == summary.scenario_name)
+                .map(|row| row.power)
+                .collect::<Vec<_>>();
+            summary.measured_power = aggregation.aggregate(&power);
+        }
+        Ok(summaries)
+    }
+
+    async fn with_trends(
+        &self,
+        mut summaries: Vec<ScenarioSummary>,
+    ) -> Result<Vec<ScenarioSummary>> {
+        for summary in summaries.iter_mut() {
+            summary.cpu_trend = self.fetch_trend(&summary.scenario_name).await?;
+        }
+        Ok(summaries)
     }
 }
 #[async_trait]
 impl ScenarioIterationDao for LocalDao {
-    async fn fetch_last(
-        &self,
-        scenario_name: &str,
-        n: u32,
-    ) -> anyhow::Result<Vec<ScenarioIteration>> {
+    async fn fetch_last(&self, scenario_name: &str, n: u32) -> Result<Vec<ScenarioIteration>> {
         sqlx::query_as!(
             ScenarioIteration,
             r#"
-            SELECT * 
-            FROM scenario_iteration 
+            WITH project_iteration AS (
+                SELECT si.*
+                FROM scenario_iteration si
+                WHERE COALESCE((SELECT r.project_id FROM run r WHERE r.id = si.run_id), 'default') = ?3
+            )
+            SELECT
+                run_id AS "run_id!",
+                scenario_name AS "scenario_name!",
+                iteration AS "iteration!: i64",
+                start_time AS "start_time!: i64",
+                stop_time AS "stop_time!: i64",
+                status AS "status!",
+                cold_start AS "cold_start!: bool"
+            FROM project_iteration 
             WHERE scenario_name = ?1 AND run_id in (
                 SELECT run_id 
-                FROM scenario_iteration 
+                FROM project_iteration 
                 WHERE scenario_name = ?1 
+                    AND run_id NOT IN (SELECT id FROM run WHERE archived)
                 GROUP BY run_id 
                 ORDER BY start_time DESC
                 LIMIT ?2
             )
             "#,
             scenario_name,
-            n
+            n,
+            self.project
         )
         .fetch_all(&self.pool)
         .await
         .context("Error fetching scenarios")
     }
 
-    async fn persist(&self, scenario_iteration: &ScenarioIteration) -> anyhow::Result<()> {
-        sqlx::query!("INSERT INTO scenario_iteration (run_id, scenario_name, iteration, start_time, stop_time) VALUES (?1, ?2, ?3, ?4, ?5)", 
+    async fn fetch_run(&self, scenario_name: &str, run_id: &str) -> Result<Vec<ScenarioIteration>> {
+        sqlx::query_as!(
+            ScenarioIteration,
+            r#"
+            SELECT
+                run_id AS "run_id!",
+                scenario_name AS "scenario_name!",
+                iteration AS "iteration!: i64",
+                start_time AS "start_time!: i64",
+                stop_time AS "stop_time!: i64",
+                status AS "status!",
+                cold_start AS "cold_start!: bool"
+            FROM scenario_iteration
+            WHERE scenario_name = ?1 AND run_id = ?2
+            ORDER BY iteration
+            "#,
+            scenario_name,
+            run_id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Error fetching scenario iterations of run")
+    }
+
+    async fn fetch_by_run(&self, run_id: &str) -> Result<Vec<ScenarioIteration>> {
+        sqlx::query_as!(
+            ScenarioIteration,
+            r#"
+            SELECT
+                run_id AS "run_id!",
+                scenario_name AS "scenario_name!",
+                iteration AS "iteration!: i64",
+                start_time AS "start_time!: i64",
+                stop_time AS "stop_time!: i64",
+                status AS "status!",
+                cold_start AS "cold_start!: bool"
+            FROM scenario_iteration
+            WHERE run_id = ?1
+            ORDER BY start_time
+            "#,
+            run_id
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Error fetching scenario iterations of run")
+    }
+
+    async fn fetch_scenarios(&self, page: &PageRequest) -> Result<Page<ScenarioSummary>> {
+        let total_items = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(DISTINCT si.scenario_name) AS "count!: u32"
+            FROM scenario_iteration si
+            WHERE COALESCE((SELECT r.project_id FROM run r WHERE r.id = si.run_id), 'default') = ?1
+                AND si.run_id NOT IN (SELECT id FROM run WHERE archived)
+            "#,
+            self.project
+        )
+        .fetch_one(&self.pool)
+        .await
+        .context("Error counting scenarios in db.")?;
+
+        let sort_by = page.sort_by.as_str();
+        let order = page.order.as_str();
+        let limit = page.limit();
+        let offset = page.offset();
+        let rows = sqlx::query!(
+            r#"
+            WITH project_iteration AS (
+                SELECT si.*
+                FROM scenario_iteration si
+                WHERE COALESCE((SELECT r.project_id FROM run r WHERE r.id = si.run_id), 'default') = ?5
+                    AND si.run_id NOT IN (SELECT id FROM run WHERE archived)
+            ),
+            summary AS (
+                SELECT
+                    si.scenario_name AS name,
+                    MAX(si.start_time) AS last_run,
+                    COUNT(DISTINCT si.run_id) AS runs,
+                    (
+                        SELECT AVG(pm.power)
+                        FROM power_metrics pm
+                        JOIN project_iteration s ON pm.run_id = s.run_id
+                            AND pm.timestamp BETWEEN s.start_time AND s.stop_time
+                        WHERE s.scenario_name = si.scenario_name
+                    ) AS power,
+                    (
+                        SELECT AVG(ri.manufacture_gwp + ri.use_gwp)
+                        FROM run_impact ri
+                        WHERE ri.run_id IN (
+                            SELECT s.run_id
+                            FROM project_iteration s
+                            WHERE s.scenario_name = si.scenario_name
+                        )
+                    ) AS co2
+                FROM project_iteration si
+                GROUP BY si.scenario_name
+            )
+            SELECT
+                name AS "scenario_name!",
+                last_run AS "last_run!: i64",
+                runs AS "runs!: i64",
+                power AS "power: f64",
+                co2 AS "co2: f64"
+            FROM summary
+            ORDER BY
+                CASE WHEN ?2 = 'asc' THEN
+                    CASE ?1 WHEN 'power' THEN power WHEN 'co2' THEN co2 WHEN 'name' THEN name ELSE last_run END
+                END ASC,
+                CASE WHEN ?2 = 'desc' THEN
+                    CASE ?1 WHEN 'power' THEN power WHEN 'co2' THEN co2 WHEN 'name' THEN name ELSE last_run END
+                END DESC,
+                name ASC
+            LIMIT ?3 OFFSET ?4
+            "#,
+            sort_by,
+            order,
+            limit,
+            offset,
+            self.project
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Error fetching scenarios from db.")?;
+        let items = rows
+            .into_iter()
+            .map(|row| ScenarioSummary {
+                scenario_name: row.scenario_name,
+                last_run: row.last_run,
+                runs: row.runs,
+                measured_power: row.power,
+                co2: row.co2,
+                cpu_trend: None,
+                energy_trend: None,
+                energy_wh: None,
+            })
+            .collect();
+
+        let items = self.with_aggregated_power(items).await?;
+        Ok(Page {
+            items: self.with_trends(items).await?,
+            pagination: Pagination::new(page, total_items),
+        })
+    }
+
+    async fn fetch_by_query(
+        &self,
+        query: &str,
+        page: &PageRequest,
+    ) -> Result<Page<ScenarioSummary>> {
+        let pattern = search::like_pattern(query);
+        let names = sqlx::query_scalar!(
+            r#"
+            SELECT DISTINCT si.scenario_name
+            FROM scenario_iteration si
+            WHERE LOWER(si.scenario_name) LIKE ?1 ESCAPE '\'
+                AND COALESCE((SELECT r.project_id FROM run r WHERE r.id = si.run_id), 'default') = ?2
+                AND si.run_id NOT IN (SELECT id FROM run WHERE archived)
+            "#,
+            pattern,
+            self.project
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Error searching scenarios in db.")?;
+
+        // rank the matches and keep the requested page
+        let mut ranked = names
+            .into_iter()
+            .filter_map(|name| search::score(query, &name).map(|score| (score, name)))
+            .collect::<Vec<_>>();
+        ranked.sort_by(|(a_score, a_name), (b_score, b_name)| {
+            b_score.cmp(a_score).then_with(|| a_name.cmp(b_name))
+        });
+        let total_items = ranked.len() as u32;
+        let names = ranked
+            .into_iter()
+            .skip(page.offset() as usize)
+            .take(page.limit() as usize)
+            .map(|(_, name)| name)
+            .collect::<Vec<_>>();
+
+        let names_json = serde_json::to_string(&names).expect("names should serialize");
+        let rows = sqlx::query!(
+            r#"
+            WITH project_iteration AS (
+                SELECT si.*
+                FROM scenario_iteration si
+                WHERE COALESCE((SELECT r.project_id FROM run r WHERE r.id = si.run_id), 'default') = ?2
+                    AND si.run_id NOT IN (SELECT id FROM run WHERE archived)
+            )
+            SELECT
+                si.scenario_name AS "scenario_name!",
+                MAX(si.start_time) AS "last_run!: i64",
+                COUNT(DISTINCT si.run_id) AS "runs!: i64",
+                (
+                    SELECT AVG(pm.power)
+                    FROM power_metrics pm
+                    JOIN project_iteration s ON pm.run_id = s.run_id
+                        AND pm.timestamp BETWEEN s.start_time AND s.stop_time
+                    WHERE s.scenario_name = si.scenario_name
+                ) AS "power: f64",
+                (
+                    SELECT AVG(ri.manufacture_gwp + ri.use_gwp)
+                    FROM run_impact ri
+                    WHERE ri.run_id IN (
+                        SELECT s.run_id
+                        FROM project_iteration s
+                        WHERE s.scenario_name = si.scenario_name
+                    )
+                ) AS "co2: f64"
+            FROM project_iteration si
+            WHERE si.scenario_name IN (SELECT value FROM json_each(?1))
+            GROUP BY si.scenario_name
+            "#,
+            names_json,
+            self.project
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Error fetching scenarios from db.")?;
+        let mut summaries = rows
+            .into_iter()
+            .map(|row| ScenarioSummary {
+                scenario_name: row.scenario_name,
+                last_run: row.last_run,
+                runs: row.runs,
+                measured_power: row.power,
+                co2: row.co2,
+                cpu_trend: None,
+                energy_trend: None,
+                energy_wh: None,
+            })
+            .collect::<Vec<_>>();
+        summaries
+            .sort_by_key(|summary| names.iter().position(|name| *name == summary.scenario_name));
+
+        let summaries = self.with_aggregated_power(summaries).await?;
+        Ok(Page {
+            items: self.with_trends(summaries).await?,
+            pagination: Pagination::new(page, total_items),
+        })
+    }
+
+    async fn persist(&self, scenario_iteration: &ScenarioIteration) -> Result<()> {
+        sqlx::query!("INSERT OR REPLACE INTO scenario_iteration (run_id, scenario_name, iteration, start_time, stop_time, status, cold_start) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)", 
             scenario_iteration.run_id,
             scenario_iteration.scenario_name,
             scenario_iteration.iteration,
             scenario_iteration.start_time,
-            scenario_iteration.stop_time)
+            scenario_iteration.stop_time,
+            scenario_iteration.status,
+            scenario_iteration.cold_start)
             .execute(&self.pool)
             .await
             .map(|_| ())
@@ -97,12 +576,314 @@ impl ScenarioIterationDao for LocalDao {
     }
 }
 
+// //////////////////////////////////////
+// MySqlDao
+
+/// Summarises the scenarios of the project's unarchived runs, the project is bound to its only
+/// parameter. Callers filter, group and sort the iterations, `si`.
+const MYSQL_SUMMARY: &str = r#"
+    WITH project_iteration AS (
+        SELECT si.*
+        FROM scenario_iteration si
+        WHERE COALESCE((SELECT r.project_id FROM run r WHERE r.id = si.run_id), 'default') = ?
+            AND si.run_id NOT IN (SELECT id FROM run WHERE archived)
+    )
+    SELECT
+        si.scenario_name AS scenario_name,
+        MAX(si.start_time) AS last_run,
+        COUNT(DISTINCT si.run_id) AS runs,
+        (
+            SELECT AVG(pm.power)
+            FROM power_metrics pm
+            JOIN project_iteration s ON pm.run_id = s.run_id
+                AND pm.timestamp BETWEEN s.start_time AND s.stop_time
+            WHERE s.scenario_name = si.scenario_name
+        ) AS measured_power,
+        (
+            SELECT AVG(ri.manufacture_gwp + ri.use_gwp)
+            FROM run_impact ri
+            WHERE ri.run_id IN (
+                SELECT s.run_id
+                FROM project_iteration s
+                WHERE s.scenario_name = si.scenario_name
+            )
+        ) AS co2
+    FROM project_iteration si
+"#;
+
+pub struct MySqlDao {
+    pub pool: sqlx::MySqlPool,
+    project: String,
+    trend_runs: u32,
+}
+impl MySqlDao {
+    pub fn new(pool: sqlx::MySqlPool) -> Self {
+        Self {
+            pool,
+            project: DEFAULT_PROJECT.to_string(),
+            trend_runs: TREND_RUNS,
+        }
+    }
+
+    /// Only reads the scenarios of runs recorded against the given project.
+    pub fn for_project(mut self, project: &str) -> Self {
+        self.project = project.to_string();
+        self
+    }
+
+    /// Fits a line to the average CPU usage of the scenario's iterations in each of its recent
+    /// unarchived runs, weighting samples by the seconds they cover like the SQLite dao.
+    async fn fetch_trend(&self, scenario_name: &str) -> Result<Option<Trend>> {
+        // literals are doubles (1e0) rather than decimals so the usage decodes as f64
+        let usage = sqlx::query_scalar::<_, f64>(
+            r#"
+            WITH project_iteration AS (
+                SELECT si.*
+                FROM scenario_iteration si
+                WHERE si.scenario_name = ?
+                    AND COALESCE((SELECT r.project_id FROM run r WHERE r.id = si.run_id), 'default') = ?
+                    AND si.run_id NOT IN (SELECT id FROM run WHERE archived)
+            ),
+            recent_run AS (
+                SELECT run_id, MIN(start_time) AS start_time, COUNT(*) AS iterations
+                FROM project_iteration
+                GROUP BY run_id
+                ORDER BY start_time DESC
+                LIMIT ?
+            )
+            SELECT
+                (
+                    SELECT COALESCE(SUM(
+                        cm.cpu_usage * COALESCE(cm.sample_interval, 1e0)
+                            / (NULLIF(si.stop_time - si.start_time, 0) / 1e3)
+                    ), 0e0)
+                    FROM cpu_metrics cm
+                    JOIN project_iteration si ON cm.run_id = si.run_id
+                        AND cm.timestamp BETWEEN si.start_time AND si.stop_time
+                    WHERE si.run_id = rr.run_id
+                ) / rr.iterations
+            FROM recent_run rr
+            ORDER BY rr.start_time ASC
+            "#,
+        )
+        .bind(scenario_name)
+        .bind(&self.project)
+        .bind(self.trend_runs)
+        .fetch_all(&self.pool)
+        .await
+        .context("Error fetching scenario trend from db.")?;
+
+        Ok(Trend::new(&usage))
+    }
+
+    async fn with_trends(
+        &self,
+        mut summaries: Vec<ScenarioSummary>,
+    ) -> Result<Vec<ScenarioSummary>> {
+        for summary in summaries.iter_mut() {
+            summary.cpu_trend = self.fetch_trend(&summary.scenario_name).await?;
+        }
+        Ok(summaries)
+    }
+}
+#[async_trait]
+impl ScenarioIterationDao for MySqlDao {
+    async fn fetch_last(&self, scenario_name: &str, n: u32) -> Result<Vec<ScenarioIteration>> {
+        // MySQL doesn't allow LIMIT in an IN sub-query, the recent runs are a derived table
+        sqlx::query_as(
+            r#"
+            WITH project_iteration AS (
+                SELECT si.*
+                FROM scenario_iteration si
+                WHERE COALESCE((SELECT r.project_id FROM run r WHERE r.id = si.run_id), 'default') = ?
+            )
+            SELECT run_id, scenario_name, iteration, start_time, stop_time, status, cold_start
+            FROM project_iteration
+            WHERE scenario_name = ? AND run_id IN (
+                SELECT run_id FROM (
+                    SELECT run_id
+                    FROM project_iteration
+                    WHERE scenario_name = ?
+                        AND run_id NOT IN (SELECT id FROM run WHERE archived)
+                    GROUP BY run_id
+                    ORDER BY MIN(start_time) DESC
+                    LIMIT ?
+                ) recent_run
+            )
+            "#,
+        )
+        .bind(&self.project)
+        .bind(scenario_name)
+        .bind(scenario_name)
+        .bind(n)
+        .fetch_all(&self.pool)
+        .await
+        .context("Error fetching scenarios")
+    }
+
+    async fn fetch_run(&self, scenario_name: &str, run_id: &str) -> Result<Vec<ScenarioIteration>> {
+        sqlx::query_as(
+            r#"
+            SELECT run_id, scenario_name, iteration, start_time, stop_time, status, cold_start
+            FROM scenario_iteration
+            WHERE scenario_name = ? AND run_id = ?
+            ORDER BY iteration
+            "#,
+        )
+        .bind(scenario_name)
+        .bind(run_id)
+        .fetch_all(&self.pool)
+        .await
+        .context("Error fetching scenario iterations of run")
+    }
+
+    async fn fetch_by_run(&self, run_id: &str) -> Result<Vec<ScenarioIteration>> {
+        sqlx::query_as(
+            r#"
+            SELECT run_id, scenario_name, iteration, start_time, stop_time, status, cold_start
+            FROM scenario_iteration
+            WHERE run_id = ?
+            ORDER BY start_time
+            "#,
+        )
+        .bind(run_id)
+        .fetch_all(&self.pool)
+        .await
+        .context("Error fetching scenario iterations of run")
+    }
+
+    async fn fetch_scenarios(&self, page: &PageRequest) -> Result<Page<ScenarioSummary>> {
+        let total_items = sqlx::query_scalar::<_, i64>(
+            r#"
+            SELECT COUNT(DISTINCT si.scenario_name)
+            FROM scenario_iteration si
+            WHERE COALESCE((SELECT r.project_id FROM run r WHERE r.id = si.run_id), 'default') = ?
+                AND si.run_id NOT IN (SELECT id FROM run WHERE archived)
+            "#,
+        )
+        .bind(&self.project)
+        .fetch_one(&self.pool)
+        .await
+        .context("Error counting scenarios in db.")?;
+
+        // MySQL can't sort by a CASE mixing text and numbers so the column is picked here
+        let sort_by = match page.sort_by {
+            SortBy::Power => "measured_power",
+            SortBy::Co2 => "co2",
+            SortBy::Name => "scenario_name",
+            SortBy::LastRun => "last_run",
+        };
+        let order = match page.order {
+            Order::Asc => "ASC",
+            Order::Desc => "DESC",
+        };
+        let items = sqlx::query_as(&format!(
+            "{MYSQL_SUMMARY} GROUP BY si.scenario_name \
+             ORDER BY {sort_by} {order}, scenario_name ASC LIMIT ? OFFSET ?"
+        ))
+        .bind(&self.project)
+        .bind(page.limit())
+        .bind(page.offset())
+        .fetch_all(&self.pool)
+        .await
+        .context("Error fetching scenarios from db.")?;
+
+        Ok(Page {
+            items: self.with_trends(items).await?,
+            pagination: Pagination::new(page, total_items as u32),
+        })
+    }
+
+    async fn fetch_by_query(
+        &self,
+        query: &str,
+        page: &PageRequest,
+    ) -> Result<Page<ScenarioSummary>> {
+        let pattern = search::like_pattern(query);
+        let names = sqlx::query_scalar::<_, String>(
+            r#"
+            SELECT DISTINCT si.scenario_name
+            FROM scenario_iteration si
+            WHERE LOWER(si.scenario_name) LIKE ? ESCAPE '\\'
+                AND COALESCE((SELECT r.project_id FROM run r WHERE r.id = si.run_id), 'default') = ?
+                AND si.run_id NOT IN (SELECT id FROM run WHERE archived)
+            "#,
+        )
+        .bind(pattern)
+        .bind(&self.project)
+        .fetch_all(&self.pool)
+        .await
+        .context("Error searching scenarios in db.")?;
+
+        // rank the matches and keep the requested page
+        let mut ranked = names
+            .into_iter()
+            .filter_map(|name| search::score(query, &name).map(|score| (score, name)))
+            .collect::<Vec<_>>();
+        ranked.sort_by(|(a_score, a_name), (b_score, b_name)| {
+            b_score.cmp(a_score).then_with(|| a_name.cmp(b_name))
+        });
+        let total_items = ranked.len() as u32;
+        let names = ranked
+            .into_iter()
+            .skip(page.offset() as usize)
+            .take(page.limit() as usize)
+            .map(|(_, name)| name)
+            .collect::<Vec<_>>();
+        if names.is_empty() {
+            return Ok(Page {
+                items: vec![],
+                pagination: Pagination::new(page, total_items),
+            });
+        }
+
+        let placeholders = vec!["?"; names.len()].join(", ");
+        let sql = format!(
+            "{MYSQL_SUMMARY} WHERE si.scenario_name IN ({placeholders}) GROUP BY si.scenario_name"
+        );
+        let mut summaries = sqlx::query_as::<_, ScenarioSummary>(&sql).bind(&self.project);
+        for name in names.iter() {
+            summaries = summaries.bind(name);
+        }
+        let mut summaries = summaries
+            .fetch_all(&self.pool)
+            .await
+            .context("Error fetching scenarios from db.")?;
+        summaries
+            .sort_by_key(|summary| names.iter().position(|name| *name == summary.scenario_name));
+
+        Ok(Page {
+            items: self.with_trends(summaries).await?,
+            pagination: Pagination::new(page, total_items),
+        })
+    }
+
+    async fn persist(&self, scenario_iteration: &ScenarioIteration) -> Result<()> {
+        sqlx::query(
+            "REPLACE INTO scenario_iteration (run_id, scenario_name, iteration, start_time, stop_time, status, cold_start) \
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&scenario_iteration.run_id)
+        .bind(&scenario_iteration.scenario_name)
+        .bind(scenario_iteration.iteration)
+        .bind(scenario_iteration.start_time)
+        .bind(scenario_iteration.stop_time)
+        .bind(&scenario_iteration.status)
+        .bind(scenario_iteration.cold_start)
+        .execute(&self.pool)
+        .await
+        .map(|_| ())
+        .context("Error inserting scenario into db.")
+    }
+}
+
 // //////////////////////////////////////
 // RemoteDao
 
 pub struct RemoteDao {
     base_url: String,
     client: reqwest::Client,
+    project: String,
 }
 impl RemoteDao {
     pub fn new(base_url: &str) -> Self {
@@ -110,20 +891,68 @@ impl RemoteDao {
         Self {
             base_url: String::from(base_url),
             client: reqwest::Client::new(),
+            project: DEFAULT_PROJECT.to_string(),
         }
     }
+
+    pub fn for_project(mut self, project: &str) -> Self {
+        self.project = project.to_string();
+        self
+    }
 }
 #[async_trait]
 impl ScenarioIterationDao for RemoteDao {
-    async fn fetch_last(
+    async fn fetch_last(&self, _scenario_name: &str, _n: u32) -> Result<Vec<ScenarioIteration>> {
+        todo!()
+    }
+
+    async fn fetch_run(
         &self,
         _scenario_name: &str,
-        _n: u32,
-    ) -> anyhow::Result<Vec<ScenarioIteration>> {
-        todo!()
+        _run_id: &str,
+    ) -> Result<Vec<ScenarioIteration>> {
+        Err(CardamonError::InvalidInput(
+            "Fetching the iterations of a run isn't supported by the remote server".to_string(),
+        ))
+    }
+
+    async fn fetch_by_run(&self, _run_id: &str) -> Result<Vec<ScenarioIteration>> {
+        Err(CardamonError::InvalidInput(
+            "Fetching the iterations of a run isn't supported by the remote server".to_string(),
+        ))
     }
 
-    async fn persist(&self, scenario_iteration: &ScenarioIteration) -> anyhow::Result<()> {
+    async fn fetch_scenarios(&self, page: &PageRequest) -> Result<Page<ScenarioSummary>> {
+        self.client
+            .get(format!("{}/api/scenarios", self.base_url))
+            .query(page)
+            .query(&[("project", &self.project)])
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Page<ScenarioSummary>>()
+            .await
+            .context("Error fetching scenarios from remote server")
+    }
+
+    async fn fetch_by_query(
+        &self,
+        query: &str,
+        page: &PageRequest,
+    ) -> Result<Page<ScenarioSummary>> {
+        self.client
+            .get(format!("{}/api/scenarios", self.base_url))
+            .query(page)
+            .query(&[("search_query", query), ("project", &self.project)])
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Page<ScenarioSummary>>()
+            .await
+            .context("Error searching scenarios on remote server")
+    }
+
+    async fn persist(&self, scenario_iteration: &ScenarioIteration) -> Result<()> {
         self.client
             .post(format!("{}/scenario", self.base_url))
             .json(scenario_iteration)
@@ -138,6 +967,12 @@ impl ScenarioIterationDao for RemoteDao {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::data_access::{
+        cpu_metrics::{self, CpuMetrics, CpuMetricsDao},
+        pagination::{Order, SortBy},
+        run::{self, Run, RunDao},
+    };
+    use anyhow::Context as _;
 
     #[sqlx::test(
         migrations = "./migrations",
@@ -178,4 +1013,257 @@ mod tests {
 
         Ok(())
     }
+
+    #[sqlx::test(
+        migrations = "./migrations",
+        fixtures(
+            "../../fixtures/scenario_iterations.sql",
+            "../../fixtures/power_metrics.sql",
+            "../../fixtures/run_impacts.sql"
+        )
+    )]
+    async fn scenarios_can_be_paged_and_sorted(pool: sqlx::SqlitePool) -> anyhow::Result<()> {
+        let scenario_service = LocalDao::new(pool.clone());
+
+        // most recently run first by default
+        let page = scenario_service
+            .fetch_scenarios(&PageRequest::new(1, 2))
+            .await?;
+        let names = page
+            .items
+            .iter()
+            .map(|s| s.scenario_name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["scenario_3", "scenario_2"]);
+        assert_eq!(page.pagination.total_items, 3);
+        assert_eq!(page.pagination.total_pages, 2);
+
+        let scenario_3 = &page.items[0];
+        assert_eq!(scenario_3.runs, 3);
+        assert_eq!(scenario_3.last_run, 1717507794000);
+
+        let page = scenario_service
+            .fetch_scenarios(&PageRequest::new(2, 2))
+            .await?;
+        assert_eq!(page.items.len(), 1);
+        assert_eq!(page.items[0].scenario_name, "scenario_1");
+
+        // scenario_1 wasn't running while power was measured
+        let page = scenario_service
+            .fetch_scenarios(&PageRequest::new(1, 10).sorted(SortBy::Power, Order::Desc))
+            .await?;
+        let names = page
+            .items
+            .iter()
+            .map(|s| s.scenario_name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["scenario_2", "scenario_3", "scenario_1"]);
+        assert_eq!(page.items[1].measured_power, Some(43.75));
+        assert_eq!(page.items[2].measured_power, None);
+
+        let page = scenario_service
+            .fetch_scenarios(&PageRequest::new(1, 10).sorted(SortBy::Name, Order::Asc))
+            .await?;
+        let names = page
+            .items
+            .iter()
+            .map(|s| s.scenario_name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["scenario_1", "scenario_2", "scenario_3"]);
+        assert!(page.items[0].co2.is_some());
+
+        Ok(())
+    }
+
+    #[sqlx::test(
+        migrations = "./migrations",
+        fixtures(
+            "../../fixtures/scenario_iterations.sql",
+            "../../fixtures/power_metrics.sql",
+            "../../fixtures/run_impacts.sql"
+        )
+    )]
+    async fn archived_runs_are_left_out_of_summaries(pool: sqlx::SqlitePool) -> anyhow::Result<()> {
+        run::LocalDao::new(pool.clone())
+            .set_archived("1", true)
+            .await?;
+        let scenario_service = LocalDao::new(pool.clone());
+
+        // scenario_1 only ran in the archived run
+        let page = scenario_service
+            .fetch_scenarios(&PageRequest::new(1, 10).sorted(SortBy::Name, Order::Asc))
+            .await?;
+        let names = page
+            .items
+            .iter()
+            .map(|s| s.scenario_name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["scenario_2", "scenario_3"]);
+        assert_eq!(page.pagination.total_items, 2);
+
+        // the power and impact of scenario_3 were only measured in the archived run
+        let scenario_3 = &page.items[1];
+        assert_eq!(scenario_3.runs, 2);
+        assert_eq!(scenario_3.measured_power, None);
+        assert_eq!(scenario_3.co2, None);
+
+        let page = scenario_service
+            .fetch_by_query("scenario", &PageRequest::default())
+            .await?;
+        assert_eq!(page.pagination.total_items, 2);
+        assert!(page.items.iter().all(|s| s.measured_power.is_none()));
+
+        pool.close().await;
+        Ok(())
+    }
+
+    #[sqlx::test(
+        migrations = "./migrations",
+        fixtures(
+            "../../fixtures/scenario_iterations.sql",
+            "../../fixtures/cpu_metrics.sql"
+        )
+    )]
+    async fn scenario_trends_are_fitted_to_recent_runs(
+        pool: sqlx::SqlitePool,
+    ) -> anyhow::Result<()> {
+        let page = LocalDao::new(pool.clone())
+            .fetch_scenarios(&PageRequest::new(1, 10).sorted(SortBy::Name, Order::Asc))
+            .await?;
+        assert_eq!(page.items[0].cpu_trend, None);
+        assert_eq!(page.items[1].cpu_trend.as_ref().map(|t| t.runs), Some(2));
+        assert_eq!(page.items[2].cpu_trend.as_ref().map(|t| t.runs), Some(3));
+
+        let page = LocalDao::new(pool.clone())
+            .with_trend_runs(2)
+            .fetch_by_query("scenario_3", &PageRequest::default())
+            .await?;
+        assert_eq!(page.items[0].cpu_trend.as_ref().map(|t| t.runs), Some(2));
+
+        pool.close().await;
+        Ok(())
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn scenario_trends_do_not_depend_on_the_sample_rate(
+        pool: sqlx::SqlitePool,
+    ) -> anyhow::Result<()> {
+        let scenario_service = LocalDao::new(pool.clone());
+        let cpu_metrics_service = cpu_metrics::LocalDao::new(pool.clone());
+
+        // both runs use half a cpu for 10 seconds, sampled every second then every 2 seconds
+        for (run_id, start, interval) in [("1", 0, 1), ("2", 100_000, 2)] {
+            run::LocalDao::new(pool.clone())
+                .persist(&Run::new(run_id, None))
+                .await?;
+            scenario_service
+                .persist(&ScenarioIteration::new(
+                    run_id,
+                    "scenario_1",
+                    1,
+                    start,
+                    start + 10_000,
+                ))
+                .await?;
+            for i in (interval..=10).step_by(interval as usize) {
+                cpu_metrics_service
+                    .persist(
+                        &CpuMetrics::new(run_id, "1", "yarn", 50.0, 50.0, 4, start + i * 1000)
+                            .with_sample_interval(Some(interval as f64)),
+                    )
+                    .await?;
+            }
+        }
+
+        let trend = scenario_service
+            .fetch_trend("scenario_1")
+            .await?
+            .context("expected a trend over 2 runs")?;
+        assert!(trend.slope.abs() < 1e-9);
+
+        pool.close().await;
+        Ok(())
+    }
+
+    #[sqlx::test(
+        migrations = "./migrations",
+        fixtures("../../fixtures/scenario_iterations.sql")
+    )]
+    async fn scenarios_can_be_searched(pool: sqlx::SqlitePool) -> anyhow::Result<()> {
+        let scenario_service = LocalDao::new(pool.clone());
+
+        let page = scenario_service
+            .fetch_by_query("SCENARIO_3", &PageRequest::default())
+            .await?;
+        let names = page
+            .items
+            .iter()
+            .map(|s| s.scenario_name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["scenario_3"]);
+        assert_eq!(page.items[0].runs, 3);
+
+        // fuzzy matches are ranked, then sorted by name
+        let page = scenario_service
+            .fetch_by_query("sc2", &PageRequest::new(1, 1))
+            .await?;
+        assert_eq!(page.items[0].scenario_name, "scenario_2");
+        assert_eq!(page.pagination.total_items, 1);
+
+        let page = scenario_service
+            .fetch_by_query("nario", &PageRequest::default())
+            .await?;
+        assert_eq!(page.pagination.total_items, 3);
+        assert_eq!(page.items[0].scenario_name, "scenario_1");
+
+        // wildcards are matched literally
+        let page = scenario_service
+            .fetch_by_query("%", &PageRequest::default())
+            .await?;
+        assert!(page.items.is_empty());
+        let page = scenario_service
+            .fetch_by_query("o_", &PageRequest::default())
+            .await?;
+        assert_eq!(page.pagination.total_items, 3);
+
+        Ok(())
+    }
+
+    #[sqlx::test(
+        migrations = "./migrations",
+        fixtures("../../fixtures/scenario_iterations.sql")
+    )]
+    async fn projects_do_not_share_scenarios(pool: sqlx::SqlitePool) -> anyhow::Result<()> {
+        run::LocalDao::new(pool.clone())
+            .persist(&Run::new("4", None).in_project("team_b"))
+            .await?;
+        let team_b = LocalDao::new(pool.clone()).for_project("team_b");
+        team_b
+            .persist(
+                &ScenarioIteration::new("4", "scenario_1", 1, 1717507890000, 1717507891000)
+                    .with_cold_start(true),
+            )
+            .await?;
+
+        // the same scenario name in another project is left out
+        let scenario_iterations = LocalDao::new(pool.clone())
+            .fetch_last("scenario_1", 5)
+            .await?;
+        assert!(scenario_iterations.iter().all(|it| it.run_id == "1"));
+
+        let scenario_iterations = team_b.fetch_last("scenario_1", 5).await?;
+        assert_eq!(scenario_iterations.len(), 1);
+        assert_eq!(scenario_iterations[0].run_id, "4");
+        assert!(scenario_iterations[0].cold_start);
+
+        let page = team_b.fetch_scenarios(&PageRequest::default()).await?;
+        assert_eq!(page.pagination.total_items, 1);
+        assert_eq!(page.items[0].runs, 1);
+
+        let projects = run::LocalDao::new(pool.clone()).fetch_projects().await?;
+        assert_eq!(projects, vec!["default", "team_b"]);
+
+        pool.close().await;
+        Ok(())
+    }
 }
@@ -0,0 +1,154 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Fuzzy matching for searches. Candidates are narrowed down in SQL with a LIKE pattern which
+//! matches the query as a subsequence, then ranked by how closely they match.
+
+use std::cmp::Ordering;
+
+/// The character used to escape LIKE wildcards in patterns built by this module, used with
+/// `ESCAPE '\'`.
+pub const LIKE_ESCAPE: char = '\\';
+
+/// Builds a LIKE pattern matching anything containing the characters of the query in order,
+/// e.g. "bskt" becomes "%b%s%k%t%". Wildcards in the query are escaped so they match literally.
+pub fn like_pattern(query: &str) -> String {
+    let mut pattern = String::from("%");
+    for c in query.to_lowercase().chars() {
+        if c == '%' || c == '_' || c == LIKE_ESCAPE {
+            pattern.push(LIKE_ESCAPE);
+        }
+        pattern.push(c);
+        pattern.push('%');
+    }
+    pattern
+}
+
+/// How a candidate matched the query, from worst to best.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MatchKind {
+    /// The characters of the query appear in order, e.g. "bskt" in "basket".
+    Subsequence,
+    /// The query appears somewhere in the candidate.
+    Substring,
+    Prefix,
+    Exact,
+}
+
+/// The quality of a match. Better matches compare as greater.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Score {
+    pub kind: MatchKind,
+    /// How far the match is from ideal: where it starts, how spread out it is and how many
+    /// characters of the candidate it leaves unmatched.
+    pub penalty: usize,
+}
+impl Ord for Score {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.kind
+            .cmp(&other.kind)
+            .then_with(|| other.penalty.cmp(&self.penalty))
+    }
+}
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Scores a candidate against the query, ignoring case. None if the candidate doesn't match.
+pub fn score(query: &str, candidate: &str) -> Option<Score> {
+    let query = query.to_lowercase().chars().collect::<Vec<_>>();
+    let candidate = candidate.to_lowercase().chars().collect::<Vec<_>>();
+    if query.len() > candidate.len() {
+        return None;
+    }
+    let unmatched = candidate.len() - query.len();
+
+    if query.is_empty() {
+        return Some(Score {
+            kind: MatchKind::Prefix,
+            penalty: unmatched,
+        });
+    }
+
+    if query == candidate {
+        return Some(Score {
+            kind: MatchKind::Exact,
+            penalty: 0,
+        });
+    }
+
+    if let Some(pos) = candidate
+        .windows(query.len())
+        .position(|window| window == query.as_slice())
+    {
+        let kind = if pos == 0 {
+            MatchKind::Prefix
+        } else {
+            MatchKind::Substring
+        };
+        return Some(Score {
+            kind,
+            penalty: pos + unmatched,
+        });
+    }
+
+    // match each character as early as possible
+    let mut positions = Vec::with_capacity(query.len());
+    let mut rest = candidate.iter().enumerate();
+    for q in query.iter() {
+        let (pos, _) = rest.find(|(_, c)| *c == q)?;
+        positions.push(pos);
+    }
+    let first = positions.first().copied().unwrap_or(0);
+    let last = positions.last().copied().unwrap_or(0);
+    let gaps = last + 1 - first - query.len();
+
+    Some(Score {
+        kind: MatchKind::Subsequence,
+        penalty: gaps * candidate.len() + first + unmatched,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcards_are_escaped_in_like_patterns() {
+        assert_eq!(like_pattern("Bskt"), "%b%s%k%t%");
+        assert_eq!(like_pattern("100%_"), "%1%0%0%\\%%\\_%");
+        assert_eq!(like_pattern(""), "%");
+    }
+
+    #[test]
+    fn closer_matches_rank_higher() {
+        let query = "cart";
+        let mut candidates = vec![
+            "add_to_basket",
+            "checkout_cart",
+            "cart_checkout",
+            "CART",
+            "c_a_r_t",
+            "carrot",
+        ];
+        candidates.sort_by_key(|candidate| std::cmp::Reverse(score(query, candidate)));
+
+        assert_eq!(
+            candidates,
+            vec![
+                "CART",
+                "cart_checkout",
+                "checkout_cart",
+                "carrot",
+                "c_a_r_t",
+                "add_to_basket"
+            ]
+        );
+        assert_eq!(score(query, "add_to_basket"), None);
+    }
+}
@@ -0,0 +1,1078 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use super::{
+    baseline::Baseline,
+    cpu_metrics::CpuMetrics,
+    endpoint_request::EndpointRequest,
+    energy_mix::EnergyMix,
+    iteration_phase::IterationPhase,
+    iteration_result::IterationResult,
+    power_metrics::PowerMetrics,
+    process_event::ProcessEvent,
+    process_info::ProcessInfo,
+    run::{ReferenceRun, Run},
+    run_context::RunContext,
+    run_impact::RunImpact,
+    run_phase::RunPhase,
+    scenario_iteration::ScenarioIteration,
+};
+use anyhow::Context;
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{BufReader, BufWriter, Read},
+    path::Path,
+};
+
+/// Name of the snapshot inside `.tar.zst` archives.
+const ARCHIVE_ENTRY: &str = "snapshot.json";
+
+/// Version of the snapshot format, bumped whenever a change would stop older versions of
+/// cardamon from reading it.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Every row in the database in a form which doesn't depend on the database backend, used to move
+/// data between databases or share it between machines.
+#[derive(Debug, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct Snapshot {
+    pub version: u32,
+    pub runs: Vec<Run>,
+    pub baselines: Vec<Baseline>,
+    pub scenario_iterations: Vec<ScenarioIteration>,
+    pub cpu_metrics: Vec<CpuMetrics>,
+    pub power_metrics: Vec<PowerMetrics>,
+    pub process_events: Vec<ProcessEvent>,
+    pub run_impacts: Vec<RunImpact>,
+    /// Added after the first version of the format so may be missing from older snapshots.
+    #[serde(default)]
+    pub energy_mixes: Vec<EnergyMix>,
+    #[serde(default)]
+    pub run_contexts: Vec<RunContext>,
+    #[serde(default)]
+    pub iteration_results: Vec<IterationResult>,
+    #[serde(default)]
+    pub process_infos: Vec<ProcessInfo>,
+    #[serde(default)]
+    pub run_phases: Vec<RunPhase>,
+    #[serde(default)]
+    pub iteration_phases: Vec<IterationPhase>,
+    #[serde(default)]
+    pub endpoint_requests: Vec<EndpointRequest>,
+    #[serde(default)]
+    pub reference_runs: Vec<ReferenceRun>,
+}
+impl Snapshot {
+    /// Ids of every run with at least one row in the snapshot.
+    fn run_ids(&self) -> HashSet<&str> {
+        self.runs
+            .iter()
+            .map(|run| run.id.as_str())
+            .chain(self.scenario_iterations.iter().map(|it| it.run_id.as_str()))
+            .chain(self.cpu_metrics.iter().map(|m| m.run_id.as_str()))
+            .chain(self.power_metrics.iter().map(|m| m.run_id.as_str()))
+            .chain(self.process_events.iter().map(|e| e.run_id.as_str()))
+            .chain(self.run_impacts.iter().map(|i| i.run_id.as_str()))
+            .chain(self.energy_mixes.iter().map(|m| m.run_id.as_str()))
+            .chain(self.run_contexts.iter().map(|c| c.run_id.as_str()))
+            .chain(self.iteration_results.iter().map(|r| r.run_id.as_str()))
+            .chain(self.process_infos.iter().map(|i| i.run_id.as_str()))
+            .chain(self.run_phases.iter().map(|p| p.run_id.as_str()))
+            .chain(self.iteration_phases.iter().map(|p| p.run_id.as_str()))
+            .chain(self.endpoint_requests.iter().map(|r| r.run_id.as_str()))
+            .collect()
+    }
+
+    /// Writes the snapshot as JSON inside a zstd compressed tar archive if the path ends in
+    /// `.tar.zst`, otherwise as plain JSON.
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        let file = BufWriter::new(
+            File::create(path).with_context(|| format!("Unable to create {}", path.display()))?,
+        );
+        if !is_archive(path) {
+            return serde_json::to_writer(file, self).context("Unable to write snapshot");
+        }
+
+        let json = serde_json::to_vec(self)?;
+        let mut header = tar::Header::new_gnu();
+        header.set_size(json.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(chrono::Utc::now().timestamp().max(0) as u64);
+        header.set_cksum();
+
+        let mut archive = tar::Builder::new(zstd::Encoder::new(file, 0)?.auto_finish());
+        archive
+            .append_data(&mut header, ARCHIVE_ENTRY, json.as_slice())
+            .context("Unable to write snapshot")?;
+        archive.into_inner()?;
+        Ok(())
+    }
+
+    /// Reads a snapshot written by [`Snapshot::write`].
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        let file = BufReader::new(
+            File::open(path).with_context(|| format!("Unable to open {}", path.display()))?,
+        );
+        let not_a_snapshot = || format!("{} is not a cardamon snapshot", path.display());
+        if !is_archive(path) {
+            return serde_json::from_reader(file).with_context(not_a_snapshot);
+        }
+
+        let mut archive = tar::Archive::new(zstd::Decoder::new(file)?);
+        for entry in archive.entries().with_context(not_a_snapshot)? {
+            let mut entry = entry.with_context(not_a_snapshot)?;
+            if entry.path()?.as_os_str() == ARCHIVE_ENTRY {
+                let mut json = vec![];
+                entry.read_to_end(&mut json)?;
+                return serde_json::from_slice(&json).with_context(not_a_snapshot);
+            }
+        }
+        Err(anyhow::anyhow!(not_a_snapshot()))
+    }
+}
+
+fn is_archive(path: &Path) -> bool {
+    path.to_string_lossy().ends_with(".tar.zst")
+}
+
+/// Reads the whole database into a snapshot.
+pub async fn export(pool: &sqlx::SqlitePool) -> anyhow::Result<Snapshot> {
+    Ok(Snapshot {
+        version: SNAPSHOT_VERSION,
+        runs: sqlx::query_as!(Run, "SELECT * FROM run ORDER BY id")
+            .fetch_all(pool)
+            .await
+            .context("Error fetching runs from db.")?,
+        baselines: sqlx::query_as!(Baseline, "SELECT * FROM baseline ORDER BY id")
+            .fetch_all(pool)
+            .await
+            .context("Error fetching baselines from db.")?,
+        scenario_iterations: sqlx::query_as!(
+            ScenarioIteration,
+            "SELECT * FROM scenario_iteration ORDER BY run_id, scenario_name, iteration"
+        )
+        .fetch_all(pool)
+        .await
+        .context("Error fetching scenario iterations from db.")?,
+        cpu_metrics: sqlx::query_as!(
+            CpuMetrics,
+            "SELECT * FROM cpu_metrics ORDER BY run_id, process_id, timestamp"
+        )
+        .fetch_all(pool)
+        .await
+        .context("Error fetching cpu metrics from db.")?,
+        power_metrics: sqlx::query_as!(
+            PowerMetrics,
+            "SELECT * FROM power_metrics ORDER BY run_id, source, timestamp"
+        )
+        .fetch_all(pool)
+        .await
+        .context("Error fetching power metrics from db.")?,
+        process_events: sqlx::query_as!(
+            ProcessEvent,
+            "SELECT * FROM process_event ORDER BY run_id, timestamp, process_name, event"
+        )
+        .fetch_all(pool)
+        .await
+        .context("Error fetching process events from db.")?,
+        run_impacts: sqlx::query_as!(RunImpact, "SELECT * FROM run_impact ORDER BY run_id")
+            .fetch_all(pool)
+            .await
+            .context("Error fetching run impacts from db.")?,
+        energy_mixes: sqlx::query_as!(EnergyMix, "SELECT * FROM energy_mix ORDER BY run_id, fuel")
+            .fetch_all(pool)
+            .await
+            .context("Error fetching energy mixes from db.")?,
+        run_contexts: sqlx::query_as!(RunContext, "SELECT * FROM run_context ORDER BY run_id")
+            .fetch_all(pool)
+            .await
+            .context("Error fetching run contexts from db.")?,
+        iteration_results: sqlx::query_as!(
+            IterationResult,
+            "SELECT * FROM iteration_result ORDER BY run_id, scenario_name, iteration"
+        )
+        .fetch_all(pool)
+        .await
+        .context("Error fetching iteration results from db.")?,
+        process_infos: sqlx::query_as!(
+            ProcessInfo,
+            "SELECT * FROM process_info ORDER BY run_id, process_name"
+        )
+        .fetch_all(pool)
+        .await
+        .context("Error fetching process info from db.")?,
+        run_phases: sqlx::query_as!(
+            RunPhase,
+            "SELECT * FROM run_phase ORDER BY run_id, start_time"
+        )
+        .fetch_all(pool)
+        .await
+        .context("Error fetching run phases from db.")?,
+        iteration_phases: sqlx::query_as!(
+            IterationPhase,
+            "SELECT * FROM iteration_phase ORDER BY run_id, scenario_name, iteration, start_time"
+        )
+        .fetch_all(pool)
+        .await
+        .context("Error fetching iteration phases from db.")?,
+        endpoint_requests: sqlx::query_as!(
+            EndpointRequest,
+            "SELECT * FROM endpoint_request ORDER BY run_id, scenario_name, iteration, endpoint"
+        )
+        .fetch_all(pool)
+        .await
+        .context("Error fetching endpoint requests from db.")?,
+        reference_runs: sqlx::query_as!(
+            ReferenceRun,
+            "SELECT * FROM reference_run ORDER BY project_id"
+        )
+        .fetch_all(pool)
+        .await
+        .context("Error fetching reference runs from db.")?,
+    })
+}
+
+/// Writes a snapshot to the database in a single transaction. Runs and baselines which already
+/// exist in the database are skipped so the same snapshot can be imported more than once, and
+/// projects keep their reference run if they already have one.
+///
+/// # Returns
+///
+/// The number of runs imported.
+pub async fn import(pool: &sqlx::SqlitePool, snapshot: &Snapshot) -> anyhow::Result<usize> {
+    if snapshot.version > SNAPSHOT_VERSION {
+        return Err(anyhow::anyhow!(
+            "Snapshot version {} is newer than this version of cardamon supports ({})",
+            snapshot.version,
+            SNAPSHOT_VERSION
+        ));
+    }
+
+    let existing = existing_ids(pool).await?;
+    let new_runs = snapshot
+        .run_ids()
+        .into_iter()
+        .filter(|run_id| !existing.0.contains(*run_id))
+        .collect::<HashSet<_>>();
+    let is_new = |run_id: &str| new_runs.contains(run_id);
+
+    let mut tx = pool.begin().await?;
+
+    for baseline in snapshot
+        .baselines
+        .iter()
+        .filter(|baseline| !existing.1.contains(&baseline.id))
+    {
+        sqlx::query!(
+            "INSERT INTO baseline (id, start_time, stop_time, cpu_usage, core_count) \
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            baseline.id,
+            baseline.start_time,
+            baseline.stop_time,
+            baseline.cpu_usage,
+            baseline.core_count
+        )
+        .execute(&mut *tx)
+        .await
+        .context("Error inserting baseline into db.")?;
+    }
+
+    for run in snapshot.runs.iter().filter(|run| is_new(&run.id)) {
+        sqlx::query!(
+            "INSERT OR IGNORE INTO project (id) VALUES (?1)",
+            run.project_id
+        )
+        .execute(&mut *tx)
+        .await
+        .context("Error inserting project into db.")?;
+
+        sqlx::query!(
+            "INSERT INTO run (id, baseline_id, archived, note, project_id, schedule, iterations, \
+             observation, ambient_load, stop_time, scenarios) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            run.id,
+            run.baseline_id,
+            run.archived,
+            run.note,
+            run.project_id,
+            run.schedule,
+            run.iterations,
+            run.observation,
+            run.ambient_load,
+            run.stop_time,
+            run.scenarios
+        )
+        .execute(&mut *tx)
+        .await
+        .context("Error inserting run into db.")?;
+    }
+
+    for it in snapshot
+        .scenario_iterations
+        .iter()
+        .filter(|it| is_new(&it.run_id))
+    {
+        sqlx::query!(
+            "INSERT INTO scenario_iteration (run_id, scenario_name, iteration, start_time, stop_time, status, cold_start) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            it.run_id,
+            it.scenario_name,
+            it.iteration,
+            it.start_time,
+            it.stop_time,
+            it.status,
+            it.cold_start
+        )
+        .execute(&mut *tx)
+        .await
+        .context("Error inserting scenario iteration into db.")?;
+    }
+
+    for metrics in snapshot
+        .cpu_metrics
+        .iter()
+        .filter(|metrics| is_new(&metrics.run_id))
+    {
+        sqlx::query!(
+            "INSERT INTO cpu_metrics (run_id, process_id, process_name, cpu_usage, total_usage, core_count, timestamp, cpu_frequency, memory_usage, power, sample_interval, memory_limit, cpu_periods, throttled_periods) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+            metrics.run_id,
+            metrics.process_id,
+            metrics.process_name,
+            metrics.cpu_usage,
+            metrics.total_usage,
+            metrics.core_count,
+            metrics.timestamp,
+            metrics.cpu_frequency,
+            metrics.memory_usage,
+            metrics.power,
+            metrics.sample_interval,
+            metrics.memory_limit,
+            metrics.cpu_periods,
+            metrics.throttled_periods
+        )
+        .execute(&mut *tx)
+        .await
+        .context("Error inserting cpu metrics into db.")?;
+    }
+
+    for metrics in snapshot
+        .power_metrics
+        .iter()
+        .filter(|metrics| is_new(&metrics.run_id))
+    {
+        sqlx::query!(
+            "INSERT INTO power_metrics (run_id, source, power, timestamp) VALUES (?1, ?2, ?3, ?4)",
+            metrics.run_id,
+            metrics.source,
+            metrics.power,
+            metrics.timestamp
+        )
+        .execute(&mut *tx)
+        .await
+        .context("Error inserting power metrics into db.")?;
+    }
+
+    for event in snapshot
+        .process_events
+        .iter()
+        .filter(|event| is_new(&event.run_id))
+    {
+        sqlx::query!(
+            "INSERT INTO process_event (run_id, process_name, event, timestamp) VALUES (?1, ?2, ?3, ?4)",
+            event.run_id,
+            event.process_name,
+            event.event,
+            event.timestamp
+        )
+        .execute(&mut *tx)
+        .await
+        .context("Error inserting process event into db.")?;
+    }
+
+    for impact in snapshot
+        .run_impacts
+        .iter()
+        .filter(|impact| is_new(&impact.run_id))
+    {
+        sqlx::query!(
+            "INSERT INTO run_impact (run_id, duration_hours, manufacture_gwp, use_gwp) \
+             VALUES (?1, ?2, ?3, ?4)",
+            impact.run_id,
+            impact.duration_hours,
+            impact.manufacture_gwp,
+            impact.use_gwp
+        )
+        .execute(&mut *tx)
+        .await
+        .context("Error inserting run impact into db.")?;
+    }
+
+    for energy_mix in snapshot
+        .energy_mixes
+        .iter()
+        .filter(|energy_mix| is_new(&energy_mix.run_id))
+    {
+        sqlx::query!(
+            "INSERT INTO energy_mix (run_id, fuel, percentage) VALUES (?1, ?2, ?3)",
+            energy_mix.run_id,
+            energy_mix.fuel,
+            energy_mix.percentage
+        )
+        .execute(&mut *tx)
+        .await
+        .context("Error inserting energy mix into db.")?;
+    }
+
+    for run_context in snapshot
+        .run_contexts
+        .iter()
+        .filter(|run_context| is_new(&run_context.run_id))
+    {
+        sqlx::query!(
+            "INSERT INTO run_context (run_id, os_version, kernel_version, total_memory, \
+             core_count, cpu_governor, container_runtime, cardamon_version, pinning) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            run_context.run_id,
+            run_context.os_version,
+            run_context.kernel_version,
+            run_context.total_memory,
+            run_context.core_count,
+            run_context.cpu_governor,
+            run_context.container_runtime,
+            run_context.cardamon_version,
+            run_context.pinning
+        )
+        .execute(&mut *tx)
+        .await
+        .context("Error inserting run context into db.")?;
+    }
+
+    for iteration_result in snapshot
+        .iteration_results
+        .iter()
+        .filter(|iteration_result| is_new(&iteration_result.run_id))
+    {
+        sqlx::query!(
+            "INSERT INTO iteration_result \
+             (run_id, scenario_name, iteration, requests, p50_ms, p95_ms, p99_ms) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            iteration_result.run_id,
+            iteration_result.scenario_name,
+            iteration_result.iteration,
+            iteration_result.requests,
+            iteration_result.p50_ms,
+            iteration_result.p95_ms,
+            iteration_result.p99_ms
+        )
+        .execute(&mut *tx)
+        .await
+        .context("Error inserting iteration result into db.")?;
+    }
+
+    for process_info in snapshot
+        .process_infos
+        .iter()
+        .filter(|process_info| is_new(&process_info.run_id))
+    {
+        sqlx::query!(
+            "INSERT INTO process_info \
+             (run_id, process_name, image, cpu_limit, memory_limit, replicas) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            process_info.run_id,
+            process_info.process_name,
+            process_info.image,
+            process_info.cpu_limit,
+            process_info.memory_limit,
+            process_info.replicas
+        )
+        .execute(&mut *tx)
+        .await
+        .context("Error inserting process info into db.")?;
+    }
+
+    for run_phase in snapshot
+        .run_phases
+        .iter()
+        .filter(|run_phase| is_new(&run_phase.run_id))
+    {
+        sqlx::query!(
+            "INSERT INTO run_phase (run_id, phase, start_time, stop_time) VALUES (?1, ?2, ?3, ?4)",
+            run_phase.run_id,
+            run_phase.phase,
+            run_phase.start_time,
+            run_phase.stop_time
+        )
+        .execute(&mut *tx)
+        .await
+        .context("Error inserting run phase into db.")?;
+    }
+
+    for iteration_phase in snapshot
+        .iteration_phases
+        .iter()
+        .filter(|iteration_phase| is_new(&iteration_phase.run_id))
+    {
+        sqlx::query!(
+            "INSERT INTO iteration_phase \
+             (run_id, scenario_name, iteration, phase, start_time, stop_time, trace_id, span_id) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            iteration_phase.run_id,
+            iteration_phase.scenario_name,
+            iteration_phase.iteration,
+            iteration_phase.phase,
+            iteration_phase.start_time,
+            iteration_phase.stop_time,
+            iteration_phase.trace_id,
+            iteration_phase.span_id
+        )
+        .execute(&mut *tx)
+        .await
+        .context("Error inserting iteration phase into db.")?;
+    }
+
+    for endpoint_request in snapshot
+        .endpoint_requests
+        .iter()
+        .filter(|endpoint_request| is_new(&endpoint_request.run_id))
+    {
+        sqlx::query!(
+            "INSERT INTO endpoint_request \
+             (run_id, scenario_name, iteration, endpoint, requests) VALUES (?1, ?2, ?3, ?4, ?5)",
+            endpoint_request.run_id,
+            endpoint_request.scenario_name,
+            endpoint_request.iteration,
+            endpoint_request.endpoint,
+            endpoint_request.requests
+        )
+        .execute(&mut *tx)
+        .await
+        .context("Error inserting endpoint request into db.")?;
+    }
+
+    for reference_run in snapshot.reference_runs.iter().filter(|reference_run| {
+        is_new(&reference_run.run_id) || existing.0.contains(&reference_run.run_id)
+    }) {
+        sqlx::query!(
+            "INSERT OR IGNORE INTO reference_run (project_id, run_id, name) VALUES (?1, ?2, ?3)",
+            reference_run.project_id,
+            reference_run.run_id,
+            reference_run.name
+        )
+        .execute(&mut *tx)
+        .await
+        .context("Error inserting reference run into db.")?;
+    }
+
+    tx.commit().await?;
+    Ok(new_runs.len())
+}
+
+/// Ids of the runs and baselines already in the database.
+async fn existing_ids(
+    pool: &sqlx::SqlitePool,
+) -> anyhow::Result<(HashSet<String>, HashSet<String>)> {
+    let run_ids = sqlx::query_scalar!(
+        "SELECT id AS \"id!\" FROM run \
+         UNION SELECT run_id FROM scenario_iteration \
+         UNION SELECT run_id FROM cpu_metrics \
+         UNION SELECT run_id FROM power_metrics \
+         UNION SELECT run_id FROM process_event \
+         UNION SELECT run_id FROM run_impact \
+         UNION SELECT run_id FROM energy_mix \
+         UNION SELECT run_id FROM run_context \
+         UNION SELECT run_id FROM iteration_result \
+         UNION SELECT run_id FROM process_info \
+         UNION SELECT run_id FROM run_phase \
+         UNION SELECT run_id FROM iteration_phase \
+         UNION SELECT run_id FROM endpoint_request"
+    )
+    .fetch_all(pool)
+    .await
+    .context("Error fetching run ids from db.")?;
+
+    let baseline_ids = sqlx::query_scalar!("SELECT id FROM baseline")
+        .fetch_all(pool)
+        .await
+        .context("Error fetching baseline ids from db.")?;
+
+    Ok((
+        run_ids.into_iter().collect(),
+        baseline_ids.into_iter().collect(),
+    ))
+}
+
+/// Reads the whole of a MySQL database into a snapshot, e.g. to move it to SQLite.
+pub async fn export_mysql(pool: &sqlx::MySqlPool) -> anyhow::Result<Snapshot> {
+    Ok(Snapshot {
+        version: SNAPSHOT_VERSION,
+        runs: fetch_mysql(pool, "run", "id").await?,
+        baselines: fetch_mysql(pool, "baseline", "id").await?,
+        scenario_iterations: fetch_mysql(
+            pool,
+            "scenario_iteration",
+            "run_id, scenario_name, iteration",
+        )
+        .await?,
+        cpu_metrics: fetch_mysql(pool, "cpu_metrics", "run_id, process_id, timestamp").await?,
+        power_metrics: fetch_mysql(pool, "power_metrics", "run_id, source, timestamp").await?,
+        process_events: fetch_mysql(
+            pool,
+            "process_event",
+            "run_id, timestamp, process_name, event",
+        )
+        .await?,
+        run_impacts: fetch_mysql(pool, "run_impact", "run_id").await?,
+        energy_mixes: fetch_mysql(pool, "energy_mix", "run_id, fuel").await?,
+        run_contexts: fetch_mysql(pool, "run_context", "run_id").await?,
+        iteration_results: fetch_mysql(
+            pool,
+            "iteration_result",
+            "run_id, scenario_name, iteration",
+        )
+        .await?,
+        process_infos: fetch_mysql(pool, "process_info", "run_id, process_name").await?,
+        run_phases: fetch_mysql(pool, "run_phase", "run_id, start_time").await?,
+        iteration_phases: fetch_mysql(
+            pool,
+            "iteration_phase",
+            "run_id, scenario_name, iteration, start_time",
+        )
+        .await?,
+        endpoint_requests: fetch_mysql(
+            pool,
+            "endpoint_request",
+            "run_id, scenario_name, iteration, endpoint",
+        )
+        .await?,
+        reference_runs: fetch_mysql(pool, "reference_run", "project_id").await?,
+    })
+}
+
+/// Every row of a MySQL table in the given order.
+async fn fetch_mysql<T>(pool: &sqlx::MySqlPool, table: &str, order: &str) -> anyhow::Result<Vec<T>>
+where
+    T: for<'r> sqlx::FromRow<'r, sqlx::mysql::MySqlRow> + Send + Unpin,
+{
+    sqlx::query_as(&format!("SELECT * FROM {table} ORDER BY {order}"))
+        .fetch_all(pool)
+        .await
+        .with_context(|| format!("Error fetching {table} from db."))
+}
+
+/// Writes a snapshot to a MySQL database like [`import`], e.g. to gather the runs recorded in
+/// SQLite on several machines into one database.
+///
+/// # Returns
+///
+/// The number of runs imported.
+pub async fn import_mysql(pool: &sqlx::MySqlPool, snapshot: &Snapshot) -> anyhow::Result<usize> {
+    if snapshot.version > SNAPSHOT_VERSION {
+        return Err(anyhow::anyhow!(
+            "Snapshot version {} is newer than this version of cardamon supports ({})",
+            snapshot.version,
+            SNAPSHOT_VERSION
+        ));
+    }
+
+    let existing_runs = sqlx::query_scalar::<_, String>(
+        "SELECT id FROM run \
+         UNION SELECT run_id FROM scenario_iteration \
+         UNION SELECT run_id FROM cpu_metrics \
+         UNION SELECT run_id FROM power_metrics \
+         UNION SELECT run_id FROM process_event \
+         UNION SELECT run_id FROM run_impact \
+         UNION SELECT run_id FROM energy_mix \
+         UNION SELECT run_id FROM run_context \
+         UNION SELECT run_id FROM iteration_result \
+         UNION SELECT run_id FROM process_info \
+         UNION SELECT run_id FROM run_phase \
+         UNION SELECT run_id FROM iteration_phase \
+         UNION SELECT run_id FROM endpoint_request",
+    )
+    .fetch_all(pool)
+    .await
+    .context("Error fetching run ids from db.")?
+    .into_iter()
+    .collect::<HashSet<_>>();
+    let existing_baselines = sqlx::query_scalar::<_, String>("SELECT id FROM baseline")
+        .fetch_all(pool)
+        .await
+        .context("Error fetching baseline ids from db.")?
+        .into_iter()
+        .collect::<HashSet<_>>();
+    let new_runs = snapshot
+        .run_ids()
+        .into_iter()
+        .filter(|run_id| !existing_runs.contains(*run_id))
+        .collect::<HashSet<_>>();
+    let is_new = |run_id: &str| new_runs.contains(run_id);
+
+    let mut tx = pool.begin().await?;
+
+    for baseline in snapshot
+        .baselines
+        .iter()
+        .filter(|baseline| !existing_baselines.contains(&baseline.id))
+    {
+        sqlx::query(
+            "INSERT INTO baseline (id, start_time, stop_time, cpu_usage, core_count) \
+             VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(&baseline.id)
+        .bind(baseline.start_time)
+        .bind(baseline.stop_time)
+        .bind(baseline.cpu_usage)
+        .bind(baseline.core_count)
+        .execute(&mut *tx)
+        .await
+        .context("Error inserting baseline into db.")?;
+    }
+
+    for run in snapshot.runs.iter().filter(|run| is_new(&run.id)) {
+        sqlx::query("INSERT IGNORE INTO project (id) VALUES (?)")
+            .bind(&run.project_id)
+            .execute(&mut *tx)
+            .await
+            .context("Error inserting project into db.")?;
+
+        sqlx::query(
+            "INSERT INTO run (id, baseline_id, archived, note, project_id, schedule, iterations, \
+             observation, ambient_load, stop_time, scenarios) \
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&run.id)
+        .bind(&run.baseline_id)
+        .bind(run.archived)
+        .bind(&run.note)
+        .bind(&run.project_id)
+        .bind(&run.schedule)
+        .bind(run.iterations)
+        .bind(&run.observation)
+        .bind(run.ambient_load)
+        .bind(run.stop_time)
+        .bind(&run.scenarios)
+        .execute(&mut *tx)
+        .await
+        .context("Error inserting run into db.")?;
+    }
+
+    for it in snapshot
+        .scenario_iterations
+        .iter()
+        .filter(|it| is_new(&it.run_id))
+    {
+        sqlx::query(
+            "INSERT INTO scenario_iteration (run_id, scenario_name, iteration, start_time, stop_time, status, cold_start) \
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&it.run_id)
+        .bind(&it.scenario_name)
+        .bind(it.iteration)
+        .bind(it.start_time)
+        .bind(it.stop_time)
+        .bind(&it.status)
+        .bind(it.cold_start)
+        .execute(&mut *tx)
+        .await
+        .context("Error inserting scenario iteration into db.")?;
+    }
+
+    for metrics in snapshot
+        .cpu_metrics
+        .iter()
+        .filter(|metrics| is_new(&metrics.run_id))
+    {
+        sqlx::query(
+            "INSERT INTO cpu_metrics (run_id, process_id, process_name, cpu_usage, total_usage, core_count, timestamp, cpu_frequency, memory_usage, power, sample_interval, memory_limit, cpu_periods, throttled_periods) \
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&metrics.run_id)
+        .bind(&metrics.process_id)
+        .bind(&metrics.process_name)
+        .bind(metrics.cpu_usage)
+        .bind(metrics.total_usage)
+        .bind(metrics.core_count)
+        .bind(metrics.timestamp)
+        .bind(metrics.cpu_frequency)
+        .bind(metrics.memory_usage)
+        .bind(metrics.power)
+        .bind(metrics.sample_interval)
+        .bind(metrics.memory_limit)
+        .bind(metrics.cpu_periods)
+        .bind(metrics.throttled_periods)
+        .execute(&mut *tx)
+        .await
+        .context("Error inserting cpu metrics into db.")?;
+    }
+
+    for metrics in snapshot
+        .power_metrics
+        .iter()
+        .filter(|metrics| is_new(&metrics.run_id))
+    {
+        sqlx::query(
+            "INSERT INTO power_metrics (run_id, source, power, timestamp) VALUES (?, ?, ?, ?)",
+        )
+        .bind(&metrics.run_id)
+        .bind(&metrics.source)
+        .bind(metrics.power)
+        .bind(metrics.timestamp)
+        .execute(&mut *tx)
+        .await
+        .context("Error inserting power metrics into db.")?;
+    }
+
+    for event in snapshot
+        .process_events
+        .iter()
+        .filter(|event| is_new(&event.run_id))
+    {
+        sqlx::query(
+            "INSERT INTO process_event (run_id, process_name, event, timestamp) VALUES (?, ?, ?, ?)",
+        )
+        .bind(&event.run_id)
+        .bind(&event.process_name)
+        .bind(&event.event)
+        .bind(event.timestamp)
+        .execute(&mut *tx)
+        .await
+        .context("Error inserting process event into db.")?;
+    }
+
+    for impact in snapshot
+        .run_impacts
+        .iter()
+        .filter(|impact| is_new(&impact.run_id))
+    {
+        sqlx::query(
+            "INSERT INTO run_impact (run_id, duration_hours, manufacture_gwp, use_gwp) \
+             VALUES (?, ?, ?, ?)",
+        )
+        .bind(&impact.run_id)
+        .bind(impact.duration_hours)
+        .bind(impact.manufacture_gwp)
+        .bind(impact.use_gwp)
+        .execute(&mut *tx)
+        .await
+        .context("Error inserting run impact into db.")?;
+    }
+
+    for energy_mix in snapshot
+        .energy_mixes
+        .iter()
+        .filter(|energy_mix| is_new(&energy_mix.run_id))
+    {
+        sqlx::query("INSERT INTO energy_mix (run_id, fuel, percentage) VALUES (?, ?, ?)")
+            .bind(&energy_mix.run_id)
+            .bind(&energy_mix.fuel)
+            .bind(energy_mix.percentage)
+            .execute(&mut *tx)
+            .await
+            .context("Error inserting energy mix into db.")?;
+    }
+
+    for run_context in snapshot
+        .run_contexts
+        .iter()
+        .filter(|run_context| is_new(&run_context.run_id))
+    {
+        sqlx::query(
+            "INSERT INTO run_context (run_id, os_version, kernel_version, total_memory, \
+             core_count, cpu_governor, container_runtime, cardamon_version, pinning) \
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&run_context.run_id)
+        .bind(&run_context.os_version)
+        .bind(&run_context.kernel_version)
+        .bind(run_context.total_memory)
+        .bind(run_context.core_count)
+        .bind(&run_context.cpu_governor)
+        .bind(&run_context.container_runtime)
+        .bind(&run_context.cardamon_version)
+        .bind(&run_context.pinning)
+        .execute(&mut *tx)
+        .await
+        .context("Error inserting run context into db.")?;
+    }
+
+    for iteration_result in snapshot
+        .iteration_results
+        .iter()
+        .filter(|iteration_result| is_new(&iteration_result.run_id))
+    {
+        sqlx::query(
+            "INSERT INTO iteration_result \
+             (run_id, scenario_name, iteration, requests, p50_ms, p95_ms, p99_ms) \
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&iteration_result.run_id)
+        .bind(&iteration_result.scenario_name)
+        .bind(iteration_result.iteration)
+        .bind(iteration_result.requests)
+        .bind(iteration_result.p50_ms)
+        .bind(iteration_result.p95_ms)
+        .bind(iteration_result.p99_ms)
+        .execute(&mut *tx)
+        .await
+        .context("Error inserting iteration result into db.")?;
+    }
+
+    for process_info in snapshot
+        .process_infos
+        .iter()
+        .filter(|process_info| is_new(&process_info.run_id))
+    {
+        sqlx::query(
+            "INSERT INTO process_info \
+             (run_id, process_name, image, cpu_limit, memory_limit, replicas) \
+             VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&process_info.run_id)
+        .bind(&process_info.process_name)
+        .bind(&process_info.image)
+        .bind(process_info.cpu_limit)
+        .bind(process_info.memory_limit)
+        .bind(process_info.replicas)
+        .execute(&mut *tx)
+        .await
+        .context("Error inserting process info into db.")?;
+    }
+
+    for run_phase in snapshot
+        .run_phases
+        .iter()
+        .filter(|run_phase| is_new(&run_phase.run_id))
+    {
+        sqlx::query(
+            "INSERT INTO run_phase (run_id, phase, start_time, stop_time) VALUES (?, ?, ?, ?)",
+        )
+        .bind(&run_phase.run_id)
+        .bind(&run_phase.phase)
+        .bind(run_phase.start_time)
+        .bind(run_phase.stop_time)
+        .execute(&mut *tx)
+        .await
+        .context("Error inserting run phase into db.")?;
+    }
+
+    for iteration_phase in snapshot
+        .iteration_phases
+        .iter()
+        .filter(|iteration_phase| is_new(&iteration_phase.run_id))
+    {
+        sqlx::query(
+            "INSERT INTO iteration_phase \
+             (run_id, scenario_name, iteration, phase, start_time, stop_time, trace_id, span_id) \
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&iteration_phase.run_id)
+        .bind(&iteration_phase.scenario_name)
+        .bind(iteration_phase.iteration)
+        .bind(&iteration_phase.phase)
+        .bind(iteration_phase.start_time)
+        .bind(iteration_phase.stop_time)
+        .bind(&iteration_phase.trace_id)
+        .bind(&iteration_phase.span_id)
+        .execute(&mut *tx)
+        .await
+        .context("Error inserting iteration phase into db.")?;
+    }
+
+    for endpoint_request in snapshot
+        .endpoint_requests
+        .iter()
+        .filter(|endpoint_request| is_new(&endpoint_request.run_id))
+    {
+        sqlx::query(
+            "INSERT INTO endpoint_request \
+             (run_id, scenario_name, iteration, endpoint, requests) VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(&endpoint_request.run_id)
+        .bind(&endpoint_request.scenario_name)
+        .bind(endpoint_request.iteration)
+        .bind(&endpoint_request.endpoint)
+        .bind(endpoint_request.requests)
+        .execute(&mut *tx)
+        .await
+        .context("Error inserting endpoint request into db.")?;
+    }
+
+    for reference_run in snapshot.reference_runs.iter().filter(|reference_run| {
+        is_new(&reference_run.run_id) || existing_runs.contains(&reference_run.run_id)
+    }) {
+        sqlx::query("INSERT IGNORE INTO reference_run (project_id, run_id, name) VALUES (?, ?, ?)")
+            .bind(&reference_run.project_id)
+            .bind(&reference_run.run_id)
+            .bind(&reference_run.name)
+            .execute(&mut *tx)
+            .await
+            .context("Error inserting reference run into db.")?;
+    }
+
+    tx.commit().await?;
+    Ok(new_runs.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[sqlx::test(
+        migrations = "./migrations",
+        fixtures(
+            "../../fixtures/scenario_iterations.sql",
+            "../../fixtures/cpu_metrics.sql",
+            "../../fixtures/power_metrics.sql",
+            "../../fixtures/baselines.sql"
+        )
+    )]
+    async fn snapshots_round_trip(pool: sqlx::SqlitePool) -> anyhow::Result<()> {
+        sqlx::query(
+            "INSERT INTO reference_run (project_id, run_id, name) VALUES ('default', '1', 'v1')",
+        )
+        .execute(&pool)
+        .await?;
+        let snapshot = export(&pool).await?;
+        assert!(!snapshot.scenario_iterations.is_empty());
+        assert!(!snapshot.cpu_metrics.is_empty());
+        assert_eq!(snapshot.reference_runs.len(), 1);
+
+        let dir = std::env::temp_dir().join(format!("cardamon-snapshot-{}", nanoid::nanoid!(8)));
+        std::fs::create_dir_all(&dir)?;
+        for file in ["snapshot.json", "snapshot.tar.zst"] {
+            let path = dir.join(file);
+            snapshot.write(&path)?;
+            assert_eq!(Snapshot::read(&path)?, snapshot);
+        }
+        let path = dir.join("snapshot.tar.zst");
+        assert!(serde_json::from_slice::<Snapshot>(&std::fs::read(&path)?).is_err());
+        let snapshot = Snapshot::read(&path)?;
+        std::fs::remove_dir_all(dir)?;
+
+        // everything already exists so nothing is imported twice
+        assert_eq!(import(&pool, &snapshot).await?, 0);
+        assert_eq!(export(&pool).await?, snapshot);
+
+        for table in [
+            "run",
+            "baseline",
+            "scenario_iteration",
+            "cpu_metrics",
+            "power_metrics",
+            "reference_run",
+        ] {
+            sqlx::query(&format!("DELETE FROM {table}"))
+                .execute(&pool)
+                .await?;
+        }
+        assert_eq!(import(&pool, &snapshot).await?, snapshot.run_ids().len());
+        assert_eq!(export(&pool).await?, snapshot);
+
+        pool.close().await;
+        Ok(())
+    }
+}
@@ -1,6 +1,234 @@
-use crate::data_access::{cpu_metrics::CpuMetrics, scenario_iteration::ScenarioIteration};
+use crate::{
+    config::{OutlierDetection, OutlierMethod},
+    data_access::{
+        cpu_metrics::CpuMetrics, power_metrics::PowerMetrics, process_event::ProcessEvent,
+        scenario_iteration::ScenarioIteration,
+    },
+    metrics::THERMAL_THROTTLED,
+};
 use itertools::{Itertools, MinMaxResult};
-use std::collections::{hash_map::Entry, HashMap};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    str::FromStr,
+};
+
+/// How values from several iterations or runs are combined into one. Energy data is noisy so
+/// the median or a percentile is often more representative than the average.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum AggregationMethod {
+    MostRecent,
+    #[default]
+    Average,
+    Sum,
+    Median,
+    P95,
+    Min,
+    Max,
+}
+impl AggregationMethod {
+    /// Combines the given values, which should be in chronological order.
+    ///
+    /// # Returns
+    ///
+    /// The aggregated value or None if there are no values.
+    pub fn aggregate(&self, values: &[f64]) -> Option<f64> {
+        if values.is_empty() {
+            return None;
+        }
+
+        let sorted = || {
+            let mut sorted = values.to_vec();
+            sorted.sort_by(|a, b| a.total_cmp(b));
+            sorted
+        };
+
+        match self {
+            AggregationMethod::MostRecent => values.last().copied(),
+            AggregationMethod::Average => Some(values.iter().sum::<f64>() / values.len() as f64),
+            AggregationMethod::Sum => Some(values.iter().sum()),
+            AggregationMethod::Median => {
+                let sorted = sorted();
+                let mid = sorted.len() / 2;
+                if sorted.len() % 2 == 0 {
+                    Some((sorted[mid - 1] + sorted[mid]) / 2.0)
+                } else {
+                    Some(sorted[mid])
+                }
+            }
+            AggregationMethod::P95 => {
+                // nearest-rank percentile
+                let sorted = sorted();
+                let rank = (0.95 * sorted.len() as f64).ceil() as usize;
+                Some(sorted[rank.max(1) - 1])
+            }
+            AggregationMethod::Min => values.iter().copied().reduce(f64::min),
+            AggregationMethod::Max => values.iter().copied().reduce(f64::max),
+        }
+    }
+}
+impl FromStr for AggregationMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "most-recent" | "most_recent" => Ok(AggregationMethod::MostRecent),
+            "average" | "mean" => Ok(AggregationMethod::Average),
+            "sum" => Ok(AggregationMethod::Sum),
+            "median" => Ok(AggregationMethod::Median),
+            "p95" => Ok(AggregationMethod::P95),
+            "min" => Ok(AggregationMethod::Min),
+            "max" => Ok(AggregationMethod::Max),
+            _ => Err(format!(
+                "Unknown aggregation method {s}, expected one of most-recent, average, sum, median, p95, min or max"
+            )),
+        }
+    }
+}
+
+/// Flags values which are unusually far from the rest.
+///
+/// # Returns
+///
+/// A vector the same length as `values` which is true where the value is an outlier.
+pub fn find_outliers(values: &[f64], detection: &OutlierDetection) -> Vec<bool> {
+    // there isn't enough data to say anything is unusual
+    if values.len() < 3 {
+        return vec![false; values.len()];
+    }
+
+    let (lower, upper) = match detection.method {
+        OutlierMethod::ZScore => {
+            let mean = values.iter().sum::<f64>() / values.len() as f64;
+            let variance =
+                values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64;
+            let spread = detection.threshold * variance.sqrt();
+            (mean - spread, mean + spread)
+        }
+        OutlierMethod::Iqr => {
+            let mut sorted = values.to_vec();
+            sorted.sort_by(|a, b| a.total_cmp(b));
+            let q1 = quantile(&sorted, 0.25);
+            let q3 = quantile(&sorted, 0.75);
+            let spread = detection.threshold * (q3 - q1);
+            (q1 - spread, q3 + spread)
+        }
+    };
+
+    values.iter().map(|v| *v < lower || *v > upper).collect()
+}
+
+/// Includes or excludes processes by name or id using glob patterns (`*` and `?`). A process is
+/// kept if it matches any `only` pattern (or there are none) and doesn't match any `exclude`
+/// pattern.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ProcessFilter {
+    only: Vec<String>,
+    exclude: Vec<String>,
+}
+impl ProcessFilter {
+    pub fn new(only: Vec<String>, exclude: Vec<String>) -> Self {
+        Self { only, exclude }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.only.is_empty() && self.exclude.is_empty()
+    }
+
+    pub fn includes(&self, process_name: &str, process_id: &str) -> bool {
+        let matches =
+            |pattern: &String| glob_match(pattern, process_name) || glob_match(pattern, process_id);
+        (self.only.is_empty() || self.only.iter().any(matches)) && !self.exclude.iter().any(matches)
+    }
+}
+
+/// Matches text against a glob pattern where `*` matches any run of characters and `?` matches
+/// a single character.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+    let (mut p, mut t) = (0, 0);
+    // position of the last `*` and the text position it was tried against
+    let mut backtrack = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, star_t)) => {
+                    p = star + 1;
+                    t = star_t + 1;
+                    backtrack = Some((star, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Linearly interpolated quantile of already sorted values.
+fn quantile(sorted: &[f64], q: f64) -> f64 {
+    let pos = q * (sorted.len() - 1) as f64;
+    let (lo, hi) = (pos.floor() as usize, pos.ceil() as usize);
+    sorted[lo] + (sorted[hi] - sorted[lo]) * (pos - lo as f64)
+}
+
+/// Aggregates the metrics of each process across the given iterations.
+fn aggregate_iterations(
+    iterations: &[&IterationWithMetrics],
+    method: AggregationMethod,
+) -> Vec<ProcessMetrics> {
+    // iterations are aggregated in the order they happened so that MostRecent is meaningful
+    let iterations = iterations
+        .iter()
+        .sorted_by_key(|it| it.scenario_iteration.start_time)
+        .collect::<Vec<_>>();
+
+    let mut metrics_by_process: HashMap<String, Vec<ProcessMetrics>> = HashMap::new();
+    for process_metrics in iterations.iter().flat_map(|it| it.accumulate_by_process()) {
+        metrics_by_process
+            .entry(process_metrics.process_id.clone())
+            .or_default()
+            .push(process_metrics);
+    }
+
+    metrics_by_process
+        .into_iter()
+        .map(|(process_id, process_metrics)| {
+            let means = process_metrics
+                .iter()
+                .map(|m| m.cpu_usage_mean)
+                .collect::<Vec<_>>();
+            let totals = process_metrics
+                .iter()
+                .map(|m| m.cpu_usage_total)
+                .collect::<Vec<_>>();
+            let cpu_usage_minmax = process_metrics
+                .iter()
+                .flat_map(|m| match m.cpu_usage_minmax {
+                    MinMaxResult::NoElements => vec![],
+                    MinMaxResult::OneElement(val) => vec![val],
+                    MinMaxResult::MinMax(min, max) => vec![min, max],
+                })
+                .minmax();
+
+            ProcessMetrics {
+                process_id,
+                cpu_usage_minmax,
+                cpu_usage_mean: method.aggregate(&means).unwrap_or(0.0),
+                cpu_usage_total: method.aggregate(&totals).unwrap_or(0.0),
+            }
+        })
+        .collect()
+}
 
 /// Read-only struct containing metrics for a single process.
 #[derive(Debug)]
@@ -28,28 +256,242 @@ impl ProcessMetrics {
     }
 }
 
+/// An iteration is throttled if the CPU ran below this share of its maximum frequency...
+const THROTTLE_RATIO: f64 = 0.8;
+
+/// ...for at least this many consecutive samples.
+const THROTTLE_SAMPLES: usize = 5;
+
 /// Associates a single ScenarioIteration with all the metrics captured for it.
 #[derive(Debug)]
 pub struct IterationWithMetrics {
     scenario_iteration: ScenarioIteration,
     cpu_metrics: Vec<CpuMetrics>,
+    process_events: Vec<ProcessEvent>,
+    power_metrics: Vec<PowerMetrics>,
 }
 impl IterationWithMetrics {
-    pub fn new(scenario_it: ScenarioIteration, cpu_metrics: Vec<CpuMetrics>) -> Self {
+    pub fn new(
+        scenario_it: ScenarioIteration,
+        cpu_metrics: Vec<CpuMetrics>,
+        process_events: Vec<ProcessEvent>,
+    ) -> Self {
         Self {
             scenario_iteration: scenario_it,
             cpu_metrics,
+            process_events,
+            power_metrics: vec![],
         }
     }
 
+    /// Adds wall power measured for the whole machine during this iteration.
+    pub fn with_power_metrics(mut self, power_metrics: Vec<PowerMetrics>) -> Self {
+        self.power_metrics = power_metrics;
+        self
+    }
+
     pub fn scenario_iteration(&self) -> &ScenarioIteration {
         &self.scenario_iteration
     }
 
+    /// The part of this iteration between `start_time` and `stop_time` as an iteration of its own
+    /// named `name`, e.g. a phase the scenario command marked, so it can be modelled separately.
+    pub fn slice(&self, name: &str, start_time: i64, stop_time: i64) -> IterationWithMetrics {
+        let within = |timestamp: i64| timestamp >= start_time && timestamp <= stop_time;
+        IterationWithMetrics {
+            scenario_iteration: ScenarioIteration::new(
+                &self.scenario_iteration.run_id,
+                name,
+                self.scenario_iteration.iteration,
+                start_time,
+                stop_time,
+            ),
+            cpu_metrics: self
+                .cpu_metrics
+                .iter()
+                .filter(|m| within(m.timestamp))
+                .cloned()
+                .collect(),
+            process_events: self
+                .process_events
+                .iter()
+                .filter(|e| within(e.timestamp))
+                .cloned()
+                .collect(),
+            power_metrics: self
+                .power_metrics
+                .iter()
+                .filter(|m| within(m.timestamp))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Slices this iteration into the given windows, e.g. the spans of traced requests, sharing
+    /// each sample equally between the windows it falls in so energy isn't counted twice where
+    /// windows overlap.
+    ///
+    /// # Arguments
+    ///
+    /// * windows - the name, start and stop time of each window
+    pub fn split(&self, windows: &[(&str, i64, i64)]) -> Vec<IterationWithMetrics> {
+        let sharing = |timestamp: i64| {
+            windows
+                .iter()
+                .filter(|(_, start, stop)| timestamp >= *start && timestamp <= *stop)
+                .count()
+                .max(1) as f64
+        };
+
+        windows
+            .iter()
+            .map(|(name, start_time, stop_time)| {
+                let mut slice = self.slice(name, *start_time, *stop_time);
+                for metrics in slice.cpu_metrics.iter_mut() {
+                    let share = sharing(metrics.timestamp);
+                    metrics.cpu_usage /= share;
+                    metrics.memory_usage = metrics
+                        .memory_usage
+                        .map(|usage| (usage as f64 / share) as i64);
+                    metrics.power = metrics.power.map(|power| power / share);
+                }
+                for metrics in slice.power_metrics.iter_mut() {
+                    metrics.power /= sharing(metrics.timestamp);
+                }
+                slice
+            })
+            .collect()
+    }
+
     pub fn cpu_metrics(&self) -> &[CpuMetrics] {
         &self.cpu_metrics
     }
 
+    /// Process state changes (stops, restarts, OOM kills) which happened during this iteration
+    /// and may explain gaps in its metrics.
+    pub fn process_events(&self) -> &[ProcessEvent] {
+        &self.process_events
+    }
+
+    /// True if power was measured during this iteration, either at the wall or per process.
+    pub fn has_measured_power(&self) -> bool {
+        !self.power_metrics.is_empty() || self.cpu_metrics.iter().any(|m| m.power.is_some())
+    }
+
+    /// Wall power of the whole machine during this iteration, empty if there's no power meter.
+    pub fn power_metrics(&self) -> &[PowerMetrics] {
+        &self.power_metrics
+    }
+
+    /// Drops the metrics and events of processes the filter doesn't include.
+    pub fn retain_processes(&mut self, filter: &ProcessFilter) {
+        self.cpu_metrics
+            .retain(|m| filter.includes(&m.process_name, &m.process_id));
+        self.process_events
+            .retain(|e| filter.includes(&e.process_name, &e.process_name));
+    }
+
+    /// True if the CPU throttled itself for being too hot during this iteration, or ran well
+    /// below `max_frequency` (MHz) for several samples in a row.
+    pub fn is_throttled(&self, max_frequency: Option<i64>) -> bool {
+        if self
+            .process_events
+            .iter()
+            .any(|event| event.event == THERMAL_THROTTLED)
+        {
+            return true;
+        }
+        let Some(max_frequency) = max_frequency.filter(|max| *max > 0) else {
+            return false;
+        };
+
+        // the frequency is the same for every process sampled at the same time
+        let mut frequency_by_timestamp = self
+            .cpu_metrics
+            .iter()
+            .filter_map(|m| m.cpu_frequency.map(|frequency| (m.timestamp, frequency)))
+            .collect::<Vec<_>>();
+        frequency_by_timestamp.sort();
+        frequency_by_timestamp.dedup_by_key(|(timestamp, _)| *timestamp);
+
+        let mut consecutive = 0;
+        for (_, frequency) in frequency_by_timestamp {
+            if (frequency as f64) < max_frequency as f64 * THROTTLE_RATIO {
+                consecutive += 1;
+                if consecutive >= THROTTLE_SAMPLES {
+                    return true;
+                }
+            } else {
+                consecutive = 0;
+            }
+        }
+        false
+    }
+
+    /// Containers which hit their CPU quota during this iteration, with the percentage of quota
+    /// periods they were throttled in. A throttled container's CPU usage is capped by its quota
+    /// rather than by the work it had to do, so its energy is under-reported.
+    pub fn cpu_quota_throttling(&self) -> Vec<(&str, f64)> {
+        self.cpu_metrics
+            .iter()
+            .filter_map(|m| {
+                Some((
+                    m.process_name.as_str(),
+                    m.cpu_periods?,
+                    m.throttled_periods?,
+                ))
+            })
+            .into_group_map_by(|(process_name, _, _)| *process_name)
+            .into_iter()
+            .filter_map(|(process_name, samples)| {
+                let periods = samples.iter().map(|(_, periods, _)| periods).sum::<i64>();
+                let throttled = samples
+                    .iter()
+                    .map(|(_, _, throttled)| throttled)
+                    .sum::<i64>();
+                (throttled > 0 && periods > 0)
+                    .then(|| (process_name, throttled as f64 / periods as f64 * 100.0))
+            })
+            .sorted_by(|a, b| a.0.cmp(b.0))
+            .collect()
+    }
+
+    /// The average seconds between samples during this iteration, None if the loggers didn't
+    /// record their interval. Loggers sample less often while collecting is slow.
+    pub fn sample_interval(&self) -> Option<f64> {
+        let intervals = self
+            .cpu_metrics
+            .iter()
+            .filter_map(|m| m.sample_interval)
+            .collect::<Vec<_>>();
+        (!intervals.is_empty()).then(|| intervals.iter().sum::<f64>() / intervals.len() as f64)
+    }
+
+    /// Total CPU usage of all processes during this iteration.
+    pub fn cpu_usage_total(&self) -> f64 {
+        self.cpu_metrics.iter().map(|m| m.cpu_usage).sum()
+    }
+
+    /// Total CPU usage of all processes per sample, which unlike the total doesn't grow with
+    /// the length of the iteration.
+    pub fn cpu_usage_mean(&self) -> f64 {
+        let samples = self
+            .cpu_metrics
+            .iter()
+            .map(|m| m.timestamp)
+            .unique()
+            .count();
+        self.cpu_usage_total() / samples.max(1) as f64
+    }
+
+    /// Average power measured during this iteration, None without a power meter.
+    pub fn power_mean(&self) -> Option<f64> {
+        self.has_measured_power().then(|| {
+            self.power_metrics.iter().map(|m| m.power).sum::<f64>()
+                / self.power_metrics.len() as f64
+        })
+    }
+
     pub fn accumulate_by_process(&self) -> Vec<ProcessMetrics> {
         let mut metrics_by_process: HashMap<String, Vec<&CpuMetrics>> = HashMap::new();
         for metric in self.cpu_metrics.iter() {
@@ -92,6 +534,17 @@ impl<'a> ObservationDataset {
         &self.data
     }
 
+    /// Leaves out processes the filter doesn't include, e.g. the load generator, so they don't
+    /// count towards any totals.
+    pub fn filter_processes(mut self, filter: &ProcessFilter) -> Self {
+        if !filter.is_empty() {
+            for iteration in self.data.iter_mut() {
+                iteration.retain_processes(filter);
+            }
+        }
+        self
+    }
+
     pub fn by_scenario(&'a self) -> Vec<ScenarioDataset<'a>> {
         // get all the scenarios in the observation
         let scenario_names = self
@@ -137,6 +590,11 @@ impl<'a> ScenarioDataset<'a> {
         &self.data
     }
 
+    /// Aggregates each process across every iteration of every run in this dataset.
+    pub fn aggregated(&'a self, method: AggregationMethod) -> Vec<ProcessMetrics> {
+        aggregate_iterations(&self.data, method)
+    }
+
     pub fn by_run(&'a self) -> Vec<RunDataset<'a>> {
         let runs = self
             .data
@@ -183,10 +641,109 @@ impl<'a> RunDataset<'a> {
         self.run_id
     }
 
+    /// When the first iteration of this run started.
+    pub fn start_time(&self) -> i64 {
+        self.data
+            .iter()
+            .map(|it| it.scenario_iteration.start_time)
+            .min()
+            .unwrap_or(0)
+    }
+
     pub fn by_iterations(&'a self) -> &'a [&'a IterationWithMetrics] {
         &self.data
     }
 
+    pub fn process_events(&'a self) -> Vec<&'a ProcessEvent> {
+        self.data
+            .iter()
+            .flat_map(|it| it.process_events.iter())
+            .collect()
+    }
+
+    /// Iterations whose average power is unusually far from the other iterations in this run.
+    /// Without a power meter for every iteration their average CPU usage is compared instead.
+    /// Averages are used so a slower iteration isn't an outlier just for running longer.
+    pub fn outliers(&self, detection: &OutlierDetection) -> Vec<&'a IterationWithMetrics> {
+        let load = match self
+            .data
+            .iter()
+            .map(|it| it.power_mean())
+            .collect::<Option<Vec<_>>>()
+        {
+            Some(power) => power,
+            None => self.data.iter().map(|it| it.cpu_usage_mean()).collect(),
+        };
+
+        self.data
+            .iter()
+            .zip(find_outliers(&load, detection))
+            .filter_map(|(it, is_outlier)| is_outlier.then_some(*it))
+            .collect()
+    }
+
+    /// Iterations where the CPU was throttled, see `IterationWithMetrics::is_throttled`. Without a
+    /// known maximum frequency the highest frequency observed during the run is used.
+    pub fn throttled(&self, max_frequency: Option<i64>) -> Vec<&'a IterationWithMetrics> {
+        let max_frequency = max_frequency.or_else(|| {
+            self.data
+                .iter()
+                .flat_map(|it| it.cpu_metrics.iter())
+                .filter_map(|m| m.cpu_frequency)
+                .max()
+        });
+
+        self.data
+            .iter()
+            .filter(|it| it.is_throttled(max_frequency))
+            .copied()
+            .collect()
+    }
+
+    /// The percentage of the CPU used by every observed process which the named process used
+    /// across the run, 0-100.
+    pub fn cpu_share(&self, process_name: &str) -> f64 {
+        let (used, total) = self.data.iter().flat_map(|it| it.cpu_metrics.iter()).fold(
+            (0.0, 0.0),
+            |(used, total), m| {
+                let used = if m.process_name == process_name {
+                    used + m.cpu_usage
+                } else {
+                    used
+                };
+                (used, total + m.cpu_usage)
+            },
+        );
+
+        if total > 0.0 {
+            used / total * 100.0
+        } else {
+            0.0
+        }
+    }
+
+    /// A copy of this dataset with outlying iterations removed so they don't skew aggregates.
+    pub fn without_outliers(&self, detection: &OutlierDetection) -> RunDataset<'a> {
+        let outliers = self.outliers(detection);
+        let data = self
+            .data
+            .iter()
+            .filter(|it| !outliers.iter().any(|outlier| std::ptr::eq(*outlier, **it)))
+            .cloned()
+            .collect();
+
+        RunDataset {
+            scenario_name: self.scenario_name,
+            run_id: self.run_id,
+            data,
+        }
+    }
+
+    /// Aggregates each process across the iterations of this run.
+    pub fn aggregated(&'a self, method: AggregationMethod) -> Vec<ProcessMetrics> {
+        aggregate_iterations(&self.data, method)
+    }
+
     pub fn averaged(&'a self) -> Vec<ProcessMetrics> {
         let all_process_metrics = self
             .data
@@ -211,8 +768,8 @@ impl<'a> RunDataset<'a> {
 
         // average across iterations
         process_metrics_to_iterations
-            .into_iter()
-            .flat_map(|(_, process_metrics)| {
+            .into_values()
+            .flat_map(|process_metrics| {
                 process_metrics.into_iter().reduce(|a, b| {
                     let a_minmax = match a.cpu_usage_minmax {
                         MinMaxResult::NoElements => None,
@@ -225,12 +782,11 @@ impl<'a> RunDataset<'a> {
                         MinMaxResult::MinMax(min, max) => Some((min, max)),
                     };
 
-                    let cpu_usage_minmax = if a_minmax.is_some() && b_minmax.is_some() {
-                        let (a_min, a_max) = a_minmax.unwrap();
-                        let (b_min, b_max) = b_minmax.unwrap();
-                        MinMaxResult::MinMax(a_min + b_min / 2.0, a_max + b_max / 2.0)
-                    } else {
-                        MinMaxResult::NoElements
+                    let cpu_usage_minmax = match (a_minmax, b_minmax) {
+                        (Some((a_min, a_max)), Some((b_min, b_max))) => {
+                            MinMaxResult::MinMax(a_min + b_min / 2.0, a_max + b_max / 2.0)
+                        }
+                        _ => MinMaxResult::NoElements,
                     };
 
                     ProcessMetrics {
@@ -247,12 +803,353 @@ impl<'a> RunDataset<'a> {
 
 #[cfg(test)]
 mod tests {
+    use super::*;
     use crate::data_access::{DataAccessService, LocalDataAccessService};
     use sqlx::SqlitePool;
 
+    #[test]
+    fn globs_match_names() {
+        assert!(glob_match("k6", "k6"));
+        assert!(glob_match("post*", "postgres"));
+        assert!(glob_match("*gres", "postgres"));
+        assert!(glob_match("p?stg*s", "postgres"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("*a*b", "xaybab"));
+        assert!(!glob_match("post", "postgres"));
+        assert!(!glob_match("?", ""));
+    }
+
+    #[test]
+    fn iterations_can_be_sliced_into_phases() {
+        let metrics = (0..10)
+            .map(|i| CpuMetrics::new("1", "1", "llm", 10.0, 0.0, 4, i * 1000))
+            .collect();
+        let iteration = IterationWithMetrics::new(
+            ScenarioIteration::new("1", "infer", 2, 0, 9000),
+            metrics,
+            vec![],
+        );
+
+        let slice = iteration.slice("inference", 3000, 6000);
+        assert_eq!(slice.scenario_iteration().scenario_name, "inference");
+        assert_eq!(slice.scenario_iteration().iteration, 2);
+        assert_eq!(slice.cpu_metrics().len(), 4);
+        assert_eq!(slice.cpu_usage_total(), 40.0);
+
+        // the samples at 2s and 3s are shared by both windows
+        let slices = iteration.split(&[("GET /a", 0, 3000), ("GET /b", 2000, 5000)]);
+        assert_eq!(slices[0].cpu_usage_total(), 30.0);
+        assert_eq!(slices[1].cpu_usage_total(), 30.0);
+        assert_eq!(
+            slices.iter().map(|s| s.cpu_usage_total()).sum::<f64>(),
+            iteration.slice("both", 0, 5000).cpu_usage_total()
+        );
+    }
+
+    #[test]
+    fn processes_can_be_filtered() {
+        let metrics = ["yarn", "postgres", "k6"]
+            .iter()
+            .enumerate()
+            .map(|(i, name)| CpuMetrics::new("1", &i.to_string(), name, 10.0, 0.0, 4, 0))
+            .collect();
+        let dataset = ObservationDataset::new(vec![IterationWithMetrics::new(
+            ScenarioIteration::new("1", "basket_10", 1, 0, 1000),
+            metrics,
+            vec![],
+        )]);
+
+        let exclude = ProcessFilter::new(vec![], vec!["k6".to_string()]);
+        let dataset = dataset.filter_processes(&exclude);
+        assert_eq!(dataset.data()[0].cpu_usage_total(), 20.0);
+
+        let only = ProcessFilter::new(vec!["post*".to_string(), "0".to_string()], vec![]);
+        let dataset = dataset.filter_processes(&only);
+        let names = dataset.data()[0]
+            .cpu_metrics()
+            .iter()
+            .map(|m| m.process_name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["yarn", "postgres"]);
+    }
+
+    #[test]
+    fn aggregation_methods_work() {
+        let values = [4.0, 1.0, 3.0, 2.0];
+
+        assert_eq!(AggregationMethod::MostRecent.aggregate(&values), Some(2.0));
+        assert_eq!(AggregationMethod::Average.aggregate(&values), Some(2.5));
+        assert_eq!(AggregationMethod::Sum.aggregate(&values), Some(10.0));
+        assert_eq!(AggregationMethod::Median.aggregate(&values), Some(2.5));
+        assert_eq!(
+            AggregationMethod::Median.aggregate(&[3.0, 1.0, 2.0]),
+            Some(2.0)
+        );
+        assert_eq!(AggregationMethod::P95.aggregate(&values), Some(4.0));
+        assert_eq!(AggregationMethod::Min.aggregate(&values), Some(1.0));
+        assert_eq!(AggregationMethod::Max.aggregate(&values), Some(4.0));
+        assert_eq!(AggregationMethod::Median.aggregate(&[]), None);
+
+        let many = (1..=100).map(|v| v as f64).collect::<Vec<_>>();
+        assert_eq!(AggregationMethod::P95.aggregate(&many), Some(95.0));
+    }
+
+    #[test]
+    fn aggregation_methods_can_be_parsed() {
+        assert_eq!("p95".parse(), Ok(AggregationMethod::P95));
+        assert_eq!("most-recent".parse(), Ok(AggregationMethod::MostRecent));
+        assert!("mode".parse::<AggregationMethod>().is_err());
+    }
+
+    #[test]
+    fn outliers_can_be_found_with_zscore_and_iqr() {
+        let values = [10.0, 11.0, 9.0, 10.0, 10.5, 9.5, 30.0];
+
+        let zscore = OutlierDetection {
+            method: OutlierMethod::ZScore,
+            threshold: 2.0,
+            exclude: false,
+        };
+        let iqr = OutlierDetection {
+            method: OutlierMethod::Iqr,
+            threshold: 1.5,
+            exclude: false,
+        };
+        let expected = vec![false, false, false, false, false, false, true];
+        assert_eq!(find_outliers(&values, &zscore), expected);
+        assert_eq!(find_outliers(&values, &iqr), expected);
+
+        // too few values to judge and identical values are never outliers
+        assert_eq!(find_outliers(&[1.0, 100.0], &iqr), vec![false, false]);
+        assert_eq!(find_outliers(&[5.0; 4], &zscore), vec![false; 4]);
+    }
+
+    #[test]
+    fn outlying_iterations_can_be_excluded() {
+        let iteration = |iteration: i64, cpu_usage: f64| {
+            IterationWithMetrics::new(
+                ScenarioIteration::new(
+                    "1",
+                    "basket_10",
+                    iteration,
+                    iteration * 1000,
+                    iteration * 1000 + 500,
+                ),
+                vec![CpuMetrics::new(
+                    "1",
+                    "1337",
+                    "yarn",
+                    cpu_usage,
+                    0.0,
+                    4,
+                    iteration * 1000,
+                )],
+                vec![],
+            )
+        };
+        let dataset = ObservationDataset::new(vec![
+            iteration(1, 10.0),
+            iteration(2, 11.0),
+            iteration(3, 9.0),
+            iteration(4, 95.0),
+        ]);
+        let detection = OutlierDetection {
+            method: OutlierMethod::Iqr,
+            threshold: 1.5,
+            exclude: true,
+        };
+        let scenario_datasets = dataset.by_scenario();
+        let run_datasets = scenario_datasets[0].by_run();
+
+        let outliers = run_datasets[0].outliers(&detection);
+        assert_eq!(outliers.len(), 1);
+        assert_eq!(outliers[0].scenario_iteration().iteration, 4);
+
+        let filtered = run_datasets[0].without_outliers(&detection);
+        assert_eq!(filtered.by_iterations().len(), 3);
+        let max = filtered.aggregated(AggregationMethod::Max);
+        assert_eq!(max[0].cpu_usage_total(), 11.0);
+    }
+
+    #[test]
+    fn slower_iterations_at_the_same_power_are_not_outliers() {
+        // one sample a second at 10% CPU, and 40W if measured
+        let iteration = |iteration: i64, seconds: i64, measured: bool| {
+            let start = iteration * 100_000;
+            let timestamps = (0..seconds).map(|i| start + i * 1000).collect::<Vec<_>>();
+            IterationWithMetrics::new(
+                ScenarioIteration::new("1", "basket_10", iteration, start, start + seconds * 1000),
+                timestamps
+                    .iter()
+                    .map(|timestamp| CpuMetrics::new("1", "1337", "yarn", 10.0, 0.0, 4, *timestamp))
+                    .collect(),
+                vec![],
+            )
+            .with_power_metrics(
+                timestamps
+                    .iter()
+                    .filter(|_| measured)
+                    .map(|timestamp| PowerMetrics::new("1", "tasmota", 40.0, *timestamp))
+                    .collect(),
+            )
+        };
+        let detection = OutlierDetection {
+            method: OutlierMethod::Iqr,
+            threshold: 1.5,
+            exclude: true,
+        };
+        // the same goes for CPU usage without a power meter
+        for measured in [true, false] {
+            let dataset = ObservationDataset::new(
+                [5, 5, 6, 5, 20]
+                    .into_iter()
+                    .zip(1..)
+                    .map(|(seconds, i)| iteration(i, seconds, measured))
+                    .collect(),
+            );
+            let scenario_datasets = dataset.by_scenario();
+            let run_datasets = scenario_datasets[0].by_run();
+            assert!(run_datasets[0].outliers(&detection).is_empty());
+        }
+    }
+
+    #[test]
+    fn throttled_iterations_are_found() {
+        // one sample a second at the given frequencies
+        let iteration = |iteration: i64, frequencies: &[i64], events: Vec<ProcessEvent>| {
+            let start = iteration * 100_000;
+            IterationWithMetrics::new(
+                ScenarioIteration::new("1", "basket_10", iteration, start, start + 10_000),
+                frequencies
+                    .iter()
+                    .enumerate()
+                    .map(|(i, frequency)| {
+                        CpuMetrics::new("1", "1337", "yarn", 50.0, 0.0, 4, start + i as i64 * 1000)
+                            .with_cpu_frequency(Some(*frequency))
+                    })
+                    .collect(),
+                events,
+            )
+        };
+        let dataset = ObservationDataset::new(vec![
+            iteration(1, &[3000; 8], vec![]),
+            // dips briefly, not sustained
+            iteration(2, &[3000, 2000, 2000, 3000, 2000, 2000, 2000, 2000], vec![]),
+            iteration(3, &[3000, 2000, 2000, 2000, 2000, 2000, 3000], vec![]),
+            iteration(
+                4,
+                &[3000; 8],
+                vec![ProcessEvent::new("1", "cpu", THERMAL_THROTTLED, 400_000)],
+            ),
+        ]);
+        let scenario_datasets = dataset.by_scenario();
+        let run_datasets = scenario_datasets[0].by_run();
+
+        let iterations = |throttled: Vec<&IterationWithMetrics>| {
+            throttled
+                .iter()
+                .map(|it| it.scenario_iteration().iteration)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(iterations(run_datasets[0].throttled(None)), vec![3, 4]);
+
+        // everything is well below a faster maximum
+        assert_eq!(
+            iterations(run_datasets[0].throttled(Some(5000))),
+            vec![1, 2, 3, 4]
+        );
+    }
+
+    #[test]
+    fn containers_which_hit_their_cpu_quota_are_found() {
+        let sample = |process_name: &str, timestamp: i64, periods: i64, throttled: i64| {
+            CpuMetrics::new("1", process_name, process_name, 100.0, 0.0, 4, timestamp)
+                .with_throttling(Some(periods), Some(throttled))
+        };
+        let iteration = IterationWithMetrics::new(
+            ScenarioIteration::new("1", "basket_10", 1, 0, 10_000),
+            vec![
+                sample("db", 0, 10, 0),
+                sample("db", 1000, 10, 0),
+                sample("web", 0, 10, 2),
+                sample("web", 1000, 10, 3),
+                CpuMetrics::new("1", "1337", "yarn", 50.0, 0.0, 4, 0),
+            ],
+            vec![],
+        );
+
+        assert_eq!(iteration.cpu_quota_throttling(), vec![("web", 25.0)]);
+    }
+
+    #[test]
+    fn cpu_share_of_a_process_is_found() {
+        let dataset = ObservationDataset::new(vec![IterationWithMetrics::new(
+            ScenarioIteration::new("1", "basket_10", 1, 0, 10_000),
+            vec![
+                CpuMetrics::new("1", "1337", "yarn", 30.0, 0.0, 4, 0),
+                CpuMetrics::new("1", "42", "cardamon", 10.0, 0.0, 4, 0),
+                CpuMetrics::new("1", "1337", "yarn", 50.0, 0.0, 4, 1000),
+                CpuMetrics::new("1", "42", "cardamon", 10.0, 0.0, 4, 1000),
+            ],
+            vec![],
+        )]);
+        let scenario_datasets = dataset.by_scenario();
+        let run_datasets = scenario_datasets[0].by_run();
+
+        assert_eq!(run_datasets[0].cpu_share("cardamon"), 20.0);
+        assert_eq!(run_datasets[0].cpu_share("postgres"), 0.0);
+    }
+
+    #[test]
+    fn iterations_are_aggregated_by_process() {
+        let iteration = |iteration: i64, cpu_usage: f64| {
+            IterationWithMetrics::new(
+                ScenarioIteration::new(
+                    "1",
+                    "basket_10",
+                    iteration,
+                    iteration * 1000,
+                    iteration * 1000 + 500,
+                ),
+                vec![CpuMetrics::new(
+                    "1",
+                    "1337",
+                    "yarn",
+                    cpu_usage,
+                    0.0,
+                    4,
+                    iteration * 1000,
+                )],
+                vec![],
+            )
+        };
+        let dataset = ObservationDataset::new(vec![
+            iteration(3, 90.0),
+            iteration(1, 10.0),
+            iteration(2, 20.0),
+        ]);
+        let scenario_datasets = dataset.by_scenario();
+        let run_datasets = scenario_datasets[0].by_run();
+
+        let median = run_datasets[0].aggregated(AggregationMethod::Median);
+        assert_eq!(median.len(), 1);
+        assert_eq!(median[0].cpu_usage_total(), 20.0);
+        assert!(matches!(
+            median[0].cpu_usage_minmax(),
+            MinMaxResult::MinMax(min, max) if *min == 10.0 && *max == 90.0
+        ));
+
+        let most_recent = scenario_datasets[0].aggregated(AggregationMethod::MostRecent);
+        assert_eq!(most_recent[0].cpu_usage_total(), 90.0);
+    }
+
     #[sqlx::test(
         migrations = "./migrations",
-        fixtures("../fixtures/scenario_iterations.sql", "../fixtures/cpu_metrics.sql")
+        fixtures(
+            "../fixtures/scenario_iterations.sql",
+            "../fixtures/cpu_metrics.sql",
+            "../fixtures/process_events.sql"
+        )
     )]
     async fn datasets_work(pool: SqlitePool) -> anyhow::Result<()> {
         let data_access_service = LocalDataAccessService::new(pool.clone());
@@ -270,6 +1167,12 @@ mod tests {
             let run_datasets = scenario_dataset.by_run();
             assert_eq!(run_datasets.len(), 2);
 
+            let process_events = run_datasets
+                .iter()
+                .map(|run_dataset| run_dataset.process_events().len())
+                .sum::<usize>();
+            assert_eq!(process_events, 4);
+
             for run_dataset in run_datasets.iter() {
                 // println!("{:?}", run_dataset);
                 let avg = run_dataset.averaged();
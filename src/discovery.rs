@@ -0,0 +1,172 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Turns the benchmarks and tests of an existing suite into scenarios so they can be observed
+//! without writing a scenario for each one. Every discovered scenario observes its own command.
+
+use crate::config::Scenario;
+use anyhow::Context;
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// A benchmark executable built by cargo.
+#[derive(Debug, PartialEq)]
+struct BenchTarget {
+    name: String,
+    executable: PathBuf,
+}
+
+/// Builds the benchmarks of the cargo package in the current directory and creates a scenario for
+/// each benchmark, which runs the benchmark's executable directly so it can be observed.
+pub fn cargo_benches(iterations: u32) -> anyhow::Result<Vec<Scenario>> {
+    println!("Building benchmarks");
+    let output = Command::new("cargo")
+        .args(["bench", "--no-run", "--message-format=json"])
+        .output()
+        .context("Unable to run cargo")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Unable to build benchmarks: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    let mut scenarios = vec![];
+    for target in bench_targets(&String::from_utf8_lossy(&output.stdout)) {
+        let output = Command::new(&target.executable)
+            .args(["--bench", "--list"])
+            .output()
+            .with_context(|| format!("Unable to list the benchmarks in {}", target.name))?;
+
+        for bench in bench_names(&String::from_utf8_lossy(&output.stdout)) {
+            let executable = target.executable.to_string_lossy();
+            let command = shlex::try_join([&executable, "--bench", "--exact", bench])?;
+            scenarios.push(discovered(
+                &format!("{}/{bench}", target.name),
+                &format!("Benchmark {bench} in {}", target.name),
+                command,
+                iterations,
+            ));
+        }
+    }
+    Ok(scenarios)
+}
+
+/// Collects the tests pytest finds at the given path and creates a scenario for each test.
+pub fn pytest(path: &Path, iterations: u32) -> anyhow::Result<Vec<Scenario>> {
+    let output = Command::new("pytest")
+        .args(["--collect-only", "-q"])
+        .arg(path)
+        .output()
+        .context("Unable to run pytest")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Unable to collect tests: {}",
+            String::from_utf8_lossy(&output.stdout)
+        );
+    }
+
+    test_ids(&String::from_utf8_lossy(&output.stdout))
+        .into_iter()
+        .map(|test| {
+            let command = shlex::try_join(["pytest", "-q", test])?;
+            Ok(discovered(
+                test,
+                &format!("Test {test}"),
+                command,
+                iterations,
+            ))
+        })
+        .collect()
+}
+
+fn discovered(name: &str, desc: &str, command: String, iterations: u32) -> Scenario {
+    Scenario {
+        name: String::from(name),
+        desc: String::from(desc),
+        command,
+        iterations,
+        processes: vec![],
+        parameter: None,
+        observe_command: true,
+        container: None,
+        cold_start: false,
+        cpus: None,
+    }
+}
+
+/// Executables cargo built for benchmarking, read from the JSON messages of
+/// `cargo bench --no-run --message-format=json`.
+fn bench_targets(messages: &str) -> Vec<BenchTarget> {
+    messages
+        .lines()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .filter(|message| message["reason"] == "compiler-artifact")
+        .filter_map(|message| {
+            Some(BenchTarget {
+                name: message["target"]["name"].as_str()?.to_string(),
+                executable: PathBuf::from(message["executable"].as_str()?),
+            })
+        })
+        .collect()
+}
+
+/// Benchmarks listed by a libtest or criterion benchmark executable run with `--list`.
+fn bench_names(list: &str) -> Vec<&str> {
+    list.lines()
+        .filter_map(|line| {
+            line.strip_suffix(": benchmark")
+                .or_else(|| line.strip_suffix(": bench"))
+        })
+        .collect()
+}
+
+/// Node ids printed by `pytest --collect-only -q`.
+fn test_ids(collected: &str) -> Vec<&str> {
+    collected
+        .lines()
+        .take_while(|line| !line.trim().is_empty())
+        .filter(|line| line.contains("::"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn benchmarks_are_discovered_from_cargo_output() {
+        let messages = r#"{"reason":"compiler-artifact","target":{"kind":["lib"],"name":"app"},"executable":null}
+{"reason":"compiler-artifact","target":{"kind":["bench"],"name":"parsing"},"executable":"/app/target/release/deps/parsing-1a2b"}
+{"reason":"build-finished","success":true}"#;
+        assert_eq!(
+            bench_targets(messages),
+            vec![BenchTarget {
+                name: String::from("parsing"),
+                executable: PathBuf::from("/app/target/release/deps/parsing-1a2b"),
+            }]
+        );
+
+        let libtest = "tests::it_works: test\nbenches::parse_small: bench\n";
+        assert_eq!(bench_names(libtest), vec!["benches::parse_small"]);
+        let criterion = "parse/small: benchmark\nparse/large: benchmark\n";
+        assert_eq!(bench_names(criterion), vec!["parse/small", "parse/large"]);
+    }
+
+    #[test]
+    fn tests_are_discovered_from_pytest_output() {
+        let collected = "tests/test_api.py::test_list\ntests/test_api.py::test_get[1-2]\n\n2 tests collected in 0.01s\n";
+        assert_eq!(
+            test_ids(collected),
+            vec![
+                "tests/test_api.py::test_list",
+                "tests/test_api.py::test_get[1-2]"
+            ]
+        );
+    }
+}
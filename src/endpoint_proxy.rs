@@ -0,0 +1,201 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! A reverse proxy scenarios send their requests through on the way to the application. It
+//! counts the requests made to each endpoint so the energy of an iteration can be shared between
+//! the endpoints it served.
+
+use crate::config::Proxy;
+use anyhow::Context;
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{header, HeaderMap, Method, StatusCode},
+    response::{IntoResponse, Response},
+    Router,
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tokio::task::JoinHandle;
+
+struct ProxyState {
+    target: String,
+    client: reqwest::Client,
+    counts: Arc<Mutex<HashMap<String, i64>>>,
+}
+
+/// The running proxy, which stops when dropped.
+pub struct ProxyHandle {
+    counts: Arc<Mutex<HashMap<String, i64>>>,
+    task: JoinHandle<()>,
+}
+impl ProxyHandle {
+    /// Requests made to each endpoint since the counts were last taken.
+    pub fn take_counts(&self) -> HashMap<String, i64> {
+        self.counts
+            .lock()
+            .map(|mut counts| std::mem::take(&mut *counts))
+            .unwrap_or_default()
+    }
+}
+impl Drop for ProxyHandle {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Starts listening for requests to forward to the application.
+pub async fn start(proxy: &Proxy) -> anyhow::Result<ProxyHandle> {
+    let counts = Arc::new(Mutex::new(HashMap::new()));
+    let state = ProxyState {
+        target: proxy.target.trim_end_matches('/').to_string(),
+        // redirects are the client's to follow, each request is counted against its own endpoint
+        client: reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()?,
+        counts: counts.clone(),
+    };
+    let app = Router::new().fallback(forward).with_state(Arc::new(state));
+
+    let listener = tokio::net::TcpListener::bind(&proxy.listen)
+        .await
+        .with_context(|| format!("Unable to start the endpoint proxy on {}", proxy.listen))?;
+    let task = tokio::spawn(async move {
+        if let Err(err) = axum::serve(listener, app).await {
+            tracing::error!("Endpoint proxy stopped: {err}");
+        }
+    });
+
+    Ok(ProxyHandle { counts, task })
+}
+
+async fn forward(State(state): State<Arc<ProxyState>>, request: Request) -> Response {
+    let (parts, body) = request.into_parts();
+    if let Ok(mut counts) = state.counts.lock() {
+        *counts
+            .entry(endpoint(&parts.method, parts.uri.path()))
+            .or_default() += 1;
+    }
+
+    let body = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(err) => return (StatusCode::BAD_REQUEST, err.to_string()).into_response(),
+    };
+    let path_and_query = parts.uri.path_and_query().map_or("/", |p| p.as_str());
+    let mut headers = parts.headers;
+    headers.remove(header::HOST);
+
+    let response = state
+        .client
+        .request(parts.method, format!("{}{path_and_query}", state.target))
+        .headers(headers)
+        .body(body)
+        .send()
+        .await;
+    match response {
+        Ok(response) => {
+            let status = response.status();
+            let mut headers = response.headers().clone();
+            strip_hop_by_hop(&mut headers);
+            match response.bytes().await {
+                Ok(body) => (status, headers, Body::from(body)).into_response(),
+                Err(err) => (StatusCode::BAD_GATEWAY, err.to_string()).into_response(),
+            }
+        }
+        Err(err) => (StatusCode::BAD_GATEWAY, err.to_string()).into_response(),
+    }
+}
+
+/// The response body is forwarded whole, so how it was framed between the application and the
+/// proxy doesn't apply to the client.
+fn strip_hop_by_hop(headers: &mut HeaderMap) {
+    for name in [header::CONNECTION, header::TRANSFER_ENCODING] {
+        headers.remove(name);
+    }
+}
+
+/// The endpoint a request was made to, with ids in the path replaced by `:id` so requests for
+/// different resources are counted together, e.g. `GET /cart/42` is `GET /cart/:id`.
+pub fn endpoint(method: &Method, path: &str) -> String {
+    let path = path
+        .split('/')
+        .map(|segment| if is_id(segment) { ":id" } else { segment })
+        .collect::<Vec<_>>()
+        .join("/");
+    format!("{method} {path}")
+}
+
+/// Numbers, UUIDs and long hex strings, e.g. hashes or object ids.
+fn is_id(segment: &str) -> bool {
+    let hex = segment.chars().all(|c| c.is_ascii_hexdigit() || c == '-');
+    !segment.is_empty()
+        && (segment.chars().all(|c| c.is_ascii_digit())
+            || (hex && segment.len() >= 16 && segment.chars().any(|c| c.is_ascii_digit())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_in_paths_are_counted_together() {
+        assert_eq!(endpoint(&Method::GET, "/cart/42"), "GET /cart/:id");
+        assert_eq!(
+            endpoint(
+                &Method::DELETE,
+                "/users/7c9e6679-7425-40de-944b-e07fc1f90ae7/sessions"
+            ),
+            "DELETE /users/:id/sessions"
+        );
+        assert_eq!(
+            endpoint(&Method::GET, "/commits/507f1f77bcf86cd799439011"),
+            "GET /commits/:id"
+        );
+        assert_eq!(endpoint(&Method::POST, "/search/"), "POST /search/");
+        assert_eq!(endpoint(&Method::GET, "/v2/decade"), "GET /v2/decade");
+        assert_eq!(endpoint(&Method::GET, "/"), "GET /");
+    }
+
+    #[tokio::test]
+    async fn requests_are_forwarded_and_counted() -> anyhow::Result<()> {
+        let app = Router::new().fallback(|request: Request| async move {
+            format!("{} {}", request.method(), request.uri())
+        });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let target = format!("http://{}", listener.local_addr()?);
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        // find a free port for the proxy
+        let listen = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await?
+            .local_addr()?
+            .to_string();
+        let proxy = start(&Proxy {
+            listen: listen.clone(),
+            target,
+        })
+        .await?;
+
+        let client = reqwest::Client::new();
+        for path in ["/cart/1?full=true", "/cart/2", "/search"] {
+            let body = client
+                .get(format!("http://{listen}{path}"))
+                .send()
+                .await?
+                .text()
+                .await?;
+            assert_eq!(body, format!("GET {path}"));
+        }
+
+        let counts = proxy.take_counts();
+        assert_eq!(counts.get("GET /cart/:id"), Some(&2));
+        assert_eq!(counts.get("GET /search"), Some(&1));
+        assert!(proxy.take_counts().is_empty());
+        Ok(())
+    }
+}
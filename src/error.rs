@@ -0,0 +1,156 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Errors returned by the public API of the crate (config loading, data access and the metrics
+//! loggers). Each error carries a machine readable code so library users and the server can map
+//! failures to helpful messages and status codes without matching on error text.
+
+use std::path::PathBuf;
+
+pub type Result<T> = std::result::Result<T, CardamonError>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum CardamonError {
+    #[error("Unable to read config file {}", path.display())]
+    ConfigRead {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("Error parsing config file.")]
+    ConfigParse(#[from] toml::de::Error),
+
+    #[error("{0}")]
+    NotFound(String),
+
+    #[error("{0}")]
+    InvalidInput(String),
+
+    #[error("{context}")]
+    Database {
+        context: String,
+        #[source]
+        source: sqlx::Error,
+    },
+
+    #[error("{context}")]
+    Remote {
+        context: String,
+        #[source]
+        source: reqwest::Error,
+    },
+
+    #[error("{context}")]
+    Io {
+        context: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("Metrics log contains {0} errors, please check trace")]
+    MetricsLog(usize),
+}
+impl CardamonError {
+    /// A stable identifier for the kind of error, safe to match on and to return from the API.
+    pub fn code(&self) -> &'static str {
+        match self {
+            CardamonError::ConfigRead { .. } => "config_read",
+            CardamonError::ConfigParse(_) => "config_parse",
+            CardamonError::NotFound(_) => "not_found",
+            CardamonError::InvalidInput(_) => "invalid_input",
+            CardamonError::Database { .. } => "database",
+            CardamonError::Remote { .. } => "remote",
+            CardamonError::Io { .. } => "io",
+            CardamonError::MetricsLog(_) => "metrics_log",
+        }
+    }
+
+    /// Finds the first `CardamonError` in the chain of an `anyhow::Error`, for callers which
+    /// receive errors through code that still uses anyhow.
+    pub fn find(err: &anyhow::Error) -> Option<&CardamonError> {
+        err.chain()
+            .find_map(|cause| cause.downcast_ref::<CardamonError>())
+    }
+}
+
+impl From<reqwest::Error> for CardamonError {
+    fn from(source: reqwest::Error) -> Self {
+        CardamonError::Remote {
+            context: "Unable to reach remote server".to_string(),
+            source,
+        }
+    }
+}
+
+impl From<sqlx::Error> for CardamonError {
+    fn from(source: sqlx::Error) -> Self {
+        CardamonError::Database {
+            context: "Database error".to_string(),
+            source,
+        }
+    }
+}
+
+/// Attaches a message to the errors of the libraries cardamon builds on, in the same way as
+/// `anyhow::Context`.
+pub trait Context<T> {
+    fn context<C: Into<String>>(self, context: C) -> Result<T>;
+}
+impl<T> Context<T> for std::result::Result<T, sqlx::Error> {
+    fn context<C: Into<String>>(self, context: C) -> Result<T> {
+        self.map_err(|source| CardamonError::Database {
+            context: context.into(),
+            source,
+        })
+    }
+}
+impl<T> Context<T> for std::result::Result<T, reqwest::Error> {
+    fn context<C: Into<String>>(self, context: C) -> Result<T> {
+        self.map_err(|source| CardamonError::Remote {
+            context: context.into(),
+            source,
+        })
+    }
+}
+impl<T> Context<T> for std::result::Result<T, std::io::Error> {
+    fn context<C: Into<String>>(self, context: C) -> Result<T> {
+        self.map_err(|source| CardamonError::Io {
+            context: context.into(),
+            source,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors_can_be_found_behind_anyhow_context() {
+        let err = anyhow::Error::new(CardamonError::NotFound(
+            "Unable to find scenario with name: nope".to_string(),
+        ))
+        .context("Error creating execution plan");
+
+        let cardamon_err = CardamonError::find(&err).expect("should find the cardamon error");
+        assert_eq!(cardamon_err.code(), "not_found");
+        assert_eq!(
+            cardamon_err.to_string(),
+            "Unable to find scenario with name: nope"
+        );
+    }
+
+    #[test]
+    fn library_errors_keep_their_context() {
+        let res: std::result::Result<(), sqlx::Error> = Err(sqlx::Error::RowNotFound);
+        let err = res.context("Error fetching run from db.").unwrap_err();
+
+        assert_eq!(err.code(), "database");
+        assert_eq!(err.to_string(), "Error fetching run from db.");
+        assert!(std::error::Error::source(&err).is_some());
+    }
+}
@@ -0,0 +1,159 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Runs exported as Green Software Foundation Impact Framework manifests, so the energy cardamon
+//! measured can be fed through IF plugins such as `sci`. The manifest has a child per scenario,
+//! each with a child per process whose inputs are the energy it used in each iteration. No
+//! plugins are configured, they're added to the manifest before running it with `if-run`.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+const WH_PER_KWH: f64 = 1000.0;
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Manifest {
+    pub name: String,
+    pub description: String,
+    pub tags: BTreeMap<String, String>,
+    pub aggregation: Aggregation,
+    pub initialize: Initialize,
+    pub tree: Node,
+}
+impl Manifest {
+    pub fn new(run_id: &str, description: &str) -> Self {
+        Self {
+            name: format!("cardamon run {run_id}"),
+            description: description.to_string(),
+            tags: BTreeMap::from([(String::from("cardamon/run-id"), run_id.to_string())]),
+            aggregation: Aggregation {
+                metrics: vec![String::from("energy")],
+                r#type: String::from("both"),
+            },
+            initialize: Initialize::default(),
+            tree: Node::default(),
+        }
+    }
+
+    /// Adds the energy a process used during an iteration of a scenario.
+    ///
+    /// # Arguments
+    ///
+    /// * `start_time` - when the iteration started, in milliseconds since the unix epoch
+    /// * `stop_time` - when the iteration stopped, in milliseconds since the unix epoch
+    pub fn add_input(
+        &mut self,
+        scenario_name: &str,
+        process_name: &str,
+        iteration: i64,
+        (start_time, stop_time): (i64, i64),
+        energy_wh: f64,
+    ) {
+        let timestamp = chrono::DateTime::from_timestamp_millis(start_time)
+            .unwrap_or_default()
+            .to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+        self.tree
+            .children
+            .entry(scenario_name.to_string())
+            .or_default()
+            .children
+            .entry(process_name.to_string())
+            .or_default()
+            .inputs
+            .push(Input {
+                timestamp,
+                duration: (stop_time - start_time) as f64 / 1000.0,
+                energy: energy_wh / WH_PER_KWH,
+                iteration,
+            });
+    }
+
+    pub fn to_yaml(&self) -> anyhow::Result<String> {
+        Ok(serde_yaml::to_string(self)?)
+    }
+}
+
+/// The metrics IF totals across each component's inputs and children.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Aggregation {
+    pub metrics: Vec<String>,
+    pub r#type: String,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct Initialize {
+    pub plugins: BTreeMap<String, serde_yaml::Value>,
+}
+
+/// A component of the tree, either grouping other components or observed directly.
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct Node {
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub children: BTreeMap<String, Node>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub inputs: Vec<Input>,
+}
+
+/// An observation of a component, named as IF's plugins expect.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Input {
+    /// RFC 3339 UTC time the observation starts.
+    pub timestamp: String,
+    /// Seconds the observation covers.
+    pub duration: f64,
+    /// Kilowatt hours used during the observation.
+    pub energy: f64,
+    #[serde(rename = "cardamon/iteration")]
+    pub iteration: i64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn iterations_are_inputs_of_each_scenarios_processes() -> anyhow::Result<()> {
+        let mut manifest = Manifest::new("abc", "checkout observation");
+        manifest.add_input(
+            "basket_10",
+            "server",
+            1,
+            (1_700_000_000_000, 1_700_000_002_500),
+            5.0,
+        );
+        manifest.add_input(
+            "basket_10",
+            "server",
+            2,
+            (1_700_000_003_000, 1_700_000_005_000),
+            4.0,
+        );
+        manifest.add_input(
+            "basket_10",
+            "db",
+            1,
+            (1_700_000_000_000, 1_700_000_002_500),
+            1.0,
+        );
+
+        let server = &manifest.tree.children["basket_10"].children["server"];
+        assert_eq!(server.inputs.len(), 2);
+        assert_eq!(server.inputs[0].timestamp, "2023-11-14T22:13:20.000Z");
+        assert_eq!(server.inputs[0].duration, 2.5);
+        assert_eq!(server.inputs[0].energy, 0.005);
+
+        let yaml = manifest.to_yaml()?;
+        let parsed = serde_yaml::from_str::<serde_yaml::Value>(&yaml)?;
+        assert_eq!(parsed["name"].as_str(), Some("cardamon run abc"));
+        assert!(parsed["initialize"]["plugins"].is_mapping());
+        assert_eq!(
+            parsed["tree"]["children"]["basket_10"]["children"]["db"]["inputs"][0]["energy"]
+                .as_f64(),
+            Some(0.001)
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,298 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use crate::data_access::{
+    cpu_metrics::CpuMetrics, run::Run, scenario_iteration::ScenarioIteration, DataAccessService,
+};
+use anyhow::Context;
+use itertools::Itertools;
+use serde_json::Value;
+use std::path::Path;
+
+/// A single measurement taken by another tool. Like cardamon's own samples, each record is
+/// assumed to cover one second.
+#[derive(Debug, PartialEq)]
+pub struct ImportedRecord {
+    /// Milliseconds since the unix epoch.
+    pub timestamp: i64,
+    pub process_name: String,
+    /// Defaults to the process name when the source has no process ids.
+    pub process_id: String,
+    /// Defaults to 1 when the source doesn't distinguish iterations.
+    pub iteration: i64,
+    /// CPU usage as a percentage of a single core.
+    pub cpu_usage: f64,
+    pub core_count: i64,
+    /// Memory in bytes.
+    pub memory_usage: Option<i64>,
+    /// Power in watts.
+    pub power: Option<f64>,
+}
+
+/// Reads measurements from a CSV file with a header row or a JSON array of objects. The columns
+/// (or keys) are `timestamp` and `process` which are required and `pid`, `iteration`, `cpu_usage`,
+/// `core_count`, `memory` and `power` which are optional. Timestamps are either milliseconds since
+/// the unix epoch or RFC 3339.
+pub fn read_records(path: &Path) -> anyhow::Result<Vec<ImportedRecord>> {
+    let contents =
+        std::fs::read_to_string(path).context(format!("Unable to read {}", path.display()))?;
+
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("csv") => parse_csv(&contents),
+        Some("json") => parse_json(&contents),
+        _ => Err(anyhow::anyhow!(
+            "Unable to import {}, expected a .csv or .json file",
+            path.display()
+        )),
+    }
+}
+
+/// Stores imported measurements as a new run of the given scenario so they can be analysed like
+/// any other run.
+///
+/// # Returns
+///
+/// The id of the new run.
+pub async fn import(
+    scenario_name: &str,
+    records: &[ImportedRecord],
+    data_access_service: &dyn DataAccessService,
+) -> anyhow::Result<String> {
+    if records.is_empty() {
+        return Err(anyhow::anyhow!("Nothing to import"));
+    }
+
+    let run_id = nanoid::nanoid!(5);
+    let (scenario_iterations, cpu_metrics) = into_rows(&run_id, scenario_name, records);
+
+    let stop_time = scenario_iterations.iter().map(|it| it.stop_time).max();
+    data_access_service
+        .run_dao()
+        .persist(
+            &Run::new(&run_id, None)
+                .in_project(data_access_service.project())
+                .with_stop_time(stop_time),
+        )
+        .await?;
+    for scenario_iteration in scenario_iterations.iter() {
+        data_access_service
+            .scenario_iteration_dao()
+            .persist(scenario_iteration)
+            .await?;
+    }
+    for metrics in cpu_metrics.iter() {
+        data_access_service
+            .cpu_metrics_dao()
+            .persist(metrics)
+            .await?;
+    }
+
+    Ok(run_id)
+}
+
+/// Maps records onto scenario iterations spanning the first to last record of each iteration
+/// and the metrics within them.
+fn into_rows(
+    run_id: &str,
+    scenario_name: &str,
+    records: &[ImportedRecord],
+) -> (Vec<ScenarioIteration>, Vec<CpuMetrics>) {
+    let scenario_iterations = records
+        .iter()
+        .into_group_map_by(|record| record.iteration)
+        .into_iter()
+        .sorted_by_key(|(iteration, _)| *iteration)
+        .map(|(iteration, records)| {
+            let timestamps = records.iter().map(|record| record.timestamp);
+            ScenarioIteration::new(
+                run_id,
+                scenario_name,
+                iteration,
+                timestamps.clone().min().unwrap_or(0),
+                timestamps.max().unwrap_or(0),
+            )
+        })
+        .collect();
+
+    let cpu_metrics = records
+        .iter()
+        .map(|record| {
+            CpuMetrics::new(
+                run_id,
+                &record.process_id,
+                &record.process_name,
+                record.cpu_usage,
+                0.0,
+                record.core_count,
+                record.timestamp,
+            )
+            .with_memory_usage(record.memory_usage)
+            .with_power(record.power)
+        })
+        .collect();
+
+    (scenario_iterations, cpu_metrics)
+}
+
+fn parse_json(contents: &str) -> anyhow::Result<Vec<ImportedRecord>> {
+    let records = serde_json::from_str::<Vec<serde_json::Map<String, Value>>>(contents)
+        .context("Expected a JSON array of objects")?;
+
+    records
+        .iter()
+        .enumerate()
+        .map(|(i, record)| {
+            parse_record(|key| match record.get(key)? {
+                Value::Null => None,
+                Value::String(value) => Some(value.clone()),
+                value => Some(value.to_string()),
+            })
+            .context(format!("Invalid record at index {i}"))
+        })
+        .collect()
+}
+
+fn parse_csv(contents: &str) -> anyhow::Result<Vec<ImportedRecord>> {
+    let mut lines = contents.lines().filter(|line| !line.trim().is_empty());
+    let header = split_csv_line(lines.next().context("CSV file is empty")?);
+
+    lines
+        .enumerate()
+        .map(|(i, line)| {
+            let fields = split_csv_line(line);
+            parse_record(|key| {
+                let index = header.iter().position(|column| column == key)?;
+                fields.get(index).filter(|field| !field.is_empty()).cloned()
+            })
+            .context(format!("Invalid record on line {}", i + 2))
+        })
+        .collect()
+}
+
+/// Splits a line of CSV into its fields, honouring double quoted fields containing commas or
+/// escaped ("") quotes.
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            ('"', _) => quoted = !quoted,
+            (',', false) => fields.push(std::mem::take(&mut field).trim().to_string()),
+            (c, _) => field.push(c),
+        }
+    }
+    fields.push(field.trim().to_string());
+    fields
+}
+
+fn parse_record(get: impl Fn(&str) -> Option<String>) -> anyhow::Result<ImportedRecord> {
+    let parse_num = |key: &str| -> anyhow::Result<Option<f64>> {
+        get(key)
+            .map(|value| {
+                value
+                    .parse::<f64>()
+                    .context(format!("{key} should be a number, found {value}"))
+            })
+            .transpose()
+    };
+
+    let timestamp = get("timestamp").context("Missing timestamp")?;
+    let process_name = get("process").context("Missing process")?;
+
+    Ok(ImportedRecord {
+        timestamp: parse_timestamp(&timestamp)?,
+        process_id: get("pid").unwrap_or_else(|| process_name.clone()),
+        process_name,
+        iteration: parse_num("iteration")?.map_or(1, |iteration| iteration as i64),
+        cpu_usage: parse_num("cpu_usage")?.unwrap_or(0.0),
+        core_count: parse_num("core_count")?.map_or(0, |core_count| core_count as i64),
+        memory_usage: parse_num("memory")?.map(|memory| memory as i64),
+        power: parse_num("power")?,
+    })
+}
+
+fn parse_timestamp(timestamp: &str) -> anyhow::Result<i64> {
+    if let Ok(millis) = timestamp.parse::<i64>() {
+        return Ok(millis);
+    }
+    chrono::DateTime::parse_from_rfc3339(timestamp)
+        .map(|datetime| datetime.timestamp_millis())
+        .context(format!(
+            "timestamp should be milliseconds or RFC 3339, found {timestamp}"
+        ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_can_be_parsed_from_csv() -> anyhow::Result<()> {
+        let csv = "timestamp,process,pid,cpu_usage,power\n\
+                   1718000000000,\"nginx, worker\",101,12.5,3.2\n\
+                   2024-06-10T06:13:21Z,postgres,,40,\n";
+        let records = parse_csv(csv)?;
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].process_name, "nginx, worker");
+        assert_eq!(records[0].process_id, "101");
+        assert_eq!(records[0].power, Some(3.2));
+        assert_eq!(records[1].timestamp, 1718000001000);
+        assert_eq!(records[1].process_id, "postgres");
+        assert_eq!(records[1].iteration, 1);
+        assert_eq!(records[1].power, None);
+
+        assert!(parse_csv("timestamp,process\nyesterday,nginx\n").is_err());
+        assert!(parse_csv("timestamp,cpu_usage\n1,5\n").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn records_can_be_parsed_from_json() -> anyhow::Result<()> {
+        let json = r#"[
+            { "timestamp": 1000, "process": "yarn", "pid": 42, "iteration": 2, "memory": 1048576 },
+            { "timestamp": "1970-01-01T00:00:02Z", "process": "yarn", "cpu_usage": 50 }
+        ]"#;
+        let records = parse_json(json)?;
+
+        assert_eq!(records[0].process_id, "42");
+        assert_eq!(records[0].iteration, 2);
+        assert_eq!(records[0].memory_usage, Some(1048576));
+        assert_eq!(records[1].timestamp, 2000);
+        assert_eq!(records[1].cpu_usage, 50.0);
+
+        assert!(parse_json(r#"{ "timestamp": 1000 }"#).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn records_are_grouped_into_iterations() -> anyhow::Result<()> {
+        let json = r#"[
+            { "timestamp": 3000, "process": "yarn", "iteration": 2 },
+            { "timestamp": 1000, "process": "yarn" },
+            { "timestamp": 2000, "process": "yarn" },
+            { "timestamp": 4000, "process": "yarn", "iteration": 2 }
+        ]"#;
+        let (iterations, metrics) = into_rows("1", "basket_10", &parse_json(json)?);
+
+        assert_eq!(
+            iterations,
+            vec![
+                ScenarioIteration::new("1", "basket_10", 1, 1000, 2000),
+                ScenarioIteration::new("1", "basket_10", 2, 3000, 4000),
+            ]
+        );
+        assert_eq!(metrics.len(), 4);
+        Ok(())
+    }
+}
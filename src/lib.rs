@@ -1,78 +1,156 @@
+pub mod active_runs;
+pub mod agent;
+pub mod alerts;
+pub mod badge;
+pub mod baseline;
+pub mod boavizta;
+pub mod carbon;
+pub mod cloud;
 pub mod config;
 pub mod data_access;
 pub mod dataset;
+pub mod discovery;
+pub mod endpoint_proxy;
+pub mod error;
+pub mod impact_framework;
+pub mod import;
+pub mod load_test;
+pub mod logs;
 pub mod metrics;
 pub mod metrics_logger;
+pub mod model;
+pub mod notifications;
+pub mod orphans;
+pub mod process_group;
+pub mod progress;
+pub mod report;
+pub mod secrets;
+pub mod signing;
+pub mod stats;
+pub mod system_context;
+pub mod telemetry;
+pub mod watch;
 
 use anyhow::{anyhow, Context};
-use config::{ExecutionPlan, ProcessToObserve, ProcessType, Redirect, ScenarioToExecute};
-use data_access::{scenario_iteration::ScenarioIteration, DataAccessService};
+use config::{
+    ExecutionPlan, ProcessToExecute, ProcessToObserve, ProcessType, Redirect, ScenarioContainer,
+    ScenarioToExecute, StopSignal,
+};
+use data_access::{
+    endpoint_request::EndpointRequest,
+    energy_mix::EnergyMix,
+    iteration_phase::IterationPhase,
+    iteration_result::IterationResult,
+    process_event::ProcessEvent,
+    run::Run,
+    run_phase::{RunPhase, PHASE_SHUTDOWN, PHASE_STARTUP},
+    scenario_iteration::{ScenarioIteration, STATUS_CANCELLED, STATUS_PROCESS_EXITED},
+    DataAccessService,
+};
 use dataset::ObservationDataset;
-use std::{fs::File, path::Path, time};
-use subprocess::{Exec, NullFile, Redirection};
+use futures_util::FutureExt;
+use itertools::Itertools;
+use metrics_logger::LoggingOptions;
+use std::{path::Path, time};
+use subprocess::{Popen, PopenConfig, Redirection};
+use tokio::{
+    io::AsyncBufReadExt,
+    process::{Child, ChildStdout},
+};
+
+/// The name cardamon's own process is recorded under when measuring its overhead.
+pub const SELF_PROCESS_NAME: &str = "cardamon";
 
 /// Runs the given command as a detached processes. This function does not block because the
-/// process is managed by the OS and running separately from this thread.
+/// process is managed by the OS and running separately from this thread. On unix the process
+/// leads a new process group so it can be stopped along with any children it starts.
 ///
 /// # Arguments
 ///
 /// * command - The command to run.
+/// * outputs - Where the stdout and stderr of the process are written.
 ///
 /// # Returns
 ///
 /// The PID returned by the operating system
-fn run_command_detached(command: &str, redirect: &Option<Redirect>) -> anyhow::Result<u32> {
-    let redirect = redirect.unwrap_or(Redirect::File);
-
+fn run_command_detached(
+    command: &str,
+    (stdout, stderr): (Redirection, Redirection),
+) -> anyhow::Result<u32> {
     // break command string into POSIX words
     let words = shlex::split(command).expect("Command string is not POSIX compliant.");
+    if words.is_empty() {
+        return Err(anyhow!(""));
+    }
 
-    // split command string into command and args
-    match &words[..] {
-        [command, args @ ..] => {
-            let exec = Exec::cmd(command).args(args);
-            // for arg in args {
-            //     exec = exec.arg(arg);
-            // }
-            //
-
-            let exec = match redirect {
-                Redirect::Null => exec.stdout(NullFile).stderr(NullFile),
-                Redirect::Parent => exec,
-                Redirect::File => {
-                    let out_file = File::create(Path::new("./.stdout"))?;
-                    let err_file = File::create(Path::new("./.stderr"))?;
-
-                    exec.stdout(Redirection::File(out_file))
-                        .stderr(Redirection::File(err_file))
-                }
-            };
+    Popen::create(
+        &words,
+        PopenConfig {
+            stdout,
+            stderr,
+            detached: true,
+            #[cfg(unix)]
+            setpgid: true,
+            ..Default::default()
+        },
+    )
+    .context("Failed to spawn detached process")?
+    .pid()
+    .context("Process should have a PID")
+}
 
-            exec.detached()
-                .popen()
-                .context("Failed to spawn detached process")?
-                .pid()
-                .context("Process should have a PID")
+/// Where the stdout and stderr of a process's commands are written. Processes redirected to a
+/// file log to their own files under `.cardamon/logs/<run_id>/`.
+///
+/// # Arguments
+///
+/// * proc - The process running the command
+/// * run_id - The run the process was started for
+/// * append - Add to the end of the process's logs rather than replacing them
+fn outputs(
+    proc: &config::ProcessToExecute,
+    run_id: &str,
+    append: bool,
+) -> anyhow::Result<(Redirection, Redirection)> {
+    match proc.redirect.unwrap_or(Redirect::File) {
+        Redirect::Null => Ok((null_file()?, null_file()?)),
+        Redirect::Parent => Ok((Redirection::None, Redirection::None)),
+        Redirect::File => {
+            let (out_file, err_file) =
+                logs::open(Path::new(logs::LOG_DIR), run_id, &proc.name, append)?;
+            Ok((Redirection::File(out_file), Redirection::File(err_file)))
         }
-        _ => Err(anyhow!("")),
     }
 }
 
+fn null_file() -> anyhow::Result<Redirection> {
+    let path = if cfg!(windows) { "nul" } else { "/dev/null" };
+    let file = std::fs::OpenOptions::new()
+        .write(true)
+        .open(path)
+        .context("Unable to open the null device")?;
+    Ok(Redirection::File(file))
+}
+
 /// Run the given process as a detached process and return a list of all things to observe (in
 /// Docker it's possible to have a single docker compose process which starts multiple containers).
 ///
 /// # Arguments
 ///
 /// * proc - The Process to run
+/// * run_id - The run the process is started for
 ///
 /// # Returns
 ///
 /// A list of all the processes to observe
-fn run_process(proc: &config::ProcessToExecute) -> anyhow::Result<Vec<ProcessToObserve>> {
+fn run_process(
+    proc: &config::ProcessToExecute,
+    run_id: &str,
+) -> anyhow::Result<Vec<ProcessToObserve>> {
     match &proc.process {
         config::ProcessType::Docker { containers } => {
             // run the command
-            run_command_detached(&proc.up, &proc.redirect)?;
+            run_command_detached(&proc.up, outputs(proc, run_id, false)?)?;
 
             // return the containers as vector of ProcessToObserve
             Ok(containers
@@ -83,7 +161,7 @@ fn run_process(proc: &config::ProcessToExecute) -> anyhow::Result<Vec<ProcessToO
 
         config::ProcessType::BareMetal => {
             // run the command
-            let pid = run_command_detached(&proc.up, &proc.redirect)?;
+            let pid = run_command_detached(&pinned_up(proc)?, outputs(proc, run_id, false)?)?;
 
             // return the pid as a ProcessToObserve
             Ok(vec![ProcessToObserve::Pid(Some(proc.name.clone()), pid)])
@@ -91,26 +169,130 @@ fn run_process(proc: &config::ProcessToExecute) -> anyhow::Result<Vec<ProcessToO
     }
 }
 
-async fn run_scenario<'a>(
-    run_id: &str,
-    scenario_to_execute: &ScenarioToExecute<'a>,
-) -> anyhow::Result<ScenarioIteration> {
-    let start = time::SystemTime::now()
-        .duration_since(time::UNIX_EPOCH)?
-        .as_millis();
+/// The command which starts a bare metal process, pinned to its cores if it has any.
+fn pinned_up(proc: &config::ProcessToExecute) -> anyhow::Result<String> {
+    match &proc.cpus {
+        Some(cpus) => Ok(format!("{} {}", taskset(cpus)?.join(" "), proc.up)),
+        None => Ok(proc.up.clone()),
+    }
+}
+
+/// The command line prefix which pins a command to the given cores, e.g. "0-3" or "0,2". taskset
+/// execs the command, so the pid of the pinned command is the pid taskset was started with.
+fn taskset(cpus: &str) -> anyhow::Result<Vec<String>> {
+    check_cpus(cpus)?;
+    if !cfg!(target_os = "linux") {
+        return Err(anyhow!(
+            "Commands are pinned to cores with taskset, which is only available on Linux"
+        ));
+    }
+    Ok(vec![
+        String::from("taskset"),
+        String::from("-c"),
+        cpus.to_string(),
+    ])
+}
+
+/// Fails unless the cores are a list such as "0-3" or "0,2,4-5".
+fn check_cpus(cpus: &str) -> anyhow::Result<()> {
+    let is_core = |core: &str| !core.is_empty() && core.chars().all(|c| c.is_ascii_digit());
+    let valid = cpus.split(',').all(|range| match range.split_once('-') {
+        Some((first, last)) => is_core(first) && is_core(last),
+        None => is_core(range),
+    });
+    if !valid {
+        return Err(anyhow!(
+            "\"{cpus}\" isn't a list of cores, expected something like \"0-3\" or \"0,2\""
+        ));
+    }
+    Ok(())
+}
+
+/// Pins the containers a docker process started to the process's cores. Containers only exist
+/// once the `up` command has got round to creating them, so this waits until they're ready.
+async fn pin_containers(proc: &ProcessToExecute) -> anyhow::Result<()> {
+    let (Some(cpus), ProcessType::Docker { containers }) = (&proc.cpus, &proc.process) else {
+        return Ok(());
+    };
+    check_cpus(cpus)?;
+
+    let status = tokio::process::Command::new("docker")
+        .args(["update", "--cpuset-cpus", cpus])
+        .args(containers)
+        .stdout(std::process::Stdio::null())
+        .status()
+        .await
+        .context("Unable to run docker update")?;
+    if !status.success() {
+        return Err(anyhow!("Unable to pin containers {containers:?} to {cpus}"));
+    }
+    Ok(())
+}
+
+/// Measures the CPU usage of the whole machine before the run starts, since anything else
+/// running would be measured alongside the application. Strict runs fail if the machine is busy.
+///
+/// # Returns
+///
+/// The CPU usage as a percentage, None if the check is turned off.
+async fn check_load(exec_plan: &ExecutionPlan<'_>) -> anyhow::Result<Option<f64>> {
+    let load_check = exec_plan.load_check;
+    if load_check.duration == 0 {
+        return Ok(None);
+    }
+
+    let cpu_usage = baseline::measure(time::Duration::from_secs(load_check.duration))
+        .await?
+        .cpu_usage;
+    if cpu_usage > load_check.max_cpu_usage {
+        let message = format!(
+            "Background CPU usage is {cpu_usage:.1}%, above {:.1}%, and will be measured \
+             alongside the application",
+            load_check.max_cpu_usage
+        );
+        if exec_plan.strict {
+            return Err(anyhow!("{message}, stopping the strict run"));
+        }
+        tracing::warn!("{message}");
+    }
+    Ok(Some(cpu_usage))
+}
 
-    // Split the scenario_command into a vector
-    let command_parts: Vec<&str> = scenario_to_execute
-        .scenario
-        .command
-        .split_whitespace()
-        .collect();
+/// Starts one iteration of a scenario.
+fn start_scenario(
+    run_id: &str,
+    scenario_to_execute: &ScenarioToExecute<'_>,
+) -> anyhow::Result<Child> {
+    let scenario = scenario_to_execute.scenario;
+    let mut command_parts = shlex::split(&scenario.command)
+        .ok_or_else(|| anyhow::anyhow!("Command string is not POSIX compliant"))?;
+    let cpus = scenario.cpus.as_deref();
+    match (&scenario.container, cpus) {
+        (Some(ScenarioContainer::Exec { exec }), Some(_)) => {
+            return Err(anyhow!(
+                "Scenario {} runs in container {exec}, which it can't be pinned to cores in, \
+                 pin the container instead",
+                scenario.name
+            ));
+        }
+        (Some(container), _) => {
+            if let Some(cpus) = cpus {
+                check_cpus(cpus)?;
+            }
+            command_parts = container.command_line(
+                command_parts,
+                &scenario_to_execute.container_name(run_id),
+                cpus,
+            );
+        }
+        (None, Some(cpus)) => command_parts = [taskset(cpus)?, command_parts].concat(),
+        (None, None) => {}
+    }
 
     // Get the command and arguments
-    let command = command_parts
-        .first()
+    let (command, args) = command_parts
+        .split_first()
         .ok_or_else(|| anyhow::anyhow!("Empty command"))?;
-    let args = &command_parts[1..];
 
     // run scenario ...
     println!(
@@ -118,25 +300,91 @@ async fn run_scenario<'a>(
         scenario_to_execute.scenario.name,
         scenario_to_execute.iteration + 1
     );
-    let output = tokio::process::Command::new(command)
+    let child = tokio::process::Command::new(command)
         .args(args)
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
         .kill_on_drop(true)
-        .output()
-        .await?;
+        .spawn()?;
+    Ok(child)
+}
+
+/// Reads what a scenario command prints as it's printed, timestamping each line in milliseconds
+/// since the unix epoch so phase markers can be placed in the iteration.
+async fn read_lines(stdout: ChildStdout) -> anyhow::Result<Vec<(i64, String)>> {
+    let mut lines = tokio::io::BufReader::new(stdout).split(b'\n');
+    let mut read = vec![];
+    while let Some(line) = lines.next_segment().await? {
+        read.push((now_millis()?, String::from_utf8_lossy(&line).to_string()));
+    }
+    Ok(read)
+}
+
+/// Waits for an iteration of a scenario to finish.
+///
+/// # Arguments
+/// * start - when the iteration started, in milliseconds since the unix epoch
+///
+/// # Returns
+///
+/// The iteration, the result the scenario command printed if it printed one and the phases it
+/// marked.
+async fn finish_scenario(
+    run_id: &str,
+    scenario_to_execute: &ScenarioToExecute<'_>,
+    start: i64,
+    mut child: Child,
+) -> anyhow::Result<(
+    ScenarioIteration,
+    Option<IterationResult>,
+    Vec<IterationPhase>,
+)> {
+    let stdout = child
+        .stdout
+        .take()
+        .map(|stdout| tokio::spawn(read_lines(stdout)));
+    let output = child.wait_with_output().await?;
+    let lines = match stdout {
+        Some(reader) => reader.await??,
+        None => vec![],
+    };
+
+    // docker removes the transient container when it exits, this makes sure it's gone if the
+    // docker cli was killed first
+    if let Some(ScenarioContainer::Transient { .. }) = &scenario_to_execute.scenario.container {
+        let _ = tokio::process::Command::new("docker")
+            .args(["rm", "--force", &scenario_to_execute.container_name(run_id)])
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .status()
+            .await;
+    }
 
     if output.status.success() {
-        let stop = time::SystemTime::now()
-            .duration_since(time::UNIX_EPOCH)?
-            .as_millis();
+        let stop = now_millis()?;
 
         let scenario_iteration = ScenarioIteration::new(
             run_id,
             &scenario_to_execute.scenario.name,
             scenario_to_execute.iteration as i64,
-            start as i64,
-            stop as i64,
+            start,
+            stop,
+        )
+        .with_cold_start(scenario_to_execute.scenario.cold_start);
+        let iteration_result = IterationResult::parse(
+            run_id,
+            &scenario_to_execute.scenario.name,
+            scenario_to_execute.iteration as i64,
+            &lines.iter().map(|(_, line)| line.as_str()).join("\n"),
         );
-        Ok(scenario_iteration)
+        let phases = IterationPhase::from_markers(
+            run_id,
+            &scenario_to_execute.scenario.name,
+            scenario_to_execute.iteration as i64,
+            &lines,
+            stop,
+        );
+        Ok((scenario_iteration, iteration_result, phases))
     } else {
         let error_message = String::from_utf8_lossy(&output.stderr).to_string();
         Err(anyhow::anyhow!(
@@ -146,88 +394,587 @@ async fn run_scenario<'a>(
     }
 }
 
-fn shutdown_application(
-    exec_plan: &ExecutionPlan,
+/// Stops every process cardamon started. Each process's `down` command is run if it has one.
+/// Bare-metal processes are then sent their stop signal (SIGTERM if they have no `down` command)
+/// and given their grace period to exit before the process and any children it started are
+/// killed.
+async fn shutdown_application(
+    processes_to_execute: &[&ProcessToExecute],
+    run_id: &str,
     running_processes: &[ProcessToObserve],
 ) -> anyhow::Result<()> {
-    // for each process in the execution plan that has a "down" command, attempt to run that
-    // command.
-    for proc in exec_plan.processes_to_execute.iter() {
-        if let Some(down_command) = &proc.down {
-            match proc.process {
-                ProcessType::BareMetal => {
-                    // find the pid associated with this process
-                    let pid = running_processes.iter().find_map(|p| match p {
-                        ProcessToObserve::Pid(Some(name), pid) if name == &proc.name => Some(*pid),
-                        _ => None,
-                    });
-
-                    // if pid can't be found then log an error
-                    if let Some(pid) = pid {
-                        // replace {pid} with the actual PID in the down command
-                        let down_command = down_command.replace("{pid}", &pid.to_string());
-
-                        let res = run_command_detached(&down_command, &proc.redirect);
-                        if res.is_err() {
-                            let err = res.unwrap_err();
-                            tracing::warn!(
+    let mut stopping = vec![];
+    for proc in processes_to_execute.iter() {
+        match proc.process {
+            ProcessType::BareMetal => {
+                // find the pid associated with this process
+                let pid = running_processes.iter().find_map(|p| match p {
+                    ProcessToObserve::Pid(Some(name), pid) if name == &proc.name => Some(*pid),
+                    _ => None,
+                });
+
+                // if pid can't be found then log an error
+                let Some(pid) = pid else {
+                    tracing::warn!(
+                        "Unable to find PID for bare-metal process with name: {}",
+                        proc.name
+                    );
+                    continue;
+                };
+
+                if let Some(down_command) = &proc.down {
+                    // replace {pid} with the actual PID in the down command
+                    let down_command = down_command.replace("{pid}", &pid.to_string());
+
+                    let res = outputs(proc, run_id, true)
+                        .and_then(|outputs| run_command_detached(&down_command, outputs));
+                    if let Err(err) = res {
+                        tracing::warn!(
+                            "Failed to shutdown process with name {}\n{}",
+                            proc.name,
+                            err
+                        );
+                    }
+                }
+
+                let signal = match (&proc.down, proc.stop_signal) {
+                    (None, None) => Some(StopSignal::Sigterm),
+                    (_, signal) => signal,
+                };
+                stopping.push(
+                    async move {
+                        match process_group::stop(pid, signal, proc.grace_period()).await {
+                            Ok(true) => tracing::warn!(
+                                "Process {} didn't exit within {:?} and was killed",
+                                proc.name,
+                                proc.grace_period()
+                            ),
+                            Ok(false) => {}
+                            Err(err) => tracing::warn!(
                                 "Failed to shutdown process with name {}\n{}",
                                 proc.name,
                                 err
-                            );
+                            ),
                         }
-                    } else {
-                        tracing::warn!(
-                            "Unable to find PID for bare-metal process with name: {}",
-                            proc.name
-                        );
                     }
-                }
-                ProcessType::Docker { containers: _ } => {
-                    let res = run_command_detached(down_command, &proc.redirect);
-                    if res.is_err() {
-                        let err = res.unwrap_err();
-                        tracing::warn!(
+                    .boxed(),
+                );
+            }
+            ProcessType::Docker { containers: _ } => {
+                if let Some(down_command) = &proc.down {
+                    let res = outputs(proc, run_id, true)
+                        .and_then(|outputs| run_command_detached(down_command, outputs));
+                    match res {
+                        // the containers have stopped once the down command exits
+                        Ok(pid) => stopping.push(
+                            async move {
+                                if !process_group::wait_for_exit(pid, proc.grace_period()).await {
+                                    tracing::warn!(
+                                        "Down command of process {} is still running after {:?}",
+                                        proc.name,
+                                        proc.grace_period()
+                                    );
+                                }
+                            }
+                            .boxed(),
+                        ),
+                        Err(err) => tracing::warn!(
                             "Failed to shutdown process with name {}\n{}",
                             proc.name,
                             err
-                        );
+                        ),
                     }
                 }
             }
         }
     }
 
+    // processes are given their grace period at the same time
+    futures_util::future::join_all(stopping).await;
+
+    Ok(())
+}
+
+/// Retries the process's `ready` command until it succeeds, failing once the process's ready
+/// timeout has passed. Processes without a `ready` command are ready straight away.
+async fn wait_until_ready(proc: &ProcessToExecute) -> anyhow::Result<()> {
+    let Some(ready_command) = &proc.ready else {
+        return Ok(());
+    };
+    let words = shlex::split(ready_command)
+        .filter(|words| !words.is_empty())
+        .ok_or_else(|| {
+            anyhow!(
+                "Ready command of process {} is not POSIX compliant",
+                proc.name
+            )
+        })?;
+
+    let deadline = tokio::time::Instant::now() + proc.ready_timeout();
+    loop {
+        let status = tokio::time::timeout_at(
+            deadline,
+            tokio::process::Command::new(&words[0])
+                .args(&words[1..])
+                .stdout(std::process::Stdio::null())
+                .stderr(std::process::Stdio::null())
+                .kill_on_drop(true)
+                .status(),
+        )
+        .await;
+        match status {
+            Ok(Ok(status)) if status.success() => return Ok(()),
+            Err(_) => break,
+            _ => {}
+        }
+        if tokio::time::Instant::now() + READY_INTERVAL >= deadline {
+            break;
+        }
+        tokio::time::sleep(READY_INTERVAL).await;
+    }
+
+    Err(anyhow!(
+        "Process {} wasn't ready within {:?}",
+        proc.name,
+        proc.ready_timeout()
+    ))
+}
+
+/// Writes the metrics, power metrics and events sampled during a run to the db.
+async fn persist_metrics_log(
+    data_access_service: &dyn DataAccessService,
+    run_id: &str,
+    metrics_log: &metrics::MetricsLog,
+) -> anyhow::Result<()> {
+    for metrics in metrics_log.get_metrics() {
+        data_access_service
+            .cpu_metrics_dao()
+            .persist(&metrics.into_data_access(run_id))
+            .await?;
+    }
+
+    for power_metrics in metrics_log.get_power_metrics() {
+        data_access_service
+            .power_metrics_dao()
+            .persist(&power_metrics.into_data_access(run_id))
+            .await?;
+    }
+
+    for event in metrics_log.get_events() {
+        data_access_service
+            .process_event_dao()
+            .persist(&event.into_data_access(run_id))
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Records when the run stopped, once its application has been shut down, and removes its lock.
+async fn finish_run(
+    data_access_service: &dyn DataAccessService,
+    run_id: &str,
+    lock_path: &Path,
+) -> anyhow::Result<()> {
+    orphans::RunLock::remove(lock_path)?;
+    data_access_service
+        .run_dao()
+        .set_stop_time(run_id, Some(now_millis()?))
+        .await?;
+    Ok(())
+}
+
+/// Writes a phase of the run outside its scenarios along with what was sampled during it. Errors
+/// sampling the phase are only logged as they don't affect the scenarios' results.
+async fn persist_phase(
+    data_access_service: &dyn DataAccessService,
+    run_phase: &RunPhase,
+    metrics_log: &metrics::MetricsLog,
+) -> anyhow::Result<()> {
+    for err in metrics_log.get_errors() {
+        tracing::warn!("Error measuring the {} of the run: {err}", run_phase.phase);
+    }
+
+    data_access_service
+        .run_phase_dao()
+        .persist(run_phase)
+        .await?;
+    persist_metrics_log(data_access_service, &run_phase.run_id, metrics_log).await
+}
+
+fn now_millis() -> anyhow::Result<i64> {
+    Ok(time::SystemTime::now()
+        .duration_since(time::UNIX_EPOCH)?
+        .as_millis() as i64)
+}
+
+/// Restarts the observed processes so the next iteration measures them starting cold.
+/// Bare-metal processes cardamon runs are stopped and run again, and every observed container is
+/// restarted. Other processes can't be restarted so they're left running.
+async fn restart_application(
+    exec_plan: &ExecutionPlan<'_>,
+    run_id: &str,
+    running_processes: &mut Vec<ProcessToObserve>,
+    lock: &mut orphans::RunLock,
+) -> anyhow::Result<()> {
+    let bare_metal = exec_plan
+        .processes_to_execute
+        .iter()
+        .copied()
+        .filter(|proc| proc.process == ProcessType::BareMetal)
+        .collect::<Vec<_>>();
+    shutdown_application(&bare_metal, run_id, running_processes).await?;
+    for proc in bare_metal {
+        running_processes.retain(|running| match running {
+            ProcessToObserve::Pid(Some(name), pid) if name == &proc.name => {
+                lock.untrack_pid(*pid);
+                false
+            }
+            _ => true,
+        });
+
+        let pid = run_command_detached(&pinned_up(proc)?, outputs(proc, run_id, true)?)?;
+        let restarted = ProcessToObserve::Pid(Some(proc.name.clone()), pid);
+        lock.track(std::slice::from_ref(&restarted));
+        running_processes.push(restarted);
+    }
+
+    let containers = running_processes
+        .iter()
+        .filter_map(|process| match process {
+            ProcessToObserve::ContainerName(name) => Some(name.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    if !containers.is_empty() {
+        let status = tokio::process::Command::new("docker")
+            .arg("restart")
+            .args(&containers)
+            .stdout(std::process::Stdio::null())
+            .status()
+            .await
+            .context("Unable to run docker restart")?;
+        if !status.success() {
+            return Err(anyhow!("Unable to restart containers {containers:?}"));
+        }
+    }
+
+    for process in exec_plan.external_processes_to_observe.iter() {
+        if !matches!(process, ProcessToObserve::ContainerName(_)) {
+            tracing::warn!("{process:?} can't be restarted, it's measured warm");
+        }
+    }
+
+    futures_util::future::try_join_all(
+        exec_plan
+            .processes_to_execute
+            .iter()
+            .map(|proc| wait_until_ready(proc)),
+    )
+    .await?;
+
     Ok(())
 }
 
+/// The power meters and exporters the plan's loggers read from. Samples are mirrored to InfluxDB
+/// if the plan has a bucket for them, tagged with the run and what was being measured, e.g.
+/// `("scenario", "basket_10")`.
+fn logging_options<'a>(
+    exec_plan: &ExecutionPlan<'a>,
+    run_id: &str,
+    (key, value): (&str, &str),
+) -> anyhow::Result<LoggingOptions<'a>> {
+    let influx_sink = match exec_plan.influxdb {
+        Some(influxdb) => Some(
+            metrics_logger::influxdb::InfluxSink::new(influxdb)?
+                .with_tag("run_id", run_id)
+                .with_tag(key, value),
+        ),
+        None => None,
+    };
+    Ok(LoggingOptions {
+        power_meter: exec_plan.power_meter,
+        scaphandre: exec_plan.scaphandre,
+        cadvisor: exec_plan.cadvisor,
+        influx_sink,
+    })
+}
+
+/// How often a process's `ready` command is retried.
+const READY_INTERVAL: time::Duration = time::Duration::from_millis(500);
+
 pub async fn run<'a>(
     exec_plan: ExecutionPlan<'a>,
     data_access_service: &dyn DataAccessService,
 ) -> anyhow::Result<ObservationDataset> {
-    // create a unique cardamon run id
-    let run_id = nanoid::nanoid!(5);
+    // create a unique cardamon run id unless one was handed out already
+    let run_id = exec_plan
+        .run_id
+        .clone()
+        .unwrap_or_else(|| nanoid::nanoid!(5));
+
+    // runs measuring the machine at the same time would skew each other's measurements
+    let _machine_lock = orphans::MachineLock::acquire(&run_id, exec_plan.queue).await?;
+    let active_run = active_runs::register(&run_id);
+    let ambient_load = check_load(&exec_plan).await?;
+
+    // a resumed run was recorded, along with the machine it runs on, when it first started
+    if !exec_plan.resuming {
+        data_access_service
+            .run_dao()
+            .persist(
+                &Run::new(&run_id, exec_plan.baseline_id.as_deref())
+                    .with_note(exec_plan.note.as_deref())
+                    .with_schedule(exec_plan.schedule.as_deref())
+                    .with_iterations(exec_plan.iterations.map(i64::from))
+                    .with_observation(Some(&exec_plan.name))
+                    .with_scenarios(&exec_plan.scenario_names())
+                    .with_ambient_load(ambient_load)
+                    .in_project(data_access_service.project()),
+            )
+            .await?;
+
+        // record the machine the run executes on and which cores the application is pinned to
+        data_access_service
+            .run_context_dao()
+            .persist(
+                &system_context::capture(&run_id)
+                    .await
+                    .with_pinning(exec_plan.pinning()),
+            )
+            .await?;
+    } else {
+        // it's in progress again until it stops
+        data_access_service
+            .run_dao()
+            .set_stop_time(&run_id, None)
+            .await?;
+    }
 
     let mut processes_to_observe = exec_plan.external_processes_to_observe.to_vec(); // external procs to observe are cloned here.
 
-    // run the application if there is anything to run
+    // run the application if there is anything to run, recording what's started so it can be
+    // cleaned up if cardamon crashes. The lock also lets `card cancel` find the process running
+    // the run
+    let lock_path = Path::new(orphans::LOCK_FILE);
+    let mut lock = orphans::RunLock::new(&run_id);
+    lock.write(lock_path)?;
     if !exec_plan.processes_to_execute.is_empty() {
+        match logs::rotate(Path::new(logs::LOG_DIR), logs::RUNS_KEPT) {
+            Ok(removed) if removed > 0 => tracing::debug!("Removed logs of {removed} old runs"),
+            Ok(_) => {}
+            Err(err) => tracing::warn!("Unable to remove old logs: {err}"),
+        }
+
+        let startup_start = now_millis()?;
         for proc in exec_plan.processes_to_execute.iter() {
-            let process_to_observe = run_process(proc)?;
+            let process_to_observe = run_process(proc, &run_id)?;
+            lock.track(&process_to_observe);
+            lock.write(lock_path)?;
             processes_to_observe.extend(process_to_observe);
         }
+
+        // measure the application starting until every process is ready
+        let stop_handle = metrics_logger::start_logging(
+            &processes_to_observe,
+            logging_options(&exec_plan, &run_id, ("phase", PHASE_STARTUP))?,
+        )?;
+        let ready = futures_util::future::try_join_all(exec_plan.processes_to_execute.iter().map(
+            |proc| async move {
+                wait_until_ready(proc).await?;
+                pin_containers(proc).await
+            },
+        ))
+        .await;
+        // errors measuring the startup are logged with the phase, the application is shut down
+        // regardless
+        let metrics_log = stop_handle.finish().await;
+        if let Err(err) = ready {
+            shutdown_application(
+                &exec_plan.processes_to_execute,
+                &run_id,
+                &processes_to_observe,
+            )
+            .await?;
+            finish_run(data_access_service, &run_id, lock_path).await?;
+            return Err(err);
+        }
+        let startup = RunPhase::new(&run_id, PHASE_STARTUP, startup_start, now_millis()?);
+        persist_phase(data_access_service, &startup, &metrics_log).await?;
+    }
+
+    // record how the observed containers were deployed
+    let container_names = processes_to_observe
+        .iter()
+        .filter_map(|process| match process {
+            ProcessToObserve::ContainerName(name) => Some(name.clone()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    for process_info in system_context::containers(&run_id, &container_names).await {
+        data_access_service
+            .process_info_dao()
+            .persist(&process_info)
+            .await?;
     }
 
+    // requests the scenarios make through the proxy are counted per endpoint
+    let proxy = match exec_plan.proxy {
+        Some(proxy) => Some(endpoint_proxy::start(proxy).await?),
+        None => None,
+    };
+
     // ---- for each scenario ----
+    let mut cancelled = false;
     for scenario_to_execute in exec_plan.scenarios_to_execute.iter() {
-        // start the metrics loggers
-        let stop_handle = metrics_logger::start_logging(&processes_to_observe)?;
+        if active_run.is_cancelled() {
+            cancelled = true;
+            break;
+        }
+
+        if scenario_to_execute.scenario.cold_start {
+            restart_application(&exec_plan, &run_id, &mut processes_to_observe, &mut lock).await?;
+            if !exec_plan.processes_to_execute.is_empty() {
+                lock.write(lock_path)?;
+            }
+        }
+
+        // cardamon is sampled like any other process but is never stopped with the application
+        let mut processes_to_log = processes_to_observe.clone();
+        if exec_plan.measure_overhead {
+            processes_to_log.push(ProcessToObserve::Pid(
+                Some(SELF_PROCESS_NAME.to_string()),
+                std::process::id(),
+            ));
+        }
 
         // run the scenario
-        let scenario_iteration = run_scenario(&run_id, scenario_to_execute).await?;
+        let throttle_count = system_context::throttle_count();
+        let cpu_governor = system_context::cpu_governor();
+        let start = time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)?
+            .as_millis() as i64;
+        if let Some(proxy) = &proxy {
+            // requests made between iterations don't belong to this one
+            proxy.take_counts();
+        }
+
+        // start the metrics loggers before the scenario so all of it is measured
+        let mut stop_handle = metrics_logger::start_logging(
+            &processes_to_log,
+            logging_options(
+                &exec_plan,
+                &run_id,
+                ("scenario", &scenario_to_execute.scenario.name),
+            )?,
+        )?;
+        let child = start_scenario(&run_id, scenario_to_execute)?;
+        active_run.start_iteration(
+            &scenario_to_execute.scenario.name,
+            i64::from(scenario_to_execute.iteration) + 1,
+        );
+
+        // also log the scenario's command if it does the work itself, which is the container it
+        // runs in if it isn't run on the host
+        if scenario_to_execute.scenario.observe_command {
+            let command = match (&scenario_to_execute.scenario.container, child.id()) {
+                (Some(container), _) => Some(ProcessToObserve::ContainerName(
+                    container.observed_container(&scenario_to_execute.container_name(&run_id)),
+                )),
+                (None, Some(pid)) => Some(ProcessToObserve::Pid(
+                    Some(scenario_to_execute.scenario.name.clone()),
+                    pid,
+                )),
+                (None, None) => None,
+            };
+            if let Some(command) = command {
+                stop_handle.observe(&[command])?;
+            }
+        }
+
+        // keep the progress line and the active run's power up to date until the scenario ends
+        let finished = finish_scenario(&run_id, scenario_to_execute, start, child);
+        tokio::pin!(finished);
+        let progress_line = exec_plan.show_progress.then(progress::ProgressLine::start);
+        let mut refresh = tokio::time::interval(progress::REFRESH_INTERVAL);
+        let finished = loop {
+            tokio::select! {
+                finished = &mut finished => break Some(finished),
+                _ = active_run.cancelled() => break None,
+                _ = refresh.tick() => {
+                    let progress = stop_handle.progress();
+                    active_run.update_power(progress::power(&progress, exec_plan.cpu));
+                    if let Some(progress_line) = &progress_line {
+                        progress_line.update(&progress, exec_plan.cpu);
+                    }
+                }
+            }
+        };
+        if let Some(progress_line) = progress_line {
+            progress_line.finish();
+        }
+
+        // keep what was measured of the iteration, the scenario command is killed once it's
+        // dropped
+        let Some(finished) = finished else {
+            // the cancelled application is still shut down if the loggers had errors
+            let metrics_log = stop_handle.finish().await;
+            for err in metrics_log.get_errors() {
+                tracing::warn!("Error measuring the cancelled iteration: {err}");
+            }
+            let scenario_iteration = ScenarioIteration::new(
+                &run_id,
+                &scenario_to_execute.scenario.name,
+                i64::from(scenario_to_execute.iteration),
+                start,
+                now_millis()?,
+            )
+            .with_cold_start(scenario_to_execute.scenario.cold_start)
+            .with_status(STATUS_CANCELLED);
+            data_access_service
+                .scenario_iteration_dao()
+                .persist(&scenario_iteration)
+                .await?;
+            persist_metrics_log(data_access_service, &run_id, &metrics_log).await?;
+
+            tracing::info!(
+                "Run {run_id} cancelled during scenario {} iteration {}",
+                scenario_to_execute.scenario.name,
+                scenario_to_execute.iteration + 1
+            );
+            cancelled = true;
+            break;
+        };
+        let (mut scenario_iteration, iteration_result, phases) = finished?;
 
         // stop the metrics loggers
-        let metrics_log = stop_handle.stop().await?;
+        let mut metrics_log = stop_handle.stop().await?;
+
+        // throttled iterations shouldn't be compared with unthrottled ones
+        if system_context::throttle_count() > throttle_count {
+            tracing::warn!(
+                "CPU was thermally throttled during scenario {} iteration {}",
+                scenario_to_execute.scenario.name,
+                scenario_to_execute.iteration + 1
+            );
+            metrics_log.push_event(metrics::ProcessEvent {
+                process_name: "cpu".to_string(),
+                kind: metrics::ProcessEventKind::ThermalThrottled,
+                timestamp: scenario_iteration.stop_time,
+            });
+        }
+        if system_context::cpu_governor() != cpu_governor {
+            tracing::warn!(
+                "CPU governor changed during scenario {} iteration {}",
+                scenario_to_execute.scenario.name,
+                scenario_to_execute.iteration + 1
+            );
+            metrics_log.push_event(metrics::ProcessEvent {
+                process_name: "cpu".to_string(),
+                kind: metrics::ProcessEventKind::GovernorChanged,
+                timestamp: scenario_iteration.stop_time,
+            });
+        }
 
         // if metrics log contains errors then display them to the user and don't save anything
         if metrics_log.has_errors() {
@@ -238,23 +985,95 @@ pub async fn run<'a>(
             return Err(anyhow!("Metric log contained errors, please see logs."));
         }
 
+        // the metrics of processes which exited part way through don't cover the whole scenario
+        let exited = metrics_log.exited_processes();
+        if !exited.is_empty() {
+            tracing::warn!(
+                "Processes {:?} exited during scenario {} iteration {}",
+                exited,
+                scenario_to_execute.scenario.name,
+                scenario_to_execute.iteration + 1
+            );
+            scenario_iteration = scenario_iteration.with_status(STATUS_PROCESS_EXITED);
+        }
+
         // write scenario and metrics to db
         data_access_service
             .scenario_iteration_dao()
             .persist(&scenario_iteration)
             .await?;
 
-        for metrics in metrics_log.get_metrics() {
+        if let Some(iteration_result) = iteration_result {
             data_access_service
-                .cpu_metrics_dao()
-                .persist(&metrics.into_data_access(&run_id))
+                .iteration_result_dao()
+                .persist(&iteration_result)
+                .await?;
+        }
+
+        for phase in phases.iter() {
+            data_access_service
+                .iteration_phase_dao()
+                .persist(phase)
                 .await?;
         }
+
+        if let Some(proxy) = &proxy {
+            for (endpoint, requests) in proxy.take_counts() {
+                data_access_service
+                    .endpoint_request_dao()
+                    .persist(&EndpointRequest::new(
+                        &run_id,
+                        &scenario_iteration.scenario_name,
+                        scenario_iteration.iteration,
+                        &endpoint,
+                        requests,
+                    ))
+                    .await?;
+            }
+        }
+
+        persist_metrics_log(data_access_service, &run_id, &metrics_log).await?;
+
+        // the iteration is kept so the run can be inspected but nothing else is measured
+        if exec_plan.strict && !exited.is_empty() {
+            shutdown_application(
+                &exec_plan.processes_to_execute,
+                &run_id,
+                &processes_to_observe,
+            )
+            .await?;
+            finish_run(data_access_service, &run_id, lock_path).await?;
+            return Err(anyhow!(
+                "Processes {:?} exited during scenario {}, stopping run {run_id}",
+                exited,
+                scenario_to_execute.scenario.name
+            ));
+        }
     }
     // ---- end for ----
+    drop(proxy);
 
-    // stop the application
-    shutdown_application(&exec_plan, &processes_to_observe)?;
+    // stop the application, measuring it until every process has exited
+    if !exec_plan.processes_to_execute.is_empty() {
+        let shutdown_start = now_millis()?;
+        let stop_handle = metrics_logger::start_logging(
+            &processes_to_observe,
+            logging_options(&exec_plan, &run_id, ("phase", PHASE_SHUTDOWN))?,
+        )?;
+        shutdown_application(
+            &exec_plan.processes_to_execute,
+            &run_id,
+            &processes_to_observe,
+        )
+        .await?;
+        let metrics_log = stop_handle.finish().await;
+        let shutdown = RunPhase::new(&run_id, PHASE_SHUTDOWN, shutdown_start, now_millis()?);
+        persist_phase(data_access_service, &shutdown, &metrics_log).await?;
+    }
+    finish_run(data_access_service, &run_id, lock_path).await?;
+    if cancelled {
+        return Err(active_runs::Cancelled(run_id).into());
+    }
 
     // create a summary to return to the user
     let scenario_names = exec_plan.scenario_names();
@@ -263,18 +1082,299 @@ pub async fn run<'a>(
         .fetch_observation_dataset(scenario_names, previous_runs)
         .await?;
 
+    // record the lifecycle impact of the server over the run
+    if let Some(boavizta) = exec_plan.boavizta {
+        let iterations = observation_dataset
+            .data()
+            .iter()
+            .filter(|it| it.scenario_iteration().run_id == run_id)
+            .collect::<Vec<_>>();
+        match boavizta::fetch_impact(boavizta, exec_plan.cloud, &run_id, &iterations).await {
+            Ok(run_impact) => {
+                data_access_service
+                    .run_impact_dao()
+                    .persist(&run_impact)
+                    .await?
+            }
+            Err(err) => tracing::warn!("Unable to fetch server impact from Boavizta: {:?}", err),
+        }
+    }
+
+    // record how the electricity used by the run was generated
+    if let Some(carbon_intensity) = exec_plan.carbon_intensity {
+        let iterations = observation_dataset
+            .data()
+            .iter()
+            .map(|it| it.scenario_iteration())
+            .filter(|it| it.run_id == run_id);
+        let start = iterations.clone().map(|it| it.start_time).min();
+        let stop = iterations.map(|it| it.stop_time).max();
+        let window = start.zip(stop).and_then(|(start, stop)| {
+            Some((
+                chrono::DateTime::from_timestamp_millis(start)?,
+                chrono::DateTime::from_timestamp_millis(stop)?,
+            ))
+        });
+
+        if let Some((from, to)) = window {
+            match carbon::fetch_generation_mix(carbon_intensity, from, to).await {
+                Ok(mix) => {
+                    for (fuel, percentage) in mix.iter() {
+                        data_access_service
+                            .energy_mix_dao()
+                            .persist(&EnergyMix::new(&run_id, fuel, *percentage))
+                            .await?;
+                    }
+                }
+                Err(err) => tracing::warn!("Unable to fetch the generation mix: {:?}", err),
+            }
+        }
+    }
+
+    // let anyone listening know the run has finished
+    if let Some(notifications) = exec_plan.notifications {
+        let summary = notifications::RunSummary::new(
+            &run_id,
+            &observation_dataset,
+            notifications.budget.as_ref(),
+            exec_plan.config,
+        );
+        notifications::notify(notifications, &summary).await;
+    }
+    if let Some(ci) = exec_plan.ci {
+        let budget = exec_plan
+            .notifications
+            .and_then(|notifications| notifications.budget.as_ref());
+        let summary =
+            notifications::RunSummary::new(&run_id, &observation_dataset, budget, exec_plan.config);
+        notifications::publish_to_ci(ci, &summary);
+    }
+
     Ok(observation_dataset)
 }
 
+/// When live monitoring should stop by itself, it runs until cancelled if neither is set.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct LiveStopCondition {
+    pub duration: Option<time::Duration>,
+    /// Stop once any observed process has been sampled this many times.
+    pub samples: Option<usize>,
+}
+impl LiveStopCondition {
+    fn is_met(&self, elapsed: time::Duration, samples: usize) -> bool {
+        self.duration.is_some_and(|duration| elapsed >= duration)
+            || self
+                .samples
+                .is_some_and(|max_samples| samples >= max_samples)
+    }
+}
+
+/// Parses a duration such as `90`, `30s`, `10m` or `2h`, plain numbers are seconds.
+pub fn parse_duration(duration: &str) -> anyhow::Result<time::Duration> {
+    let duration = duration.trim();
+    let (value, unit) = duration
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .map(|i| duration.split_at(i))
+        .unwrap_or((duration, "s"));
+    let value = value
+        .parse::<f64>()
+        .context(format!("Invalid duration {duration}"))?;
+    let secs = match unit {
+        "s" => value,
+        "m" => value * 60.0,
+        "h" => value * 3600.0,
+        _ => return Err(anyhow!("Invalid duration {duration}, expected s, m or h")),
+    };
+    Ok(time::Duration::from_secs_f64(secs))
+}
+
+/// Observes already running processes as a single iteration of the given scenario until the
+/// token is cancelled or the stop condition is met. Metrics are saved as they're collected so
+/// nothing is lost if monitoring ends abruptly.
+///
+/// # Arguments
+///
+/// * `scenario_name` - The name to record the monitoring period under
+/// * `processes_to_observe` - The running processes to observe
+/// * `stop_condition` - When to stop monitoring without being cancelled
+/// * `token` - Cancel this token to stop monitoring, e.g. on ctrl-c
+/// * `alerts` - Rules checked as metrics are collected, fired alerts are logged and recorded as
+///   events on the run
+/// * `notifications` - Webhooks fired alerts are sent to
+/// * `data_access_service` - Where the run is saved
+///
+/// # Returns
+///
+/// A dataset containing the live run.
+pub async fn run_live(
+    scenario_name: &str,
+    processes_to_observe: &[ProcessToObserve],
+    stop_condition: LiveStopCondition,
+    token: tokio_util::sync::CancellationToken,
+    mut alerts: Option<alerts::AlertMonitor>,
+    notifications: Option<&config::Notifications>,
+    data_access_service: &dyn DataAccessService,
+) -> anyhow::Result<ObservationDataset> {
+    let run_id = nanoid::nanoid!(5);
+    data_access_service
+        .run_dao()
+        .persist(&Run::new(&run_id, None).in_project(data_access_service.project()))
+        .await?;
+
+    let started = time::Instant::now();
+    let start_time = time::SystemTime::now()
+        .duration_since(time::UNIX_EPOCH)?
+        .as_millis() as i64;
+    let stop_handle =
+        metrics_logger::start_logging(processes_to_observe, LoggingOptions::default())?;
+
+    let mut samples_by_process = std::collections::HashMap::new();
+    while !stop_condition.is_met(
+        started.elapsed(),
+        samples_by_process.values().copied().max().unwrap_or(0),
+    ) {
+        tokio::select! {
+            _ = token.cancelled() => break,
+            _ = tokio::time::sleep(time::Duration::from_millis(1000)) => {}
+        }
+
+        let mut collected = vec![];
+        for metrics in stop_handle.drain_metrics() {
+            *samples_by_process
+                .entry(metrics.process_id.clone())
+                .or_insert(0_usize) += 1;
+            let metrics = metrics.into_data_access(&run_id);
+            data_access_service
+                .cpu_metrics_dao()
+                .persist(&metrics)
+                .await?;
+            collected.push(metrics);
+        }
+
+        let fired = alerts
+            .as_mut()
+            .map(|alerts| alerts.observe(&collected))
+            .unwrap_or_default();
+        for alert in fired.iter() {
+            tracing::warn!("{}", alert.to_text());
+            let process_name = alert.process.as_deref().unwrap_or(scenario_name);
+            data_access_service
+                .process_event_dao()
+                .persist(&ProcessEvent::new(
+                    &run_id,
+                    process_name,
+                    &format!("alert {}", alert.name),
+                    alert.timestamp,
+                ))
+                .await?;
+            if let Some(notifications) = notifications {
+                notifications::notify_alert(notifications, &run_id, alert).await;
+            }
+        }
+    }
+
+    let metrics_log = stop_handle.stop().await?;
+    for metrics in metrics_log.get_metrics() {
+        data_access_service
+            .cpu_metrics_dao()
+            .persist(&metrics.into_data_access(&run_id))
+            .await?;
+    }
+
+    // the run only ends once monitoring stops
+    let stop_time = time::SystemTime::now()
+        .duration_since(time::UNIX_EPOCH)?
+        .as_millis() as i64;
+    data_access_service
+        .scenario_iteration_dao()
+        .persist(&ScenarioIteration::new(
+            &run_id,
+            scenario_name,
+            1,
+            start_time,
+            stop_time,
+        ))
+        .await?;
+    data_access_service
+        .run_dao()
+        .set_stop_time(&run_id, Some(stop_time))
+        .await?;
+
+    Ok(data_access_service
+        .fetch_observation_dataset(vec![scenario_name], 1)
+        .await?)
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{
-        config::{ProcessToExecute, ProcessType},
-        metrics_logger, run_process, ProcessToObserve,
+        check_cpus,
+        config::{ProcessToExecute, ProcessType, Role},
+        metrics_logger, parse_duration, pinned_up, run_process, LiveStopCondition,
+        ProcessToObserve,
     };
+    use anyhow::Context;
     use std::time::Duration;
     use sysinfo::{Pid, System};
 
+    #[test]
+    fn cores_are_given_as_lists_and_ranges() {
+        assert!(check_cpus("0-3").is_ok());
+        assert!(check_cpus("0,2,4-5").is_ok());
+        assert!(check_cpus("").is_err());
+        assert!(check_cpus("0-").is_err());
+        assert!(check_cpus("0;rm -rf /").is_err());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn pinned_processes_are_started_under_taskset() -> anyhow::Result<()> {
+        let mut process = ProcessToExecute {
+            name: "sleep".to_string(),
+            up: "sleep 15".to_string(),
+            down: None,
+            redirect: None,
+            process: ProcessType::BareMetal,
+            hardware: None,
+            role: Role::Sut,
+            stop_signal: None,
+            stop_timeout: None,
+            ready: None,
+            ready_timeout: None,
+            cpus: Some("0-1".to_string()),
+        };
+        assert_eq!(pinned_up(&process)?, "taskset -c 0-1 sleep 15");
+
+        process.cpus = None;
+        assert_eq!(pinned_up(&process)?, "sleep 15");
+        Ok(())
+    }
+
+    #[test]
+    fn durations_can_be_parsed() -> anyhow::Result<()> {
+        assert_eq!(parse_duration("90")?, Duration::from_secs(90));
+        assert_eq!(parse_duration("30s")?, Duration::from_secs(30));
+        assert_eq!(parse_duration("10m")?, Duration::from_secs(600));
+        assert_eq!(parse_duration("1.5h")?, Duration::from_secs(5400));
+        assert!(parse_duration("10 minutes").is_err());
+        assert!(parse_duration("m").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn live_monitoring_stops_at_the_first_condition_met() {
+        let unbounded = LiveStopCondition::default();
+        assert!(!unbounded.is_met(Duration::from_secs(3600), 1000));
+
+        let bounded = LiveStopCondition {
+            duration: Some(Duration::from_secs(60)),
+            samples: Some(10),
+        };
+        assert!(!bounded.is_met(Duration::from_secs(30), 5));
+        assert!(bounded.is_met(Duration::from_secs(60), 5));
+        assert!(bounded.is_met(Duration::from_secs(30), 10));
+    }
+
     #[cfg(target_family = "windows")]
     mod windows {
         use super::*;
@@ -287,21 +1387,25 @@ mod tests {
                 down: None,
                 redirect: None,
                 process: ProcessType::BareMetal,
+                hardware: None,
+                role: Role::Sut,
+                stop_signal: None,
+                stop_timeout: None,
+                ready: None,
+                ready_timeout: None,
+                cpus: None,
             };
-            let processes_to_observe = run_process(&process)?;
+            let processes_to_observe = run_process(&process, "test")?;
 
             assert_eq!(processes_to_observe.len(), 1);
 
-            match processes_to_observe.first().expect("process should exist") {
-                ProcessToObserve::Pid(_, pid) => {
-                    let mut system = System::new();
-                    system.refresh_all();
-                    let proc = system.process(Pid::from_u32(*pid));
-                    assert!(proc.is_some());
-                }
-
-                _ => panic!("expected to find a process id"),
-            }
+            let pid = processes_to_observe
+                .first()
+                .and_then(ProcessToObserve::pid)
+                .context("expected to find a process id")?;
+            let mut system = System::new();
+            system.refresh_all();
+            assert!(system.process(Pid::from_u32(pid)).is_some());
 
             Ok(())
         }
@@ -314,9 +1418,19 @@ mod tests {
                 down: None,
                 redirect: None,
                 process: ProcessType::BareMetal,
+                hardware: None,
+                role: Role::Sut,
+                stop_signal: None,
+                stop_timeout: None,
+                ready: None,
+                ready_timeout: None,
+                cpus: None,
             };
-            let processes_to_observe = run_process(&process)?;
-            let stop_handle = metrics_logger::start_logging(&processes_to_observe)?;
+            let processes_to_observe = run_process(&process, "test")?;
+            let stop_handle = metrics_logger::start_logging(
+                &processes_to_observe,
+                metrics_logger::LoggingOptions::default(),
+            )?;
 
             tokio::time::sleep(Duration::from_secs(10)).await;
 
@@ -332,7 +1446,7 @@ mod tests {
     #[cfg(target_family = "unix")]
     mod unix {
         use super::*;
-        use crate::config::Redirect;
+        use crate::{config::Redirect, wait_until_ready};
 
         #[test]
         fn can_run_a_bare_metal_process() -> anyhow::Result<()> {
@@ -341,22 +1455,26 @@ mod tests {
                 up: "sleep 15".to_string(),
                 down: None,
                 redirect: Some(Redirect::Null),
-                process_type: ProcessType::BareMetal,
+                process: ProcessType::BareMetal,
+                hardware: None,
+                role: Role::Sut,
+                stop_signal: None,
+                stop_timeout: None,
+                ready: None,
+                ready_timeout: None,
+                cpus: None,
             };
-            let processes_to_observe = run_process(&process)?;
+            let processes_to_observe = run_process(&process, "test")?;
 
             assert_eq!(processes_to_observe.len(), 1);
 
-            match processes_to_observe.first().expect("process should exist") {
-                ProcessToObserve::Pid(None, pid) => {
-                    let mut system = System::new();
-                    system.refresh_all();
-                    let proc = system.process(Pid::from_u32(*pid));
-                    assert!(proc.is_some());
-                }
-
-                _ => panic!("expected to find a process id"),
-            }
+            let pid = processes_to_observe
+                .first()
+                .and_then(ProcessToObserve::pid)
+                .context("expected to find a process id")?;
+            let mut system = System::new();
+            system.refresh_all();
+            assert!(system.process(Pid::from_u32(pid)).is_some());
 
             Ok(())
         }
@@ -368,10 +1486,20 @@ mod tests {
                 up: "sleep 20".to_string(),
                 down: None,
                 redirect: Some(Redirect::Null),
-                process_type: ProcessType::BareMetal,
+                process: ProcessType::BareMetal,
+                hardware: None,
+                role: Role::Sut,
+                stop_signal: None,
+                stop_timeout: None,
+                ready: None,
+                ready_timeout: None,
+                cpus: None,
             };
-            let processes_to_observe = run_process(&process)?;
-            let stop_handle = metrics_logger::start_logging(&processes_to_observe)?;
+            let processes_to_observe = run_process(&process, "test")?;
+            let stop_handle = metrics_logger::start_logging(
+                &processes_to_observe,
+                metrics_logger::LoggingOptions::default(),
+            )?;
 
             tokio::time::sleep(Duration::from_secs(10)).await;
 
@@ -382,5 +1510,34 @@ mod tests {
 
             Ok(())
         }
+
+        #[tokio::test]
+        async fn processes_are_ready_once_their_ready_command_succeeds() -> anyhow::Result<()> {
+            let marker = std::env::temp_dir().join(nanoid::nanoid!(5));
+            let mut process = ProcessToExecute {
+                name: "server".to_string(),
+                up: "sleep 5".to_string(),
+                down: None,
+                redirect: Some(Redirect::Null),
+                process: ProcessType::BareMetal,
+                hardware: None,
+                role: Role::Sut,
+                stop_signal: None,
+                stop_timeout: None,
+                ready: Some(format!("test -f {}", marker.display())),
+                ready_timeout: Some(1),
+                cpus: None,
+            };
+            assert!(wait_until_ready(&process).await.is_err());
+
+            std::fs::write(&marker, "")?;
+            wait_until_ready(&process).await?;
+            std::fs::remove_file(&marker)?;
+
+            process.ready = None;
+            wait_until_ready(&process).await?;
+
+            Ok(())
+        }
     }
 }
@@ -0,0 +1,230 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Reads the work a scenario command did from what it printed, either a JSON result on the last
+//! line, e.g. `{"requests": 10000, "p95_ms": 12}`, or the end of test summary of a load testing
+//! tool (k6, Gatling or JMeter).
+
+/// Requests made by a scenario and how long they took.
+#[derive(Debug, Default, PartialEq, serde::Deserialize)]
+pub struct Summary {
+    pub requests: Option<i64>,
+    pub p50_ms: Option<f64>,
+    pub p95_ms: Option<f64>,
+    pub p99_ms: Option<f64>,
+}
+impl Summary {
+    fn is_empty(&self) -> bool {
+        self.requests.is_none()
+            && self.p50_ms.is_none()
+            && self.p95_ms.is_none()
+            && self.p99_ms.is_none()
+    }
+}
+
+/// Finds the summary in a scenario command's output, trying a JSON result first then each
+/// load testing tool's summary.
+///
+/// # Returns
+///
+/// The summary, None if the output doesn't contain one.
+pub fn parse(output: &str) -> Option<Summary> {
+    [json, k6, gatling, jmeter]
+        .iter()
+        .filter_map(|parse| parse(output))
+        .find(|summary| !summary.is_empty())
+}
+
+/// A JSON object on the last line of output.
+fn json(output: &str) -> Option<Summary> {
+    let last_line = output.lines().rev().find(|line| !line.trim().is_empty())?;
+    serde_json::from_str(last_line.trim()).ok()
+}
+
+/// The end of test summary k6 prints, e.g.
+/// ```text
+/// http_req_duration..............: avg=11.2ms min=1.01ms med=10.3ms max=201ms p(90)=14.1ms p(95)=16.7ms
+/// http_reqs......................: 10000  333.33/s
+/// ```
+fn k6(output: &str) -> Option<Summary> {
+    let metric = |name: &str| {
+        output.lines().find_map(|line| {
+            let (metric, values) = line.trim().split_once(':')?;
+            (metric.trim_end_matches('.') == name).then_some(values)
+        })
+    };
+
+    let durations = metric("http_req_duration").unwrap_or_default();
+    let percentile = |stat: &str| {
+        durations.split_whitespace().find_map(|value| {
+            let (key, value) = value.split_once('=')?;
+            (key == stat).then(|| duration_ms(value)).flatten()
+        })
+    };
+
+    Some(Summary {
+        requests: metric("http_reqs")
+            .and_then(|values| values.split_whitespace().next())
+            .and_then(|requests| requests.parse().ok()),
+        p50_ms: percentile("med").or_else(|| percentile("p(50)")),
+        p95_ms: percentile("p(95)"),
+        p99_ms: percentile("p(99)"),
+    })
+}
+
+/// Durations k6 prints in Go's format, e.g. `1m2.5s`, `12.3ms` or `850µs`.
+fn duration_ms(duration: &str) -> Option<f64> {
+    let mut total = 0.0;
+    let mut rest = duration;
+    while !rest.is_empty() {
+        let number_len = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(rest.len());
+        let value = rest[..number_len].parse::<f64>().ok()?;
+        rest = &rest[number_len..];
+
+        let unit_len = rest
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .unwrap_or(rest.len());
+        let scale = match &rest[..unit_len] {
+            "h" => 3_600_000.0,
+            "m" => 60_000.0,
+            "s" => 1_000.0,
+            "ms" => 1.0,
+            "µs" | "us" => 0.001,
+            "ns" => 0.000_001,
+            _ => return None,
+        };
+        rest = &rest[unit_len..];
+        total += value * scale;
+    }
+    Some(total)
+}
+
+/// The global statistics Gatling prints at the end of a simulation, e.g.
+/// ```text
+/// > request count                                      10000 (OK=10000  KO=0     )
+/// > response time 95th percentile                         20 (OK=20     KO=-     )
+/// ```
+fn gatling(output: &str) -> Option<Summary> {
+    let statistic = |name: &str| {
+        output.lines().find_map(|line| {
+            let line = line.trim().strip_prefix('>')?;
+            let (line, _) = line.split_once('(').unwrap_or((line, ""));
+            let (label, value) = line.trim().rsplit_once(char::is_whitespace)?;
+            (label.trim() == name).then_some(value)
+        })
+    };
+    let percentile = |name: &str| statistic(name).and_then(|value| value.parse::<f64>().ok());
+
+    Some(Summary {
+        requests: statistic("request count").and_then(|value| value.parse().ok()),
+        p50_ms: percentile("response time 50th percentile"),
+        p95_ms: percentile("response time 95th percentile"),
+        p99_ms: percentile("response time 99th percentile"),
+    })
+}
+
+/// The last cumulative summary JMeter prints when run without its GUI, which has no
+/// percentiles, e.g.
+/// ```text
+/// summary =  10000 in 00:00:30 =  333.3/s Avg:    12 Min:     1 Max:   200 Err:     0 (0.00%)
+/// ```
+fn jmeter(output: &str) -> Option<Summary> {
+    let requests = output.lines().rev().find_map(|line| {
+        let totals = line.trim().strip_prefix("summary =")?;
+        totals.split_whitespace().next()?.parse().ok()
+    });
+
+    Some(Summary {
+        requests,
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_results_are_read_from_the_last_line() {
+        let output = "warming up\n{\"requests\": 10000, \"p95_ms\": 12, \"errors\": 0}\n\n";
+        assert_eq!(
+            parse(output),
+            Some(Summary {
+                requests: Some(10000),
+                p95_ms: Some(12.0),
+                ..Default::default()
+            })
+        );
+
+        assert_eq!(parse("{\"requests\": 1}\ndone"), None);
+        assert_eq!(parse("{\"errors\": 0}"), None);
+        assert_eq!(parse(""), None);
+    }
+
+    #[test]
+    fn k6_summaries_are_read() {
+        let output = r#"
+     checks.........................: 100.00% ✓ 10000      ✗ 0
+     http_req_blocked...............: avg=5.2µs   min=1µs     med=3µs     max=1.2ms  p(90)=6µs    p(95)=8µs
+     http_req_duration..............: avg=11.2ms  min=1.01ms  med=10.3ms  max=1m2s   p(90)=14.1ms p(95)=16.7ms p(99)=850µs
+       { expected_response:true }...: avg=11.2ms  min=1.01ms  med=10.3ms  max=201ms  p(90)=14.1ms p(95)=16.7ms
+     http_reqs......................: 10000   333.33/s
+"#;
+        assert_eq!(
+            parse(output),
+            Some(Summary {
+                requests: Some(10000),
+                p50_ms: Some(10.3),
+                p95_ms: Some(16.7),
+                p99_ms: Some(0.85),
+            })
+        );
+        assert_eq!(duration_ms("1m2.5s"), Some(62_500.0));
+        assert_eq!(duration_ms("12 parsecs"), None);
+    }
+
+    #[test]
+    fn gatling_summaries_are_read() {
+        let output = r#"
+---- Global Information --------------------------------------------------------
+> request count                                      10000 (OK=10000  KO=0     )
+> min response time                                      1 (OK=1      KO=-     )
+> response time 50th percentile                         10 (OK=10     KO=-     )
+> response time 75th percentile                         14 (OK=14     KO=-     )
+> response time 95th percentile                         20 (OK=20     KO=-     )
+> response time 99th percentile                         30 (OK=30     KO=-     )
+"#;
+        assert_eq!(
+            parse(output),
+            Some(Summary {
+                requests: Some(10000),
+                p50_ms: Some(10.0),
+                p95_ms: Some(20.0),
+                p99_ms: Some(30.0),
+            })
+        );
+    }
+
+    #[test]
+    fn jmeter_summaries_are_read() {
+        let output = r#"
+summary +   4000 in 00:00:12 =  333.3/s Avg:    12 Min:     1 Max:   200 Err:     0 (0.00%) Active: 10
+summary =   4000 in 00:00:12 =  333.3/s Avg:    12 Min:     1 Max:   200 Err:     0 (0.00%)
+summary +   6000 in 00:00:18 =  333.3/s Avg:    12 Min:     1 Max:   200 Err:     0 (0.00%) Active: 0
+summary =  10000 in 00:00:30 =  333.3/s Avg:    12 Min:     1 Max:   200 Err:     0 (0.00%)
+Tidying up ...
+"#;
+        assert_eq!(
+            parse(output),
+            Some(Summary {
+                requests: Some(10000),
+                ..Default::default()
+            })
+        );
+    }
+}
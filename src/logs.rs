@@ -0,0 +1,215 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Output of the processes cardamon runs. Each process writes to its own files under
+//! `.cardamon/logs/<run_id>/` and only the logs of the most recent runs are kept.
+
+use crate::error::{CardamonError, Context, Result};
+use std::{
+    fs::{File, OpenOptions},
+    path::{Path, PathBuf},
+};
+
+/// Where process logs are written.
+pub const LOG_DIR: &str = ".cardamon/logs";
+
+/// The number of previous runs whose logs are kept when a new run starts.
+pub const RUNS_KEPT: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+impl Stream {
+    fn extension(&self) -> &'static str {
+        match self {
+            Stream::Stdout => "stdout",
+            Stream::Stderr => "stderr",
+        }
+    }
+}
+
+/// Run ids and process names become file names, so anything which could escape the log
+/// directory is rejected.
+fn validate(name: &str) -> Result<&str> {
+    let is_valid =
+        !name.is_empty() && name != "." && name != ".." && !name.contains(['/', '\\', '\0']);
+    if is_valid {
+        Ok(name)
+    } else {
+        Err(CardamonError::InvalidInput(format!(
+            "Invalid run id or process name: {name:?}"
+        )))
+    }
+}
+
+/// The file a stream of the given process is written to.
+pub fn log_path(
+    log_dir: &Path,
+    run_id: &str,
+    process_name: &str,
+    stream: Stream,
+) -> Result<PathBuf> {
+    Ok(log_dir.join(validate(run_id)?).join(format!(
+        "{}.{}",
+        validate(process_name)?,
+        stream.extension()
+    )))
+}
+
+/// Opens the stdout and stderr files of a process for writing, creating the run's log directory
+/// if needed.
+///
+/// # Arguments
+///
+/// * `log_dir` - The directory holding the logs of every run
+/// * `run_id` - The run the process belongs to
+/// * `process_name` - The name of the process
+/// * `append` - Add to the end of existing logs rather than replacing them, used for `down`
+///   commands so their output follows the output of the process
+pub fn open(
+    log_dir: &Path,
+    run_id: &str,
+    process_name: &str,
+    append: bool,
+) -> Result<(File, File)> {
+    let open_stream = |stream| -> Result<File> {
+        let path = log_path(log_dir, run_id, process_name, stream)?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).context(format!(
+                "Unable to create log directory {}",
+                parent.display()
+            ))?;
+        }
+
+        OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(&path)
+            .context(format!("Unable to open log file {}", path.display()))
+    };
+
+    Ok((open_stream(Stream::Stdout)?, open_stream(Stream::Stderr)?))
+}
+
+/// Reads a stream logged by a process during a run.
+pub fn read(log_dir: &Path, run_id: &str, process_name: &str, stream: Stream) -> Result<String> {
+    let path = log_path(log_dir, run_id, process_name, stream)?;
+    std::fs::read_to_string(&path).map_err(|source| match source.kind() {
+        std::io::ErrorKind::NotFound => CardamonError::NotFound(format!(
+            "No {} logged by process {process_name} in run {run_id}",
+            stream.extension()
+        )),
+        _ => CardamonError::Io {
+            context: format!("Unable to read log file {}", path.display()),
+            source,
+        },
+    })
+}
+
+/// Removes the logs of all but the `keep` most recently modified runs.
+///
+/// # Returns
+///
+/// The number of runs whose logs were removed.
+pub fn rotate(log_dir: &Path, keep: usize) -> Result<usize> {
+    if !log_dir.exists() {
+        return Ok(0);
+    }
+
+    let mut run_dirs = std::fs::read_dir(log_dir)
+        .context(format!(
+            "Unable to read log directory {}",
+            log_dir.display()
+        ))?
+        .flatten()
+        .filter(|entry| entry.path().is_dir())
+        .map(|entry| {
+            let modified = entry
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .unwrap_or(std::time::UNIX_EPOCH);
+            (modified, entry.path())
+        })
+        .collect::<Vec<_>>();
+    run_dirs.sort_by(|(a, _), (b, _)| b.cmp(a));
+
+    let mut removed = 0;
+    for (_, path) in run_dirs.into_iter().skip(keep) {
+        std::fs::remove_dir_all(&path)
+            .context(format!("Unable to remove old logs {}", path.display()))?;
+        removed += 1;
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn temp_log_dir() -> PathBuf {
+        std::env::temp_dir().join(nanoid::nanoid!(5)).join("logs")
+    }
+
+    #[test]
+    fn processes_log_to_their_own_files() -> anyhow::Result<()> {
+        let log_dir = temp_log_dir();
+
+        let (mut stdout, _) = open(&log_dir, "abc", "db", false)?;
+        writeln!(stdout, "db started")?;
+        let (mut stdout, _) = open(&log_dir, "abc", "web", false)?;
+        writeln!(stdout, "web started")?;
+
+        // down commands append to the output of the process
+        let (mut stdout, _) = open(&log_dir, "abc", "db", true)?;
+        writeln!(stdout, "db stopped")?;
+
+        assert_eq!(
+            read(&log_dir, "abc", "db", Stream::Stdout)?,
+            "db started\ndb stopped\n"
+        );
+        assert_eq!(
+            read(&log_dir, "abc", "web", Stream::Stdout)?,
+            "web started\n"
+        );
+        assert_eq!(read(&log_dir, "abc", "web", Stream::Stderr)?, "");
+        assert!(read(&log_dir, "abc", "cache", Stream::Stdout).is_err());
+
+        std::fs::remove_dir_all(log_dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn names_cannot_escape_the_log_directory() {
+        let log_dir = Path::new(LOG_DIR);
+        assert!(log_path(log_dir, "..", "db", Stream::Stdout).is_err());
+        assert!(log_path(log_dir, "abc", "../../cardamon", Stream::Stdout).is_err());
+        assert!(log_path(log_dir, "abc", "db", Stream::Stdout).is_ok());
+    }
+
+    #[test]
+    fn only_the_most_recent_runs_are_kept() -> anyhow::Result<()> {
+        let log_dir = temp_log_dir();
+        for run_id in ["1", "2", "3"] {
+            open(&log_dir, run_id, "db", false)?;
+            // make sure each run has a distinct modified time
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+
+        assert_eq!(rotate(&log_dir, 2)?, 1);
+        assert!(!log_dir.join("1").exists());
+        assert!(log_dir.join("2").exists());
+        assert!(log_dir.join("3").exists());
+        assert_eq!(rotate(&log_dir, 2)?, 0);
+
+        std::fs::remove_dir_all(log_dir)?;
+        Ok(())
+    }
+}
@@ -1,13 +1,47 @@
-use std::path::Path;
+use std::{
+    collections::{HashMap, HashSet},
+    io::IsTerminal,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
+use anyhow::Context;
 use cardamon::{
-    config::{self, ProcessToObserve},
-    data_access::LocalDataAccessService,
-    run,
+    active_runs, agent,
+    alerts::AlertMonitor,
+    badge::{self, Metric},
+    baseline,
+    carbon::{self, Region},
+    cloud,
+    config::{self, ProcessToObserve, Role},
+    data_access::{self, LocalDataAccessService, MySqlDataAccessService},
+    data_access::{
+        energy_mix, run_context::RunContext, run_phase::PHASE_STARTUP,
+        scenario_iteration::STATUS_CANCELLED, snapshot, DataAccessService, DEFAULT_PROJECT,
+    },
+    dataset::{AggregationMethod, ObservationDataset, ProcessFilter, RunDataset},
+    discovery,
+    error::CardamonError,
+    impact_framework, import,
+    logs::{self, Stream},
+    metrics_logger,
+    model::{self, iteration_energy, EnergyByRole},
+    orphans::{self, RunLock},
+    parse_duration, report, run, run_live, signing,
+    stats::{Comparison, CurveFit, Spread, Trend},
+    telemetry::{self, LogFormat},
+    watch, LiveStopCondition,
 };
-use clap::{Parser, Subcommand};
-use sqlx::{migrate::MigrateDatabase, SqlitePool};
-use tracing::Level;
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use futures_util::TryStreamExt;
+use sqlx::SqlitePool;
+use tokio_util::sync::CancellationToken;
+
+/// The name cardamon is installed as, used by completions and manual pages.
+const BIN_NAME: &str = "card";
+
+/// Iterations whose power varies by more than this percentage of the mean are flagged as noisy.
+const NOISY_CV: f64 = 10.0;
 
 #[derive(Parser, Debug)]
 #[command(author = "Oliver Winks (@ohuu), William Kimbell (@seal)", version, about, long_about = None)]
@@ -18,29 +52,400 @@ pub struct Cli {
     #[arg(short, long)]
     pub file: Option<String>,
 
+    /// Write logs as human readable text or as JSON for log aggregators
+    #[arg(long, value_name = "text|json", default_value_t = LogFormat::Text, global = true)]
+    pub log_format: LogFormat,
+
+    /// Append logs to this file as well as stdout
+    #[arg(long, global = true)]
+    pub log_file: Option<PathBuf>,
+
+    /// Record and read runs of this project, overrides the project in the config file
+    #[arg(long, env = "CARDAMON_PROJECT", global = true)]
+    pub project: Option<String>,
+
+    /// Record and read runs in this SQLite file, given as a path or "sqlite://" url, or in this
+    /// "mysql://" database instead of the database in the config file
+    #[arg(long, value_name = "PATH|URL", env = "CARDAMON_DB", global = true)]
+    pub db: Option<String>,
+
+    /// Apply the overrides of this `[profile.<name>]` of the config file, e.g. ci
+    #[arg(long, env = "CARDAMON_PROFILE", global = true)]
+    pub profile: Option<String>,
+
     #[command(subcommand)]
     pub command: Commands,
 }
 
 #[derive(Subcommand, Debug)]
 pub enum Commands {
+    Init {
+        #[arg(value_name = "PROVIDER:INSTANCE", long)]
+        cloud: Option<String>,
+    },
+
     Run {
-        name: String,
+        /// The observation or scenario to run, picked from the config if not given
+        name: Option<String>,
 
-        #[arg(value_name = "EXTERNAL PIDs", short, long, value_delimiter = ',')]
-        pids: Option<Vec<String>>,
+        #[command(flatten)]
+        external: ProcessArgs,
 
-        #[arg(
-            value_name = "EXTERNAL CONTAINER NAMES",
-            short,
-            long,
-            value_delimiter = ','
-        )]
-        containers: Option<Vec<String>>,
+        #[arg(value_name = "REMOTE:PID", long, value_delimiter = ',')]
+        remote_pids: Option<Vec<String>>,
 
         #[arg(long)]
         external_only: bool,
+
+        #[arg(value_name = "METHOD", long, default_value = "average")]
+        aggregation: AggregationMethod,
+
+        #[arg(value_name = "BASELINE ID | latest", long)]
+        baseline: Option<String>,
+
+        #[arg(long)]
+        strict: bool,
+
+        /// Wait for a run already measuring this machine to finish instead of failing
+        #[arg(long)]
+        wait: bool,
+
+        /// Don't show the live progress line while scenarios run
+        #[arg(short, long)]
+        quiet: bool,
+
+        /// Run every scenario for this many iterations instead of the number in the config
+        #[arg(value_name = "N", long)]
+        iterations: Option<u32>,
+
+        /// Only run these scenarios of the observation
+        #[arg(value_name = "SCENARIOS", long, value_delimiter = ',')]
+        only: Vec<String>,
+
+        /// Don't run these scenarios of the observation
+        #[arg(value_name = "SCENARIOS", long, value_delimiter = ',')]
+        skip: Vec<String>,
+
+        /// Print what the run would do without starting anything
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Describe the run, e.g. what changed since the last one
+        #[arg(long)]
+        note: Option<String>,
+
+        /// Wait until the forecast carbon intensity of the grid is lowest before starting
+        #[arg(long)]
+        carbon_aware: bool,
+
+        /// How long a carbon aware run may wait, e.g. 6h
+        #[arg(value_name = "DURATION", long, value_parser = parse_duration, default_value = "6h", requires = "carbon_aware")]
+        window: Duration,
+
+        /// Print when a carbon aware run would start instead of waiting and running it
+        #[arg(long, requires = "carbon_aware")]
+        suggest: bool,
+
+        /// Run each benchmark of the cargo package in the current directory as a scenario of a
+        /// new observation with the given name
+        #[arg(long, conflicts_with = "pytest")]
+        cargo_bench: bool,
+
+        /// Run each test pytest collects from the path as a scenario of a new observation with
+        /// the given name
+        #[arg(value_name = "PATH", long)]
+        pytest: Option<PathBuf>,
+
+        #[command(flatten)]
+        filter: ProcessFilterArgs,
+    },
+
+    /// Re-run an observation whenever source files change, showing how its energy changed
+    Watch {
+        name: String,
+
+        /// Directory to watch for changes
+        #[arg(value_name = "PATH", long, default_value = ".")]
+        path: PathBuf,
+
+        /// How long files must stop changing for before the observation is re-run, e.g. 0.5s
+        #[arg(value_name = "DURATION", long, value_parser = parse_duration, default_value = "1s")]
+        debounce: Duration,
+    },
+
+    Live {
+        #[arg(default_value = "live")]
+        name: String,
+
+        #[command(flatten)]
+        processes: ProcessArgs,
+
+        #[arg(value_name = "DURATION", long, value_parser = parse_duration)]
+        duration: Option<Duration>,
+
+        #[arg(value_name = "N", long)]
+        samples: Option<usize>,
+    },
+
+    Baseline {
+        #[arg(value_name = "SECONDS", long, default_value_t = 30)]
+        duration: u64,
+
+        #[command(subcommand)]
+        command: Option<BaselineCommands>,
+    },
+
+    Agent {
+        /// The cardamon server, read from the environment if it includes credentials so they
+        /// aren't visible in the process list
+        #[arg(long, env = "CARDAMON_SERVER_URL")]
+        server_url: String,
+
+        #[arg(long)]
+        run_id: String,
+
+        #[command(flatten)]
+        processes: ProcessArgs,
+
+        #[arg(value_name = "SECONDS", long, default_value_t = 5)]
+        flush_interval: u64,
+
+        #[arg(long, default_value_t = 100_000)]
+        buffer_capacity: usize,
+    },
+
+    Daemon {
+        #[command(flatten)]
+        filter: ProcessFilterArgs,
+    },
+
+    Import {
+        #[arg(long)]
+        scenario: String,
+
+        #[arg(value_name = "FILE.csv | FILE.json")]
+        file: String,
+    },
+
+    Curve {
+        #[arg(value_name = "SCENARIOS", long, value_delimiter = ',')]
+        scenarios: Option<Vec<String>>,
+
+        #[arg(long, default_value_t = 1)]
+        previous_runs: u32,
+
+        #[arg(value_name = "FILE", long)]
+        csv: Option<String>,
+    },
+
+    /// Show the energy and emissions of recent runs
+    Stats {
+        #[arg(value_name = "SCENARIOS", long, value_delimiter = ',')]
+        scenarios: Option<Vec<String>>,
+
+        #[arg(long, default_value_t = 1)]
+        previous_runs: u32,
+
+        /// What each run would have emitted in these regions, country codes like GB or
+        /// NAME=gCO2/kWh
+        #[arg(value_name = "REGIONS", long, value_delimiter = ',')]
+        regions: Vec<Region>,
+
+        /// How the energy of each iteration of a run is combined
+        #[arg(value_name = "METHOD", long, default_value = "average")]
+        aggregation: AggregationMethod,
+
+        #[command(flatten)]
+        filter: ProcessFilterArgs,
+    },
+
+    Db {
+        #[command(subcommand)]
+        command: DbCommands,
+    },
+
+    Runs {
+        #[command(subcommand)]
+        command: RunsCommands,
     },
+
+    Diff {
+        scenario: String,
+
+        #[arg(long, default_value_t = 1)]
+        previous_runs: u32,
+
+        #[command(flatten)]
+        filter: ProcessFilterArgs,
+    },
+
+    /// Write a JSON summary of a run, signed with the local signing key if asked so the figures
+    /// can be verified later
+    Report {
+        run_id: String,
+
+        file: String,
+
+        #[arg(long)]
+        sign: bool,
+
+        /// The signing key, created if it doesn't exist yet, defaults to ~/.cardamon/signing.key
+        #[arg(value_name = "FILE", long, env = "CARDAMON_SIGNING_KEY")]
+        key: Option<PathBuf>,
+    },
+
+    /// Write a run as a Green Software Foundation Impact Framework manifest, for IF plugins such
+    /// as sci to work from
+    Manifest {
+        run_id: String,
+
+        #[arg(value_name = "FILE.yml")]
+        file: String,
+    },
+
+    /// Write an SVG badge of the energy of a scenario's latest run, for embedding in a README
+    Badge {
+        scenario: String,
+
+        #[arg(value_name = "FILE.svg")]
+        file: String,
+
+        /// What the badge shows
+        #[arg(long, value_name = "energy|co2|trend", default_value_t = Metric::Energy)]
+        metric: Metric,
+    },
+
+    /// Check a signed report hasn't changed since it was signed
+    Verify {
+        file: String,
+
+        /// Only accept reports signed with this public key, as printed when the report was signed
+        #[arg(value_name = "BASE64", long)]
+        public_key: Option<String>,
+    },
+
+    Clean,
+
+    /// Stop a run in progress in this directory, keeping what was measured before it stopped
+    Cancel {
+        run_id: String,
+    },
+
+    /// Continue a run which was interrupted from the first scenario iteration it didn't complete
+    Resume {
+        run_id: String,
+    },
+
+    Logs {
+        run: String,
+
+        process: String,
+
+        #[arg(long)]
+        stderr: bool,
+    },
+
+    /// Print a completion script for the shell, e.g. `card completions bash > ~/.local/share/bash-completion/completions/card`
+    Completions {
+        shell: clap_complete::Shell,
+    },
+
+    /// Print the manual page, or write a page for every command to a directory
+    Man {
+        #[arg(value_name = "DIR", long)]
+        out_dir: Option<PathBuf>,
+    },
+}
+
+/// Processes started outside of cardamon to observe.
+#[derive(Args, Debug)]
+pub struct ProcessArgs {
+    #[arg(value_name = "PIDs", short, long, value_delimiter = ',')]
+    pids: Vec<u32>,
+
+    #[arg(value_name = "CONTAINER NAMES", short, long, value_delimiter = ',')]
+    containers: Vec<String>,
+
+    #[arg(value_name = "SYSTEMD UNITS", long, value_delimiter = ',')]
+    units: Vec<String>,
+
+    #[arg(value_name = "CGROUP PATHS", long, value_delimiter = ',')]
+    cgroups: Vec<String>,
+}
+impl ProcessArgs {
+    fn into_processes(self) -> Vec<ProcessToObserve> {
+        self.pids
+            .into_iter()
+            .map(ProcessToObserve::from)
+            .chain(
+                self.containers
+                    .into_iter()
+                    .map(ProcessToObserve::ContainerName),
+            )
+            .chain(self.units.into_iter().map(ProcessToObserve::SystemdUnit))
+            .chain(self.cgroups.into_iter().map(ProcessToObserve::Cgroup))
+            .collect()
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct ProcessFilterArgs {
+    /// Only include processes whose name or id matches one of these globs
+    #[arg(value_name = "GLOB", long, value_delimiter = ',')]
+    only_process: Vec<String>,
+
+    /// Leave out processes whose name or id matches one of these globs
+    #[arg(value_name = "GLOB", long, value_delimiter = ',')]
+    exclude_process: Vec<String>,
+}
+impl ProcessFilterArgs {
+    fn into_filter(self) -> ProcessFilter {
+        ProcessFilter::new(self.only_process, self.exclude_process)
+    }
+}
+
+/// Without a subcommand `baseline` measures the idle machine.
+#[derive(Subcommand, Debug)]
+pub enum BaselineCommands {
+    /// Compare the summaries of later runs against this run, e.g. the last release
+    Set {
+        run_id: String,
+
+        /// What the run represents, e.g. "release 1.0", defaults to the run id
+        #[arg(long)]
+        name: Option<String>,
+    },
+
+    /// Stop comparing runs against a reference run
+    Unset,
+
+    /// Show the run later runs are compared against
+    Show,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum RunsCommands {
+    /// Permanently delete a run along with all of its iterations and metrics
+    Delete { id: String },
+
+    /// Leave a run out of trends and comparisons without deleting it
+    Archive { id: String },
+
+    /// Include an archived run in trends and comparisons again
+    Unarchive { id: String },
+
+    /// Set the note describing a run, or remove it if no note is given
+    Annotate { id: String, note: Option<String> },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum DbCommands {
+    /// Write every run, baseline and metric in the database to a snapshot, compressed if the
+    /// file ends in .tar.zst and JSON otherwise
+    Export { file: String },
+
+    /// Add the runs and baselines in a snapshot which aren't already in the database
+    Import { file: String },
 }
 
 #[tokio::main]
@@ -49,24 +454,87 @@ async fn main() -> anyhow::Result<()> {
     let args = Cli::parse();
 
     // Initialize tracing
-    let level = if args.verbose {
-        Level::DEBUG
-    } else {
-        Level::WARN
+    let level = if args.verbose { "debug" } else { "warn" };
+    telemetry::init("cardamon", args.log_format, level, args.log_file.as_deref())?;
+
+    // settings shared by every command, the config file is optional for most commands
+    let config_path = args.file.as_deref().unwrap_or("./cardamon.toml");
+    let file_config = match config::Config::from_path_with_profile(
+        Path::new(config_path),
+        args.profile.as_deref(),
+    ) {
+        Ok(config) => Some(config),
+        // a missing file is no config, unless a profile was asked for since it can't be picked
+        // without the file it's in. A file which can't be parsed isn't silently ignored.
+        Err(CardamonError::ConfigRead { source, .. })
+            if source.kind() == std::io::ErrorKind::NotFound && args.profile.is_none() =>
+        {
+            None
+        }
+        Err(err) => return Err(err.into()),
     };
-    let subscriber = tracing_subscriber::fmt().with_max_level(level).finish();
-    tracing::subscriber::set_global_default(subscriber)?;
+    if let Some(interval) = file_config
+        .as_ref()
+        .and_then(|config| config.sample_interval())
+    {
+        metrics_logger::set_sample_interval(interval);
+    }
+    let project = args
+        .project
+        .clone()
+        .or_else(|| {
+            file_config
+                .as_ref()
+                .and_then(|config| config.project.clone())
+        })
+        .unwrap_or_else(|| String::from(DEFAULT_PROJECT));
+    let mut database = file_config
+        .map(|config| config.database)
+        .unwrap_or_default();
+    if let Some(location) = &args.db {
+        database.set_location(location)?;
+    }
 
     match args.command {
+        Commands::Init { cloud } => {
+            let path = match &args.file {
+                Some(path) => Path::new(path),
+                None => Path::new("./cardamon.toml"),
+            };
+            if path.exists() {
+                return Err(anyhow::anyhow!("{} already exists", path.display()));
+            }
+
+            let instance = cloud.as_deref().map(cloud::find_instance).transpose()?;
+            std::fs::write(path, config::starter_config(instance))
+                .context(format!("Unable to write {}", path.display()))?;
+            println!("Created {}", path.display());
+        }
+
         Commands::Run {
             name,
-            pids,
-            containers,
+            external,
+            remote_pids,
             external_only,
+            aggregation,
+            baseline,
+            strict,
+            wait,
+            quiet,
+            iterations,
+            only,
+            skip,
+            dry_run,
+            note,
+            carbon_aware,
+            window,
+            suggest,
+            cargo_bench,
+            pytest,
+            filter,
         } => {
             // set up local data access
-            let pool = create_db().await?;
-            let data_access_service = LocalDataAccessService::new(pool);
+            let data_access_service = open_db(&database, &project).await?;
 
             // open config file
             let path = match &args.file {
@@ -74,8 +542,26 @@ async fn main() -> anyhow::Result<()> {
                 None => Path::new("./cardamon.toml"),
             };
 
-            // create an execution plan
-            let config = config::Config::from_path(path)?;
+            // create an execution plan, the scenarios may come from a test framework
+            let mut config = config::Config::from_path_with_profile(path, args.profile.as_deref())?;
+            let name = if cargo_bench {
+                let name = name.unwrap_or_else(|| String::from("cargo-bench"));
+                config.add_discovered(&name, discovery::cargo_benches(1)?)?;
+                name
+            } else if let Some(pytest) = pytest {
+                let name = name.unwrap_or_else(|| String::from("pytest"));
+                config.add_discovered(&name, discovery::pytest(&pytest, 1)?)?;
+                name
+            } else {
+                match name {
+                    Some(name) => name,
+                    None => pick_runnable(&config)?,
+                }
+            };
+            config.filter_scenarios(only, skip)?;
+            if let Some(iterations) = iterations {
+                config.override_iterations(iterations)?;
+            }
             let mut execution_plan = if external_only {
                 config.create_execution_plan_external_only(&name)
             } else {
@@ -83,51 +569,1765 @@ async fn main() -> anyhow::Result<()> {
             }?;
 
             // add external processes to observe.
-            for pid in pids.unwrap_or(vec![]) {
-                let pid = pid.parse::<u32>()?;
-                execution_plan.observe_external_process(ProcessToObserve::Pid(None, pid));
+            for process in external.into_processes() {
+                execution_plan.observe_external_process(process);
+            }
+            for remote_pid in remote_pids.unwrap_or(vec![]) {
+                let (remote_name, pid) = remote_pid
+                    .split_once(':')
+                    .context("Remote PIDs should be formatted as <remote>:<pid>")?;
+                let remote = config
+                    .find_remote(remote_name)
+                    .context(format!("Unable to find remote with name: {remote_name}"))?;
+                execution_plan.observe_external_process(ProcessToObserve::RemotePid(
+                    remote.clone(),
+                    pid.parse()?,
+                ));
+            }
+
+            // subtract an idle baseline from the results
+            if let Some(baseline) = baseline {
+                let baseline_dao = data_access_service.baseline_dao();
+                let baseline = if baseline == "latest" {
+                    baseline_dao.fetch_latest().await?
+                } else {
+                    baseline_dao.fetch(&baseline).await?
+                }
+                .context(format!("Unable to find baseline: {baseline}"))?;
+                execution_plan.use_baseline(&baseline.id);
+            }
+
+            if strict {
+                execution_plan.use_strict_mode();
+            }
+
+            if wait {
+                execution_plan.queue_behind_other_runs();
+            }
+
+            if quiet {
+                execution_plan.hide_progress();
+            }
+
+            if let Some(note) = &note {
+                execution_plan.annotate(note);
+            }
+
+            if dry_run {
+                println!("{}", execution_plan.describe());
+                if carbon_aware {
+                    println!(
+                        "Waits up to {:.1}h for the greenest time to start",
+                        window.as_secs_f64() / 3600.0
+                    );
+                }
+                return Ok(());
             }
-            for container_name in containers.unwrap_or(vec![]) {
-                execution_plan
-                    .observe_external_process(ProcessToObserve::ContainerName(container_name));
+
+            // start when the grid is greenest within the window
+            if carbon_aware {
+                let provider = config.carbon_intensity.clone().unwrap_or_default();
+                let now = chrono::Utc::now();
+                let forecast = carbon::fetch_forecast(&provider, now).await?;
+                let schedule = carbon::greenest_start(&forecast, now, window)
+                    .context("The carbon intensity forecast doesn't cover the window")?;
+                println!(
+                    "Start at {} when the forecast intensity is {:.0} gCO2/kWh",
+                    schedule.start.format("%Y-%m-%d %H:%M UTC"),
+                    schedule.intensity
+                );
+                if suggest {
+                    return Ok(());
+                }
+
+                tokio::time::sleep(schedule.wait(now)).await;
+                execution_plan.record_schedule(&schedule.describe(now));
             }
 
+            // processes left running by a crashed run would be measured alongside this one
+            warn_about_leftovers().await?;
+
             // run it!
-            let observation_dataset = run(execution_plan, &data_access_service).await?;
+            let interrupt = cancel_on_ctrl_c();
+            let observation_dataset = run(execution_plan, data_access_service.as_ref()).await;
+            interrupt.abort();
+            let observation_dataset = observation_dataset?.filter_processes(&filter.into_filter());
 
+            let reference = data_access_service.run_dao().fetch_reference().await?;
+            let mut phases_shown = HashSet::new();
             for scenario_dataset in observation_dataset.by_scenario().iter() {
                 println!("Scenario: {:?}", scenario_dataset.scenario_name());
                 println!("--------------------------------");
 
                 for run_dataset in scenario_dataset.by_run().iter() {
                     println!("Run: {:?}", run_dataset.run_id());
+                    let run_details = data_access_service
+                        .run_dao()
+                        .fetch(run_dataset.run_id())
+                        .await?;
+                    if let Some(note) = run_details.as_ref().and_then(|run| run.note.as_deref()) {
+                        println!("\tNote: {note}");
+                    }
+                    if let Some(schedule) =
+                        run_details.as_ref().and_then(|run| run.schedule.as_deref())
+                    {
+                        println!("\tSchedule: {schedule}");
+                    }
+                    if let Some(iterations) = run_details.as_ref().and_then(|run| run.iterations) {
+                        println!("\tIterations: {iterations}, overriding the config");
+                    }
+                    if let Some(ambient_load) =
+                        run_details.as_ref().and_then(|run| run.ambient_load)
+                    {
+                        println!("\tAmbient load: {ambient_load:.1}% CPU before the run");
+                    }
+                    let cold_starts = run_dataset
+                        .by_iterations()
+                        .iter()
+                        .filter(|it| it.scenario_iteration().cold_start)
+                        .count();
+                    if cold_starts > 0 {
+                        println!("\tCold starts: {cold_starts} iterations started with the processes restarted");
+                    }
+
+                    // an observed process exited so the iteration wasn't fully measured
+                    for it in run_dataset.by_iterations() {
+                        let scenario_iteration = it.scenario_iteration();
+                        if !scenario_iteration.is_ok() {
+                            println!(
+                                "\tIteration {} is incomplete ({})",
+                                scenario_iteration.iteration, scenario_iteration.status
+                            );
+                        }
+                    }
 
-                    for avged_dataset in run_dataset.averaged().iter() {
-                        println!("\t{:?}", avged_dataset);
+                    // throttled iterations give misleading energy and performance numbers
+                    let max_frequency = config.cpu.as_ref().and_then(|cpu| cpu.max_frequency);
+                    for throttled in run_dataset.throttled(max_frequency) {
+                        println!(
+                            "\tIteration {} was throttled, compare it with care",
+                            throttled.scenario_iteration().iteration
+                        );
                     }
+
+                    // containers capped by their cpu quota under-report the work they did
+                    for it in run_dataset.by_iterations() {
+                        for (container, throttled) in it.cpu_quota_throttling() {
+                            println!(
+                                "\tIteration {} hit the CPU quota of {container} in {throttled:.0}% of periods, its energy is under-reported",
+                                it.scenario_iteration().iteration
+                            );
+                        }
+                    }
+
+                    // sampling backs off while collecting is slow, energy accounts for it
+                    for it in run_dataset.by_iterations() {
+                        if let Some(interval) = it.sample_interval().filter(|secs| *secs > 1.5) {
+                            println!(
+                                "\tIteration {} was sampled every {interval:.1}s under load",
+                                it.scenario_iteration().iteration
+                            );
+                        }
+                    }
+
+                    // flag noisy iterations and optionally leave them out of the results
+                    let mut filtered_dataset = None;
+                    if let Some(detection) = &config.outliers {
+                        for outlier in run_dataset.outliers(detection) {
+                            println!(
+                                "\tIteration {} is an outlier",
+                                outlier.scenario_iteration().iteration
+                            );
+                        }
+                        if detection.exclude {
+                            filtered_dataset = Some(run_dataset.without_outliers(detection));
+                        }
+                    }
+                    let run_dataset = filtered_dataset.as_ref().unwrap_or(run_dataset);
+
+                    for process_metrics in run_dataset.aggregated(aggregation).iter() {
+                        println!("\t{:?}", process_metrics);
+                    }
+
+                    // prefer energy measured by a power meter over modelling it from the cpu
+                    let iterations = run_dataset.by_iterations();
+                    let measured = iterations.iter().any(|it| it.has_measured_power());
+                    if config.cpu.is_some() || measured {
+                        let run_baseline = match run_details.and_then(|run| run.baseline_id) {
+                            Some(baseline_id) => {
+                                data_access_service
+                                    .baseline_dao()
+                                    .fetch(&baseline_id)
+                                    .await?
+                            }
+                            None => None,
+                        };
+
+                        let cpu_for = |metrics: &cardamon::data_access::cpu_metrics::CpuMetrics| {
+                            config.cpu_for(&metrics.process_id, &metrics.process_name)
+                        };
+                        let energy = iteration_energy(&config, iterations, run_baseline.as_ref());
+
+                        // load generators are reported separately from the system under test
+                        let EnergyByRole {
+                            sut: energy,
+                            load: load_energy,
+                        } = EnergyByRole::new(&config, energy);
+
+                        // cardamon's own energy is always reported and optionally left out
+                        let subtract_overhead =
+                            config.overhead.is_some_and(|overhead| overhead.subtract);
+                        let (overhead_energy, energy): (Vec<_>, Vec<_>) =
+                            energy.into_iter().partition(|e| {
+                                subtract_overhead && e.process_name == cardamon::SELF_PROCESS_NAME
+                            });
+
+                        let reported = |e: &model::ProcessEnergy| {
+                            e.role(&config) == Role::Sut
+                                && !(subtract_overhead
+                                    && e.process_name == cardamon::SELF_PROCESS_NAME)
+                        };
+                        let per_iteration = |wh: f64| wh / iterations.len().max(1) as f64;
+                        let cpu_wh = per_iteration(energy.iter().map(|e| e.cpu_energy_wh).sum());
+                        let memory_wh =
+                            per_iteration(energy.iter().map(|e| e.memory_energy_wh).sum());
+
+                        // account for data centre overheads when running in the cloud
+                        let pue = config.cloud.as_ref().map(|cloud| cloud.pue).unwrap_or(1.0);
+                        let (cpu_wh, memory_wh) = (cpu_wh * pue, memory_wh * pue);
+                        println!(
+                            "\tEnergy: {:.4} Wh per iteration (cpu: {cpu_wh:.4} Wh, memory: {memory_wh:.4} Wh){}",
+                            cpu_wh + memory_wh,
+                            if measured {
+                                " (measured)"
+                            } else if run_baseline.is_some() {
+                                " (idle baseline subtracted)"
+                            } else {
+                                ""
+                            }
+                        );
+
+                        // starting and stopping the application is shown once per run
+                        if phases_shown.insert(run_dataset.run_id().to_string()) {
+                            let phases = data_access_service
+                                .run_phase_dao()
+                                .fetch(run_dataset.run_id())
+                                .await?;
+                            let phase_iterations = data_access_service
+                                .fetch_metrics(phases.iter().map(|p| p.as_iteration()).collect())
+                                .await?;
+                            for (phase, it) in phases.iter().zip(phase_iterations.iter()) {
+                                let wh = iteration_energy(&config, &[it], run_baseline.as_ref())
+                                    .iter()
+                                    .filter(|e| reported(e))
+                                    .map(|e| e.energy_wh())
+                                    .sum::<f64>()
+                                    * pue;
+                                println!(
+                                    "\tEnergy to {}: {wh:.4} Wh over {:.1}s",
+                                    if phase.phase == PHASE_STARTUP {
+                                        "start"
+                                    } else {
+                                        "stop"
+                                    },
+                                    (phase.stop_time - phase.start_time) as f64 / 1000.0
+                                );
+                            }
+                        }
+
+                        // energy of the phases the scenario command marked in its iterations, or
+                        // of the traced requests it made, by phase name e.g. endpoint
+                        let iteration_phases = data_access_service
+                            .iteration_phase_dao()
+                            .fetch(run_dataset.run_id())
+                            .await?;
+                        let mut phase_wh: Vec<(&str, f64, usize)> = vec![];
+                        for it in iterations.iter() {
+                            let phases = iteration_phases
+                                .iter()
+                                .filter(|p| {
+                                    p.scenario_name == scenario_dataset.scenario_name()
+                                        && p.iteration == it.scenario_iteration().iteration
+                                })
+                                .collect::<Vec<_>>();
+                            let windows = phases
+                                .iter()
+                                .map(|p| (p.phase.as_str(), p.start_time, p.stop_time))
+                                .collect::<Vec<_>>();
+                            for (phase, slice) in phases.iter().zip(it.split(&windows)) {
+                                let wh =
+                                    iteration_energy(&config, &[&slice], run_baseline.as_ref())
+                                        .iter()
+                                        .filter(|e| reported(e))
+                                        .map(|e| e.energy_wh())
+                                        .sum::<f64>()
+                                        * pue;
+                                let spans = usize::from(phase.span_id.is_some());
+                                match phase_wh.iter_mut().find(|(name, ..)| *name == phase.phase) {
+                                    Some((_, total, total_spans)) => {
+                                        *total += wh;
+                                        *total_spans += spans;
+                                    }
+                                    None => phase_wh.push((&phase.phase, wh, spans)),
+                                }
+                            }
+                        }
+                        for (phase, wh, spans) in phase_wh {
+                            println!(
+                                "\tPhase {phase}: {:.4} Wh per iteration{}",
+                                per_iteration(wh),
+                                if spans > 0 {
+                                    format!(
+                                        " ({spans} spans, {:.6} Wh per span)",
+                                        wh / spans as f64
+                                    )
+                                } else {
+                                    String::new()
+                                }
+                            );
+                        }
+
+                        // energy of each iteration shared between the endpoints it served by
+                        // their share of its requests
+                        let endpoint_requests = data_access_service
+                            .endpoint_request_dao()
+                            .fetch(run_dataset.run_id())
+                            .await?;
+                        let scenario_requests = endpoint_requests
+                            .iter()
+                            .filter(|r| r.scenario_name == scenario_dataset.scenario_name())
+                            .collect::<Vec<_>>();
+                        let iteration_wh = iterations
+                            .iter()
+                            .map(|it| {
+                                let wh = iteration_energy(&config, &[it], run_baseline.as_ref())
+                                    .iter()
+                                    .filter(|e| reported(e))
+                                    .map(|e| e.energy_wh())
+                                    .sum::<f64>()
+                                    * pue;
+                                (it.scenario_iteration().iteration, wh)
+                            })
+                            .collect::<Vec<_>>();
+                        for share in model::share_by_endpoint(&iteration_wh, &scenario_requests) {
+                            println!(
+                                "\tEndpoint {}: {} requests, {:.6} Wh per request (estimated)",
+                                share.endpoint,
+                                share.requests,
+                                share.wh_per_request()
+                            );
+                        }
+
+                        // work reported by the scenario command gives energy per request
+                        let iteration_results = data_access_service
+                            .iteration_result_dao()
+                            .fetch(run_dataset.run_id())
+                            .await?
+                            .into_iter()
+                            .filter(|result| {
+                                result.scenario_name == scenario_dataset.scenario_name()
+                                    && iterations.iter().any(|it| {
+                                        it.scenario_iteration().iteration == result.iteration
+                                    })
+                            })
+                            .collect::<Vec<_>>();
+                        let requests = iteration_results
+                            .iter()
+                            .filter_map(|result| result.requests)
+                            .map(|requests| requests as f64)
+                            .collect::<Vec<_>>();
+                        let p95_ms = iteration_results
+                            .iter()
+                            .filter_map(|result| result.p95_ms)
+                            .collect::<Vec<_>>();
+                        let mean_requests = requests.iter().sum::<f64>() / requests.len() as f64;
+                        if !requests.is_empty() && mean_requests > 0.0 {
+                            println!(
+                                "\tEfficiency: {:.4} J per request ({mean_requests:.0} requests per iteration){}",
+                                (cpu_wh + memory_wh) * 3600.0 / mean_requests,
+                                if p95_ms.is_empty() {
+                                    String::new()
+                                } else {
+                                    format!(
+                                        ", p95 latency {:.1} ms",
+                                        p95_ms.iter().sum::<f64>() / p95_ms.len() as f64
+                                    )
+                                }
+                            );
+                        }
+
+                        // a single averaged number hides whether the measurement is stable
+                        if iterations.len() > 1 {
+                            let iteration_power = iterations
+                                .iter()
+                                .map(|it| {
+                                    let wh =
+                                        iteration_energy(&config, &[*it], run_baseline.as_ref())
+                                            .iter()
+                                            .filter(|e| reported(e))
+                                            .map(|e| e.energy_wh())
+                                            .sum::<f64>()
+                                            * pue;
+                                    let scenario_iteration = it.scenario_iteration();
+                                    let hours = (scenario_iteration.stop_time
+                                        - scenario_iteration.start_time)
+                                        as f64
+                                        / 3_600_000.0;
+                                    if hours > 0.0 {
+                                        wh / hours
+                                    } else {
+                                        0.0
+                                    }
+                                })
+                                .collect::<Vec<_>>();
+                            if let Some(spread) = Spread::new(&iteration_power) {
+                                println!(
+                                    "\tPower: {:.2} W ± {:.2} W per iteration (CV {:.1}%){}",
+                                    spread.mean,
+                                    spread.stddev,
+                                    spread.cv,
+                                    if spread.cv > NOISY_CV {
+                                        ", too noisy to trust the average"
+                                    } else {
+                                        ""
+                                    }
+                                );
+                            }
+                        }
+
+                        // compare against the run pinned with `card baseline set`
+                        if let Some(reference) = reference
+                            .as_ref()
+                            .filter(|reference| reference.run_id != run_dataset.run_id())
+                        {
+                            let reference_iterations = data_access_service
+                                .fetch_metrics(
+                                    data_access_service
+                                        .scenario_iteration_dao()
+                                        .fetch_run(
+                                            scenario_dataset.scenario_name(),
+                                            &reference.run_id,
+                                        )
+                                        .await?,
+                                )
+                                .await?;
+                            if !reference_iterations.is_empty() {
+                                let reference_baseline = match data_access_service
+                                    .run_dao()
+                                    .fetch(&reference.run_id)
+                                    .await?
+                                    .and_then(|run| run.baseline_id)
+                                {
+                                    Some(baseline_id) => {
+                                        data_access_service
+                                            .baseline_dao()
+                                            .fetch(&baseline_id)
+                                            .await?
+                                    }
+                                    None => None,
+                                };
+                                let reference_iterations =
+                                    reference_iterations.iter().collect::<Vec<_>>();
+                                let reference_wh = iteration_energy(
+                                    &config,
+                                    &reference_iterations,
+                                    reference_baseline.as_ref(),
+                                )
+                                .iter()
+                                .filter(|e| reported(e))
+                                .map(|e| e.energy_wh())
+                                .sum::<f64>()
+                                    / reference_iterations.len() as f64
+                                    * pue;
+                                let energy_wh = cpu_wh + memory_wh;
+                                println!(
+                                    "\tvs {} (run {}): {:+.4} Wh per iteration ({:+.1}%)",
+                                    reference.name,
+                                    reference.run_id,
+                                    energy_wh - reference_wh,
+                                    if reference_wh > 0.0 {
+                                        (energy_wh - reference_wh) / reference_wh * 100.0
+                                    } else {
+                                        0.0
+                                    }
+                                );
+                            }
+                        }
+
+                        // which processes dominated, like the UI's breakdown of a run
+                        let grid_intensity = config.grid_intensity();
+                        for share in model::share_by_process(&energy).iter() {
+                            let wh = per_iteration(share.energy_wh) * pue;
+                            println!(
+                                "\t\t{:<24}{:>6.1}%{:>12.4} Wh{:>12.4} gCO2e",
+                                share.process_name,
+                                share.percentage,
+                                wh,
+                                wh / 1000.0 * grid_intensity
+                            );
+                        }
+                        if config.overhead.is_some() {
+                            let overhead_wh = per_iteration(
+                                energy
+                                    .iter()
+                                    .chain(overhead_energy.iter())
+                                    .filter(|e| e.process_name == cardamon::SELF_PROCESS_NAME)
+                                    .map(|e| e.energy_wh())
+                                    .sum(),
+                            ) * pue;
+                            let total_wh = cpu_wh
+                                + memory_wh
+                                + if subtract_overhead { overhead_wh } else { 0.0 };
+                            println!(
+                                "\tCardamon overhead: {:.1}% of CPU, {:.1}% of energy ({overhead_wh:.4} Wh per iteration, {})",
+                                run_dataset.cpu_share(cardamon::SELF_PROCESS_NAME),
+                                if total_wh > 0.0 {
+                                    overhead_wh / total_wh * 100.0
+                                } else {
+                                    0.0
+                                },
+                                if subtract_overhead {
+                                    "subtracted"
+                                } else {
+                                    "included above"
+                                }
+                            );
+                        }
+                        if !load_energy.is_empty() {
+                            let load_wh =
+                                per_iteration(load_energy.iter().map(|e| e.energy_wh()).sum());
+                            println!(
+                                "\tLoad generator: {:.4} Wh per iteration (not included above)",
+                                load_wh * pue
+                            );
+                        }
+
+                        // show the model next to the measurement so it can be validated, the
+                        // measurement only covers local processes and includes idle power
+                        if let (true, Some(cpu)) = (measured, &config.cpu) {
+                            let modelled_wh = per_iteration(
+                                iterations
+                                    .iter()
+                                    .flat_map(|it| {
+                                        model::rab_model(
+                                            it,
+                                            cpu,
+                                            cpu_for,
+                                            config.memory.as_ref(),
+                                            None,
+                                        )
+                                    })
+                                    .filter(|e| {
+                                        config.cpu_for(&e.process_id, &e.process_name).is_none()
+                                            && e.role(&config) == Role::Sut
+                                    })
+                                    .map(|e| e.energy_wh())
+                                    .sum(),
+                            ) * pue;
+                            let measured_wh = cpu_wh + memory_wh;
+                            println!(
+                                "\tModelled: {modelled_wh:.4} Wh per iteration ({:+.1}% of measured)",
+                                if measured_wh > 0.0 {
+                                    (modelled_wh - measured_wh) / measured_wh * 100.0
+                                } else {
+                                    0.0
+                                }
+                            );
+                        }
+                    }
+
+                    // manufacturing and usage impact of the server over the whole run
+                    if let Some(run_impact) = data_access_service
+                        .run_impact_dao()
+                        .fetch(run_dataset.run_id())
+                        .await?
+                    {
+                        println!(
+                            "\tLifecycle: {:.4} kgCO2e manufacturing, {:.4} kgCO2e usage over {:.2} hours",
+                            run_impact.manufacture_gwp,
+                            run_impact.use_gwp,
+                            run_impact.duration_hours
+                        );
+                    }
+
+                    // the machine the run executed on
+                    if let Some(run_context) = data_access_service
+                        .run_context_dao()
+                        .fetch(run_dataset.run_id())
+                        .await?
+                    {
+                        println!("\tContext: {}", run_context.describe());
+                    }
+                    for process_info in data_access_service
+                        .process_info_dao()
+                        .fetch(run_dataset.run_id())
+                        .await?
+                    {
+                        println!(
+                            "\tContainer {}: {}",
+                            process_info.process_name,
+                            process_info.describe()
+                        );
+                    }
+
+                    // how the grid generated the electricity while the run executed
+                    let energy_mix = data_access_service
+                        .energy_mix_dao()
+                        .fetch(run_dataset.run_id())
+                        .await?;
+                    if !energy_mix.is_empty() {
+                        println!("\tEnergy mix: {}", energy_mix::describe(&energy_mix));
+                    }
+
+                    for event in run_dataset.process_events() {
+                        println!(
+                            "\t{} {} at {}",
+                            event.process_name, event.event, event.timestamp
+                        );
+                    }
+                }
+            }
+        }
+
+        Commands::Watch {
+            name,
+            path,
+            debounce,
+        } => {
+            let data_access_service = open_db(&database, &project).await?;
+            let config_path = match &args.file {
+                Some(path) => Path::new(path),
+                None => Path::new("./cardamon.toml"),
+            };
+
+            let mut watcher = watch::SourceWatcher::new(&path)?;
+            let mut previous_wh = HashMap::new();
+            loop {
+                // the config is read each time so changes to it are picked up too
+                let config =
+                    config::Config::from_path_with_profile(config_path, args.profile.as_deref())?;
+                let execution_plan = config.create_execution_plan(&name)?;
+                match run(execution_plan, data_access_service.as_ref()).await {
+                    Ok(observation_dataset) => {
+                        for scenario_dataset in observation_dataset.by_scenario().iter() {
+                            let run_datasets = scenario_dataset.by_run();
+                            let Some(run_dataset) =
+                                run_datasets.iter().max_by_key(|run| run.start_time())
+                            else {
+                                continue;
+                            };
+
+                            let iterations = run_dataset.by_iterations();
+                            let energy_wh = EnergyByRole::new(
+                                &config,
+                                iteration_energy(&config, iterations, None),
+                            )
+                            .without_overhead()
+                            .sut_wh()
+                                / iterations.len().max(1) as f64;
+
+                            let scenario_name = scenario_dataset.scenario_name().to_string();
+                            match previous_wh.insert(scenario_name.clone(), energy_wh) {
+                                Some(previous_wh) if previous_wh > 0.0 => println!(
+                                    "{scenario_name}: {energy_wh:.4} Wh per iteration ({:+.4} Wh, {:+.1}%)",
+                                    energy_wh - previous_wh,
+                                    (energy_wh - previous_wh) / previous_wh * 100.0
+                                ),
+                                _ => println!("{scenario_name}: {energy_wh:.4} Wh per iteration"),
+                            }
+                        }
+                    }
+
+                    // a broken build shouldn't stop the watch, the next change may fix it
+                    Err(err) => tracing::error!("Run failed: {err:#}"),
                 }
+
+                println!("Waiting for changes in {}", path.display());
+                watcher.clear();
+                let Some(changed) = watcher.changed(debounce).await else {
+                    break;
+                };
+                println!("{} files changed, re-running {name}", changed.len());
             }
         }
+
+        Commands::Live {
+            name,
+            processes,
+            duration,
+            samples,
+        } => {
+            let data_access_service = open_db(&database, &project).await?;
+
+            let processes_to_observe = processes.into_processes();
+            if processes_to_observe.is_empty() {
+                anyhow::bail!("Nothing to observe, pass some pids, containers, units or cgroups");
+            }
+
+            // stop monitoring on ctrl-c
+            let token = CancellationToken::new();
+            let ctrl_c_token = token.clone();
+            tokio::spawn(async move {
+                if tokio::signal::ctrl_c().await.is_ok() {
+                    ctrl_c_token.cancel();
+                }
+            });
+
+            // alert rules come from the config file, if there is one
+            let config = match &args.file {
+                Some(path) => Some(config::Config::from_path_with_profile(
+                    Path::new(path),
+                    args.profile.as_deref(),
+                )?),
+                None if Path::new("./cardamon.toml").exists() => {
+                    Some(config::Config::from_path_with_profile(
+                        Path::new("./cardamon.toml"),
+                        args.profile.as_deref(),
+                    )?)
+                }
+                None => None,
+            };
+            let alerts = match &config {
+                Some(config) if !config.alerts.is_empty() => {
+                    Some(AlertMonitor::new(&config.alerts, config.cpu.as_ref())?)
+                }
+                _ => None,
+            };
+
+            let stop_condition = LiveStopCondition { duration, samples };
+            let observation_dataset = run_live(
+                &name,
+                &processes_to_observe,
+                stop_condition,
+                token,
+                alerts,
+                config
+                    .as_ref()
+                    .and_then(|config| config.notifications.as_ref()),
+                data_access_service.as_ref(),
+            )
+            .await?;
+
+            for scenario_dataset in observation_dataset.by_scenario().iter() {
+                for run_dataset in scenario_dataset.by_run().iter() {
+                    println!("Run: {:?}", run_dataset.run_id());
+                    for process_metrics in run_dataset.averaged().iter() {
+                        println!("\t{:?}", process_metrics);
+                    }
+                }
+            }
+        }
+
+        Commands::Agent {
+            server_url,
+            run_id,
+            processes,
+            flush_interval,
+            buffer_capacity,
+        } => {
+            let processes_to_observe = processes.into_processes();
+
+            // stop the agent on ctrl-c
+            let token = CancellationToken::new();
+            let ctrl_c_token = token.clone();
+            tokio::spawn(async move {
+                if tokio::signal::ctrl_c().await.is_ok() {
+                    ctrl_c_token.cancel();
+                }
+            });
+
+            agent::run_agent(
+                &run_id,
+                &processes_to_observe,
+                &server_url,
+                Duration::from_secs(flush_interval),
+                buffer_capacity,
+                token,
+            )
+            .await?;
+        }
+
+        Commands::Baseline {
+            command: Some(command),
+            ..
+        } => {
+            let data_access_service = open_db(&database, &project).await?;
+            let run_dao = data_access_service.run_dao();
+
+            match command {
+                BaselineCommands::Set { run_id, name } => {
+                    let name = name.unwrap_or_else(|| run_id.clone());
+                    run_dao.set_reference(&run_id, &name).await?;
+                    println!(
+                        "Runs of project {project} are compared against {name} (run {run_id})"
+                    );
+                }
+
+                BaselineCommands::Unset => {
+                    run_dao.clear_reference().await?;
+                    println!("Runs of project {project} are no longer compared against a run");
+                }
+
+                BaselineCommands::Show => match run_dao.fetch_reference().await? {
+                    Some(reference) => println!("{} (run {})", reference.name, reference.run_id),
+                    None => println!("Runs of project {project} aren't compared against a run"),
+                },
+            }
+        }
+
+        Commands::Baseline {
+            duration,
+            command: None,
+        } => {
+            let data_access_service = open_db(&database, &project).await?;
+
+            println!("Measuring idle system for {duration} seconds, leave the machine idle...");
+            let baseline = baseline::measure(Duration::from_secs(duration)).await?;
+            data_access_service
+                .baseline_dao()
+                .persist(&baseline)
+                .await?;
+
+            println!(
+                "Baseline {}: {:.2}% CPU while idle",
+                baseline.id, baseline.cpu_usage
+            );
+            println!("Use it with `card run <name> --baseline {}`", baseline.id);
+        }
+
+        Commands::Daemon { filter } => {
+            let data_access_service = open_db(&database, &project).await?;
+
+            let path = match &args.file {
+                Some(path) => Path::new(path),
+                None => Path::new("./cardamon.toml"),
+            };
+            let config = config::Config::from_path_with_profile(path, args.profile.as_deref())?;
+            let email = config
+                .email
+                .as_ref()
+                .context("Daemon mode requires an [email] section in the config file")?;
+            let scenario_names = config
+                .scenarios
+                .iter()
+                .map(|scenario| scenario.name.as_str())
+                .collect::<Vec<_>>();
+
+            // stop the daemon on ctrl-c
+            let token = CancellationToken::new();
+            let ctrl_c_token = token.clone();
+            tokio::spawn(async move {
+                if tokio::signal::ctrl_c().await.is_ok() {
+                    ctrl_c_token.cancel();
+                }
+            });
+
+            report::keep_reporting(
+                email,
+                scenario_names,
+                &filter.into_filter(),
+                data_access_service.as_ref(),
+                token,
+            )
+            .await?;
+        }
+
+        Commands::Import { scenario, file } => {
+            let data_access_service = open_db(&database, &project).await?;
+
+            let records = import::read_records(Path::new(&file))?;
+            let run_id = import::import(&scenario, &records, data_access_service.as_ref()).await?;
+            println!(
+                "Imported {} records into run {run_id} of scenario {scenario}",
+                records.len()
+            );
+        }
+
+        Commands::Curve {
+            scenarios,
+            previous_runs,
+            csv,
+        } => {
+            let data_access_service = open_db(&database, &project).await?;
+
+            let path = match &args.file {
+                Some(path) => Path::new(path),
+                None => Path::new("./cardamon.toml"),
+            };
+            let config = config::Config::from_path_with_profile(path, args.profile.as_deref())?;
+
+            // scenarios default to every scenario with a load parameter
+            let scenario_names = match scenarios {
+                Some(scenarios) => scenarios,
+                None => config
+                    .scenarios
+                    .iter()
+                    .filter(|scenario| scenario.parameter.is_some())
+                    .map(|scenario| scenario.name.clone())
+                    .collect(),
+            };
+            let parameter_for = |scenario_name: &str| {
+                config
+                    .scenarios
+                    .iter()
+                    .find(|scenario| scenario.name == scenario_name)
+                    .and_then(|scenario| scenario.parameter)
+            };
+
+            let mut observation_datasets = data_access_service.stream_observation_dataset(
+                scenario_names.iter().map(String::as_str).collect(),
+                previous_runs,
+            );
+
+            // average power of each run of each scenario, fetched a run at a time
+            let mut points = vec![];
+            while let Some(observation_dataset) = observation_datasets.try_next().await? {
+                for scenario_dataset in observation_dataset.by_scenario().iter() {
+                    let parameter =
+                        parameter_for(scenario_dataset.scenario_name()).context(format!(
+                            "Scenario {} has no parameter",
+                            scenario_dataset.scenario_name()
+                        ))?;
+
+                    for run_dataset in scenario_dataset.by_run().iter() {
+                        let iterations = run_dataset.by_iterations();
+                        let energy_wh =
+                            EnergyByRole::new(&config, iteration_energy(&config, iterations, None))
+                                .without_overhead()
+                                .sut_wh();
+                        let hours = iterations
+                            .iter()
+                            .map(|it| {
+                                let it = it.scenario_iteration();
+                                (it.stop_time - it.start_time) as f64 / 3_600_000.0
+                            })
+                            .sum::<f64>();
+
+                        if hours > 0.0 {
+                            points.push((
+                                scenario_dataset.scenario_name().to_string(),
+                                run_dataset.run_id().to_string(),
+                                parameter,
+                                energy_wh / hours,
+                            ));
+                        }
+                    }
+                }
+            }
+            if config.cpu.is_none() && points.iter().all(|point| point.3 == 0.0) {
+                anyhow::bail!("Plotting power against load needs a [cpu] section or a power meter");
+            }
+            points.sort_by(|a, b| a.2.total_cmp(&b.2));
+
+            let xs = points.iter().map(|point| point.2).collect::<Vec<_>>();
+            let ys = points.iter().map(|point| point.3).collect::<Vec<_>>();
+            let fit = CurveFit::new(&xs, &ys);
+
+            println!("Parameter\tPower (W)\tFitted (W)\tScenario\tRun");
+            for (scenario_name, run_id, parameter, power) in points.iter() {
+                let fitted = fit.as_ref().map(|fit| fit.predict(*parameter));
+                println!(
+                    "{parameter}\t\t{power:.2}\t\t{}\t\t{scenario_name}\t{run_id}",
+                    fitted.map_or("-".to_string(), |fitted| format!("{fitted:.2}"))
+                );
+            }
+            match &fit {
+                Some(fit) => {
+                    let [a, b, c] = fit.coefficients;
+                    println!(
+                        "Fit: power = {a:.4} + {b:.4}x + {c:.6}x² W (R² = {:.3})",
+                        fit.r_squared
+                    );
+                }
+                None => println!("Fitting a curve needs runs at two or more parameter values"),
+            }
+
+            if let Some(csv) = csv {
+                let mut out = String::from("scenario,run_id,parameter,power_w,fitted_w\n");
+                for (scenario_name, run_id, parameter, power) in points.iter() {
+                    let fitted = fit
+                        .as_ref()
+                        .map_or(String::new(), |fit| fit.predict(*parameter).to_string());
+                    out.push_str(&format!(
+                        "{scenario_name},{run_id},{parameter},{power},{fitted}\n"
+                    ));
+                }
+                std::fs::write(&csv, out).context(format!("Unable to write {csv}"))?;
+                println!("Wrote {csv}");
+            }
+        }
+
+        Commands::Completions { shell } => {
+            clap_complete::generate(shell, &mut Cli::command(), BIN_NAME, &mut std::io::stdout());
+        }
+
+        Commands::Man { out_dir } => {
+            let command = Cli::command().name(BIN_NAME);
+            match out_dir {
+                Some(out_dir) => {
+                    std::fs::create_dir_all(&out_dir)?;
+                    clap_mangen::generate_to(command, &out_dir)?;
+                    println!("Wrote manual pages to {}", out_dir.display());
+                }
+                None => clap_mangen::Man::new(command).render(&mut std::io::stdout())?,
+            }
+        }
+
+        Commands::Clean => {
+            let lock_path = Path::new(orphans::LOCK_FILE);
+            let Some(lock) = RunLock::read(lock_path)? else {
+                println!("Nothing to clean up");
+                return Ok(());
+            };
+            if !lock.is_stale() {
+                return Err(anyhow::anyhow!(
+                    "Run {} is still in progress (cardamon pid {})",
+                    lock.run_id,
+                    lock.owner.pid
+                ));
+            }
+
+            let leftovers = lock.leftovers().await;
+            let stopped = leftovers.stop().await;
+            println!(
+                "Stopped {stopped} of {} processes left running by run {}",
+                leftovers.len(),
+                lock.run_id
+            );
+            if stopped == leftovers.len() {
+                RunLock::remove(lock_path)?;
+            }
+        }
+
+        Commands::Cancel { run_id } => {
+            let lock_path = Path::new(orphans::LOCK_FILE);
+            match RunLock::read(lock_path)? {
+                Some(lock) if lock.run_id == run_id && !lock.is_stale() => {
+                    lock.interrupt_owner()?;
+                    println!("Cancelling run {run_id} (cardamon pid {})", lock.owner.pid);
+                }
+                _ => {
+                    return Err(anyhow::anyhow!(
+                        "Run {run_id} isn't in progress in this directory"
+                    ))
+                }
+            }
+        }
+
+        Commands::Resume { run_id } => {
+            let data_access_service = open_db(&database, &project).await?;
+            let recorded = data_access_service
+                .run_dao()
+                .fetch(&run_id)
+                .await?
+                .context(format!("Unable to find run {run_id}"))?;
+            let name = recorded.observation.as_deref().context(format!(
+                "Run {run_id} doesn't record what was run, so it can't be resumed"
+            ))?;
+
+            let path = match &args.file {
+                Some(path) => Path::new(path),
+                None => Path::new("./cardamon.toml"),
+            };
+            let mut config = config::Config::from_path_with_profile(path, args.profile.as_deref())?;
+            if !config.can_run(name) {
+                return Err(anyhow::anyhow!(
+                    "Run {run_id} ran {name}, which isn't in the config. Runs of benchmarks or \
+                     tests discovered with --cargo-bench or --pytest can't be resumed"
+                ));
+            }
+            if let Some(iterations) = recorded.iterations {
+                config.override_iterations(u32::try_from(iterations)?)?;
+            }
+            // scenarios filtered out of the run stay out of it
+            if let Some(scenarios) = recorded.scenario_names() {
+                config.filter_scenarios(scenarios, vec![])?;
+            }
+            let mut execution_plan = config.create_execution_plan(name)?;
+
+            // iterations cut short by a cancellation are run again
+            let scenario_names = execution_plan
+                .scenario_names()
+                .into_iter()
+                .map(String::from)
+                .collect::<Vec<_>>();
+            let mut completed = HashSet::new();
+            for scenario_name in scenario_names.iter() {
+                let iterations = data_access_service
+                    .scenario_iteration_dao()
+                    .fetch_run(scenario_name, &run_id)
+                    .await?;
+                for iteration in iterations {
+                    if iteration.status != STATUS_CANCELLED {
+                        completed
+                            .insert((iteration.scenario_name, u32::try_from(iteration.iteration)?));
+                    }
+                }
+            }
+
+            if let Some(baseline_id) = &recorded.baseline_id {
+                execution_plan.use_baseline(baseline_id);
+            }
+            execution_plan.resume(&run_id, &completed);
+            if execution_plan.scenarios_to_execute.is_empty() {
+                println!("Run {run_id} has already completed");
+                return Ok(());
+            }
+            println!(
+                "Resuming run {run_id}, {} scenario iterations are left to run",
+                execution_plan.scenarios_to_execute.len()
+            );
+
+            warn_about_leftovers().await?;
+            let interrupt = cancel_on_ctrl_c();
+            let resumed = run(execution_plan, data_access_service.as_ref()).await;
+            interrupt.abort();
+            resumed?;
+            println!("Run {run_id} has completed, see `card stats` for its results");
+        }
+
+        Commands::Logs {
+            run,
+            process,
+            stderr,
+        } => {
+            let stream = if stderr {
+                Stream::Stderr
+            } else {
+                Stream::Stdout
+            };
+            print!(
+                "{}",
+                logs::read(Path::new(logs::LOG_DIR), &run, &process, stream)?
+            );
+        }
+
+        Commands::Stats {
+            scenarios,
+            previous_runs,
+            regions,
+            aggregation,
+            filter,
+        } => {
+            let data_access_service = open_db(&database, &project).await?;
+
+            let path = match &args.file {
+                Some(path) => Path::new(path),
+                None => Path::new("./cardamon.toml"),
+            };
+            let config = config::Config::from_path_with_profile(path, args.profile.as_deref())?;
+
+            let scenario_names = scenarios.unwrap_or_else(|| {
+                config
+                    .scenarios
+                    .iter()
+                    .map(|scenario| scenario.name.clone())
+                    .collect()
+            });
+            let mut observation_datasets = data_access_service.stream_observation_dataset(
+                scenario_names.iter().map(String::as_str).collect(),
+                previous_runs,
+            );
+
+            // energy used by the system under test per iteration of each run
+            let filter = filter.into_filter();
+            let mut rows = vec![];
+            while let Some(observation_dataset) = observation_datasets.try_next().await? {
+                let observation_dataset = observation_dataset.filter_processes(&filter);
+                for scenario_dataset in observation_dataset.by_scenario().iter() {
+                    for run_dataset in scenario_dataset.by_run().iter() {
+                        let iteration_energy = run_dataset
+                            .by_iterations()
+                            .iter()
+                            .map(|it| model::sut_energy_wh(&config, &[*it]))
+                            .collect::<Vec<_>>();
+
+                        rows.push((
+                            scenario_dataset.scenario_name().to_string(),
+                            run_dataset.run_id().to_string(),
+                            run_dataset.start_time(),
+                            aggregation.aggregate(&iteration_energy).unwrap_or(0.0),
+                        ));
+                    }
+                }
+            }
+            if config.cpu.is_none() && rows.iter().all(|row| row.3 == 0.0) {
+                anyhow::bail!("Working out energy needs a [cpu] section or a power meter");
+            }
+            rows.sort_by(|a, b| a.0.cmp(&b.0).then(a.2.cmp(&b.2)));
+
+            // what if the same energy had been used on another grid
+            print!("{:<24}{:<40}{:>16}", "Scenario", "Run", "Wh/iteration");
+            for region in regions.iter() {
+                print!("{:>16}", format!("{} gCO2e", region.name));
+            }
+            println!();
+            for (scenario_name, run_id, _, energy_wh) in rows.iter() {
+                print!("{scenario_name:<24}{run_id:<40}{energy_wh:>16.4}");
+                for region in regions.iter() {
+                    print!("{:>16.4}", region.emissions_g(*energy_wh));
+                }
+                println!();
+            }
+
+            // a line through the runs of each scenario, oldest first
+            for scenario_name in scenario_names.iter() {
+                let energy = rows
+                    .iter()
+                    .filter(|row| row.0 == *scenario_name)
+                    .map(|row| row.3)
+                    .collect::<Vec<_>>();
+                if let Some(trend) = Trend::new(&energy) {
+                    println!(
+                        "Trend {scenario_name}: {:+.4} Wh/iteration per run (R² = {:.3}) over {} runs",
+                        trend.slope, trend.r_squared, trend.runs
+                    );
+                }
+            }
+        }
+
+        Commands::Db { command } => match command {
+            DbCommands::Export { file } => {
+                let snapshot = match &database.url {
+                    Some(url) => {
+                        let pool = data_access::connect_mysql(url, database.pool_size).await?;
+                        snapshot::export_mysql(&pool).await?
+                    }
+                    None => snapshot::export(&create_db(&database).await?).await?,
+                };
+                snapshot.write(Path::new(&file))?;
+                println!(
+                    "Exported {} runs and {} baselines to {file}",
+                    snapshot.runs.len(),
+                    snapshot.baselines.len()
+                );
+            }
+
+            DbCommands::Import { file } => {
+                let snapshot = snapshot::Snapshot::read(Path::new(&file))?;
+                let imported = match &database.url {
+                    Some(url) => {
+                        let pool = data_access::connect_mysql(url, database.pool_size).await?;
+                        snapshot::import_mysql(&pool, &snapshot).await?
+                    }
+                    None => snapshot::import(&create_db(&database).await?, &snapshot).await?,
+                };
+                println!("Imported {imported} runs from {file}");
+            }
+        },
+
+        Commands::Runs { command } => {
+            let data_access_service = open_db(&database, &project).await?;
+            let run_dao = data_access_service.run_dao();
+
+            match command {
+                RunsCommands::Delete { id } => {
+                    run_dao.delete(&id).await?;
+                    println!("Deleted run {id}");
+                }
+
+                RunsCommands::Archive { id } => {
+                    run_dao.set_archived(&id, true).await?;
+                    println!("Archived run {id}");
+                }
+
+                RunsCommands::Unarchive { id } => {
+                    run_dao.set_archived(&id, false).await?;
+                    println!("Restored run {id}");
+                }
+
+                RunsCommands::Annotate { id, note } => {
+                    run_dao.set_note(&id, note.as_deref()).await?;
+                    println!("Updated note of run {id}");
+                }
+            }
+        }
+
+        Commands::Diff {
+            scenario,
+            previous_runs,
+            filter,
+        } => {
+            let data_access_service = open_db(&database, &project).await?;
+
+            let path = match &args.file {
+                Some(path) => Path::new(path),
+                None => Path::new("./cardamon.toml"),
+            };
+            let config = config::Config::from_path_with_profile(path, args.profile.as_deref())?;
+
+            // compare the latest run of the scenario against the runs before it
+            let observation_dataset = data_access_service
+                .fetch_observation_dataset(vec![&scenario], previous_runs + 1)
+                .await?
+                .filter_processes(&filter.into_filter());
+            let scenario_datasets = observation_dataset.by_scenario();
+            let scenario_dataset = scenario_datasets
+                .first()
+                .context(format!("Unable to find any runs of scenario {scenario}"))?;
+            let mut run_datasets = scenario_dataset.by_run();
+            run_datasets.sort_by_key(|run_dataset| run_dataset.start_time());
+
+            let (candidate, baseline) = match run_datasets.split_last() {
+                Some((candidate, baseline)) if !baseline.is_empty() => (candidate, baseline),
+                _ => anyhow::bail!("Scenario {scenario} needs at least two runs to compare"),
+            };
+            // energy used by the system under test in each iteration, leaving out throttled
+            // iterations as they would skew the comparison
+            let iteration_energy = |run_datasets: &[RunDataset]| {
+                run_datasets
+                    .iter()
+                    .flat_map(|run_dataset| {
+                        let throttled = run_dataset.throttled(None);
+                        if !throttled.is_empty() {
+                            println!(
+                                "Leaving out {} throttled iterations of run {}",
+                                throttled.len(),
+                                run_dataset.run_id()
+                            );
+                        }
+                        run_dataset
+                            .by_iterations()
+                            .iter()
+                            .filter(move |it| !throttled.iter().any(|t| std::ptr::eq(*t, **it)))
+                    })
+                    .map(|it| {
+                        let scenario_iteration = it.scenario_iteration();
+                        let duration_ms =
+                            scenario_iteration.stop_time - scenario_iteration.start_time;
+                        (model::sut_energy_wh(&config, &[it]), duration_ms)
+                    })
+                    .collect::<Vec<_>>()
+            };
+            let baseline_energy = iteration_energy(baseline);
+            let candidate_energy = iteration_energy(std::slice::from_ref(candidate));
+            if config.cpu.is_none()
+                && baseline_energy
+                    .iter()
+                    .chain(candidate_energy.iter())
+                    .all(|(energy_wh, _)| *energy_wh == 0.0)
+            {
+                anyhow::bail!("Working out energy needs a [cpu] section or a power meter");
+            }
+            let comparison = Comparison::new(
+                &baseline_energy.iter().map(|e| e.0).collect::<Vec<_>>(),
+                &candidate_energy.iter().map(|e| e.0).collect::<Vec<_>>(),
+            );
+            let mean_power = |energy: &[(f64, i64)]| {
+                let duration_ms = energy.iter().map(|e| e.1).sum::<i64>();
+                let energy_wh = energy.iter().map(|e| e.0).sum::<f64>();
+                if duration_ms > 0 {
+                    energy_wh * 3600.0 * 1000.0 / duration_ms as f64
+                } else {
+                    0.0
+                }
+            };
+
+            println!("Scenario: {scenario}");
+            println!("--------------------------------");
+            println!(
+                "Baseline ({} runs): {:.4} Wh/iteration at {:.2} W",
+                baseline.len(),
+                comparison.baseline_mean,
+                mean_power(&baseline_energy)
+            );
+            println!(
+                "Run {}: {:.4} Wh/iteration at {:.2} W",
+                candidate.run_id(),
+                comparison.candidate_mean,
+                mean_power(&candidate_energy)
+            );
+            println!(
+                "Difference: {:+.4} Wh/iteration (95% CI {:+.4} to {:+.4}), p = {:.3}",
+                comparison.delta(),
+                comparison.delta_ci.0,
+                comparison.delta_ci.1,
+                comparison.p_value
+            );
+            if comparison.is_significant() {
+                println!("The difference is statistically significant");
+            } else {
+                println!("The difference is not statistically significant");
+            }
+        }
+
+        Commands::Report {
+            run_id,
+            file,
+            sign,
+            key,
+        } => {
+            let data_access_service = open_db(&database, &project).await?;
+
+            let path = match &args.file {
+                Some(path) => Path::new(path),
+                None => Path::new("./cardamon.toml"),
+            };
+            let config = config::Config::from_path_with_profile(path, args.profile.as_deref())?;
+            let report = serde_json::to_value(
+                run_report(&config, &run_id, data_access_service.as_ref()).await?,
+            )?;
+
+            let writer = std::io::BufWriter::new(
+                std::fs::File::create(&file).context(format!("Unable to create {file}"))?,
+            );
+            if sign {
+                let key_path = key.or_else(signing::default_key_path).context(
+                    "Unable to find the home directory, give the signing key with --key",
+                )?;
+                let signing_key = signing::SigningKey::load_or_create(&key_path)?;
+                serde_json::to_writer_pretty(writer, &signing_key.sign(report)?)?;
+                println!("Wrote report of run {run_id} to {file}");
+                println!(
+                    "Signed with public key {}, publish it so the report can be verified with `{BIN_NAME} verify {file} --public-key <key>`",
+                    signing_key.public_key()
+                );
+            } else {
+                serde_json::to_writer_pretty(writer, &report)?;
+                println!("Wrote report of run {run_id} to {file}");
+            }
+        }
+
+        Commands::Manifest { run_id, file } => {
+            let data_access_service = open_db(&database, &project).await?;
+
+            let path = match &args.file {
+                Some(path) => Path::new(path),
+                None => Path::new("./cardamon.toml"),
+            };
+            let config = config::Config::from_path_with_profile(path, args.profile.as_deref())?;
+            let run = data_access_service
+                .run_dao()
+                .fetch(&run_id)
+                .await?
+                .context(format!("Unable to find run {run_id}"))?;
+            let observation_dataset =
+                fetch_run_dataset(&config, &run_id, data_access_service.as_ref()).await?;
+
+            let description = match &run.observation {
+                Some(observation) => format!("Observation {observation} measured by cardamon"),
+                None => String::from("Measured by cardamon"),
+            };
+            let mut manifest = impact_framework::Manifest::new(&run_id, &description);
+            let pue = config.cloud.as_ref().map(|cloud| cloud.pue).unwrap_or(1.0);
+            for it in observation_dataset.data() {
+                let scenario_iteration = it.scenario_iteration();
+                // processes are grouped by name since their ids change when they're restarted
+                let mut energy_by_process = HashMap::<String, f64>::new();
+                let energy = EnergyByRole::new(&config, iteration_energy(&config, &[it], None))
+                    .without_overhead();
+                for energy in energy.sut.iter() {
+                    *energy_by_process
+                        .entry(energy.process_name.clone())
+                        .or_default() += energy.energy_wh();
+                }
+                for (process_name, energy_wh) in energy_by_process {
+                    manifest.add_input(
+                        &scenario_iteration.scenario_name,
+                        &process_name,
+                        scenario_iteration.iteration,
+                        (scenario_iteration.start_time, scenario_iteration.stop_time),
+                        energy_wh * pue,
+                    );
+                }
+            }
+            if manifest.tree.children.is_empty() {
+                anyhow::bail!(
+                    "No energy to export for run {run_id}, configure the CPU or measure power to model it"
+                );
+            }
+
+            std::fs::write(&file, manifest.to_yaml()?)
+                .context(format!("Unable to write {file}"))?;
+            println!("Wrote Impact Framework manifest of run {run_id} to {file}");
+        }
+
+        Commands::Badge {
+            scenario,
+            file,
+            metric,
+        } => {
+            let data_access_service = open_db(&database, &project).await?;
+
+            let path = match &args.file {
+                Some(path) => Path::new(path),
+                None => Path::new("./cardamon.toml"),
+            };
+            let config = config::Config::from_path_with_profile(path, args.profile.as_deref())?;
+            let badge =
+                badge::scenario_badge(&scenario, metric, &config, data_access_service.as_ref())
+                    .await?;
+
+            std::fs::write(&file, badge.to_svg()).context(format!("Unable to write {file}"))?;
+            println!(
+                "Wrote badge \"{}: {}\" to {file}",
+                badge.label, badge.message
+            );
+        }
+
+        Commands::Verify { file, public_key } => {
+            let reader = std::io::BufReader::new(
+                std::fs::File::open(&file).context(format!("Unable to open {file}"))?,
+            );
+            let signed: signing::SignedReport = serde_json::from_reader(reader)
+                .context(format!("{file} is not a signed cardamon report"))?;
+            signed.verify(public_key.as_deref())?;
+
+            println!(
+                "{file} is unchanged since it was signed with public key {}",
+                signed.signature.public_key
+            );
+            if public_key.is_none() {
+                println!("Check this is the key the report's author published, or pass it with --public-key");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// A run summarised for export, e.g. to attach its energy to a release.
+#[derive(Debug, serde::Serialize)]
+struct RunReport {
+    run_id: String,
+    project: String,
+    observation: Option<String>,
+    note: Option<String>,
+    /// Milliseconds since the unix epoch.
+    start_time: Option<i64>,
+    /// CPU usage of the whole machine before the run started, as a percentage.
+    ambient_load: Option<f64>,
+    context: Option<RunContext>,
+    scenarios: Vec<ScenarioReport>,
+    cardamon_version: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct ScenarioReport {
+    scenario_name: String,
+    iterations: usize,
+    /// Energy used by the system under test, None if it was neither measured nor modelled.
+    energy_wh_per_iteration: Option<f64>,
+    /// Energy of the iterations shared between the endpoints they served, empty if requests
+    /// weren't counted.
+    endpoints: Vec<EndpointReport>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct EndpointReport {
+    endpoint: String,
+    requests: i64,
+    energy_wh_per_request: f64,
+}
+
+/// Every iteration of the config's scenarios in the run, with their metrics.
+async fn fetch_run_dataset(
+    config: &config::Config,
+    run_id: &str,
+    data_access_service: &dyn DataAccessService,
+) -> anyhow::Result<ObservationDataset> {
+    let mut scenario_iterations = vec![];
+    for scenario in config.scenarios.iter() {
+        scenario_iterations.extend(
+            data_access_service
+                .scenario_iteration_dao()
+                .fetch_run(&scenario.name, run_id)
+                .await?,
+        );
+    }
+    Ok(ObservationDataset::new(
+        data_access_service
+            .fetch_metrics(scenario_iterations)
+            .await?,
+    ))
+}
+
+/// Summarises the energy the run's scenarios used, modelled with the config like `stats`.
+async fn run_report(
+    config: &config::Config,
+    run_id: &str,
+    data_access_service: &dyn DataAccessService,
+) -> anyhow::Result<RunReport> {
+    let run = data_access_service
+        .run_dao()
+        .fetch(run_id)
+        .await?
+        .context(format!("Unable to find run {run_id}"))?;
+    let observation_dataset = fetch_run_dataset(config, run_id, data_access_service).await?;
+
+    let endpoint_requests = data_access_service
+        .endpoint_request_dao()
+        .fetch(run_id)
+        .await?;
+
+    let pue = config.cloud.as_ref().map(|cloud| cloud.pue).unwrap_or(1.0);
+    let mut start_times = vec![];
+    let mut scenarios = vec![];
+    for scenario_dataset in observation_dataset.by_scenario().iter() {
+        for run_dataset in scenario_dataset.by_run().iter() {
+            start_times.push(run_dataset.start_time());
+            let iterations = run_dataset.by_iterations();
+            let energy = EnergyByRole::new(config, iteration_energy(config, iterations, None))
+                .without_overhead()
+                .sut;
+
+            let scenario_requests = endpoint_requests
+                .iter()
+                .filter(|r| r.scenario_name == scenario_dataset.scenario_name())
+                .collect::<Vec<_>>();
+            let iteration_wh = iterations
+                .iter()
+                .map(|it| {
+                    (
+                        it.scenario_iteration().iteration,
+                        model::sut_energy_wh(config, &[it]),
+                    )
+                })
+                .collect::<Vec<_>>();
+            let endpoints = model::share_by_endpoint(&iteration_wh, &scenario_requests)
+                .iter()
+                .map(|share| EndpointReport {
+                    endpoint: share.endpoint.clone(),
+                    requests: share.requests,
+                    energy_wh_per_request: share.wh_per_request(),
+                })
+                .collect();
+
+            scenarios.push(ScenarioReport {
+                scenario_name: scenario_dataset.scenario_name().to_string(),
+                iterations: iterations.len(),
+                energy_wh_per_iteration: (!energy.is_empty()).then(|| {
+                    energy.iter().map(|e| e.energy_wh()).sum::<f64>() * pue
+                        / iterations.len().max(1) as f64
+                }),
+                endpoints,
+            });
+        }
+    }
+
+    Ok(RunReport {
+        run_id: run.id,
+        project: run.project_id,
+        observation: run.observation,
+        note: run.note,
+        start_time: start_times.into_iter().min(),
+        ambient_load: run.ambient_load,
+        context: data_access_service.run_context_dao().fetch(run_id).await?,
+        scenarios,
+        cardamon_version: env!("CARGO_PKG_VERSION").to_string(),
+    })
+}
+
+/// Asks which observation or scenario in the config to run.
+fn pick_runnable(config: &config::Config) -> anyhow::Result<String> {
+    if !std::io::stdin().is_terminal() {
+        return Err(anyhow::anyhow!(
+            "Give the name of an observation or scenario to run"
+        ));
+    }
+
+    let runnables = config.runnables();
+    if runnables.is_empty() {
+        return Err(anyhow::anyhow!(
+            "The config has no observations or scenarios"
+        ));
+    }
+    let items = runnables
+        .iter()
+        .map(|runnable| format!("{} - {}", runnable.name, runnable.desc))
+        .collect::<Vec<_>>();
+    let picked = dialoguer::Select::new()
+        .with_prompt("What would you like to run?")
+        .items(&items)
+        .default(0)
+        .interact()?;
+    Ok(runnables[picked].name.to_string())
+}
+
+/// Ctrl-C cancels the run in progress, shutting the application down and keeping what was
+/// measured, a second Ctrl-C stops cardamon straight away.
+fn cancel_on_ctrl_c() -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            println!("Cancelling run, press Ctrl-C again to stop immediately");
+            active_runs::cancel_all();
+            if tokio::signal::ctrl_c().await.is_ok() {
+                std::process::exit(130);
+            }
+        }
+    })
+}
+
+/// Warns if a previous run crashed and left processes running. The lock file is removed if
+/// everything it started has since exited.
+async fn warn_about_leftovers() -> anyhow::Result<()> {
+    let lock_path = Path::new(orphans::LOCK_FILE);
+    let Some(lock) = RunLock::read(lock_path)? else {
+        return Ok(());
+    };
+    if !lock.is_stale() {
+        return Ok(());
     }
 
+    let leftovers = lock.leftovers().await;
+    if leftovers.is_empty() {
+        return RunLock::remove(lock_path);
+    }
+
+    println!(
+        "Run {} didn't finish and left {} processes running ({:?} {:?}) which will be measured \
+         alongside this run. Stop them with `card clean`.",
+        lock.run_id,
+        leftovers.len(),
+        leftovers
+            .pids
+            .iter()
+            .map(|process| process.pid)
+            .collect::<Vec<_>>(),
+        leftovers.containers
+    );
     Ok(())
 }
 
-async fn create_db() -> anyhow::Result<SqlitePool> {
-    let db_url = "sqlite://cardamon.db";
-    if !sqlx::Sqlite::database_exists(db_url).await? {
-        sqlx::Sqlite::create_database(db_url).await?;
+/// Opens the database runs are recorded in, the MySQL database if the config gives a url and the
+/// SQLite file otherwise.
+async fn open_db(
+    database: &config::Database,
+    project: &str,
+) -> anyhow::Result<Box<dyn DataAccessService>> {
+    match &database.url {
+        Some(url) => {
+            let pool = data_access::connect_mysql(url, database.pool_size).await?;
+            Ok(Box::new(
+                MySqlDataAccessService::new(pool).for_project(project),
+            ))
+        }
+        None => {
+            let pool = create_db(database).await?;
+            Ok(Box::new(
+                LocalDataAccessService::new(pool).for_project(project),
+            ))
+        }
     }
+}
 
-    let db = sqlx::sqlite::SqlitePoolOptions::new()
-        .max_connections(4)
+async fn create_db(database: &config::Database) -> anyhow::Result<SqlitePool> {
+    let file = database.file();
+    if let Some(dir) = file.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Unable to create {}", dir.display()))?;
+    }
+
+    let db = database
+        .pool_options()
         .connect_with(
-            sqlx::sqlite::SqliteConnectOptions::new()
-                .filename("cardamon.db")
-                .pragma("journal_mode", "DELETE"), // Disable WAL mode
+            database
+                .connect_options()
+                .filename(&file)
+                .create_if_missing(true),
         )
-        // .connect(db_url) with wal and shm
-        .await?;
+        .await
+        .with_context(|| format!("Unable to open database {}", file.display()))?;
 
     sqlx::migrate!().run(&db).await?;
 
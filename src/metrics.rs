@@ -5,16 +5,24 @@
  */
 
 use crate::data_access;
+use itertools::Itertools;
+
+/// The event recorded when the CPU throttled itself during an iteration.
+pub const THERMAL_THROTTLED: &str = "thermal_throttled";
 
 #[derive(Debug)]
 pub struct MetricsLog {
     log: Vec<CpuMetrics>,
+    power: Vec<PowerMetrics>,
+    events: Vec<ProcessEvent>,
     err: Vec<anyhow::Error>,
 }
 impl MetricsLog {
     pub fn new() -> Self {
         Self {
             log: vec![],
+            power: vec![],
+            events: vec![],
             err: vec![],
         }
     }
@@ -23,18 +31,95 @@ impl MetricsLog {
         self.log.push(metrics);
     }
 
+    pub fn push_power(&mut self, power: PowerMetrics) {
+        self.power.push(power);
+    }
+
+    pub fn push_event(&mut self, event: ProcessEvent) {
+        self.events.push(event);
+    }
+
     pub fn push_error(&mut self, err: anyhow::Error) {
         self.err.push(err);
     }
 
+    /// Moves everything logged in `other` to the end of this log, leaving `other` empty.
+    pub fn append(&mut self, other: &mut MetricsLog) {
+        self.log.append(&mut other.log);
+        self.power.append(&mut other.power);
+        self.events.append(&mut other.events);
+        self.err.append(&mut other.err);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.log.is_empty()
+            && self.power.is_empty()
+            && self.events.is_empty()
+            && self.err.is_empty()
+    }
+
+    /// Summarises what's been logged so far, for showing progress while a scenario runs.
+    pub fn progress(&self) -> Progress {
+        let latest = self
+            .log
+            .iter()
+            .into_group_map_by(|metrics| &metrics.process_id)
+            .into_values()
+            .filter_map(|samples| samples.into_iter().max_by_key(|metrics| metrics.timestamp))
+            .collect::<Vec<_>>();
+
+        // a power meter measures the whole machine, otherwise processes may measure their own
+        let metered = self
+            .power
+            .iter()
+            .max_by_key(|power| power.timestamp)
+            .map(|power| power.power);
+        let process_power = latest
+            .iter()
+            .map(|metrics| metrics.power)
+            .sum::<Option<f64>>()
+            .filter(|_| !latest.is_empty());
+
+        Progress {
+            samples: self.log.len() + self.power.len(),
+            cpu_usage: latest
+                .iter()
+                .map(|metrics| metrics.cpu_usage / metrics.core_count.max(1) as f64)
+                .sum(),
+            power: metered.or(process_power),
+        }
+    }
+
+    /// Removes and returns all the metrics logged so far, leaving events and errors in place.
+    pub fn drain_metrics(&mut self) -> Vec<CpuMetrics> {
+        std::mem::take(&mut self.log)
+    }
+
     pub fn get_metrics(&self) -> &Vec<CpuMetrics> {
         &self.log
     }
 
+    pub fn get_power_metrics(&self) -> &Vec<PowerMetrics> {
+        &self.power
+    }
+
+    pub fn get_events(&self) -> &Vec<ProcessEvent> {
+        &self.events
+    }
+
     pub fn get_errors(&self) -> &Vec<anyhow::Error> {
         &self.err
     }
 
+    /// Names of the observed processes which stopped or were killed while logging.
+    pub fn exited_processes(&self) -> Vec<&str> {
+        self.events
+            .iter()
+            .filter(|event| event.kind != ProcessEventKind::Started)
+            .map(|event| event.process_name.as_str())
+            .collect()
+    }
+
     pub fn has_errors(&self) -> bool {
         !self.err.is_empty()
     }
@@ -45,6 +130,17 @@ impl Default for MetricsLog {
     }
 }
 
+/// What's been logged while a scenario is running.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Progress {
+    pub samples: usize,
+    /// CPU usage of the observed processes in their latest samples, as a percentage of the whole
+    /// CPU.
+    pub cpu_usage: f64,
+    /// Power in watts of the latest measurement, if power is being measured.
+    pub power: Option<f64>,
+}
+
 #[derive(Debug)]
 pub struct CpuMetrics {
     pub process_id: String,
@@ -52,6 +148,21 @@ pub struct CpuMetrics {
     pub cpu_usage: f64,
     pub core_count: i32,
     pub timestamp: i64,
+    /// Average frequency of the CPU in MHz when the sample was taken, if it's known.
+    pub cpu_frequency: Option<i64>,
+    /// Resident memory of the process in bytes when the sample was taken, if it's known.
+    pub memory_usage: Option<i64>,
+    /// Power drawn by the process in watts if it was measured rather than modelled.
+    pub power: Option<f64>,
+    /// Seconds since the logger's previous sample of the process, None if the logger samples at
+    /// a fixed rate of once a second.
+    pub sample_interval: Option<f64>,
+    /// Bytes of memory the container may use, if the process is a container with a known limit.
+    pub memory_limit: Option<i64>,
+    /// CPU quota periods of the container since the previous sample and how many of them it was
+    /// throttled in, if the process is a container.
+    pub cpu_periods: Option<i64>,
+    pub throttled_periods: Option<i64>,
 }
 impl CpuMetrics {
     pub fn into_data_access(&self, run_id: &str) -> data_access::cpu_metrics::CpuMetrics {
@@ -64,5 +175,68 @@ impl CpuMetrics {
             self.core_count as i64,
             self.timestamp,
         )
+        .with_cpu_frequency(self.cpu_frequency)
+        .with_memory_usage(self.memory_usage)
+        .with_power(self.power)
+        .with_sample_interval(self.sample_interval)
+        .with_memory_limit(self.memory_limit)
+        .with_throttling(self.cpu_periods, self.throttled_periods)
+    }
+}
+
+/// Wall power of the whole machine running cardamon.
+#[derive(Debug)]
+pub struct PowerMetrics {
+    pub source: String,
+    pub power: f64,
+    pub timestamp: i64,
+}
+impl PowerMetrics {
+    pub fn into_data_access(&self, run_id: &str) -> data_access::power_metrics::PowerMetrics {
+        data_access::power_metrics::PowerMetrics::new(
+            run_id,
+            &self.source,
+            self.power,
+            self.timestamp,
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProcessEventKind {
+    Started,
+    Stopped,
+    OomKilled,
+    /// The CPU throttled itself because it was too hot.
+    ThermalThrottled,
+    /// The CPU frequency scaling governor changed, e.g. from powersave to performance.
+    GovernorChanged,
+}
+impl ProcessEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProcessEventKind::Started => "started",
+            ProcessEventKind::Stopped => "stopped",
+            ProcessEventKind::OomKilled => "oom_killed",
+            ProcessEventKind::ThermalThrottled => THERMAL_THROTTLED,
+            ProcessEventKind::GovernorChanged => "governor_changed",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ProcessEvent {
+    pub process_name: String,
+    pub kind: ProcessEventKind,
+    pub timestamp: i64,
+}
+impl ProcessEvent {
+    pub fn into_data_access(&self, run_id: &str) -> data_access::process_event::ProcessEvent {
+        data_access::process_event::ProcessEvent::new(
+            run_id,
+            &self.process_name,
+            self.kind.as_str(),
+            self.timestamp,
+        )
     }
 }
@@ -5,18 +5,218 @@
  */
 
 pub mod bare_metal;
+pub mod cadvisor;
+pub mod cgroup;
+pub mod clock;
+pub mod collector;
+pub mod cri;
 pub mod docker;
+pub mod influxdb;
+pub mod power_meter;
+pub mod remote;
+pub mod scaphandre;
 
-use crate::{metrics::MetricsLog, ProcessToObserve};
-use itertools::Itertools;
-use std::sync::{Arc, Mutex};
-use tokio::task::JoinSet;
+use crate::{
+    config::{Cadvisor, PowerMeter, Scaphandre},
+    error::{CardamonError, Result},
+    metrics::{CpuMetrics, MetricsLog, PowerMetrics, ProcessEvent, Progress},
+    ProcessToObserve,
+};
+use cadvisor::CadvisorCollector;
+use clock::{Clock, SystemClock};
+use collector::{CollectorKind, CollectorRegistry, MetricsCollector};
+use influxdb::{InfluxSink, Mirror};
+use power_meter::PowerMeterCollector;
+use scaphandre::ScaphandreCollector;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex, MutexGuard, PoisonError,
+};
+use sysinfo::System;
+use tokio::{task::JoinSet, time::Duration};
 use tokio_util::sync::CancellationToken;
 
+/// Reads the average frequency of the local CPU's cores in MHz, None if the frequency isn't
+/// available (e.g. in some virtual machines).
+fn cpu_frequency(system: &mut System) -> Option<i64> {
+    system.refresh_cpu_frequency();
+    let cpus = system.cpus();
+    if cpus.is_empty() {
+        return None;
+    }
+
+    let frequency = cpus.iter().map(|cpu| cpu.frequency()).sum::<u64>() / cpus.len() as u64;
+    (frequency > 0).then_some(frequency as i64)
+}
+
+/// Locks the shared metrics log. A logger which panicked while holding the lock leaves the log
+/// poisoned but every sample in it is still valid, so the poison is ignored.
+fn lock(metrics_log: &Mutex<MetricsLog>) -> MutexGuard<'_, MetricsLog> {
+    metrics_log.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// How often loggers sample while collecting keeps up, unless set otherwise.
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(1000);
+
+/// The shortest interval loggers can be set to, collecting any faster measures mostly itself.
+const MIN_SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The longest a logger backs off to between samples.
+const MAX_SAMPLE_INTERVAL: Duration = Duration::from_secs(16);
+
+/// Milliseconds between samples for every logger in the process, see [`set_sample_interval`].
+static SAMPLE_INTERVAL_MS: AtomicU64 = AtomicU64::new(SAMPLE_INTERVAL.as_millis() as u64);
+
+/// Sets how often every logger samples while collecting keeps up, e.g. more often in CI where
+/// fidelity matters more than overhead. The interval is kept between 100ms and 16s.
+pub fn set_sample_interval(interval: Duration) {
+    let interval = interval.clamp(MIN_SAMPLE_INTERVAL, MAX_SAMPLE_INTERVAL);
+    SAMPLE_INTERVAL_MS.store(interval.as_millis() as u64, Ordering::Relaxed);
+}
+
+/// How often loggers sample while collecting keeps up, a second unless set otherwise.
+pub fn sample_interval() -> Duration {
+    Duration::from_millis(SAMPLE_INTERVAL_MS.load(Ordering::Relaxed))
+}
+
+/// Paces a logger's collection cycles. When a cycle takes longer than the logger's budget, e.g.
+/// because many containers are observed, the interval doubles so collecting doesn't become a
+/// significant load itself. It halves back towards the sample interval when cycles are quick
+/// again.
+#[derive(Debug)]
+pub struct SamplePacer {
+    budget: Duration,
+    interval: Duration,
+    last_cycle: Option<std::time::Instant>,
+    clock: Arc<dyn Clock>,
+}
+impl SamplePacer {
+    pub fn new(budget: Duration) -> Self {
+        Self {
+            budget,
+            interval: sample_interval(),
+            last_cycle: None,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Times cycles with the given clock rather than the system clock.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Marks the start of a collection cycle.
+    ///
+    /// # Returns
+    ///
+    /// The seconds since the previous cycle started, i.e. the time covered by the samples taken
+    /// in this cycle. The first cycle is assumed to cover the current interval.
+    pub fn start_cycle(&mut self) -> f64 {
+        let now = self.clock.now();
+        let covered = self
+            .last_cycle
+            .map(|last_cycle| now.duration_since(last_cycle))
+            .unwrap_or(self.interval);
+        self.last_cycle = Some(now);
+        covered.as_secs_f64()
+    }
+
+    /// Adjusts the interval given how long a collection cycle took.
+    ///
+    /// # Returns
+    ///
+    /// How long to wait before starting the next cycle.
+    pub fn finish_cycle(&mut self, latency: Duration) -> Duration {
+        let interval = if latency > self.budget {
+            (self.interval * 2).min(MAX_SAMPLE_INTERVAL)
+        } else if latency < self.budget / 2 {
+            (self.interval / 2).max(sample_interval())
+        } else {
+            self.interval
+        };
+        if interval != self.interval {
+            tracing::debug!(
+                "Collecting took {latency:?}, sampling every {interval:?} instead of {:?}",
+                self.interval
+            );
+        }
+        self.interval = interval;
+
+        self.interval.saturating_sub(latency)
+    }
+}
+
+/// Collects the samples of a single logger before they're flushed to the shared metrics log.
+/// Anything not yet flushed is flushed when the buffer is dropped, which happens when the logger
+/// is cancelled part way through sampling or panics, so no samples are lost however the logger
+/// ends.
+pub struct LogBuffer {
+    pending: MetricsLog,
+    shared: Arc<Mutex<MetricsLog>>,
+    mirror: Option<Mirror>,
+}
+impl LogBuffer {
+    pub fn new(shared: Arc<Mutex<MetricsLog>>) -> Self {
+        Self {
+            pending: MetricsLog::new(),
+            shared,
+            mirror: None,
+        }
+    }
+
+    /// Also sends samples to InfluxDB as they're flushed.
+    pub fn with_mirror(mut self, mirror: Option<Mirror>) -> Self {
+        self.mirror = mirror;
+        self
+    }
+
+    /// A new buffer flushing to the same shared metrics log, e.g. for work a collector runs in
+    /// the background.
+    pub fn sibling(&self) -> Self {
+        Self::new(self.shared.clone()).with_mirror(self.mirror.clone())
+    }
+
+    pub fn push_metrics(&mut self, metrics: CpuMetrics) {
+        self.pending.push_metrics(metrics);
+    }
+
+    pub fn push_power(&mut self, power: PowerMetrics) {
+        self.pending.push_power(power);
+    }
+
+    pub fn push_event(&mut self, event: ProcessEvent) {
+        self.pending.push_event(event);
+    }
+
+    pub fn push_error(&mut self, err: anyhow::Error) {
+        self.pending.push_error(err);
+    }
+
+    /// Moves everything collected so far to the shared metrics log.
+    pub fn flush(&mut self) {
+        if !self.pending.is_empty() {
+            if let Some(mirror) = &self.mirror {
+                mirror.send(&self.pending);
+            }
+            lock(&self.shared).append(&mut self.pending);
+        }
+    }
+}
+impl Drop for LogBuffer {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
 pub struct StopHandle {
     token: CancellationToken,
     join_set: JoinSet<()>,
     shared_metrics_log: Arc<Mutex<MetricsLog>>,
+    influx_sink: Option<InfluxSink>,
+    /// Read from for processes observed after logging started, as for those it started with.
+    scaphandre: Option<Scaphandre>,
+    cadvisor: Option<Cadvisor>,
 }
 impl StopHandle {
     fn new(
@@ -28,33 +228,97 @@ impl StopHandle {
             token,
             join_set,
             shared_metrics_log,
+            influx_sink: None,
+            scaphandre: None,
+            cadvisor: None,
         }
     }
 
-    pub async fn stop(mut self) -> anyhow::Result<MetricsLog> {
-        // cancel loggers
-        self.token.cancel();
-        loop {
-            if self.join_set.join_next().await.is_none() {
-                break;
-            }
+    /// Starts observing more processes, e.g. a command started once logging had begun. They're
+    /// sampled by the same kinds of collector and mirrored to the same sinks as the processes
+    /// logging started with.
+    pub fn observe(&mut self, processes: &[ProcessToObserve]) -> Result<()> {
+        let collectors =
+            collectors_for(processes, self.scaphandre.as_ref(), self.cadvisor.as_ref())?;
+        for collector in collectors {
+            self.spawn(collector);
         }
+        Ok(())
+    }
 
-        // take ownership of metrics log
-        let metrics_log = Arc::try_unwrap(self.shared_metrics_log)
-            .expect("Mutex guarding metrics_log shouldn't have multiple owners!")
-            .into_inner()
-            .expect("Should be able to take ownership of metrics_log");
+    /// Samples with the collector in its own task until the handle is stopped.
+    fn spawn(&mut self, mut collector: Box<dyn MetricsCollector>) {
+        let token = self.token.clone();
+        let mut log = LogBuffer::new(self.shared_metrics_log.clone())
+            .with_mirror(self.influx_sink.as_ref().map(InfluxSink::mirror));
+
+        self.join_set.spawn(async move {
+            tracing::info!("Logging {}", collector.describe());
+            tokio::select! {
+                _ = token.cancelled() => {}
+                _ = collector::keep_collecting(collector.as_mut(), &mut log) => {}
+            }
+            collector.stop(&mut log).await;
+        });
+    }
+
+    /// Takes the metrics collected so far without stopping the loggers. Used when metrics need to
+    /// be shipped while logging continues (e.g. by the agent).
+    pub fn drain_metrics(&self) -> Vec<CpuMetrics> {
+        lock(&self.shared_metrics_log).drain_metrics()
+    }
+
+    /// Summarises what's been collected so far without stopping the loggers.
+    pub fn progress(&self) -> Progress {
+        lock(&self.shared_metrics_log).progress()
+    }
+
+    pub async fn stop(self) -> Result<MetricsLog> {
+        let metrics_log = self.finish().await;
 
         // return error if metrics log contains any errors
         if metrics_log.has_errors() {
-            return Err(anyhow::anyhow!(
-                "Metrics log contains errors, please check trace"
-            ));
+            return Err(CardamonError::MetricsLog(metrics_log.get_errors().len()));
         }
 
         Ok(metrics_log)
     }
+
+    /// Stops the loggers like `stop`, returning what they collected along with any errors they
+    /// had for the caller to handle.
+    pub async fn finish(mut self) -> MetricsLog {
+        // cancel loggers, each flushes its samples as it's dropped
+        self.token.cancel();
+        while let Some(res) = self.join_set.join_next().await {
+            if let Err(err) = res {
+                tracing::error!("Metrics logger failed: {err}");
+            }
+        }
+        if let Some(influx_sink) = self.influx_sink.take() {
+            influx_sink.finish().await;
+        }
+
+        // take ownership of metrics log
+        Arc::try_unwrap(self.shared_metrics_log)
+            .expect("Mutex guarding metrics_log shouldn't have multiple owners!")
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Where the loggers read metrics from besides the observed processes themselves, and where
+/// samples are mirrored to. Nothing is used unless it's given.
+#[derive(Default)]
+pub struct LoggingOptions<'a> {
+    /// Smart plug measuring the wall power of the machine.
+    pub power_meter: Option<&'a PowerMeter>,
+    /// Scaphandre exporter to read the power of local processes and cgroups from instead of
+    /// sampling them directly.
+    pub scaphandre: Option<&'a Scaphandre>,
+    /// cAdvisor to read the metrics of containers from instead of Docker.
+    pub cadvisor: Option<&'a Cadvisor>,
+    /// InfluxDB bucket to mirror samples to as they're collected.
+    pub influx_sink: Option<InfluxSink>,
 }
 
 /// Logs a single scenario run
@@ -62,91 +326,255 @@ impl StopHandle {
 /// # Arguments
 ///
 /// * `processes` - The processes you wish to observe during the scenario run
+/// * `options` - Power meters and exporters to read from and sinks to mirror samples to
 ///
 /// # Returns
 ///
 /// A `Result` containing the metrics log for the given scenario or an `Error` if either
 /// the scenario failed to complete successfully or any of the loggers contained errors.
-pub fn start_logging(processes_to_observe: &[ProcessToObserve]) -> anyhow::Result<StopHandle> {
-    let metrics_log = MetricsLog::new();
-    let metrics_log_mutex = Mutex::new(metrics_log);
-    let shared_metrics_log = Arc::new(metrics_log_mutex);
-
-    // split processes into bare metal & docker processes
-    let (pids, container_names): (Vec<_>, Vec<_>) =
-        processes_to_observe
-            .iter()
-            .partition_map(|proc| match proc {
-                ProcessToObserve::Pid(_, id) => itertools::Either::Left(id),
-                ProcessToObserve::ContainerName(name) => itertools::Either::Right(name.clone()),
-            });
+pub fn start_logging(
+    processes_to_observe: &[ProcessToObserve],
+    options: LoggingOptions<'_>,
+) -> Result<StopHandle> {
+    let mut collectors =
+        collectors_for(processes_to_observe, options.scaphandre, options.cadvisor)?;
+    if let Some(power_meter) = options.power_meter.cloned() {
+        collectors.push(Box::new(PowerMeterCollector::new(power_meter)));
+    }
 
-    // create a new cancellation token
-    let token = CancellationToken::new();
+    let mut stop_handle = start(collectors, options.influx_sink);
+    stop_handle.scaphandre = options.scaphandre.cloned();
+    stop_handle.cadvisor = options.cadvisor.cloned();
+    Ok(stop_handle)
+}
 
-    // start threads to collect metrics
-    let mut join_set = JoinSet::new();
-    if !pids.is_empty() {
-        let token = token.clone();
-        let shared_metrics_log = shared_metrics_log.clone();
+/// The collectors sampling the processes, each reading from scaphandre or cAdvisor in place of
+/// the built in collector if it's given.
+fn collectors_for(
+    processes_to_observe: &[ProcessToObserve],
+    scaphandre: Option<&Scaphandre>,
+    cadvisor: Option<&Cadvisor>,
+) -> Result<Vec<Box<dyn MetricsCollector>>> {
+    let mut collectors: Vec<Box<dyn MetricsCollector>> = vec![];
+    let mut processes_to_observe = processes_to_observe.to_vec();
 
-        join_set.spawn(async move {
-            tracing::info!("Logging PIDs: {:?}", pids);
-            tokio::select! {
-                _ = token.cancelled() => {}
-                _ = bare_metal::keep_logging(
-                        pids,
-                        shared_metrics_log,
-                    ) => {}
-            }
-        });
+    // scaphandre reads local processes and cgroups in place of their usual collectors
+    if let Some(scaphandre) = scaphandre {
+        let (local, others): (Vec<_>, Vec<_>) =
+            processes_to_observe.into_iter().partition(|proc| {
+                matches!(
+                    CollectorKind::of(proc),
+                    CollectorKind::Pid | CollectorKind::Cgroup
+                )
+            });
+        if !local.is_empty() {
+            collectors.push(Box::new(ScaphandreCollector::new(
+                scaphandre.clone(),
+                local.iter().filter_map(ProcessToObserve::pid).collect(),
+                local.iter().filter_map(collector::cgroup_of).collect(),
+            )));
+        }
+        processes_to_observe = others;
     }
 
-    if !container_names.is_empty() {
-        let token = token.clone();
-        let shared_metrics_log = shared_metrics_log.clone();
+    collectors.extend(collector_registry(cadvisor).collectors_for(&processes_to_observe)?);
+    Ok(collectors)
+}
 
-        join_set.spawn(async move {
-            tracing::info!("Logging containers: {:?}", container_names);
-            tokio::select! {
-                _ = token.cancelled() => {}
-                _ = docker::keep_logging(
-                        container_names,
-                        shared_metrics_log,
-                    ) => {}
-            }
+/// The built in collectors, with cAdvisor reading containers in place of docker if it's given.
+fn collector_registry(cadvisor: Option<&Cadvisor>) -> CollectorRegistry {
+    let mut registry = CollectorRegistry::default();
+    if let Some(cadvisor) = cadvisor.cloned() {
+        registry.register(CollectorKind::Container, move |processes| {
+            Box::new(CadvisorCollector::new(
+                cadvisor.clone(),
+                collector::container_names(processes),
+            ))
         });
     }
+    registry
+}
 
-    Ok(StopHandle::new(token, join_set, shared_metrics_log))
+/// Starts each collector in its own task, sampling until the returned handle is stopped. Used
+/// with collectors other than those in the default registry, e.g. from another crate.
+pub fn start_collectors(collectors: Vec<Box<dyn MetricsCollector>>) -> StopHandle {
+    start(collectors, None)
 }
 
-/// Enters an infinite loop logging metrics for each process to the metrics log. This function is
-/// intended to be used to log live environments which do not exit. If it is run on the main thread
-/// it will block.
-///
-/// **WARNING**
-///
-/// This function should only be called from within a task that can execute it on another thread
-/// otherwise it will block the main thread completely.
-///
-/// This function is intended to used in conjunction with other code that will periodically save
-/// and flush the metrics log it writes to.
-///
-/// # Arguments
-///
-/// * `processes` - The processes to observe in the live environment
-/// * `metrics_log` - A log of all observed metrics. Another thread should periodically save and
-/// flush this shared log.
-///
-/// # Returns
-///
-/// This function does not return, it requires that it's thread is cancelled.
-pub async fn log_live(
-    _processes_to_observe: Vec<ProcessToObserve>,
-    _metrics_log: Arc<Mutex<MetricsLog>>,
-) {
-    // TODO: This should do exactly what log_scenario does but it should save the shared_metrics_log
-    // at regular fixed intervals (either space or time)
-    todo!("implement this!")
+fn start(
+    collectors: Vec<Box<dyn MetricsCollector>>,
+    influx_sink: Option<InfluxSink>,
+) -> StopHandle {
+    let shared_metrics_log = Arc::new(Mutex::new(MetricsLog::new()));
+    let mut stop_handle =
+        StopHandle::new(CancellationToken::new(), JoinSet::new(), shared_metrics_log);
+    stop_handle.influx_sink = influx_sink;
+    for collector in collectors.into_iter() {
+        stop_handle.spawn(collector);
+    }
+    stop_handle
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metrics(timestamp: i64) -> CpuMetrics {
+        CpuMetrics {
+            process_id: "1337".to_string(),
+            process_name: "yarn".to_string(),
+            cpu_usage: 50.0,
+            core_count: 4,
+            timestamp,
+            cpu_frequency: None,
+            memory_usage: None,
+            power: None,
+            sample_interval: None,
+            memory_limit: None,
+            cpu_periods: None,
+            throttled_periods: None,
+        }
+    }
+
+    #[test]
+    fn sampling_backs_off_while_collecting_is_slow() {
+        let mut pacer = SamplePacer::new(Duration::from_millis(500));
+
+        // quick cycles sample once a second
+        assert_eq!(
+            pacer.finish_cycle(Duration::from_millis(100)),
+            Duration::from_millis(900)
+        );
+
+        // slow cycles double the interval up to the maximum
+        assert_eq!(
+            pacer.finish_cycle(Duration::from_millis(600)),
+            Duration::from_millis(1400)
+        );
+        assert_eq!(
+            pacer.finish_cycle(Duration::from_millis(600)),
+            Duration::from_millis(3400)
+        );
+        for _ in 0..10 {
+            pacer.finish_cycle(Duration::from_millis(600));
+        }
+        assert_eq!(
+            pacer.finish_cycle(Duration::from_millis(600)),
+            MAX_SAMPLE_INTERVAL - Duration::from_millis(600)
+        );
+
+        // within budget keeps the interval, quick cycles halve it back down
+        assert_eq!(
+            pacer.finish_cycle(Duration::from_millis(400)),
+            MAX_SAMPLE_INTERVAL - Duration::from_millis(400)
+        );
+        for _ in 0..10 {
+            pacer.finish_cycle(Duration::ZERO);
+        }
+        assert_eq!(pacer.finish_cycle(Duration::ZERO), SAMPLE_INTERVAL);
+    }
+
+    #[test]
+    fn cycles_cover_the_time_since_the_previous_cycle() {
+        let clock = clock::ManualClock::new(0);
+        let mut pacer = SamplePacer::new(Duration::from_millis(500)).with_clock(clock.clone());
+
+        // the first cycle covers a whole interval
+        assert_eq!(pacer.start_cycle(), 1.0);
+
+        clock.advance(Duration::from_millis(2500));
+        assert_eq!(pacer.start_cycle(), 2.5);
+        assert_eq!(pacer.start_cycle(), 0.0);
+    }
+
+    #[test]
+    fn cadvisor_reads_containers_when_its_given() -> anyhow::Result<()> {
+        let processes = [ProcessToObserve::ContainerName("db".to_string())];
+        let cadvisor = Cadvisor {
+            url: "http://localhost:8080/metrics".to_string(),
+        };
+
+        let collectors = collector_registry(Some(&cadvisor)).collectors_for(&processes)?;
+        let described = collectors
+            .iter()
+            .map(|collector| collector.describe())
+            .collect::<Vec<_>>();
+        assert_eq!(described, vec![r#"containers ["db"] from cAdvisor"#]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn processes_can_be_observed_once_logging_has_started() -> anyhow::Result<()> {
+        let mut stop_handle = start_logging(&[], LoggingOptions::default())?;
+        let pid = std::process::id();
+        stop_handle.observe(&[ProcessToObserve::Pid(None, pid)])?;
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+
+        let metrics_log = stop_handle.stop().await?;
+        assert!(metrics_log
+            .get_metrics()
+            .iter()
+            .any(|metrics| metrics.process_id == pid.to_string()));
+        Ok(())
+    }
+
+    /// Logs a sample then waits forever without reaching the end of its sampling interval.
+    async fn log_without_flushing(metrics_log: Arc<Mutex<MetricsLog>>) {
+        let mut log = LogBuffer::new(metrics_log);
+        log.push_metrics(metrics(1));
+        std::future::pending::<()>().await;
+    }
+
+    #[tokio::test]
+    async fn samples_are_flushed_when_logging_is_cancelled() -> anyhow::Result<()> {
+        let token = CancellationToken::new();
+        let shared_metrics_log = Arc::new(Mutex::new(MetricsLog::new()));
+        let mut join_set = JoinSet::new();
+        {
+            let token = token.clone();
+            let shared_metrics_log = shared_metrics_log.clone();
+            join_set.spawn(async move {
+                tokio::select! {
+                    _ = token.cancelled() => {}
+                    _ = log_without_flushing(shared_metrics_log) => {}
+                }
+            });
+        }
+        tokio::task::yield_now().await;
+
+        let metrics_log = StopHandle::new(token, join_set, shared_metrics_log)
+            .stop()
+            .await?;
+        assert_eq!(metrics_log.get_metrics().len(), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn samples_are_flushed_when_a_logger_panics() -> anyhow::Result<()> {
+        let shared_metrics_log = Arc::new(Mutex::new(MetricsLog::new()));
+        let mut join_set = JoinSet::new();
+        {
+            let shared_metrics_log = shared_metrics_log.clone();
+            join_set.spawn(async move {
+                let mut log = LogBuffer::new(shared_metrics_log);
+                log.push_metrics(metrics(1));
+                log.push_metrics(metrics(2));
+                panic!("logger failed");
+            });
+        }
+
+        // flushing while unwinding poisons the mutex, the samples should still be returned
+        let metrics_log = StopHandle::new(CancellationToken::new(), join_set, shared_metrics_log)
+            .stop()
+            .await?;
+        let timestamps = metrics_log
+            .get_metrics()
+            .iter()
+            .map(|m| m.timestamp)
+            .collect::<Vec<_>>();
+        assert_eq!(timestamps, vec![1, 2]);
+
+        Ok(())
+    }
 }
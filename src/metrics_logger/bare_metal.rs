@@ -4,59 +4,98 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/.
  */
 
-use crate::metrics::{CpuMetrics, MetricsLog};
-use std::sync::{Arc, Mutex};
-use sysinfo::{Pid, System};
-use tokio::time::Duration;
-
-/// Enters an infinite loop logging metrics for each process to the metrics log. This function is
-/// intended to be called from `metrics_logger::log_scenario` or `metrics_logger::log_live`
-///
-/// **WARNING**
-///
-/// This function should only be called from within a task that can execute it on another thread
-/// otherwise it will block the main thread completely.
-///
-/// # Arguments
-///
-/// * `pids` - The process ids to observe
-/// * `metrics_log` - A log of all observed metrics. Another thread should periodically save and
-/// flush this shared log.
-///
-/// # Returns
-///
-/// This function does not return, it requires that it's thread is cancelled.
-pub async fn keep_logging(pids: Vec<u32>, metrics_log: Arc<Mutex<MetricsLog>>) {
-    let mut system = System::new_all();
-
-    loop {
-        tokio::time::sleep(Duration::from_millis(1000)).await;
-        for pid in pids.iter() {
-            let metrics = get_metrics(&mut system, *pid).await;
-            update_metrics_log(metrics, &metrics_log);
+use super::{collector::MetricsCollector, LogBuffer, SamplePacer};
+use crate::metrics::{CpuMetrics, ProcessEvent, ProcessEventKind};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use sysinfo::{Pid, ProcessStatus, System};
+use tokio::time::{Duration, Instant};
+
+/// How long sampling every process may take before sampling backs off, each sample refreshes the
+/// whole process table.
+const CYCLE_BUDGET: Duration = Duration::from_millis(500);
+
+/// Samples local processes by pid with sysinfo. A process which exits is logged as a stopped event
+/// and no longer sampled.
+pub struct BareMetalCollector {
+    pids: Vec<u32>,
+    system: System,
+    names: HashMap<u32, String>,
+    pacer: SamplePacer,
+}
+impl BareMetalCollector {
+    pub fn new(pids: Vec<u32>) -> Self {
+        Self {
+            pids,
+            system: System::new_all(),
+            names: HashMap::new(),
+            pacer: SamplePacer::new(CYCLE_BUDGET),
         }
     }
 }
+#[async_trait]
+impl MetricsCollector for BareMetalCollector {
+    fn describe(&self) -> String {
+        format!("PIDs {:?}", self.pids)
+    }
+
+    /// CPU usage is measured since the previous refresh so the first sample waits a second.
+    async fn start(&mut self, _log: &mut LogBuffer) -> anyhow::Result<()> {
+        tokio::time::sleep(Duration::from_millis(1000)).await;
+        Ok(())
+    }
 
-fn update_metrics_log(metrics: anyhow::Result<CpuMetrics>, metrics_log: &Arc<Mutex<MetricsLog>>) {
-    match metrics {
-        Ok(metrics) => metrics_log
-            .lock()
-            .expect("Should be able to acquire lock on metrics log")
-            .push_metrics(metrics),
-        Err(error) => metrics_log
-            .lock()
-            .expect("Should be able to acquire lock on metrics err")
-            .push_error(error),
+    async fn sample(&mut self, log: &mut LogBuffer) -> Duration {
+        let sample_interval = self.pacer.start_cycle();
+        let started = Instant::now();
+        let mut exited = vec![];
+        for pid in self.pids.iter() {
+            match get_metrics(&mut self.system, *pid).await {
+                Ok(metrics) => {
+                    self.names.insert(*pid, metrics.process_name.clone());
+                    log.push_metrics(CpuMetrics {
+                        sample_interval: Some(sample_interval),
+                        ..metrics
+                    });
+                }
+                Err(_) if !is_alive(&self.system, *pid) => {
+                    let process_name = self.names.remove(pid).unwrap_or_else(|| pid.to_string());
+                    tracing::warn!("Process {process_name} ({pid}) exited");
+                    log.push_event(ProcessEvent {
+                        process_name,
+                        kind: ProcessEventKind::Stopped,
+                        timestamp: chrono::Utc::now().timestamp_millis(),
+                    });
+                    exited.push(*pid);
+                }
+                Err(error) => log.push_error(error),
+            }
+        }
+        self.pids.retain(|pid| !exited.contains(pid));
+        self.pacer.finish_cycle(started.elapsed())
     }
 }
 
+/// Exited processes which haven't been reaped by their parent remain as zombies, these are
+/// treated as not running.
+fn is_alive(system: &System, pid: u32) -> bool {
+    system
+        .process(Pid::from_u32(pid))
+        .is_some_and(|process| process.status() != ProcessStatus::Zombie)
+}
+
 async fn get_metrics(system: &mut System, pid: u32) -> anyhow::Result<CpuMetrics> {
     // refresh system information
     system.refresh_all();
+    let cpu_frequency = super::cpu_frequency(system);
+
+    if !is_alive(system, pid) {
+        return Err(anyhow::anyhow!(format!("process with id {pid} not found")));
+    }
 
     if let Some(process) = system.process(Pid::from_u32(pid)) {
         let cpu_usage = process.cpu_usage() as f64;
+        let memory_usage = Some(process.memory() as i64);
         let core_count = system.physical_core_count().unwrap_or(0) as i32;
         let timestamp = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)?
@@ -68,6 +107,13 @@ async fn get_metrics(system: &mut System, pid: u32) -> anyhow::Result<CpuMetrics
             cpu_usage,
             core_count,
             timestamp,
+            cpu_frequency,
+            memory_usage,
+            power: None,
+            sample_interval: None,
+            memory_limit: None,
+            cpu_periods: None,
+            throttled_periods: None,
         };
 
         Ok(metrics)
@@ -184,4 +230,29 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    #[cfg(target_family = "unix")]
+    async fn processes_which_exit_are_logged_as_stopped() -> anyhow::Result<()> {
+        // exits part way through logging and isn't reaped so lingers as a zombie
+        let proc = Exec::cmd("sleep")
+            .arg("1.5")
+            .detached()
+            .popen()
+            .context("Failed to spawn detached process")?;
+        let pid = proc.pid().context("Process should have a pid")?;
+
+        let stop_handle =
+            super::super::start_collectors(vec![Box::new(BareMetalCollector::new(vec![pid]))]);
+        sleep(Duration::from_millis(3500)).await;
+
+        let metrics_log = stop_handle.stop().await?;
+        assert!(!metrics_log.get_metrics().is_empty());
+        let events = metrics_log.get_events();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].process_name, "sleep");
+        assert_eq!(events[0].kind, ProcessEventKind::Stopped);
+
+        Ok(())
+    }
 }
@@ -0,0 +1,272 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use super::{collector::MetricsCollector, sample_interval, scaphandre::parse_line, LogBuffer};
+use crate::{config::Cadvisor, metrics::CpuMetrics};
+use anyhow::Context;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use tokio::time::{Duration, Instant};
+
+const CPU_METRIC: &str = "container_cpu_usage_seconds_total";
+const MEMORY_METRIC: &str = "container_memory_working_set_bytes";
+const MEMORY_LIMIT_METRIC: &str = "container_spec_memory_limit_bytes";
+const PERIODS_METRIC: &str = "container_cpu_cfs_periods_total";
+const THROTTLED_METRIC: &str = "container_cpu_cfs_throttled_periods_total";
+
+/// The metrics cAdvisor reports for a single container. CPU time and periods are cumulative.
+#[derive(Debug, Default, PartialEq)]
+struct ContainerSample {
+    id: String,
+    /// Seconds of CPU time, by the `cpu` label as some versions report each core separately.
+    cpu_seconds: HashMap<String, f64>,
+    memory_usage: Option<i64>,
+    memory_limit: Option<i64>,
+    cpu_periods: Option<i64>,
+    throttled_periods: Option<i64>,
+}
+impl ContainerSample {
+    /// Seconds of CPU time used by the container since it started.
+    fn cpu_seconds(&self) -> f64 {
+        match self.cpu_seconds.get("total") {
+            Some(total) => *total,
+            None => self.cpu_seconds.values().sum(),
+        }
+    }
+}
+
+/// Scrapes an existing cAdvisor for the metrics of observed containers instead of querying the
+/// Docker daemon, which is much cheaper where cAdvisor already runs.
+pub struct CadvisorCollector {
+    cadvisor: Cadvisor,
+    container_names: Vec<String>,
+    core_count: i32,
+    client: reqwest::Client,
+    // the previous scrape of each container, used to turn cumulative counters into deltas
+    previous: HashMap<String, (ContainerSample, Instant)>,
+}
+impl CadvisorCollector {
+    pub fn new(cadvisor: Cadvisor, container_names: Vec<String>) -> Self {
+        Self {
+            cadvisor,
+            container_names,
+            core_count: std::thread::available_parallelism()
+                .map(|n| n.get() as i32)
+                .unwrap_or(0),
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(2))
+                .build()
+                .expect("Should be able to build a http client"),
+            previous: HashMap::new(),
+        }
+    }
+}
+#[async_trait]
+impl MetricsCollector for CadvisorCollector {
+    fn describe(&self) -> String {
+        format!("containers {:?} from cAdvisor", self.container_names)
+    }
+
+    async fn sample(&mut self, log: &mut LogBuffer) -> Duration {
+        let exposition = scrape(&self.client, &self.cadvisor.url).await;
+        let now = Instant::now();
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or(0);
+
+        match exposition {
+            Ok(exposition) => {
+                let mut samples = parse_samples(&exposition);
+                for name in self.container_names.iter() {
+                    let Some(sample) = samples.remove(name) else {
+                        continue;
+                    };
+                    let previous = self
+                        .previous
+                        .get(name)
+                        .filter(|(previous, _)| previous.id == sample.id);
+                    let metrics = to_metrics(
+                        name,
+                        &sample,
+                        previous.map(|(previous, at)| (previous, now.duration_since(*at))),
+                        self.core_count,
+                        timestamp,
+                    );
+                    log.push_metrics(metrics);
+                    self.previous.insert(name.clone(), (sample, now));
+                }
+            }
+            Err(err) => log.push_error(err),
+        }
+
+        sample_interval()
+    }
+}
+
+async fn scrape(client: &reqwest::Client, url: &str) -> anyhow::Result<String> {
+    client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await
+        .context(format!("Unable to scrape cAdvisor {url}"))
+}
+
+/// Collects the per-container metrics in a Prometheus text exposition, keyed by the container's
+/// name, or `<pod>/<container>` for containers in a Kubernetes pod.
+fn parse_samples(exposition: &str) -> HashMap<String, ContainerSample> {
+    let mut samples: HashMap<String, ContainerSample> = HashMap::new();
+    for (name, labels, value) in exposition.lines().filter_map(parse_line) {
+        if ![
+            CPU_METRIC,
+            MEMORY_METRIC,
+            MEMORY_LIMIT_METRIC,
+            PERIODS_METRIC,
+            THROTTLED_METRIC,
+        ]
+        .contains(&name)
+        {
+            continue;
+        }
+        let container = match (labels.get("pod"), labels.get("container")) {
+            (Some(pod), Some(container)) if !pod.is_empty() && !container.is_empty() => {
+                format!("{pod}/{container}")
+            }
+            _ => match labels.get("name").filter(|name| !name.is_empty()) {
+                Some(name) => name.clone(),
+                // cgroups which aren't containers, e.g. the root cgroup
+                None => continue,
+            },
+        };
+
+        let sample = samples.entry(container).or_default();
+        if let Some(id) = labels.get("id") {
+            sample.id.clone_from(id);
+        }
+        match name {
+            CPU_METRIC => {
+                let cpu = labels.get("cpu").cloned().unwrap_or_default();
+                sample.cpu_seconds.insert(cpu, value);
+            }
+            MEMORY_METRIC => sample.memory_usage = Some(value as i64),
+            // cAdvisor reports 0 for containers without a limit
+            MEMORY_LIMIT_METRIC => sample.memory_limit = Some(value as i64).filter(|l| *l > 0),
+            PERIODS_METRIC => sample.cpu_periods = Some(value as i64),
+            _ => sample.throttled_periods = Some(value as i64),
+        }
+    }
+    samples
+}
+
+/// Turns a scrape of a container into metrics, comparing its counters with the previous scrape of
+/// the same container. The first scrape has nothing to compare with so it reports no CPU usage.
+fn to_metrics(
+    name: &str,
+    sample: &ContainerSample,
+    previous: Option<(&ContainerSample, Duration)>,
+    core_count: i32,
+    timestamp: i64,
+) -> CpuMetrics {
+    let delta = |current: Option<i64>, previous: Option<i64>| {
+        Some(current?.saturating_sub(previous?).max(0))
+    };
+    let (cpu_usage, sample_interval, cpu_periods, throttled_periods) = match previous {
+        Some((previous, elapsed)) if !elapsed.is_zero() => (
+            (sample.cpu_seconds() - previous.cpu_seconds()).max(0.0) / elapsed.as_secs_f64()
+                * 100.0,
+            Some(elapsed.as_secs_f64()),
+            delta(sample.cpu_periods, previous.cpu_periods),
+            delta(sample.throttled_periods, previous.throttled_periods),
+        ),
+        _ => (0.0, None, None, None),
+    };
+
+    CpuMetrics {
+        process_id: sample.id.clone(),
+        process_name: name.to_string(),
+        cpu_usage,
+        core_count,
+        timestamp,
+        // cadvisor doesn't report cpu frequency
+        cpu_frequency: None,
+        memory_usage: sample.memory_usage,
+        power: None,
+        sample_interval,
+        memory_limit: sample.memory_limit,
+        cpu_periods,
+        throttled_periods,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXPOSITION: &str = r#"
+# HELP container_cpu_usage_seconds_total Cumulative cpu time consumed in seconds.
+# TYPE container_cpu_usage_seconds_total counter
+container_cpu_usage_seconds_total{cpu="total",id="/",image="",name=""} 9000.5
+container_cpu_usage_seconds_total{cpu="total",id="/docker/abc",image="nginx",name="web"} 12.5 1718000000000
+container_cpu_usage_seconds_total{cpu="cpu00",id="/kubepods/pod1/def",container="api",pod="shop",name="k8s_api"} 3
+container_cpu_usage_seconds_total{cpu="cpu01",id="/kubepods/pod1/def",container="api",pod="shop",name="k8s_api"} 4
+container_memory_working_set_bytes{id="/docker/abc",image="nginx",name="web"} 1048576
+container_spec_memory_limit_bytes{id="/docker/abc",image="nginx",name="web"} 4194304
+container_spec_memory_limit_bytes{id="/kubepods/pod1/def",container="api",pod="shop",name="k8s_api"} 0
+container_cpu_cfs_periods_total{id="/docker/abc",image="nginx",name="web"} 100
+container_cpu_cfs_throttled_periods_total{id="/docker/abc",image="nginx",name="web"} 5
+"#;
+
+    #[test]
+    fn samples_are_grouped_by_container() {
+        let samples = parse_samples(EXPOSITION);
+        assert_eq!(samples.len(), 2);
+
+        let web = &samples["web"];
+        assert_eq!(web.id, "/docker/abc");
+        assert_eq!(web.cpu_seconds(), 12.5);
+        assert_eq!(web.memory_usage, Some(1048576));
+        assert_eq!(web.memory_limit, Some(4194304));
+        assert_eq!(web.cpu_periods, Some(100));
+        assert_eq!(web.throttled_periods, Some(5));
+
+        // per core cpu time is summed, kubernetes containers are named after their pod
+        let api = &samples["shop/api"];
+        assert_eq!(api.cpu_seconds(), 7.0);
+        assert_eq!(api.memory_limit, None);
+    }
+
+    #[test]
+    fn counters_are_compared_with_the_previous_scrape() {
+        let previous = ContainerSample {
+            id: String::from("/docker/abc"),
+            cpu_seconds: HashMap::from([(String::from("total"), 11.5)]),
+            cpu_periods: Some(90),
+            throttled_periods: Some(1),
+            ..Default::default()
+        };
+        let samples = parse_samples(EXPOSITION);
+
+        let first = to_metrics("web", &samples["web"], None, 4, 1000);
+        assert_eq!(first.cpu_usage, 0.0);
+        assert_eq!(first.throttled_periods, None);
+
+        let metrics = to_metrics(
+            "web",
+            &samples["web"],
+            Some((&previous, Duration::from_secs(2))),
+            4,
+            2000,
+        );
+        assert_eq!(metrics.cpu_usage, 50.0);
+        assert_eq!(metrics.sample_interval, Some(2.0));
+        assert_eq!(metrics.cpu_periods, Some(10));
+        assert_eq!(metrics.throttled_periods, Some(4));
+        assert_eq!(metrics.memory_usage, Some(1048576));
+    }
+}
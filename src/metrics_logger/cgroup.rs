@@ -0,0 +1,249 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use super::{collector::MetricsCollector, LogBuffer, SamplePacer};
+use crate::metrics::CpuMetrics;
+use anyhow::Context;
+use async_trait::async_trait;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+use tokio::time::{Duration, Instant};
+
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// How long reading every cgroup may take before sampling backs off.
+const CYCLE_BUDGET: Duration = Duration::from_millis(500);
+
+/// A cgroup v2 directory to observe along with the name it should be logged under.
+#[derive(Debug, Clone)]
+pub struct CgroupToObserve {
+    pub name: String,
+    pub path: PathBuf,
+}
+impl CgroupToObserve {
+    /// Observe a cgroup by its path relative to the cgroup v2 mount point, e.g.
+    /// `system.slice/docker-<id>.scope`.
+    pub fn from_path(path: &str) -> Self {
+        Self {
+            name: path.to_string(),
+            path: Path::new(CGROUP_ROOT).join(path.trim_start_matches('/')),
+        }
+    }
+
+    /// Observe the cgroup of a systemd unit. The control group is looked up with `systemctl`,
+    /// falling back to `system.slice/<unit>` if systemctl isn't available.
+    pub fn from_systemd_unit(unit: &str) -> Self {
+        let control_group = std::process::Command::new("systemctl")
+            .args(["show", "--property=ControlGroup", "--value", unit])
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+            .filter(|control_group| !control_group.is_empty())
+            .unwrap_or_else(|| format!("system.slice/{unit}"));
+
+        Self {
+            name: unit.to_string(),
+            path: Path::new(CGROUP_ROOT).join(control_group.trim_start_matches('/')),
+        }
+    }
+}
+
+/// Samples cgroup v2 directories, e.g. of systemd units. CPU usage is derived from `usage_usec` in
+/// the cgroup's `cpu.stat` which is cheaper and more accurate than polling the Docker stats API.
+pub struct CgroupCollector {
+    cgroups: Vec<CgroupToObserve>,
+    core_count: i32,
+    previous: HashMap<PathBuf, (u64, Instant)>,
+    system: sysinfo::System,
+    pacer: SamplePacer,
+}
+impl CgroupCollector {
+    pub fn new(cgroups: Vec<CgroupToObserve>) -> Self {
+        Self {
+            cgroups,
+            core_count: std::thread::available_parallelism()
+                .map(|n| n.get() as i32)
+                .unwrap_or(0),
+            previous: HashMap::new(),
+            system: sysinfo::System::new(),
+            pacer: SamplePacer::new(CYCLE_BUDGET),
+        }
+    }
+}
+#[async_trait]
+impl MetricsCollector for CgroupCollector {
+    fn describe(&self) -> String {
+        format!("cgroups {:?}", self.cgroups)
+    }
+
+    async fn sample(&mut self, log: &mut LogBuffer) -> Duration {
+        let started = Instant::now();
+        let cpu_frequency = super::cpu_frequency(&mut self.system);
+        for cgroup in self.cgroups.iter() {
+            let usage_usec = match read_usage_usec(&cgroup.path) {
+                Ok(usage_usec) => usage_usec,
+                Err(err) => {
+                    log.push_error(err);
+                    continue;
+                }
+            };
+            let now = Instant::now();
+
+            // the first reading only establishes a baseline
+            if let Some((prev_usage_usec, prev_instant)) =
+                self.previous.insert(cgroup.path.clone(), (usage_usec, now))
+            {
+                let elapsed_usec = now.duration_since(prev_instant).as_micros() as u64;
+                let timestamp = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map(|d| d.as_millis() as i64)
+                    .unwrap_or(0);
+
+                log.push_metrics(CpuMetrics {
+                    process_id: cgroup.path.to_string_lossy().to_string(),
+                    process_name: cgroup.name.clone(),
+                    cpu_usage: cpu_usage_percent(
+                        usage_usec.saturating_sub(prev_usage_usec),
+                        elapsed_usec,
+                    ),
+                    core_count: self.core_count,
+                    timestamp,
+                    cpu_frequency,
+                    memory_usage: read_memory_current(&cgroup.path),
+                    power: None,
+                    sample_interval: Some(elapsed_usec as f64 / 1_000_000.0),
+                    memory_limit: None,
+                    cpu_periods: None,
+                    throttled_periods: None,
+                });
+            }
+        }
+
+        self.pacer.finish_cycle(started.elapsed())
+    }
+}
+
+/// Finds the cgroup v2 directory a process belongs to, None if the process has exited or cgroup
+/// v2 isn't mounted.
+pub(crate) fn cgroup_of_pid(pid: u32) -> Option<PathBuf> {
+    let proc_cgroup = std::fs::read_to_string(format!("/proc/{pid}/cgroup")).ok()?;
+    parse_proc_cgroup(&proc_cgroup)
+        .map(|path| Path::new(CGROUP_ROOT).join(path.trim_start_matches('/')))
+}
+
+/// The unified hierarchy is the line with hierarchy id 0, e.g. `0::/system.slice/nginx.service`.
+fn parse_proc_cgroup(proc_cgroup: &str) -> Option<&str> {
+    proc_cgroup
+        .lines()
+        .find_map(|line| line.strip_prefix("0::"))
+        .map(str::trim)
+}
+
+/// Reads the memory currently used by the cgroup in bytes, None if the memory controller isn't
+/// enabled for it.
+fn read_memory_current(cgroup_path: &Path) -> Option<i64> {
+    std::fs::read_to_string(cgroup_path.join("memory.current"))
+        .ok()
+        .and_then(|memory| memory.trim().parse::<i64>().ok())
+}
+
+fn read_usage_usec(cgroup_path: &Path) -> anyhow::Result<u64> {
+    let cpu_stat_path = cgroup_path.join("cpu.stat");
+    let cpu_stat = std::fs::read_to_string(&cpu_stat_path)
+        .context(format!("Unable to read {}", cpu_stat_path.display()))?;
+
+    parse_usage_usec(&cpu_stat).context(format!(
+        "{} does not contain usage_usec",
+        cpu_stat_path.display()
+    ))
+}
+
+fn parse_usage_usec(cpu_stat: &str) -> Option<u64> {
+    cpu_stat.lines().find_map(|line| {
+        line.strip_prefix("usage_usec ")
+            .and_then(|val| val.trim().parse().ok())
+    })
+}
+
+/// CPU usage as a percentage of a single core, matching the convention used by sysinfo.
+fn cpu_usage_percent(usage_delta_usec: u64, elapsed_usec: u64) -> f64 {
+    if elapsed_usec == 0 {
+        return 0.0;
+    }
+    usage_delta_usec as f64 / elapsed_usec as f64 * 100.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn usage_usec_can_be_parsed_from_cpu_stat() {
+        let cpu_stat = "usage_usec 123456\nuser_usec 100000\nsystem_usec 23456\n";
+        assert_eq!(parse_usage_usec(cpu_stat), Some(123456));
+        assert_eq!(parse_usage_usec("user_usec 1\n"), None);
+    }
+
+    #[test]
+    fn cpu_usage_is_relative_to_a_single_core() {
+        assert_eq!(cpu_usage_percent(500_000, 1_000_000), 50.0);
+        assert_eq!(cpu_usage_percent(2_000_000, 1_000_000), 200.0);
+        assert_eq!(cpu_usage_percent(100, 0), 0.0);
+    }
+
+    #[test]
+    fn unified_cgroup_can_be_parsed_from_proc() {
+        let proc_cgroup = "1:name=systemd:/init.scope\n0::/system.slice/nginx.service\n";
+        assert_eq!(
+            parse_proc_cgroup(proc_cgroup),
+            Some("/system.slice/nginx.service")
+        );
+        assert_eq!(parse_proc_cgroup("1:cpu:/\n"), None);
+    }
+
+    #[test]
+    fn cgroup_paths_are_relative_to_the_cgroup_root() {
+        let cgroup = CgroupToObserve::from_path("/system.slice/docker-abc.scope");
+        assert_eq!(
+            cgroup.path,
+            Path::new("/sys/fs/cgroup/system.slice/docker-abc.scope")
+        );
+    }
+
+    #[tokio::test]
+    async fn metrics_can_be_gathered_from_a_cgroup_directory() -> anyhow::Result<()> {
+        let dir = std::env::temp_dir().join(format!("cardamon-cgroup-{}", nanoid::nanoid!(5)));
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(dir.join("cpu.stat"), "usage_usec 1000\n")?;
+        std::fs::write(dir.join("memory.current"), "1048576\n")?;
+
+        let cgroup = CgroupToObserve {
+            name: "test.service".to_string(),
+            path: dir.clone(),
+        };
+        let stop_handle =
+            super::super::start_collectors(vec![Box::new(CgroupCollector::new(vec![cgroup]))]);
+
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        std::fs::write(dir.join("cpu.stat"), "usage_usec 501000\n")?;
+        tokio::time::sleep(Duration::from_millis(1000)).await;
+
+        let metrics_log = stop_handle.stop().await?;
+        let metrics = metrics_log
+            .get_metrics()
+            .first()
+            .expect("should have metrics");
+        assert_eq!(metrics.process_name, "test.service");
+        assert!(metrics.cpu_usage > 0.0);
+        assert_eq!(metrics.memory_usage, Some(1048576));
+
+        std::fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+}
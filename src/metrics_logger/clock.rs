@@ -0,0 +1,73 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! The time collectors pace their sampling by and timestamp samples with. Collectors use the
+//! system clock unless given another, tests use a manual clock to simulate sample timing.
+
+use std::{
+    fmt::Debug,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+pub trait Clock: Debug + Send + Sync {
+    /// A monotonic instant for measuring how long collecting takes.
+    fn now(&self) -> Instant;
+
+    /// Milliseconds since the unix epoch, which samples are timestamped with.
+    fn timestamp(&self) -> i64;
+}
+
+/// The clock of the machine cardamon runs on.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn timestamp(&self) -> i64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since_epoch| since_epoch.as_millis() as i64)
+            .unwrap_or(0)
+    }
+}
+
+/// A clock which only moves when it's advanced.
+#[derive(Debug)]
+pub struct ManualClock {
+    start: Instant,
+    start_timestamp: i64,
+    elapsed: Mutex<Duration>,
+}
+impl ManualClock {
+    /// A clock whose timestamps start at the given milliseconds since the unix epoch.
+    pub fn new(start_timestamp: i64) -> Arc<Self> {
+        Arc::new(Self {
+            start: Instant::now(),
+            start_timestamp,
+            elapsed: Mutex::new(Duration::ZERO),
+        })
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self.elapsed.lock().unwrap_or_else(PoisonError::into_inner) += duration;
+    }
+
+    fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed()
+    }
+
+    fn timestamp(&self) -> i64 {
+        self.start_timestamp + self.elapsed().as_millis() as i64
+    }
+}
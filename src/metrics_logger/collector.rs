@@ -0,0 +1,287 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! The interface metrics backends implement and the registry which decides which backend observes
+//! each kind of process.
+
+use super::{
+    bare_metal::BareMetalCollector,
+    cgroup::{CgroupCollector, CgroupToObserve},
+    cri::{self, CriCollector},
+    docker::DockerCollector,
+    remote::RemoteCollector,
+    LogBuffer,
+};
+use crate::{
+    error::{CardamonError, Result},
+    ProcessToObserve,
+};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use tokio::time::Duration;
+
+/// A backend which samples metrics, e.g. of local processes or containers.
+#[async_trait]
+pub trait MetricsCollector: Send {
+    /// What the collector observes, for logging.
+    fn describe(&self) -> String;
+
+    /// Prepares to sample, e.g. connects to the backend. A collector which fails to start doesn't
+    /// sample anything and its error is added to the metrics log.
+    async fn start(&mut self, _log: &mut LogBuffer) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Takes one round of samples, adding them to the log.
+    ///
+    /// # Returns
+    ///
+    /// How long to wait before sampling again.
+    async fn sample(&mut self, log: &mut LogBuffer) -> Duration;
+
+    /// Called once sampling has been cancelled, e.g. to stop work started in the background.
+    async fn stop(&mut self, _log: &mut LogBuffer) {}
+}
+
+/// Starts the collector then samples, flushes and waits for as long as the collector asks.
+///
+/// **WARNING**
+///
+/// This function should only be called from within a task that can execute it on another thread
+/// otherwise it will block the main thread completely.
+///
+/// # Returns
+///
+/// This function only returns if the collector fails to start, otherwise it requires that it's
+/// thread is cancelled.
+pub async fn keep_collecting(collector: &mut dyn MetricsCollector, log: &mut LogBuffer) {
+    if let Err(err) = collector.start(log).await {
+        log.push_error(err);
+        return;
+    }
+
+    loop {
+        let wait = collector.sample(log).await;
+        log.flush();
+        tokio::time::sleep(wait).await;
+    }
+}
+
+/// The kinds of process a collector can be registered for, each variant of `ProcessToObserve`
+/// is one kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CollectorKind {
+    Pid,
+    Container,
+    /// Systemd units and cgroup paths, units are observed through their cgroup.
+    Cgroup,
+    RemotePid,
+}
+impl CollectorKind {
+    pub fn of(process: &ProcessToObserve) -> Self {
+        match process {
+            ProcessToObserve::Pid(..) => CollectorKind::Pid,
+            ProcessToObserve::ContainerName(_) => CollectorKind::Container,
+            ProcessToObserve::SystemdUnit(_) | ProcessToObserve::Cgroup(_) => CollectorKind::Cgroup,
+            ProcessToObserve::RemotePid(..) => CollectorKind::RemotePid,
+        }
+    }
+}
+
+/// Creates a collector observing every process of the kind it's registered for.
+pub type CollectorFactory =
+    Box<dyn Fn(Vec<ProcessToObserve>) -> Box<dyn MetricsCollector> + Send + Sync>;
+
+/// The collector used for each kind of process. The default registry uses the collectors built
+/// into cardamon, any of which can be replaced.
+pub struct CollectorRegistry {
+    factories: HashMap<CollectorKind, CollectorFactory>,
+}
+impl CollectorRegistry {
+    /// A registry without any collectors.
+    pub fn empty() -> Self {
+        Self {
+            factories: HashMap::new(),
+        }
+    }
+
+    /// Uses the given collector for a kind of process, replacing any registered before.
+    pub fn register(
+        &mut self,
+        kind: CollectorKind,
+        factory: impl Fn(Vec<ProcessToObserve>) -> Box<dyn MetricsCollector> + Send + Sync + 'static,
+    ) {
+        self.factories.insert(kind, Box::new(factory));
+    }
+
+    /// Groups the processes by kind and creates one collector for each group.
+    ///
+    /// # Returns
+    ///
+    /// The collectors, or an error if no collector is registered for one of the kinds.
+    pub fn collectors_for(
+        &self,
+        processes: &[ProcessToObserve],
+    ) -> Result<Vec<Box<dyn MetricsCollector>>> {
+        let mut by_kind: Vec<(CollectorKind, Vec<ProcessToObserve>)> = vec![];
+        for process in processes.iter() {
+            let kind = CollectorKind::of(process);
+            match by_kind.iter_mut().find(|(k, _)| *k == kind) {
+                Some((_, processes)) => processes.push(process.clone()),
+                None => by_kind.push((kind, vec![process.clone()])),
+            }
+        }
+
+        by_kind
+            .into_iter()
+            .map(|(kind, processes)| {
+                let factory = self.factories.get(&kind).ok_or_else(|| {
+                    CardamonError::InvalidInput(format!(
+                        "No metrics collector is registered for {kind:?} processes"
+                    ))
+                })?;
+                Ok(factory(processes))
+            })
+            .collect()
+    }
+}
+impl Default for CollectorRegistry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry.register(CollectorKind::Pid, |processes| {
+            Box::new(BareMetalCollector::new(
+                processes.iter().filter_map(ProcessToObserve::pid).collect(),
+            ))
+        });
+        registry.register(CollectorKind::Container, |processes| {
+            let container_names = container_names(processes);
+
+            // hosts running containerd without docker, e.g. k3s nodes, are sampled through the cri
+            match cri::runtime_endpoint() {
+                Some(socket) => Box::new(CriCollector::new(container_names, socket)),
+                None => Box::new(DockerCollector::new(container_names)),
+            }
+        });
+        registry.register(CollectorKind::Cgroup, |processes| {
+            Box::new(CgroupCollector::new(
+                processes.iter().filter_map(cgroup_of).collect(),
+            ))
+        });
+        registry.register(CollectorKind::RemotePid, |processes| {
+            Box::new(RemoteCollector::new(
+                processes
+                    .into_iter()
+                    .filter_map(|process| match process {
+                        ProcessToObserve::RemotePid(remote, pid) => Some((remote, pid)),
+                        _ => None,
+                    })
+                    .collect(),
+            ))
+        });
+        registry
+    }
+}
+
+pub(crate) fn container_names(processes: Vec<ProcessToObserve>) -> Vec<String> {
+    processes
+        .into_iter()
+        .filter_map(|process| match process {
+            ProcessToObserve::ContainerName(name) => Some(name),
+            _ => None,
+        })
+        .collect()
+}
+
+pub(crate) fn cgroup_of(process: &ProcessToObserve) -> Option<CgroupToObserve> {
+    match process {
+        ProcessToObserve::SystemdUnit(unit) => Some(CgroupToObserve::from_systemd_unit(unit)),
+        ProcessToObserve::Cgroup(path) => Some(CgroupToObserve::from_path(path)),
+        ProcessToObserve::Pid(..)
+        | ProcessToObserve::ContainerName(_)
+        | ProcessToObserve::RemotePid(..) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::CpuMetrics;
+
+    /// Logs one sample per observed pid every 10ms.
+    struct FakeCollector {
+        pids: Vec<u32>,
+    }
+    #[async_trait]
+    impl MetricsCollector for FakeCollector {
+        fn describe(&self) -> String {
+            format!("fake {:?}", self.pids)
+        }
+
+        async fn sample(&mut self, log: &mut LogBuffer) -> Duration {
+            for pid in self.pids.iter() {
+                log.push_metrics(CpuMetrics {
+                    process_id: pid.to_string(),
+                    process_name: "fake".to_string(),
+                    cpu_usage: 50.0,
+                    core_count: 4,
+                    timestamp: 0,
+                    cpu_frequency: None,
+                    memory_usage: None,
+                    power: None,
+                    sample_interval: None,
+                    memory_limit: None,
+                    cpu_periods: None,
+                    throttled_periods: None,
+                });
+            }
+            Duration::from_millis(10)
+        }
+    }
+
+    #[test]
+    fn processes_are_grouped_by_kind() -> anyhow::Result<()> {
+        let processes = [
+            ProcessToObserve::Pid(None, 1),
+            ProcessToObserve::Cgroup("system.slice/nginx.service".to_string()),
+            ProcessToObserve::Pid(Some("db".to_string()), 2),
+        ];
+
+        // a kind without a collector can't be observed
+        let mut registry = CollectorRegistry::empty();
+        registry.register(CollectorKind::Pid, |processes| {
+            Box::new(FakeCollector {
+                pids: processes.iter().filter_map(ProcessToObserve::pid).collect(),
+            })
+        });
+        assert!(registry.collectors_for(&processes).is_err());
+
+        let collectors = registry.collectors_for(&processes[..1])?;
+        assert_eq!(collectors.len(), 1);
+
+        let collectors = CollectorRegistry::default().collectors_for(&processes)?;
+        let descriptions = collectors
+            .iter()
+            .map(|collector| collector.describe())
+            .collect::<Vec<_>>();
+        assert_eq!(descriptions.len(), 2);
+        assert_eq!(descriptions[0], "PIDs [1, 2]");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn collectors_sample_until_stopped() -> anyhow::Result<()> {
+        let stop_handle =
+            super::super::start_collectors(vec![Box::new(FakeCollector { pids: vec![1, 2] })]);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let metrics_log = stop_handle.stop().await?;
+
+        let sampled = metrics_log.get_metrics().len();
+        assert!(sampled >= 2 && sampled % 2 == 0);
+
+        Ok(())
+    }
+}
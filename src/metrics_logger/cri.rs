@@ -0,0 +1,445 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Samples containers through the Kubernetes Container Runtime Interface (CRI) served by
+//! containerd and CRI-O, for hosts which run containers without a Docker daemon, e.g. k3s nodes.
+
+use super::{
+    clock::{Clock, SystemClock},
+    collector::MetricsCollector,
+    docker::ContainerTracker,
+    sample_interval, LogBuffer, SamplePacer,
+};
+use crate::metrics::CpuMetrics;
+use anyhow::Context;
+use async_trait::async_trait;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Instant,
+};
+use tokio::time::Duration;
+
+pub mod proto {
+    tonic::include_proto!("runtime.v1");
+}
+use proto::runtime_service_client::RuntimeServiceClient;
+
+/// Where the Docker daemon listens, if it's present containers are sampled through Docker.
+const DOCKER_SOCKET: &str = "/var/run/docker.sock";
+
+/// Where containerd, k3s' embedded containerd and CRI-O serve the CRI, in the order they're tried.
+const CRI_SOCKETS: [&str; 3] = [
+    "/run/containerd/containerd.sock",
+    "/run/k3s/containerd/containerd.sock",
+    "/run/crio/crio.sock",
+];
+
+/// The label the kubelet gives containers with the name of their pod.
+const POD_NAME_LABEL: &str = "io.kubernetes.pod.name";
+
+/// How often the list of running containers is refreshed.
+const RESOLVE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How long sampling may take before sampling backs off. Every container is sampled by a single
+/// call so this is much less than Docker's budget.
+const CYCLE_BUDGET: Duration = Duration::from_millis(500);
+
+/// The CRI socket containers should be sampled through, if any. `CONTAINER_RUNTIME_ENDPOINT` is
+/// used if it's set (as it is for crictl), otherwise the first CRI socket found, but only if
+/// Docker isn't available because Docker is preferred when it is.
+pub fn runtime_endpoint() -> Option<PathBuf> {
+    let docker_available =
+        std::env::var_os("DOCKER_HOST").is_some() || Path::new(DOCKER_SOCKET).exists();
+    find_runtime_endpoint(
+        std::env::var("CONTAINER_RUNTIME_ENDPOINT").ok(),
+        docker_available,
+        |path| path.exists(),
+    )
+}
+
+fn find_runtime_endpoint(
+    configured: Option<String>,
+    docker_available: bool,
+    exists: impl Fn(&Path) -> bool,
+) -> Option<PathBuf> {
+    if let Some(endpoint) = configured.filter(|endpoint| !endpoint.is_empty()) {
+        let path = endpoint.strip_prefix("unix://").unwrap_or(&endpoint);
+        return Some(PathBuf::from(path));
+    }
+    if docker_available {
+        return None;
+    }
+    CRI_SOCKETS
+        .iter()
+        .map(PathBuf::from)
+        .find(|socket| exists(socket))
+}
+
+/// The parts of the CRI containers are sampled through, implemented by the gRPC client. Tests
+/// implement it to simulate a container runtime.
+#[async_trait]
+pub trait CriApi: Send + Sync {
+    /// Returns (name, id) pairs for every running container.
+    async fn running_containers(&self) -> anyhow::Result<Vec<(String, String)>>;
+
+    /// The latest stats of every container.
+    async fn stats(&self) -> anyhow::Result<Vec<proto::ContainerStats>>;
+}
+
+/// A CRI client connected to a runtime's unix socket.
+pub struct CriClient {
+    client: RuntimeServiceClient<tonic::transport::Channel>,
+}
+impl CriClient {
+    #[cfg(unix)]
+    pub async fn connect(socket: &Path) -> anyhow::Result<Self> {
+        let socket = socket.to_path_buf();
+        let connector_socket = socket.clone();
+        // the uri is ignored, every connection is made to the socket
+        let channel = tonic::transport::Endpoint::try_from("http://[::]:50051")?
+            .connect_with_connector(tower::service_fn(move |_: tonic::transport::Uri| {
+                tokio::net::UnixStream::connect(connector_socket.clone())
+            }))
+            .await
+            .with_context(|| format!("Unable to connect to {}", socket.display()))?;
+
+        let mut client = RuntimeServiceClient::new(channel);
+        let version = client
+            .version(proto::VersionRequest::default())
+            .await
+            .with_context(|| format!("{} doesn't serve the CRI", socket.display()))?
+            .into_inner();
+        tracing::info!(
+            "Sampling containers through {} {}",
+            version.runtime_name,
+            version.runtime_version
+        );
+
+        Ok(Self { client })
+    }
+
+    #[cfg(not(unix))]
+    pub async fn connect(socket: &Path) -> anyhow::Result<Self> {
+        anyhow::bail!(
+            "Unable to connect to {}, the CRI is only supported on unix",
+            socket.display()
+        )
+    }
+}
+#[async_trait]
+impl CriApi for CriClient {
+    async fn running_containers(&self) -> anyhow::Result<Vec<(String, String)>> {
+        let request = proto::ListContainersRequest {
+            filter: Some(proto::ContainerFilter {
+                state: Some(proto::ContainerStateValue {
+                    state: proto::ContainerState::ContainerRunning.into(),
+                }),
+                ..Default::default()
+            }),
+        };
+        let containers = self
+            .client
+            .clone()
+            .list_containers(request)
+            .await
+            .context("Unable to list CRI containers")?
+            .into_inner()
+            .containers;
+
+        Ok(containers.iter().flat_map(container_names).collect())
+    }
+
+    async fn stats(&self) -> anyhow::Result<Vec<proto::ContainerStats>> {
+        Ok(self
+            .client
+            .clone()
+            .list_container_stats(proto::ListContainerStatsRequest::default())
+            .await
+            .context("Unable to read CRI container stats")?
+            .into_inner()
+            .stats)
+    }
+}
+
+/// The names a container can be observed by, its own name and, for containers in a Kubernetes
+/// pod, `<pod>/<name>` since container names are only unique within a pod.
+fn container_names(container: &proto::Container) -> Vec<(String, String)> {
+    let Some(name) = container.metadata.as_ref().map(|metadata| &metadata.name) else {
+        return vec![];
+    };
+    let mut names = vec![(name.clone(), container.id.clone())];
+    if let Some(pod) = container.labels.get(POD_NAME_LABEL) {
+        names.push((format!("{pod}/{name}"), container.id.clone()));
+    }
+    names
+}
+
+/// Samples the named containers through a CRI runtime such as containerd.
+pub struct CriCollector {
+    container_names: Vec<String>,
+    socket: PathBuf,
+    cri: Option<Box<dyn CriApi>>,
+    clock: Arc<dyn Clock>,
+    tracker: ContainerTracker,
+    last_resolved: Option<Instant>,
+    // cumulative cpu nanoseconds and the time they were read, in nanoseconds, by container id
+    previous: HashMap<String, (u64, i64)>,
+    core_count: i32,
+    pacer: SamplePacer,
+}
+impl CriCollector {
+    /// A collector which connects to the runtime serving the CRI on the given socket when it
+    /// starts.
+    pub fn new(container_names: Vec<String>, socket: PathBuf) -> Self {
+        Self {
+            tracker: ContainerTracker::new(container_names.clone()),
+            container_names,
+            socket,
+            cri: None,
+            clock: Arc::new(SystemClock),
+            last_resolved: None,
+            previous: HashMap::new(),
+            core_count: std::thread::available_parallelism()
+                .map(|n| n.get() as i32)
+                .unwrap_or(0),
+            pacer: SamplePacer::new(CYCLE_BUDGET),
+        }
+    }
+
+    /// Samples through the given CRI rather than connecting to the socket.
+    pub fn with_api(mut self, cri: Box<dyn CriApi>) -> Self {
+        self.cri = Some(cri);
+        self
+    }
+
+    /// Paces and timestamps samples with the given clock rather than the system clock.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.pacer = SamplePacer::new(CYCLE_BUDGET).with_clock(clock.clone());
+        self.clock = clock;
+        self
+    }
+
+    /// CPU usage of the container since it was last sampled, where 100% is one core. The runtime's
+    /// own estimate is used for the first sample.
+    fn cpu_usage(&mut self, id: &str, cpu: Option<&proto::CpuUsage>) -> f64 {
+        let Some(cpu) = cpu else {
+            return 0.0;
+        };
+        let usage = cpu
+            .usage_core_nano_seconds
+            .as_ref()
+            .map(|usage| usage.value);
+        let previous = match usage {
+            Some(usage) => self.previous.insert(id.to_string(), (usage, cpu.timestamp)),
+            None => None,
+        };
+
+        match (usage, previous) {
+            (Some(usage), Some((previous_usage, previous_timestamp)))
+                if cpu.timestamp > previous_timestamp =>
+            {
+                usage.saturating_sub(previous_usage) as f64
+                    / (cpu.timestamp - previous_timestamp) as f64
+                    * 100.0
+            }
+            _ => cpu
+                .usage_nano_cores
+                .as_ref()
+                .map(|nano_cores| nano_cores.value as f64 / 10_000_000.0)
+                .unwrap_or(0.0),
+        }
+    }
+}
+#[async_trait]
+impl MetricsCollector for CriCollector {
+    fn describe(&self) -> String {
+        format!(
+            "containers {:?} through {}",
+            self.container_names,
+            self.socket.display()
+        )
+    }
+
+    async fn start(&mut self, _log: &mut LogBuffer) -> anyhow::Result<()> {
+        if self.cri.is_none() {
+            self.cri = Some(Box::new(CriClient::connect(&self.socket).await?));
+        }
+        Ok(())
+    }
+
+    async fn sample(&mut self, log: &mut LogBuffer) -> Duration {
+        let Some(cri) = self.cri.take() else {
+            return sample_interval();
+        };
+
+        if self
+            .last_resolved
+            .is_none_or(|t| self.clock.now().duration_since(t) >= RESOLVE_INTERVAL)
+        {
+            match cri.running_containers().await {
+                Ok(running) => {
+                    let (attached, detached) = self.tracker.resolve(&running);
+                    for name in detached {
+                        tracing::info!("Container {name} stopped, detaching");
+                    }
+                    for name in attached {
+                        tracing::info!("Container {name} running, attaching");
+                    }
+                }
+                Err(err) => log.push_error(err),
+            }
+            self.last_resolved = Some(self.clock.now());
+        }
+
+        let attached = self.tracker.attached();
+        if attached.is_empty() {
+            // nothing was sampled so the next samples only cover the time after this
+            self.pacer.start_cycle();
+            self.cri = Some(cri);
+            return sample_interval();
+        }
+
+        let sample_interval = self.pacer.start_cycle();
+        let started = self.clock.now();
+        match cri.stats().await {
+            Ok(stats) => {
+                let timestamp = self.clock.timestamp();
+                for (name, id) in attached {
+                    let Some(stats) = stats
+                        .iter()
+                        .find(|stats| stats.attributes.as_ref().is_some_and(|a| a.id == id))
+                    else {
+                        continue;
+                    };
+                    log.push_metrics(CpuMetrics {
+                        process_id: id.clone(),
+                        process_name: name,
+                        cpu_usage: self.cpu_usage(&id, stats.cpu.as_ref()),
+                        core_count: self.core_count,
+                        timestamp,
+                        // the cri doesn't report cpu frequency
+                        cpu_frequency: None,
+                        memory_usage: stats
+                            .memory
+                            .as_ref()
+                            .and_then(|memory| memory.working_set_bytes.as_ref())
+                            .map(|working_set| working_set.value as i64),
+                        power: None,
+                        sample_interval: Some(sample_interval),
+                        memory_limit: None,
+                        cpu_periods: None,
+                        throttled_periods: None,
+                    });
+                }
+            }
+            Err(err) => log.push_error(err),
+        }
+        self.cri = Some(cri);
+        self.pacer
+            .finish_cycle(self.clock.now().duration_since(started))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{metrics::MetricsLog, metrics_logger::clock::ManualClock};
+    use std::sync::Mutex;
+
+    /// A runtime running a single container in the "shop" pod which uses half a core.
+    struct FakeCri {
+        reads: Mutex<u64>,
+    }
+    #[async_trait]
+    impl CriApi for FakeCri {
+        async fn running_containers(&self) -> anyhow::Result<Vec<(String, String)>> {
+            Ok(container_names(&proto::Container {
+                id: String::from("abc"),
+                metadata: Some(proto::ContainerMetadata {
+                    name: String::from("web"),
+                    attempt: 0,
+                }),
+                labels: HashMap::from([(POD_NAME_LABEL.to_string(), String::from("shop"))]),
+                ..Default::default()
+            }))
+        }
+
+        async fn stats(&self) -> anyhow::Result<Vec<proto::ContainerStats>> {
+            let mut reads = self.reads.lock().unwrap();
+            *reads += 1;
+            let seconds = *reads as i64;
+            Ok(vec![proto::ContainerStats {
+                attributes: Some(proto::ContainerAttributes {
+                    id: String::from("abc"),
+                    ..Default::default()
+                }),
+                cpu: Some(proto::CpuUsage {
+                    timestamp: seconds * 1_000_000_000,
+                    usage_core_nano_seconds: Some(proto::UInt64Value {
+                        value: seconds as u64 * 500_000_000,
+                    }),
+                    usage_nano_cores: Some(proto::UInt64Value { value: 250_000_000 }),
+                }),
+                memory: Some(proto::MemoryUsage {
+                    working_set_bytes: Some(proto::UInt64Value { value: 1024 }),
+                    ..Default::default()
+                }),
+            }])
+        }
+    }
+
+    #[tokio::test]
+    async fn containers_can_be_sampled_without_a_runtime() -> anyhow::Result<()> {
+        let clock = ManualClock::new(1_000);
+        let mut collector =
+            CriCollector::new(vec!["shop/web".to_string()], PathBuf::from("/dev/null"))
+                .with_api(Box::new(FakeCri {
+                    reads: Mutex::new(0),
+                }))
+                .with_clock(clock.clone());
+
+        let shared = Arc::new(Mutex::new(MetricsLog::new()));
+        let mut log = LogBuffer::new(shared.clone());
+        collector.start(&mut log).await?;
+
+        collector.sample(&mut log).await;
+        clock.advance(Duration::from_secs(1));
+        collector.sample(&mut log).await;
+        log.flush();
+
+        let shared = shared.lock().unwrap();
+        let metrics = shared.get_metrics();
+        assert_eq!(metrics.len(), 2);
+        assert_eq!(metrics[0].process_name, "shop/web");
+        assert_eq!(metrics[0].memory_usage, Some(1024));
+        // the runtime's estimate, then from the cpu time used since the first sample
+        assert_eq!(metrics[0].cpu_usage, 25.0);
+        assert_eq!(metrics[1].cpu_usage, 50.0);
+        assert_eq!(metrics[1].timestamp, 2_000);
+        Ok(())
+    }
+
+    #[test]
+    fn docker_is_preferred_to_the_cri() {
+        let exists = |path: &Path| path == Path::new("/run/k3s/containerd/containerd.sock");
+        assert_eq!(
+            find_runtime_endpoint(None, false, exists),
+            Some(PathBuf::from("/run/k3s/containerd/containerd.sock"))
+        );
+        assert_eq!(find_runtime_endpoint(None, true, exists), None);
+        assert_eq!(find_runtime_endpoint(None, false, |_| false), None);
+        assert_eq!(
+            find_runtime_endpoint(
+                Some(String::from("unix:///run/crio/crio.sock")),
+                true,
+                exists
+            ),
+            Some(PathBuf::from("/run/crio/crio.sock"))
+        );
+    }
+}
@@ -1,408 +1,815 @@
-use crate::metrics::{CpuMetrics, MetricsLog};
-use std::sync::{Arc, Mutex};
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
 
-/// Enters an infinite loop logging metrics for each process to the metrics log. This function is
-/// intended to be called from `metrics_logger::log_scenario` or `metrics_logger::log_live`
-///
-/// **WARNING**
-///
-/// This function should only be called from within a task that can execute it on another thread
-/// otherwise it will block the main thread completely.
-///
-/// # Arguments
-///
-/// * `processes` - The processes to observe in the live environment
-/// * `metrics_log` - A log of all observed metrics. Another thread should periodically save and
-/// flush this shared log.
-///
-/// # Returns
+use super::{
+    clock::{Clock, SystemClock},
+    collector::MetricsCollector,
+    sample_interval, LogBuffer, SamplePacer,
+};
+use crate::metrics::{CpuMetrics, ProcessEvent, ProcessEventKind};
+use anyhow::Context;
+use async_trait::async_trait;
+use bollard::{
+    container::{ListContainersOptions, Stats, StatsOptions},
+    system::EventsOptions,
+    Docker,
+};
+use futures_util::stream::StreamExt;
+use std::{collections::HashMap, sync::Arc, time::Instant};
+use tokio::{task::JoinHandle, time::Duration};
+
+/// How often the list of running containers is refreshed. Containers which start (or are
+/// recreated) after logging has begun are attached on the next refresh.
+const RESOLVE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How long sampling every container may take before sampling backs off. Each stats call blocks
+/// for roughly a second so this allows a couple of containers to be sampled every second.
+const CYCLE_BUDGET: Duration = Duration::from_secs(2);
+
+/// The operating system the Docker daemon runs containers on, which decides how container CPU
+/// usage is accounted.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Platform {
+    /// cgroup CPU time in nanoseconds, compared with the host's CPU time.
+    #[default]
+    Linux,
+    /// CPU time in 100ns intervals, compared with the time between the two stats reads.
+    Windows,
+}
+impl Platform {
+    /// The platform from the `OSType` the daemon reports, Linux if it isn't recognised.
+    fn from_os_type(os_type: Option<&str>) -> Self {
+        match os_type {
+            Some(os_type) if os_type.eq_ignore_ascii_case("windows") => Platform::Windows,
+            _ => Platform::Linux,
+        }
+    }
+}
+
+/// The parts of the Docker API containers are sampled through, implemented by the bollard client.
+/// Tests implement it to simulate container stats without a Docker daemon.
+#[async_trait]
+pub trait DockerApi: Send + Sync {
+    /// Returns (name, id) pairs for every running container.
+    async fn running_containers(&self) -> anyhow::Result<Vec<(String, String)>>;
+
+    /// A single stats sample of the container with the given id.
+    async fn stats(&self, id: &str) -> anyhow::Result<Stats>;
+
+    /// The platform containers run on.
+    async fn platform(&self) -> anyhow::Result<Platform> {
+        Ok(Platform::Linux)
+    }
+
+    /// Records start, stop and OOM events of the named containers in the background.
+    ///
+    /// # Returns
+    ///
+    /// The background task, or None if events aren't available.
+    fn watch_events(
+        &self,
+        _container_names: Vec<String>,
+        _log: LogBuffer,
+    ) -> Option<JoinHandle<()>> {
+        None
+    }
+}
+#[async_trait]
+impl DockerApi for Docker {
+    async fn running_containers(&self) -> anyhow::Result<Vec<(String, String)>> {
+        list_running_containers(self).await
+    }
+
+    async fn stats(&self, id: &str) -> anyhow::Result<Stats> {
+        let stats = Docker::stats(
+            self,
+            id,
+            Some(StatsOptions {
+                stream: false,
+                one_shot: false,
+            }),
+        )
+        .next()
+        .await
+        .context(format!("No stats returned for container {id}"))??;
+        Ok(stats)
+    }
+
+    async fn platform(&self) -> anyhow::Result<Platform> {
+        let info = self
+            .info()
+            .await
+            .context("Unable to read docker daemon info")?;
+        Ok(Platform::from_os_type(info.os_type.as_deref()))
+    }
+
+    fn watch_events(&self, container_names: Vec<String>, log: LogBuffer) -> Option<JoinHandle<()>> {
+        let docker = self.clone();
+        Some(tokio::spawn(async move {
+            watch_events(&docker, &container_names, log).await
+        }))
+    }
+}
+
+/// Keeps track of which of the requested containers are currently running and should be sampled.
+#[derive(Debug)]
+pub(super) struct ContainerTracker {
+    names: Vec<String>,
+    attached: HashMap<String, String>,
+}
+impl ContainerTracker {
+    pub(super) fn new(names: Vec<String>) -> Self {
+        Self {
+            names,
+            attached: HashMap::new(),
+        }
+    }
+
+    /// Updates the set of attached containers from a listing of running containers.
+    ///
+    /// # Arguments
+    ///
+    /// * `running` - (name, id) pairs for every running container
+    ///
+    /// # Returns
+    ///
+    /// A tuple containing the names of newly attached containers and the names of containers
+    /// which are no longer running. A container which has been recreated under the same name
+    /// (and therefore has a new id) appears in both.
+    pub(super) fn resolve(&mut self, running: &[(String, String)]) -> (Vec<String>, Vec<String>) {
+        let mut attached = vec![];
+        let mut detached = vec![];
+
+        for name in self.names.iter() {
+            let running_id = running
+                .iter()
+                .find(|(running_name, _)| running_name == name)
+                .map(|(_, id)| id);
+
+            match (self.attached.get(name), running_id) {
+                (None, Some(id)) => {
+                    self.attached.insert(name.clone(), id.clone());
+                    attached.push(name.clone());
+                }
+                (Some(old_id), Some(id)) if old_id != id => {
+                    self.attached.insert(name.clone(), id.clone());
+                    detached.push(name.clone());
+                    attached.push(name.clone());
+                }
+                (Some(_), None) => {
+                    self.attached.remove(name);
+                    detached.push(name.clone());
+                }
+                _ => {}
+            }
+        }
+
+        (attached, detached)
+    }
+
+    /// Stops sampling the given container until it is seen running again.
+    pub(super) fn detach(&mut self, name: &str) {
+        self.attached.remove(name);
+    }
+
+    /// Returns the (name, id) pairs of all the containers currently being sampled.
+    pub(super) fn attached(&self) -> Vec<(String, String)> {
+        self.attached
+            .iter()
+            .map(|(name, id)| (name.clone(), id.clone()))
+            .collect()
+    }
+}
+
+/// Samples containers through the Docker stats API.
 ///
-/// This function does not return, it requires that it's thread is cancelled.
-pub async fn keep_logging(_container_names: Vec<String>, _metrics_log: Arc<Mutex<MetricsLog>>) {
-    todo!()
-    /*
-    let mut buffer: Vec<CpuStats> = vec![];
-    let mut i = 0;
-    loop {
-        // generate random number (this will be replaced by call to sysinfo)
-        // TODO: replace 1338 with actual data
-        buffer.push(1338);
-
-        // if buffer is full then write to shared metrics log
-        if i == 9 {
-            let mut metrics_log = metrics_log.lock().expect("");
-            metrics_log.append(&mut buffer);
-            println!("hello from docker");
-
-            i = 0;
-            buffer.clear();
-        } else {
-            i += 1;
+/// Containers are re-resolved periodically so containers which are not running when logging
+/// begins, or which are restarted mid-scenario, are sampled as soon as they are running. Container
+/// starts, stops and OOM kills are recorded from the events API in the background as process
+/// events so gaps in the metrics can be explained.
+pub struct DockerCollector {
+    container_names: Vec<String>,
+    docker: Option<Box<dyn DockerApi>>,
+    platform: Platform,
+    clock: Arc<dyn Clock>,
+    tracker: ContainerTracker,
+    last_resolved: Option<Instant>,
+    pacer: SamplePacer,
+    events: Option<JoinHandle<()>>,
+}
+impl DockerCollector {
+    /// A collector which connects to the local Docker daemon when it starts.
+    pub fn new(container_names: Vec<String>) -> Self {
+        Self {
+            tracker: ContainerTracker::new(container_names.clone()),
+            container_names,
+            docker: None,
+            platform: Platform::default(),
+            clock: Arc::new(SystemClock),
+            last_resolved: None,
+            pacer: SamplePacer::new(CYCLE_BUDGET),
+            events: None,
+        }
+    }
+
+    /// Samples through the given Docker API rather than connecting to the local daemon.
+    pub fn with_api(mut self, docker: Box<dyn DockerApi>) -> Self {
+        self.docker = Some(docker);
+        self
+    }
+
+    /// Paces and timestamps samples with the given clock rather than the system clock.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.pacer = SamplePacer::new(CYCLE_BUDGET).with_clock(clock.clone());
+        self.clock = clock;
+        self
+    }
+}
+#[async_trait]
+impl MetricsCollector for DockerCollector {
+    fn describe(&self) -> String {
+        format!("containers {:?}", self.container_names)
+    }
+
+    async fn start(&mut self, log: &mut LogBuffer) -> anyhow::Result<()> {
+        let docker = match self.docker.take() {
+            Some(docker) => docker,
+            None => Box::new(Docker::connect_with_defaults()?),
+        };
+
+        self.platform = match docker.platform().await {
+            Ok(platform) => platform,
+            Err(err) => {
+                tracing::warn!("{err}, assuming Linux containers");
+                Platform::Linux
+            }
+        };
+        self.events = docker.watch_events(self.container_names.clone(), log.sibling());
+        self.docker = Some(docker);
+
+        Ok(())
+    }
+
+    async fn sample(&mut self, log: &mut LogBuffer) -> Duration {
+        let Some(docker) = self.docker.as_ref() else {
+            return sample_interval();
+        };
+
+        if self
+            .last_resolved
+            .is_none_or(|t| self.clock.now().duration_since(t) >= RESOLVE_INTERVAL)
+        {
+            match docker.running_containers().await {
+                Ok(running) => {
+                    let (attached, detached) = self.tracker.resolve(&running);
+                    for name in detached {
+                        tracing::info!("Container {name} stopped, detaching");
+                    }
+                    for name in attached {
+                        tracing::info!("Container {name} running, attaching");
+                    }
+                }
+                Err(err) => log.push_error(err),
+            }
+            self.last_resolved = Some(self.clock.now());
+        }
+
+        let attached = self.tracker.attached();
+        if attached.is_empty() {
+            // nothing was sampled so the next samples only cover the time after this
+            self.pacer.start_cycle();
+            return sample_interval();
+        }
+
+        // each stats call blocks for roughly a second while docker computes the cpu delta
+        let sample_interval = self.pacer.start_cycle();
+        let started = self.clock.now();
+        for (name, id) in attached {
+            match get_metrics(
+                docker.as_ref(),
+                self.platform,
+                &name,
+                &id,
+                self.clock.timestamp(),
+            )
+            .await
+            {
+                Ok(metrics) => log.push_metrics(CpuMetrics {
+                    sample_interval: Some(sample_interval),
+                    ..metrics
+                }),
+
+                // the container may have stopped since it was last resolved, stop sampling it
+                // until it's seen running again
+                Err(err) => {
+                    tracing::warn!("Unable to sample container {name}, detaching: {err}");
+                    self.tracker.detach(&name);
+                }
+            }
         }
+        self.pacer
+            .finish_cycle(self.clock.now().duration_since(started))
+    }
 
-        // simulate waiting for more metrics
-        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+    async fn stop(&mut self, _log: &mut LogBuffer) {
+        // wait for the task to finish aborting so its log is dropped before the loggers stop
+        if let Some(events) = self.events.take() {
+            events.abort();
+            let _ = events.await;
+        }
     }
-        */
 }
 
-// mod common {
-//     use bollard::container::{
-//         Config, CreateContainerOptions, ListContainersOptions, StartContainerOptions,
-//     };
-//     use bollard::models::HostConfig;
-//     use bollard::Docker;
-//     use futures_util::stream::StreamExt;
-//
-//     pub async fn create_container(
-//         docker: &Docker,
-//         image_name: &str,
-//         container_name: &str,
-//         port: Option<u16>,
-//     ) -> Result<String, Box<dyn std::error::Error>> {
-//         // Create tiny-image (Just an HTTP server, could be any program that stays up)
-//         let mut image_stream = docker.create_image(
-//             Some(bollard::image::CreateImageOptions {
-//                 from_image: image_name,
-//                 ..Default::default()
-//             }),
-//             None,
-//             None,
-//         );
-//         while let Some(info) = image_stream.next().await {
-//             if let Err(err) = info {
-//                 eprintln!("Error creating image: {}", err);
-//                 break;
-//             }
-//         }
-//
-//         // Run the HTTP server (image is created)
-//         let mut container_config = Config {
-//             image: Some(image_name.to_string()),
-//             cmd: Some(vec![
-//                 "/bin/sh".to_string(),
-//                 "-c".to_string(),
-//                 "python -m http.server 80".to_string(),
-//             ]),
-//             host_config: Some(HostConfig {
-//                 auto_remove: Some(true),
-//                 ..Default::default()
-//             }),
-//             ..Default::default()
-//         };
-//
-//         if let Some(port) = port {
-//             container_config.cmd = Some(vec![
-//                 "/bin/sh".to_string(),
-//                 "-c".to_string(),
-//                 format!("python -m http.server {}", port),
-//             ]);
-//             container_config.exposed_ports = Some(std::collections::HashMap::from([(
-//                 format!("{}/tcp", port),
-//                 std::collections::HashMap::new(),
-//             )]));
-//             container_config.host_config = Some(HostConfig {
-//                 auto_remove: Some(true),
-//                 port_bindings: Some(std::collections::HashMap::from([(
-//                     format!("{}/tcp", port),
-//                     Some(vec![bollard::models::PortBinding {
-//                         host_ip: Some("0.0.0.0".to_string()),
-//                         host_port: Some(port.to_string()),
-//                     }]),
-//                 )])),
-//                 ..Default::default()
-//             });
-//         }
-//
-//         let create_options = CreateContainerOptions {
-//             name: container_name.to_string(),
-//             ..Default::default()
-//         };
-//
-//         // Create a container with the specified name
-//         let container = docker
-//             .create_container(Some(create_options), container_config)
-//             .await?;
-//
-//         // Start the container
-//         docker
-//             .start_container(&container.id, None::<StartContainerOptions<String>>)
-//             .await?;
-//         Ok(container.id)
-//     }
-//     pub async fn cleanup_cardamon_containers(container_name: &str) {
-//         let docker = Docker::connect_with_defaults().unwrap();
-//         let container_list = docker
-//             .list_containers(None::<ListContainersOptions<String>>)
-//             .await
-//             .unwrap();
-//
-//         for container in container_list {
-//             if let Some(names) = container.names {
-//                 for name in names {
-//                     if name.starts_with(container_name) {
-//                         let container_id = container.id.clone().unwrap();
-//                         docker
-//                             .remove_container(
-//                                 &container_id,
-//                                 Some(bollard::container::RemoveContainerOptions {
-//                                     force: true,
-//                                     ..Default::default()
-//                                 }),
-//                             )
-//                             .await
-//                             .unwrap();
-//
-//                         // Wait for the container to be removed
-//                         docker
-//                             .wait_container(
-//                                 &container_id,
-//                                 None::<bollard::container::WaitContainerOptions<String>>,
-//                             )
-//                             .collect::<Vec<_>>()
-//                             .await;
-//                     }
-//                 }
-//             }
-//         }
-//     }
-// }
+/// Listens to the docker events API and records start, stop and OOM events for the observed
+/// containers. Event recording is best effort, if the event stream fails a warning is logged and
+/// sampling continues without it.
+async fn watch_events(docker: &Docker, container_names: &[String], mut log: LogBuffer) {
+    let mut filters = HashMap::new();
+    filters.insert("type", vec!["container"]);
+    filters.insert("event", vec!["start", "die", "oom"]);
+
+    let mut events = docker.events(Some(EventsOptions {
+        filters,
+        ..Default::default()
+    }));
+
+    while let Some(event) = events.next().await {
+        let event = match event {
+            Ok(event) => event,
+            Err(err) => {
+                tracing::warn!("Unable to watch docker events: {err}");
+                return;
+            }
+        };
+
+        let name = event
+            .actor
+            .and_then(|actor| actor.attributes)
+            .and_then(|mut attributes| attributes.remove("name"));
+        let kind = event.action.as_deref().and_then(event_kind);
+
+        if let (Some(name), Some(kind)) = (name, kind) {
+            if !container_names.contains(&name) {
+                continue;
+            }
+
+            if kind != ProcessEventKind::Started {
+                tracing::warn!("Container {name} {}", kind.as_str());
+            }
+
+            let timestamp = match event.time_nano {
+                Some(nanos) => nanos / 1_000_000,
+                None => event.time.unwrap_or(0) * 1000,
+            };
+
+            log.push_event(ProcessEvent {
+                process_name: name,
+                kind,
+                timestamp,
+            });
+            log.flush();
+        }
+    }
+}
+
+/// Maps a docker container event action onto a process event. Returns None for actions which
+/// don't change whether the container is running.
+fn event_kind(action: &str) -> Option<ProcessEventKind> {
+    match action {
+        "start" => Some(ProcessEventKind::Started),
+        "die" => Some(ProcessEventKind::Stopped),
+        "oom" => Some(ProcessEventKind::OomKilled),
+        _ => None,
+    }
+}
+
+/// Returns (name, id) pairs for every running container. Docker prefixes container names with
+/// a `/` which is stripped here.
+async fn list_running_containers(docker: &Docker) -> anyhow::Result<Vec<(String, String)>> {
+    let mut filters = HashMap::new();
+    filters.insert("status", vec!["running"]);
+
+    let containers = docker
+        .list_containers(Some(ListContainersOptions {
+            filters,
+            ..Default::default()
+        }))
+        .await
+        .context("Unable to list docker containers")?;
+
+    Ok(containers
+        .into_iter()
+        .flat_map(|container| {
+            let id = container.id.unwrap_or_default();
+            container
+                .names
+                .unwrap_or_default()
+                .into_iter()
+                .map(move |name| (name.trim_start_matches('/').to_string(), id.clone()))
+        })
+        .collect())
+}
+
+async fn get_metrics(
+    docker: &dyn DockerApi,
+    platform: Platform,
+    name: &str,
+    id: &str,
+    timestamp: i64,
+) -> anyhow::Result<CpuMetrics> {
+    let stats = docker.stats(id).await?;
+
+    let (cpu_usage, core_count, memory_usage, throttling) = match platform {
+        Platform::Linux => (
+            calculate_cpu_usage(&stats),
+            stats.cpu_stats.online_cpus.unwrap_or(0),
+            stats.memory_stats.usage,
+            Some(calculate_throttling(&stats)),
+        ),
+        // windows containers don't report cpu quota enforcement
+        Platform::Windows => (
+            calculate_windows_cpu_usage(&stats),
+            stats.num_procs as u64,
+            stats.memory_stats.privateworkingset,
+            None,
+        ),
+    };
+
+    Ok(CpuMetrics {
+        process_id: id.to_string(),
+        process_name: name.to_string(),
+        cpu_usage,
+        core_count: core_count as i32,
+        timestamp,
+        // docker doesn't report cpu frequency
+        cpu_frequency: None,
+        memory_usage: memory_usage.map(|usage| usage as i64),
+        power: None,
+        sample_interval: None,
+        memory_limit: stats.memory_stats.limit.map(|limit| limit as i64),
+        cpu_periods: throttling.map(|(periods, _)| periods),
+        throttled_periods: throttling.map(|(_, throttled)| throttled),
+    })
+}
 
 // cpu_usage = (cpu_delta / system_delta) * number_cpus * 100.0
 // Delta is calculated via the previous stats, docker records this
-// If it's our first check, we have to check twice
-async fn _get_metrics(_container_names: Vec<String>) -> anyhow::Result<CpuMetrics> {
-    todo!()
-    // let docker = Docker::connect_with_defaults().map_err(CPUError::DockerBollardError)?;
-    // let mut filter = HashMap::new();
-    // filter.insert(String::from("status"), vec![String::from("running")]);
-    //
-    // let containers = &docker
-    //     .list_containers(Some(ListContainersOptions {
-    //         all: true,
-    //         filters: filter,
-    //         ..Default::default()
-    //     }))
-    //     .await
-    //     .map_err(CPUError::DockerBollardError)?;
-    //
-    // let mut stats = Vec::new();
-    //
-    // for container in containers {
-    //     let container_name = container.names.as_ref().unwrap()[0].clone();
-    //     let container_id = container.id.as_ref().unwrap().clone();
-    //     let matches = match &docker_type {
-    //         //Container names are formatted
-    //         DockerType::ContainerName(name) => format!("/{}", *name) == container_name,
-    //         DockerType::ContainerID(id) => container_id == *id,
-    //         DockerType::Port(port) => {
-    //             if let Some(ports) = &container.ports {
-    //                 ports.iter().any(|p| p.private_port == *port)
-    //             } else {
-    //                 false
-    //             }
-    //         }
-    //     };
-    //
-    //     if matches {
-    //         let stats_response = docker
-    //             .stats(&container.id.as_ref().unwrap(), None)
-    //             .try_next()
-    //             .await
-    //             .map_err(CPUError::DockerBollardError)?
-    //             .ok_or_else(|| {
-    //                 CPUError::ProcessNotFound(format!(
-    //                     "stat response failed container_id -> {}",
-    //                     container_id
-    //                 ))
-    //             })?;
-    //
-    //         let cpu_delta = stats_response.cpu_stats.cpu_usage.total_usage
-    //             - stats_response.precpu_stats.cpu_usage.total_usage;
-    //         let system_delta = stats_response
-    //             .cpu_stats
-    //             .system_cpu_usage
-    //             .zip(stats_response.precpu_stats.system_cpu_usage)
-    //             .map(|(current, previous)| current - previous);
-    //
-    //         // If we don't return 0 values, it appears to return "inf" psosiblity due to diving by
-    //         // zero on line where we do
-    //         //let cpu_usage = (cpu_delta as f64 / system_delta as f64) * number_cpus * 100.0;
-    //         let system_delta = match system_delta {
-    //             Some(delta) => delta,
-    //             None => {
-    //                 return Ok(CPUStatus {
-    //                     stats: vec![Stat {
-    //                         id: container_id,
-    //                         name: container_name,
-    //                         total_usage: 0.0,
-    //                         usage_by_process: 0.0,
-    //                         core_count: stats_response.cpu_stats.online_cpus.unwrap_or(0) as f32,
-    //                     }],
-    //                 });
-    //             }
-    //         };
-    //         let number_cpus = stats_response.cpu_stats.online_cpus.unwrap_or(0) as f64;
-    //         let cpu_usage = (cpu_delta as f64 / system_delta as f64) * number_cpus * 100.0;
-    //
-    //         let stat = Stat {
-    //             id: container_id,
-    //             name: container_name,
-    //             usage_by_process: cpu_usage as f32,
-    //             total_usage: cpu_usage as f32,
-    //             core_count: stats_response.cpu_stats.online_cpus.unwrap_or(0) as f32,
-    //         };
-    //
-    //         stats.push(stat);
-    //     }
-    // }
-    // if stats.is_empty() {
-    //     match docker_type {
-    //         DockerType::ContainerName(name) => Err(CPUError::ContainerNameNotFound(format!(
-    //             "container_name -> {}",
-    //             name
-    //         ))),
-    //         DockerType::ContainerID(id) => Err(CPUError::ContainerIDNotFound(format!(
-    //             "container_id -> {}",
-    //             id
-    //         ))),
-    //         DockerType::Port(port) => Err(CPUError::PortNotFound(format!("port -> {}", port))),
-    //     }
-    // } else {
-    //     Ok(CPUStatus { stats })
-    // }
+fn calculate_cpu_usage(stats: &Stats) -> f64 {
+    let cpu_delta = stats
+        .cpu_stats
+        .cpu_usage
+        .total_usage
+        .saturating_sub(stats.precpu_stats.cpu_usage.total_usage);
+    let system_delta = stats
+        .cpu_stats
+        .system_cpu_usage
+        .zip(stats.precpu_stats.system_cpu_usage)
+        .map(|(current, previous)| current.saturating_sub(previous));
+    let number_cpus = stats.cpu_stats.online_cpus.unwrap_or(0);
+
+    cpu_usage_percent(cpu_delta, system_delta, number_cpus)
+}
+
+/// The CPU quota periods which elapsed since the previous stats and how many of them the container
+/// was throttled in. Both are zero if the container has no CPU quota.
+fn calculate_throttling(stats: &Stats) -> (i64, i64) {
+    let current = &stats.cpu_stats.throttling_data;
+    let previous = &stats.precpu_stats.throttling_data;
+    (
+        current.periods.saturating_sub(previous.periods) as i64,
+        current
+            .throttled_periods
+            .saturating_sub(previous.throttled_periods) as i64,
+    )
+}
+
+// cpu_usage = (cpu_delta / read_delta) * 100.0
+// Windows doesn't report the host's cpu time, the cpu time used (in 100ns intervals) is compared
+// with the time between this read and the previous one instead
+fn calculate_windows_cpu_usage(stats: &Stats) -> f64 {
+    let cpu_delta = stats
+        .cpu_stats
+        .cpu_usage
+        .total_usage
+        .saturating_sub(stats.precpu_stats.cpu_usage.total_usage);
+    let read_delta = chrono::DateTime::parse_from_rfc3339(&stats.read)
+        .ok()
+        .zip(chrono::DateTime::parse_from_rfc3339(&stats.preread).ok())
+        .and_then(|(read, preread)| (read - preread).num_nanoseconds())
+        .and_then(|nanos| u64::try_from(nanos / 100).ok());
+
+    cpu_usage_percent(cpu_delta, read_delta, 1)
+}
+
+fn cpu_usage_percent(cpu_delta: u64, system_delta: Option<u64>, number_cpus: u64) -> f64 {
+    match system_delta {
+        // the first stats sample has no previous sample to diff against
+        None | Some(0) => 0.0,
+        Some(system_delta) => (cpu_delta as f64 / system_delta as f64) * number_cpus as f64 * 100.0,
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    //     use crate::metrics::common::*;
-    //     use crate::metrics::start::get_metrics;
-    //
-    //     use super::*;
-    //     // Tests related to docker.rs
-    //     // ...
-    //
-    //     use bollard::container::ListContainersOptions;
-    //     use bollard::Docker;
-    //     use uuid::Uuid;
-    //
-    //     use crate::metrics::types::CPUType::*;
-    //     #[tokio::test]
-    //     async fn docker_invalid_container_name() {
-    //         let docker = Docker::connect_with_defaults().unwrap();
-    //         // Need to check it's an invalid container name
-    //         let mut container_name = format!("cardamon-test-container-{}", Uuid::new_v4().to_string());
-    //
-    //         let container_list = docker
-    //             .list_containers(None::<ListContainersOptions<String>>)
-    //             .await
-    //             .unwrap();
-    //         // Create another if it exists
-    //         while container_list.iter().any(|container| {
-    //             container.names.as_ref().map_or(false, |names| {
-    //                 names
-    //                     .iter()
-    //                     .any(|name| name == &format!("/{}", container_name))
-    //             })
-    //         }) {
-    //             container_name = format!("cardamon-test-container-{}", Uuid::new_v4().to_string());
-    //         }
-    //
-    //         let result = get_metrics(DockerStats(DockerType::ContainerName(
-    //             container_name.to_string(),
-    //         )))
-    //         .await;
-    //
-    //         assert!(result.is_err());
-    //     }
-    //
-    //     #[tokio::test]
-    //     async fn docker_valid_container_name() {
-    //         let docker = Docker::connect_with_defaults().unwrap();
-    //         let container_name = format!(
-    //             "cardamon-test-valid-container-name-{}",
-    //             Uuid::new_v4().to_string()
-    //         );
-    //         let image_name = "python:3-alpine";
-    //
-    //         // Create and start the container
-    //         let _container_id = create_container(&docker, image_name, &container_name, None)
-    //             .await
-    //             .unwrap();
-    //
-    //         // Get metrics
-    //         let result = get_metrics(DockerStats(DockerType::ContainerName(
-    //             container_name.to_string(),
-    //         )))
-    //         .await;
-    //         assert!(result.is_ok(), "get_metrics failed: {:?}", result.err());
-    //         // Cleanup
-    //         cleanup_cardamon_containers("/cardamon-test-valid-container-name-").await;
-    //     }
-    //
-    //     #[tokio::test]
-    //     async fn test_invalid_container_name() {
-    //         let invalid_container_name = "".to_string();
-    //
-    //         // Get metrics using invalid container name
-    //         assert!(get_metrics(DockerStats(DockerType::ContainerName(
-    //             invalid_container_name
-    //         )))
-    //         .await
-    //         .is_err());
-    //     }
-    //
-    //     #[tokio::test]
-    //     async fn test_invalid_container_id() {
-    //         let invalid_container_id = "".to_string();
-    //
-    //         // Get metrics using invalid container ID
-    //         assert!(
-    //             get_metrics(DockerStats(DockerType::ContainerID(invalid_container_id)))
-    //                 .await
-    //                 .is_err()
-    //         );
-    //     }
-    //
-    //     #[tokio::test]
-    //     async fn test_valid_port() {
-    //         let docker = Docker::connect_with_defaults().unwrap();
-    //         let container_name = format!("cardamon-test-valid-port-{}", Uuid::new_v4().to_string());
-    //         let image_name = "python:3-alpine";
-    //         let mut port = 8000;
-    //
-    //         // Iterate over ports until a free one is found
-    //         while is_port_in_use(port).await {
-    //             port += 1;
-    //         }
-    //
-    //         let _container_id = create_container(&docker, image_name, &container_name, Some(port))
-    //             .await
-    //             .unwrap();
-    //         //Could be a one-liner, but easier to debug if we can print
-    //         let result = get_metrics(DockerStats(DockerType::Port(port))).await;
-    //         //println!("{:?}", result);
-    //         assert!(result.is_ok());
-    //
-    //         // Cleanup
-    //         cleanup_cardamon_containers("/cardamon-test-valid-port-").await;
-    //     }
-    //
-    //     async fn is_port_in_use(port: u16) -> bool {
-    //         use tokio::net::TcpListener;
-    //
-    //         match TcpListener::bind(("0.0.0.0", port)).await {
-    //             Ok(_) => false,
-    //             Err(_) => true,
-    //         }
-    //     }
-    //     #[tokio::test]
-    //     async fn test_invalid_port() {
-    //         let invalid_port = 9999;
-    //
-    //         // Get metrics using invalid port
-    //         assert!(get_metrics(DockerStats(DockerType::Port(invalid_port)))
-    //             .await
-    //             .is_err());
-    //     }
+    use super::*;
+    use crate::{metrics::MetricsLog, metrics_logger::clock::ManualClock};
+    use std::sync::Mutex;
+
+    /// Serves the same stats for every running container.
+    struct FakeDocker {
+        running: Arc<Mutex<Vec<(String, String)>>>,
+        platform: Platform,
+    }
+    #[async_trait]
+    impl DockerApi for FakeDocker {
+        async fn running_containers(&self) -> anyhow::Result<Vec<(String, String)>> {
+            Ok(self.running.lock().unwrap().clone())
+        }
+
+        async fn stats(&self, id: &str) -> anyhow::Result<Stats> {
+            if !self
+                .running
+                .lock()
+                .unwrap()
+                .iter()
+                .any(|(_, running)| running == id)
+            {
+                anyhow::bail!("No such container: {id}");
+            }
+            match self.platform {
+                Platform::Linux => linux_stats(),
+                Platform::Windows => windows_stats(),
+            }
+        }
+
+        async fn platform(&self) -> anyhow::Result<Platform> {
+            Ok(self.platform)
+        }
+
+        /// Holds on to the log until it's aborted, like the events API stream.
+        fn watch_events(
+            &self,
+            _container_names: Vec<String>,
+            log: LogBuffer,
+        ) -> Option<JoinHandle<()>> {
+            Some(tokio::spawn(async move {
+                let _log = log;
+                std::future::pending::<()>().await
+            }))
+        }
+    }
+
+    fn cpu_stats(
+        total_usage: u64,
+        system_cpu_usage: Option<u64>,
+        throttling: (u64, u64),
+    ) -> serde_json::Value {
+        serde_json::json!({
+            "cpu_usage": {
+                "total_usage": total_usage,
+                "usage_in_usermode": total_usage,
+                "usage_in_kernelmode": 0
+            },
+            "system_cpu_usage": system_cpu_usage,
+            "online_cpus": system_cpu_usage.map(|_| 4),
+            "throttling_data": {
+                "periods": throttling.0,
+                "throttled_periods": throttling.1,
+                "throttled_time": 0
+            }
+        })
+    }
+
+    /// 50 of the 200 cpu ticks on 4 cpus since the previous sample is one whole cpu, throttled in 3
+    /// of the 10 quota periods.
+    fn linux_stats() -> anyhow::Result<Stats> {
+        Ok(serde_json::from_value(serde_json::json!({
+            "read": "2024-07-15T09:00:01Z",
+            "preread": "2024-07-15T09:00:00Z",
+            "num_procs": 0,
+            "pids_stats": {},
+            "memory_stats": { "usage": 1024, "limit": 4096 },
+            "blkio_stats": {},
+            "cpu_stats": cpu_stats(150, Some(1200), (110, 8)),
+            "precpu_stats": cpu_stats(100, Some(1000), (100, 5)),
+            "storage_stats": {}
+        }))?)
+    }
+
+    /// 2 seconds of cpu time (in 100ns intervals) in the 1 second between reads is two whole cpus.
+    fn windows_stats() -> anyhow::Result<Stats> {
+        Ok(serde_json::from_value(serde_json::json!({
+            "read": "2024-07-15T09:00:01.5000000Z",
+            "preread": "2024-07-15T09:00:00.5000000Z",
+            "num_procs": 4,
+            "pids_stats": {},
+            "memory_stats": { "commitbytes": 4096, "privateworkingset": 2048 },
+            "blkio_stats": {},
+            "cpu_stats": cpu_stats(30_000_000, None, (0, 0)),
+            "precpu_stats": cpu_stats(10_000_000, None, (0, 0)),
+            "storage_stats": {}
+        }))?)
+    }
+
+    /// The container and timestamp of every sample flushed to the shared log.
+    fn sampled(shared: &Mutex<MetricsLog>) -> Vec<(String, i64)> {
+        let mut sampled = shared
+            .lock()
+            .unwrap()
+            .get_metrics()
+            .iter()
+            .map(|metrics| (metrics.process_name.clone(), metrics.timestamp))
+            .collect::<Vec<_>>();
+        sampled.sort();
+        sampled
+    }
+
+    #[tokio::test]
+    async fn containers_can_be_sampled_without_a_daemon() -> anyhow::Result<()> {
+        let running_containers = Arc::new(Mutex::new(running(&[("db", "1")])));
+        let clock = ManualClock::new(1_000);
+        let mut collector = DockerCollector::new(vec!["db".to_string(), "web".to_string()])
+            .with_api(Box::new(FakeDocker {
+                running: running_containers.clone(),
+                platform: Platform::Linux,
+            }))
+            .with_clock(clock.clone());
+
+        let shared = Arc::new(Mutex::new(MetricsLog::new()));
+        let mut log = LogBuffer::new(shared.clone());
+        collector.start(&mut log).await?;
+
+        // db is running when sampling starts
+        assert_eq!(collector.sample(&mut log).await, Duration::from_secs(1));
+        log.flush();
+        assert_eq!(sampled(&shared), vec![("db".to_string(), 1_000)]);
+        {
+            let shared = shared.lock().unwrap();
+            let metrics = &shared.get_metrics()[0];
+            assert_eq!(metrics.cpu_usage, 100.0);
+            assert_eq!(metrics.memory_usage, Some(1024));
+            assert_eq!(metrics.sample_interval, Some(1.0));
+            assert_eq!(metrics.memory_limit, Some(4096));
+            assert_eq!(metrics.cpu_periods, Some(10));
+            assert_eq!(metrics.throttled_periods, Some(3));
+        }
+
+        // web starts but isn't attached until containers are resolved again
+        running_containers
+            .lock()
+            .unwrap()
+            .push(("web".to_string(), "2".to_string()));
+        clock.advance(Duration::from_secs(1));
+        collector.sample(&mut log).await;
+        log.flush();
+        assert_eq!(sampled(&shared).len(), 2);
+
+        clock.advance(Duration::from_secs(1));
+        collector.sample(&mut log).await;
+        log.flush();
+        assert!(sampled(&shared).contains(&("web".to_string(), 3_000)));
+
+        // db stops between resolves and is detached when it can't be sampled
+        running_containers
+            .lock()
+            .unwrap()
+            .retain(|(name, _)| name != "db");
+        clock.advance(Duration::from_secs(1));
+        collector.sample(&mut log).await;
+        log.flush();
+        assert!(!sampled(&shared).contains(&("db".to_string(), 4_000)));
+        assert!(sampled(&shared).contains(&("web".to_string(), 4_000)));
+        assert_eq!(collector.tracker.attached(), running(&[("web", "2")]));
+
+        collector.stop(&mut log).await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn stopping_drops_the_events_log() -> anyhow::Result<()> {
+        let mut collector =
+            DockerCollector::new(vec!["db".to_string()]).with_api(Box::new(FakeDocker {
+                running: Arc::new(Mutex::new(vec![])),
+                platform: Platform::Linux,
+            }));
+
+        let shared = Arc::new(Mutex::new(MetricsLog::new()));
+        let mut log = LogBuffer::new(shared.clone());
+        collector.start(&mut log).await?;
+        assert!(collector.events.is_some());
+
+        collector.stop(&mut log).await;
+        drop(log);
+        assert!(Arc::try_unwrap(shared).is_ok());
+        Ok(())
+    }
+
+    fn running(containers: &[(&str, &str)]) -> Vec<(String, String)> {
+        containers
+            .iter()
+            .map(|(name, id)| (name.to_string(), id.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn late_starting_containers_are_attached() {
+        let mut tracker = ContainerTracker::new(vec!["db".to_string(), "web".to_string()]);
+
+        // nothing is running yet
+        let (attached, detached) = tracker.resolve(&running(&[("other", "0")]));
+        assert!(attached.is_empty());
+        assert!(detached.is_empty());
+        assert!(tracker.attached().is_empty());
+
+        // db starts
+        let (attached, _) = tracker.resolve(&running(&[("other", "0"), ("db", "1")]));
+        assert_eq!(attached, vec!["db"]);
+
+        // web starts later
+        let (attached, _) = tracker.resolve(&running(&[("db", "1"), ("web", "2")]));
+        assert_eq!(attached, vec!["web"]);
+        assert_eq!(tracker.attached().len(), 2);
+    }
+
+    #[test]
+    fn stopped_and_recreated_containers_are_detected() {
+        let mut tracker = ContainerTracker::new(vec!["db".to_string()]);
+        tracker.resolve(&running(&[("db", "1")]));
+
+        // recreated with a new id
+        let (attached, detached) = tracker.resolve(&running(&[("db", "2")]));
+        assert_eq!(attached, vec!["db"]);
+        assert_eq!(detached, vec!["db"]);
+        assert_eq!(tracker.attached(), running(&[("db", "2")]));
+
+        // stopped
+        let (attached, detached) = tracker.resolve(&[]);
+        assert!(attached.is_empty());
+        assert_eq!(detached, vec!["db"]);
+        assert!(tracker.attached().is_empty());
+    }
+
+    #[test]
+    fn detached_containers_are_reattached_when_seen_again() {
+        let mut tracker = ContainerTracker::new(vec!["db".to_string()]);
+        tracker.resolve(&running(&[("db", "1")]));
+        tracker.detach("db");
+        assert!(tracker.attached().is_empty());
+
+        let (attached, _) = tracker.resolve(&running(&[("db", "1")]));
+        assert_eq!(attached, vec!["db"]);
+    }
+
+    #[test]
+    fn docker_actions_map_to_process_events() {
+        assert_eq!(event_kind("start"), Some(ProcessEventKind::Started));
+        assert_eq!(event_kind("die"), Some(ProcessEventKind::Stopped));
+        assert_eq!(event_kind("oom"), Some(ProcessEventKind::OomKilled));
+        assert_eq!(event_kind("exec_start"), None);
+    }
+
+    #[tokio::test]
+    async fn windows_containers_are_sampled_from_their_own_accounting() -> anyhow::Result<()> {
+        let mut collector = DockerCollector::new(vec!["iis".to_string()])
+            .with_api(Box::new(FakeDocker {
+                running: Arc::new(Mutex::new(running(&[("iis", "1")]))),
+                platform: Platform::Windows,
+            }))
+            .with_clock(ManualClock::new(1_000));
+
+        let shared = Arc::new(Mutex::new(MetricsLog::new()));
+        let mut log = LogBuffer::new(shared.clone());
+        collector.start(&mut log).await?;
+        assert_eq!(collector.platform, Platform::Windows);
+
+        collector.sample(&mut log).await;
+        log.flush();
+        let shared = shared.lock().unwrap();
+        let metrics = &shared.get_metrics()[0];
+        assert_eq!(metrics.cpu_usage, 200.0);
+        assert_eq!(metrics.core_count, 4);
+        assert_eq!(metrics.memory_usage, Some(2048));
+        assert_eq!(metrics.throttled_periods, None);
+
+        // windows stats have no host cpu time for the linux calculation to compare against
+        assert_eq!(calculate_cpu_usage(&windows_stats()?), 0.0);
+        Ok(())
+    }
+
+    #[test]
+    fn platform_is_read_from_the_daemon_os_type() {
+        assert_eq!(Platform::from_os_type(Some("windows")), Platform::Windows);
+        assert_eq!(Platform::from_os_type(Some("linux")), Platform::Linux);
+        assert_eq!(Platform::from_os_type(None), Platform::Linux);
+    }
+
+    #[test]
+    fn cpu_usage_is_zero_without_a_previous_sample() {
+        assert_eq!(cpu_usage_percent(100, None, 4), 0.0);
+        assert_eq!(cpu_usage_percent(100, Some(0), 4), 0.0);
+        assert_eq!(cpu_usage_percent(50, Some(200), 4), 100.0);
+    }
 }
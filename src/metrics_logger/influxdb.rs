@@ -0,0 +1,232 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Mirrors samples to an InfluxDB 2 bucket in line protocol as loggers flush them, for sites
+//! which keep all their time series in InfluxDB. Writing happens in the background and failed
+//! writes are only logged, the samples are still stored by cardamon as usual.
+
+use crate::{
+    config::InfluxDb,
+    metrics::{CpuMetrics, MetricsLog, PowerMetrics},
+};
+use anyhow::Context;
+use tokio::{
+    sync::mpsc::{self, UnboundedReceiver, UnboundedSender},
+    task::JoinHandle,
+    time::Duration,
+};
+
+const CPU_MEASUREMENT: &str = "cardamon_cpu";
+const POWER_MEASUREMENT: &str = "cardamon_power";
+
+/// The most lines sent in a single write, InfluxDB recommends batches of around 5000.
+const MAX_BATCH: usize = 5000;
+
+/// Writes the lines it's sent to InfluxDB until every [`Mirror`] of it is dropped.
+pub struct InfluxSink {
+    sender: UnboundedSender<Vec<String>>,
+    writer: JoinHandle<()>,
+    tags: String,
+}
+impl InfluxSink {
+    /// Starts writing to the bucket in the background, so it must be called within a tokio
+    /// runtime.
+    pub fn new(influxdb: &InfluxDb) -> anyhow::Result<Self> {
+        let token = influxdb
+            .token
+            .as_ref()
+            .map(|token| token.resolve())
+            .transpose()
+            .context("Unable to read the InfluxDB token")?;
+        let url = reqwest::Url::parse_with_params(
+            &format!("{}/api/v2/write", influxdb.url.trim_end_matches('/')),
+            [
+                ("org", influxdb.org.as_str()),
+                ("bucket", influxdb.bucket.as_str()),
+                ("precision", "ms"),
+            ],
+        )
+        .with_context(|| format!("InfluxDB url {} is invalid", influxdb.url))?;
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(5))
+            .build()
+            .expect("Should be able to build a http client");
+
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let writer = tokio::spawn(write_batches(client, url, token, receiver));
+        Ok(Self {
+            sender,
+            writer,
+            tags: String::new(),
+        })
+    }
+
+    /// Adds a tag to every point, e.g. the run the samples were taken in.
+    pub fn with_tag(mut self, key: &str, value: &str) -> Self {
+        self.tags
+            .push_str(&format!(",{}={}", escape_tag(key), escape_tag(value)));
+        self
+    }
+
+    /// A handle for a logger to send its samples through.
+    pub fn mirror(&self) -> Mirror {
+        Mirror {
+            sender: self.sender.clone(),
+            tags: self.tags.clone(),
+        }
+    }
+
+    /// Waits for everything sent so far to be written. The loggers must have stopped first, as
+    /// the writer only finishes once their mirrors are dropped.
+    pub async fn finish(self) {
+        drop(self.sender);
+        if let Err(err) = self.writer.await {
+            tracing::error!("InfluxDB writer failed: {err}");
+        }
+    }
+}
+
+/// Sends the samples a logger flushes to the [`InfluxSink`] it came from.
+#[derive(Clone)]
+pub struct Mirror {
+    sender: UnboundedSender<Vec<String>>,
+    tags: String,
+}
+impl Mirror {
+    pub fn send(&self, metrics_log: &MetricsLog) {
+        let lines = metrics_log
+            .get_metrics()
+            .iter()
+            .map(|metrics| cpu_line(metrics, &self.tags))
+            .chain(
+                metrics_log
+                    .get_power_metrics()
+                    .iter()
+                    .map(|power| power_line(power, &self.tags)),
+            )
+            .collect::<Vec<_>>();
+        if !lines.is_empty() {
+            // the writer only stops once every mirror is dropped
+            let _ = self.sender.send(lines);
+        }
+    }
+}
+
+async fn write_batches(
+    client: reqwest::Client,
+    url: reqwest::Url,
+    token: Option<String>,
+    mut receiver: UnboundedReceiver<Vec<String>>,
+) {
+    while let Some(mut batch) = receiver.recv().await {
+        while batch.len() < MAX_BATCH {
+            match receiver.try_recv() {
+                Ok(mut lines) => batch.append(&mut lines),
+                Err(_) => break,
+            }
+        }
+
+        let mut request = client.post(url.clone()).body(batch.join("\n"));
+        if let Some(token) = token.as_deref() {
+            request = request.header(reqwest::header::AUTHORIZATION, format!("Token {token}"));
+        }
+        let res = request.send().await.and_then(|res| res.error_for_status());
+        if let Err(err) = res {
+            tracing::warn!("Unable to write {} points to InfluxDB: {err}", batch.len());
+        }
+    }
+}
+
+fn cpu_line(metrics: &CpuMetrics, tags: &str) -> String {
+    let mut fields = vec![
+        format!("cpu_usage={}", metrics.cpu_usage),
+        format!("core_count={}i", metrics.core_count),
+    ];
+    let optional_fields = [
+        ("cpu_frequency", metrics.cpu_frequency),
+        ("memory_usage", metrics.memory_usage),
+        ("memory_limit", metrics.memory_limit),
+        ("cpu_periods", metrics.cpu_periods),
+        ("throttled_periods", metrics.throttled_periods),
+    ];
+    for (key, value) in optional_fields {
+        if let Some(value) = value {
+            fields.push(format!("{key}={value}i"));
+        }
+    }
+    if let Some(power) = metrics.power {
+        fields.push(format!("power={power}"));
+    }
+
+    format!(
+        "{CPU_MEASUREMENT},process_name={},process_id={}{tags} {} {}",
+        escape_tag(&metrics.process_name),
+        escape_tag(&metrics.process_id),
+        fields.join(","),
+        metrics.timestamp
+    )
+}
+
+fn power_line(power: &PowerMetrics, tags: &str) -> String {
+    format!(
+        "{POWER_MEASUREMENT},source={}{tags} power={} {}",
+        escape_tag(&power.source),
+        power.power,
+        power.timestamp
+    )
+}
+
+/// Tag keys and values can't contain unescaped commas, equals signs or spaces.
+fn escape_tag(tag: &str) -> String {
+    let tag = tag
+        .replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace('=', "\\=")
+        .replace(' ', "\\ ");
+    // empty tag values aren't allowed
+    if tag.is_empty() {
+        String::from("none")
+    } else {
+        tag
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_are_written_as_line_protocol() {
+        let metrics = CpuMetrics {
+            process_id: "1337".to_string(),
+            process_name: "my app".to_string(),
+            cpu_usage: 12.5,
+            core_count: 4,
+            timestamp: 1_700_000_000_000,
+            cpu_frequency: Some(2400),
+            memory_usage: None,
+            power: Some(3.25),
+            sample_interval: None,
+            memory_limit: None,
+            cpu_periods: None,
+            throttled_periods: None,
+        };
+        assert_eq!(
+            cpu_line(&metrics, ",run_id=abc"),
+            "cardamon_cpu,process_name=my\\ app,process_id=1337,run_id=abc cpu_usage=12.5,core_count=4i,cpu_frequency=2400i,power=3.25 1700000000000"
+        );
+
+        let power = PowerMetrics {
+            source: "plug,kitchen".to_string(),
+            power: 40.0,
+            timestamp: 1_700_000_000_000,
+        };
+        assert_eq!(
+            power_line(&power, ""),
+            "cardamon_power,source=plug\\,kitchen power=40 1700000000000"
+        );
+    }
+}
@@ -0,0 +1,291 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use super::{collector::MetricsCollector, sample_interval, LogBuffer};
+use crate::{config::PowerMeter, metrics::PowerMetrics, secrets};
+use anyhow::Context;
+use async_trait::async_trait;
+use serde_json::Value;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::Duration,
+};
+
+const KASA_PORT: u16 = 9999;
+const KASA_REALTIME: &str = r#"{"emeter":{"get_realtime":{}}}"#;
+
+/// Polls the power drawn by the whole machine from a smart plug or BMC.
+pub struct PowerMeterCollector {
+    power_meter: PowerMeter,
+    client: reqwest::Client,
+    /// The meter's password, read once when sampling starts.
+    password: Option<String>,
+}
+impl PowerMeterCollector {
+    pub fn new(power_meter: PowerMeter) -> Self {
+        let insecure = matches!(power_meter, PowerMeter::Redfish { insecure: true, .. });
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(2))
+            .danger_accept_invalid_certs(insecure)
+            .build()
+            .expect("Should be able to build a http client");
+
+        Self {
+            power_meter,
+            client,
+            password: None,
+        }
+    }
+}
+#[async_trait]
+impl MetricsCollector for PowerMeterCollector {
+    fn describe(&self) -> String {
+        format!("power meter {:?}", self.power_meter)
+    }
+
+    async fn start(&mut self, _log: &mut LogBuffer) -> anyhow::Result<()> {
+        self.password = match &self.power_meter {
+            PowerMeter::Redfish { password, .. } => Some(password.resolve()?),
+            PowerMeter::Ipmi {
+                password: Some(password),
+                ..
+            } => Some(password.resolve()?),
+            _ => None,
+        };
+        Ok(())
+    }
+
+    async fn sample(&mut self, log: &mut LogBuffer) -> Duration {
+        let power = read_power(&self.client, &self.power_meter, self.password.as_deref()).await;
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or(0);
+
+        match power {
+            Ok(power) => log.push_power(PowerMetrics {
+                source: self.power_meter.name().to_string(),
+                power,
+                timestamp,
+            }),
+            Err(err) => log.push_error(err),
+        }
+
+        sample_interval()
+    }
+}
+
+/// Reads the power currently drawn in watts.
+async fn read_power(
+    client: &reqwest::Client,
+    power_meter: &PowerMeter,
+    password: Option<&str>,
+) -> anyhow::Result<f64> {
+    match power_meter {
+        PowerMeter::Tasmota { host } => {
+            let status = client
+                .get(format!("http://{host}/cm?cmnd=Status%208"))
+                .send()
+                .await?
+                .json::<Value>()
+                .await
+                .context(format!("Unable to read power from Tasmota plug {host}"))?;
+            parse_tasmota(&status)
+        }
+
+        PowerMeter::Shelly { host } => {
+            let status = client
+                .get(format!("http://{host}/rpc/Switch.GetStatus?id=0"))
+                .send()
+                .await?
+                .json::<Value>()
+                .await
+                .context(format!("Unable to read power from Shelly plug {host}"))?;
+            parse_shelly(&status)
+        }
+
+        PowerMeter::Kasa { host } => {
+            let mut stream = TcpStream::connect((host.as_str(), KASA_PORT))
+                .await
+                .context(format!("Unable to connect to Kasa plug {host}"))?;
+            stream.write_all(&kasa_encrypt(KASA_REALTIME)).await?;
+
+            let len = stream.read_u32().await? as usize;
+            let mut response = vec![0; len];
+            stream.read_exact(&mut response).await?;
+
+            let realtime = serde_json::from_str::<Value>(&kasa_decrypt(&response))
+                .context(format!("Unable to read power from Kasa plug {host}"))?;
+            parse_kasa(&realtime)
+        }
+
+        PowerMeter::Redfish {
+            url,
+            chassis,
+            username,
+            ..
+        } => {
+            let power = client
+                .get(format!(
+                    "{}/redfish/v1/Chassis/{chassis}/Power",
+                    url.strip_suffix('/').unwrap_or(url)
+                ))
+                .basic_auth(username, password)
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<Value>()
+                .await
+                .with_context(|| {
+                    format!(
+                        "Unable to read power from Redfish BMC {}",
+                        secrets::redact_url(url)
+                    )
+                })?;
+            parse_redfish(&power)
+        }
+
+        PowerMeter::Ipmi { host, username, .. } => {
+            let mut command = tokio::process::Command::new("ipmitool");
+            if let Some(host) = host {
+                command.args(["-I", "lanplus", "-H", host]);
+            }
+            if let Some(username) = username {
+                command.args(["-U", username]);
+            }
+            // passed in the environment so it isn't visible in the process list
+            if let Some(password) = password {
+                command.arg("-E").env("IPMI_PASSWORD", password);
+            }
+
+            let output = command
+                .args(["dcmi", "power", "reading"])
+                .kill_on_drop(true)
+                .output()
+                .await
+                .context("Failed to run ipmitool, is it installed?")?;
+            if !output.status.success() {
+                return Err(anyhow::anyhow!(
+                    "ipmitool failed: {}",
+                    String::from_utf8_lossy(&output.stderr)
+                ));
+            }
+            parse_ipmi(&String::from_utf8_lossy(&output.stdout))
+        }
+    }
+}
+
+fn parse_tasmota(status: &Value) -> anyhow::Result<f64> {
+    status["StatusSNS"]["ENERGY"]["Power"]
+        .as_f64()
+        .context(format!("Tasmota status has no power reading: {status}"))
+}
+
+fn parse_shelly(status: &Value) -> anyhow::Result<f64> {
+    status["apower"]
+        .as_f64()
+        .context(format!("Shelly status has no power reading: {status}"))
+}
+
+/// Older Kasa hardware reports watts, newer hardware reports milliwatts.
+fn parse_kasa(realtime: &Value) -> anyhow::Result<f64> {
+    let realtime = &realtime["emeter"]["get_realtime"];
+    realtime["power"]
+        .as_f64()
+        .or_else(|| realtime["power_mw"].as_f64().map(|mw| mw / 1000.0))
+        .context(format!("Kasa response has no power reading: {realtime}"))
+}
+
+fn parse_redfish(power: &Value) -> anyhow::Result<f64> {
+    power["PowerControl"][0]["PowerConsumedWatts"]
+        .as_f64()
+        .context(format!("Redfish power has no consumed watts: {power}"))
+}
+
+/// Parses the output of `ipmitool dcmi power reading`.
+fn parse_ipmi(output: &str) -> anyhow::Result<f64> {
+    output
+        .lines()
+        .find_map(|line| {
+            let (label, reading) = line.split_once(':')?;
+            if label.trim() != "Instantaneous power reading" {
+                return None;
+            }
+            reading.split_whitespace().next()?.parse::<f64>().ok()
+        })
+        .context(format!("ipmitool output has no power reading: {output}"))
+}
+
+/// Kasa plugs use an autokey XOR cipher with a big endian length prefix.
+fn kasa_encrypt(plaintext: &str) -> Vec<u8> {
+    let mut key = 171_u8;
+    let mut message = (plaintext.len() as u32).to_be_bytes().to_vec();
+    for byte in plaintext.bytes() {
+        key ^= byte;
+        message.push(key);
+    }
+    message
+}
+
+fn kasa_decrypt(ciphertext: &[u8]) -> String {
+    let mut key = 171_u8;
+    let plaintext = ciphertext
+        .iter()
+        .map(|byte| {
+            let plain = key ^ byte;
+            key = *byte;
+            plain
+        })
+        .collect::<Vec<_>>();
+    String::from_utf8_lossy(&plaintext).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn power_can_be_parsed_from_each_plug() -> anyhow::Result<()> {
+        let tasmota = json!({ "StatusSNS": { "ENERGY": { "Power": 42.0, "Voltage": 230 } } });
+        assert_eq!(parse_tasmota(&tasmota)?, 42.0);
+
+        let shelly = json!({ "id": 0, "output": true, "apower": 12.5 });
+        assert_eq!(parse_shelly(&shelly)?, 12.5);
+
+        let kasa_v1 = json!({ "emeter": { "get_realtime": { "power": 7.5, "err_code": 0 } } });
+        assert_eq!(parse_kasa(&kasa_v1)?, 7.5);
+        let kasa_v2 = json!({ "emeter": { "get_realtime": { "power_mw": 7500, "err_code": 0 } } });
+        assert_eq!(parse_kasa(&kasa_v2)?, 7.5);
+
+        assert!(parse_shelly(&json!({ "id": 0 })).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn power_can_be_parsed_from_each_bmc() -> anyhow::Result<()> {
+        let redfish = json!({
+            "@odata.id": "/redfish/v1/Chassis/1/Power",
+            "PowerControl": [{ "MemberId": "0", "PowerConsumedWatts": 184 }]
+        });
+        assert_eq!(parse_redfish(&redfish)?, 184.0);
+
+        let ipmi = "\n    Instantaneous power reading:                   212 Watts\n    \
+                    Minimum during sampling period:                 98 Watts\n    \
+                    Maximum during sampling period:                305 Watts\n";
+        assert_eq!(parse_ipmi(ipmi)?, 212.0);
+        assert!(parse_ipmi("Error: DCMI not supported").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn kasa_messages_round_trip() {
+        let message = kasa_encrypt(KASA_REALTIME);
+        assert_eq!(&message[..4], &(KASA_REALTIME.len() as u32).to_be_bytes());
+        assert_eq!(kasa_decrypt(&message[4..]), KASA_REALTIME);
+    }
+}
@@ -0,0 +1,320 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use super::{collector::MetricsCollector, sample_interval, LogBuffer};
+use crate::{
+    config::Remote,
+    metrics::{CpuMetrics, ProcessEvent, ProcessEventKind},
+};
+use anyhow::{anyhow, Context};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use tokio::time::{Duration, Instant};
+
+/// Printed in place of a process's stat line once it has exited.
+const EXITED: &str = "exited";
+
+/// CPU time consumed by a single process on a remote host, measured in clock ticks.
+#[derive(Debug, PartialEq)]
+struct ProcessTicks {
+    pid: u32,
+    name: String,
+    ticks: u64,
+}
+
+/// Samples processes on remote hosts. Each sample runs `cat /proc/<pid>/stat` on the remote host
+/// over SSH, the connection is kept open between samples using SSH connection multiplexing.
+/// Each pid is read on its own so one exiting doesn't fail the sample of the others.
+pub struct RemoteCollector {
+    /// Pids grouped by remote host so each host is sampled with a single ssh command.
+    pids_by_remote: HashMap<String, (Remote, Vec<u32>)>,
+    previous: HashMap<(String, u32), (u64, Instant)>,
+    names: HashMap<(String, u32), String>,
+}
+impl RemoteCollector {
+    pub fn new(processes: Vec<(Remote, u32)>) -> Self {
+        let mut pids_by_remote: HashMap<String, (Remote, Vec<u32>)> = HashMap::new();
+        for (remote, pid) in processes.into_iter() {
+            pids_by_remote
+                .entry(remote.name.clone())
+                .or_insert((remote, vec![]))
+                .1
+                .push(pid);
+        }
+
+        Self {
+            pids_by_remote,
+            previous: HashMap::new(),
+            names: HashMap::new(),
+        }
+    }
+}
+#[async_trait]
+impl MetricsCollector for RemoteCollector {
+    fn describe(&self) -> String {
+        let mut remote_pids = self
+            .pids_by_remote
+            .iter()
+            .flat_map(|(name, (_, pids))| pids.iter().map(move |pid| format!("{name}:{pid}")))
+            .collect::<Vec<_>>();
+        remote_pids.sort();
+        format!("remote PIDs {:?}", remote_pids)
+    }
+
+    async fn sample(&mut self, log: &mut LogBuffer) -> Duration {
+        for (remote, pids) in self.pids_by_remote.values_mut() {
+            if pids.is_empty() {
+                continue;
+            }
+            let sample = match sample(remote, pids).await {
+                Ok(sample) => sample,
+                Err(err) => {
+                    log.push_error(err);
+                    continue;
+                }
+            };
+            let now = Instant::now();
+            let timestamp = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_millis() as i64)
+                .unwrap_or(0);
+
+            for pid in sample.exited.iter() {
+                let key = (remote.name.clone(), *pid);
+                self.previous.remove(&key);
+                let process_name = self
+                    .names
+                    .remove(&key)
+                    .unwrap_or_else(|| format!("{}:{pid}", remote.name));
+                tracing::warn!("Process {process_name} ({pid}) on {} exited", remote.name);
+                log.push_event(ProcessEvent {
+                    process_name,
+                    kind: ProcessEventKind::Stopped,
+                    timestamp,
+                });
+            }
+            pids.retain(|pid| !sample.exited.contains(pid));
+
+            for proc in sample.processes {
+                let key = (remote.name.clone(), proc.pid);
+                self.names.insert(key.clone(), proc.name.clone());
+
+                // the first reading only establishes a baseline
+                if let Some((prev_ticks, prev_instant)) =
+                    self.previous.insert(key, (proc.ticks, now))
+                {
+                    let elapsed = now.duration_since(prev_instant).as_secs_f64();
+                    let cpu_seconds =
+                        proc.ticks.saturating_sub(prev_ticks) as f64 / sample.clock_ticks as f64;
+
+                    log.push_metrics(CpuMetrics {
+                        process_id: format!("{}:{}", remote.name, proc.pid),
+                        process_name: proc.name,
+                        cpu_usage: cpu_seconds / elapsed * 100.0,
+                        core_count: sample.core_count,
+                        timestamp,
+                        cpu_frequency: None,
+                        memory_usage: None,
+                        power: None,
+                        sample_interval: None,
+                        memory_limit: None,
+                        cpu_periods: None,
+                        throttled_periods: None,
+                    });
+                }
+            }
+        }
+
+        sample_interval()
+    }
+}
+
+#[derive(Debug)]
+struct RemoteSample {
+    clock_ticks: u64,
+    core_count: i32,
+    processes: Vec<ProcessTicks>,
+    /// Pids which weren't running any more.
+    exited: Vec<u32>,
+}
+
+async fn sample(remote: &Remote, pids: &[u32]) -> anyhow::Result<RemoteSample> {
+    let pids = pids
+        .iter()
+        .map(|pid| pid.to_string())
+        .collect::<Vec<_>>()
+        .join(" ");
+    let remote_command = format!(
+        "getconf CLK_TCK; nproc; for pid in {pids}; do cat /proc/$pid/stat 2>/dev/null || echo \"$pid {EXITED}\"; done"
+    );
+
+    let output = tokio::process::Command::new("ssh")
+        .args(ssh_args(remote))
+        .arg(remote_command)
+        .kill_on_drop(true)
+        .output()
+        .await
+        .context("Failed to run ssh, is it installed?")?;
+
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Unable to sample processes on remote {}: {}",
+            remote.name,
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+
+    parse_sample(&String::from_utf8_lossy(&output.stdout))
+}
+
+fn ssh_args(remote: &Remote) -> Vec<String> {
+    let mut args = vec![
+        "-o".to_string(),
+        "BatchMode=yes".to_string(),
+        "-o".to_string(),
+        "ControlMaster=auto".to_string(),
+        "-o".to_string(),
+        "ControlPath=/tmp/cardamon-ssh-%r@%h:%p".to_string(),
+        "-o".to_string(),
+        "ControlPersist=60".to_string(),
+    ];
+    if let Some(port) = remote.port {
+        args.push("-p".to_string());
+        args.push(port.to_string());
+    }
+    if let Some(key) = &remote.key {
+        args.push("-i".to_string());
+        args.push(key.clone());
+    }
+    match &remote.user {
+        Some(user) => args.push(format!("{user}@{}", remote.host)),
+        None => args.push(remote.host.clone()),
+    }
+    args
+}
+
+fn parse_sample(output: &str) -> anyhow::Result<RemoteSample> {
+    let mut lines = output.lines();
+    let clock_ticks = lines
+        .next()
+        .and_then(|line| line.trim().parse::<u64>().ok())
+        .context("Unable to parse CLK_TCK from remote")?;
+    let core_count = lines
+        .next()
+        .and_then(|line| line.trim().parse::<i32>().ok())
+        .context("Unable to parse core count from remote")?;
+
+    let mut processes = vec![];
+    let mut exited = vec![];
+    for line in lines.filter(|line| !line.trim().is_empty()) {
+        match line.trim().split_once(' ') {
+            Some((pid, EXITED)) => exited.push(
+                pid.parse::<u32>()
+                    .context(format!("Malformed exited pid from remote: {line}"))?,
+            ),
+            _ => processes.push(parse_proc_stat(line)?),
+        }
+    }
+
+    Ok(RemoteSample {
+        clock_ticks,
+        core_count,
+        processes,
+        exited,
+    })
+}
+
+/// Parses a line of `/proc/<pid>/stat`. The process name is wrapped in parentheses and may
+/// contain spaces so fields are counted from the closing parenthesis.
+fn parse_proc_stat(line: &str) -> anyhow::Result<ProcessTicks> {
+    let (pid, rest) = line
+        .split_once(" (")
+        .context(format!("Malformed /proc stat line: {line}"))?;
+    let (name, fields) = rest
+        .rsplit_once(") ")
+        .context(format!("Malformed /proc stat line: {line}"))?;
+    let fields = fields.split_whitespace().collect::<Vec<_>>();
+
+    // utime and stime are the 14th and 15th fields, the 3rd field is the first after the name
+    let utime = fields.get(11).and_then(|v| v.parse::<u64>().ok());
+    let stime = fields.get(12).and_then(|v| v.parse::<u64>().ok());
+
+    match (pid.trim().parse::<u32>(), utime, stime) {
+        (Ok(pid), Some(utime), Some(stime)) => Ok(ProcessTicks {
+            pid,
+            name: name.to_string(),
+            ticks: utime + stime,
+        }),
+        _ => Err(anyhow!("Malformed /proc stat line: {line}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proc_stat_lines_can_be_parsed() -> anyhow::Result<()> {
+        let line = "1337 (node server) S 1 1337 1337 0 -1 4194560 12 0 0 0 250 50 0 0 20 0 11 0";
+        let proc = parse_proc_stat(line)?;
+        assert_eq!(
+            proc,
+            ProcessTicks {
+                pid: 1337,
+                name: "node server".to_string(),
+                ticks: 300
+            }
+        );
+
+        assert!(parse_proc_stat("garbage").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn remote_samples_can_be_parsed() -> anyhow::Result<()> {
+        let output = "100\n8\n\
+            1 (init) S 0 1 1 0 -1 4194560 12 0 0 0 10 5 0 0 20 0 1 0\n\
+            2 (nginx) S 1 2 2 0 -1 4194560 12 0 0 0 30 5 0 0 20 0 1 0\n";
+        let sample = parse_sample(output)?;
+
+        assert_eq!(sample.clock_ticks, 100);
+        assert_eq!(sample.core_count, 8);
+        assert_eq!(sample.processes.len(), 2);
+        assert_eq!(sample.processes[1].ticks, 35);
+        assert!(sample.exited.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn exited_pids_dont_fail_the_sample() -> anyhow::Result<()> {
+        let output = "100\n8\n\
+            41 exited\n\
+            2 (nginx) S 1 2 2 0 -1 4194560 12 0 0 0 30 5 0 0 20 0 1 0\n";
+        let sample = parse_sample(output)?;
+
+        assert_eq!(sample.processes.len(), 1);
+        assert_eq!(sample.processes[0].pid, 2);
+        assert_eq!(sample.exited, vec![41]);
+        Ok(())
+    }
+
+    #[test]
+    fn ssh_args_include_user_port_and_key() {
+        let remote = Remote {
+            name: "api".to_string(),
+            host: "10.0.0.5".to_string(),
+            user: Some("ubuntu".to_string()),
+            port: Some(2222),
+            key: Some("~/.ssh/id".to_string()),
+            hardware: None,
+        };
+        let args = ssh_args(&remote);
+
+        assert_eq!(args.last().map(|s| s.as_str()), Some("ubuntu@10.0.0.5"));
+        assert!(args.windows(2).any(|w| w == ["-p", "2222"]));
+        assert!(args.windows(2).any(|w| w == ["-i", "~/.ssh/id"]));
+    }
+}
@@ -0,0 +1,358 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use super::{
+    cgroup::{self, CgroupToObserve},
+    collector::MetricsCollector,
+    sample_interval, LogBuffer,
+};
+use crate::{config::Scaphandre, metrics::CpuMetrics};
+use anyhow::Context;
+use async_trait::async_trait;
+use std::{collections::HashMap, path::PathBuf};
+use tokio::time::Duration;
+
+const POWER_METRIC: &str = "scaph_process_power_consumption_microwatts";
+const CPU_METRIC: &str = "scaph_process_cpu_usage_percentage";
+const MEMORY_METRIC: &str = "scaph_process_memory_bytes";
+
+/// The metrics Scaphandre reports for a single process.
+#[derive(Debug, Default, PartialEq)]
+struct ProcessSample {
+    exe: String,
+    /// Power in watts.
+    power: f64,
+    /// CPU usage as a percentage of all cores.
+    cpu_usage: f64,
+    memory_usage: Option<i64>,
+}
+
+/// Scrapes a Scaphandre Prometheus exporter for the power of each observed process and cgroup.
+/// Cgroups are matched by looking up the cgroup of every process Scaphandre reports and the values
+/// of all processes inside them are summed.
+pub struct ScaphandreCollector {
+    scaphandre: Scaphandre,
+    pids: Vec<u32>,
+    cgroups: Vec<CgroupToObserve>,
+    core_count: i32,
+    client: reqwest::Client,
+}
+impl ScaphandreCollector {
+    pub fn new(scaphandre: Scaphandre, pids: Vec<u32>, cgroups: Vec<CgroupToObserve>) -> Self {
+        Self {
+            scaphandre,
+            pids,
+            cgroups,
+            core_count: std::thread::available_parallelism()
+                .map(|n| n.get() as i32)
+                .unwrap_or(0),
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(2))
+                .build()
+                .expect("Should be able to build a http client"),
+        }
+    }
+}
+#[async_trait]
+impl MetricsCollector for ScaphandreCollector {
+    fn describe(&self) -> String {
+        format!(
+            "PIDs {:?} and cgroups {:?} from Scaphandre",
+            self.pids, self.cgroups
+        )
+    }
+
+    /// Scaphandre averages over its own interval so the first scrape waits for one to pass.
+    async fn start(&mut self, _log: &mut LogBuffer) -> anyhow::Result<()> {
+        tokio::time::sleep(Duration::from_millis(1000)).await;
+        Ok(())
+    }
+
+    async fn sample(&mut self, log: &mut LogBuffer) -> Duration {
+        let exposition = scrape(&self.client, &self.scaphandre.url).await;
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or(0);
+
+        match exposition {
+            Ok(exposition) => {
+                let samples = parse_samples(&exposition);
+                let metrics = map_to_observed(
+                    &samples,
+                    &self.pids,
+                    &self.cgroups,
+                    cgroup::cgroup_of_pid,
+                    self.core_count,
+                    timestamp,
+                );
+
+                for metrics in metrics {
+                    log.push_metrics(metrics);
+                }
+            }
+            Err(err) => log.push_error(err),
+        }
+
+        sample_interval()
+    }
+}
+
+async fn scrape(client: &reqwest::Client, url: &str) -> anyhow::Result<String> {
+    client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await
+        .context(format!("Unable to scrape Scaphandre exporter {url}"))
+}
+
+/// Collects the per-process metrics in a Prometheus text exposition, keyed by pid.
+fn parse_samples(exposition: &str) -> HashMap<u32, ProcessSample> {
+    let mut samples: HashMap<u32, ProcessSample> = HashMap::new();
+    for (name, labels, value) in exposition.lines().filter_map(parse_line) {
+        if ![POWER_METRIC, CPU_METRIC, MEMORY_METRIC].contains(&name) {
+            continue;
+        }
+        let Some(pid) = labels.get("pid").and_then(|pid| pid.parse::<u32>().ok()) else {
+            continue;
+        };
+
+        let sample = samples.entry(pid).or_default();
+        if let Some(exe) = labels.get("exe") {
+            sample.exe.clone_from(exe);
+        }
+        match name {
+            POWER_METRIC => sample.power = value / 1_000_000.0,
+            CPU_METRIC => sample.cpu_usage = value,
+            _ => sample.memory_usage = Some(value as i64),
+        }
+    }
+    samples
+}
+
+/// Parses a single sample line, e.g. `metric{label="value"} 42`. Comments, blank lines and
+/// malformed lines are ignored.
+pub(super) fn parse_line(line: &str) -> Option<(&str, HashMap<String, String>, f64)> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+
+    let (name, labels, rest) = match line.find('{') {
+        Some(open) => {
+            let (labels, rest) = parse_labels(&line[open + 1..])?;
+            (&line[..open], labels, rest)
+        }
+        None => {
+            let (name, rest) = line.split_once(char::is_whitespace)?;
+            (name, HashMap::new(), rest)
+        }
+    };
+
+    // the value may be followed by an optional timestamp
+    let value = rest.split_whitespace().next()?.parse::<f64>().ok()?;
+    Some((name.trim(), labels, value))
+}
+
+/// Parses the labels following the opening brace, returning them along with the rest of the line
+/// after the closing brace.
+fn parse_labels(input: &str) -> Option<(HashMap<String, String>, &str)> {
+    let mut labels = HashMap::new();
+    let mut rest = input;
+
+    loop {
+        rest = rest.trim_start().trim_start_matches(',').trim_start();
+        if let Some(rest) = rest.strip_prefix('}') {
+            return Some((labels, rest));
+        }
+
+        let (key, after_key) = rest.split_once('=')?;
+        let mut chars = after_key.trim_start().strip_prefix('"')?.char_indices();
+        let mut value = String::new();
+        let end = loop {
+            match chars.next()? {
+                (i, '"') => break i,
+                (_, '\\') => match chars.next()?.1 {
+                    'n' => value.push('\n'),
+                    escaped => value.push(escaped),
+                },
+                (_, c) => value.push(c),
+            }
+        };
+
+        labels.insert(key.trim().to_string(), value);
+        rest = &after_key.trim_start()[end + 2..];
+    }
+}
+
+/// Turns the samples reported by Scaphandre into metrics for the observed processes and cgroups.
+/// Processes Scaphandre doesn't report on are skipped for this scrape.
+fn map_to_observed(
+    samples: &HashMap<u32, ProcessSample>,
+    pids: &[u32],
+    cgroups: &[CgroupToObserve],
+    cgroup_of: impl Fn(u32) -> Option<PathBuf>,
+    core_count: i32,
+    timestamp: i64,
+) -> Vec<CpuMetrics> {
+    // Scaphandre reports cpu usage as a share of all cores, cardamon as a share of a single core
+    let to_single_core = |cpu_usage: f64| cpu_usage * core_count.max(1) as f64;
+
+    let mut metrics = pids
+        .iter()
+        .filter_map(|pid| {
+            let sample = samples.get(pid)?;
+            Some(CpuMetrics {
+                process_id: format!("{pid}"),
+                process_name: sample.exe.clone(),
+                cpu_usage: to_single_core(sample.cpu_usage),
+                core_count,
+                timestamp,
+                cpu_frequency: None,
+                memory_usage: sample.memory_usage,
+                power: Some(sample.power),
+                sample_interval: None,
+                memory_limit: None,
+                cpu_periods: None,
+                throttled_periods: None,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    if cgroups.is_empty() {
+        return metrics;
+    }
+
+    let sample_cgroups = samples
+        .iter()
+        .filter_map(|(pid, sample)| Some((cgroup_of(*pid)?, sample)))
+        .collect::<Vec<_>>();
+
+    for cgroup in cgroups {
+        let members = sample_cgroups
+            .iter()
+            .filter(|(path, _)| path.starts_with(&cgroup.path))
+            .map(|(_, sample)| *sample)
+            .collect::<Vec<_>>();
+        if members.is_empty() {
+            continue;
+        }
+
+        let memory_usage = members.iter().filter_map(|sample| sample.memory_usage);
+        metrics.push(CpuMetrics {
+            process_id: cgroup.path.to_string_lossy().to_string(),
+            process_name: cgroup.name.clone(),
+            cpu_usage: to_single_core(members.iter().map(|sample| sample.cpu_usage).sum()),
+            core_count,
+            timestamp,
+            cpu_frequency: None,
+            memory_usage: members
+                .iter()
+                .any(|sample| sample.memory_usage.is_some())
+                .then(|| memory_usage.sum()),
+            power: Some(members.iter().map(|sample| sample.power).sum()),
+            sample_interval: None,
+            memory_limit: None,
+            cpu_periods: None,
+            throttled_periods: None,
+        });
+    }
+
+    metrics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXPOSITION: &str = r#"
+# HELP scaph_process_power_consumption_microwatts Power consumption due to the process, measured on at the topology level, in microwatts
+# TYPE scaph_process_power_consumption_microwatts gauge
+scaph_process_power_consumption_microwatts{exe="nginx",pid="101",cmdline="nginx: worker \"process\""} 2500000
+scaph_process_power_consumption_microwatts{exe="nginx",pid="102",cmdline="nginx: worker"} 1500000
+scaph_process_power_consumption_microwatts{exe="postgres",pid="200",cmdline="postgres -D /data"} 4000000
+scaph_process_cpu_usage_percentage{exe="nginx",pid="101",cmdline="nginx"} 10
+scaph_process_cpu_usage_percentage{exe="nginx",pid="102",cmdline="nginx"} 5
+scaph_process_cpu_usage_percentage{exe="postgres",pid="200",cmdline="postgres"} 25
+scaph_process_memory_bytes{exe="nginx",pid="101",cmdline="nginx"} 1048576
+scaph_host_power_microwatts 30000000
+"#;
+
+    #[test]
+    fn lines_can_be_parsed() {
+        let (name, labels, value) =
+            parse_line(r#"metric{a="1",b="quoted \"value\", with comma"} 4.5 1718000000"#).unwrap();
+        assert_eq!(name, "metric");
+        assert_eq!(labels["a"], "1");
+        assert_eq!(labels["b"], r#"quoted "value", with comma"#);
+        assert_eq!(value, 4.5);
+
+        let (name, labels, value) = parse_line("scaph_host_power_microwatts 30").unwrap();
+        assert_eq!(name, "scaph_host_power_microwatts");
+        assert!(labels.is_empty());
+        assert_eq!(value, 30.0);
+
+        assert!(parse_line("# TYPE metric gauge").is_none());
+        assert!(parse_line(r#"metric{a="unterminated} 1"#).is_none());
+    }
+
+    #[test]
+    fn samples_are_grouped_by_pid() {
+        let samples = parse_samples(EXPOSITION);
+        assert_eq!(samples.len(), 3);
+        assert_eq!(
+            samples[&101],
+            ProcessSample {
+                exe: "nginx".to_string(),
+                power: 2.5,
+                cpu_usage: 10.0,
+                memory_usage: Some(1048576),
+            }
+        );
+        assert_eq!(samples[&200].memory_usage, None);
+    }
+
+    #[test]
+    fn samples_are_mapped_onto_pids_and_cgroups() {
+        let samples = parse_samples(EXPOSITION);
+        let cgroup = CgroupToObserve::from_path("system.slice/nginx.service");
+        let cgroup_of = |pid: u32| match pid {
+            101 | 102 => Some(cgroup.path.join("workers")),
+            _ => Some(PathBuf::from(
+                "/sys/fs/cgroup/system.slice/postgres.service",
+            )),
+        };
+
+        let metrics = map_to_observed(
+            &samples,
+            &[200, 999],
+            std::slice::from_ref(&cgroup),
+            cgroup_of,
+            4,
+            0,
+        );
+        assert_eq!(metrics.len(), 2);
+
+        let postgres = &metrics[0];
+        assert_eq!(postgres.process_id, "200");
+        assert_eq!(postgres.process_name, "postgres");
+        assert_eq!(postgres.cpu_usage, 100.0);
+        assert_eq!(postgres.power, Some(4.0));
+
+        let nginx = &metrics[1];
+        assert_eq!(
+            nginx.process_id,
+            "/sys/fs/cgroup/system.slice/nginx.service"
+        );
+        assert_eq!(nginx.process_name, "system.slice/nginx.service");
+        assert_eq!(nginx.cpu_usage, 60.0);
+        assert_eq!(nginx.memory_usage, Some(1048576));
+        assert_eq!(nginx.power, Some(4.0));
+    }
+}
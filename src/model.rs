@@ -0,0 +1,757 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use crate::{
+    config::{Config, Cpu, Memory, PowerModel, Role},
+    data_access::{baseline::Baseline, cpu_metrics::CpuMetrics, endpoint_request::EndpointRequest},
+    dataset::IterationWithMetrics,
+    SELF_PROCESS_NAME,
+};
+use std::collections::HashMap;
+
+/// The time represented by the first sample of a process when it doesn't record its own interval,
+/// the metrics loggers sample once a second unless they've backed off under load.
+const SAMPLE_INTERVAL_SECS: f64 = 1.0;
+
+/// The longest time a single sample can cover. Longer gaps mean nothing was sampled (e.g. a
+/// container was restarting) rather than that sampling was slow, so aren't attributed to the next
+/// sample.
+const MAX_GAP_SECS: f64 = 16.0;
+
+/// The seconds each sample covers, in the same order as the samples. A sample's own recorded
+/// interval is used if it has one, otherwise the time since the previous sample from the same
+/// source, so irregular sampling (GC pauses, slow Docker stats calls) doesn't skew the energy.
+///
+/// # Arguments
+///
+/// * `samples` - The samples of an iteration in any order
+/// * `source_of` - Which series a sample belongs to, e.g. its process, and when it was taken
+/// * `recorded` - The interval the sample records, if any
+fn sample_intervals<T>(
+    samples: &[T],
+    source_of: impl Fn(&T) -> (&str, i64),
+    recorded: impl Fn(&T) -> Option<f64>,
+) -> Vec<f64> {
+    let mut order = (0..samples.len()).collect::<Vec<_>>();
+    order.sort_by_key(|i| source_of(&samples[*i]));
+
+    let mut intervals = vec![SAMPLE_INTERVAL_SECS; samples.len()];
+    let mut previous: Option<(&str, i64)> = None;
+    for i in order {
+        let (source, timestamp) = source_of(&samples[i]);
+        intervals[i] = match (recorded(&samples[i]), previous) {
+            (Some(interval), _) => interval,
+            (None, Some((previous_source, previous_timestamp))) if previous_source == source => {
+                ((timestamp - previous_timestamp) as f64 / 1000.0).clamp(0.0, MAX_GAP_SECS)
+            }
+            (None, _) => SAMPLE_INTERVAL_SECS,
+        };
+        previous = Some((source, timestamp));
+    }
+    intervals
+}
+
+/// The seconds each CPU metrics sample covers, see `sample_intervals`.
+fn cpu_intervals(metrics: &[CpuMetrics]) -> Vec<f64> {
+    sample_intervals(
+        metrics,
+        |m| (m.process_id.as_str(), m.timestamp),
+        |m| m.sample_interval,
+    )
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ProcessEnergy {
+    pub process_id: String,
+    pub process_name: String,
+    pub cpu_energy_wh: f64,
+    pub memory_energy_wh: f64,
+}
+impl ProcessEnergy {
+    /// Combined CPU and memory energy in watt hours.
+    pub fn energy_wh(&self) -> f64 {
+        self.cpu_energy_wh + self.memory_energy_wh
+    }
+
+    /// What the process was doing according to the config.
+    pub fn role(&self, config: &Config) -> Role {
+        config.role_for(&self.process_name)
+    }
+}
+
+/// Energy of the system under test and of the load generators driving it, which is always
+/// reported separately.
+#[derive(Debug, Default, PartialEq)]
+pub struct EnergyByRole {
+    pub sut: Vec<ProcessEnergy>,
+    pub load: Vec<ProcessEnergy>,
+}
+impl EnergyByRole {
+    pub fn new(config: &Config, energy: Vec<ProcessEnergy>) -> Self {
+        let (sut, load) = energy
+            .into_iter()
+            .partition(|e| e.role(config) == Role::Sut);
+        Self { sut, load }
+    }
+
+    /// Leaves cardamon's own processes out of the system under test.
+    pub fn without_overhead(mut self) -> Self {
+        self.sut.retain(|e| e.process_name != SELF_PROCESS_NAME);
+        self
+    }
+
+    /// Wh used by the system under test.
+    pub fn sut_wh(&self) -> f64 {
+        self.sut.iter().map(|e| e.energy_wh()).sum()
+    }
+}
+
+/// A process' share of the energy used by a run, processes are grouped by name since their ids
+/// change between iterations.
+#[derive(Debug, PartialEq)]
+pub struct ProcessShare {
+    pub process_name: String,
+    pub energy_wh: f64,
+    /// Percentage of the energy used by every process, 0-100.
+    pub percentage: f64,
+}
+
+/// Totals the energy of each process by name, largest first.
+pub fn share_by_process(energy: &[ProcessEnergy]) -> Vec<ProcessShare> {
+    let mut by_name: Vec<ProcessShare> = vec![];
+    for process_energy in energy.iter() {
+        match by_name
+            .iter_mut()
+            .find(|share| share.process_name == process_energy.process_name)
+        {
+            Some(share) => share.energy_wh += process_energy.energy_wh(),
+            None => by_name.push(ProcessShare {
+                process_name: process_energy.process_name.clone(),
+                energy_wh: process_energy.energy_wh(),
+                percentage: 0.0,
+            }),
+        }
+    }
+
+    let total_wh = by_name.iter().map(|share| share.energy_wh).sum::<f64>();
+    for share in by_name.iter_mut() {
+        if total_wh > 0.0 {
+            share.percentage = share.energy_wh / total_wh * 100.0;
+        }
+    }
+    by_name.sort_by(|a, b| b.energy_wh.total_cmp(&a.energy_wh));
+    by_name
+}
+
+/// An endpoint's share of the energy used by a scenario, totalled over its iterations.
+#[derive(Debug, PartialEq)]
+pub struct EndpointShare {
+    /// Method and path of the endpoint, e.g. `GET /cart/:id`.
+    pub endpoint: String,
+    pub requests: i64,
+    pub energy_wh: f64,
+}
+impl EndpointShare {
+    pub fn wh_per_request(&self) -> f64 {
+        self.energy_wh / self.requests as f64
+    }
+}
+
+/// Shares the energy of each iteration between the endpoints it served by their share of its
+/// requests, in the order the endpoints were first seen. Iterations without requests aren't
+/// shared.
+///
+/// # Arguments
+///
+/// * `iteration_wh` - The iteration number and Wh used by each iteration of a scenario
+/// * `requests` - The requests each endpoint served during the scenario's iterations
+pub fn share_by_endpoint(
+    iteration_wh: &[(i64, f64)],
+    requests: &[&EndpointRequest],
+) -> Vec<EndpointShare> {
+    let mut by_endpoint: Vec<EndpointShare> = vec![];
+    for (iteration, wh) in iteration_wh.iter() {
+        let iteration_requests = requests
+            .iter()
+            .filter(|r| r.iteration == *iteration)
+            .collect::<Vec<_>>();
+        let total = iteration_requests.iter().map(|r| r.requests).sum::<i64>();
+        if total == 0 {
+            continue;
+        }
+        for r in iteration_requests {
+            let energy_wh = wh * r.requests as f64 / total as f64;
+            match by_endpoint
+                .iter_mut()
+                .find(|share| share.endpoint == r.endpoint)
+            {
+                Some(share) => {
+                    share.energy_wh += energy_wh;
+                    share.requests += r.requests;
+                }
+                None => by_endpoint.push(EndpointShare {
+                    endpoint: r.endpoint.clone(),
+                    requests: r.requests,
+                    energy_wh,
+                }),
+            }
+        }
+    }
+    by_endpoint
+}
+
+const BYTES_PER_GB: f64 = 1024.0 * 1024.0 * 1024.0;
+
+/// Estimates the energy used by each process during an iteration by scaling the TDP of the CPU
+/// each process ran on by the share of that CPU the process used.
+///
+/// When a CPU uses the frequency power model, each sample is further scaled by the cube of its
+/// frequency relative to the CPU's maximum, since dynamic power grows with frequency and voltage.
+///
+/// If memory is given, the energy of each process' resident memory is modelled separately from
+/// its CPU energy, following the Cloud Carbon Footprint methodology of a fixed power per GB.
+///
+/// If a baseline is given, the CPU energy the idle local machine would have used over the iteration
+/// is subtracted from the processes running locally, shared in proportion to their energy, so
+/// that results reflect the marginal energy of the workload.
+///
+/// # Arguments
+///
+/// * `iteration` - The scenario iteration and its metrics
+/// * `local_cpu` - The CPU of the machine running cardamon
+/// * `cpu_for` - Finds the CPU of processes which ran on other hardware, None if local
+/// * `memory` - Optional power coefficient for memory, memory energy is zero if not given
+/// * `baseline` - Optional idle measurement of the local machine to subtract
+///
+/// # Returns
+///
+/// The energy in watt hours used by each process.
+pub fn rab_model<'c>(
+    iteration: &IterationWithMetrics,
+    local_cpu: &'c Cpu,
+    cpu_for: impl Fn(&CpuMetrics) -> Option<&'c Cpu>,
+    memory: Option<&Memory>,
+    baseline: Option<&Baseline>,
+) -> Vec<ProcessEnergy> {
+    let max_observed_frequency = iteration
+        .cpu_metrics()
+        .iter()
+        .filter_map(|metrics| metrics.cpu_frequency)
+        .max();
+
+    let mut energy_by_process: HashMap<&str, (ProcessEnergy, bool)> = HashMap::new();
+    let intervals = cpu_intervals(iteration.cpu_metrics());
+    for (metrics, interval) in iteration.cpu_metrics().iter().zip(intervals) {
+        let other_cpu = cpu_for(metrics);
+        let cpu = other_cpu.unwrap_or(local_cpu);
+        let core_count = metrics.core_count.max(1) as f64;
+        let power = cpu.total_tdp() * (metrics.cpu_usage / 100.0) / core_count
+            * frequency_scale(cpu, metrics.cpu_frequency, max_observed_frequency);
+        let memory_power = match (memory, metrics.memory_usage) {
+            (Some(memory), Some(memory_usage)) => {
+                memory.power_per_gb * memory_usage as f64 / BYTES_PER_GB
+            }
+            _ => 0.0,
+        };
+
+        let (process_energy, _) =
+            energy_by_process
+                .entry(&metrics.process_id)
+                .or_insert_with(|| {
+                    let process_energy = ProcessEnergy {
+                        process_id: metrics.process_id.clone(),
+                        process_name: metrics.process_name.clone(),
+                        cpu_energy_wh: 0.0,
+                        memory_energy_wh: 0.0,
+                    };
+                    (process_energy, other_cpu.is_none())
+                });
+        process_energy.cpu_energy_wh += power * interval / 3600.0;
+        process_energy.memory_energy_wh += memory_power * interval / 3600.0;
+    }
+
+    if let Some(baseline) = baseline {
+        let scenario_iteration = iteration.scenario_iteration();
+        let duration_secs =
+            (scenario_iteration.stop_time - scenario_iteration.start_time) as f64 / 1000.0;
+        let baseline_wh =
+            local_cpu.total_tdp() * (baseline.cpu_usage / 100.0) * duration_secs / 3600.0;
+
+        // the baseline was measured on the local machine so only applies to local processes
+        let local_wh = energy_by_process
+            .values()
+            .filter(|(_, is_local)| *is_local)
+            .map(|(e, _)| e.cpu_energy_wh)
+            .sum::<f64>();
+        if local_wh > 0.0 {
+            let marginal_share = (1.0 - baseline_wh / local_wh).max(0.0);
+            for (process_energy, _) in energy_by_process
+                .values_mut()
+                .filter(|(_, is_local)| *is_local)
+            {
+                process_energy.cpu_energy_wh *= marginal_share;
+            }
+        }
+    }
+
+    energy_by_process
+        .into_values()
+        .map(|(process_energy, _)| process_energy)
+        .collect()
+}
+
+/// Splits the wall energy measured by a power meter during an iteration between the processes
+/// running on the measured machine in proportion to their share of the CPU time used. Measured
+/// energy covers the whole machine (CPU, memory, disks, fans, etc.) so it is reported as CPU
+/// energy with no separate memory energy. Processes with their own power measurements (e.g. read
+/// from Scaphandre) use those instead of a share of the wall energy.
+///
+/// # Arguments
+///
+/// * `iteration` - The scenario iteration and its metrics
+/// * `is_local` - Whether a process ran on the measured machine, others are left out
+///
+/// # Returns
+///
+/// The energy in watt hours used by each local process.
+pub fn measured_model(
+    iteration: &IterationWithMetrics,
+    is_local: impl Fn(&CpuMetrics) -> bool,
+) -> Vec<ProcessEnergy> {
+    let power_intervals = sample_intervals(
+        iteration.power_metrics(),
+        |power_metrics| (power_metrics.source.as_str(), power_metrics.timestamp),
+        |_| None,
+    );
+    let measured_wh = iteration
+        .power_metrics()
+        .iter()
+        .zip(power_intervals)
+        .map(|(power_metrics, interval)| power_metrics.power * interval / 3600.0)
+        .sum::<f64>();
+
+    // usage and any energy measured for the process itself
+    let mut usage_by_process: HashMap<&str, (&str, f64, Option<f64>)> = HashMap::new();
+    let intervals = cpu_intervals(iteration.cpu_metrics());
+    for (metrics, interval) in iteration
+        .cpu_metrics()
+        .iter()
+        .zip(intervals)
+        .filter(|(m, _)| is_local(m))
+    {
+        let entry = usage_by_process.entry(&metrics.process_id).or_insert((
+            &metrics.process_name,
+            0.0,
+            None,
+        ));
+        entry.1 += metrics.cpu_usage * interval;
+        if let Some(power) = metrics.power {
+            *entry.2.get_or_insert(0.0) += power * interval / 3600.0;
+        }
+    }
+
+    // if nothing used any cpu then nothing stands out, share the energy evenly
+    let total_usage = usage_by_process
+        .values()
+        .map(|(_, usage, _)| usage)
+        .sum::<f64>();
+    let process_count = usage_by_process.len() as f64;
+    usage_by_process
+        .into_iter()
+        .map(|(process_id, (process_name, usage, process_wh))| {
+            let share = if total_usage > 0.0 {
+                usage / total_usage
+            } else {
+                1.0 / process_count
+            };
+            ProcessEnergy {
+                process_id: process_id.to_string(),
+                process_name: process_name.to_string(),
+                cpu_energy_wh: process_wh.unwrap_or(measured_wh * share),
+                memory_energy_wh: 0.0,
+            }
+        })
+        .collect()
+}
+
+/// The factor power is scaled by to account for CPU frequency, 1.0 unless the CPU uses the
+/// frequency model and both the current and maximum frequencies are known.
+fn frequency_scale(cpu: &Cpu, frequency: Option<i64>, max_observed: Option<i64>) -> f64 {
+    match (cpu.model, frequency, cpu.max_frequency.or(max_observed)) {
+        (PowerModel::Frequency, Some(frequency), Some(max_frequency)) if max_frequency > 0 => {
+            (frequency as f64 / max_frequency as f64).min(1.0).powi(3)
+        }
+        _ => 1.0,
+    }
+}
+
+/// Energy used by each process during the iterations, measured by a power meter when one was
+/// used and otherwise modelled from the CPU. Iterations are modelled with the local CPU unless
+/// their process ran on other hardware.
+pub fn iteration_energy(
+    config: &Config,
+    iterations: &[&IterationWithMetrics],
+    baseline: Option<&Baseline>,
+) -> Vec<ProcessEnergy> {
+    let cpu_for = |metrics: &CpuMetrics| config.cpu_for(&metrics.process_id, &metrics.process_name);
+    iterations
+        .iter()
+        .flat_map(|it| match &config.cpu {
+            _ if it.has_measured_power() => {
+                measured_model(it, |metrics| cpu_for(metrics).is_none())
+            }
+            Some(cpu) => rab_model(it, cpu, cpu_for, config.memory.as_ref(), baseline),
+            None => vec![],
+        })
+        .collect()
+}
+
+/// Wh the system under test used during the iterations, leaving out load generators and cardamon
+/// itself, with the PUE of the cloud it ran in.
+pub fn sut_energy_wh(config: &Config, iterations: &[&IterationWithMetrics]) -> f64 {
+    let pue = config.cloud.as_ref().map(|cloud| cloud.pue).unwrap_or(1.0);
+    EnergyByRole::new(config, iteration_energy(config, iterations, None))
+        .without_overhead()
+        .sut_wh()
+        * pue
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_access::{power_metrics::PowerMetrics, scenario_iteration::ScenarioIteration};
+
+    fn cpu(tdp: f64, sockets: u32) -> Cpu {
+        Cpu {
+            name: "test".to_string(),
+            tdp,
+            sockets,
+            model: PowerModel::Rab,
+            max_frequency: None,
+        }
+    }
+
+    fn iteration() -> IterationWithMetrics {
+        // two processes each using a full core of a 4 core cpu for 36 seconds
+        let metrics = (0..36)
+            .flat_map(|i| {
+                vec![
+                    CpuMetrics::new("1", "1", "yarn", 100.0, 0.0, 4, i * 1000),
+                    CpuMetrics::new("1", "2", "postgres", 100.0, 0.0, 4, i * 1000),
+                ]
+            })
+            .collect();
+        IterationWithMetrics::new(
+            ScenarioIteration::new("1", "basket_10", 1, 0, 36_000),
+            metrics,
+            vec![],
+        )
+    }
+
+    fn energy_of(energy: &[ProcessEnergy], process_id: &str) -> f64 {
+        energy
+            .iter()
+            .find(|e| e.process_id == process_id)
+            .map(|e| e.energy_wh())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn load_generators_and_cardamon_are_not_the_system_under_test() -> anyhow::Result<()> {
+        let config = Config::from_path(std::path::Path::new("./fixtures/cardamon.roles.toml"))?;
+        let process_energy = |process_name: &str, cpu_energy_wh| ProcessEnergy {
+            process_id: process_name.to_string(),
+            process_name: process_name.to_string(),
+            cpu_energy_wh,
+            memory_energy_wh: 0.0,
+        };
+        let energy = EnergyByRole::new(
+            &config,
+            vec![
+                process_energy("postgres", 1.0),
+                process_energy("k6", 2.0),
+                process_energy(SELF_PROCESS_NAME, 0.5),
+            ],
+        );
+
+        assert_eq!(energy.load, vec![process_energy("k6", 2.0)]);
+        assert_eq!(energy.sut_wh(), 1.5);
+        assert_eq!(energy.without_overhead().sut_wh(), 1.0);
+        Ok(())
+    }
+
+    #[test]
+    fn energy_is_proportional_to_cpu_share() {
+        let energy = rab_model(&iteration(), &cpu(100.0, 1), |_| None, None, None);
+
+        // 25 W for 36 seconds = 0.25 Wh
+        assert_eq!(energy.len(), 2);
+        assert!((energy_of(&energy, "1") - 0.25).abs() < 1e-9);
+        assert!((energy_of(&energy, "2") - 0.25).abs() < 1e-9);
+    }
+
+    #[test]
+    fn energy_is_shared_by_process_name() {
+        let process_energy = |process_id: &str, process_name: &str, cpu_energy_wh| ProcessEnergy {
+            process_id: process_id.to_string(),
+            process_name: process_name.to_string(),
+            cpu_energy_wh,
+            memory_energy_wh: 0.0,
+        };
+        // yarn restarted between iterations so has two ids
+        let energy = vec![
+            process_energy("1", "yarn", 0.5),
+            process_energy("2", "postgres", 1.0),
+            process_energy("3", "yarn", 2.5),
+        ];
+
+        assert_eq!(
+            share_by_process(&energy),
+            vec![
+                ProcessShare {
+                    process_name: "yarn".to_string(),
+                    energy_wh: 3.0,
+                    percentage: 75.0
+                },
+                ProcessShare {
+                    process_name: "postgres".to_string(),
+                    energy_wh: 1.0,
+                    percentage: 25.0
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn energy_is_shared_by_endpoint_requests() {
+        let requests = [
+            EndpointRequest::new("1", "basket_10", 1, "GET /cart", 3),
+            EndpointRequest::new("1", "basket_10", 1, "POST /cart", 1),
+            EndpointRequest::new("1", "basket_10", 2, "GET /cart", 1),
+        ];
+        let requests = requests.iter().collect::<Vec<_>>();
+
+        // the third iteration served no requests so isn't shared
+        assert_eq!(
+            share_by_endpoint(&[(1, 4.0), (2, 1.0), (3, 2.0)], &requests),
+            vec![
+                EndpointShare {
+                    endpoint: "GET /cart".to_string(),
+                    requests: 4,
+                    energy_wh: 4.0
+                },
+                EndpointShare {
+                    endpoint: "POST /cart".to_string(),
+                    requests: 1,
+                    energy_wh: 1.0
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn baseline_is_shared_between_processes() {
+        // the idle system used 10% of the cpu, 10 W for 36 seconds = 0.1 Wh
+        let baseline = Baseline::new("idle", 0, 30_000, 10.0, 4);
+        let energy = rab_model(
+            &iteration(),
+            &cpu(100.0, 1),
+            |_| None,
+            None,
+            Some(&baseline),
+        );
+
+        assert!((energy_of(&energy, "1") - 0.2).abs() < 1e-9);
+        assert!((energy_of(&energy, "2") - 0.2).abs() < 1e-9);
+
+        // energy never goes negative
+        let baseline = Baseline::new("busy", 0, 30_000, 90.0, 4);
+        let energy = rab_model(
+            &iteration(),
+            &cpu(100.0, 1),
+            |_| None,
+            None,
+            Some(&baseline),
+        );
+        assert_eq!(energy_of(&energy, "1"), 0.0);
+    }
+
+    #[test]
+    fn processes_use_the_cpu_they_ran_on() {
+        // postgres ran on a dual socket server
+        let local_cpu = cpu(100.0, 1);
+        let server_cpu = cpu(100.0, 2);
+        let cpu_for =
+            |metrics: &CpuMetrics| (metrics.process_name == "postgres").then_some(&server_cpu);
+
+        let energy = rab_model(&iteration(), &local_cpu, cpu_for, None, None);
+        assert!((energy_of(&energy, "1") - 0.25).abs() < 1e-9);
+        assert!((energy_of(&energy, "2") - 0.5).abs() < 1e-9);
+
+        // the local baseline isn't subtracted from remote processes
+        let baseline = Baseline::new("idle", 0, 30_000, 10.0, 4);
+        let energy = rab_model(&iteration(), &local_cpu, cpu_for, None, Some(&baseline));
+        assert!((energy_of(&energy, "1") - 0.15).abs() < 1e-9);
+        assert!((energy_of(&energy, "2") - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn frequency_model_scales_with_the_cube_of_frequency() {
+        // yarn ran at half the maximum frequency of 4 GHz
+        let metrics = (0..36)
+            .map(|i| {
+                CpuMetrics::new("1", "1", "yarn", 100.0, 0.0, 4, i * 1000)
+                    .with_cpu_frequency(Some(2000))
+            })
+            .collect();
+        let half_speed = IterationWithMetrics::new(
+            ScenarioIteration::new("1", "basket_10", 1, 0, 36_000),
+            metrics,
+            vec![],
+        );
+        let mut cpu = cpu(100.0, 1);
+        cpu.model = PowerModel::Frequency;
+        cpu.max_frequency = Some(4000);
+
+        let energy = rab_model(&half_speed, &cpu, |_| None, None, None);
+        assert!((energy_of(&energy, "1") - 0.25 / 8.0).abs() < 1e-9);
+
+        // without a configured maximum the highest observed frequency is used
+        cpu.max_frequency = None;
+        let energy = rab_model(&half_speed, &cpu, |_| None, None, None);
+        assert!((energy_of(&energy, "1") - 0.25).abs() < 1e-9);
+
+        // samples without a frequency fall back to the utilisation model
+        cpu.max_frequency = Some(4000);
+        let energy = rab_model(&iteration(), &cpu, |_| None, None, None);
+        assert!((energy_of(&energy, "1") - 0.25).abs() < 1e-9);
+    }
+
+    #[test]
+    fn samples_cover_their_recorded_interval() {
+        // sampling backed off to every 4 seconds, 9 samples still cover 36 seconds
+        let metrics = (0..9)
+            .map(|i| {
+                CpuMetrics::new("1", "1", "yarn", 100.0, 0.0, 4, i * 4000)
+                    .with_sample_interval(Some(4.0))
+            })
+            .collect();
+        let iteration = IterationWithMetrics::new(
+            ScenarioIteration::new("1", "basket_10", 1, 0, 36_000),
+            metrics,
+            vec![],
+        );
+
+        // 25 W for 36 seconds = 0.25 Wh
+        let energy = rab_model(&iteration, &cpu(100.0, 1), |_| None, None, None);
+        assert!((energy_of(&energy, "1") - 0.25).abs() < 1e-9);
+        assert_eq!(iteration.sample_interval(), Some(4.0));
+    }
+
+    #[test]
+    fn samples_cover_the_time_since_the_previous_sample() {
+        // a pause delayed the third sample, the process wasn't sampled for a minute after that
+        let metrics = [0, 1000, 5000, 6000, 66_000]
+            .into_iter()
+            .map(|timestamp| CpuMetrics::new("1", "1", "yarn", 100.0, 0.0, 4, timestamp))
+            .chain([CpuMetrics::new("1", "2", "postgres", 100.0, 0.0, 4, 3000)])
+            .collect::<Vec<_>>();
+        assert_eq!(
+            cpu_intervals(&metrics),
+            vec![1.0, 1.0, 4.0, 1.0, MAX_GAP_SECS, 1.0]
+        );
+
+        // 25 W for 23 seconds
+        let iteration = IterationWithMetrics::new(
+            ScenarioIteration::new("1", "basket_10", 1, 0, 66_000),
+            metrics,
+            vec![],
+        );
+        let energy = rab_model(&iteration, &cpu(100.0, 1), |_| None, None, None);
+        assert!((energy_of(&energy, "1") - 25.0 * 23.0 / 3600.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn memory_energy_is_modelled_separately() {
+        // 2 GB resident for 36 seconds
+        let metrics = (0..36)
+            .map(|i| {
+                CpuMetrics::new("1", "1", "postgres", 0.0, 0.0, 4, i * 1000)
+                    .with_memory_usage(Some(2 * 1024 * 1024 * 1024))
+            })
+            .collect();
+        let iteration = IterationWithMetrics::new(
+            ScenarioIteration::new("1", "basket_10", 1, 0, 36_000),
+            metrics,
+            vec![],
+        );
+        let memory = Memory { power_per_gb: 0.5 };
+
+        // 1 W for 36 seconds = 0.01 Wh
+        let energy = rab_model(&iteration, &cpu(100.0, 1), |_| None, Some(&memory), None);
+        assert_eq!(energy[0].cpu_energy_wh, 0.0);
+        assert!((energy[0].memory_energy_wh - 0.01).abs() < 1e-9);
+
+        // the idle baseline only applies to cpu energy
+        let baseline = Baseline::new("idle", 0, 30_000, 10.0, 4);
+        let energy = rab_model(
+            &iteration,
+            &cpu(100.0, 1),
+            |_| None,
+            Some(&memory),
+            Some(&baseline),
+        );
+        assert!((energy_of(&energy, "1") - 0.01).abs() < 1e-9);
+    }
+
+    #[test]
+    fn measured_power_is_split_by_cpu_share() {
+        // 100 W at the wall for 36 seconds = 1 Wh
+        let power_metrics = (0..36)
+            .map(|i| PowerMetrics::new("1", "tasmota", 100.0, i * 1000))
+            .collect();
+        let metrics = (0..36)
+            .flat_map(|i| {
+                vec![
+                    CpuMetrics::new("1", "1", "yarn", 75.0, 0.0, 4, i * 1000),
+                    CpuMetrics::new("1", "2", "postgres", 25.0, 0.0, 4, i * 1000),
+                    CpuMetrics::new("1", "db:3", "redis", 50.0, 0.0, 4, i * 1000),
+                ]
+            })
+            .collect();
+        let iteration = IterationWithMetrics::new(
+            ScenarioIteration::new("1", "basket_10", 1, 0, 36_000),
+            metrics,
+            vec![],
+        )
+        .with_power_metrics(power_metrics);
+
+        // redis ran on another machine so isn't covered by the meter
+        let energy = measured_model(&iteration, |metrics| metrics.process_id != "db:3");
+        assert_eq!(energy.len(), 2);
+        assert!((energy_of(&energy, "1") - 0.75).abs() < 1e-9);
+        assert!((energy_of(&energy, "2") - 0.25).abs() < 1e-9);
+    }
+
+    #[test]
+    fn process_power_is_used_when_measured() {
+        // 20 W for 36 seconds = 0.2 Wh
+        let metrics = (0..36)
+            .map(|i| {
+                CpuMetrics::new("1", "1", "yarn", 75.0, 0.0, 4, i * 1000).with_power(Some(20.0))
+            })
+            .collect();
+        let iteration = IterationWithMetrics::new(
+            ScenarioIteration::new("1", "basket_10", 1, 0, 36_000),
+            metrics,
+            vec![],
+        );
+
+        assert!(iteration.has_measured_power());
+        let energy = measured_model(&iteration, |_| true);
+        assert!((energy_of(&energy, "1") - 0.2).abs() < 1e-9);
+    }
+}
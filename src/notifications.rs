@@ -0,0 +1,487 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use crate::{
+    alerts::Alert,
+    config::{Budget, CiProvider, Config, Notifications, Webhook, WebhookFormat},
+    dataset::ObservationDataset,
+    model,
+};
+use anyhow::Context;
+use serde::Serialize;
+use std::time::Duration;
+
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunEvent {
+    RunCompleted,
+    BudgetExceeded,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ScenarioSummary {
+    pub scenario_name: String,
+    pub cpu_usage: f64,
+    /// Average power of the system under test (W).
+    pub power: f64,
+    /// Emissions of the system under test per iteration (gCO2e).
+    pub co2: f64,
+}
+impl ScenarioSummary {
+    /// The value of a budgeted metric, by its name in the config.
+    fn value(&self, metric: &str) -> f64 {
+        match metric {
+            "power" => self.power,
+            "co2" => self.co2,
+            _ => self.cpu_usage,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct BudgetBreach {
+    pub scenario_name: String,
+    pub metric: String,
+    pub value: f64,
+    pub limit: f64,
+}
+
+/// The JSON body sent to webhooks once a run has finished.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct RunSummary {
+    pub event: RunEvent,
+    pub run_id: String,
+    pub scenarios: Vec<ScenarioSummary>,
+    pub budget_breaches: Vec<BudgetBreach>,
+}
+impl RunSummary {
+    /// Summarises a single run from a dataset which may contain several runs, checking each
+    /// scenario against the budget if one is given. Power and emissions are modelled with the
+    /// config as `stats` does.
+    pub fn new(
+        run_id: &str,
+        dataset: &ObservationDataset,
+        budget: Option<&Budget>,
+        config: &Config,
+    ) -> Self {
+        let mut scenarios = vec![];
+        for scenario_dataset in dataset.by_scenario().iter() {
+            for run_dataset in scenario_dataset.by_run().iter() {
+                if run_dataset.run_id() != run_id {
+                    continue;
+                }
+
+                let cpu_usage = run_dataset
+                    .averaged()
+                    .iter()
+                    .map(|process_metrics| process_metrics.cpu_usage_total())
+                    .sum::<f64>();
+
+                let iterations = run_dataset.by_iterations();
+                let energy_wh = model::sut_energy_wh(config, iterations);
+                let duration_ms = iterations
+                    .iter()
+                    .map(|it| {
+                        it.scenario_iteration().stop_time - it.scenario_iteration().start_time
+                    })
+                    .sum::<i64>();
+                let power = match duration_ms {
+                    0 => 0.0,
+                    duration_ms => energy_wh * 3600.0 * 1000.0 / duration_ms as f64,
+                };
+                let co2 =
+                    energy_wh / iterations.len().max(1) as f64 / 1000.0 * config.grid_intensity();
+
+                scenarios.push(ScenarioSummary {
+                    scenario_name: scenario_dataset.scenario_name().to_string(),
+                    cpu_usage,
+                    power,
+                    co2,
+                });
+            }
+        }
+
+        let mut budget_breaches = vec![];
+        if let Some(budget) = budget {
+            let limits = [
+                ("cpu_usage", budget.cpu_usage),
+                ("power", budget.power),
+                ("co2", budget.co2),
+            ];
+            for (metric, limit) in limits {
+                let Some(limit) = limit else { continue };
+                for scenario in scenarios.iter().filter(|s| s.value(metric) > limit) {
+                    budget_breaches.push(BudgetBreach {
+                        scenario_name: scenario.scenario_name.clone(),
+                        metric: metric.to_string(),
+                        value: scenario.value(metric),
+                        limit,
+                    });
+                }
+            }
+        }
+
+        let event = if budget_breaches.is_empty() {
+            RunEvent::RunCompleted
+        } else {
+            RunEvent::BudgetExceeded
+        };
+
+        Self {
+            event,
+            run_id: run_id.to_string(),
+            scenarios,
+            budget_breaches,
+        }
+    }
+
+    /// A short human readable description of the run for chat integrations.
+    fn to_text(&self) -> String {
+        let mut text = match self.event {
+            RunEvent::RunCompleted => format!("Cardamon run {} completed", self.run_id),
+            RunEvent::BudgetExceeded => {
+                format!("Cardamon run {} exceeded its budget", self.run_id)
+            }
+        };
+
+        for scenario in self.scenarios.iter() {
+            text.push_str(&format!(
+                "\n• {}: {:.2}% CPU, {:.2} W, {:.4} gCO2e",
+                scenario.scenario_name, scenario.cpu_usage, scenario.power, scenario.co2
+            ));
+        }
+        for breach in self.budget_breaches.iter() {
+            text.push_str(&format!(
+                "\n⚠ {} {} {:.2} exceeds limit {:.2}",
+                breach.scenario_name, breach.metric, breach.value, breach.limit
+            ));
+        }
+
+        text
+    }
+
+    /// The run as a markdown table for CI job summaries.
+    fn to_markdown(&self) -> String {
+        let mut markdown = format!("### Cardamon run {}\n\n", self.run_id);
+        markdown.push_str("| Scenario | CPU | Power | CO2 |\n| --- | --- | --- | --- |\n");
+        for scenario in self.scenarios.iter() {
+            markdown.push_str(&format!(
+                "| {} | {:.2}% | {:.2} W | {:.4} gCO2e |\n",
+                scenario.scenario_name, scenario.cpu_usage, scenario.power, scenario.co2
+            ));
+        }
+        for breach in self.budget_breaches.iter() {
+            markdown.push_str(&format!(
+                "\n:warning: {} {} {:.2} exceeds limit {:.2}\n",
+                breach.scenario_name, breach.metric, breach.value, breach.limit
+            ));
+        }
+        markdown
+    }
+}
+
+/// Publishes the run summary to the CI system the run happens in. Failures are logged rather
+/// than failing the run, the results have already been saved by this point.
+pub fn publish_to_ci(provider: CiProvider, summary: &RunSummary) {
+    match provider {
+        CiProvider::Github => {
+            // the summary file is only set inside a GitHub Actions job
+            if let Some(path) = std::env::var_os("GITHUB_STEP_SUMMARY") {
+                if let Err(err) = append_step_summary(std::path::Path::new(&path), summary) {
+                    tracing::warn!("{err:?}");
+                }
+            }
+            for breach in summary.budget_breaches.iter() {
+                println!(
+                    "::warning title=Cardamon budget exceeded::{} {} {:.2} exceeds limit {:.2}",
+                    breach.scenario_name, breach.metric, breach.value, breach.limit
+                );
+            }
+        }
+        CiProvider::Gitlab => {
+            let timestamp = chrono::Utc::now().timestamp();
+            let section = format!("cardamon_{}", summary.run_id);
+            println!(
+                "\x1b[0Ksection_start:{timestamp}:{section}[collapsed=false]\r\x1b[0KCardamon run {}",
+                summary.run_id
+            );
+            println!("{}", summary.to_text());
+            println!("\x1b[0Ksection_end:{timestamp}:{section}\r\x1b[0K");
+        }
+    }
+}
+
+fn append_step_summary(path: &std::path::Path, summary: &RunSummary) -> anyhow::Result<()> {
+    use std::io::Write;
+
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| writeln!(file, "{}", summary.to_markdown()))
+        .context(format!("Error writing job summary to {}", path.display()))
+}
+
+fn webhook_body(format: WebhookFormat, summary: &RunSummary) -> serde_json::Value {
+    match format {
+        WebhookFormat::Json => serde_json::json!(summary),
+        // Slack and Teams incoming webhooks both accept a plain text message
+        WebhookFormat::Slack | WebhookFormat::Teams => serde_json::json!({
+            "text": summary.to_text()
+        }),
+    }
+}
+
+/// The JSON body sent to webhooks when an alert fires while monitoring live.
+#[derive(Debug, PartialEq, Serialize)]
+struct AlertFired<'a> {
+    event: &'static str,
+    run_id: &'a str,
+    alert: &'a Alert,
+}
+
+fn alert_body(format: WebhookFormat, run_id: &str, alert: &Alert) -> serde_json::Value {
+    match format {
+        WebhookFormat::Json => serde_json::json!(AlertFired {
+            event: "alert_fired",
+            run_id,
+            alert
+        }),
+        WebhookFormat::Slack | WebhookFormat::Teams => serde_json::json!({
+            "text": format!("Cardamon run {run_id}: {}", alert.to_text())
+        }),
+    }
+}
+
+/// How long a webhook is given to respond, so one which hangs doesn't hold up the run.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(WEBHOOK_TIMEOUT)
+        .build()
+        .expect("Should be able to build a http client")
+}
+
+/// The host of a webhook, which is all that's logged of it. Slack and Teams webhook urls are
+/// credentials in themselves.
+fn webhook_host(url: &str) -> String {
+    reqwest::Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(String::from))
+        .unwrap_or_else(|| String::from("webhook"))
+}
+
+async fn post(
+    client: &reqwest::Client,
+    webhook: &Webhook,
+    body: &serde_json::Value,
+) -> anyhow::Result<()> {
+    client
+        .post(&webhook.url)
+        .json(body)
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map(|_| ())
+        .map_err(reqwest::Error::without_url)
+        .context(format!(
+            "Error sending notification to {}",
+            webhook_host(&webhook.url)
+        ))
+}
+
+/// POSTs the run summary to every configured webhook. A failing webhook is logged rather than
+/// failing the run, the results have already been saved by this point.
+pub async fn notify(notifications: &Notifications, summary: &RunSummary) {
+    let client = client();
+    for webhook in notifications.webhooks.iter() {
+        let body = webhook_body(webhook.format, summary);
+        if let Err(err) = post(&client, webhook, &body).await {
+            tracing::warn!("{err:?}");
+        }
+    }
+}
+
+/// POSTs a fired alert to every configured webhook, failures are logged.
+pub async fn notify_alert(notifications: &Notifications, run_id: &str, alert: &Alert) {
+    let client = client();
+    for webhook in notifications.webhooks.iter() {
+        let body = alert_body(webhook.format, run_id, alert);
+        if let Err(err) = post(&client, webhook, &body).await {
+            tracing::warn!("{err:?}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        data_access::{cpu_metrics::CpuMetrics, scenario_iteration::ScenarioIteration},
+        dataset::IterationWithMetrics,
+    };
+
+    fn dataset() -> ObservationDataset {
+        let iteration = |run_id: &str, cpu_usage: f64| {
+            IterationWithMetrics::new(
+                ScenarioIteration::new(run_id, "basket_10", 1, 1000, 2000),
+                vec![
+                    CpuMetrics::new(run_id, "1", "yarn", cpu_usage, 0.0, 4, 1000),
+                    CpuMetrics::new(run_id, "1", "yarn", cpu_usage, 0.0, 4, 2000),
+                ],
+                vec![],
+            )
+        };
+        ObservationDataset::new(vec![iteration("old", 10.0), iteration("new", 100.0)])
+    }
+
+    fn config() -> Config {
+        toml::from_str::<Config>(
+            r#"
+            grid_intensity = 500.0
+
+            [cpu]
+            name = "test"
+            tdp = 40.0
+            "#,
+        )
+        .expect("config should parse")
+    }
+
+    #[test]
+    fn summary_only_includes_the_given_run() {
+        let summary = RunSummary::new("new", &dataset(), None, &config());
+
+        assert_eq!(summary.event, RunEvent::RunCompleted);
+        assert_eq!(summary.scenarios.len(), 1);
+        let scenario = &summary.scenarios[0];
+        assert_eq!(scenario.scenario_name, "basket_10");
+        assert_eq!(scenario.cpu_usage, 200.0);
+        // two samples at a quarter of the 40 W TDP over a one second iteration
+        assert_eq!(scenario.power, 20.0);
+        assert!((scenario.co2 - 20.0 / 3600.0 / 1000.0 * 500.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn scenarios_over_budget_are_reported() {
+        let budget = Budget {
+            cpu_usage: Some(150.0),
+            power: None,
+            co2: None,
+        };
+
+        let summary = RunSummary::new("new", &dataset(), Some(&budget), &config());
+        assert_eq!(summary.event, RunEvent::BudgetExceeded);
+        assert_eq!(summary.budget_breaches.len(), 1);
+        assert_eq!(summary.budget_breaches[0].limit, 150.0);
+
+        let summary = RunSummary::new("old", &dataset(), Some(&budget), &config());
+        assert_eq!(summary.event, RunEvent::RunCompleted);
+        assert!(summary.budget_breaches.is_empty());
+    }
+
+    #[test]
+    fn scenarios_over_power_or_co2_budgets_are_reported() {
+        let power_budget = Budget {
+            cpu_usage: None,
+            power: Some(15.0),
+            co2: None,
+        };
+        let summary = RunSummary::new("new", &dataset(), Some(&power_budget), &config());
+        assert_eq!(summary.event, RunEvent::BudgetExceeded);
+        assert_eq!(summary.budget_breaches.len(), 1);
+        assert_eq!(summary.budget_breaches[0].metric, "power");
+        assert_eq!(summary.budget_breaches[0].value, 20.0);
+        let summary = RunSummary::new("old", &dataset(), Some(&power_budget), &config());
+        assert!(summary.budget_breaches.is_empty());
+
+        let co2_budget = Budget {
+            cpu_usage: None,
+            power: None,
+            co2: Some(0.001),
+        };
+        let summary = RunSummary::new("new", &dataset(), Some(&co2_budget), &config());
+        assert_eq!(summary.event, RunEvent::BudgetExceeded);
+        assert_eq!(summary.budget_breaches.len(), 1);
+        assert_eq!(summary.budget_breaches[0].metric, "co2");
+        let summary = RunSummary::new("old", &dataset(), Some(&co2_budget), &config());
+        assert!(summary.budget_breaches.is_empty());
+    }
+
+    #[test]
+    fn chat_webhooks_receive_a_text_message() {
+        let summary = RunSummary::new("new", &dataset(), None, &config());
+
+        let body = webhook_body(WebhookFormat::Slack, &summary);
+        let text = body["text"].as_str().unwrap_or_default();
+        assert!(text.starts_with("Cardamon run new completed"));
+        assert!(text.contains("basket_10: 200.00% CPU"));
+
+        let body = webhook_body(WebhookFormat::Json, &summary);
+        assert_eq!(body["event"], "run_completed");
+        assert_eq!(body["run_id"], "new");
+    }
+
+    #[test]
+    fn github_job_summaries_are_appended_to() -> anyhow::Result<()> {
+        let path = std::env::temp_dir().join(format!("cardamon-summary-{}", nanoid::nanoid!(8)));
+        std::fs::write(&path, "earlier step\n")?;
+
+        let budget = Budget {
+            cpu_usage: Some(150.0),
+            power: None,
+            co2: None,
+        };
+        append_step_summary(
+            &path,
+            &RunSummary::new("new", &dataset(), Some(&budget), &config()),
+        )?;
+        let markdown = std::fs::read_to_string(&path)?;
+        std::fs::remove_file(&path)?;
+
+        assert!(markdown.starts_with("earlier step\n### Cardamon run new"));
+        assert!(markdown.contains("| basket_10 | 200.00% |"));
+        assert!(markdown.contains("basket_10 cpu_usage 200.00 exceeds limit 150.00"));
+        Ok(())
+    }
+
+    #[test]
+    fn alerts_are_sent_with_the_run() {
+        let alert = Alert {
+            name: "hot".to_string(),
+            process: None,
+            value: 60.0,
+            above: 50.0,
+            timestamp: 0,
+        };
+
+        let body = alert_body(WebhookFormat::Json, "live", &alert);
+        assert_eq!(body["event"], "alert_fired");
+        assert_eq!(body["alert"]["name"], "hot");
+
+        let body = alert_body(WebhookFormat::Teams, "live", &alert);
+        assert_eq!(
+            body["text"],
+            "Cardamon run live: Alert hot: 60.00 is above 50.00"
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_notifications_only_log_the_webhook_host() {
+        let webhook = Webhook {
+            url: "http://127.0.0.1:9/services/T000/B000/s3cret".to_string(),
+            format: WebhookFormat::Slack,
+        };
+
+        let err = post(&client(), &webhook, &serde_json::json!({}))
+            .await
+            .expect_err("Nothing should be listening on the discard port");
+        let logged = format!("{err:?}");
+        assert!(logged.contains("127.0.0.1"));
+        assert!(!logged.contains("s3cret"));
+    }
+}
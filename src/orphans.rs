@@ -0,0 +1,447 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Keeps track of the processes started by an in-flight run so anything left running after
+//! cardamon crashes can be found and stopped before it pollutes the measurements of later runs.
+
+use crate::{config::StopSignal, process_group, ProcessToObserve};
+use anyhow::Context;
+use bollard::Docker;
+use serde::{Deserialize, Serialize};
+use std::{path::Path, time::Duration};
+
+/// Where the processes started by the current run are recorded.
+pub const LOCK_FILE: &str = ".cardamon/run.lock";
+
+/// Held by the run measuring the machine, kept in the temp directory so runs started from other
+/// directories or recording into other databases see it too.
+pub const MACHINE_LOCK_FILE: &str = "cardamon-machine.lock";
+
+/// How long leftover processes are given to exit before they're killed.
+const GRACE_PERIOD: Duration = Duration::from_secs(10);
+
+/// How often a run waiting for the machine checks whether it's free.
+const LOCK_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A process recorded in a lock file. Its name and start time are kept with its pid so a pid the
+/// OS has since given to an unrelated process is never mistaken for it.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize)]
+pub struct TrackedProcess {
+    pub pid: u32,
+    pub name: String,
+    /// Seconds since the epoch.
+    pub start_time: u64,
+}
+impl TrackedProcess {
+    /// The running process with the pid, None if there isn't one.
+    pub fn of(pid: u32) -> Option<Self> {
+        let mut system = sysinfo::System::new();
+        let sys_pid = sysinfo::Pid::from_u32(pid);
+        system.refresh_process(sys_pid);
+        system.process(sys_pid).map(|process| Self {
+            pid,
+            name: process.name().to_string(),
+            start_time: process.start_time(),
+        })
+    }
+
+    /// True if the pid still belongs to this process.
+    pub fn is_running(&self) -> bool {
+        Self::of(self.pid).is_some_and(|process| process == *self)
+    }
+
+    /// True if this process, or the process group it leads, is still running. The OS doesn't
+    /// give a group's id to new processes while the group exists, so once the leader has exited
+    /// a running group is still the one it started.
+    fn is_left_over(&self) -> bool {
+        let is_same_leader = Self::of(self.pid).is_none_or(|process| process == *self);
+        is_same_leader && process_group::is_running(self.pid)
+    }
+}
+
+/// The processes and containers started by a run, written when the run starts them and removed
+/// once they've been shut down.
+#[derive(Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct RunLock {
+    pub run_id: String,
+    /// The cardamon process running the run.
+    pub owner: TrackedProcess,
+    pub pids: Vec<TrackedProcess>,
+    pub containers: Vec<String>,
+}
+impl RunLock {
+    pub fn new(run_id: &str) -> Self {
+        let pid = std::process::id();
+        Self {
+            run_id: run_id.to_string(),
+            owner: TrackedProcess::of(pid).unwrap_or(TrackedProcess {
+                pid,
+                ..Default::default()
+            }),
+            pids: vec![],
+            containers: vec![],
+        }
+    }
+
+    /// Records processes started for the run. Processes which have already exited are left out
+    /// as there's nothing to clean up.
+    pub fn track(&mut self, processes: &[ProcessToObserve]) {
+        for proc in processes.iter() {
+            match proc {
+                ProcessToObserve::Pid(_, pid) => self.pids.extend(TrackedProcess::of(*pid)),
+                ProcessToObserve::ContainerName(name) => self.containers.push(name.clone()),
+                // cardamon never starts units, cgroups or remote processes
+                ProcessToObserve::SystemdUnit(_)
+                | ProcessToObserve::Cgroup(_)
+                | ProcessToObserve::RemotePid(..) => {}
+            }
+        }
+    }
+
+    /// Forgets a process which was stopped, e.g. to be restarted for a cold start.
+    pub fn untrack_pid(&mut self, pid: u32) {
+        self.pids.retain(|tracked| tracked.pid != pid);
+    }
+
+    /// Reads the lock file at the given path, None if there isn't one.
+    pub fn read(path: &Path) -> anyhow::Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let json = std::fs::read_to_string(path)
+            .context(format!("Unable to read lock file {}", path.display()))?;
+        serde_json::from_str(&json)
+            .map(Some)
+            .context(format!("Error parsing lock file {}", path.display()))
+    }
+
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        std::fs::write(path, serde_json::to_string_pretty(self)?)
+            .context(format!("Unable to write lock file {}", path.display()))
+    }
+
+    pub fn remove(path: &Path) -> anyhow::Result<()> {
+        match std::fs::remove_file(path) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                Err(err).context(format!("Unable to remove lock file {}", path.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// True if the cardamon process which wrote the lock has exited without removing it, i.e.
+    /// the run crashed or was killed.
+    pub fn is_stale(&self) -> bool {
+        !self.owner.is_running()
+    }
+
+    /// Interrupts the cardamon process running the run, which cancels it the same way as pressing
+    /// Ctrl-C would.
+    #[cfg(unix)]
+    pub fn interrupt_owner(&self) -> anyhow::Result<()> {
+        // SAFETY: only sends a signal, the pid is checked to be a running cardamon process by the
+        // caller
+        if unsafe { libc::kill(self.owner.pid as libc::pid_t, libc::SIGINT) } == -1 {
+            return Err(anyhow::anyhow!(
+                "Unable to interrupt cardamon process {}: {}",
+                self.owner.pid,
+                std::io::Error::last_os_error()
+            ));
+        }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    pub fn interrupt_owner(&self) -> anyhow::Result<()> {
+        Err(anyhow::anyhow!(
+            "Cancelling runs from another process isn't supported on this OS"
+        ))
+    }
+
+    /// The processes and containers started by the run which are still running.
+    pub async fn leftovers(&self) -> Leftovers {
+        let pids = self
+            .pids
+            .iter()
+            .filter(|process| process.is_left_over())
+            .cloned()
+            .collect();
+
+        let mut containers = vec![];
+        if let Ok(docker) = Docker::connect_with_defaults() {
+            for name in self.containers.iter() {
+                let running = docker
+                    .inspect_container(name, None)
+                    .await
+                    .ok()
+                    .and_then(|container| container.state)
+                    .and_then(|state| state.running)
+                    .unwrap_or(false);
+                if running {
+                    containers.push(name.clone());
+                }
+            }
+        }
+
+        Leftovers { pids, containers }
+    }
+}
+
+/// Stops two runs measuring the machine at once, which would silently mix their processes' CPU
+/// usage up with each other's. The OS releases the lock when it's dropped or cardamon dies.
+#[derive(Debug)]
+pub struct MachineLock {
+    _file: std::fs::File,
+}
+impl MachineLock {
+    /// Takes the lock for the run.
+    ///
+    /// # Arguments
+    ///
+    /// * wait - wait for the run holding the lock to finish rather than failing straight away
+    pub async fn acquire(run_id: &str, wait: bool) -> anyhow::Result<Self> {
+        Self::acquire_at(&std::env::temp_dir().join(MACHINE_LOCK_FILE), run_id, wait).await
+    }
+
+    async fn acquire_at(path: &Path, run_id: &str, wait: bool) -> anyhow::Result<Self> {
+        // the file may have been created by another user, it can still be locked if it can't be
+        // written to
+        let mut file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .or_else(|_| std::fs::File::open(path))
+            .context(format!("Unable to open lock file {}", path.display()))?;
+
+        let mut waiting = false;
+        while !try_lock(&file)? {
+            let holder = std::fs::read_to_string(path).unwrap_or_default();
+            if !wait {
+                return Err(anyhow::anyhow!(
+                    "Run {} is already measuring this machine and the runs would skew each \
+                     other's measurements. Wait for it to finish with --wait or stop it with \
+                     `card cancel`",
+                    holder.trim()
+                ));
+            }
+            if !waiting {
+                println!("Waiting for run {} to finish", holder.trim());
+                waiting = true;
+            }
+            tokio::time::sleep(LOCK_POLL_INTERVAL).await;
+        }
+
+        // say who holds the lock for runs which find it taken
+        use std::io::Write;
+        let _ = file
+            .set_len(0)
+            .and_then(|_| write!(file, "{run_id} (cardamon pid {})", std::process::id()));
+        Ok(Self { _file: file })
+    }
+}
+
+/// Locks the file without blocking, false if another process (or another handle in this one)
+/// holds the lock.
+#[cfg(unix)]
+fn try_lock(file: &std::fs::File) -> anyhow::Result<bool> {
+    use std::os::unix::io::AsRawFd;
+
+    // SAFETY: the descriptor stays open for as long as the file is borrowed
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        return Ok(true);
+    }
+    let err = std::io::Error::last_os_error();
+    if err.raw_os_error() == Some(libc::EWOULDBLOCK) {
+        Ok(false)
+    } else {
+        Err(err).context("Unable to lock the machine for the run")
+    }
+}
+
+#[cfg(not(unix))]
+fn try_lock(_file: &std::fs::File) -> anyhow::Result<bool> {
+    Ok(true)
+}
+
+/// Processes and containers left running by a run which didn't finish.
+#[derive(Debug, Default, PartialEq)]
+pub struct Leftovers {
+    pub pids: Vec<TrackedProcess>,
+    pub containers: Vec<String>,
+}
+impl Leftovers {
+    pub fn len(&self) -> usize {
+        self.pids.len() + self.containers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Stops every leftover process along with any children it started and every leftover
+    /// container. Processes are checked again first in case they exited and their pid was reused
+    /// since they were found. Failures are logged so one stubborn process doesn't stop the rest
+    /// being cleaned up.
+    ///
+    /// # Returns
+    ///
+    /// The number of processes and containers stopped.
+    pub async fn stop(&self) -> usize {
+        let mut stopped = 0;
+
+        for process in self.pids.iter() {
+            let pid = process.pid;
+            if !process.is_left_over() {
+                tracing::info!("Process {pid} ({}) has already exited", process.name);
+                stopped += 1;
+                continue;
+            }
+            match process_group::stop(pid, Some(StopSignal::Sigterm), GRACE_PERIOD).await {
+                Ok(_) => stopped += 1,
+                Err(err) => tracing::warn!("Unable to stop process {pid}: {err}"),
+            }
+        }
+
+        if !self.containers.is_empty() {
+            match Docker::connect_with_defaults() {
+                Ok(docker) => {
+                    for name in self.containers.iter() {
+                        match docker.stop_container(name, None).await {
+                            Ok(()) => stopped += 1,
+                            Err(err) => tracing::warn!("Unable to stop container {name}: {err}"),
+                        }
+                    }
+                }
+                Err(err) => tracing::warn!("Unable to connect to docker: {err}"),
+            }
+        }
+
+        stopped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lock_files_round_trip() -> anyhow::Result<()> {
+        let path = std::env::temp_dir()
+            .join(nanoid::nanoid!(5))
+            .join("run.lock");
+        assert_eq!(RunLock::read(&path)?, None);
+
+        let mut lock = RunLock::new("abc");
+        lock.track(&[
+            ProcessToObserve::Pid(Some("db".to_string()), std::process::id()),
+            ProcessToObserve::ContainerName("postgres".to_string()),
+            ProcessToObserve::SystemdUnit("nginx.service".to_string()),
+        ]);
+        lock.write(&path)?;
+
+        let read = RunLock::read(&path)?.expect("lock file should exist");
+        assert_eq!(read, lock);
+        assert_eq!(read.pids, vec![read.owner.clone()]);
+        assert!(read.owner.start_time > 0);
+        assert_eq!(read.containers, vec!["postgres".to_string()]);
+
+        // the lock was written by this process which is still running
+        assert!(!read.is_stale());
+
+        RunLock::remove(&path)?;
+        assert_eq!(RunLock::read(&path)?, None);
+        RunLock::remove(&path)?;
+
+        Ok(())
+    }
+
+    #[cfg(target_family = "unix")]
+    #[tokio::test]
+    async fn only_one_run_measures_the_machine_at_a_time() -> anyhow::Result<()> {
+        let path = std::env::temp_dir().join(format!("cardamon-lock-{}", nanoid::nanoid!(8)));
+
+        let lock = MachineLock::acquire_at(&path, "first", false).await?;
+        let err = MachineLock::acquire_at(&path, "second", false)
+            .await
+            .expect_err("the machine should be locked");
+        assert!(err.to_string().starts_with("Run first (cardamon pid"));
+
+        // a waiting run starts once the lock is released
+        let waiting = tokio::spawn({
+            let path = path.clone();
+            async move { MachineLock::acquire_at(&path, "second", true).await }
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!waiting.is_finished());
+        drop(lock);
+        let lock = waiting.await??;
+        assert!(std::fs::read_to_string(&path)?.starts_with("second"));
+
+        drop(lock);
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[cfg(target_family = "unix")]
+    #[tokio::test]
+    async fn leftover_processes_are_stopped() -> anyhow::Result<()> {
+        let popen = subprocess::Popen::create(
+            &["sleep", "30"],
+            subprocess::PopenConfig {
+                detached: true,
+                setpgid: true,
+                ..Default::default()
+            },
+        )?;
+        let pid = popen.pid().expect("process should have a pid");
+
+        let process = TrackedProcess::of(pid).expect("process should be running");
+
+        // a run which crashed, leaving its process running
+        let lock = RunLock {
+            run_id: "abc".to_string(),
+            owner: TrackedProcess {
+                pid: u32::MAX,
+                ..Default::default()
+            },
+            pids: vec![process.clone()],
+            containers: vec![],
+        };
+        assert!(lock.is_stale());
+
+        // the pid was reused by another process after the run crashed
+        let reused = RunLock {
+            pids: vec![TrackedProcess {
+                start_time: process.start_time - 60,
+                ..process.clone()
+            }],
+            ..Default::default()
+        };
+        assert!(reused.leftovers().await.is_empty());
+        let owner_reused = RunLock {
+            owner: TrackedProcess {
+                name: "bash".to_string(),
+                ..process.clone()
+            },
+            ..Default::default()
+        };
+        assert!(owner_reused.is_stale());
+
+        let leftovers = lock.leftovers().await;
+        assert_eq!(leftovers.pids, vec![process]);
+        assert_eq!(leftovers.stop().await, 1);
+        assert!(lock.leftovers().await.is_empty());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,198 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Stops processes started by cardamon along with every child they've started. Each process is
+//! started in its own process group so signals reach the whole tree, otherwise children left
+//! behind by a shell or process manager keep consuming energy after the run.
+
+use crate::config::StopSignal;
+use std::time::Duration;
+
+/// How often the process group is checked while waiting for it to exit.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long to wait for a killed process group to be cleaned up by the OS.
+#[cfg(unix)]
+const KILL_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[cfg(unix)]
+fn raw_signal(signal: StopSignal) -> libc::c_int {
+    match signal {
+        StopSignal::Sigterm => libc::SIGTERM,
+        StopSignal::Sigint => libc::SIGINT,
+        StopSignal::Sighup => libc::SIGHUP,
+        StopSignal::Sigquit => libc::SIGQUIT,
+        StopSignal::Sigusr1 => libc::SIGUSR1,
+        StopSignal::Sigusr2 => libc::SIGUSR2,
+        StopSignal::Sigkill => libc::SIGKILL,
+    }
+}
+
+/// Sends a signal to every process in the group led by `pgid`. Succeeds if the group has already
+/// exited.
+#[cfg(unix)]
+fn send_signal(pgid: u32, signal: libc::c_int) -> anyhow::Result<()> {
+    // SAFETY: kill has no memory safety requirements, a negative pid targets the process group
+    let res = unsafe { libc::kill(-(pgid as libc::pid_t), signal) };
+    if res == -1 {
+        let err = std::io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::ESRCH) {
+            return Err(anyhow::anyhow!(
+                "Unable to signal process group {pgid}: {err}"
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Returns true while any process in the group led by `pgid` is still running. The group leader
+/// is reaped if it has exited so it doesn't linger as a zombie.
+#[cfg(unix)]
+pub fn is_running(pgid: u32) -> bool {
+    // SAFETY: a null status pointer is allowed and WNOHANG never blocks. Fails harmlessly if the
+    // leader isn't a child of this process.
+    unsafe { libc::waitpid(pgid as libc::pid_t, std::ptr::null_mut(), libc::WNOHANG) };
+
+    // SAFETY: signal 0 only checks the group exists
+    let exists = unsafe { libc::kill(-(pgid as libc::pid_t), 0) == 0 };
+    exists && has_live_members(pgid)
+}
+
+/// Checks the group has a member which isn't a zombie. Orphaned children are only reaped by init,
+/// which may never happen when there's no init process (e.g. in a container).
+#[cfg(target_os = "linux")]
+fn has_live_members(pgid: u32) -> bool {
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return true;
+    };
+
+    entries.flatten().any(|entry| {
+        std::fs::read_to_string(entry.path().join("stat"))
+            .ok()
+            .and_then(|stat| {
+                // the fields after the command name are state, parent pid then process group
+                let mut fields = stat.get(stat.rfind(')')? + 1..)?.split_whitespace();
+                let state = fields.next()?;
+                let group = fields.nth(1)?.parse::<u32>().ok()?;
+                Some(group == pgid && state != "Z")
+            })
+            .unwrap_or(false)
+    })
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn has_live_members(_pgid: u32) -> bool {
+    true
+}
+
+/// Stops every process in the group led by `pgid`. The group is sent `signal` if one is given and
+/// then given `grace_period` to exit before whatever is left is killed.
+///
+/// # Arguments
+///
+/// * `pgid` - The id of the process group, the same as the pid of the process cardamon started
+/// * `signal` - Signal asking the processes to exit, None if they've already been asked (e.g. by
+///   a `down` command)
+/// * `grace_period` - How long to wait for the processes to exit before killing them
+///
+/// # Returns
+///
+/// True if the group had to be killed.
+#[cfg(unix)]
+pub async fn stop(
+    pgid: u32,
+    signal: Option<StopSignal>,
+    grace_period: Duration,
+) -> anyhow::Result<bool> {
+    if let Some(signal) = signal {
+        send_signal(pgid, raw_signal(signal))?;
+    }
+
+    if wait_for_exit(pgid, grace_period).await {
+        return Ok(false);
+    }
+
+    send_signal(pgid, libc::SIGKILL)?;
+    if !wait_for_exit(pgid, KILL_TIMEOUT).await {
+        tracing::warn!("Process group {pgid} is still running after being killed");
+    }
+    Ok(true)
+}
+
+/// Waits up to `timeout` for every process in the group to exit, returns false if some are still
+/// running.
+pub async fn wait_for_exit(pgid: u32, timeout: Duration) -> bool {
+    let deadline = tokio::time::Instant::now() + timeout;
+    while is_running(pgid) {
+        if tokio::time::Instant::now() >= deadline {
+            return false;
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+    true
+}
+
+/// Process groups aren't available on Windows so only the process itself is checked.
+#[cfg(not(unix))]
+pub fn is_running(pgid: u32) -> bool {
+    let mut system = sysinfo::System::new();
+    system.refresh_process(sysinfo::Pid::from_u32(pgid))
+}
+
+/// Process groups aren't available on Windows, processes are only stopped by their `down`
+/// command.
+#[cfg(not(unix))]
+pub async fn stop(
+    _pgid: u32,
+    _signal: Option<StopSignal>,
+    _grace_period: Duration,
+) -> anyhow::Result<bool> {
+    Ok(false)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use subprocess::{Popen, PopenConfig};
+
+    fn start_group(command: &str) -> anyhow::Result<u32> {
+        let popen = Popen::create(
+            &["sh", "-c", command],
+            PopenConfig {
+                detached: true,
+                setpgid: true,
+                ..Default::default()
+            },
+        )?;
+        popen
+            .pid()
+            .ok_or_else(|| anyhow::anyhow!("Process should have a PID"))
+    }
+
+    #[tokio::test]
+    async fn process_groups_exit_when_signalled() -> anyhow::Result<()> {
+        let pgid = start_group("sleep 30 & sleep 30; wait")?;
+
+        let killed = stop(pgid, Some(StopSignal::Sigterm), Duration::from_secs(5)).await?;
+        assert!(!killed);
+        assert!(!is_running(pgid));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn process_groups_ignoring_the_signal_are_killed() -> anyhow::Result<()> {
+        let pgid = start_group("trap '' TERM; sleep 30 & sleep 30; wait")?;
+        // give the shell time to ignore the signal
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let killed = stop(pgid, Some(StopSignal::Sigterm), Duration::from_millis(500)).await?;
+        assert!(killed);
+        assert!(!is_running(pgid));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,113 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! A live line showing how a scenario iteration is getting on while it runs. The line is only
+//! drawn when stderr is a terminal.
+
+use crate::{config::Cpu, metrics::Progress};
+use indicatif::{ProgressBar, ProgressStyle};
+use std::time::Duration;
+
+/// How often the progress line is refreshed.
+pub const REFRESH_INTERVAL: Duration = Duration::from_millis(500);
+
+pub struct ProgressLine {
+    bar: ProgressBar,
+}
+impl ProgressLine {
+    pub fn start() -> Self {
+        let bar = ProgressBar::new_spinner();
+        bar.set_style(
+            ProgressStyle::with_template("{spinner} {elapsed} {msg}")
+                .unwrap_or_else(|_| ProgressStyle::default_spinner()),
+        );
+        bar.enable_steady_tick(REFRESH_INTERVAL);
+        Self { bar }
+    }
+
+    pub fn update(&self, progress: &Progress, cpu: Option<&Cpu>) {
+        self.bar.set_message(describe(progress, cpu));
+    }
+
+    pub fn finish(self) {
+        self.bar.finish_and_clear();
+    }
+}
+
+/// Watts being drawn, measured if there's a power meter or estimated from the CPU's TDP
+/// otherwise.
+pub fn power(progress: &Progress, cpu: Option<&Cpu>) -> Option<f64> {
+    progress
+        .power
+        .or_else(|| cpu.map(|cpu| cpu.total_tdp() * progress.cpu_usage / 100.0))
+}
+
+/// The number of samples collected and the power being drawn, measured if there's a power
+/// meter or estimated from the CPU's TDP otherwise.
+fn describe(progress: &Progress, cpu: Option<&Cpu>) -> String {
+    let power = match (progress.power, cpu) {
+        (Some(power), _) => format!(", {power:.1} W"),
+        (None, Some(cpu)) => format!(", ~{:.1} W", cpu.total_tdp() * progress.cpu_usage / 100.0),
+        (None, None) => String::new(),
+    };
+    format!("{} samples{power}", progress.samples)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::PowerModel,
+        metrics::{CpuMetrics, MetricsLog, PowerMetrics},
+    };
+
+    fn sample(process_id: &str, cpu_usage: f64, timestamp: i64) -> CpuMetrics {
+        CpuMetrics {
+            process_id: process_id.to_string(),
+            process_name: process_id.to_string(),
+            cpu_usage,
+            core_count: 4,
+            timestamp,
+            cpu_frequency: None,
+            memory_usage: None,
+            power: None,
+            sample_interval: None,
+            memory_limit: None,
+            cpu_periods: None,
+            throttled_periods: None,
+        }
+    }
+
+    #[test]
+    fn progress_estimates_power_from_the_latest_samples() {
+        let mut log = MetricsLog::new();
+        log.push_metrics(sample("1", 400.0, 1000));
+        log.push_metrics(sample("1", 100.0, 2000));
+        log.push_metrics(sample("2", 100.0, 2000));
+        let cpu = Cpu {
+            name: String::from("Test CPU"),
+            tdp: 100.0,
+            sockets: 1,
+            model: PowerModel::default(),
+            max_frequency: None,
+        };
+
+        let progress = log.progress();
+        assert_eq!(progress.samples, 3);
+        assert_eq!(progress.cpu_usage, 50.0);
+        assert_eq!(describe(&progress, Some(&cpu)), "3 samples, ~50.0 W");
+        assert_eq!(describe(&progress, None), "3 samples");
+        assert_eq!(power(&progress, Some(&cpu)), Some(50.0));
+        assert_eq!(power(&progress, None), None);
+
+        log.push_power(PowerMetrics {
+            source: String::from("meter"),
+            power: 42.0,
+            timestamp: 2000,
+        });
+        assert_eq!(describe(&log.progress(), Some(&cpu)), "4 samples, 42.0 W");
+    }
+}
@@ -0,0 +1,387 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+use crate::{
+    config::{Email, ReportSchedule},
+    data_access::DataAccessService,
+    dataset::{ObservationDataset, ProcessFilter},
+};
+use anyhow::Context;
+use futures_util::stream::{BoxStream, StreamExt, TryStreamExt};
+use lettre::{
+    message::Mailbox, transport::smtp::authentication::Credentials, AsyncSmtpTransport,
+    AsyncTransport, Message, Tokio1Executor,
+};
+use std::collections::HashMap;
+use tokio_util::sync::CancellationToken;
+
+/// The number of previous runs of each scenario considered when building a report.
+const PREVIOUS_RUNS: u32 = 100;
+
+/// How much more CPU the latest run of a scenario can use compared to the average of earlier runs
+/// before it's reported as a regression.
+const REGRESSION_THRESHOLD: f64 = 0.1;
+
+/// The number of processes listed as top consumers.
+const TOP_CONSUMERS: usize = 5;
+
+/// Totals of the runs added so far, turned into a report once every run has been seen.
+struct ReportBuilder {
+    since: i64,
+    /// Start time and total cpu usage of each run of each scenario in the period.
+    runs_by_scenario: Vec<(String, Vec<(i64, f64)>)>,
+    usage_by_process: HashMap<String, f64>,
+}
+impl ReportBuilder {
+    fn new(since: i64) -> Self {
+        Self {
+            since,
+            runs_by_scenario: vec![],
+            usage_by_process: HashMap::new(),
+        }
+    }
+
+    fn add(&mut self, dataset: &ObservationDataset) {
+        for scenario_dataset in dataset.by_scenario().iter() {
+            let scenario_name = scenario_dataset.scenario_name();
+            let index = match self
+                .runs_by_scenario
+                .iter()
+                .position(|(name, _)| name == scenario_name)
+            {
+                Some(index) => index,
+                None => {
+                    self.runs_by_scenario
+                        .push((scenario_name.to_string(), vec![]));
+                    self.runs_by_scenario.len() - 1
+                }
+            };
+
+            for run_dataset in scenario_dataset.by_run().iter() {
+                let start_time = run_dataset.start_time();
+                if start_time < self.since {
+                    continue;
+                }
+
+                for iteration in run_dataset.by_iterations().iter() {
+                    for metrics in iteration.cpu_metrics() {
+                        *self
+                            .usage_by_process
+                            .entry(metrics.process_name.clone())
+                            .or_default() += metrics.cpu_usage;
+                    }
+                }
+
+                let cpu_usage = run_dataset
+                    .averaged()
+                    .iter()
+                    .map(|process_metrics| process_metrics.cpu_usage_total())
+                    .sum::<f64>();
+                self.runs_by_scenario[index].1.push((start_time, cpu_usage));
+            }
+        }
+    }
+
+    fn build(self) -> Report {
+        let mut trends = vec![];
+        for (scenario_name, mut runs) in self.runs_by_scenario.into_iter() {
+            // in the order they started
+            runs.sort_by_key(|(start_time, _)| *start_time);
+
+            if let (Some((_, first)), Some((_, last))) = (runs.first(), runs.last()) {
+                let earlier = &runs[..runs.len() - 1];
+                let regressed = !earlier.is_empty() && {
+                    let mean =
+                        earlier.iter().map(|(_, usage)| usage).sum::<f64>() / earlier.len() as f64;
+                    *last > mean * (1.0 + REGRESSION_THRESHOLD)
+                };
+
+                trends.push(ScenarioTrend {
+                    scenario_name,
+                    runs: runs.len(),
+                    first_cpu_usage: *first,
+                    last_cpu_usage: *last,
+                    regressed,
+                });
+            }
+        }
+
+        let mut top_consumers = self.usage_by_process.into_iter().collect::<Vec<_>>();
+        top_consumers.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        top_consumers.truncate(TOP_CONSUMERS);
+
+        Report {
+            trends,
+            top_consumers,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ScenarioTrend {
+    pub scenario_name: String,
+    pub runs: usize,
+    pub first_cpu_usage: f64,
+    pub last_cpu_usage: f64,
+    pub regressed: bool,
+}
+
+/// A summary of all runs within a period, sent by email while running as a daemon.
+#[derive(Debug, PartialEq)]
+pub struct Report {
+    pub trends: Vec<ScenarioTrend>,
+    pub top_consumers: Vec<(String, f64)>,
+}
+impl Report {
+    /// Builds a report from every run in the dataset which started at or after `since`.
+    ///
+    /// # Arguments
+    ///
+    /// * `dataset` - Runs of the scenarios to report on
+    /// * `since` - Unix timestamp in milliseconds marking the start of the report period
+    pub fn new(dataset: &ObservationDataset, since: i64) -> Self {
+        let mut builder = ReportBuilder::new(since);
+        builder.add(dataset);
+        builder.build()
+    }
+
+    /// Builds a report from a stream of runs such as `stream_observation_dataset`, holding the
+    /// metrics of one run in memory at a time.
+    pub async fn from_stream(
+        mut datasets: BoxStream<'_, crate::error::Result<ObservationDataset>>,
+        since: i64,
+    ) -> anyhow::Result<Self> {
+        let mut builder = ReportBuilder::new(since);
+        while let Some(dataset) = datasets.try_next().await? {
+            builder.add(&dataset);
+        }
+        Ok(builder.build())
+    }
+
+    pub fn regressions(&self) -> Vec<&ScenarioTrend> {
+        self.trends.iter().filter(|trend| trend.regressed).collect()
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::from("Scenario trends\n---------------\n");
+        if self.trends.is_empty() {
+            text.push_str("No runs in this period.\n");
+        }
+        for trend in self.trends.iter() {
+            text.push_str(&format!(
+                "{}: {:.2}% -> {:.2}% CPU over {} runs{}\n",
+                trend.scenario_name,
+                trend.first_cpu_usage,
+                trend.last_cpu_usage,
+                trend.runs,
+                if trend.regressed { " (regression)" } else { "" }
+            ));
+        }
+
+        text.push_str("\nTop consumers\n-------------\n");
+        for (process_name, cpu_usage) in self.top_consumers.iter() {
+            text.push_str(&format!("{process_name}: {cpu_usage:.2}% CPU\n"));
+        }
+
+        let regressions = self.regressions();
+        if !regressions.is_empty() {
+            text.push_str("\nRegressions\n-----------\n");
+            for trend in regressions {
+                text.push_str(&format!("{}\n", trend.scenario_name));
+            }
+        }
+
+        text
+    }
+}
+
+fn build_message(email: &Email, report: &Report) -> anyhow::Result<Message> {
+    let from = email
+        .from
+        .parse::<Mailbox>()
+        .context(format!("Invalid email address: {}", email.from))?;
+
+    let mut builder = Message::builder().from(from).subject(match email.schedule {
+        ReportSchedule::Weekly => "Cardamon weekly report",
+        ReportSchedule::Monthly => "Cardamon monthly report",
+    });
+    for to in email.to.iter() {
+        let to = to
+            .parse::<Mailbox>()
+            .context(format!("Invalid email address: {to}"))?;
+        builder = builder.to(to);
+    }
+
+    builder
+        .body(report.to_text())
+        .context("Error building report email")
+}
+
+/// Emails the report to every recipient using the configured SMTP server.
+pub async fn send_report(email: &Email, report: &Report) -> anyhow::Result<()> {
+    let message = build_message(email, report)?;
+
+    let mut transport = AsyncSmtpTransport::<Tokio1Executor>::relay(&email.host)
+        .context(format!("Unable to connect to SMTP server {}", email.host))?;
+    if let Some(port) = email.port {
+        transport = transport.port(port);
+    }
+    if let (Some(username), Some(password)) = (&email.username, &email.password) {
+        transport = transport.credentials(Credentials::new(username.clone(), password.resolve()?));
+    }
+
+    transport
+        .build()
+        .send(message)
+        .await
+        .map(|_| ())
+        .context("Error sending report email")
+}
+
+/// Emails a report of the given scenarios at the end of every scheduled period until the
+/// cancellation token is cancelled.
+///
+/// # Arguments
+///
+/// * `email` - SMTP settings and the report schedule
+/// * `scenario_names` - The scenarios to include in each report
+/// * `filter` - The processes to include in each report
+/// * `data_access_service` - Where runs are read from
+/// * `token` - Cancel this token to stop reporting
+pub async fn keep_reporting(
+    email: &Email,
+    scenario_names: Vec<&str>,
+    filter: &ProcessFilter,
+    data_access_service: &dyn DataAccessService,
+    token: CancellationToken,
+) -> anyhow::Result<()> {
+    let period = email.schedule.period();
+    loop {
+        tokio::select! {
+            _ = token.cancelled() => break,
+            _ = tokio::time::sleep(period) => {}
+        }
+
+        let since = chrono::Utc::now().timestamp_millis() - period.as_millis() as i64;
+        let datasets = data_access_service
+            .stream_observation_dataset(scenario_names.clone(), PREVIOUS_RUNS)
+            .map_ok(|dataset| dataset.filter_processes(filter))
+            .boxed();
+        let report = Report::from_stream(datasets, since).await?;
+
+        // a failed email shouldn't stop future reports
+        match send_report(email, &report).await {
+            Ok(()) => tracing::info!("Sent {:?} report to {:?}", email.schedule, email.to),
+            Err(err) => tracing::error!("{err:?}"),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        data_access::{cpu_metrics::CpuMetrics, scenario_iteration::ScenarioIteration},
+        dataset::IterationWithMetrics,
+    };
+
+    fn iteration(
+        run_id: &str,
+        start_time: i64,
+        process: &str,
+        cpu_usage: f64,
+    ) -> IterationWithMetrics {
+        IterationWithMetrics::new(
+            ScenarioIteration::new(run_id, "basket_10", 1, start_time, start_time + 1000),
+            vec![CpuMetrics::new(
+                run_id, "1", process, cpu_usage, 0.0, 4, start_time,
+            )],
+            vec![],
+        )
+    }
+
+    fn dataset() -> ObservationDataset {
+        ObservationDataset::new(vec![
+            iteration("1", 1000, "yarn", 500.0),
+            iteration("2", 10_000, "yarn", 50.0),
+            iteration("3", 20_000, "yarn", 50.0),
+            iteration("4", 30_000, "postgres", 80.0),
+        ])
+    }
+
+    #[test]
+    fn runs_before_the_period_are_ignored() {
+        let report = Report::new(&dataset(), 5_000);
+
+        assert_eq!(
+            report.trends,
+            vec![ScenarioTrend {
+                scenario_name: "basket_10".to_string(),
+                runs: 3,
+                first_cpu_usage: 50.0,
+                last_cpu_usage: 80.0,
+                regressed: true,
+            }]
+        );
+        assert_eq!(
+            report.top_consumers,
+            vec![("yarn".to_string(), 100.0), ("postgres".to_string(), 80.0)]
+        );
+    }
+
+    #[test]
+    fn a_single_run_is_not_a_regression() {
+        let report = Report::new(&dataset(), 30_000);
+        assert!(report.regressions().is_empty());
+        assert!(report
+            .to_text()
+            .contains("basket_10: 80.00% -> 80.00% CPU over 1 runs"));
+    }
+
+    #[tokio::test]
+    async fn streamed_reports_match_reports_built_at_once() -> anyhow::Result<()> {
+        let runs = dataset()
+            .data()
+            .iter()
+            .map(|it| {
+                let run_id = &it.scenario_iteration().run_id;
+                let start_time = it.scenario_iteration().start_time;
+                let cpu_usage = it.cpu_metrics()[0].cpu_usage;
+                let process = &it.cpu_metrics()[0].process_name;
+                Ok(ObservationDataset::new(vec![iteration(
+                    run_id, start_time, process, cpu_usage,
+                )]))
+            })
+            .collect::<Vec<_>>();
+
+        let report = Report::from_stream(futures_util::stream::iter(runs).boxed(), 5_000).await?;
+        assert_eq!(report, Report::new(&dataset(), 5_000));
+
+        Ok(())
+    }
+
+    #[test]
+    fn report_emails_are_sent_to_every_recipient() -> anyhow::Result<()> {
+        let email = Email {
+            host: "smtp.example.com".to_string(),
+            port: None,
+            username: None,
+            password: None,
+            from: "cardamon@example.com".to_string(),
+            to: vec!["a@example.com".to_string(), "b@example.com".to_string()],
+            schedule: ReportSchedule::Weekly,
+        };
+
+        let message = build_message(&email, &Report::new(&dataset(), 0))?;
+        let message = String::from_utf8(message.formatted())?;
+        assert!(message.contains("To: a@example.com, b@example.com"));
+        assert!(message.contains("Subject: Cardamon weekly report"));
+
+        Ok(())
+    }
+}
@@ -1,16 +1,87 @@
 mod errors;
+pub mod grafana;
+pub mod grpc;
+pub mod runs;
+pub mod ui;
 use chrono::Utc;
 
+use anyhow::Context;
 use axum::{
-    extract::{Path, Query, State},
+    extract::{FromRef, Path, Query, State},
+    http::{header, HeaderValue},
+    response::IntoResponse,
     Json,
 };
-use cardamon::data_access::{cpu_metrics::CpuMetrics, scenario_iteration::ScenarioIteration};
+use axum_server::tls_rustls::RustlsConfig;
+use cardamon::{
+    badge::{self, Metric},
+    config::Config,
+    data_access::{
+        baseline::Baseline,
+        cpu_metrics::CpuMetrics,
+        endpoint_request::EndpointRequest,
+        energy_mix::EnergyMix,
+        iteration_phase::IterationPhase,
+        iteration_result::IterationResult,
+        pagination::{Page, PageRequest},
+        power_metrics::PowerMetrics,
+        process_event::ProcessEvent,
+        process_info::ProcessInfo,
+        run::{self, ReferenceRun, Run, RunDao, RunSummary},
+        run_context::RunContext,
+        run_impact::RunImpact,
+        run_phase::RunPhase,
+        scenario_iteration::{self, ScenarioIteration, ScenarioIterationDao, ScenarioSummary},
+        DataAccessService, LocalDataAccessService, DEFAULT_PROJECT,
+    },
+    dataset::{AggregationMethod, ProcessFilter},
+    error::CardamonError,
+    logs::{self, Stream},
+    model,
+    stats::Trend,
+};
 use errors::ServerError;
 use serde::Deserialize;
 use sqlx::SqlitePool;
+use std::path::PathBuf;
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
 use tracing::instrument;
 
+/// Shared by the routes, most of which only need the database.
+#[derive(Clone, Debug)]
+pub struct AppState {
+    pub pool: SqlitePool,
+    pub runs: runs::RunRegistry,
+    /// The config file observations started through the API are read from.
+    pub config: Option<PathBuf>,
+    pub api_token: Option<String>,
+}
+impl AppState {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self {
+            pool,
+            runs: runs::RunRegistry::default(),
+            config: None,
+            api_token: None,
+        }
+    }
+
+    /// The server's config file, for routes which model energy.
+    pub fn read_config(&self) -> Result<Config, ServerError> {
+        let path = self.config.as_deref().ok_or_else(|| {
+            CardamonError::InvalidInput(String::from(
+                "The server was started without a config file to model energy with",
+            ))
+        })?;
+        Ok(Config::from_path(path)?)
+    }
+}
+impl FromRef<AppState> for SqlitePool {
+    fn from_ref(state: &AppState) -> Self {
+        state.pool.clone()
+    }
+}
+
 // Must receive data from src/data_access/cpu_metrics.rs in this format:
 /*
 
@@ -122,14 +193,21 @@ async fn insert_metrics_into_db(
     metrics: &CpuMetrics,
 ) -> Result<(), sqlx::Error> {
     sqlx::query!(
-        "INSERT INTO cpu_metrics (run_id, process_id, process_name, cpu_usage, total_usage, core_count, timestamp) VALUES (?, ?, ?, ?, ?, ?, ?)",
+        "INSERT INTO cpu_metrics (run_id, process_id, process_name, cpu_usage, total_usage, core_count, timestamp, cpu_frequency, memory_usage, power, sample_interval, memory_limit, cpu_periods, throttled_periods) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
         metrics.run_id,
         metrics.process_id,
         metrics.process_name,
         metrics.cpu_usage,
         metrics.total_usage,
         metrics.core_count,
-        metrics.timestamp
+        metrics.timestamp,
+        metrics.cpu_frequency,
+        metrics.memory_usage,
+        metrics.power,
+        metrics.sample_interval,
+        metrics.memory_limit,
+        metrics.cpu_periods,
+        metrics.throttled_periods
     )
     .execute(pool)
     .await?;
@@ -173,6 +251,7 @@ async fn insert_metrics_into_db(
             .context("Error deleting scenario from remote server")
     }
 */
+#[allow(dead_code)]
 #[instrument(name = "Fetch last scenario_iteration")]
 pub async fn scenario_iteration_fetch_last(
     State(pool): State<SqlitePool>,
@@ -206,6 +285,7 @@ pub async fn scenario_iteration_persist(
     Ok("Scenario run persisted".to_string())
 }
 
+#[allow(dead_code)]
 #[inline]
 async fn fetch_last_scenario_iteration(
     pool: &SqlitePool,
@@ -224,14 +304,1334 @@ async fn insert_scenario_iteration_into_db(
     scenario_iteration: &ScenarioIteration,
 ) -> Result<(), sqlx::Error> {
     sqlx::query!(
-        "INSERT INTO scenario_iteration (run_id, scenario_name, iteration, start_time, stop_time) VALUES (?, ?, ?, ?, ?)",
+        "INSERT OR REPLACE INTO scenario_iteration (run_id, scenario_name, iteration, start_time, stop_time, status, cold_start) VALUES (?, ?, ?, ?, ?, ?, ?)",
         scenario_iteration.run_id,
         scenario_iteration.scenario_name,
         scenario_iteration.iteration,
         scenario_iteration.start_time,
-        scenario_iteration.stop_time
+        scenario_iteration.stop_time,
+        scenario_iteration.status,
+        scenario_iteration.cold_start
     )
     .execute(pool)
     .await?;
     Ok(())
 }
+
+// Below routes must confirm to these routes found in src/data_access/process_event.rs
+#[instrument(name = "Fetch process events within a time range")]
+pub async fn process_event_fetch_within(
+    Path(run_id): Path<String>,
+    Query(params): Query<WithinParams>,
+    State(pool): State<SqlitePool>,
+) -> anyhow::Result<Json<Vec<ProcessEvent>>, ServerError> {
+    let begin = params.begin.unwrap_or(0);
+    let end = params.end.unwrap_or_else(|| Utc::now().timestamp_millis());
+
+    let process_events = sqlx::query_as!(
+        ProcessEvent,
+        "SELECT * FROM process_event WHERE run_id = ? AND timestamp BETWEEN ? AND ? ORDER BY timestamp",
+        run_id,
+        begin,
+        end
+    )
+    .fetch_all(&pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to fetch process events from database: {:?}", e);
+        ServerError::DatabaseError(e)
+    })?;
+
+    tracing::info!(
+        "Successfully fetched {} process events",
+        process_events.len()
+    );
+    Ok(Json(process_events))
+}
+
+#[instrument(name = "Persist process event")]
+pub async fn process_event_persist(
+    State(pool): State<SqlitePool>,
+    Json(payload): Json<ProcessEvent>,
+) -> anyhow::Result<String, ServerError> {
+    tracing::debug!("Received payload: {:?}", payload);
+
+    sqlx::query!(
+        "INSERT INTO process_event (run_id, process_name, event, timestamp) VALUES (?, ?, ?, ?)",
+        payload.run_id,
+        payload.process_name,
+        payload.event,
+        payload.timestamp
+    )
+    .execute(&pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to persist process event: {:?}", e);
+        ServerError::DatabaseError(e)
+    })?;
+
+    tracing::info!("Process event persisted successfully");
+    Ok("Process event persisted".to_string())
+}
+
+// Below routes must confirm to these routes found in src/data_access/power_metrics.rs
+#[instrument(name = "Fetch power metrics within a time range")]
+pub async fn power_metrics_fetch_within(
+    Path(run_id): Path<String>,
+    Query(params): Query<WithinParams>,
+    State(pool): State<SqlitePool>,
+) -> anyhow::Result<Json<Vec<PowerMetrics>>, ServerError> {
+    let begin = params.begin.unwrap_or(0);
+    let end = params.end.unwrap_or_else(|| Utc::now().timestamp_millis());
+
+    let power_metrics = sqlx::query_as!(
+        PowerMetrics,
+        "SELECT * FROM power_metrics WHERE run_id = ? AND timestamp BETWEEN ? AND ? ORDER BY timestamp",
+        run_id,
+        begin,
+        end
+    )
+    .fetch_all(&pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to fetch power metrics from database: {:?}", e);
+        ServerError::DatabaseError(e)
+    })?;
+
+    tracing::info!("Successfully fetched {} power metrics", power_metrics.len());
+    Ok(Json(power_metrics))
+}
+
+#[instrument(name = "Persist power metrics")]
+pub async fn power_metrics_persist(
+    State(pool): State<SqlitePool>,
+    Json(payload): Json<PowerMetrics>,
+) -> anyhow::Result<String, ServerError> {
+    tracing::debug!("Received payload: {:?}", payload);
+
+    sqlx::query!(
+        "INSERT INTO power_metrics (run_id, source, power, timestamp) VALUES (?, ?, ?, ?)",
+        payload.run_id,
+        payload.source,
+        payload.power,
+        payload.timestamp
+    )
+    .execute(&pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to persist power metrics: {:?}", e);
+        ServerError::DatabaseError(e)
+    })?;
+
+    tracing::info!("Power metrics persisted successfully");
+    Ok("Power metrics persisted".to_string())
+}
+
+// Below routes must confirm to these routes found in src/data_access/baseline.rs
+#[instrument(name = "Fetch baseline")]
+pub async fn baseline_fetch(
+    Path(id): Path<String>,
+    State(pool): State<SqlitePool>,
+) -> anyhow::Result<Json<Option<Baseline>>, ServerError> {
+    let baseline = sqlx::query_as!(Baseline, "SELECT * FROM baseline WHERE id = ?", id)
+        .fetch_optional(&pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to fetch baseline from database: {:?}", e);
+            ServerError::DatabaseError(e)
+        })?;
+
+    Ok(Json(baseline))
+}
+
+#[instrument(name = "Fetch latest baseline")]
+pub async fn baseline_fetch_latest(
+    State(pool): State<SqlitePool>,
+) -> anyhow::Result<Json<Option<Baseline>>, ServerError> {
+    let baseline = sqlx::query_as!(
+        Baseline,
+        "SELECT * FROM baseline ORDER BY start_time DESC LIMIT 1"
+    )
+    .fetch_optional(&pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to fetch latest baseline from database: {:?}", e);
+        ServerError::DatabaseError(e)
+    })?;
+
+    Ok(Json(baseline))
+}
+
+#[instrument(name = "Persist baseline")]
+pub async fn baseline_persist(
+    State(pool): State<SqlitePool>,
+    Json(payload): Json<Baseline>,
+) -> anyhow::Result<String, ServerError> {
+    tracing::debug!("Received payload: {:?}", payload);
+
+    sqlx::query!(
+        "INSERT INTO baseline (id, start_time, stop_time, cpu_usage, core_count) VALUES (?, ?, ?, ?, ?)",
+        payload.id,
+        payload.start_time,
+        payload.stop_time,
+        payload.cpu_usage,
+        payload.core_count
+    )
+    .execute(&pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to persist baseline: {:?}", e);
+        ServerError::DatabaseError(e)
+    })?;
+
+    tracing::info!("Baseline persisted successfully");
+    Ok("Baseline persisted".to_string())
+}
+
+// Below routes must confirm to these routes found in src/data_access/run.rs
+#[instrument(name = "Fetch run")]
+pub async fn run_fetch(
+    Path(id): Path<String>,
+    State(pool): State<SqlitePool>,
+) -> anyhow::Result<Json<Option<Run>>, ServerError> {
+    let run = sqlx::query_as!(Run, "SELECT * FROM run WHERE id = ?", id)
+        .fetch_optional(&pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to fetch run from database: {:?}", e);
+            ServerError::DatabaseError(e)
+        })?;
+
+    Ok(Json(run))
+}
+
+#[instrument(name = "Persist run")]
+pub async fn run_persist(
+    State(pool): State<SqlitePool>,
+    Json(payload): Json<Run>,
+) -> anyhow::Result<String, ServerError> {
+    tracing::debug!("Received payload: {:?}", payload);
+
+    sqlx::query!(
+        "INSERT OR IGNORE INTO project (id) VALUES (?)",
+        payload.project_id
+    )
+    .execute(&pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to persist project: {:?}", e);
+        ServerError::DatabaseError(e)
+    })?;
+
+    sqlx::query!(
+        "INSERT INTO run (id, baseline_id, archived, note, project_id, schedule, iterations, \
+         observation, ambient_load, stop_time, scenarios) \
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        payload.id,
+        payload.baseline_id,
+        payload.archived,
+        payload.note,
+        payload.project_id,
+        payload.schedule,
+        payload.iterations,
+        payload.observation,
+        payload.ambient_load,
+        payload.stop_time,
+        payload.scenarios
+    )
+    .execute(&pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to persist run: {:?}", e);
+        ServerError::DatabaseError(e)
+    })?;
+
+    tracing::info!("Run persisted successfully");
+    Ok("Run persisted".to_string())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StopTimePayload {
+    stop_time: Option<i64>,
+}
+
+#[instrument(name = "Persist run stop time")]
+pub async fn run_stop_time_persist(
+    Path(id): Path<String>,
+    State(pool): State<SqlitePool>,
+    Json(payload): Json<StopTimePayload>,
+) -> anyhow::Result<String, ServerError> {
+    run::LocalDao::new(pool)
+        .set_stop_time(&id, payload.stop_time)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to persist stop time of run: {:?}", e);
+            ServerError::from(e)
+        })?;
+
+    Ok("Run stop time persisted".to_string())
+}
+
+// Below routes must confirm to these routes found in src/data_access/run_impact.rs
+#[instrument(name = "Fetch run impact")]
+pub async fn run_impact_fetch(
+    Path(run_id): Path<String>,
+    State(pool): State<SqlitePool>,
+) -> anyhow::Result<Json<Option<RunImpact>>, ServerError> {
+    let run_impact = sqlx::query_as!(
+        RunImpact,
+        "SELECT * FROM run_impact WHERE run_id = ?",
+        run_id
+    )
+    .fetch_optional(&pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to fetch run impact from database: {:?}", e);
+        ServerError::DatabaseError(e)
+    })?;
+
+    Ok(Json(run_impact))
+}
+
+#[instrument(name = "Persist run impact")]
+pub async fn run_impact_persist(
+    State(pool): State<SqlitePool>,
+    Json(payload): Json<RunImpact>,
+) -> anyhow::Result<String, ServerError> {
+    tracing::debug!("Received payload: {:?}", payload);
+
+    sqlx::query!(
+        "INSERT INTO run_impact (run_id, duration_hours, manufacture_gwp, use_gwp) VALUES (?, ?, ?, ?)",
+        payload.run_id,
+        payload.duration_hours,
+        payload.manufacture_gwp,
+        payload.use_gwp
+    )
+    .execute(&pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to persist run impact: {:?}", e);
+        ServerError::DatabaseError(e)
+    })?;
+
+    tracing::info!("Run impact persisted successfully");
+    Ok("Run impact persisted".to_string())
+}
+
+// Below routes must confirm to these routes found in src/data_access/energy_mix.rs
+#[instrument(name = "Fetch energy mix")]
+pub async fn energy_mix_fetch(
+    Path(run_id): Path<String>,
+    State(pool): State<SqlitePool>,
+) -> anyhow::Result<Json<Vec<EnergyMix>>, ServerError> {
+    let energy_mix = sqlx::query_as!(
+        EnergyMix,
+        "SELECT * FROM energy_mix WHERE run_id = ? ORDER BY fuel",
+        run_id
+    )
+    .fetch_all(&pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to fetch energy mix from database: {:?}", e);
+        ServerError::DatabaseError(e)
+    })?;
+
+    Ok(Json(energy_mix))
+}
+
+#[instrument(name = "Persist energy mix")]
+pub async fn energy_mix_persist(
+    State(pool): State<SqlitePool>,
+    Json(payload): Json<EnergyMix>,
+) -> anyhow::Result<String, ServerError> {
+    tracing::debug!("Received payload: {:?}", payload);
+
+    sqlx::query!(
+        "INSERT INTO energy_mix (run_id, fuel, percentage) VALUES (?, ?, ?)",
+        payload.run_id,
+        payload.fuel,
+        payload.percentage
+    )
+    .execute(&pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to persist energy mix: {:?}", e);
+        ServerError::DatabaseError(e)
+    })?;
+
+    tracing::info!("Energy mix persisted successfully");
+    Ok("Energy mix persisted".to_string())
+}
+
+// Below routes must confirm to these routes found in src/data_access/run_context.rs
+#[instrument(name = "Fetch run context")]
+pub async fn run_context_fetch(
+    Path(run_id): Path<String>,
+    State(pool): State<SqlitePool>,
+) -> anyhow::Result<Json<Option<RunContext>>, ServerError> {
+    let run_context = sqlx::query_as!(
+        RunContext,
+        "SELECT * FROM run_context WHERE run_id = ?",
+        run_id
+    )
+    .fetch_optional(&pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to fetch run context from database: {:?}", e);
+        ServerError::DatabaseError(e)
+    })?;
+
+    Ok(Json(run_context))
+}
+
+#[instrument(name = "Persist run context")]
+pub async fn run_context_persist(
+    State(pool): State<SqlitePool>,
+    Json(payload): Json<RunContext>,
+) -> anyhow::Result<String, ServerError> {
+    tracing::debug!("Received payload: {:?}", payload);
+
+    sqlx::query!(
+        "INSERT INTO run_context (run_id, os_version, kernel_version, total_memory, core_count, cpu_governor, container_runtime, cardamon_version, pinning) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        payload.run_id,
+        payload.os_version,
+        payload.kernel_version,
+        payload.total_memory,
+        payload.core_count,
+        payload.cpu_governor,
+        payload.container_runtime,
+        payload.cardamon_version,
+        payload.pinning
+    )
+    .execute(&pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to persist run context: {:?}", e);
+        ServerError::DatabaseError(e)
+    })?;
+
+    tracing::info!("Run context persisted successfully");
+    Ok("Run context persisted".to_string())
+}
+
+// Below routes must confirm to these routes found in src/data_access/iteration_result.rs
+#[instrument(name = "Fetch iteration results")]
+pub async fn iteration_result_fetch(
+    Path(run_id): Path<String>,
+    State(pool): State<SqlitePool>,
+) -> anyhow::Result<Json<Vec<IterationResult>>, ServerError> {
+    let iteration_results = sqlx::query_as!(
+        IterationResult,
+        "SELECT * FROM iteration_result WHERE run_id = ? ORDER BY scenario_name, iteration",
+        run_id
+    )
+    .fetch_all(&pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to fetch iteration results from database: {:?}", e);
+        ServerError::DatabaseError(e)
+    })?;
+
+    Ok(Json(iteration_results))
+}
+
+#[instrument(name = "Persist iteration result")]
+pub async fn iteration_result_persist(
+    State(pool): State<SqlitePool>,
+    Json(payload): Json<IterationResult>,
+) -> anyhow::Result<String, ServerError> {
+    tracing::debug!("Received payload: {:?}", payload);
+
+    sqlx::query!(
+        "INSERT INTO iteration_result (run_id, scenario_name, iteration, requests, p50_ms, p95_ms, p99_ms) VALUES (?, ?, ?, ?, ?, ?, ?)",
+        payload.run_id,
+        payload.scenario_name,
+        payload.iteration,
+        payload.requests,
+        payload.p50_ms,
+        payload.p95_ms,
+        payload.p99_ms
+    )
+    .execute(&pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to persist iteration result: {:?}", e);
+        ServerError::DatabaseError(e)
+    })?;
+
+    tracing::info!("Iteration result persisted successfully");
+    Ok("Iteration result persisted".to_string())
+}
+
+// Below routes must confirm to these routes found in src/data_access/process_info.rs
+#[instrument(name = "Fetch process info")]
+pub async fn process_info_fetch(
+    Path(run_id): Path<String>,
+    State(pool): State<SqlitePool>,
+) -> anyhow::Result<Json<Vec<ProcessInfo>>, ServerError> {
+    let process_infos = sqlx::query_as!(
+        ProcessInfo,
+        "SELECT * FROM process_info WHERE run_id = ? ORDER BY process_name",
+        run_id
+    )
+    .fetch_all(&pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to fetch process info from database: {:?}", e);
+        ServerError::DatabaseError(e)
+    })?;
+
+    Ok(Json(process_infos))
+}
+
+#[instrument(name = "Persist process info")]
+pub async fn process_info_persist(
+    State(pool): State<SqlitePool>,
+    Json(payload): Json<ProcessInfo>,
+) -> anyhow::Result<String, ServerError> {
+    tracing::debug!("Received payload: {:?}", payload);
+
+    sqlx::query!(
+        "INSERT OR REPLACE INTO process_info (run_id, process_name, image, cpu_limit, memory_limit, replicas) VALUES (?, ?, ?, ?, ?, ?)",
+        payload.run_id,
+        payload.process_name,
+        payload.image,
+        payload.cpu_limit,
+        payload.memory_limit,
+        payload.replicas
+    )
+    .execute(&pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to persist process info: {:?}", e);
+        ServerError::DatabaseError(e)
+    })?;
+
+    tracing::info!("Process info persisted successfully");
+    Ok("Process info persisted".to_string())
+}
+
+// Below routes must confirm to these routes found in src/data_access/run_phase.rs
+#[instrument(name = "Fetch run phases")]
+pub async fn run_phase_fetch(
+    Path(run_id): Path<String>,
+    State(pool): State<SqlitePool>,
+) -> anyhow::Result<Json<Vec<RunPhase>>, ServerError> {
+    let run_phases = sqlx::query_as!(
+        RunPhase,
+        "SELECT * FROM run_phase WHERE run_id = ? ORDER BY start_time",
+        run_id
+    )
+    .fetch_all(&pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to fetch run phases from database: {:?}", e);
+        ServerError::DatabaseError(e)
+    })?;
+
+    Ok(Json(run_phases))
+}
+
+#[instrument(name = "Persist run phase")]
+pub async fn run_phase_persist(
+    State(pool): State<SqlitePool>,
+    Json(payload): Json<RunPhase>,
+) -> anyhow::Result<String, ServerError> {
+    tracing::debug!("Received payload: {:?}", payload);
+
+    sqlx::query!(
+        "INSERT OR REPLACE INTO run_phase (run_id, phase, start_time, stop_time) VALUES (?, ?, ?, ?)",
+        payload.run_id,
+        payload.phase,
+        payload.start_time,
+        payload.stop_time
+    )
+    .execute(&pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to persist run phase: {:?}", e);
+        ServerError::DatabaseError(e)
+    })?;
+
+    tracing::info!("Run phase persisted successfully");
+    Ok("Run phase persisted".to_string())
+}
+
+// Below routes must confirm to these routes found in src/data_access/iteration_phase.rs
+#[instrument(name = "Fetch iteration phases")]
+pub async fn iteration_phase_fetch(
+    Path(run_id): Path<String>,
+    State(pool): State<SqlitePool>,
+) -> anyhow::Result<Json<Vec<IterationPhase>>, ServerError> {
+    let iteration_phases = sqlx::query_as!(
+        IterationPhase,
+        "SELECT * FROM iteration_phase WHERE run_id = ? ORDER BY scenario_name, iteration, start_time, span_id",
+        run_id
+    )
+    .fetch_all(&pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to fetch iteration phases from database: {:?}", e);
+        ServerError::DatabaseError(e)
+    })?;
+
+    Ok(Json(iteration_phases))
+}
+
+#[instrument(name = "Persist iteration phase")]
+pub async fn iteration_phase_persist(
+    State(pool): State<SqlitePool>,
+    Json(payload): Json<IterationPhase>,
+) -> anyhow::Result<String, ServerError> {
+    tracing::debug!("Received payload: {:?}", payload);
+
+    sqlx::query!(
+        "INSERT OR REPLACE INTO iteration_phase (run_id, scenario_name, iteration, phase, start_time, stop_time, trace_id, span_id) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+        payload.run_id,
+        payload.scenario_name,
+        payload.iteration,
+        payload.phase,
+        payload.start_time,
+        payload.stop_time,
+        payload.trace_id,
+        payload.span_id
+    )
+    .execute(&pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to persist iteration phase: {:?}", e);
+        ServerError::DatabaseError(e)
+    })?;
+
+    tracing::info!("Iteration phase persisted successfully");
+    Ok("Iteration phase persisted".to_string())
+}
+
+// Below routes must confirm to these routes found in src/data_access/endpoint_request.rs
+#[instrument(name = "Fetch endpoint requests")]
+pub async fn endpoint_request_fetch(
+    Path(run_id): Path<String>,
+    State(pool): State<SqlitePool>,
+) -> anyhow::Result<Json<Vec<EndpointRequest>>, ServerError> {
+    let endpoint_requests = sqlx::query_as!(
+        EndpointRequest,
+        "SELECT * FROM endpoint_request WHERE run_id = ? ORDER BY scenario_name, iteration, endpoint",
+        run_id
+    )
+    .fetch_all(&pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to fetch endpoint requests from database: {:?}", e);
+        ServerError::DatabaseError(e)
+    })?;
+
+    Ok(Json(endpoint_requests))
+}
+
+#[instrument(name = "Persist endpoint request")]
+pub async fn endpoint_request_persist(
+    State(pool): State<SqlitePool>,
+    Json(payload): Json<EndpointRequest>,
+) -> anyhow::Result<String, ServerError> {
+    tracing::debug!("Received payload: {:?}", payload);
+
+    sqlx::query!(
+        "INSERT OR REPLACE INTO endpoint_request (run_id, scenario_name, iteration, endpoint, requests) VALUES (?, ?, ?, ?, ?)",
+        payload.run_id,
+        payload.scenario_name,
+        payload.iteration,
+        payload.endpoint,
+        payload.requests
+    )
+    .execute(&pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to persist endpoint request: {:?}", e);
+        ServerError::DatabaseError(e)
+    })?;
+
+    tracing::info!("Endpoint request persisted successfully");
+    Ok("Endpoint request persisted".to_string())
+}
+
+// Paged lists for the UI, sorted with `sort_by` (power, co2, last_run or name) and `order`
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+    search_query: Option<String>,
+}
+
+/// Scopes a request to the runs of one project, the default project if none is given.
+#[derive(Debug, Deserialize)]
+pub struct ProjectQuery {
+    #[serde(default = "default_project")]
+    project: String,
+}
+
+fn default_project() -> String {
+    String::from(DEFAULT_PROJECT)
+}
+
+/// How many recent runs the trend of each scenario is fitted to.
+#[derive(Debug, Deserialize)]
+pub struct TrendQuery {
+    #[serde(default = "default_trend_runs")]
+    trend_runs: u32,
+}
+
+fn default_trend_runs() -> u32 {
+    scenario_iteration::TREND_RUNS
+}
+
+/// How the power of each iteration is combined, e.g. `median`. Every sample is averaged if
+/// none is given.
+#[derive(Debug, Deserialize)]
+pub struct AggregationQuery {
+    aggregation: Option<String>,
+}
+impl AggregationQuery {
+    fn method(&self) -> Result<Option<AggregationMethod>, ServerError> {
+        self.aggregation
+            .as_deref()
+            .map(|aggregation| aggregation.parse().map_err(CardamonError::InvalidInput))
+            .transpose()
+            .map_err(ServerError::from)
+    }
+}
+
+/// Comma separated globs of the processes to include in or leave out of modelled energy,
+/// matched against process names and ids.
+#[derive(Debug, Deserialize)]
+pub struct ProcessFilterQuery {
+    only_process: Option<String>,
+    exclude_process: Option<String>,
+}
+impl ProcessFilterQuery {
+    fn filter(&self) -> ProcessFilter {
+        let globs = |globs: &Option<String>| {
+            globs
+                .iter()
+                .flat_map(|globs| globs.split(','))
+                .map(str::trim)
+                .filter(|glob| !glob.is_empty())
+                .map(String::from)
+                .collect::<Vec<_>>()
+        };
+        ProcessFilter::new(globs(&self.only_process), globs(&self.exclude_process))
+    }
+}
+
+#[instrument(name = "Fetch projects")]
+pub async fn projects_fetch(
+    State(pool): State<SqlitePool>,
+) -> anyhow::Result<Json<Vec<String>>, ServerError> {
+    let projects = run::LocalDao::new(pool)
+        .fetch_projects()
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to fetch projects from database: {:?}", e);
+            ServerError::from(e)
+        })?;
+
+    Ok(Json(projects))
+}
+
+/// Scenarios are summarised with their modelled energy if the server has a config file.
+#[instrument(name = "Fetch scenarios", skip(state))]
+pub async fn scenarios_fetch(
+    State(state): State<AppState>,
+    Query(page): Query<PageRequest>,
+    Query(search): Query<SearchQuery>,
+    Query(project): Query<ProjectQuery>,
+    Query(trend): Query<TrendQuery>,
+    Query(aggregation): Query<AggregationQuery>,
+) -> anyhow::Result<Json<Page<ScenarioSummary>>, ServerError> {
+    let config = state
+        .config
+        .is_some()
+        .then(|| state.read_config())
+        .transpose()?;
+    let dao = scenario_iteration::LocalDao::new(state.pool.clone())
+        .for_project(&project.project)
+        .with_trend_runs(trend.trend_runs)
+        .with_aggregation(aggregation.method()?);
+    let scenarios = match search.search_query.as_deref().map(str::trim) {
+        Some(query) if !query.is_empty() => dao.fetch_by_query(query, &page).await,
+        _ => dao.fetch_scenarios(&page).await,
+    };
+    let mut scenarios = scenarios.map_err(|e| {
+        tracing::error!("Failed to fetch scenarios from database: {:?}", e);
+        ServerError::from(e)
+    })?;
+
+    if let Some(config) = config {
+        let data_access_service =
+            LocalDataAccessService::new(state.pool).for_project(&project.project);
+        for summary in scenarios.items.iter_mut() {
+            let observation_dataset = data_access_service
+                .fetch_observation_dataset(vec![&summary.scenario_name], trend.trend_runs.max(1))
+                .await?;
+
+            // energy per iteration of each run, oldest first
+            let mut energy_by_run = vec![];
+            for scenario_dataset in observation_dataset.by_scenario().iter() {
+                let mut run_datasets = scenario_dataset.by_run();
+                run_datasets.sort_by_key(|run_dataset| run_dataset.start_time());
+                for run_dataset in run_datasets.iter() {
+                    let iterations = run_dataset.by_iterations();
+                    let energy_wh = model::sut_energy_wh(&config, iterations);
+                    energy_by_run.push(energy_wh / iterations.len().max(1) as f64);
+                }
+            }
+            summary.energy_wh = energy_by_run.last().copied();
+            summary.energy_trend = Trend::new(&energy_by_run);
+        }
+    }
+
+    Ok(Json(scenarios))
+}
+
+/// Runs are summarised with their modelled energy if the server has a config file, which is
+/// needed to filter processes.
+#[instrument(name = "Fetch runs", skip(state))]
+pub async fn runs_fetch(
+    State(state): State<AppState>,
+    Query(page): Query<PageRequest>,
+    Query(project): Query<ProjectQuery>,
+    Query(aggregation): Query<AggregationQuery>,
+    Query(filter): Query<ProcessFilterQuery>,
+) -> anyhow::Result<Json<Page<RunSummary>>, ServerError> {
+    let filter = filter.filter();
+    let config = (state.config.is_some() || !filter.is_empty())
+        .then(|| state.read_config())
+        .transpose()?;
+
+    let mut runs = run::LocalDao::new(state.pool.clone())
+        .for_project(&project.project)
+        .with_aggregation(aggregation.method()?)
+        .fetch_summaries(&page)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to fetch runs from database: {:?}", e);
+            ServerError::from(e)
+        })?;
+
+    if let Some(config) = config {
+        let data_access_service =
+            LocalDataAccessService::new(state.pool).for_project(&project.project);
+        for summary in runs.items.iter_mut() {
+            let observation_dataset = data_access_service
+                .fetch_run_dataset(&summary.run_id)
+                .await?
+                .filter_processes(&filter);
+            let iterations = observation_dataset.data().iter().collect::<Vec<_>>();
+            summary.energy_wh = Some(model::sut_energy_wh(&config, &iterations));
+        }
+    }
+
+    Ok(Json(runs))
+}
+
+#[instrument(name = "Delete run")]
+pub async fn run_delete(
+    Path(id): Path<String>,
+    State(pool): State<SqlitePool>,
+) -> anyhow::Result<String, ServerError> {
+    run::LocalDao::new(pool).delete(&id).await.map_err(|e| {
+        tracing::error!("Failed to delete run from database: {:?}", e);
+        ServerError::from(e)
+    })?;
+
+    tracing::info!("Run {id} deleted");
+    Ok("Run deleted".to_string())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ArchivedPayload {
+    archived: bool,
+}
+
+#[instrument(name = "Archive run")]
+pub async fn run_archive(
+    Path(id): Path<String>,
+    State(pool): State<SqlitePool>,
+    Json(payload): Json<ArchivedPayload>,
+) -> anyhow::Result<String, ServerError> {
+    run::LocalDao::new(pool)
+        .set_archived(&id, payload.archived)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to archive run in database: {:?}", e);
+            ServerError::from(e)
+        })?;
+
+    tracing::info!("Run {id} archived: {}", payload.archived);
+    Ok("Run updated".to_string())
+}
+
+/// `{"note": null}` removes the note.
+#[derive(Debug, Deserialize)]
+pub struct RunPatch {
+    note: Option<String>,
+}
+
+#[instrument(name = "Annotate run")]
+pub async fn run_patch(
+    Path(id): Path<String>,
+    State(pool): State<SqlitePool>,
+    Json(payload): Json<RunPatch>,
+) -> anyhow::Result<String, ServerError> {
+    run::LocalDao::new(pool)
+        .set_note(&id, payload.note.as_deref())
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to annotate run in database: {:?}", e);
+            ServerError::from(e)
+        })?;
+
+    tracing::info!("Run {id} annotated");
+    Ok("Run updated".to_string())
+}
+
+#[instrument(name = "Fetch reference run")]
+pub async fn reference_fetch(
+    State(pool): State<SqlitePool>,
+    Query(project): Query<ProjectQuery>,
+) -> anyhow::Result<Json<Option<ReferenceRun>>, ServerError> {
+    let reference = run::LocalDao::new(pool)
+        .for_project(&project.project)
+        .fetch_reference()
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to fetch reference run from database: {:?}", e);
+            ServerError::from(e)
+        })?;
+
+    Ok(Json(reference))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReferencePayload {
+    run_id: String,
+    name: String,
+}
+
+#[instrument(name = "Set reference run")]
+pub async fn reference_set(
+    State(pool): State<SqlitePool>,
+    Query(project): Query<ProjectQuery>,
+    Json(payload): Json<ReferencePayload>,
+) -> anyhow::Result<String, ServerError> {
+    run::LocalDao::new(pool)
+        .for_project(&project.project)
+        .set_reference(&payload.run_id, &payload.name)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to set reference run in database: {:?}", e);
+            ServerError::from(e)
+        })?;
+
+    tracing::info!(
+        "Run {} is the reference of project {}",
+        payload.run_id,
+        project.project
+    );
+    Ok("Reference run set".to_string())
+}
+
+#[instrument(name = "Clear reference run")]
+pub async fn reference_clear(
+    State(pool): State<SqlitePool>,
+    Query(project): Query<ProjectQuery>,
+) -> anyhow::Result<String, ServerError> {
+    run::LocalDao::new(pool)
+        .for_project(&project.project)
+        .clear_reference()
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to clear reference run in database: {:?}", e);
+            ServerError::from(e)
+        })?;
+
+    Ok("Reference run cleared".to_string())
+}
+
+// Output of processes run by cardamon on the same machine as the server
+#[derive(Debug, Deserialize)]
+pub struct LogsQuery {
+    #[serde(default)]
+    stderr: bool,
+}
+
+#[instrument(name = "Fetch process logs")]
+pub async fn logs_fetch(
+    Path((run_id, process)): Path<(String, String)>,
+    Query(query): Query<LogsQuery>,
+) -> anyhow::Result<String, ServerError> {
+    let stream = if query.stderr {
+        Stream::Stderr
+    } else {
+        Stream::Stdout
+    };
+
+    logs::read(
+        std::path::Path::new(logs::LOG_DIR),
+        &run_id,
+        &process,
+        stream,
+    )
+    .map_err(|e| {
+        tracing::warn!("Failed to read logs: {:?}", e);
+        ServerError::from(e)
+    })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BadgeQuery {
+    #[serde(default)]
+    metric: Metric,
+}
+
+/// An SVG shield for the scenario's latest run, energy is modelled with the server's config file.
+/// It's never cached so READMEs show the latest run.
+#[instrument(name = "Fetch badge", skip(state))]
+pub async fn badge_fetch(
+    Path(scenario): Path<String>,
+    State(state): State<AppState>,
+    Query(badge): Query<BadgeQuery>,
+    Query(project): Query<ProjectQuery>,
+) -> anyhow::Result<impl IntoResponse, ServerError> {
+    let config = state.read_config()?;
+    let data_access_service = LocalDataAccessService::new(state.pool).for_project(&project.project);
+    let badge = badge::scenario_badge(&scenario, badge.metric, &config, &data_access_service)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to build badge: {:?}", e);
+            ServerError::from(e)
+        })?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "image/svg+xml"),
+            (header::CACHE_CONTROL, "no-cache, max-age=0"),
+        ],
+        badge.to_svg(),
+    ))
+}
+
+/// What the server is running, for the UI and agents to check they're compatible with it. The
+/// database's location and credentials are never included.
+#[derive(Debug, serde::Serialize)]
+pub struct ServerInfo {
+    /// The kind of database results are stored in.
+    pub backend: &'static str,
+    /// The latest migration applied to the database.
+    pub schema_version: Option<i64>,
+    /// The version of cardamon the server was built from.
+    pub version: &'static str,
+    /// Optional parts of the server which are turned on, e.g. "grpc".
+    pub features: Vec<&'static str>,
+}
+
+#[instrument(name = "Fetch server info")]
+pub async fn server_info(
+    State(pool): State<SqlitePool>,
+) -> anyhow::Result<Json<ServerInfo>, ServerError> {
+    let schema_version = sqlx::query_scalar!(
+        "SELECT MAX(version) AS \"version: i64\" FROM _sqlx_migrations WHERE success"
+    )
+    .fetch_one(&pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to fetch schema version from database: {:?}", e);
+        ServerError::DatabaseError(e)
+    })?;
+
+    let mut features = vec!["ui"];
+    if std::env::var("GRPC_PORT").is_ok() {
+        features.push("grpc");
+    }
+
+    Ok(Json(ServerInfo {
+        backend: "sqlite",
+        schema_version,
+        version: env!("CARGO_PKG_VERSION"),
+        features,
+    }))
+}
+
+/// Lets web pages served from the given origins call the api from a browser, e.g. a dashboard
+/// hosted elsewhere. Without any origins browsers only allow pages served by the server itself.
+pub fn cors(allowed_origins: &[String]) -> anyhow::Result<Option<CorsLayer>> {
+    if allowed_origins.is_empty() {
+        return Ok(None);
+    }
+
+    let origins = if allowed_origins.iter().any(|origin| origin == "*") {
+        AllowOrigin::any()
+    } else {
+        let origins = allowed_origins
+            .iter()
+            .map(|origin| {
+                HeaderValue::from_str(origin.trim_end_matches('/'))
+                    .map_err(|_| anyhow::anyhow!("{origin} is not a valid origin"))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        AllowOrigin::list(origins)
+    };
+
+    Ok(Some(
+        CorsLayer::new()
+            .allow_origin(origins)
+            .allow_methods(Any)
+            .allow_headers(Any),
+    ))
+}
+
+/// Loads the certificate and key to serve HTTPS with, the server uses plain HTTP if neither is
+/// given.
+pub async fn rustls_config(
+    cert: Option<&std::path::Path>,
+    key: Option<&std::path::Path>,
+) -> anyhow::Result<Option<RustlsConfig>> {
+    match (cert, key) {
+        (Some(cert), Some(key)) => {
+            // ring is the only crypto provider built in, so it's the default for the process
+            let _ = rustls::crypto::ring::default_provider().install_default();
+            let config = RustlsConfig::from_pem_file(cert, key)
+                .await
+                .with_context(|| {
+                    format!(
+                        "Unable to load TLS certificate {} and key {}",
+                        cert.display(),
+                        key.display()
+                    )
+                })?;
+            Ok(Some(config))
+        }
+        (None, None) => Ok(None),
+        _ => Err(anyhow::anyhow!(
+            "Both a TLS certificate and key are needed to serve HTTPS"
+        )),
+    }
+}
+
+/// The TLS config of the gRPC api, from the same certificate and key as the REST api so agent
+/// metrics are encrypted too.
+pub fn grpc_tls_config(
+    cert: Option<&std::path::Path>,
+    key: Option<&std::path::Path>,
+) -> anyhow::Result<Option<tonic::transport::ServerTlsConfig>> {
+    match (cert, key) {
+        (Some(cert), Some(key)) => {
+            let cert_pem = std::fs::read(cert)
+                .with_context(|| format!("Unable to read TLS certificate {}", cert.display()))?;
+            let key_pem = std::fs::read(key)
+                .with_context(|| format!("Unable to read TLS key {}", key.display()))?;
+            let identity = tonic::transport::Identity::from_pem(cert_pem, key_pem);
+            Ok(Some(
+                tonic::transport::ServerTlsConfig::new().identity(identity),
+            ))
+        }
+        (None, None) => Ok(None),
+        _ => Err(anyhow::anyhow!(
+            "Both a TLS certificate and key are needed to serve HTTPS"
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        body::Body,
+        http::{header, Request},
+        routing::get,
+        Router,
+    };
+    use tower::ServiceExt;
+
+    async fn allowed_origin(app: Router, origin: &str) -> anyhow::Result<Option<String>> {
+        let response = app
+            .oneshot(
+                Request::get("/")
+                    .header(header::ORIGIN, origin)
+                    .body(Body::empty())?,
+            )
+            .await?;
+        Ok(response
+            .headers()
+            .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .and_then(|value| value.to_str().ok())
+            .map(String::from))
+    }
+
+    #[tokio::test]
+    async fn https_needs_a_certificate_and_key() -> anyhow::Result<()> {
+        assert!(rustls_config(None, None).await?.is_none());
+
+        let cert = std::path::Path::new("cert.pem");
+        assert!(rustls_config(Some(cert), None).await.is_err());
+        assert!(rustls_config(None, Some(cert)).await.is_err());
+        assert!(
+            rustls_config(Some(cert), Some(std::path::Path::new("missing.pem")))
+                .await
+                .is_err()
+        );
+
+        // the gRPC api is served with the same certificate and key
+        assert!(grpc_tls_config(None, None)?.is_none());
+        assert!(grpc_tls_config(Some(cert), None).is_err());
+        assert!(grpc_tls_config(Some(cert), Some(std::path::Path::new("missing.pem"))).is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn only_allowed_origins_can_call_the_api() -> anyhow::Result<()> {
+        let app = |origins: &[&str]| -> anyhow::Result<Router> {
+            let origins = origins.iter().map(|o| o.to_string()).collect::<Vec<_>>();
+            let app = Router::new().route("/", get(|| async { "ok" }));
+            Ok(match cors(&origins)? {
+                Some(cors) => app.layer(cors),
+                None => app,
+            })
+        };
+
+        let dashboard = "https://energy.example.com";
+        assert_eq!(allowed_origin(app(&[])?, dashboard).await?, None);
+        assert_eq!(
+            allowed_origin(app(&["https://energy.example.com/"])?, dashboard).await?,
+            Some(String::from(dashboard))
+        );
+        assert_eq!(
+            allowed_origin(app(&[dashboard])?, "https://evil.example.com").await?,
+            None
+        );
+        assert_eq!(
+            allowed_origin(app(&["*"])?, "https://evil.example.com").await?,
+            Some(String::from("*"))
+        );
+        assert!(cors(&[String::from("bad\norigin")]).is_err());
+        Ok(())
+    }
+
+    /// Modelled energy of run 1 with the given process filter.
+    async fn energy_wh(
+        state: AppState,
+        only_process: Option<&str>,
+        exclude_process: Option<&str>,
+    ) -> anyhow::Result<Option<f64>> {
+        let Json(page) = runs_fetch(
+            State(state),
+            Query(PageRequest::default()),
+            Query(ProjectQuery {
+                project: default_project(),
+            }),
+            Query(AggregationQuery { aggregation: None }),
+            Query(ProcessFilterQuery {
+                only_process: only_process.map(String::from),
+                exclude_process: exclude_process.map(String::from),
+            }),
+        )
+        .await
+        .map_err(|_| anyhow::anyhow!("Runs should be fetched"))?;
+        Ok(page
+            .items
+            .into_iter()
+            .find(|run| run.run_id == "1")
+            .and_then(|run| run.energy_wh))
+    }
+
+    #[sqlx::test(
+        migrations = "./migrations",
+        fixtures("../fixtures/scenario_iterations.sql", "../fixtures/cpu_metrics.sql")
+    )]
+    async fn processes_can_be_left_out_of_run_energy(pool: SqlitePool) -> anyhow::Result<()> {
+        // energy is only modelled with a config file
+        let state = AppState::new(pool.clone());
+        assert_eq!(energy_wh(state.clone(), None, None).await?, None);
+        assert!(energy_wh(state, Some("yarn"), None).await.is_err());
+
+        let mut state = AppState::new(pool.clone());
+        state.config = Some(PathBuf::from("./fixtures/cardamon.hardware.toml"));
+        let all = energy_wh(state.clone(), None, None)
+            .await?
+            .unwrap_or_default();
+        let yarn = energy_wh(state.clone(), Some("yarn"), None)
+            .await?
+            .unwrap_or_default();
+        let without_docker = energy_wh(state, None, Some("dock*"))
+            .await?
+            .unwrap_or_default();
+        assert!(yarn > 0.0 && yarn < all);
+        assert_eq!(yarn, without_docker);
+
+        pool.close().await;
+        Ok(())
+    }
+
+    /// Trends of the CPU usage and modelled energy of each scenario.
+    async fn scenario_trends(
+        state: AppState,
+    ) -> anyhow::Result<Vec<(Option<Trend>, Option<Trend>)>> {
+        let Json(page) = scenarios_fetch(
+            State(state),
+            Query(PageRequest::default()),
+            Query(SearchQuery { search_query: None }),
+            Query(ProjectQuery {
+                project: default_project(),
+            }),
+            Query(TrendQuery {
+                trend_runs: scenario_iteration::TREND_RUNS,
+            }),
+            Query(AggregationQuery { aggregation: None }),
+        )
+        .await
+        .map_err(|_| anyhow::anyhow!("Scenarios should be fetched"))?;
+        Ok(page
+            .items
+            .into_iter()
+            .map(|summary| (summary.cpu_trend, summary.energy_trend))
+            .collect())
+    }
+
+    #[sqlx::test(
+        migrations = "./migrations",
+        fixtures("../fixtures/scenario_iterations.sql", "../fixtures/cpu_metrics.sql")
+    )]
+    async fn scenario_energy_trends_are_fitted_to_the_same_runs(
+        pool: SqlitePool,
+    ) -> anyhow::Result<()> {
+        // energy is only modelled with a config file
+        let trends = scenario_trends(AppState::new(pool.clone())).await?;
+        assert!(trends.iter().all(|(_, energy)| energy.is_none()));
+
+        let mut state = AppState::new(pool.clone());
+        state.config = Some(PathBuf::from("./fixtures/cardamon.hardware.toml"));
+        let trends = scenario_trends(state).await?;
+        assert!(trends.iter().any(|(cpu, _)| cpu.is_some()));
+        for (cpu, energy) in trends {
+            assert_eq!(cpu.map(|trend| trend.runs), energy.map(|trend| trend.runs));
+        }
+
+        pool.close().await;
+        Ok(())
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn server_info_gives_the_schema_version(pool: SqlitePool) -> anyhow::Result<()> {
+        let Json(info) = server_info(State(pool.clone()))
+            .await
+            .map_err(|_| anyhow::anyhow!("Server info should be available"))?;
+        assert_eq!(info.backend, "sqlite");
+        assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+        assert!(info
+            .schema_version
+            .is_some_and(|version| version >= 20240725090000));
+
+        let json = serde_json::to_string(&info)?;
+        assert!(!json.contains("cardamon.db"));
+
+        pool.close().await;
+        Ok(())
+    }
+}
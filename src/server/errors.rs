@@ -1,10 +1,16 @@
 use axum::{http::StatusCode, response::IntoResponse, Json};
+use cardamon::error::CardamonError;
 use serde_json::json;
 use std::fmt;
 
 #[derive(Debug)]
 pub enum ServerError {
     DatabaseError(sqlx::Error),
+    Cardamon(CardamonError),
+    /// The request didn't carry a valid api token.
+    Unauthorized,
+    Forbidden(String),
+    Conflict(String),
     #[allow(dead_code)]
     OtherError,
 }
@@ -13,10 +19,31 @@ impl ServerError {
     pub fn status_code(&self) -> StatusCode {
         match self {
             ServerError::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ServerError::Cardamon(e) => match e {
+                CardamonError::NotFound(_) => StatusCode::NOT_FOUND,
+                CardamonError::InvalidInput(_) => StatusCode::BAD_REQUEST,
+                CardamonError::Remote { .. } => StatusCode::BAD_GATEWAY,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            },
+            ServerError::Unauthorized => StatusCode::UNAUTHORIZED,
+            ServerError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ServerError::Conflict(_) => StatusCode::CONFLICT,
             ServerError::OtherError => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 
+    /// Machine readable code returned alongside the message so clients don't need to parse it.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ServerError::DatabaseError(_) => "database",
+            ServerError::Cardamon(e) => e.code(),
+            ServerError::Unauthorized => "unauthorized",
+            ServerError::Forbidden(_) => "forbidden",
+            ServerError::Conflict(_) => "conflict",
+            ServerError::OtherError => "internal",
+        }
+    }
+
     pub fn error_message(&self) -> String {
         match self {
             //ServerError::DatabaseError(e) => format!("Database error: {}", e),
@@ -24,6 +51,9 @@ impl ServerError {
                 sqlx::Error::RowNotFound => format!("Row not found: {}", e),
                 _ => format!("Database error: {}", e),
             },
+            ServerError::Cardamon(e) => e.to_string(),
+            ServerError::Unauthorized => "A valid api token is needed".to_string(),
+            ServerError::Forbidden(message) | ServerError::Conflict(message) => message.clone(),
             ServerError::OtherError => "Un-used error".to_string(),
         }
     }
@@ -35,11 +65,17 @@ impl fmt::Display for ServerError {
     }
 }
 
+impl From<CardamonError> for ServerError {
+    fn from(err: CardamonError) -> Self {
+        ServerError::Cardamon(err)
+    }
+}
+
 impl IntoResponse for ServerError {
     fn into_response(self) -> axum::response::Response {
         (
             self.status_code(),
-            Json(json!({"error": self.error_message()})),
+            Json(json!({"error": self.error_message(), "code": self.code()})),
         )
             .into_response()
     }
@@ -0,0 +1,353 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! A datasource for Grafana's JSON (simpod-json-datasource) and Infinity plugins, so scenarios
+//! can be graphed alongside other dashboards without exporting them first. Point the datasource
+//! at `/api/grafana`, or `/api/projects/<project>/grafana` for a project other than the default.
+//!
+//! Targets are a scenario name followed by a series, e.g. `basket_10 power`. Each iteration of
+//! the scenario within the dashboard's time range is a datapoint at its start time. Energy is
+//! modelled with the server's config file, for the system under test only, as `stats` does.
+
+use super::{errors::ServerError, AppState};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use cardamon::{
+    config::Config,
+    data_access::{
+        pagination::{PageRequest, MAX_PER_PAGE},
+        scenario_iteration::ScenarioIteration,
+        DataAccessService, LocalDataAccessService, DEFAULT_PROJECT,
+    },
+    dataset::IterationWithMetrics,
+    error::CardamonError,
+    model,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt, str::FromStr};
+use tracing::instrument;
+
+/// How many of each scenario's most recent runs are graphed, older runs aren't shown even if
+/// they're within the dashboard's time range.
+const RECENT_RUNS: u32 = 100;
+
+/// A series graphed for each scenario.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Series {
+    /// Average power of the iteration (W).
+    Power,
+    /// Energy used by the iteration (Wh).
+    Energy,
+    /// Emissions of the iteration at the configured grid intensity (gCO2e).
+    Co2,
+}
+impl Series {
+    const ALL: [Series; 3] = [Series::Power, Series::Energy, Series::Co2];
+
+    /// # Arguments
+    ///
+    /// * `energy_wh` - energy used by the iteration
+    /// * `duration_ms` - how long the iteration took
+    /// * `grid_intensity` - gCO2/kWh
+    fn value(&self, energy_wh: f64, duration_ms: i64, grid_intensity: f64) -> f64 {
+        match self {
+            Series::Power if duration_ms > 0 => energy_wh * 3600.0 * 1000.0 / duration_ms as f64,
+            Series::Power => 0.0,
+            Series::Energy => energy_wh,
+            Series::Co2 => energy_wh / 1000.0 * grid_intensity,
+        }
+    }
+}
+impl FromStr for Series {
+    type Err = CardamonError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "power" => Ok(Series::Power),
+            "energy" => Ok(Series::Energy),
+            "co2" => Ok(Series::Co2),
+            _ => Err(CardamonError::InvalidInput(format!(
+                "Unknown series {s}, expected power, energy or co2"
+            ))),
+        }
+    }
+}
+impl fmt::Display for Series {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Series::Power => write!(f, "power"),
+            Series::Energy => write!(f, "energy"),
+            Series::Co2 => write!(f, "co2"),
+        }
+    }
+}
+
+/// Splits a target into its scenario and series. Scenario names may contain spaces, the series
+/// is always the last word.
+fn parse_target(target: &str) -> Result<(&str, Series), CardamonError> {
+    let (scenario_name, series) = target.trim().rsplit_once(' ').ok_or_else(|| {
+        CardamonError::InvalidInput(format!(
+            "Target {target} should be a scenario followed by power, energy or co2"
+        ))
+    })?;
+    Ok((scenario_name.trim(), series.parse()?))
+}
+
+/// The dashboard's time range.
+#[derive(Debug, Deserialize)]
+pub struct Range {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+}
+impl Range {
+    fn contains(&self, time: i64) -> bool {
+        self.from.timestamp_millis() <= time && time <= self.to.timestamp_millis()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Target {
+    pub target: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct QueryRequest {
+    pub range: Range,
+    pub targets: Vec<Target>,
+}
+
+/// A series of `[value, unix ms]` datapoints.
+#[derive(Debug, Serialize)]
+pub struct TimeSeries {
+    pub target: String,
+    pub datapoints: Vec<(f64, i64)>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AnnotationRequest {
+    pub range: Range,
+    /// The annotation being queried, returned with each annotation. Its `query` is the scenario
+    /// to mark runs of, all scenarios if it's empty.
+    pub annotation: serde_json::Value,
+}
+
+/// A run, shown as a region over the time it took.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Annotation {
+    pub annotation: serde_json::Value,
+    pub time: i64,
+    pub time_end: i64,
+    pub is_region: bool,
+    pub title: String,
+    pub text: String,
+    pub tags: Vec<String>,
+}
+
+/// Groups the iterations by run, in the order runs started.
+fn run_annotations(
+    iterations: &[&ScenarioIteration],
+    annotation: &serde_json::Value,
+) -> Vec<Annotation> {
+    let mut runs = BTreeMap::<&str, (i64, i64, Vec<&str>)>::new();
+    for it in iterations {
+        let (start_time, stop_time, scenarios) =
+            runs.entry(it.run_id.as_str())
+                .or_insert((it.start_time, it.stop_time, vec![]));
+        *start_time = (*start_time).min(it.start_time);
+        *stop_time = (*stop_time).max(it.stop_time);
+        if !scenarios.contains(&it.scenario_name.as_str()) {
+            scenarios.push(&it.scenario_name);
+        }
+    }
+
+    let mut annotations = runs
+        .into_iter()
+        .map(|(run_id, (start_time, stop_time, scenarios))| Annotation {
+            annotation: annotation.clone(),
+            time: start_time,
+            time_end: stop_time,
+            is_region: true,
+            title: format!("Run {run_id}"),
+            text: scenarios.join(", "),
+            tags: scenarios.into_iter().map(String::from).collect(),
+        })
+        .collect::<Vec<_>>();
+    annotations.sort_by_key(|annotation| annotation.time);
+    annotations
+}
+
+fn data_access_service(state: &AppState, project: Option<Path<String>>) -> LocalDataAccessService {
+    let project = project.map(|Path(project)| project);
+    LocalDataAccessService::new(state.pool.clone())
+        .for_project(project.as_deref().unwrap_or(DEFAULT_PROJECT))
+}
+
+async fn scenario_names(
+    data_access_service: &LocalDataAccessService,
+) -> Result<Vec<String>, ServerError> {
+    Ok(data_access_service
+        .scenario_iteration_dao()
+        .fetch_scenarios(&PageRequest::new(1, MAX_PER_PAGE))
+        .await?
+        .items
+        .into_iter()
+        .map(|scenario| scenario.scenario_name)
+        .collect())
+}
+
+/// Grafana checks the datasource with this when it's saved.
+pub async fn grafana_test() -> StatusCode {
+    StatusCode::OK
+}
+
+/// Every target which can be graphed, for Grafana's query editor.
+#[instrument(name = "Search Grafana targets", skip(state))]
+pub async fn grafana_search(
+    project: Option<Path<String>>,
+    State(state): State<AppState>,
+) -> anyhow::Result<Json<Vec<String>>, ServerError> {
+    let data_access_service = data_access_service(&state, project);
+    let targets = scenario_names(&data_access_service)
+        .await?
+        .iter()
+        .flat_map(|scenario_name| {
+            Series::ALL
+                .iter()
+                .map(move |series| format!("{scenario_name} {series}"))
+        })
+        .collect();
+
+    Ok(Json(targets))
+}
+
+#[instrument(name = "Query Grafana series", skip(state))]
+pub async fn grafana_query(
+    project: Option<Path<String>>,
+    State(state): State<AppState>,
+    Json(request): Json<QueryRequest>,
+) -> anyhow::Result<Json<Vec<TimeSeries>>, ServerError> {
+    let config = state.read_config()?;
+    let data_access_service = data_access_service(&state, project);
+
+    let mut series = vec![];
+    for target in request.targets.iter() {
+        let (scenario_name, kind) = parse_target(&target.target)?;
+        let observation_dataset = data_access_service
+            .fetch_observation_dataset(vec![scenario_name], RECENT_RUNS)
+            .await?;
+        let datapoints = datapoints(
+            &config,
+            observation_dataset.data().iter().collect(),
+            kind,
+            &request.range,
+        );
+        series.push(TimeSeries {
+            target: target.target.clone(),
+            datapoints,
+        });
+    }
+
+    Ok(Json(series))
+}
+
+/// A datapoint for each iteration within the range, oldest first.
+fn datapoints(
+    config: &Config,
+    mut iterations: Vec<&IterationWithMetrics>,
+    series: Series,
+    range: &Range,
+) -> Vec<(f64, i64)> {
+    iterations.retain(|it| range.contains(it.scenario_iteration().start_time));
+    iterations.sort_by_key(|it| it.scenario_iteration().start_time);
+    iterations
+        .into_iter()
+        .map(|it| {
+            let scenario_iteration = it.scenario_iteration();
+            let energy_wh = model::sut_energy_wh(config, &[it]);
+            let duration_ms = scenario_iteration.stop_time - scenario_iteration.start_time;
+            (
+                series.value(energy_wh, duration_ms, config.grid_intensity()),
+                scenario_iteration.start_time,
+            )
+        })
+        .collect()
+}
+
+/// Marks the runs of a scenario, or of every scenario, within the range.
+#[instrument(name = "Query Grafana annotations", skip(state))]
+pub async fn grafana_annotations(
+    project: Option<Path<String>>,
+    State(state): State<AppState>,
+    Json(request): Json<AnnotationRequest>,
+) -> anyhow::Result<Json<Vec<Annotation>>, ServerError> {
+    let data_access_service = data_access_service(&state, project);
+    let scenario_names = match request.annotation["query"].as_str().map(str::trim) {
+        Some(query) if !query.is_empty() => vec![query.to_string()],
+        _ => scenario_names(&data_access_service).await?,
+    };
+    let observation_dataset = data_access_service
+        .fetch_observation_dataset(
+            scenario_names.iter().map(String::as_str).collect(),
+            RECENT_RUNS,
+        )
+        .await?;
+
+    let iterations = observation_dataset
+        .data()
+        .iter()
+        .map(|it| it.scenario_iteration())
+        .filter(|it| request.range.contains(it.start_time))
+        .collect::<Vec<_>>();
+    Ok(Json(run_annotations(&iterations, &request.annotation)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn targets_are_a_scenario_and_series() {
+        assert_eq!(
+            parse_target("basket_10 power").unwrap(),
+            ("basket_10", Series::Power)
+        );
+        assert_eq!(
+            parse_target("add to basket CO2").unwrap(),
+            ("add to basket", Series::Co2)
+        );
+        assert!(parse_target("basket_10").is_err());
+        assert!(parse_target("basket_10 watts").is_err());
+
+        // 1 Wh over 2 seconds
+        assert_eq!(Series::Power.value(1.0, 2000, 500.0), 1800.0);
+        assert_eq!(Series::Energy.value(1.0, 2000, 500.0), 1.0);
+        assert_eq!(Series::Co2.value(1.0, 2000, 500.0), 0.5);
+    }
+
+    #[test]
+    fn runs_are_annotated_as_regions() {
+        let iterations = [
+            ScenarioIteration::new("b", "basket_10", 1, 5000, 6000),
+            ScenarioIteration::new("a", "basket_10", 1, 1000, 2000),
+            ScenarioIteration::new("a", "checkout", 1, 2000, 3500),
+            ScenarioIteration::new("a", "basket_10", 2, 3500, 4000),
+        ];
+        let annotation = serde_json::json!({ "name": "runs", "query": "" });
+        let annotations = run_annotations(&iterations.iter().collect::<Vec<_>>(), &annotation);
+
+        assert_eq!(annotations.len(), 2);
+        assert_eq!(annotations[0].title, "Run a");
+        assert_eq!((annotations[0].time, annotations[0].time_end), (1000, 4000));
+        assert_eq!(annotations[0].tags, vec!["basket_10", "checkout"]);
+        assert_eq!(annotations[0].annotation, annotation);
+        assert_eq!(annotations[1].title, "Run b");
+    }
+}
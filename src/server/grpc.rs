@@ -0,0 +1,310 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+pub mod proto {
+    tonic::include_proto!("cardamon.v1");
+}
+
+use cardamon::{
+    data_access::{
+        cpu_metrics::CpuMetrics, process_event::ProcessEvent,
+        scenario_iteration::ScenarioIteration, DataAccessService, LocalDataAccessService,
+    },
+    error::CardamonError,
+};
+use proto::cardamon_server::{Cardamon, CardamonServer};
+use sqlx::SqlitePool;
+use tonic::{Request, Response, Status, Streaming};
+use tracing::instrument;
+
+/// gRPC equivalent of the REST api, intended for agents and integrations pushing large volumes
+/// of metrics where JSON over HTTP is too heavy.
+pub struct CardamonService {
+    data_access_service: LocalDataAccessService,
+}
+impl CardamonService {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self {
+            data_access_service: LocalDataAccessService::new(pool),
+        }
+    }
+
+    pub fn into_server(self) -> CardamonServer<Self> {
+        CardamonServer::new(self)
+    }
+}
+
+fn database_error(err: CardamonError) -> Status {
+    match err {
+        CardamonError::NotFound(msg) => Status::not_found(msg),
+        CardamonError::InvalidInput(msg) => Status::invalid_argument(msg),
+        err => {
+            tracing::error!("Database error: {:?}", err);
+            Status::internal(format!("{}: {err}", err.code()))
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl Cardamon for CardamonService {
+    #[instrument(name = "Persist CPU metrics stream", skip_all)]
+    async fn persist_cpu_metrics(
+        &self,
+        request: Request<Streaming<proto::CpuMetrics>>,
+    ) -> Result<Response<proto::PersistReply>, Status> {
+        let mut stream = request.into_inner();
+        let mut persisted = 0;
+        while let Some(metrics) = stream.message().await? {
+            self.data_access_service
+                .cpu_metrics_dao()
+                .persist(&metrics.into())
+                .await
+                .map_err(database_error)?;
+            persisted += 1;
+        }
+
+        tracing::info!("Persisted {persisted} CPU metrics");
+        Ok(Response::new(proto::PersistReply { persisted }))
+    }
+
+    #[instrument(name = "Persist scenario iteration stream", skip_all)]
+    async fn persist_scenario_iterations(
+        &self,
+        request: Request<Streaming<proto::ScenarioIteration>>,
+    ) -> Result<Response<proto::PersistReply>, Status> {
+        let mut stream = request.into_inner();
+        let mut persisted = 0;
+        while let Some(scenario_iteration) = stream.message().await? {
+            self.data_access_service
+                .scenario_iteration_dao()
+                .persist(&scenario_iteration.into())
+                .await
+                .map_err(database_error)?;
+            persisted += 1;
+        }
+
+        tracing::info!("Persisted {persisted} scenario iterations");
+        Ok(Response::new(proto::PersistReply { persisted }))
+    }
+
+    #[instrument(name = "Persist process event stream", skip_all)]
+    async fn persist_process_events(
+        &self,
+        request: Request<Streaming<proto::ProcessEvent>>,
+    ) -> Result<Response<proto::PersistReply>, Status> {
+        let mut stream = request.into_inner();
+        let mut persisted = 0;
+        while let Some(process_event) = stream.message().await? {
+            self.data_access_service
+                .process_event_dao()
+                .persist(&process_event.into())
+                .await
+                .map_err(database_error)?;
+            persisted += 1;
+        }
+
+        tracing::info!("Persisted {persisted} process events");
+        Ok(Response::new(proto::PersistReply { persisted }))
+    }
+
+    #[instrument(name = "Fetch dataset", skip_all)]
+    async fn fetch_dataset(
+        &self,
+        request: Request<proto::DatasetRequest>,
+    ) -> Result<Response<proto::Dataset>, Status> {
+        let request = request.into_inner();
+        let scenario_names = request
+            .scenario_names
+            .iter()
+            .map(|name| name.as_str())
+            .collect::<Vec<_>>();
+
+        let dataset = self
+            .data_access_service
+            .fetch_observation_dataset(scenario_names, request.previous_runs)
+            .await
+            .map_err(database_error)?;
+
+        let iterations = dataset
+            .data()
+            .iter()
+            .map(|it| proto::IterationWithMetrics {
+                scenario_iteration: Some(it.scenario_iteration().into()),
+                cpu_metrics: it.cpu_metrics().iter().map(Into::into).collect(),
+                process_events: it.process_events().iter().map(Into::into).collect(),
+            })
+            .collect();
+
+        Ok(Response::new(proto::Dataset { iterations }))
+    }
+}
+
+// //////////////////////////////////////
+// Conversions
+
+impl From<proto::CpuMetrics> for CpuMetrics {
+    fn from(m: proto::CpuMetrics) -> Self {
+        CpuMetrics::new(
+            &m.run_id,
+            &m.process_id,
+            &m.process_name,
+            m.cpu_usage,
+            m.total_usage,
+            m.core_count,
+            m.timestamp,
+        )
+        .with_cpu_frequency(m.cpu_frequency)
+        .with_memory_usage(m.memory_usage)
+        .with_power(m.power)
+        .with_sample_interval(m.sample_interval)
+        .with_memory_limit(m.memory_limit)
+        .with_throttling(m.cpu_periods, m.throttled_periods)
+    }
+}
+impl From<&CpuMetrics> for proto::CpuMetrics {
+    fn from(m: &CpuMetrics) -> Self {
+        proto::CpuMetrics {
+            run_id: m.run_id.clone(),
+            process_id: m.process_id.clone(),
+            process_name: m.process_name.clone(),
+            cpu_usage: m.cpu_usage,
+            total_usage: m.total_usage,
+            core_count: m.core_count,
+            timestamp: m.timestamp,
+            cpu_frequency: m.cpu_frequency,
+            memory_usage: m.memory_usage,
+            power: m.power,
+            sample_interval: m.sample_interval,
+            memory_limit: m.memory_limit,
+            cpu_periods: m.cpu_periods,
+            throttled_periods: m.throttled_periods,
+        }
+    }
+}
+
+impl From<proto::ScenarioIteration> for ScenarioIteration {
+    fn from(s: proto::ScenarioIteration) -> Self {
+        let scenario_iteration = ScenarioIteration::new(
+            &s.run_id,
+            &s.scenario_name,
+            s.iteration,
+            s.start_time,
+            s.stop_time,
+        )
+        .with_cold_start(s.cold_start);
+
+        // older clients don't send a status
+        if s.status.is_empty() {
+            scenario_iteration
+        } else {
+            scenario_iteration.with_status(&s.status)
+        }
+    }
+}
+impl From<&ScenarioIteration> for proto::ScenarioIteration {
+    fn from(s: &ScenarioIteration) -> Self {
+        proto::ScenarioIteration {
+            run_id: s.run_id.clone(),
+            scenario_name: s.scenario_name.clone(),
+            iteration: s.iteration,
+            start_time: s.start_time,
+            stop_time: s.stop_time,
+            status: s.status.clone(),
+            cold_start: s.cold_start,
+        }
+    }
+}
+
+impl From<proto::ProcessEvent> for ProcessEvent {
+    fn from(e: proto::ProcessEvent) -> Self {
+        ProcessEvent::new(&e.run_id, &e.process_name, &e.event, e.timestamp)
+    }
+}
+impl From<&ProcessEvent> for proto::ProcessEvent {
+    fn from(e: &ProcessEvent) -> Self {
+        proto::ProcessEvent {
+            run_id: e.run_id.clone(),
+            process_name: e.process_name.clone(),
+            event: e.event.clone(),
+            timestamp: e.timestamp,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proto::cardamon_client::CardamonClient;
+    use tonic::transport::{server::TcpIncoming, Server};
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn metrics_streamed_over_grpc_can_be_fetched_as_a_dataset(
+        pool: SqlitePool,
+    ) -> anyhow::Result<()> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let incoming = TcpIncoming::from_listener(listener, true, None)
+            .map_err(|err| anyhow::anyhow!("{err}"))?;
+        let server = tokio::spawn(
+            Server::builder()
+                .add_service(CardamonService::new(pool.clone()).into_server())
+                .serve_with_incoming(incoming),
+        );
+
+        let mut client = CardamonClient::connect(format!("http://{addr}")).await?;
+
+        let scenario_iteration = proto::ScenarioIteration {
+            run_id: "1".to_string(),
+            scenario_name: "basket".to_string(),
+            iteration: 1,
+            start_time: 1000,
+            stop_time: 2000,
+            status: String::new(),
+            cold_start: false,
+        };
+        client
+            .persist_scenario_iterations(futures_util::stream::iter(vec![scenario_iteration]))
+            .await?;
+
+        let metrics = (0..3)
+            .map(|i| proto::CpuMetrics {
+                run_id: "1".to_string(),
+                process_id: "1337".to_string(),
+                process_name: "yarn".to_string(),
+                cpu_usage: 50.0,
+                total_usage: 0.0,
+                core_count: 4,
+                timestamp: 1000 + i * 500,
+                cpu_frequency: Some(2400),
+                memory_usage: Some(512 * 1024 * 1024),
+                power: None,
+                sample_interval: None,
+                memory_limit: None,
+                cpu_periods: None,
+                throttled_periods: None,
+            })
+            .collect::<Vec<_>>();
+        let reply = client
+            .persist_cpu_metrics(futures_util::stream::iter(metrics))
+            .await?
+            .into_inner();
+        assert_eq!(reply.persisted, 3);
+
+        let dataset = client
+            .fetch_dataset(proto::DatasetRequest {
+                scenario_names: vec!["basket".to_string()],
+                previous_runs: 1,
+            })
+            .await?
+            .into_inner();
+        assert_eq!(dataset.iterations.len(), 1);
+        assert_eq!(dataset.iterations[0].cpu_metrics.len(), 3);
+
+        server.abort();
+        pool.close().await;
+        Ok(())
+    }
+}
@@ -0,0 +1,299 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Observations started through the API. They run on the machine the server runs on, using the
+//! server's config file, and record their results in the server's database.
+
+use super::{errors::ServerError, AppState};
+use axum::{
+    extract::{Path, Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::Response,
+    Json,
+};
+use cardamon::{
+    active_runs::{self, ActiveRun},
+    config::Config,
+    data_access::{
+        run::{self, RunDao},
+        LocalDataAccessService, DEFAULT_PROJECT,
+    },
+    error::CardamonError,
+    metrics_logger,
+    orphans::{self, RunLock},
+};
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tracing::instrument;
+
+/// How a run started through the API is getting on.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum RunStatus {
+    Running,
+    Finished,
+    Cancelled,
+    Failed {
+        error: String,
+    },
+    /// Cardamon stopped before the run finished, `card resume` continues it.
+    Interrupted,
+}
+
+/// The runs started by this server. Only one runs at a time, runs sharing the machine would
+/// skew each other's measurements.
+#[derive(Clone, Debug, Default)]
+pub struct RunRegistry {
+    runs: Arc<Mutex<HashMap<String, RunStatus>>>,
+}
+impl RunRegistry {
+    /// Records the run as running, false if another run hasn't finished yet.
+    fn start(&self, run_id: &str) -> bool {
+        let Ok(mut runs) = self.runs.lock() else {
+            return false;
+        };
+        if runs.values().any(|status| *status == RunStatus::Running) {
+            return false;
+        }
+        runs.insert(run_id.to_string(), RunStatus::Running);
+        true
+    }
+
+    fn finish(&self, run_id: &str, result: anyhow::Result<()>) {
+        let status = match result {
+            Ok(()) => RunStatus::Finished,
+            Err(err) if err.downcast_ref::<active_runs::Cancelled>().is_some() => {
+                RunStatus::Cancelled
+            }
+            Err(err) => RunStatus::Failed {
+                error: format!("{err:#}"),
+            },
+        };
+        if let Ok(mut runs) = self.runs.lock() {
+            runs.insert(run_id.to_string(), status);
+        }
+    }
+
+    pub fn status(&self, run_id: &str) -> Option<RunStatus> {
+        self.runs
+            .lock()
+            .ok()
+            .and_then(|runs| runs.get(run_id).cloned())
+    }
+}
+
+/// Rejects requests without the server's API token, and every request if the server wasn't
+/// given one.
+pub async fn require_api_token(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Result<Response, ServerError> {
+    let Some(expected) = state.api_token.as_deref() else {
+        return Err(ServerError::Forbidden(String::from(
+            "Changing runs through the api is turned off, give the server an api token to turn it on",
+        )));
+    };
+
+    let token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match token {
+        Some(token) if tokens_match(token, expected) => Ok(next.run(request).await),
+        _ => Err(ServerError::Unauthorized),
+    }
+}
+
+/// Compares every byte so how long the comparison takes doesn't give away the token.
+fn tokens_match(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+#[derive(Debug, Serialize)]
+pub struct RunStarted {
+    pub run_id: String,
+}
+
+#[instrument(name = "Start observation", skip(state))]
+pub async fn observation_run(
+    Path(name): Path<String>,
+    State(state): State<AppState>,
+) -> anyhow::Result<(StatusCode, Json<RunStarted>), ServerError> {
+    let path = state.config.as_deref().ok_or_else(|| {
+        CardamonError::InvalidInput(String::from(
+            "The server was started without a config file to run observations from",
+        ))
+    })?;
+    let config = Config::from_path(path)?;
+    // check the observation exists before handing out a run id
+    config.create_execution_plan(&name)?;
+
+    let run_id = nanoid::nanoid!(5);
+    if !state.runs.start(&run_id) {
+        return Err(ServerError::Conflict(String::from(
+            "Another observation is already running",
+        )));
+    }
+
+    tracing::info!("Starting observation {name} as run {run_id}");
+    let runs = state.runs.clone();
+    let id = run_id.clone();
+    // the run holds the daos across awaits, which aren't Send, so it's driven from a thread of
+    // its own rather than spawned as a task
+    let handle = tokio::runtime::Handle::current();
+    tokio::task::spawn_blocking(move || {
+        let result = handle.block_on(run_observation(config, &name, &id, state.pool));
+        if let Err(err) = &result {
+            tracing::error!("Run {id} failed: {err:?}");
+        }
+        runs.finish(&id, result);
+    });
+
+    Ok((StatusCode::ACCEPTED, Json(RunStarted { run_id })))
+}
+
+async fn run_observation(
+    config: Config,
+    name: &str,
+    run_id: &str,
+    pool: SqlitePool,
+) -> anyhow::Result<()> {
+    if let Some(interval) = config.sample_interval() {
+        metrics_logger::set_sample_interval(interval);
+    }
+    let project = config.project.as_deref().unwrap_or(DEFAULT_PROJECT);
+    let data_access_service = LocalDataAccessService::new(pool).for_project(project);
+
+    let mut execution_plan = config.create_execution_plan(name)?;
+    execution_plan.use_run_id(run_id);
+    execution_plan.hide_progress();
+    cardamon::run(execution_plan, &data_access_service).await?;
+    Ok(())
+}
+
+/// Runs in progress in the server, i.e. observations started through the API.
+#[instrument(name = "Fetch active runs")]
+pub async fn runs_active() -> Json<Vec<ActiveRun>> {
+    Json(active_runs::active())
+}
+
+/// Stops a run in progress in the server, what was measured before it stopped is kept.
+#[instrument(name = "Cancel run")]
+pub async fn run_cancel(Path(id): Path<String>) -> anyhow::Result<StatusCode, ServerError> {
+    if !active_runs::cancel(&id) {
+        return Err(CardamonError::NotFound(format!("Run {id} isn't in progress")).into());
+    }
+
+    tracing::info!("Cancelling run {id}");
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// Runs in progress in the server are running, as are runs whose cardamon process still holds
+/// the run lock in the server's directory. Otherwise a run has finished once it's recorded when
+/// it stopped, runs without a stop time are still running elsewhere, e.g. in an agent, unless
+/// they left a stale lock behind.
+#[instrument(name = "Fetch run status", skip(state))]
+pub async fn run_status(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> anyhow::Result<Json<RunStatus>, ServerError> {
+    if let Some(status) = state.runs.status(&id) {
+        return Ok(Json(status));
+    }
+    if active_runs::active().iter().any(|run| run.run_id == id) {
+        return Ok(Json(RunStatus::Running));
+    }
+
+    let Some(run) = run::LocalDao::new(state.pool).fetch(&id).await? else {
+        return Err(CardamonError::NotFound(format!("Run {id} not found")).into());
+    };
+    // a lock which can't be read says nothing about the run
+    let lock = RunLock::read(std::path::Path::new(orphans::LOCK_FILE))
+        .ok()
+        .flatten()
+        .filter(|lock| lock.run_id == id);
+    let status = match (lock, run.stop_time) {
+        (Some(lock), _) if !lock.is_stale() => RunStatus::Running,
+        (_, Some(_)) => RunStatus::Finished,
+        (Some(_), None) => RunStatus::Interrupted,
+        (None, None) => RunStatus::Running,
+    };
+    Ok(Json(status))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_one_run_at_a_time() {
+        let runs = RunRegistry::default();
+        assert!(runs.start("first"));
+        assert!(!runs.start("second"));
+        assert_eq!(runs.status("first"), Some(RunStatus::Running));
+        assert_eq!(runs.status("second"), None);
+
+        runs.finish("first", Err(anyhow::anyhow!("scenario failed")));
+        assert_eq!(
+            runs.status("first"),
+            Some(RunStatus::Failed {
+                error: String::from("scenario failed")
+            })
+        );
+        assert!(runs.start("second"));
+        runs.finish(
+            "second",
+            Err(active_runs::Cancelled(String::from("second")).into()),
+        );
+        assert_eq!(runs.status("second"), Some(RunStatus::Cancelled));
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn runs_in_progress_are_running_until_they_stop(pool: SqlitePool) -> anyhow::Result<()> {
+        let run_id = format!("status-{}", nanoid::nanoid!(8));
+        let run_dao = run::LocalDao::new(pool.clone());
+        run_dao.persist(&run::Run::new(&run_id, None)).await?;
+        let state = AppState::new(pool.clone());
+        let status = || async {
+            run_status(Path(run_id.clone()), State(state.clone()))
+                .await
+                .map(|Json(status)| status)
+                .map_err(|_| anyhow::anyhow!("Status of run {run_id} should be fetched"))
+        };
+
+        let registration = active_runs::register(&run_id);
+        assert_eq!(status().await?, RunStatus::Running);
+        drop(registration);
+
+        // recorded when it started but still going, e.g. in an agent
+        assert_eq!(status().await?, RunStatus::Running);
+
+        run_dao.set_stop_time(&run_id, Some(1717507690000)).await?;
+        assert_eq!(status().await?, RunStatus::Finished);
+
+        pool.close().await;
+        Ok(())
+    }
+
+    #[test]
+    fn api_tokens_must_match_exactly() {
+        assert!(tokens_match("s3cret", "s3cret"));
+        assert!(!tokens_match("s3cre", "s3cret"));
+        assert!(!tokens_match("s3creT", "s3cret"));
+    }
+}
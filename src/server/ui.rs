@@ -0,0 +1,73 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Serves the web UI embedded in the binary at build time, so running the server is all that's
+//! needed for a working dashboard. The UI is built into `ui/dist` before building cardamon,
+//! builds without it still work but serve a message explaining how to include it.
+
+use axum::{
+    http::{header, StatusCode, Uri},
+    response::{IntoResponse, Response},
+};
+use rust_embed::RustEmbed;
+
+#[derive(RustEmbed)]
+#[folder = "ui/dist/"]
+#[allow_missing = true]
+struct Assets;
+
+const INDEX: &str = "index.html";
+
+/// The embedded file requested by a path. Paths without a file extension are routes handled
+/// by the UI itself so they get the index page.
+fn asset_path(path: &str) -> &str {
+    let path = path.trim_start_matches('/');
+    let is_file = path
+        .rsplit('/')
+        .next()
+        .is_some_and(|name| name.contains('.'));
+
+    if is_file {
+        path
+    } else {
+        INDEX
+    }
+}
+
+/// Fallback handler for every request not matched by the api.
+pub async fn static_handler(uri: Uri) -> Response {
+    let path = asset_path(uri.path());
+
+    match Assets::get(path) {
+        Some(file) => (
+            [(header::CONTENT_TYPE, file.metadata.mimetype().to_string())],
+            file.data,
+        )
+            .into_response(),
+
+        None if path == INDEX => (
+            StatusCode::NOT_FOUND,
+            "The web UI isn't included in this build of cardamon. Build the UI into ui/dist and \
+             rebuild cardamon to include it.",
+        )
+            .into_response(),
+
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ui_routes_are_served_the_index_page() {
+        assert_eq!(asset_path("/"), INDEX);
+        assert_eq!(asset_path("/runs/abc123"), INDEX);
+        assert_eq!(asset_path("/assets/index-4f2a.js"), "assets/index-4f2a.js");
+        assert_eq!(asset_path("/favicon.ico"), "favicon.ico");
+    }
+}
@@ -1,35 +1,216 @@
 mod server;
 
-use axum::routing::{get, post, Router};
+use anyhow::Context;
+use axum::{
+    middleware,
+    routing::{delete, get, post, put, Router},
+};
+use cardamon::{
+    config::{self, Database, JournalMode, Synchronous},
+    secrets::Secret,
+    telemetry::{self, LogFormat},
+};
+use clap::Parser;
 use dotenv::dotenv;
-use server::{fetch_within, persist_metrics, scenario_iteration_persist};
-use sqlx::{migrate::MigrateDatabase, sqlite::SqlitePool};
-use std::fs::File;
-use tracing::{info, subscriber::set_global_default, Subscriber};
-use tracing_bunyan_formatter::{BunyanFormattingLayer, JsonStorageLayer};
+use server::{
+    badge_fetch, baseline_fetch, baseline_fetch_latest, baseline_persist, endpoint_request_fetch,
+    endpoint_request_persist, energy_mix_fetch, energy_mix_persist, fetch_within,
+    grafana::{grafana_annotations, grafana_query, grafana_search, grafana_test},
+    grpc::CardamonService,
+    iteration_phase_fetch, iteration_phase_persist, iteration_result_fetch,
+    iteration_result_persist, logs_fetch, persist_metrics, power_metrics_fetch_within,
+    power_metrics_persist, process_event_fetch_within, process_event_persist, process_info_fetch,
+    process_info_persist, projects_fetch, reference_clear, reference_fetch, reference_set,
+    run_archive, run_context_fetch, run_context_persist, run_delete, run_fetch, run_impact_fetch,
+    run_impact_persist, run_patch, run_persist, run_phase_fetch, run_phase_persist,
+    run_stop_time_persist,
+    runs::{observation_run, require_api_token, run_cancel, run_status, runs_active},
+    runs_fetch, scenario_iteration_persist, scenarios_fetch, server_info, ui, AppState,
+};
+use sqlx::sqlite::SqlitePool;
+use std::path::PathBuf;
+use tracing::info;
 use tracing_log::LogTracer;
-use tracing_subscriber::{fmt::writer::MakeWriterExt, layer::SubscriberExt, EnvFilter, Registry};
+
+#[derive(Parser, Debug)]
+#[command(version, about = "Cardamon metrics server", long_about = None)]
+struct ServerArgs {
+    /// Write logs as human readable text or as JSON for log aggregators
+    #[arg(long, env = "LOG_FORMAT", value_name = "text|json", default_value_t = LogFormat::Json)]
+    log_format: LogFormat,
+
+    /// Append logs to this file as well as stdout
+    #[arg(long, env = "LOG_FILE", default_value = "debug.log")]
+    log_file: PathBuf,
+
+    /// The SQLite database file, given as a path or "sqlite://" url
+    #[arg(long, env = "DB_PATH", default_value = "cardamon.db")]
+    db_path: String,
+
+    /// SQLite journal mode, "wal" lets the UI read while metrics are being written
+    #[arg(long, env = "DB_JOURNAL_MODE", default_value = "delete")]
+    db_journal_mode: JournalMode,
+
+    /// Milliseconds to wait for a database lock before failing
+    #[arg(long, env = "DB_BUSY_TIMEOUT", default_value_t = 5000)]
+    db_busy_timeout: u64,
+
+    /// Maximum number of database connections
+    #[arg(long, env = "DB_POOL_SIZE", default_value_t = 4)]
+    db_pool_size: u32,
+
+    /// SQLite synchronous level: off, normal, full or extra
+    #[arg(long, env = "DB_SYNCHRONOUS", default_value = "full")]
+    db_synchronous: Synchronous,
+
+    /// A cardamon.toml to read the `[server]` section of
+    #[arg(long, env = "CARDAMON_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// The address to listen on, defaults to every interface
+    #[arg(long, env = "SERVER_BIND")]
+    bind: Option<String>,
+
+    /// The port the REST api and UI are served on
+    #[arg(long, env = "SERVER_PORT")]
+    port: Option<u16>,
+
+    /// Origins of web pages allowed to call the api, "*" for any
+    #[arg(long, env = "ALLOWED_ORIGINS", value_delimiter = ',')]
+    allowed_origins: Vec<String>,
+
+    /// PEM certificate chain to serve HTTPS with, requires --tls-key
+    #[arg(long, env = "TLS_CERT")]
+    tls_cert: Option<PathBuf>,
+
+    /// PEM private key of the certificate
+    #[arg(long, env = "TLS_KEY")]
+    tls_key: Option<PathBuf>,
+
+    /// Bearer token needed to start observations through the api
+    #[arg(long, env = "API_TOKEN", hide_env_values = true)]
+    api_token: Option<String>,
+}
+impl ServerArgs {
+    fn database(&self) -> anyhow::Result<Database> {
+        Ok(Database {
+            // the server only records runs in SQLite
+            path: Some(Database::parse_location(&self.db_path)?),
+            url: None,
+            journal_mode: self.db_journal_mode,
+            busy_timeout: self.db_busy_timeout,
+            pool_size: self.db_pool_size,
+            synchronous: self.db_synchronous,
+        })
+    }
+
+    /// The `[server]` settings of the config file overridden by any given as arguments.
+    fn server(&self) -> anyhow::Result<config::Server> {
+        let mut server = match &self.config {
+            Some(path) => config::Config::from_path(path)?.server,
+            None => None,
+        }
+        .unwrap_or(config::Server {
+            bind: config::Server::default_bind(),
+            port: None,
+            allowed_origins: vec![],
+            tls_cert: None,
+            tls_key: None,
+            api_token: None,
+        });
+
+        if let Some(bind) = &self.bind {
+            server.bind = bind.clone();
+        }
+        if self.port.is_some() {
+            server.port = self.port;
+        }
+        if !self.allowed_origins.is_empty() {
+            server.allowed_origins = self.allowed_origins.clone();
+        }
+        if self.tls_cert.is_some() {
+            server.tls_cert = self.tls_cert.clone();
+        }
+        if self.tls_key.is_some() {
+            server.tls_key = self.tls_key.clone();
+        }
+        if let Some(token) = &self.api_token {
+            server.api_token = Some(Secret::Plain(token.clone()));
+        }
+        Ok(server)
+    }
+}
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     dotenv().ok();
-    let subscriber = get_subscriber("cardamon".into(), "debug".into());
-    init_subscriber(subscriber);
-    let pool = create_db().await?;
-    let app = create_app(pool).await;
-    let listener = tokio::net::TcpListener::bind(format!(
-        "0.0.0.0:{}",
-        std::env::var("SERVER_PORT").expect("Server port not set")
-    ))
-    .await
-    .unwrap();
-    info!("Starting cardamon server");
-    axum::serve(listener, app).await.unwrap();
+    let args = ServerArgs::parse();
+    LogTracer::init()?;
+    telemetry::init(
+        "cardamon",
+        args.log_format,
+        "debug",
+        Some(args.log_file.as_path()),
+    )?;
+    let server = args.server()?;
+    let port = server
+        .port
+        .ok_or_else(|| anyhow::anyhow!("Server port not set, use --port or SERVER_PORT"))?;
+    let tls = server::rustls_config(server.tls_cert.as_deref(), server.tls_key.as_deref()).await?;
+    let grpc_tls = server::grpc_tls_config(server.tls_cert.as_deref(), server.tls_key.as_deref())?;
+    let pool = create_db(&args.database()?).await?;
+    let state = AppState {
+        config: args.config.clone(),
+        api_token: server.api_token.as_ref().map(|t| t.resolve()).transpose()?,
+        ..AppState::new(pool.clone())
+    };
+    let mut app = create_app(state).await;
+    if let Some(cors) = server::cors(&server.allowed_origins)? {
+        app = app.layer(cors);
+    }
+    let listener = tokio::net::TcpListener::bind((server.bind.as_str(), port))
+        .await
+        .with_context(|| format!("Unable to listen on {}:{port}", server.bind))?;
+
+    // the gRPC api is optional and served on its own port alongside the REST api
+    let grpc = match std::env::var("GRPC_PORT") {
+        Ok(port) => {
+            let addr = format!("{}:{port}", server.bind).parse()?;
+            let service = CardamonService::new(pool.clone()).into_server();
+            let mut builder = tonic::transport::Server::builder();
+            if let Some(grpc_tls) = grpc_tls {
+                builder = builder
+                    .tls_config(grpc_tls)
+                    .context("Unable to serve gRPC over TLS")?;
+                info!("Starting cardamon gRPC server on {addr} with TLS");
+            } else {
+                info!("Starting cardamon gRPC server on {addr}");
+            }
+            Some(tokio::spawn(builder.add_service(service).serve(addr)))
+        }
+        Err(_) => None,
+    };
+
+    match tls {
+        Some(tls) => {
+            info!("Starting cardamon server on https://{}:{port}", server.bind);
+            axum_server::from_tcp_rustls(listener.into_std()?, tls)
+                .serve(app.into_make_service())
+                .await?;
+        }
+        None => {
+            info!("Starting cardamon server on {}:{port}", server.bind);
+            axum::serve(listener, app).await?;
+        }
+    }
+    if let Some(grpc) = grpc {
+        grpc.abort();
+    }
     Ok(())
 }
 
 // Keep seperated for integraion tests
-async fn create_app(pool: SqlitePool) -> Router {
+async fn create_app(state: AppState) -> Router {
     // Middleware later
     /*
     let protected = Router::new()
@@ -41,65 +222,168 @@ async fn create_app(pool: SqlitePool) -> Router {
         .route("/cpu_metrics/:id", get(fetch_within))
         //.route("/cpu_metrics/:id", delete(delete_metrics)) removed for now
         .route("/scenario", post(scenario_iteration_persist))
-        .with_state(pool)
-}
-
-fn get_subscriber(name: String, env_filter: String) -> impl Subscriber + Sync + Send {
-    let env_filter =
-        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(env_filter));
-
-    let file_writer = File::create("debug.log").unwrap();
-    let stdout_writer = std::io::stdout;
-    let formatting_layer = BunyanFormattingLayer::new(name, file_writer.and(stdout_writer));
-
-    Registry::default()
-        .with(env_filter)
-        .with(JsonStorageLayer)
-        .with(formatting_layer)
-}
-/*
- *
- * Print to *one* output ( e.g. std::io:stdout )
- * ( You need to pass in std::io::stdout as an argument then)
-  fn get_subscriber<Sink>(
-    name: String,
-    env_filter: String,
-    sink: Sink,
-) -> impl Subscriber + Sync + Send
-where
-    Sink: for<'a> MakeWriter<'a> + Send + Sync + 'static,
-{
-    let env_filter =
-        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(env_filter));
-    let formatting_layer = BunyanFormattingLayer::new(name, sink);
-    Registry::default()
-        .with(env_filter)
-        .with(JsonStorageLayer)
-        .with(formatting_layer)
+        .route("/process_event", post(process_event_persist))
+        .route("/process_event/:id", get(process_event_fetch_within))
+        .route("/power_metrics", post(power_metrics_persist))
+        .route("/power_metrics/:id", get(power_metrics_fetch_within))
+        .route(
+            "/baseline",
+            get(baseline_fetch_latest).post(baseline_persist),
+        )
+        .route("/baseline/:id", get(baseline_fetch))
+        .route("/run", post(run_persist))
+        .route("/run/:id", get(run_fetch))
+        .route("/run/:id/stop_time", post(run_stop_time_persist))
+        .route("/run_impact", post(run_impact_persist))
+        .route("/run_impact/:run_id", get(run_impact_fetch))
+        .route("/energy_mix", post(energy_mix_persist))
+        .route("/energy_mix/:run_id", get(energy_mix_fetch))
+        .route("/run_context", post(run_context_persist))
+        .route("/run_context/:run_id", get(run_context_fetch))
+        .route("/iteration_result", post(iteration_result_persist))
+        .route("/iteration_result/:run_id", get(iteration_result_fetch))
+        .route("/process_info", post(process_info_persist))
+        .route("/process_info/:run_id", get(process_info_fetch))
+        .route("/run_phase", post(run_phase_persist))
+        .route("/run_phase/:run_id", get(run_phase_fetch))
+        .route("/iteration_phase", post(iteration_phase_persist))
+        .route("/iteration_phase/:run_id", get(iteration_phase_fetch))
+        .route("/endpoint_request", post(endpoint_request_persist))
+        .route("/endpoint_request/:run_id", get(endpoint_request_fetch))
+        .route("/logs/:run_id/:process", get(logs_fetch))
+        .route("/api/server_info", get(server_info))
+        .route("/api/projects", get(projects_fetch))
+        .route("/api/scenarios", get(scenarios_fetch))
+        .route("/api/badge/:scenario", get(badge_fetch))
+        .nest("/api/grafana", grafana_routes())
+        .nest("/api/projects/:project/grafana", grafana_routes())
+        .route("/api/runs", get(runs_fetch))
+        .route("/api/runs/active", get(runs_active))
+        .route(
+            "/api/runs/:id",
+            delete(run_delete)
+                .patch(run_patch)
+                .route_layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    require_api_token,
+                )),
+        )
+        .route(
+            "/api/runs/:id/archived",
+            put(run_archive).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                require_api_token,
+            )),
+        )
+        .route("/api/runs/:id/status", get(run_status))
+        .route(
+            "/api/runs/:id/active",
+            delete(run_cancel).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                require_api_token,
+            )),
+        )
+        .route(
+            "/api/observations/:name/run",
+            post(observation_run).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                require_api_token,
+            )),
+        )
+        .route(
+            "/api/reference",
+            get(reference_fetch).merge(put(reference_set).delete(reference_clear).route_layer(
+                middleware::from_fn_with_state(state.clone(), require_api_token),
+            )),
+        )
+        .fallback(ui::static_handler)
+        .with_state(state)
 }
- */
 
-fn init_subscriber(subscriber: impl Subscriber + Sync + Send) {
-    LogTracer::init().expect("Failed to set logger");
-    set_global_default(subscriber).expect("Failed to set subscriber");
+/// The Grafana datasource, served for the default project and under each project.
+fn grafana_routes() -> Router<AppState> {
+    Router::new()
+        .route("/", get(grafana_test))
+        .route("/search", post(grafana_search))
+        .route("/query", post(grafana_query))
+        .route("/annotations", post(grafana_annotations))
 }
-async fn create_db() -> anyhow::Result<SqlitePool> {
-    let db_url = "sqlite://cardamon.db";
-    if !sqlx::Sqlite::database_exists(db_url).await? {
-        sqlx::Sqlite::create_database(db_url).await?;
-    }
 
-    let db = sqlx::sqlite::SqlitePoolOptions::new()
-        .max_connections(4)
+async fn create_db(database: &Database) -> anyhow::Result<SqlitePool> {
+    let db = database
+        .pool_options()
         .connect_with(
-            sqlx::sqlite::SqliteConnectOptions::new()
-                .filename("cardamon.db")
-                .pragma("journal_mode", "DELETE"), // Disable WAL mode
+            database
+                .connect_options()
+                .filename(database.file())
+                .create_if_missing(true),
         )
-        // .connect(db_url) with wal and shm
         .await?;
 
     sqlx::migrate!().run(&db).await?;
 
     Ok(db)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        body::Body,
+        http::{header, Request, StatusCode},
+    };
+    use tower::ServiceExt;
+
+    async fn status(app: Router, request: Request<Body>) -> anyhow::Result<StatusCode> {
+        Ok(app.oneshot(request).await?.status())
+    }
+
+    #[sqlx::test(
+        migrations = "./migrations",
+        fixtures("../fixtures/scenario_iterations.sql")
+    )]
+    async fn changing_runs_needs_the_api_token(pool: SqlitePool) -> anyhow::Result<()> {
+        let requests = || -> anyhow::Result<Vec<Request<Body>>> {
+            Ok(vec![
+                Request::put("/api/runs/1/archived")
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(r#"{"archived":true}"#))?,
+                Request::patch("/api/runs/1")
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(r#"{"note":"noisy neighbour"}"#))?,
+                Request::put("/api/reference")
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(r#"{"run_id":"1","name":"v1.0"}"#))?,
+                Request::delete("/api/reference").body(Body::empty())?,
+                Request::delete("/api/runs/1").body(Body::empty())?,
+            ])
+        };
+
+        // without a token the server turns changes off altogether
+        let app = create_app(AppState::new(pool.clone())).await;
+        for request in requests()? {
+            assert_eq!(status(app.clone(), request).await?, StatusCode::FORBIDDEN);
+        }
+        let request = Request::get("/api/reference").body(Body::empty())?;
+        assert!(status(app.clone(), request).await?.is_success());
+
+        let mut state = AppState::new(pool.clone());
+        state.api_token = Some(String::from("s3cret"));
+        let app = create_app(state).await;
+        for request in requests()? {
+            assert_eq!(
+                status(app.clone(), request).await?,
+                StatusCode::UNAUTHORIZED
+            );
+        }
+        for mut request in requests()? {
+            request
+                .headers_mut()
+                .insert(header::AUTHORIZATION, "Bearer s3cret".parse()?);
+            assert!(status(app.clone(), request).await?.is_success());
+        }
+
+        pool.close().await;
+        Ok(())
+    }
+}
@@ -0,0 +1,193 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Signatures over exported reports, so energy figures attached to a release or a sustainability
+//! disclosure can be checked later. Reports are signed with an Ed25519 key kept on the machine
+//! which exported them, and carry the public key so anyone can verify them against it.
+
+use anyhow::{anyhow, Context};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ring::{
+    rand::SystemRandom,
+    signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519},
+};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+pub const ALGORITHM: &str = "ed25519";
+
+/// The signing key's file under the home directory if no other key is given.
+const DEFAULT_KEY_FILE: &str = ".cardamon/signing.key";
+
+/// The signature over a report, with the public key which checks it.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Signature {
+    pub algorithm: String,
+    /// Base64 encoded public key.
+    pub public_key: String,
+    /// Base64 encoded signature of the report serialized as compact JSON.
+    pub value: String,
+}
+
+/// A report and the signature over it, as written to the exported file.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct SignedReport {
+    pub report: serde_json::Value,
+    pub signature: Signature,
+}
+impl SignedReport {
+    /// Checks the report hasn't changed since it was signed and, if a trusted public key is
+    /// given, that it was signed with that key. Without one this only shows the report matches
+    /// the key it carries, which anyone could have signed it with.
+    pub fn verify(&self, trusted_key: Option<&str>) -> anyhow::Result<()> {
+        if self.signature.algorithm != ALGORITHM {
+            return Err(anyhow!(
+                "Reports signed with {} can't be verified, only {ALGORITHM}",
+                self.signature.algorithm
+            ));
+        }
+        if trusted_key.is_some_and(|key| key.trim() != self.signature.public_key) {
+            return Err(anyhow!(
+                "Report was signed with key {}, not the trusted key",
+                self.signature.public_key
+            ));
+        }
+
+        let public_key = STANDARD
+            .decode(&self.signature.public_key)
+            .context("Public key isn't valid base64")?;
+        let signature = STANDARD
+            .decode(&self.signature.value)
+            .context("Signature isn't valid base64")?;
+        UnparsedPublicKey::new(&ED25519, public_key)
+            .verify(&serde_json::to_vec(&self.report)?, &signature)
+            .map_err(|_| {
+                anyhow!("Signature doesn't match, the report has changed since it was signed")
+            })
+    }
+}
+
+/// The key reports are signed with.
+pub struct SigningKey {
+    key_pair: Ed25519KeyPair,
+}
+impl SigningKey {
+    /// Reads the key from the file, generating one and writing it there first if there isn't a
+    /// key yet. The file is only readable by the current user.
+    pub fn load_or_create(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+                .map_err(|_| anyhow!("Unable to generate a signing key"))?;
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            write_private(path, &STANDARD.encode(pkcs8.as_ref()))
+                .with_context(|| format!("Unable to write signing key to {}", path.display()))?;
+            tracing::info!("Created signing key {}", path.display());
+        }
+
+        let encoded = std::fs::read_to_string(path)
+            .with_context(|| format!("Unable to read signing key from {}", path.display()))?;
+        let pkcs8 = STANDARD
+            .decode(encoded.trim())
+            .with_context(|| format!("Signing key {} isn't valid base64", path.display()))?;
+        let key_pair = Ed25519KeyPair::from_pkcs8(&pkcs8)
+            .map_err(|err| anyhow!("Signing key {} is invalid: {err}", path.display()))?;
+        Ok(Self { key_pair })
+    }
+
+    /// The base64 encoded public key, which is published so reports can be verified.
+    pub fn public_key(&self) -> String {
+        STANDARD.encode(self.key_pair.public_key().as_ref())
+    }
+
+    pub fn sign(&self, report: serde_json::Value) -> anyhow::Result<SignedReport> {
+        let signature = self.key_pair.sign(&serde_json::to_vec(&report)?);
+        Ok(SignedReport {
+            report,
+            signature: Signature {
+                algorithm: ALGORITHM.to_string(),
+                public_key: self.public_key(),
+                value: STANDARD.encode(signature.as_ref()),
+            },
+        })
+    }
+}
+
+/// `~/.cardamon/signing.key`, None if the home directory isn't known.
+pub fn default_key_path() -> Option<PathBuf> {
+    std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(|home| PathBuf::from(home).join(DEFAULT_KEY_FILE))
+}
+
+#[cfg(unix)]
+fn write_private(path: &Path, contents: &str) -> std::io::Result<()> {
+    use std::{io::Write, os::unix::fs::OpenOptionsExt};
+
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?
+        .write_all(contents.as_bytes())
+}
+
+#[cfg(not(unix))]
+fn write_private(path: &Path, contents: &str) -> std::io::Result<()> {
+    std::fs::write(path, contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signed_reports_only_verify_unchanged() -> anyhow::Result<()> {
+        let dir = std::env::temp_dir().join(format!("cardamon-signing-{}", nanoid::nanoid!(8)));
+        let path = dir.join("signing.key");
+        let key = SigningKey::load_or_create(&path)?;
+        // the same key is read back rather than replaced
+        assert_eq!(
+            SigningKey::load_or_create(&path)?.public_key(),
+            key.public_key()
+        );
+
+        let signed = key.sign(serde_json::json!({ "run_id": "abc", "energy_wh": 1.25 }))?;
+        signed.verify(None)?;
+        signed.verify(Some(&key.public_key()))?;
+
+        // round trips through the exported file
+        let exported = serde_json::to_string_pretty(&signed)?;
+        serde_json::from_str::<SignedReport>(&exported)?.verify(None)?;
+
+        let mut tampered = signed.clone();
+        tampered.report["energy_wh"] = serde_json::json!(0.5);
+        assert!(tampered.verify(None).is_err());
+
+        let other = SigningKey::load_or_create(&dir.join("other.key"))?;
+        assert!(signed.verify(Some(&other.public_key())).is_err());
+
+        std::fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn full_precision_figures_verify_after_export() -> anyhow::Result<()> {
+        let dir = std::env::temp_dir().join(format!("cardamon-signing-{}", nanoid::nanoid!(8)));
+        let key = SigningKey::load_or_create(&dir.join("signing.key"))?;
+
+        let signed = key.sign(serde_json::json!({
+            "energy_wh": 212.91890726713459,
+            "energy_wh_per_iteration": 985.6906946328695,
+        }))?;
+        let exported = serde_json::to_string_pretty(&signed)?;
+        serde_json::from_str::<SignedReport>(&exported)?.verify(None)?;
+
+        std::fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,411 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+/// p-values below this are considered statistically significant.
+pub const SIGNIFICANCE_LEVEL: f64 = 0.05;
+
+/// z value for a two sided 95% confidence interval.
+const Z_95: f64 = 1.959964;
+
+/// The result of comparing measurements taken before and after a change.
+#[derive(Debug, PartialEq)]
+pub struct Comparison {
+    pub baseline_mean: f64,
+    pub candidate_mean: f64,
+    /// The 95% confidence interval of the difference in means (candidate - baseline).
+    pub delta_ci: (f64, f64),
+    /// Two sided p-value from a Mann-Whitney U test.
+    pub p_value: f64,
+}
+impl Comparison {
+    /// Compares two sets of measurements, e.g. the CPU usage of each iteration of a scenario
+    /// before and after a change.
+    pub fn new(baseline: &[f64], candidate: &[f64]) -> Self {
+        let baseline_mean = mean(baseline);
+        let candidate_mean = mean(candidate);
+
+        // Welch's standard error, approximated with a normal distribution
+        let std_err = (variance(baseline) / baseline.len() as f64
+            + variance(candidate) / candidate.len() as f64)
+            .sqrt();
+        let delta = candidate_mean - baseline_mean;
+        let delta_ci = if std_err.is_finite() {
+            (delta - Z_95 * std_err, delta + Z_95 * std_err)
+        } else {
+            (f64::NEG_INFINITY, f64::INFINITY)
+        };
+
+        Self {
+            baseline_mean,
+            candidate_mean,
+            delta_ci,
+            p_value: mann_whitney_u(baseline, candidate),
+        }
+    }
+
+    pub fn delta(&self) -> f64 {
+        self.candidate_mean - self.baseline_mean
+    }
+
+    pub fn is_significant(&self) -> bool {
+        self.p_value < SIGNIFICANCE_LEVEL
+    }
+}
+
+/// A least squares polynomial fit of y against x, quadratic when there are at least three
+/// distinct x values and linear otherwise.
+#[derive(Debug, PartialEq)]
+pub struct CurveFit {
+    /// Coefficients of x⁰, x¹ and x².
+    pub coefficients: [f64; 3],
+    /// Coefficient of determination, 1.0 is a perfect fit.
+    pub r_squared: f64,
+}
+impl CurveFit {
+    /// Fits a curve to the points, None if there are fewer than two distinct x values.
+    pub fn new(xs: &[f64], ys: &[f64]) -> Option<Self> {
+        let mut distinct = xs.to_vec();
+        distinct.sort_by(|a, b| a.total_cmp(b));
+        distinct.dedup();
+        let degree = match distinct.len() {
+            0 | 1 => return None,
+            2 => 1,
+            _ => 2,
+        };
+
+        // normal equations (XᵀX)β = Xᵀy
+        let n = degree + 1;
+        let mut matrix = vec![vec![0.0; n + 1]; n];
+        for (x, y) in xs.iter().zip(ys) {
+            for (row, equation) in matrix.iter_mut().enumerate() {
+                for (col, cell) in equation[..n].iter_mut().enumerate() {
+                    *cell += x.powi((row + col) as i32);
+                }
+                equation[n] += y * x.powi(row as i32);
+            }
+        }
+        let solution = solve(matrix)?;
+
+        let mut coefficients = [0.0; 3];
+        coefficients[..n].copy_from_slice(&solution);
+        let fit = Self {
+            coefficients,
+            r_squared: 0.0,
+        };
+
+        let y_mean = mean(ys);
+        let ss_total = ys.iter().map(|y| (y - y_mean).powi(2)).sum::<f64>();
+        let ss_residual = xs
+            .iter()
+            .zip(ys)
+            .map(|(x, y)| (y - fit.predict(*x)).powi(2))
+            .sum::<f64>();
+        let r_squared = if ss_total > 0.0 {
+            1.0 - ss_residual / ss_total
+        } else {
+            1.0
+        };
+
+        Some(Self { r_squared, ..fit })
+    }
+
+    pub fn predict(&self, x: f64) -> f64 {
+        let [a, b, c] = self.coefficients;
+        a + b * x + c * x * x
+    }
+}
+
+/// A least squares line through a measurement of consecutive runs, oldest first. A positive slope
+/// with a high R² is a steady regression rather than a noisy run or two.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct Trend {
+    /// Change in the measurement per run.
+    pub slope: f64,
+    /// Coefficient of determination, 1.0 when every run lies on the line.
+    pub r_squared: f64,
+    /// The number of runs the line was fitted to.
+    pub runs: usize,
+}
+impl Trend {
+    /// Fits a line to the values against their run number, None for fewer than two runs.
+    pub fn new(values: &[f64]) -> Option<Self> {
+        if values.len() < 2 {
+            return None;
+        }
+
+        let xs = (0..values.len()).map(|x| x as f64).collect::<Vec<_>>();
+        let (x_mean, y_mean) = (mean(&xs), mean(values));
+        let ss_xy = xs
+            .iter()
+            .zip(values)
+            .map(|(x, y)| (x - x_mean) * (y - y_mean))
+            .sum::<f64>();
+        let ss_xx = xs.iter().map(|x| (x - x_mean).powi(2)).sum::<f64>();
+        let ss_yy = values.iter().map(|y| (y - y_mean).powi(2)).sum::<f64>();
+
+        let slope = ss_xy / ss_xx;
+        let r_squared = if ss_yy > 0.0 {
+            ss_xy * ss_xy / (ss_xx * ss_yy)
+        } else {
+            1.0
+        };
+
+        Some(Self {
+            slope,
+            r_squared,
+            runs: values.len(),
+        })
+    }
+}
+
+/// How much a measurement varied between the iterations of a scenario. A single averaged number
+/// hides whether the measurement is stable enough to trust, a high coefficient of variation means
+/// it isn't.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct Spread {
+    pub mean: f64,
+    /// Sample standard deviation, zero for a single iteration.
+    pub stddev: f64,
+    /// Coefficient of variation, the standard deviation as a percentage of the mean.
+    pub cv: f64,
+    /// The number of iterations measured.
+    pub iterations: usize,
+}
+impl Spread {
+    /// The spread of the measurement of each iteration, None without any iterations.
+    pub fn new(values: &[f64]) -> Option<Self> {
+        if values.is_empty() {
+            return None;
+        }
+
+        let mean = mean(values);
+        let stddev = if values.len() < 2 {
+            0.0
+        } else {
+            variance(values).sqrt()
+        };
+        let cv = if mean != 0.0 {
+            stddev / mean.abs() * 100.0
+        } else {
+            0.0
+        };
+
+        Some(Self {
+            mean,
+            stddev,
+            cv,
+            iterations: values.len(),
+        })
+    }
+}
+
+/// Solves an augmented matrix with Gaussian elimination, None if it's singular.
+fn solve(mut matrix: Vec<Vec<f64>>) -> Option<Vec<f64>> {
+    let n = matrix.len();
+    for col in 0..n {
+        let pivot =
+            (col..n).max_by(|a, b| matrix[*a][col].abs().total_cmp(&matrix[*b][col].abs()))?;
+        if matrix[pivot][col].abs() < 1e-12 {
+            return None;
+        }
+        matrix.swap(col, pivot);
+
+        let pivot_row = matrix[col].clone();
+        for (row, equation) in matrix.iter_mut().enumerate() {
+            if row != col {
+                let factor = equation[col] / pivot_row[col];
+                for (cell, pivot_cell) in equation[col..].iter_mut().zip(&pivot_row[col..]) {
+                    *cell -= factor * pivot_cell;
+                }
+            }
+        }
+    }
+
+    Some(
+        (0..n)
+            .map(|row| matrix[row][n] / matrix[row][row])
+            .collect(),
+    )
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// Unbiased sample variance, NaN for fewer than two values.
+fn variance(values: &[f64]) -> f64 {
+    if values.len() < 2 {
+        return f64::NAN;
+    }
+    let mean = mean(values);
+    values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (values.len() - 1) as f64
+}
+
+/// Two sided Mann-Whitney U test using the normal approximation with a correction for ties. This
+/// makes no assumptions about the distribution of the measurements, which for energy data often
+/// has a long tail.
+///
+/// # Returns
+///
+/// The p-value, 1.0 if either sample is empty or every value is identical.
+pub fn mann_whitney_u(a: &[f64], b: &[f64]) -> f64 {
+    if a.is_empty() || b.is_empty() {
+        return 1.0;
+    }
+
+    // rank all values together, tied values share the average of their ranks
+    let mut all = a
+        .iter()
+        .map(|v| (*v, true))
+        .chain(b.iter().map(|v| (*v, false)))
+        .collect::<Vec<_>>();
+    all.sort_by(|x, y| x.0.total_cmp(&y.0));
+
+    let n = all.len() as f64;
+    let mut rank_sum_a = 0.0;
+    let mut tie_correction = 0.0;
+    let mut i = 0;
+    while i < all.len() {
+        let mut j = i;
+        while j + 1 < all.len() && all[j + 1].0 == all[i].0 {
+            j += 1;
+        }
+        let rank = (i + j) as f64 / 2.0 + 1.0;
+        let ties = (j - i + 1) as f64;
+        tie_correction += ties.powi(3) - ties;
+        rank_sum_a += all[i..=j].iter().filter(|(_, in_a)| *in_a).count() as f64 * rank;
+        i = j + 1;
+    }
+
+    let (n_a, n_b) = (a.len() as f64, b.len() as f64);
+    let u = rank_sum_a - n_a * (n_a + 1.0) / 2.0;
+    let mean_u = n_a * n_b / 2.0;
+    let var_u = n_a * n_b / 12.0 * ((n + 1.0) - tie_correction / (n * (n - 1.0)));
+    if var_u <= 0.0 {
+        return 1.0;
+    }
+
+    // continuity correction
+    let z = ((u - mean_u).abs() - 0.5).max(0.0) / var_u.sqrt();
+    erfc(z / std::f64::consts::SQRT_2).min(1.0)
+}
+
+/// Complementary error function (Numerical Recipes erfc approximation, accurate to ~1.2e-7).
+fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let r = t
+        * (-z * z - 1.26551223
+            + t * (1.00002368
+                + t * (0.37409196
+                    + t * (0.09678418
+                        + t * (-0.18628806
+                            + t * (0.27886807
+                                + t * (-1.13520398
+                                    + t * (1.48851587 + t * (-0.82215223 + t * 0.17087277)))))))))
+            .exp();
+    if x >= 0.0 {
+        r
+    } else {
+        2.0 - r
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn curves_can_be_fitted() {
+        // y = 2 + 0.5x + 0.1x²
+        let xs = [0.0, 10.0, 20.0, 50.0, 100.0];
+        let ys = xs.map(|x| 2.0 + 0.5 * x + 0.1 * x * x);
+        let fit = CurveFit::new(&xs, &ys).expect("should fit");
+        assert!((fit.coefficients[0] - 2.0).abs() < 1e-6);
+        assert!((fit.coefficients[1] - 0.5).abs() < 1e-6);
+        assert!((fit.coefficients[2] - 0.1).abs() < 1e-6);
+        assert!((fit.r_squared - 1.0).abs() < 1e-9);
+        assert!((fit.predict(30.0) - 107.0).abs() < 1e-6);
+
+        // two load levels can only be fitted with a line
+        let fit = CurveFit::new(&[10.0, 10.0, 20.0], &[5.0, 7.0, 16.0]).expect("should fit");
+        assert_eq!(fit.coefficients[2], 0.0);
+        assert!((fit.predict(20.0) - 16.0).abs() < 1e-9);
+
+        assert!(CurveFit::new(&[10.0, 10.0], &[5.0, 7.0]).is_none());
+    }
+
+    #[test]
+    fn trends_are_fitted_to_consecutive_runs() {
+        let trend = Trend::new(&[10.0, 12.0, 14.0, 16.0]).expect("should fit");
+        assert!((trend.slope - 2.0).abs() < 1e-9);
+        assert!((trend.r_squared - 1.0).abs() < 1e-9);
+        assert_eq!(trend.runs, 4);
+
+        // a single noisy run barely moves the line
+        let trend = Trend::new(&[10.0, 10.0, 20.0, 10.0, 10.0]).expect("should fit");
+        assert!(trend.slope.abs() < 1e-9);
+        assert!(trend.r_squared < 0.1);
+
+        let trend = Trend::new(&[5.0, 5.0]).expect("should fit");
+        assert_eq!(trend.slope, 0.0);
+        assert_eq!(trend.r_squared, 1.0);
+
+        assert!(Trend::new(&[5.0]).is_none());
+    }
+
+    #[test]
+    fn spread_of_iterations() {
+        let spread = Spread::new(&[10.0, 12.0, 14.0]).expect("should have a spread");
+        assert_eq!(spread.mean, 12.0);
+        assert_eq!(spread.stddev, 2.0);
+        assert!((spread.cv - 16.667).abs() < 0.001);
+        assert_eq!(spread.iterations, 3);
+
+        let spread = Spread::new(&[5.0]).expect("should have a spread");
+        assert_eq!((spread.stddev, spread.cv), (0.0, 0.0));
+
+        assert!(Spread::new(&[]).is_none());
+    }
+
+    #[test]
+    fn erfc_matches_known_values() {
+        assert!((erfc(0.0) - 1.0).abs() < 1e-6);
+        assert!((erfc(1.0) - 0.157299).abs() < 1e-6);
+        assert!((erfc(-1.0) - 1.842701).abs() < 1e-6);
+    }
+
+    #[test]
+    fn clearly_different_samples_are_significant() {
+        let baseline = [10.0, 11.0, 10.5, 9.5, 10.2, 9.8, 10.1, 10.4];
+        let candidate = [15.0, 14.5, 15.5, 16.0, 14.8, 15.2, 15.1, 14.9];
+
+        let comparison = Comparison::new(&baseline, &candidate);
+        assert!(comparison.is_significant());
+        assert!(comparison.delta() > 4.0);
+        assert!(comparison.delta_ci.0 > 0.0);
+    }
+
+    #[test]
+    fn noise_is_not_significant() {
+        let baseline = [10.0, 12.0, 9.0, 11.0, 10.5];
+        let candidate = [10.2, 11.5, 9.5, 10.8, 11.2];
+
+        let comparison = Comparison::new(&baseline, &candidate);
+        assert!(!comparison.is_significant());
+        assert!(comparison.delta_ci.0 < 0.0 && comparison.delta_ci.1 > 0.0);
+    }
+
+    #[test]
+    fn mann_whitney_handles_ties_and_empty_samples() {
+        // 4 vs 4 with complete separation, p = 0.0286 exact, ~0.03 approximated
+        let p = mann_whitney_u(&[1.0, 2.0, 3.0, 4.0], &[5.0, 6.0, 7.0, 8.0]);
+        assert!(p > 0.02 && p < 0.05);
+
+        assert_eq!(mann_whitney_u(&[1.0, 1.0], &[1.0, 1.0]), 1.0);
+        assert_eq!(mann_whitney_u(&[], &[1.0]), 1.0);
+    }
+}
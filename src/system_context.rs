@@ -0,0 +1,220 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Captures the machine a run executes on, and how its containers were deployed, recorded at the
+//! start of each run.
+
+use crate::data_access::{process_info::ProcessInfo, run_context::RunContext};
+use bollard::{
+    container::ListContainersOptions,
+    models::{ContainerInspectResponse, ContainerSummary},
+    Docker,
+};
+use std::{collections::HashMap, time::Duration};
+use sysinfo::System;
+
+/// Where Linux exposes the frequency scaling governor of the first CPU.
+const GOVERNOR_PATH: &str = "/sys/devices/system/cpu/cpu0/cpufreq/scaling_governor";
+
+/// Where Linux exposes the CPUs, each has a count of the times it was throttled for being too hot.
+const CPU_DIR: &str = "/sys/devices/system/cpu";
+
+/// How long to wait for the container runtime to respond before leaving it out.
+const RUNTIME_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The labels docker compose gives containers, containers with the same project and service are
+/// replicas of each other.
+const COMPOSE_PROJECT_LABEL: &str = "com.docker.compose.project";
+const COMPOSE_SERVICE_LABEL: &str = "com.docker.compose.service";
+
+/// Captures the OS, hardware and software versions of the machine running cardamon. Anything
+/// which can't be read is left out rather than failing the run.
+pub async fn capture(run_id: &str) -> RunContext {
+    let mut system = System::new();
+    system.refresh_memory();
+    system.refresh_cpu();
+
+    RunContext {
+        run_id: run_id.to_string(),
+        os_version: System::long_os_version(),
+        kernel_version: System::kernel_version(),
+        total_memory: system.total_memory() as i64,
+        core_count: system.cpus().len() as i64,
+        cpu_governor: cpu_governor(),
+        container_runtime: container_runtime().await,
+        cardamon_version: env!("CARGO_PKG_VERSION").to_string(),
+        pinning: None,
+    }
+}
+
+/// The CPU frequency scaling governor, e.g. "powersave", None if the OS doesn't expose one.
+pub fn cpu_governor() -> Option<String> {
+    std::fs::read_to_string(GOVERNOR_PATH)
+        .ok()
+        .map(|governor| governor.trim().to_string())
+        .filter(|governor| !governor.is_empty())
+}
+
+/// The number of times any core has been throttled for being too hot since boot, None if the OS
+/// doesn't expose it (only Linux on Intel CPUs does).
+pub fn throttle_count() -> Option<u64> {
+    let counts = std::fs::read_dir(CPU_DIR)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            std::fs::read_to_string(entry.path().join("thermal_throttle/core_throttle_count")).ok()
+        })
+        .filter_map(|count| count.trim().parse::<u64>().ok())
+        .collect::<Vec<_>>();
+
+    (!counts.is_empty()).then(|| counts.iter().sum())
+}
+
+/// The name and version of the container runtime, e.g. "Docker 24.0.5".
+async fn container_runtime() -> Option<String> {
+    let docker = Docker::connect_with_defaults().ok()?;
+    let version = tokio::time::timeout(RUNTIME_TIMEOUT, docker.version())
+        .await
+        .ok()?
+        .ok()?;
+    version.version.map(|version| format!("Docker {version}"))
+}
+
+/// Captures the image, resource limits and replicas of each of the named containers. Containers
+/// which can't be inspected, e.g. because they haven't started yet, are left out.
+pub async fn containers(run_id: &str, container_names: &[String]) -> Vec<ProcessInfo> {
+    if container_names.is_empty() {
+        return vec![];
+    }
+    let Ok(docker) = Docker::connect_with_defaults() else {
+        return vec![];
+    };
+    let running = tokio::time::timeout(
+        RUNTIME_TIMEOUT,
+        docker.list_containers(Some(ListContainersOptions::<String>::default())),
+    )
+    .await
+    .ok()
+    .and_then(|running| running.ok())
+    .unwrap_or_default();
+
+    let mut process_infos = vec![];
+    for name in container_names {
+        match tokio::time::timeout(RUNTIME_TIMEOUT, docker.inspect_container(name, None)).await {
+            Ok(Ok(inspect)) => process_infos.push(process_info(run_id, name, &inspect, &running)),
+            _ => tracing::debug!("Unable to inspect container {name}, leaving it out"),
+        }
+    }
+    process_infos
+}
+
+fn process_info(
+    run_id: &str,
+    name: &str,
+    inspect: &ContainerInspectResponse,
+    running: &[ContainerSummary],
+) -> ProcessInfo {
+    let host_config = inspect.host_config.as_ref();
+    let cpu_limit = host_config.and_then(|host_config| {
+        match (
+            host_config.nano_cpus,
+            host_config.cpu_quota,
+            host_config.cpu_period,
+        ) {
+            (Some(nano_cpus), _, _) if nano_cpus > 0 => Some(nano_cpus as f64 / 1_000_000_000.0),
+            (_, Some(quota), Some(period)) if quota > 0 && period > 0 => {
+                Some(quota as f64 / period as f64)
+            }
+            _ => None,
+        }
+    });
+    let memory_limit = host_config
+        .and_then(|host_config| host_config.memory)
+        .filter(|memory| *memory > 0);
+
+    let labels = inspect
+        .config
+        .as_ref()
+        .and_then(|config| config.labels.clone())
+        .unwrap_or_default();
+    let service = |labels: &HashMap<String, String>| {
+        Some((
+            labels.get(COMPOSE_PROJECT_LABEL)?.clone(),
+            labels.get(COMPOSE_SERVICE_LABEL)?.clone(),
+        ))
+    };
+    let replicas = match service(&labels) {
+        Some(this_service) => running
+            .iter()
+            .filter(|container| {
+                container.labels.as_ref().and_then(service).as_ref() == Some(&this_service)
+            })
+            .count()
+            .max(1),
+        None => 1,
+    };
+
+    ProcessInfo {
+        run_id: run_id.to_string(),
+        process_name: name.to_string(),
+        image: inspect
+            .config
+            .as_ref()
+            .and_then(|config| config.image.clone()),
+        cpu_limit,
+        memory_limit,
+        replicas: replicas as i64,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bollard::models::{ContainerConfig, HostConfig};
+
+    #[test]
+    fn container_limits_and_replicas_are_captured() {
+        let compose_labels = |service: &str| {
+            HashMap::from([
+                (COMPOSE_PROJECT_LABEL.to_string(), String::from("shop")),
+                (COMPOSE_SERVICE_LABEL.to_string(), service.to_string()),
+            ])
+        };
+        let inspect = ContainerInspectResponse {
+            config: Some(ContainerConfig {
+                image: Some(String::from("nginx:1.25-alpine")),
+                labels: Some(compose_labels("web")),
+                ..Default::default()
+            }),
+            host_config: Some(HostConfig {
+                cpu_quota: Some(150_000),
+                cpu_period: Some(100_000),
+                memory: Some(512 * 1024 * 1024),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let running = ["web", "web", "db"]
+            .iter()
+            .map(|service| ContainerSummary {
+                labels: Some(compose_labels(service)),
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+
+        let info = process_info("1", "shop-web-1", &inspect, &running);
+        assert_eq!(info.image.as_deref(), Some("nginx:1.25-alpine"));
+        assert_eq!(info.cpu_limit, Some(1.5));
+        assert_eq!(info.memory_limit, Some(512 * 1024 * 1024));
+        assert_eq!(info.replicas, 2);
+
+        // unlimited containers outside of compose
+        let info = process_info("1", "db", &ContainerInspectResponse::default(), &running);
+        assert_eq!(info.cpu_limit, None);
+        assert_eq!(info.memory_limit, None);
+        assert_eq!(info.replicas, 1);
+    }
+}
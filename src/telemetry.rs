@@ -0,0 +1,116 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Logging setup shared by the cli and the server. Logs are written to stdout either as human
+//! readable text or as bunyan style JSON for log aggregators, and optionally to a file as well.
+
+use anyhow::Context;
+use std::{fmt, fs::OpenOptions, path::Path, str::FromStr};
+use tracing_bunyan_formatter::{BunyanFormattingLayer, JsonStorageLayer};
+use tracing_subscriber::{
+    fmt::writer::{BoxMakeWriter, MakeWriterExt},
+    layer::SubscriberExt,
+    EnvFilter, Registry,
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+impl FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(anyhow::anyhow!(
+                "Unknown log format {s}, expected text or json"
+            )),
+        }
+    }
+}
+impl fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogFormat::Text => write!(f, "text"),
+            LogFormat::Json => write!(f, "json"),
+        }
+    }
+}
+
+/// Installs the global tracing subscriber.
+///
+/// # Arguments
+///
+/// * `name` - The name of the application, included in every JSON log record
+/// * `format` - How each log record is written
+/// * `default_filter` - The filter used when `RUST_LOG` isn't set, e.g. "warn"
+/// * `log_file` - Optional file which logs are appended to as well as stdout
+pub fn init(
+    name: &str,
+    format: LogFormat,
+    default_filter: &str,
+    log_file: Option<&Path>,
+) -> anyhow::Result<()> {
+    let env_filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(default_filter));
+
+    let writer = match log_file {
+        Some(path) => {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .context(format!("Unable to open log file {}", path.display()))?;
+            BoxMakeWriter::new(std::io::stdout.and(file))
+        }
+        None => BoxMakeWriter::new(std::io::stdout),
+    };
+
+    let (json_layers, text_layer) = match format {
+        LogFormat::Json => (
+            Some((
+                JsonStorageLayer,
+                BunyanFormattingLayer::new(name.to_string(), writer),
+            )),
+            None,
+        ),
+        LogFormat::Text => (
+            None,
+            Some(
+                tracing_subscriber::fmt::layer()
+                    .with_writer(writer)
+                    // escape codes are noise in log files
+                    .with_ansi(log_file.is_none()),
+            ),
+        ),
+    };
+    let (storage_layer, bunyan_layer) = json_layers.unzip();
+
+    let subscriber = Registry::default()
+        .with(env_filter)
+        .with(storage_layer)
+        .with(bunyan_layer)
+        .with(text_layer);
+    tracing::subscriber::set_global_default(subscriber).context("Failed to set subscriber")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_formats_can_be_parsed() -> anyhow::Result<()> {
+        assert_eq!("json".parse::<LogFormat>()?, LogFormat::Json);
+        assert_eq!("Text".parse::<LogFormat>()?, LogFormat::Text);
+        assert!("xml".parse::<LogFormat>().is_err());
+        assert_eq!(LogFormat::Json.to_string(), "json");
+        Ok(())
+    }
+}
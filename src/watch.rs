@@ -0,0 +1,121 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! Waits for source files to change so an observation can be re-run while a developer works.
+
+use anyhow::Context;
+use notify::{RecursiveMode, Watcher};
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio::sync::mpsc;
+
+/// Directories which change as a side effect of building or running, rather than because a
+/// developer edited them.
+const IGNORED_DIRS: [&str; 5] = [".git", ".cardamon", "target", "node_modules", "__pycache__"];
+
+/// Watches a directory tree for changes.
+pub struct SourceWatcher {
+    // events stop being sent once the watcher is dropped
+    _watcher: notify::RecommendedWatcher,
+    changes: mpsc::UnboundedReceiver<PathBuf>,
+}
+impl SourceWatcher {
+    pub fn new(path: &Path) -> anyhow::Result<Self> {
+        let (sender, changes) = mpsc::unbounded_channel();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                let Ok(event) = event else {
+                    return;
+                };
+                if event.kind.is_access() {
+                    return;
+                }
+                for path in event.paths.into_iter().filter(|path| !is_ignored(path)) {
+                    let _ = sender.send(path);
+                }
+            })?;
+        watcher
+            .watch(path, RecursiveMode::Recursive)
+            .with_context(|| format!("Unable to watch {}", path.display()))?;
+
+        Ok(Self {
+            _watcher: watcher,
+            changes,
+        })
+    }
+
+    /// Waits for a file to change then for changes to stop for the debounce period, so saving
+    /// several files at once only counts as one change.
+    ///
+    /// # Returns
+    ///
+    /// The files which changed, None if the watcher stopped.
+    pub async fn changed(&mut self, debounce: Duration) -> Option<Vec<PathBuf>> {
+        let mut changed = vec![self.changes.recv().await?];
+        while let Ok(Some(path)) = tokio::time::timeout(debounce, self.changes.recv()).await {
+            changed.push(path);
+        }
+        changed.sort();
+        changed.dedup();
+        Some(changed)
+    }
+
+    /// Forgets changes made while the observation was running, e.g. files the scenarios wrote.
+    pub fn clear(&mut self) {
+        while self.changes.try_recv().is_ok() {}
+    }
+}
+
+/// Files in build output, version control or cardamon's own directories, and cardamon's database.
+fn is_ignored(path: &Path) -> bool {
+    let in_ignored_dir = path
+        .components()
+        .any(|component| IGNORED_DIRS.iter().any(|dir| component.as_os_str() == *dir));
+    let is_database = path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .is_some_and(|name| name.contains(".db"));
+    in_ignored_dir || is_database
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_output_and_databases_are_ignored() {
+        assert!(is_ignored(Path::new("/app/target/debug/app")));
+        assert!(is_ignored(Path::new("/app/.git/index")));
+        assert!(is_ignored(Path::new("/app/.cardamon/logs/run.log")));
+        assert!(is_ignored(Path::new("/app/cardamon.db-wal")));
+        assert!(!is_ignored(Path::new("/app/src/main.rs")));
+        assert!(!is_ignored(Path::new("/app/targets.txt")));
+    }
+
+    #[tokio::test]
+    async fn changes_are_debounced() -> anyhow::Result<()> {
+        let dir = std::env::temp_dir().join(format!("cardamon-watch-{}", nanoid::nanoid!(5)));
+        std::fs::create_dir_all(&dir)?;
+        let dir = dir.canonicalize()?;
+        let mut watcher = SourceWatcher::new(&dir)?;
+
+        std::fs::write(dir.join("a.rs"), "fn a() {}")?;
+        std::fs::write(dir.join("b.rs"), "fn b() {}")?;
+        let changed = tokio::time::timeout(
+            Duration::from_secs(10),
+            watcher.changed(Duration::from_millis(200)),
+        )
+        .await?
+        .context("expected the watcher to be running")?;
+
+        std::fs::remove_dir_all(&dir)?;
+        assert!(changed.contains(&dir.join("a.rs")));
+        assert!(changed.contains(&dir.join("b.rs")));
+        Ok(())
+    }
+}
@@ -0,0 +1,148 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/.
+ */
+
+//! End-to-end tests which observe real workloads through `run` and check that every stage of the
+//! pipeline, from sampling to modelling power, produces results. They're slow so they only build
+//! with the `e2e` feature, `cargo test --features e2e`. Tests which need docker are also ignored,
+//! run them with `cargo test --features e2e -- --include-ignored`.
+#![cfg(all(feature = "e2e", unix))]
+
+use anyhow::Context;
+use cardamon::{
+    config::Config,
+    data_access::{DataAccessService, LocalDataAccessService},
+    model, run,
+};
+use std::path::PathBuf;
+use tokio::sync::Mutex;
+
+/// Runs share cardamon's lock file and log directory so only one can run at a time.
+static RUNNING: Mutex<()> = Mutex::const_new(());
+
+const CPU: &str = r#"
+[cpu]
+name = "Test CPU"
+tdp = 15
+"#;
+
+/// Writes the config to a temporary file so it can be loaded like any other.
+fn write_config(toml: &str) -> anyhow::Result<PathBuf> {
+    let path = std::env::temp_dir().join(format!("cardamon-e2e-{}.toml", nanoid::nanoid!(5)));
+    std::fs::write(&path, format!("{CPU}{toml}"))?;
+    Ok(path)
+}
+
+/// Runs the observation then checks its run, iterations and metrics were saved and that the
+/// process used some power.
+async fn observe(
+    pool: sqlx::SqlitePool,
+    toml: &str,
+    observation: &str,
+    process_name: &str,
+    iterations: usize,
+) -> anyhow::Result<()> {
+    let _running = RUNNING.lock().await;
+
+    let path = write_config(toml)?;
+    let config = Config::from_path(&path);
+    std::fs::remove_file(&path)?;
+    let config = config?;
+
+    let data_access_service = LocalDataAccessService::new(pool.clone());
+    let execution_plan = config.create_execution_plan(observation)?;
+    let observation_dataset = run(execution_plan, &data_access_service).await?;
+
+    let scenario_datasets = observation_dataset.by_scenario();
+    let scenario_dataset = scenario_datasets
+        .first()
+        .context("expected the scenario to be saved")?;
+    let run_datasets = scenario_dataset.by_run();
+    let run_dataset = run_datasets.first().context("expected a run")?;
+    assert!(data_access_service
+        .run_dao()
+        .fetch(run_dataset.run_id())
+        .await?
+        .is_some());
+
+    let cpu = config.cpu.as_ref().context("expected a cpu")?;
+    assert_eq!(run_dataset.by_iterations().len(), iterations);
+    for it in run_dataset.by_iterations() {
+        assert!(it.scenario_iteration().is_ok());
+        assert!(it
+            .cpu_metrics()
+            .iter()
+            .any(|metrics| metrics.process_name == process_name));
+
+        let energy_wh = model::rab_model(it, cpu, |_| None, None, None)
+            .iter()
+            .filter(|energy| energy.process_name == process_name)
+            .map(|energy| energy.cpu_energy_wh)
+            .sum::<f64>();
+        assert!(energy_wh > 0.0);
+    }
+
+    pool.close().await;
+    Ok(())
+}
+
+#[sqlx::test(migrations = "./migrations")]
+async fn bare_metal_workload_is_observed(pool: sqlx::SqlitePool) -> anyhow::Result<()> {
+    let toml = format!(
+        r#"
+[[processes]]
+name = "busy-loop"
+up = "{}"
+process.type = "baremetal"
+
+[[scenarios]]
+name = "burn"
+desc = "Waits while the busy loop burns CPU"
+command = "sleep 3"
+iterations = 2
+processes = ["busy-loop"]
+
+[[observations]]
+name = "e2e"
+scenarios = ["burn"]
+"#,
+        env!("CARGO_BIN_EXE_busy-loop")
+    );
+
+    observe(pool, &toml, "e2e", "busy-loop", 2).await
+}
+
+#[sqlx::test(migrations = "./migrations")]
+#[ignore = "requires docker"]
+async fn container_workload_is_observed(pool: sqlx::SqlitePool) -> anyhow::Result<()> {
+    // pulled up front so the container starts as soon as the run does
+    let pulled = tokio::process::Command::new("docker")
+        .args(["pull", "busybox"])
+        .status()
+        .await?;
+    anyhow::ensure!(pulled.success(), "unable to pull busybox");
+
+    let toml = r#"
+[[processes]]
+name = "busybox"
+up = "docker run -d --rm --name cardamon-e2e-busybox busybox sh -c 'while :; do :; done'"
+down = "docker stop -t 1 cardamon-e2e-busybox"
+process.type = "docker"
+process.containers = ["cardamon-e2e-busybox"]
+
+[[scenarios]]
+name = "burn"
+desc = "Waits while the container burns CPU"
+command = "sleep 10"
+iterations = 1
+processes = ["busybox"]
+
+[[observations]]
+name = "e2e"
+scenarios = ["burn"]
+"#;
+
+    observe(pool, toml, "e2e", "cardamon-e2e-busybox", 1).await
+}